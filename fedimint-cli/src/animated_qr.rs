@@ -0,0 +1,65 @@
+//! Animated QR export and import of ecash, notes, client configs and
+//! backups.
+//!
+//! Thin CLI wrappers around the framing provided by
+//! [`fedimint_fountain::animated_qr`].
+
+use std::io::BufRead;
+
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_fountain::animated_qr::{AnimatedQrDecoder, AnimatedQrEncoder, Frame, PayloadKind};
+
+use crate::{CliError, CliResultExt};
+
+/// Encode `payload` into `frames` frames, defaulting to twice the minimum
+/// number of frames required to decode it which leaves enough redundancy for
+/// frames missed while scanning.
+pub fn encode_frames(
+    kind: PayloadKind,
+    payload: &impl Encodable,
+    max_fragment_length: usize,
+    frames: Option<usize>,
+) -> Result<Vec<String>, CliError> {
+    if max_fragment_length == 0 {
+        return Err(CliError {
+            error: "max fragment length has to be positive".to_string(),
+        });
+    }
+
+    let mut encoder = AnimatedQrEncoder::new(kind, payload, max_fragment_length);
+
+    let frames = frames.unwrap_or(2 * encoder.fragment_count());
+
+    Ok((0..frames)
+        .map(|_| encoder.next_frame().to_string())
+        .collect())
+}
+
+/// Read frames line by line from `reader` until a payload of `kind` can be
+/// reassembled
+pub fn decode_frames<E: Decodable>(
+    kind: PayloadKind,
+    decoders: ModuleDecoderRegistry,
+    reader: impl BufRead,
+) -> Result<E, CliError> {
+    let mut decoder = AnimatedQrDecoder::<E>::new(kind, decoders);
+
+    for line in reader.lines() {
+        let line = line.map_err_cli_msg("failed to read frame")?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let frame = line.parse::<Frame>().map_err_cli_msg("invalid frame")?;
+
+        if let Some(payload) = decoder.add_frame(&frame).map_err_cli()? {
+            return Ok(payload);
+        }
+    }
+
+    Err(CliError {
+        error: "not enough frames to reassemble the payload".to_string(),
+    })
+}
@@ -8,6 +8,7 @@
 use fedimint_core::util::SafeUrl;
 use fedimint_core::{Amount, PeerId, TieredMulti};
 use fedimint_eventlog::EventLogId;
+use fedimint_fountain::animated_qr::{DEFAULT_MAX_FRAGMENT_LENGTH, PayloadKind};
 use fedimint_mint_client::{OOBNotes, SpendableNote};
 use serde::{Deserialize, Serialize};
 
@@ -206,6 +207,13 @@ pub(crate) enum DecodeType {
     /// Decode a setup code (as shared during a federation setup ceremony)
     /// string into a JSON representation
     SetupCode { setup_code: String },
+    /// Reassemble a payload from animated QR frames read line by line from
+    /// stdin
+    AnimatedQr {
+        /// Kind of the payload: ecash, oob-notes, client-config or
+        /// client-backup
+        kind: PayloadKind,
+    },
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -230,6 +238,25 @@ pub(crate) enum EncodeType {
 
     /// Encode a JSON string of notes to an ecash string
     Notes { notes_json: String },
+
+    /// Encode a payload as a stream of animated QR frames
+    ///
+    /// Client configs and backups are taken from the client, ecash and notes
+    /// have to be passed as `payload`.
+    AnimatedQr {
+        /// Kind of the payload: ecash, oob-notes, client-config or
+        /// client-backup
+        kind: PayloadKind,
+        /// Mint v2 ecash or legacy notes string
+        payload: Option<String>,
+        /// Maximum number of payload bytes carried by a single frame
+        #[clap(long, default_value_t = DEFAULT_MAX_FRAGMENT_LENGTH)]
+        max_fragment_length: usize,
+        /// Number of frames to emit, defaults to twice the minimum number of
+        /// frames needed to decode the payload
+        #[clap(long)]
+        frames: Option<usize>,
+    },
 }
 
 #[derive(Debug, Clone, Subcommand)]
@@ -386,6 +413,28 @@ pub(crate) enum DevCmd {
         #[arg(long, default_value = "10")]
         limit: u64,
     },
+    /// Export Client's Event Log as newline-delimited JSON
+    ///
+    /// Resumes after the last entry exported under the same `--name`.
+    ExportEventLog {
+        /// Name of the export, each name keeps its own cursor
+        #[arg(long, default_value = "fedimint-cli")]
+        name: String,
+        /// Only export events of these kinds (can be specified multiple times
+        /// or comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        event_kind: Vec<String>,
+        /// Only export events of these module kinds (can be specified
+        /// multiple times or comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        module: Vec<String>,
+        /// Append to this file instead of writing to stdout
+        #[arg(long)]
+        out_file: Option<PathBuf>,
+        /// Keep exporting new events instead of exiting at the end of the log
+        #[arg(long)]
+        follow: bool,
+    },
     /// Print the id the next entry appended to the client's event log will be
     /// assigned (the position just past the current end of the log).
     NextEventLogId,
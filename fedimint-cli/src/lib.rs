@@ -9,6 +9,7 @@
 #![allow(clippy::too_many_lines)]
 #![allow(clippy::large_futures)]
 
+mod animated_qr;
 mod cli;
 mod client;
 mod db;
@@ -39,6 +40,7 @@
 use fedimint_api_client::api::{DynGlobalApi, FederationApiExt, FederationError};
 use fedimint_api_client::download_from_invite_code;
 use fedimint_bip39::{Bip39RootSecretStrategy, Mnemonic};
+use fedimint_client::backup::{ClientBackup, Metadata};
 use fedimint_client::db::ApiSecretKey;
 use fedimint_client::module::meta::{FetchKind, LegacyMetaSource, MetaSource};
 use fedimint_client::module::module::init::ClientModuleInit;
@@ -47,23 +49,26 @@
 use fedimint_client::{AdminCreds, Client, ClientBuilder, ClientHandleArc, RootSecret};
 use fedimint_connectors::{Connectivity, ConnectorRegistry};
 use fedimint_core::base32::FEDIMINT_PREFIX;
-use fedimint_core::config::{FederationId, FederationIdPrefix};
-use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::config::{ClientConfig, FederationId, FederationIdPrefix};
+use fedimint_core::core::{ModuleInstanceId, ModuleKind};
 use fedimint_core::db::{Database, DatabaseValue, IDatabaseTransactionOpsCoreTyped as _};
-use fedimint_core::encoding::Decodable;
+use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::invite_code::InviteCode;
-use fedimint_core::module::registry::ModuleRegistry;
+use fedimint_core::module::registry::{ModuleDecoderRegistry, ModuleRegistry};
 use fedimint_core::module::{ApiAuth, ApiRequestErased};
 use fedimint_core::setup_code::PeerSetupCode;
 use fedimint_core::transaction::Transaction;
 use fedimint_core::util::{SafeUrl, backoff_util, handle_version_hash_command, retry};
 use fedimint_core::{PeerId, base32, fedimint_build_code_version_env, runtime};
 use fedimint_derive_secret::DerivableSecret;
-use fedimint_eventlog::EventLogTrimableId;
+use fedimint_eventlog::export::{EventLogExporter, EventLogFilter, JsonLinesSink};
+use fedimint_eventlog::{EventKind, EventLogTrimableId};
+use fedimint_fountain::animated_qr::PayloadKind;
 use fedimint_ln_client::LightningClientInit;
 use fedimint_logging::{LOG_CLIENT, TracingSetup};
 use fedimint_meta_client::{MetaClientInit, MetaModuleMetaSourceWithFallback};
 use fedimint_mint_client::{MintClientInit, MintClientModule, OOBNotes};
+use fedimint_mintv2_client::ECash;
 use fedimint_wallet_client::api::WalletFederationApi;
 use fedimint_wallet_client::{WalletClientInit, WalletClientModule};
 use futures::future::{join_all, pending};
@@ -124,6 +129,11 @@ enum CliOutput {
     },
 
     Raw(serde_json::Value),
+
+    /// The command already streamed its output to stdout, nothing else gets
+    /// printed
+    #[serde(skip)]
+    Streamed,
 }
 
 impl fmt::Display for CliOutput {
@@ -839,11 +849,12 @@ pub fn with_default_modules(self) -> Self {
             .with_module(WalletClientInit::default())
             .with_module(MetaClientInit)
             .with_module(fedimint_lnv2_client::LightningClientInit::default())
-            .with_module(fedimint_walletv2_client::WalletClientInit)
+            .with_module(fedimint_walletv2_client::WalletClientInit::default())
     }
 
     pub async fn run(&mut self) {
         match self.handle_command(self.cli_args.clone()).await {
+            Ok(CliOutput::Streamed) => {}
             Ok(output) => {
                 // ignore if there's anyone reading the stuff we're writing out
                 let _ = writeln!(std::io::stdout(), "{output}");
@@ -1580,6 +1591,47 @@ async fn handle_command(&mut self, cli: Opts) -> CliOutputResult {
 
                     Ok(CliOutput::SetupCode { setup_code })
                 }
+                DecodeType::AnimatedQr { kind } => {
+                    let stdin = std::io::stdin().lock();
+
+                    // Configs and backups contain module specific types for
+                    // which we have no decoders without opening a client
+                    let decoders = ModuleDecoderRegistry::default().with_fallback();
+
+                    match kind {
+                        PayloadKind::ECash => {
+                            let ecash: ECash = animated_qr::decode_frames(kind, decoders, stdin)?;
+
+                            Ok(CliOutput::Raw(
+                                base32::encode_prefixed(FEDIMINT_PREFIX, &ecash).into(),
+                            ))
+                        }
+                        PayloadKind::OOBNotes => {
+                            let notes: OOBNotes =
+                                animated_qr::decode_frames(kind, decoders, stdin)?;
+
+                            Ok(CliOutput::Raw(notes.to_string().into()))
+                        }
+                        PayloadKind::ClientConfig => {
+                            let config: ClientConfig =
+                                animated_qr::decode_frames(kind, decoders, stdin)?;
+
+                            Ok(CliOutput::Raw(
+                                serde_json::to_value(config)
+                                    .expect("Client config is serializable"),
+                            ))
+                        }
+                        PayloadKind::ClientBackup => {
+                            let backup: ClientBackup =
+                                animated_qr::decode_frames(kind, decoders, stdin)?;
+
+                            Ok(CliOutput::Raw(json!({
+                                "session_count": backup.session_count,
+                                "backup": backup.consensus_encode_to_hex(),
+                            })))
+                        }
+                    }
+                }
             },
             Command::Dev(DevCmd::Encode { encode_type }) => match encode_type {
                 EncodeType::InviteCode {
@@ -1598,6 +1650,48 @@ async fn handle_command(&mut self, cli: Opts) -> CliOutputResult {
                     let notes = OOBNotes::new(prefix, notes.notes);
                     Ok(CliOutput::Raw(notes.to_string().into()))
                 }
+                EncodeType::AnimatedQr {
+                    kind,
+                    payload,
+                    max_fragment_length,
+                    frames,
+                } => {
+                    let frames = match kind {
+                        PayloadKind::ECash => {
+                            let payload = payload.ok_or_cli_msg("ecash payload is required")?;
+                            let ecash: ECash = base32::decode_prefixed(FEDIMINT_PREFIX, &payload)
+                                .map_err_cli_msg("failed to decode ecash")?;
+
+                            animated_qr::encode_frames(kind, &ecash, max_fragment_length, frames)?
+                        }
+                        PayloadKind::OOBNotes => {
+                            let payload = payload.ok_or_cli_msg("notes payload is required")?;
+                            let notes = OOBNotes::from_str(&payload)
+                                .map_err_cli_msg("failed to decode notes")?;
+
+                            animated_qr::encode_frames(kind, &notes, max_fragment_length, frames)?
+                        }
+                        PayloadKind::ClientConfig => {
+                            let client = self.client_open(&cli).await?;
+                            let config = client.config().await;
+
+                            animated_qr::encode_frames(kind, &config, max_fragment_length, frames)?
+                        }
+                        PayloadKind::ClientBackup => {
+                            let client = self.client_open(&cli).await?;
+
+                            #[allow(deprecated)]
+                            let backup = client
+                                .create_backup(Metadata::empty())
+                                .await
+                                .map_err_cli_msg("failed to create backup")?;
+
+                            animated_qr::encode_frames(kind, &backup, max_fragment_length, frames)?
+                        }
+                    };
+
+                    Ok(CliOutput::Raw(json!({ "frames": frames })))
+                }
             },
             Command::Dev(DevCmd::SessionCount) => {
                 let client = self.client_open(&cli).await?;
@@ -1798,6 +1892,70 @@ struct ReactorLogState {
                     serde_json::to_value(events).expect("Can be encoded"),
                 ))
             }
+            Command::Dev(DevCmd::ExportEventLog {
+                name,
+                event_kind,
+                module,
+                out_file,
+                follow,
+            }) => {
+                let client = self.client_open(&cli).await?;
+
+                let filter = EventLogFilter::default()
+                    .with_event_kinds(event_kind.into_iter().map(EventKind::from))
+                    .with_module_kinds(
+                        module
+                            .iter()
+                            .map(String::as_str)
+                            .map(ModuleKind::clone_from_str),
+                    );
+
+                let to_stdout = out_file.is_none();
+
+                let writer: Box<dyn tokio::io::AsyncWrite + Unpin + Send> = match out_file {
+                    Some(out_file) => Box::new(
+                        tokio::fs::OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(out_file)
+                            .await
+                            .map_err_cli_msg("failed to open output file")?,
+                    ),
+                    None => Box::new(tokio::io::stdout()),
+                };
+
+                let mut sink = JsonLinesSink::new(writer);
+                let tracker = client.event_log_export_tracker(name);
+
+                if follow {
+                    // Returns once the client shuts down
+                    client
+                        .export_event_log(tracker, filter, &mut sink)
+                        .await
+                        .map_err_cli()?;
+
+                    return Ok(CliOutput::Streamed);
+                }
+
+                let mut exporter =
+                    EventLogExporter::new(client.db().clone(), tracker).with_filter(filter);
+
+                let exported = exporter.export_pending(&mut sink).await.map_err_cli()?;
+                let next_pos = exporter.cursor().await.map_err_cli()?;
+
+                let summary = json!({
+                    "exported": exported,
+                    "next_pos": next_pos,
+                });
+
+                // Keep stdout pure newline-delimited JSON if the entries went there
+                if to_stdout {
+                    eprintln!("{summary}");
+                    return Ok(CliOutput::Streamed);
+                }
+
+                Ok(CliOutput::Raw(summary))
+            }
             Command::Dev(DevCmd::NextEventLogId) => {
                 let client = self.client_open(&cli).await?;
 
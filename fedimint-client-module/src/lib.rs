@@ -58,6 +58,7 @@
 pub mod module;
 /// Operation log subsystem of the client
 pub mod oplog;
+pub mod payment_history;
 /// Secret handling & derivation
 pub mod secret;
 /// Client state machine interfaces and executor implementation
@@ -25,7 +25,8 @@
     maybe_add_send_sync,
 };
 use fedimint_eventlog::{
-    DBTransactionEventLogExt, Event, EventKind, EventLogId, EventPersistence, PersistedLogEntry,
+    DBTransactionEventLogExt, Event, EventKind, EventLogEntry, EventLogId, EventPersistence,
+    PersistedLogEntry,
 };
 use fedimint_logging::LOG_CLIENT;
 use futures::{Stream, StreamExt};
@@ -36,6 +37,7 @@
 use self::init::ClientModuleInit;
 use crate::module::recovery::{DynModuleBackup, ModuleBackup};
 use crate::oplog::{IOperationLog, OperationLogEntry, UpdateStreamOrOutcome};
+use crate::payment_history::PaymentHistoryUpdate;
 use crate::sm::executor::{ActiveStateKey, IExecutor, InactiveStateKey};
 use crate::sm::{self, ActiveStateMeta, Context, DynContext, DynState, InactiveStateMeta, State};
 use crate::transaction::{
@@ -1171,6 +1173,16 @@ async fn subscribe_balance_changes(&self) -> BoxStream<'static, ()> {
     async fn leave(&self, _dbtx: &mut DatabaseTransaction<'_>) -> anyhow::Result<()> {
         bail!("Unable to determine if safe to leave the federation: Not implemented")
     }
+
+    /// Interprets an event logged by this module as a change of the client's
+    /// payment history
+    ///
+    /// Modules processing payments should map the events they log when a
+    /// payment is started or reaches a final state, all other events are
+    /// ignored by returning `None`.
+    async fn payment_history_update(&self, _event: &EventLogEntry) -> Option<PaymentHistoryUpdate> {
+        None
+    }
 }
 
 /// Type-erased version of [`ClientModule`]
@@ -1230,6 +1242,8 @@ async fn get_balance(
     ) -> Amount;
 
     async fn subscribe_balance_changes(&self) -> BoxStream<'static, ()>;
+
+    async fn payment_history_update(&self, event: &EventLogEntry) -> Option<PaymentHistoryUpdate>;
 }
 
 #[apply(async_trait_maybe_send!)]
@@ -1363,6 +1377,10 @@ async fn get_balance(
     async fn subscribe_balance_changes(&self) -> BoxStream<'static, ()> {
         <T as ClientModule>::subscribe_balance_changes(self).await
     }
+
+    async fn payment_history_update(&self, event: &EventLogEntry) -> Option<PaymentHistoryUpdate> {
+        <T as ClientModule>::payment_history_update(self, event).await
+    }
 }
 
 dyn_newtype_define!(
@@ -0,0 +1,59 @@
+//! Module-independent view of payments for the client's payment history
+//!
+//! Modules interpret the payment events they log via
+//! [`crate::module::ClientModule::payment_history_update`], the client then
+//! maintains an index of all payments across modules from those updates.
+
+use fedimint_core::Amount;
+use fedimint_core::bitcoin::hashes::sha256;
+use fedimint_core::core::OperationId;
+use fedimint_core::encoding::{Decodable, Encodable};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentDirection {
+    Send,
+    Receive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    /// The payment was started but did not reach a final state yet
+    Pending,
+    /// The payment completed
+    Success,
+    /// The payment failed, any funds sent were returned
+    Failed,
+}
+
+/// A payment as it is recorded when it is started
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct PaymentInfo {
+    pub operation_id: OperationId,
+    pub direction: PaymentDirection,
+    /// Amount sent or received, excluding fees
+    pub amount: Amount,
+    /// Fees paid on top of `amount` when sending or deducted from it when
+    /// receiving
+    pub fee: Amount,
+    pub status: PaymentStatus,
+    /// Other party of the payment in a module specific format, e.g. a bitcoin
+    /// address or the node id of a lightning payee
+    pub counterparty: Option<String>,
+    /// Payment hash of lightning payments
+    pub payment_hash: Option<sha256::Hash>,
+}
+
+/// Change of the payment history caused by an event logged by a module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentHistoryUpdate {
+    /// A new payment was started
+    Created(PaymentInfo),
+    /// The status of a previously created payment changed
+    Status {
+        operation_id: OperationId,
+        status: PaymentStatus,
+    },
+}
@@ -66,9 +66,11 @@
     maybe_add_send_sync, runtime,
 };
 use fedimint_derive_secret::DerivableSecret;
+use fedimint_eventlog::export::{EventLogExporter, EventLogFilter, EventLogSink};
 use fedimint_eventlog::{
-    DBTransactionEventLogExt as _, DynEventLogTrimableTracker, Event, EventKind, EventLogEntry,
-    EventLogId, EventLogTrimableId, EventLogTrimableTracker, EventPersistence, PersistedLogEntry,
+    DBTransactionEventLogExt as _, DynEventLogTracker, DynEventLogTrimableTracker, Event,
+    EventKind, EventLogEntry, EventLogId, EventLogNonTrimableTracker, EventLogTrimableId,
+    EventLogTrimableTracker, EventPersistence, PersistedLogEntry,
 };
 use fedimint_logging::{LOG_CLIENT, LOG_CLIENT_NET_API, LOG_CLIENT_RECOVERY};
 use futures::stream::FuturesUnordered;
@@ -82,21 +84,24 @@
 use crate::ClientBuilder;
 use crate::api_announcements::{ApiAnnouncementPrefix, get_api_urls};
 use crate::backup::Metadata;
-use crate::client::event_log::DefaultApplicationEventLogKey;
+use crate::client::event_log::{DefaultApplicationEventLogKey, EventLogExportPosKey};
 use crate::db::{
     ApiSecretKey, CachedApiVersionSet, CachedApiVersionSetKey, ChainIdKey,
-    ChronologicalOperationLogKey, ClientConfigKey, ClientMetadataKey, ClientModuleRecovery,
-    ClientModuleRecoveryState, EncodedClientSecretKey, OperationLogKey, PeerLastApiVersionsSummary,
-    PeerLastApiVersionsSummaryKey, PendingClientConfigKey, TransactionFeesKey,
+    ChronologicalOperationLogKey, ChronologicalPaymentHistoryKey, ClientConfigKey,
+    ClientMetadataKey, ClientModuleRecovery, ClientModuleRecoveryState, EncodedClientSecretKey,
+    OperationLogKey, PeerLastApiVersionsSummary, PeerLastApiVersionsSummaryKey,
+    PendingClientConfigKey, SpendingLimitKey, SpendingLimitKeyPrefix, TransactionFeesKey,
     apply_migrations_core_client_dbtx, get_decoded_client_secret, verify_client_db_integrity_dbtx,
 };
 use crate::meta::MetaService;
 use crate::module_init::{ClientModuleInitRegistry, DynClientModuleInit, IClientModuleInit};
 use crate::oplog::OperationLog;
+use crate::payment_history::{PaymentHistoryEntry, PaymentHistoryQuery};
 use crate::sm::executor::{
     ActiveModuleOperationStateKeyPrefix, ActiveOperationStateKeyPrefix, Executor,
     InactiveModuleOperationStateKeyPrefix, InactiveOperationStateKeyPrefix,
 };
+use crate::spending_policy::{DynSpendingPolicy, SpendingLimit, enforce_spending_policy};
 
 pub(crate) mod builder;
 pub(crate) mod event_log;
@@ -270,6 +275,8 @@ pub struct Client {
     /// Modules can call this with a URL from their config to get an RPC client.
     pub(crate) user_bitcoind_rpc_no_chain_id:
         Option<fedimint_client_module::module::init::BitcoindRpcNoChainIdFactory>,
+    /// Integrator provided policy evaluated before submitting a transaction
+    pub(crate) spending_policy: Option<DynSpendingPolicy>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -1073,6 +1080,15 @@ async fn finalize_and_submit_transaction_inner(
         operation_id: OperationId,
         tx_builder: TransactionBuilder,
     ) -> anyhow::Result<OutPointRange> {
+        enforce_spending_policy(
+            &mut dbtx.to_ref_nc(),
+            &self.modules,
+            self.spending_policy.as_ref(),
+            operation_id,
+            &tx_builder,
+        )
+        .await?;
+
         let FinalizedTransaction {
             transaction,
             mut states,
@@ -2631,6 +2647,62 @@ async fn load(
         Box::new(BuiltInApplicationEventLogTracker)
     }
 
+    /// Event log (non-trimable) tracker persisting the cursor of the export
+    /// called `name` in the client database
+    ///
+    /// Every independent consumer of [`Self::export_event_log`] should use its
+    /// own `name`.
+    pub fn event_log_export_tracker(&self, name: impl Into<String>) -> DynEventLogTracker {
+        struct EventLogExportTracker(String);
+
+        #[apply(async_trait_maybe_send!)]
+        impl EventLogNonTrimableTracker for EventLogExportTracker {
+            async fn store(
+                &mut self,
+                dbtx: &mut DatabaseTransaction<NonCommittable>,
+                pos: EventLogId,
+            ) -> anyhow::Result<()> {
+                dbtx.insert_entry(&EventLogExportPosKey(self.0.clone()), &pos)
+                    .await;
+                Ok(())
+            }
+
+            async fn load(
+                &mut self,
+                dbtx: &mut DatabaseTransaction<NonCommittable>,
+            ) -> anyhow::Result<Option<EventLogId>> {
+                Ok(dbtx.get_value(&EventLogExportPosKey(self.0.clone())).await)
+            }
+        }
+
+        Box::new(EventLogExportTracker(name.into()))
+    }
+
+    /// Export the historical event log into `sink`
+    ///
+    /// Starts at the position persisted by `tracker` and keeps exporting
+    /// entries matching `filter` as they are appended to the log. Use
+    /// [`Self::event_log_export_tracker`] unless the cursor needs to be stored
+    /// elsewhere.
+    ///
+    /// This method returns only when client is shutting down or on sink
+    /// error, so typically should be called in a background task dedicated
+    /// to the export.
+    pub async fn export_event_log<S>(
+        &self,
+        tracker: DynEventLogTracker,
+        filter: EventLogFilter,
+        sink: &mut S,
+    ) -> anyhow::Result<()>
+    where
+        S: EventLogSink + ?Sized,
+    {
+        EventLogExporter::new(self.db.clone(), tracker)
+            .with_filter(filter)
+            .run(sink, self.log_event_added_rx.clone())
+            .await
+    }
+
     /// Like [`Self::handle_events`] but for historical data.
     ///
     ///
@@ -2692,6 +2764,67 @@ pub async fn handle_events<F, R>(
         .await
     }
 
+    /// Returns the last `limit` payments matching `query`, newest first. To
+    /// fetch the next page, pass the key of the last returned payment as
+    /// `last_seen`.
+    ///
+    /// The payment history is built from the event log in the background, so
+    /// it can lag slightly behind the operations that were just started.
+    pub async fn paginate_payments_rev(
+        &self,
+        query: &PaymentHistoryQuery,
+        limit: usize,
+        last_seen: Option<ChronologicalPaymentHistoryKey>,
+    ) -> Vec<(ChronologicalPaymentHistoryKey, PaymentHistoryEntry)> {
+        crate::payment_history::paginate_payments_rev(
+            &mut self.db.begin_transaction_nc().await,
+            query,
+            limit,
+            last_seen,
+        )
+        .await
+    }
+
+    /// Returns the payment history entry of an operation, if it is a payment
+    pub async fn get_payment(&self, operation_id: OperationId) -> Option<PaymentHistoryEntry> {
+        crate::payment_history::get_payment(&mut self.db.begin_transaction_nc().await, operation_id)
+            .await
+    }
+
+    /// Sets the spending limit for all modules of `module_kind`, or across all
+    /// modules if it is `None`, removes the limit if `limit` is `None`
+    pub async fn set_spending_limit(
+        &self,
+        module_kind: Option<ModuleKind>,
+        limit: Option<SpendingLimit>,
+    ) {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        match limit {
+            Some(limit) => {
+                dbtx.insert_entry(&SpendingLimitKey(module_kind), &limit)
+                    .await;
+            }
+            None => {
+                dbtx.remove_entry(&SpendingLimitKey(module_kind)).await;
+            }
+        }
+
+        dbtx.commit_tx().await;
+    }
+
+    /// Returns all spending limits, see [`Self::set_spending_limit`]
+    pub async fn get_spending_limits(&self) -> Vec<(Option<ModuleKind>, SpendingLimit)> {
+        self.db
+            .begin_transaction_nc()
+            .await
+            .find_by_prefix(&SpendingLimitKeyPrefix)
+            .await
+            .map(|(key, limit)| (key.0, limit))
+            .collect()
+            .await
+    }
+
     pub async fn get_event_log(
         &self,
         pos: Option<EventLogId>,
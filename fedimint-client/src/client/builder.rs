@@ -66,8 +66,10 @@
 use crate::meta::MetaService;
 use crate::module_init::ClientModuleInitRegistry;
 use crate::oplog::OperationLog;
+use crate::payment_history::run_payment_history_task;
 use crate::sm::executor::Executor;
 use crate::sm::notifier::Notifier;
+use crate::spending_policy::DynSpendingPolicy;
 
 /// The type of root secret hashing
 ///
@@ -127,6 +129,7 @@ pub struct ClientBuilder {
     iroh_enable_next: bool,
     bitcoind_rpc_factory: Option<BitcoindRpcFactory>,
     bitcoind_rpc_no_chain_id_factory: Option<BitcoindRpcNoChainIdFactory>,
+    spending_policy: Option<DynSpendingPolicy>,
 }
 
 impl ClientBuilder {
@@ -151,6 +154,7 @@ pub(crate) fn new() -> Self {
             iroh_enable_next: true,
             bitcoind_rpc_factory: None,
             bitcoind_rpc_no_chain_id_factory: None,
+            spending_policy: None,
         }
     }
 
@@ -170,6 +174,7 @@ pub(crate) fn from_existing(client: &Client) -> Self {
             bitcoind_rpc_factory: None,
             // Clone the no-chain-id factory from the existing client
             bitcoind_rpc_no_chain_id_factory: client.user_bitcoind_rpc_no_chain_id.clone(),
+            spending_policy: client.spending_policy.clone(),
         }
     }
 
@@ -210,6 +215,16 @@ pub fn with_iroh_enable_dht(mut self, iroh_enable_dht: bool) -> Self {
         self
     }
 
+    /// Evaluate a custom spending policy before submitting any transaction, in
+    /// addition to the spending limits persisted in the client database
+    ///
+    /// This allows applications to e.g. ask the user for confirmation of large
+    /// payments, see [`crate::spending_policy`].
+    pub fn with_spending_policy(mut self, policy: DynSpendingPolicy) -> Self {
+        self.spending_policy = Some(policy);
+        self
+    }
+
     /// Set a factory function for creating a Bitcoin RPC client
     ///
     /// This allows applications to provide their own Bitcoin RPC client
@@ -1058,6 +1073,7 @@ async fn build_stopped(
             iroh_enable_next,
             user_bitcoind_rpc,
             user_bitcoind_rpc_no_chain_id: self.bitcoind_rpc_no_chain_id_factory,
+            spending_policy: self.spending_policy,
         });
         client_inner.spawn_cancellable("MetaService::update_continuously", {
             let client_inner = client_inner.clone();
@@ -1109,6 +1125,18 @@ async fn build_stopped(
             }
         });
 
+        client_inner.spawn_cancellable("payment history task", {
+            let client_inner = client_inner.clone();
+            async move {
+                run_payment_history_task(
+                    client_inner.db.clone(),
+                    &client_inner.modules,
+                    client_inner.log_event_added_rx.clone(),
+                )
+                .await;
+            }
+        });
+
         // If chain_id is not cached yet, spawn a background task to fetch it
         // This handles the case where join/open happened before the server supported
         // the chain_id endpoint
@@ -1,6 +1,6 @@
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::impl_db_record;
-use fedimint_eventlog::EventLogTrimableId;
+use fedimint_eventlog::{EventLogId, EventLogTrimableId};
 
 use crate::db::DbKeyPrefixInternalReserved;
 
@@ -12,3 +12,14 @@
     value = EventLogTrimableId,
     db_prefix = DbKeyPrefixInternalReserved::DefaultApplicationEventLogPos,
 );
+
+/// Cursor of a named event log export, see
+/// [`crate::Client::event_log_export_tracker`]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Encodable, Decodable)]
+pub(crate) struct EventLogExportPosKey(pub String);
+
+impl_db_record!(
+    key = EventLogExportPosKey,
+    value = EventLogId,
+    db_prefix = DbKeyPrefixInternalReserved::EventLogExportPos,
+);
@@ -188,6 +188,7 @@ async fn client_for_recovery_test(
         iroh_enable_next: false,
         user_bitcoind_rpc: None,
         user_bitcoind_rpc_no_chain_id: None,
+        spending_policy: None,
     }
 }
 
@@ -9,7 +9,7 @@
 use fedimint_client_module::oplog::{JsonStringed, OperationLogEntry, OperationOutcome};
 use fedimint_client_module::sm::{ActiveStateMeta, InactiveStateMeta};
 use fedimint_core::config::{ClientConfig, ClientConfigV0, FederationId, GlobalClientConfig};
-use fedimint_core::core::{ModuleInstanceId, OperationId};
+use fedimint_core::core::{ModuleInstanceId, ModuleKind, OperationId};
 use fedimint_core::db::{
     Database, DatabaseTransaction, DatabaseVersion, DatabaseVersionKey,
     IDatabaseTransactionOpsCore, IDatabaseTransactionOpsCoreTyped, MODULE_GLOBAL_PREFIX,
@@ -21,7 +21,7 @@
 };
 use fedimint_core::module::registry::{ModuleDecoderRegistry, ModuleRegistry};
 use fedimint_core::module::{Amounts, SupportedApiVersionsSummary};
-use fedimint_core::{ChainId, PeerId, TransactionId, impl_db_lookup, impl_db_record};
+use fedimint_core::{Amount, ChainId, PeerId, TransactionId, impl_db_lookup, impl_db_record};
 use fedimint_eventlog::{
     DB_KEY_PREFIX_EVENT_LOG, DB_KEY_PREFIX_UNORDERED_EVENT_LOG, EventLogId, UnordedEventLogId,
 };
@@ -33,10 +33,12 @@
 use tracing::{debug, info, trace, warn};
 
 use crate::backup::{ClientBackup, Metadata};
+use crate::payment_history::PaymentHistoryEntry;
 use crate::sm::executor::{
     ActiveStateKeyBytes, ActiveStateKeyPrefixBytes, ExecutorDbPrefixes, InactiveStateKeyBytes,
     InactiveStateKeyPrefixBytes,
 };
+use crate::spending_policy::SpendingLimit;
 
 #[repr(u8)]
 #[derive(Clone, EnumIter, Debug)]
@@ -65,6 +67,10 @@ pub enum DbKeyPrefix {
     ClientModuleRecovery = 0x40,
     GuardianMetadata = 0x42,
     TransactionFees = 0x43,
+    PaymentHistory = 0x44,
+    ChronologicalPaymentHistory = 0x45,
+    SpendingLimit = 0x46,
+    SpendingRecord = 0x47,
 
     DatabaseVersion = fedimint_core::db::DbKeyPrefix::DatabaseVersion as u8,
     ClientBackup = fedimint_core::db::DbKeyPrefix::ClientBackup as u8,
@@ -100,6 +106,10 @@ pub enum DbKeyPrefix {
 pub(crate) enum DbKeyPrefixInternalReserved {
     /// [`crate::Client::built_in_application_event_log_tracker`]
     DefaultApplicationEventLogPos = 0xd0,
+    /// [`crate::Client::event_log_export_tracker`]
+    EventLogExportPos = 0xd1,
+    /// [`crate::payment_history`]
+    PaymentHistoryCursor = 0xd2,
 }
 
 pub(crate) async fn verify_client_db_integrity_dbtx(dbtx: &mut DatabaseTransaction<'_>) {
@@ -330,6 +340,95 @@ pub struct ClientConfigKeyV0 {
     db_prefix = DbKeyPrefix::TransactionFees,
 );
 
+/// Creation time of the payment of an operation in the payment history, see
+/// [`ChronologicalPaymentHistoryKey`]
+#[derive(Debug, Clone, Copy, Encodable, Decodable)]
+pub struct PaymentHistoryKey(pub OperationId);
+
+impl_db_record!(
+    key = PaymentHistoryKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::PaymentHistory,
+);
+
+/// Key used to lookup payment history entries in chronological order, also
+/// serves as the cursor when paginating the payment history
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    Encodable,
+    Decodable,
+)]
+pub struct ChronologicalPaymentHistoryKey {
+    /// Time the payment was created in microseconds since the unix epoch
+    pub created_at_usecs: u64,
+    pub operation_id: OperationId,
+}
+
+#[derive(Debug, Encodable)]
+pub struct ChronologicalPaymentHistoryKeyPrefix;
+
+impl_db_record!(
+    key = ChronologicalPaymentHistoryKey,
+    value = PaymentHistoryEntry,
+    db_prefix = DbKeyPrefix::ChronologicalPaymentHistory,
+);
+
+impl_db_lookup!(
+    key = ChronologicalPaymentHistoryKey,
+    query_prefix = ChronologicalPaymentHistoryKeyPrefix
+);
+
+/// Spending limit applying to all modules of a kind, or across all modules if
+/// the kind is `None`
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct SpendingLimitKey(pub Option<ModuleKind>);
+
+#[derive(Debug, Encodable)]
+pub struct SpendingLimitKeyPrefix;
+
+impl_db_record!(
+    key = SpendingLimitKey,
+    value = SpendingLimit,
+    db_prefix = DbKeyPrefix::SpendingLimit,
+);
+
+impl_db_lookup!(
+    key = SpendingLimitKey,
+    query_prefix = SpendingLimitKeyPrefix
+);
+
+/// Amounts spent per module kind by a transaction of an operation, kept for
+/// as long as a rate limit's window may cover them
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct SpendingRecordKey {
+    /// Time the transaction was submitted in microseconds since the unix epoch
+    pub spent_at_usecs: u64,
+    pub operation_id: OperationId,
+}
+
+#[derive(Debug, Encodable)]
+pub struct SpendingRecordKeyPrefix;
+
+impl_db_record!(
+    key = SpendingRecordKey,
+    value = BTreeMap<ModuleKind, Amount>,
+    db_prefix = DbKeyPrefix::SpendingRecord,
+);
+
+impl_db_lookup!(
+    key = SpendingRecordKey,
+    query_prefix = SpendingRecordKeyPrefix
+);
+
 /// Client metadata that will be stored/restored on backup&recovery
 #[derive(Debug, Encodable, Decodable, Serialize)]
 pub struct ClientMetadataKey;
@@ -101,6 +101,10 @@
 
 pub mod module_init;
 
+pub mod payment_history;
+
+pub mod spending_policy;
+
 pub mod sm;
 pub mod visualize;
 pub use client::Client;
@@ -0,0 +1,282 @@
+//! Payment history of the client across all modules
+//!
+//! The payment history is an index of all payments sent or received by the
+//! client, which can be filtered by properties common to all payment methods
+//! such as amount, status or counterparty. It is built by a background task
+//! following the event log, which lets every module interpret its own events
+//! via [`fedimint_client_module::module::ClientModule::payment_history_update`]
+//! and persists its position in the log together with the index, so every
+//! event is applied exactly once.
+
+use fedimint_client_module::module::ClientModuleRegistry;
+pub use fedimint_client_module::payment_history::{
+    PaymentDirection, PaymentHistoryUpdate, PaymentInfo, PaymentStatus,
+};
+use fedimint_core::bitcoin::hashes::sha256;
+use fedimint_core::core::{ModuleInstanceId, ModuleKind};
+use fedimint_core::db::{Database, DatabaseTransaction, IDatabaseTransactionOpsCoreTyped as _};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{Amount, impl_db_record};
+use fedimint_eventlog::{EventLogEntry, EventLogId};
+use fedimint_logging::LOG_CLIENT;
+use futures::StreamExt as _;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+use crate::db::{
+    ChronologicalPaymentHistoryKey, ChronologicalPaymentHistoryKeyPrefix,
+    DbKeyPrefixInternalReserved, PaymentHistoryKey,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// A payment in the payment history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct PaymentHistoryEntry {
+    pub module_kind: ModuleKind,
+    pub module_instance_id: ModuleInstanceId,
+    /// Time the payment was created in microseconds since the unix epoch
+    pub created_at_usecs: u64,
+    /// Time of the last status change in microseconds since the unix epoch
+    pub updated_at_usecs: u64,
+    #[serde(flatten)]
+    pub payment: PaymentInfo,
+}
+
+/// Filter for [`crate::Client::paginate_payments_rev`], every criterion that
+/// is set has to match
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentHistoryQuery {
+    pub direction: Option<PaymentDirection>,
+    pub status: Option<PaymentStatus>,
+    pub module_kind: Option<ModuleKind>,
+    pub min_amount: Option<Amount>,
+    pub max_amount: Option<Amount>,
+    /// Only payments created at or after this time in microseconds since the
+    /// unix epoch
+    pub created_since_usecs: Option<u64>,
+    /// Only payments created before this time in microseconds since the unix
+    /// epoch
+    pub created_before_usecs: Option<u64>,
+    /// Only payments whose counterparty contains this string
+    pub counterparty: Option<String>,
+    pub payment_hash: Option<sha256::Hash>,
+}
+
+impl PaymentHistoryQuery {
+    pub fn matches(&self, entry: &PaymentHistoryEntry) -> bool {
+        let payment = &entry.payment;
+
+        self.direction.is_none_or(|d| d == payment.direction)
+            && self.status.is_none_or(|s| s == payment.status)
+            && self
+                .module_kind
+                .as_ref()
+                .is_none_or(|kind| *kind == entry.module_kind)
+            && self.min_amount.is_none_or(|min| min <= payment.amount)
+            && self.max_amount.is_none_or(|max| payment.amount <= max)
+            && self
+                .created_since_usecs
+                .is_none_or(|since| since <= entry.created_at_usecs)
+            && self
+                .created_before_usecs
+                .is_none_or(|before| entry.created_at_usecs < before)
+            && self.counterparty.as_ref().is_none_or(|search| {
+                payment
+                    .counterparty
+                    .as_ref()
+                    .is_some_and(|counterparty| counterparty.contains(search.as_str()))
+            })
+            && self
+                .payment_hash
+                .is_none_or(|hash| payment.payment_hash == Some(hash))
+    }
+}
+
+/// Position of the next event log entry to be applied to the payment history
+#[derive(Debug, Encodable, Decodable)]
+pub(crate) struct PaymentHistoryEventLogPosKey;
+
+impl_db_record!(
+    key = PaymentHistoryEventLogPosKey,
+    value = EventLogId,
+    db_prefix = DbKeyPrefixInternalReserved::PaymentHistoryCursor,
+);
+
+/// Returns the last `limit` payments matching `query` created before
+/// `last_seen`, newest first
+pub(crate) async fn paginate_payments_rev(
+    dbtx: &mut DatabaseTransaction<'_>,
+    query: &PaymentHistoryQuery,
+    limit: usize,
+    last_seen: Option<ChronologicalPaymentHistoryKey>,
+) -> Vec<(ChronologicalPaymentHistoryKey, PaymentHistoryEntry)> {
+    dbtx.find_by_prefix_sorted_descending(&ChronologicalPaymentHistoryKeyPrefix)
+        .await
+        .skip_while(|(key, _)| {
+            std::future::ready(
+                last_seen.is_some_and(|last_seen| last_seen <= *key)
+                    || query
+                        .created_before_usecs
+                        .is_some_and(|before| before <= key.created_at_usecs),
+            )
+        })
+        .take_while(|(key, _)| {
+            std::future::ready(
+                query
+                    .created_since_usecs
+                    .is_none_or(|since| since <= key.created_at_usecs),
+            )
+        })
+        .filter(|(_, entry)| std::future::ready(query.matches(entry)))
+        .take(limit)
+        .collect()
+        .await
+}
+
+pub(crate) async fn get_payment(
+    dbtx: &mut DatabaseTransaction<'_>,
+    operation_id: fedimint_core::core::OperationId,
+) -> Option<PaymentHistoryEntry> {
+    let created_at_usecs = dbtx.get_value(&PaymentHistoryKey(operation_id)).await?;
+
+    dbtx.get_value(&ChronologicalPaymentHistoryKey {
+        created_at_usecs,
+        operation_id,
+    })
+    .await
+}
+
+/// Applies an update derived from an event logged at `ts_usecs` by the module
+/// `module_instance_id` of kind `module_kind`
+pub(crate) async fn apply_payment_history_update(
+    dbtx: &mut DatabaseTransaction<'_>,
+    module_kind: ModuleKind,
+    module_instance_id: ModuleInstanceId,
+    ts_usecs: u64,
+    update: PaymentHistoryUpdate,
+) {
+    match update {
+        PaymentHistoryUpdate::Created(payment) => {
+            let operation_id = payment.operation_id;
+
+            if dbtx
+                .get_value(&PaymentHistoryKey(operation_id))
+                .await
+                .is_some()
+            {
+                warn!(
+                    target: LOG_CLIENT,
+                    operation_id = %operation_id.fmt_short(),
+                    "Payment was already recorded in the payment history"
+                );
+
+                return;
+            }
+
+            dbtx.insert_new_entry(&PaymentHistoryKey(operation_id), &ts_usecs)
+                .await;
+
+            dbtx.insert_new_entry(
+                &ChronologicalPaymentHistoryKey {
+                    created_at_usecs: ts_usecs,
+                    operation_id,
+                },
+                &PaymentHistoryEntry {
+                    module_kind,
+                    module_instance_id,
+                    created_at_usecs: ts_usecs,
+                    updated_at_usecs: ts_usecs,
+                    payment,
+                },
+            )
+            .await;
+        }
+        PaymentHistoryUpdate::Status {
+            operation_id,
+            status,
+        } => {
+            let Some(created_at_usecs) = dbtx.get_value(&PaymentHistoryKey(operation_id)).await
+            else {
+                warn!(
+                    target: LOG_CLIENT,
+                    operation_id = %operation_id.fmt_short(),
+                    "Status update for a payment missing from the payment history"
+                );
+
+                return;
+            };
+
+            let key = ChronologicalPaymentHistoryKey {
+                created_at_usecs,
+                operation_id,
+            };
+
+            let mut entry = dbtx
+                .get_value(&key)
+                .await
+                .expect("Payment history index is consistent");
+
+            entry.payment.status = status;
+            entry.updated_at_usecs = ts_usecs;
+
+            dbtx.insert_entry(&key, &entry).await;
+        }
+    }
+}
+
+async fn handle_event(
+    dbtx: &mut DatabaseTransaction<'_>,
+    modules: &ClientModuleRegistry,
+    event: &EventLogEntry,
+) {
+    let Some(module) = &event.module else {
+        return;
+    };
+
+    // Modules that are unknown to this client version can not be interpreted
+    let Some(client_module) = modules.get(module.id) else {
+        return;
+    };
+
+    if let Some(update) = client_module.payment_history_update(event).await {
+        debug!(target: LOG_CLIENT, ?update, "Updating payment history");
+
+        apply_payment_history_update(dbtx, module.kind.clone(), module.id, event.ts_usecs, update)
+            .await;
+    }
+}
+
+/// Applies all events from the (non-trimable) event log to the payment
+/// history, returns only once the client is shutting down
+pub(crate) async fn run_payment_history_task(
+    db: Database,
+    modules: &ClientModuleRegistry,
+    mut log_event_added: watch::Receiver<()>,
+) {
+    loop {
+        let mut dbtx = db.begin_transaction().await;
+
+        let pos = dbtx
+            .get_value(&PaymentHistoryEventLogPosKey)
+            .await
+            .unwrap_or_default();
+
+        if let Some(event) = dbtx.get_value(&pos).await {
+            handle_event(&mut dbtx.to_ref_nc(), modules, &event).await;
+
+            dbtx.insert_entry(&PaymentHistoryEventLogPosKey, &pos.next())
+                .await;
+
+            dbtx.commit_tx().await;
+        } else {
+            drop(dbtx);
+
+            if log_event_added.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use fedimint_core::Amount;
+use fedimint_core::core::{ModuleKind, OperationId};
+use fedimint_core::db::Database;
+use fedimint_core::db::mem_impl::MemDatabase;
+use fedimint_core::module::registry::ModuleRegistry;
+
+use crate::db::ChronologicalPaymentHistoryKey;
+use crate::payment_history::{
+    PaymentDirection, PaymentHistoryQuery, PaymentHistoryUpdate, PaymentInfo, PaymentStatus,
+    apply_payment_history_update, get_payment, paginate_payments_rev,
+};
+
+const LN: ModuleKind = ModuleKind::from_static_str("ln");
+const MINT: ModuleKind = ModuleKind::from_static_str("mint");
+
+fn payment(id: u8, direction: PaymentDirection, amount: u64) -> PaymentInfo {
+    PaymentInfo {
+        operation_id: OperationId([id; 32]),
+        direction,
+        amount: Amount::from_sats(amount),
+        fee: Amount::ZERO,
+        status: PaymentStatus::Pending,
+        counterparty: Some(format!("counterparty-{id}")),
+        payment_hash: None,
+    }
+}
+
+/// Records ten payments alternating between modules and directions, payment
+/// `i` is created at time `i` with an amount of `i * 1000` sats
+async fn populated_db() -> Database {
+    let db = Database::new(MemDatabase::new(), ModuleRegistry::default());
+
+    let mut dbtx = db.begin_transaction().await;
+
+    for i in 0..10u8 {
+        let (kind, direction) = if i % 2 == 0 {
+            (LN, PaymentDirection::Send)
+        } else {
+            (MINT, PaymentDirection::Receive)
+        };
+
+        apply_payment_history_update(
+            &mut dbtx.to_ref_nc(),
+            kind,
+            u16::from(i % 2),
+            u64::from(i),
+            PaymentHistoryUpdate::Created(payment(i, direction, u64::from(i) * 1000)),
+        )
+        .await;
+    }
+
+    dbtx.commit_tx().await;
+
+    db
+}
+
+async fn query_ids(
+    db: &Database,
+    query: &PaymentHistoryQuery,
+    limit: usize,
+    last_seen: Option<ChronologicalPaymentHistoryKey>,
+) -> Vec<u8> {
+    paginate_payments_rev(
+        &mut db.begin_transaction_nc().await,
+        query,
+        limit,
+        last_seen,
+    )
+    .await
+    .into_iter()
+    .map(|(_, entry)| entry.payment.operation_id.0[0])
+    .collect()
+}
+
+#[tokio::test]
+async fn paginates_newest_first() {
+    let db = populated_db().await;
+
+    let query = PaymentHistoryQuery::default();
+
+    let first_page =
+        paginate_payments_rev(&mut db.begin_transaction_nc().await, &query, 4, None).await;
+
+    assert_eq!(
+        first_page
+            .iter()
+            .map(|(key, _)| key.created_at_usecs)
+            .collect::<Vec<_>>(),
+        vec![9, 8, 7, 6]
+    );
+
+    let last_seen = first_page.last().map(|(key, _)| *key);
+
+    assert_eq!(query_ids(&db, &query, 4, last_seen).await, vec![5, 4, 3, 2]);
+
+    assert_eq!(query_ids(&db, &query, 100, None).await.len(), 10);
+}
+
+#[tokio::test]
+async fn filters_by_query() {
+    let db = populated_db().await;
+
+    let query = PaymentHistoryQuery {
+        direction: Some(PaymentDirection::Send),
+        min_amount: Some(Amount::from_sats(2000)),
+        max_amount: Some(Amount::from_sats(6000)),
+        ..Default::default()
+    };
+
+    assert_eq!(query_ids(&db, &query, 100, None).await, vec![6, 4, 2]);
+
+    let query = PaymentHistoryQuery {
+        module_kind: Some(MINT),
+        created_since_usecs: Some(3),
+        created_before_usecs: Some(9),
+        ..Default::default()
+    };
+
+    assert_eq!(query_ids(&db, &query, 100, None).await, vec![7, 5, 3]);
+
+    let query = PaymentHistoryQuery {
+        counterparty: Some("counterparty-7".to_string()),
+        ..Default::default()
+    };
+
+    assert_eq!(query_ids(&db, &query, 100, None).await, vec![7]);
+}
+
+#[tokio::test]
+async fn applies_status_updates() {
+    let db = populated_db().await;
+
+    let mut dbtx = db.begin_transaction().await;
+
+    apply_payment_history_update(
+        &mut dbtx.to_ref_nc(),
+        LN,
+        0,
+        20,
+        PaymentHistoryUpdate::Status {
+            operation_id: OperationId([4; 32]),
+            status: PaymentStatus::Success,
+        },
+    )
+    .await;
+
+    // A duplicate creation must not reset the status
+    apply_payment_history_update(
+        &mut dbtx.to_ref_nc(),
+        LN,
+        0,
+        21,
+        PaymentHistoryUpdate::Created(payment(4, PaymentDirection::Send, 4000)),
+    )
+    .await;
+
+    dbtx.commit_tx().await;
+
+    let entry = get_payment(&mut db.begin_transaction_nc().await, OperationId([4; 32]))
+        .await
+        .expect("Payment is recorded");
+
+    assert_eq!(entry.payment.status, PaymentStatus::Success);
+    assert_eq!(entry.created_at_usecs, 4);
+    assert_eq!(entry.updated_at_usecs, 20);
+
+    let query = PaymentHistoryQuery {
+        status: Some(PaymentStatus::Success),
+        ..Default::default()
+    };
+
+    assert_eq!(query_ids(&db, &query, 100, None).await, vec![4]);
+}
@@ -0,0 +1,313 @@
+//! Limits on what the client may spend
+//!
+//! Before a transaction is finalized the client determines how much it spends
+//! per module kind and checks it against the [`SpendingLimit`]s persisted in
+//! the client database and, if set, the [`ISpendingPolicy`] of the integrator
+//! (see [`crate::ClientBuilder::with_spending_policy`]). A violation rejects
+//! the transaction before anything is submitted to the federation.
+//!
+//! The amount a transaction spends via a module kind is the amount of its
+//! outputs exceeding its inputs of that kind, in other words what has to be
+//! funded from the client's balance excluding fees. Receiving or reissuing
+//! funds therefore does not count as spending.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use fedimint_client_module::module::ClientModuleRegistry;
+use fedimint_client_module::transaction::TransactionBuilder;
+use fedimint_core::core::{ModuleKind, OperationId};
+use fedimint_core::db::{DatabaseTransaction, IDatabaseTransactionOpsCoreTyped as _};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::task::{MaybeSend, MaybeSync};
+use fedimint_core::{Amount, apply, async_trait_maybe_send, maybe_add_send_sync};
+use futures::StreamExt as _;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::db::{SpendingLimitKeyPrefix, SpendingRecordKey, SpendingRecordKeyPrefix};
+
+#[cfg(test)]
+mod tests;
+
+/// Limits on the amount spent either via a single module kind or across all
+/// modules
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct SpendingLimit {
+    /// Maximum amount a single operation may spend
+    pub max_per_operation: Option<Amount>,
+    /// Maximum amount spent within a rolling time window
+    pub rate_limit: Option<RateLimit>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct RateLimit {
+    pub max_amount: Amount,
+    pub window: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SpendingPolicyError {
+    #[error("Spending {amount} via {} exceeds the limit of {limit} per operation", fmt_scope(.module_kind))]
+    OperationLimitExceeded {
+        module_kind: Option<ModuleKind>,
+        amount: Amount,
+        limit: Amount,
+    },
+    #[error(
+        "Spending {amount} via {} exceeds the limit of {max_amount} per {window:?}, {spent} were already spent",
+        fmt_scope(.module_kind)
+    )]
+    RateLimitExceeded {
+        module_kind: Option<ModuleKind>,
+        amount: Amount,
+        spent: Amount,
+        max_amount: Amount,
+        window: Duration,
+    },
+    #[error("Transaction was rejected by the spending policy: {0}")]
+    Rejected(String),
+}
+
+fn fmt_scope(module_kind: &Option<ModuleKind>) -> String {
+    module_kind
+        .as_ref()
+        .map_or_else(|| "all modules".to_string(), ToString::to_string)
+}
+
+/// A transaction that is about to be finalized
+pub struct SpendingRequest<'a> {
+    pub operation_id: OperationId,
+    pub tx_builder: &'a TransactionBuilder,
+    /// Amount spent per module kind, kinds the transaction does not spend via
+    /// are omitted
+    pub spent: BTreeMap<ModuleKind, Amount>,
+    modules: &'a ClientModuleRegistry,
+}
+
+impl SpendingRequest<'_> {
+    /// Kind of the module with instance id `module_instance_id`, allows to
+    /// interpret the inputs and outputs of [`Self::tx_builder`]
+    pub fn module_kind(
+        &self,
+        module_instance_id: fedimint_core::core::ModuleInstanceId,
+    ) -> Option<&ModuleKind> {
+        self.modules
+            .get_with_kind(module_instance_id)
+            .map(|(kind, _)| kind)
+    }
+
+    /// Total amount spent across all modules
+    pub fn total_spent(&self) -> Amount {
+        self.spent.values().copied().sum()
+    }
+}
+
+/// Custom spending policy of an integrator
+///
+/// The policy is evaluated after the built-in [`SpendingLimit`]s as part of
+/// the database transaction submitting the transaction, so it may be
+/// evaluated more than once for the same transaction if the database
+/// transaction has to be retried and should not block for long.
+#[apply(async_trait_maybe_send!)]
+pub trait ISpendingPolicy: Debug + MaybeSend + MaybeSync {
+    async fn evaluate(&self, request: &SpendingRequest<'_>) -> Result<(), SpendingPolicyError>;
+}
+
+pub type DynSpendingPolicy = Arc<maybe_add_send_sync!(dyn ISpendingPolicy + 'static)>;
+
+fn spent_amounts(
+    tx_builder: &TransactionBuilder,
+    modules: &ClientModuleRegistry,
+) -> BTreeMap<ModuleKind, Amount> {
+    let module_kind = |module_instance_id| {
+        modules
+            .get_with_kind(module_instance_id)
+            .map(|(kind, _)| kind.clone())
+    };
+
+    let mut inputs = BTreeMap::<ModuleKind, Amount>::new();
+
+    for input in tx_builder.inputs() {
+        if let Some(kind) = module_kind(input.input.module_instance_id()) {
+            *inputs.entry(kind).or_default() += input.amounts.get_bitcoin();
+        }
+    }
+
+    let mut outputs = BTreeMap::<ModuleKind, Amount>::new();
+
+    for output in tx_builder.outputs() {
+        if let Some(kind) = module_kind(output.output.module_instance_id()) {
+            *outputs.entry(kind).or_default() += output.amounts.get_bitcoin();
+        }
+    }
+
+    outputs
+        .into_iter()
+        .map(|(kind, amount)| {
+            let input = inputs.get(&kind).copied().unwrap_or_default();
+
+            (kind, amount.saturating_sub(input))
+        })
+        .filter(|(_, amount)| *amount != Amount::ZERO)
+        .collect()
+}
+
+/// Checks the transaction built by `tx_builder` against the spending limits
+/// and `policy` and records its spending if it is allowed
+pub(crate) async fn enforce_spending_policy(
+    dbtx: &mut DatabaseTransaction<'_>,
+    modules: &ClientModuleRegistry,
+    policy: Option<&DynSpendingPolicy>,
+    operation_id: OperationId,
+    tx_builder: &TransactionBuilder,
+) -> Result<(), SpendingPolicyError> {
+    let now_usecs = duration_usecs(fedimint_core::time::duration_since_epoch());
+
+    let request = SpendingRequest {
+        operation_id,
+        tx_builder,
+        spent: spent_amounts(tx_builder, modules),
+        modules,
+    };
+
+    check_spending_limits(dbtx, &request.spent, now_usecs).await?;
+
+    if let Some(policy) = policy {
+        policy.evaluate(&request).await?;
+    }
+
+    record_spending(dbtx, operation_id, request.spent, now_usecs).await;
+
+    Ok(())
+}
+
+/// Checks `spent` against all spending limits, considering the spending
+/// recorded within the window of a rate limit
+pub(crate) async fn check_spending_limits(
+    dbtx: &mut DatabaseTransaction<'_>,
+    spent: &BTreeMap<ModuleKind, Amount>,
+    now_usecs: u64,
+) -> Result<(), SpendingPolicyError> {
+    if spent.is_empty() {
+        return Ok(());
+    }
+
+    let limits = dbtx
+        .find_by_prefix(&SpendingLimitKeyPrefix)
+        .await
+        .map(|(key, limit)| (key.0, limit))
+        .collect::<Vec<_>>()
+        .await;
+
+    let records = dbtx
+        .find_by_prefix(&SpendingRecordKeyPrefix)
+        .await
+        .collect::<Vec<_>>()
+        .await;
+
+    let amount_in_scope = |module_kind: &Option<ModuleKind>,
+                           spent: &BTreeMap<ModuleKind, Amount>| {
+        match module_kind {
+            Some(kind) => spent.get(kind).copied().unwrap_or_default(),
+            None => spent.values().copied().sum(),
+        }
+    };
+
+    for (module_kind, limit) in limits {
+        let amount = amount_in_scope(&module_kind, spent);
+
+        if amount == Amount::ZERO {
+            continue;
+        }
+
+        if let Some(max_per_operation) = limit.max_per_operation
+            && max_per_operation < amount
+        {
+            return Err(SpendingPolicyError::OperationLimitExceeded {
+                module_kind,
+                amount,
+                limit: max_per_operation,
+            });
+        }
+
+        if let Some(rate_limit) = limit.rate_limit {
+            let window_start_usecs = now_usecs.saturating_sub(duration_usecs(rate_limit.window));
+
+            let spent_in_window = records
+                .iter()
+                .filter(|(key, _)| window_start_usecs <= key.spent_at_usecs)
+                .map(|(_, record)| amount_in_scope(&module_kind, record))
+                .sum::<Amount>();
+
+            if rate_limit.max_amount < spent_in_window + amount {
+                return Err(SpendingPolicyError::RateLimitExceeded {
+                    module_kind,
+                    amount,
+                    spent: spent_in_window,
+                    max_amount: rate_limit.max_amount,
+                    window: rate_limit.window,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Records `spent` and removes all records no rate limit covers anymore
+pub(crate) async fn record_spending(
+    dbtx: &mut DatabaseTransaction<'_>,
+    operation_id: OperationId,
+    spent: BTreeMap<ModuleKind, Amount>,
+    now_usecs: u64,
+) {
+    if spent.is_empty() {
+        return;
+    }
+
+    let longest_window = dbtx
+        .find_by_prefix(&SpendingLimitKeyPrefix)
+        .await
+        .filter_map(
+            |(_, limit)| async move { limit.rate_limit.map(|rate_limit| rate_limit.window) },
+        )
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .max()
+        .unwrap_or_default();
+
+    let retain_since_usecs = now_usecs.saturating_sub(duration_usecs(longest_window));
+
+    let expired = dbtx
+        .find_by_prefix(&SpendingRecordKeyPrefix)
+        .await
+        .map(|(key, _)| key)
+        .filter(|key| std::future::ready(key.spent_at_usecs < retain_since_usecs))
+        .collect::<Vec<_>>()
+        .await;
+
+    for key in expired {
+        dbtx.remove_entry(&key).await;
+    }
+
+    let key = SpendingRecordKey {
+        spent_at_usecs: now_usecs,
+        operation_id,
+    };
+
+    let mut record = dbtx.get_value(&key).await.unwrap_or_default();
+
+    for (kind, amount) in spent {
+        *record.entry(kind).or_default() += amount;
+    }
+
+    dbtx.insert_entry(&key, &record).await;
+}
+
+fn duration_usecs(duration: Duration) -> u64 {
+    u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use fedimint_core::Amount;
+use fedimint_core::core::{ModuleKind, OperationId};
+use fedimint_core::db::mem_impl::MemDatabase;
+use fedimint_core::db::{Database, IDatabaseTransactionOpsCoreTyped as _};
+use fedimint_core::module::registry::ModuleRegistry;
+use futures::StreamExt as _;
+
+use crate::db::{SpendingLimitKey, SpendingRecordKeyPrefix};
+use crate::spending_policy::{
+    RateLimit, SpendingLimit, SpendingPolicyError, check_spending_limits, record_spending,
+};
+
+const LN: ModuleKind = ModuleKind::from_static_str("ln");
+const WALLET: ModuleKind = ModuleKind::from_static_str("wallet");
+
+const SECOND_USECS: u64 = 1_000_000;
+
+fn spent(kind: ModuleKind, sats: u64) -> BTreeMap<ModuleKind, Amount> {
+    BTreeMap::from([(kind, Amount::from_sats(sats))])
+}
+
+async fn db_with_limits(limits: Vec<(Option<ModuleKind>, SpendingLimit)>) -> Database {
+    let db = Database::new(MemDatabase::new(), ModuleRegistry::default());
+
+    let mut dbtx = db.begin_transaction().await;
+
+    for (module_kind, limit) in limits {
+        dbtx.insert_entry(&SpendingLimitKey(module_kind), &limit)
+            .await;
+    }
+
+    dbtx.commit_tx().await;
+
+    db
+}
+
+/// Checks `spent` at `now_usecs` and records it if it is allowed
+async fn spend(
+    db: &Database,
+    operation: u8,
+    spent: BTreeMap<ModuleKind, Amount>,
+    now_usecs: u64,
+) -> Result<(), SpendingPolicyError> {
+    let mut dbtx = db.begin_transaction().await;
+
+    check_spending_limits(&mut dbtx.to_ref_nc(), &spent, now_usecs).await?;
+
+    record_spending(
+        &mut dbtx.to_ref_nc(),
+        OperationId([operation; 32]),
+        spent,
+        now_usecs,
+    )
+    .await;
+
+    dbtx.commit_tx().await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn enforces_limit_per_operation() {
+    let db = db_with_limits(vec![(
+        Some(LN),
+        SpendingLimit {
+            max_per_operation: Some(Amount::from_sats(1000)),
+            rate_limit: None,
+        },
+    )])
+    .await;
+
+    spend(&db, 0, spent(LN, 1000), 0).await.unwrap();
+
+    assert_eq!(
+        spend(&db, 1, spent(LN, 1001), 0).await,
+        Err(SpendingPolicyError::OperationLimitExceeded {
+            module_kind: Some(LN),
+            amount: Amount::from_sats(1001),
+            limit: Amount::from_sats(1000),
+        })
+    );
+
+    // The limit only applies to lightning
+    spend(&db, 2, spent(WALLET, 5000), 0).await.unwrap();
+}
+
+#[tokio::test]
+async fn enforces_rate_limit_within_window() {
+    let window = Duration::from_secs(100);
+
+    let db = db_with_limits(vec![(
+        None,
+        SpendingLimit {
+            max_per_operation: None,
+            rate_limit: Some(RateLimit {
+                max_amount: Amount::from_sats(1000),
+                window,
+            }),
+        },
+    )])
+    .await;
+
+    spend(&db, 0, spent(LN, 600), 0).await.unwrap();
+
+    assert_eq!(
+        spend(&db, 1, spent(WALLET, 500), 50 * SECOND_USECS).await,
+        Err(SpendingPolicyError::RateLimitExceeded {
+            module_kind: None,
+            amount: Amount::from_sats(500),
+            spent: Amount::from_sats(600),
+            max_amount: Amount::from_sats(1000),
+            window,
+        })
+    );
+
+    spend(&db, 2, spent(WALLET, 400), 50 * SECOND_USECS)
+        .await
+        .unwrap();
+
+    // Once the first payment left the window there is room for another one
+    spend(&db, 3, spent(LN, 600), 101 * SECOND_USECS)
+        .await
+        .unwrap();
+
+    // Records outside of the window are pruned
+    let records = db
+        .begin_transaction_nc()
+        .await
+        .find_by_prefix(&SpendingRecordKeyPrefix)
+        .await
+        .map(|(key, _)| key.operation_id.0[0])
+        .collect::<Vec<_>>()
+        .await;
+
+    assert_eq!(records, vec![2, 3]);
+}
@@ -282,6 +282,39 @@ fn consensus_decode_partial<D: std::io::Read>(
     }
 }
 
+impl Encodable for lightning::offers::offer::Offer {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Error> {
+        self.to_string().consensus_encode(writer)
+    }
+}
+
+impl Decodable for lightning::offers::offer::Offer {
+    fn consensus_decode_partial<D: std::io::Read>(
+        d: &mut D,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        String::consensus_decode_partial(d, modules)?
+            .parse::<Self>()
+            .map_err(|e| DecodeError::new_custom(format_err!("Invalid BOLT12 offer: {e:?}")))
+    }
+}
+
+impl Encodable for lightning::offers::invoice::Bolt12Invoice {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Error> {
+        Writeable::encode(self).consensus_encode(writer)
+    }
+}
+
+impl Decodable for lightning::offers::invoice::Bolt12Invoice {
+    fn consensus_decode_partial<D: std::io::Read>(
+        d: &mut D,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        Self::try_from(Vec::<u8>::consensus_decode_partial(d, modules)?)
+            .map_err(|e| DecodeError::new_custom(format_err!("Invalid BOLT12 invoice: {e:?}")))
+    }
+}
+
 impl Encodable for lightning_invoice::RoutingFees {
     fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Error> {
         self.base_msat.consensus_encode(writer)?;
@@ -616,4 +649,17 @@ fn bolt11_invoice_roundtrip() {
             .unwrap();
         test_roundtrip(&invoice);
     }
+
+    #[test_log::test]
+    fn bolt12_offer_roundtrip() {
+        let signing_pubkey = bitcoin::secp256k1::SecretKey::from_slice(&[42; 32])
+            .unwrap()
+            .public_key(bitcoin::secp256k1::SECP256K1);
+        let offer = lightning::offers::offer::OfferBuilder::new(signing_pubkey)
+            .description("fedimint".to_string())
+            .amount_msats(1_000)
+            .build()
+            .unwrap();
+        test_roundtrip(&offer);
+    }
 }
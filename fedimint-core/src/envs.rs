@@ -28,6 +28,16 @@
 pub const FM_ENABLE_MODULE_WALLET_ENV: &str = "FM_ENABLE_MODULE_WALLET";
 pub const FM_ENABLE_MODULE_WALLETV2_ENV: &str = "FM_ENABLE_MODULE_WALLETV2";
 
+/// Use a taproot descriptor with threshold Schnorr signatures instead of a
+/// P2WSH multisig for the federation wallet of the walletv2 module. Only
+/// affects config generation and has to be set for all guardians.
+pub const FM_WALLETV2_TAPROOT_ENV: &str = "FM_WALLETV2_TAPROOT";
+
+/// Number of consensus blocks the walletv2 module collects peg-outs for before
+/// paying them out in a single batch transaction. Only affects config
+/// generation and has to be set to the same value for all guardians.
+pub const FM_WALLETV2_BATCH_WINDOW_ENV: &str = "FM_WALLETV2_BATCH_WINDOW";
+
 /// Disable mint base fees for testing and development environments
 pub const FM_DISABLE_BASE_FEES_ENV: &str = "FM_DISABLE_BASE_FEES";
 
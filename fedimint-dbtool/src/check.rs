@@ -0,0 +1,480 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use fedimint_client::db::{self as client_db, get_core_client_database_migrations};
+use fedimint_client::module_init::ClientModuleInitRegistry;
+use fedimint_core::config::ClientConfig;
+use fedimint_core::core::{ModuleInstanceId, ModuleKind};
+use fedimint_core::db::{
+    Database, DatabaseVersion, DatabaseVersionKey, IDatabaseTransactionOpsCore,
+    IDatabaseTransactionOpsCoreTyped, MODULE_GLOBAL_PREFIX, get_current_database_version,
+};
+use fedimint_core::encoding::Decodable;
+use fedimint_core::module::DynCommonModuleInit;
+use fedimint_core::module::registry::{ModuleDecoderRegistry, ModuleRegistry};
+use fedimint_rocksdb::RocksDbReadOnly;
+use fedimint_server::config::ServerConfig;
+use fedimint_server::consensus::db::get_global_database_migrations;
+use fedimint_server::core::ServerModuleInitRegistry;
+use fedimint_server::db as server_db;
+use futures::{FutureExt, StreamExt};
+use hex::ToHex;
+use serde::Serialize;
+use strum::IntoEnumIterator;
+
+use crate::dump::{DatabaseDump, read_configs};
+
+/// Name under which issues outside of any module are reported
+const GLOBAL_TABLE: &str = "global";
+
+/// A problem found while checking the database
+#[derive(Debug, Serialize)]
+#[serde(tag = "issue", rename_all = "snake_case")]
+enum CheckIssue {
+    /// Some entries of the table could not be decoded
+    UndecodableEntries { table: String, error: String },
+    /// Entries under a key prefix that is not used by the table
+    OrphanedPrefix {
+        table: String,
+        prefix: u8,
+        entries: usize,
+    },
+    /// Module data of a module instance that is not part of the config
+    UnknownModuleInstance {
+        module_instance_id: ModuleInstanceId,
+        entries: usize,
+    },
+    /// Key without even a prefix byte
+    EmptyKey,
+    /// Module data key that does not contain a module instance id and prefix
+    MalformedModuleKey { key: String },
+    /// The database version marker of the table is missing
+    MissingDatabaseVersion { table: String },
+    /// The database was migrated by a newer version than this tool knows
+    UnknownDatabaseVersion {
+        table: String,
+        on_disk: DatabaseVersion,
+        expected: DatabaseVersion,
+    },
+    /// The database was not yet migrated to the version of this tool, which
+    /// is expected for databases of older versions and not a corruption
+    PendingMigration {
+        table: String,
+        on_disk: DatabaseVersion,
+        expected: DatabaseVersion,
+    },
+}
+
+impl CheckIssue {
+    fn is_corruption(&self) -> bool {
+        !matches!(self, CheckIssue::PendingMigration { .. })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CheckReport {
+    entries: usize,
+    issues: Vec<CheckIssue>,
+}
+
+/// What the check expects of the data of a single module instance
+struct ModuleExpectations {
+    kind: ModuleKind,
+    init: DynCommonModuleInit,
+    database_version: DatabaseVersion,
+    used_db_prefixes: Option<BTreeSet<u8>>,
+}
+
+/// Offline integrity check of a server or client database.
+///
+/// Walks all entries of the database and reports entries that can not be
+/// decoded, entries under key prefixes that are not in use, data of module
+/// instances that are not part of the config and unexpected database version
+/// markers.
+pub struct DatabaseCheck {
+    read_only_db: Database,
+    server_cfg: Option<ServerConfig>,
+    module_inits: ServerModuleInitRegistry,
+    client_cfg: Option<ClientConfig>,
+    client_module_inits: ClientModuleInitRegistry,
+    issues: Vec<CheckIssue>,
+}
+
+impl DatabaseCheck {
+    pub async fn new(
+        cfg_dir: PathBuf,
+        data_dir: String,
+        module_inits: ServerModuleInitRegistry,
+        client_module_inits: ClientModuleInitRegistry,
+    ) -> anyhow::Result<DatabaseCheck> {
+        let Ok(read_only_rocks_db) = RocksDbReadOnly::open_read_only(data_dir).await else {
+            bail!("Error reading RocksDB database");
+        };
+
+        let read_only_db = Database::new(read_only_rocks_db, ModuleRegistry::default());
+
+        let (server_cfg, client_cfg, decoders) =
+            read_configs(&cfg_dir, &read_only_db, &module_inits, &client_module_inits).await?;
+
+        if server_cfg.is_none() && client_cfg.is_none() {
+            bail!("Only server and client databases can be checked, but no config was found");
+        }
+
+        Ok(DatabaseCheck {
+            read_only_db: read_only_db.with_decoders(decoders),
+            server_cfg,
+            module_inits,
+            client_cfg,
+            client_module_inits,
+            issues: vec![],
+        })
+    }
+
+    /// Runs all checks and prints the report as JSON. Fails if any of the
+    /// issues found indicates a corruption.
+    pub async fn check_database(self) -> anyhow::Result<()> {
+        let report = self.run().await?;
+
+        println!("{}", serde_json::to_string_pretty(&report)?);
+
+        let corrupted = report
+            .issues
+            .iter()
+            .filter(|issue| issue.is_corruption())
+            .count();
+
+        if corrupted != 0 {
+            bail!("Found {corrupted} issues indicating a corrupted database");
+        }
+
+        Ok(())
+    }
+
+    async fn run(mut self) -> anyhow::Result<CheckReport> {
+        let modules = self.module_expectations();
+
+        let entries = self.check_keys(&modules).await?;
+
+        if self.server_cfg.is_some() {
+            self.check_database_version(
+                GLOBAL_TABLE.to_string(),
+                MODULE_GLOBAL_PREFIX.into(),
+                get_current_database_version(&get_global_database_migrations()),
+            )
+            .await;
+            self.check_consensus_decodable().await;
+        } else {
+            self.check_database_version(
+                GLOBAL_TABLE.to_string(),
+                MODULE_GLOBAL_PREFIX.into(),
+                get_current_database_version(&get_core_client_database_migrations()),
+            )
+            .await;
+            self.check_client_decodable().await;
+        }
+
+        for (module_instance_id, module) in &modules {
+            let table = format!("{}-{module_instance_id}", module.kind);
+
+            self.check_database_version(
+                table.clone(),
+                *module_instance_id,
+                module.database_version,
+            )
+            .await;
+            self.check_module_decodable(table, *module_instance_id, &module.init)
+                .await;
+        }
+
+        Ok(CheckReport {
+            entries,
+            issues: self.issues,
+        })
+    }
+
+    /// Collects what to expect of every module instance in the config that
+    /// this tool has a module init for.
+    fn module_expectations(&self) -> BTreeMap<ModuleInstanceId, ModuleExpectations> {
+        let mut modules = BTreeMap::new();
+
+        if let Some(cfg) = &self.server_cfg {
+            for (module_instance_id, module_cfg) in &cfg.consensus.modules {
+                let Some(init) = self.module_inits.get(&module_cfg.kind) else {
+                    tracing::warn!(module_instance_id, kind = %module_cfg.kind, "Can not check unsupported module");
+                    continue;
+                };
+
+                modules.insert(
+                    *module_instance_id,
+                    ModuleExpectations {
+                        kind: module_cfg.kind.clone(),
+                        init: init.to_dyn_common(),
+                        database_version: get_current_database_version(
+                            &init.get_database_migrations(),
+                        ),
+                        used_db_prefixes: init.used_db_prefixes(),
+                    },
+                );
+            }
+        }
+
+        if let Some(cfg) = &self.client_cfg {
+            for (module_instance_id, module_cfg) in &cfg.modules {
+                let Some(init) = self.client_module_inits.get(&module_cfg.kind) else {
+                    tracing::warn!(module_instance_id, kind = %module_cfg.kind, "Can not check unsupported module");
+                    continue;
+                };
+
+                modules.insert(
+                    *module_instance_id,
+                    ModuleExpectations {
+                        kind: module_cfg.kind.clone(),
+                        init: init.to_dyn_common(),
+                        database_version: get_current_database_version(
+                            &init.get_database_migrations(),
+                        ),
+                        used_db_prefixes: init.used_db_prefixes(),
+                    },
+                );
+            }
+        }
+
+        modules
+    }
+
+    /// Walks all keys of the database and checks them against the prefixes
+    /// in use. Returns the number of entries in the database.
+    async fn check_keys(
+        &mut self,
+        modules: &BTreeMap<ModuleInstanceId, ModuleExpectations>,
+    ) -> anyhow::Result<usize> {
+        let mut entries = 0;
+        let mut global_prefixes = BTreeMap::<u8, usize>::new();
+        let mut module_prefixes = BTreeMap::<ModuleInstanceId, BTreeMap<u8, usize>>::new();
+
+        {
+            let mut dbtx = self.read_only_db.begin_transaction_nc().await;
+            let mut records = dbtx.raw_find_by_prefix(&[]).await?;
+
+            while let Some((key, _value)) = records.next().await {
+                entries += 1;
+
+                let Some(&prefix) = key.first() else {
+                    self.issues.push(CheckIssue::EmptyKey);
+                    continue;
+                };
+
+                if prefix != MODULE_GLOBAL_PREFIX {
+                    *global_prefixes.entry(prefix).or_default() += 1;
+                    continue;
+                }
+
+                match split_module_key(&key) {
+                    Some((module_instance_id, prefix)) => {
+                        *module_prefixes
+                            .entry(module_instance_id)
+                            .or_default()
+                            .entry(prefix)
+                            .or_default() += 1;
+                    }
+                    None => self.issues.push(CheckIssue::MalformedModuleKey {
+                        key: key.encode_hex(),
+                    }),
+                }
+            }
+        }
+
+        let used_global_prefixes = self.used_global_prefixes();
+
+        for (prefix, count) in global_prefixes {
+            if !used_global_prefixes.contains(&prefix) {
+                self.issues.push(CheckIssue::OrphanedPrefix {
+                    table: GLOBAL_TABLE.to_string(),
+                    prefix,
+                    entries: count,
+                });
+            }
+        }
+
+        for (module_instance_id, prefixes) in module_prefixes {
+            let known_instance = self
+                .server_cfg
+                .as_ref()
+                .is_some_and(|cfg| cfg.consensus.modules.contains_key(&module_instance_id))
+                || self
+                    .client_cfg
+                    .as_ref()
+                    .is_some_and(|cfg| cfg.modules.contains_key(&module_instance_id));
+
+            if !known_instance {
+                self.issues.push(CheckIssue::UnknownModuleInstance {
+                    module_instance_id,
+                    entries: prefixes.values().sum(),
+                });
+                continue;
+            }
+
+            let Some(module) = modules.get(&module_instance_id) else {
+                continue;
+            };
+
+            let Some(used_db_prefixes) = &module.used_db_prefixes else {
+                continue;
+            };
+
+            for (prefix, count) in prefixes {
+                if !used_db_prefixes.contains(&prefix) {
+                    self.issues.push(CheckIssue::OrphanedPrefix {
+                        table: format!("{}-{module_instance_id}", module.kind),
+                        prefix,
+                        entries: count,
+                    });
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn used_global_prefixes(&self) -> BTreeSet<u8> {
+        if self.server_cfg.is_some() {
+            server_db::DbKeyPrefix::iter()
+                .map(|prefix| prefix as u8)
+                .collect()
+        } else {
+            // Everything from `UserData` upwards is reserved for applications
+            // and internal use, so we can't tell what is expected there.
+            client_db::DbKeyPrefix::iter()
+                .map(|prefix| prefix as u8)
+                .chain(client_db::DbKeyPrefix::UserData as u8..=u8::MAX)
+                .collect()
+        }
+    }
+
+    async fn check_database_version(
+        &mut self,
+        table: String,
+        module_instance_id: ModuleInstanceId,
+        expected: DatabaseVersion,
+    ) {
+        let on_disk = self
+            .read_only_db
+            .begin_transaction_nc()
+            .await
+            .get_value(&DatabaseVersionKey(module_instance_id))
+            .await;
+
+        let issue = match on_disk {
+            None => CheckIssue::MissingDatabaseVersion { table },
+            Some(on_disk) if expected < on_disk => CheckIssue::UnknownDatabaseVersion {
+                table,
+                on_disk,
+                expected,
+            },
+            Some(on_disk) if on_disk < expected => CheckIssue::PendingMigration {
+                table,
+                on_disk,
+                expected,
+            },
+            Some(_) => return,
+        };
+
+        self.issues.push(issue);
+    }
+
+    async fn check_consensus_decodable(&mut self) {
+        for table in server_db::DbKeyPrefix::iter() {
+            let mut dbtx = self.read_only_db.begin_transaction_nc().await;
+            let mut serialized = BTreeMap::new();
+
+            if let Err(error) = catch_decode_panic(DatabaseDump::write_serialized_consensus_range(
+                table.clone(),
+                &mut dbtx,
+                &mut serialized,
+            ))
+            .await
+            {
+                self.issues.push(CheckIssue::UndecodableEntries {
+                    table: format!("consensus-{table}"),
+                    error,
+                });
+            }
+        }
+    }
+
+    async fn check_client_decodable(&mut self) {
+        let mut dbtx = self.read_only_db.begin_transaction_nc().await;
+        let mut serialized = BTreeMap::new();
+
+        if let Err(error) = catch_decode_panic(DatabaseDump::write_serialized_client_operation_log(
+            &mut serialized,
+            &mut dbtx,
+        ))
+        .await
+        {
+            self.issues.push(CheckIssue::UndecodableEntries {
+                table: GLOBAL_TABLE.to_string(),
+                error,
+            });
+        }
+    }
+
+    async fn check_module_decodable(
+        &mut self,
+        table: String,
+        module_instance_id: ModuleInstanceId,
+        init: &DynCommonModuleInit,
+    ) {
+        let mut dbtx = self.read_only_db.begin_transaction_nc().await;
+        let mut isolated_dbtx = dbtx.to_ref_with_prefix_module_id(module_instance_id).0;
+
+        // An empty list of prefix names makes the module decode all of its
+        // tables
+        let decode = async {
+            init.dump_database(&mut isolated_dbtx.to_ref_nc(), vec![])
+                .await
+                .for_each(drop);
+        };
+
+        if let Err(error) = catch_decode_panic(decode).await {
+            self.issues
+                .push(CheckIssue::UndecodableEntries { table, error });
+        }
+    }
+}
+
+/// Splits a key of module data into the module instance id and the key
+/// prefix used by the module
+fn split_module_key(key: &[u8]) -> Option<(ModuleInstanceId, u8)> {
+    let mut rest = key.strip_prefix(&[MODULE_GLOBAL_PREFIX])?;
+
+    let module_instance_id =
+        ModuleInstanceId::consensus_decode_partial(&mut rest, &ModuleDecoderRegistry::default())
+            .ok()?;
+
+    Some((module_instance_id, *rest.first()?))
+}
+
+/// Runs `decode` and returns the panic message if it panicked, which is how
+/// the typed database accessors react to entries they fail to decode
+///
+/// The panic hook is process global, so it is left alone and the panic is
+/// still printed to stderr in addition to being reported as an issue.
+async fn catch_decode_panic(decode: impl Future<Output = ()>) -> Result<(), String> {
+    AssertUnwindSafe(decode)
+        .catch_unwind()
+        .await
+        .map_err(|panic| {
+            panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| panic.downcast_ref::<&str>().map(ToString::to_string))
+                .unwrap_or_else(|| "Unknown error".to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests;
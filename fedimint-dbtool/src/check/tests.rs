@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+
+use fedimint_client::db::{ClientConfigKey, DbKeyPrefix};
+use fedimint_client::module_init::ClientModuleInitRegistry;
+use fedimint_core::config::{ClientConfig, GlobalClientConfig};
+use fedimint_core::db::{
+    Database, IDatabaseTransactionOpsCore as _, IDatabaseTransactionOpsCoreTyped as _,
+};
+use fedimint_core::module::CoreConsensusVersion;
+use fedimint_core::module::registry::ModuleRegistry;
+use fedimint_rocksdb::RocksDb;
+use fedimint_server::core::ServerModuleInitRegistry;
+
+use super::{CheckIssue, DatabaseCheck, GLOBAL_TABLE};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reports_corrupted_records() -> anyhow::Result<()> {
+    let cfg_dir = tempfile::tempdir()?;
+    let data_dir = tempfile::tempdir()?;
+    let db_path = data_dir.path().join("client.db");
+
+    {
+        let db = Database::new(
+            RocksDb::build(&db_path).open().await?,
+            ModuleRegistry::default(),
+        );
+        let mut dbtx = db.begin_transaction().await;
+
+        dbtx.insert_new_entry(
+            &ClientConfigKey,
+            &ClientConfig {
+                global: GlobalClientConfig {
+                    api_endpoints: BTreeMap::new(),
+                    broadcast_public_keys: None,
+                    consensus_version: CoreConsensusVersion::new(2, 1),
+                    meta: BTreeMap::new(),
+                },
+                modules: BTreeMap::new(),
+            },
+        )
+        .await;
+
+        // An operation log entry whose value is not an `OperationLogEntry`
+        let operation_log_key = [&[DbKeyPrefix::OperationLog as u8][..], &[0x42; 32]].concat();
+        dbtx.raw_insert_bytes(&operation_log_key, &[0xff]).await?;
+
+        dbtx.raw_insert_bytes(&[], &[0x00]).await?;
+
+        dbtx.commit_tx().await;
+    }
+
+    let report = DatabaseCheck::new(
+        cfg_dir.path().to_path_buf(),
+        db_path.to_str().expect("Temp path is UTF-8").to_string(),
+        ServerModuleInitRegistry::new(),
+        ClientModuleInitRegistry::new(),
+    )
+    .await?
+    .run()
+    .await?;
+
+    assert_eq!(report.entries, 3);
+
+    assert!(
+        report
+            .issues
+            .iter()
+            .any(|issue| matches!(issue, CheckIssue::EmptyKey))
+    );
+
+    assert!(report.issues.iter().any(|issue| matches!(
+        issue,
+        CheckIssue::UndecodableEntries { table, .. } if table == GLOBAL_TABLE
+    )));
+
+    assert!(report.issues.iter().all(CheckIssue::is_corruption));
+
+    Ok(())
+}
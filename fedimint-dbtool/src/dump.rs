@@ -1,5 +1,5 @@
 use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use erased_serde::Serialize;
 use fedimint_client::db::{ClientConfigKey, OperationLogKeyPrefix};
@@ -79,35 +79,8 @@ pub async fn new(
 
         let read_only_db = Database::new(read_only_rocks_db, ModuleRegistry::default());
 
-        let (server_cfg, client_cfg, decoders) = if let Ok(cfg) = read_server_config(&cfg_dir) {
-            // Successfully read the server's config, that means this database is a server
-            // db
-            let decoders = module_inits
-                .available_decoders(cfg.iter_module_instances())
-                .unwrap()
-                .with_fallback();
-            (Some(cfg), None, decoders)
-        } else {
-            // Check if this database is a client database by reading the `ClientConfig`
-            // from the database.
-
-            let mut dbtx = read_only_db.begin_transaction_nc().await;
-            let client_cfg_or = dbtx.get_value(&ClientConfigKey).await;
-
-            match client_cfg_or {
-                Some(client_cfg) => {
-                    // Successfully read the client config, that means this database is a client db
-                    let kinds = client_cfg.modules.iter().map(|(k, v)| (*k, &v.kind));
-                    let decoders = client_module_inits
-                        .available_decoders(kinds)
-                        .unwrap()
-                        .with_fallback();
-                    let client_cfg = client_cfg.redecode_raw(&decoders)?;
-                    (None, Some(client_cfg), decoders)
-                }
-                _ => (None, None, ModuleDecoderRegistry::default()),
-            }
-        };
+        let (server_cfg, client_cfg, decoders) =
+            read_configs(&cfg_dir, &read_only_db, &module_inits, &client_module_inits).await?;
 
         Ok(DatabaseDump {
             serialized: BTreeMap::new(),
@@ -122,6 +95,51 @@ pub async fn new(
     }
 }
 
+/// Detects whether `db` belongs to a server or a client by looking for the
+/// respective config and returns it together with the matching decoders.
+///
+/// Both configs are `None` for any other database, e.g. a gateway's.
+pub(crate) async fn read_configs(
+    cfg_dir: &Path,
+    db: &Database,
+    module_inits: &ServerModuleInitRegistry,
+    client_module_inits: &ClientModuleInitRegistry,
+) -> anyhow::Result<(
+    Option<ServerConfig>,
+    Option<ClientConfig>,
+    ModuleDecoderRegistry,
+)> {
+    if let Ok(cfg) = read_server_config(cfg_dir) {
+        // Successfully read the server's config, that means this database is a server
+        // db
+        let decoders = module_inits
+            .available_decoders(cfg.iter_module_instances())
+            .unwrap()
+            .with_fallback();
+        Ok((Some(cfg), None, decoders))
+    } else {
+        // Check if this database is a client database by reading the `ClientConfig`
+        // from the database.
+
+        let mut dbtx = db.begin_transaction_nc().await;
+        let client_cfg_or = dbtx.get_value(&ClientConfigKey).await;
+
+        match client_cfg_or {
+            Some(client_cfg) => {
+                // Successfully read the client config, that means this database is a client db
+                let kinds = client_cfg.modules.iter().map(|(k, v)| (*k, &v.kind));
+                let decoders = client_module_inits
+                    .available_decoders(kinds)
+                    .unwrap()
+                    .with_fallback();
+                let client_cfg = client_cfg.redecode_raw(&decoders)?;
+                Ok((None, Some(client_cfg), decoders))
+            }
+            _ => Ok((None, None, ModuleDecoderRegistry::default())),
+        }
+    }
+}
+
 impl DatabaseDump {
     /// Prints the contents of the `BTreeMap` to a pretty JSON string
     fn print_database(&self) {
@@ -266,7 +284,7 @@ async fn retrieve_consensus_data(&mut self) {
             .insert("Consensus".to_string(), Box::new(consensus));
     }
 
-    async fn write_serialized_consensus_range(
+    pub(crate) async fn write_serialized_consensus_range(
         table: server_db::DbKeyPrefix,
         dbtx: &mut DatabaseTransaction<'_>,
         consensus: &mut BTreeMap<String, Box<dyn Serialize>>,
@@ -339,7 +357,7 @@ async fn write_serialized_consensus_range(
             }
         }
     }
-    async fn write_serialized_client_operation_log(
+    pub(crate) async fn write_serialized_client_operation_log(
         serialized: &mut BTreeMap<String, Box<dyn Serialize>>,
         dbtx: &mut DatabaseTransaction<'_>,
     ) {
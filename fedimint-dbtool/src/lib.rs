@@ -6,7 +6,7 @@
 
 pub mod envs;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use bytes::Bytes;
@@ -28,10 +28,14 @@
 use futures::StreamExt;
 use hex::ToHex;
 
+use crate::check::DatabaseCheck;
 use crate::dump::DatabaseDump;
 use crate::envs::{FM_DBTOOL_CONFIG_DIR_ENV, FM_DBTOOL_DATABASE_ENV};
+use crate::snapshot::{restore_database, snapshot_database};
 
+mod check;
 mod dump;
+mod snapshot;
 
 #[derive(Debug, Clone, Parser)]
 #[command(version)]
@@ -86,6 +90,36 @@ enum DbCommand {
         #[arg(long, required = false)]
         prefixes: Option<String>,
     },
+    /// Check the integrity of a server or client database. Reports entries
+    /// that can not be decoded, orphaned key prefixes, data of unknown module
+    /// instances and unexpected database version markers as JSON and fails if
+    /// any of them indicate a corruption. Pending migrations are reported,
+    /// but do not fail the check.
+    Check {
+        #[clap(long, env = FM_DBTOOL_CONFIG_DIR_ENV)]
+        cfg_dir: PathBuf,
+    },
+    /// Create a consistent snapshot of a guardian database and write it to a
+    /// versioned archive containing checksums of all files. The guardian may
+    /// keep running while the snapshot is taken.
+    Snapshot {
+        #[clap(long, env = FM_DBTOOL_CONFIG_DIR_ENV)]
+        cfg_dir: PathBuf,
+        /// Path of the archive to create, must not exist yet
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Restore a guardian database from an archive created by `snapshot`.
+    /// The database directory must not exist yet. The archive is verified
+    /// against its checksums, the config hash of the guardian and the session
+    /// count recorded in the archive before it is moved into place.
+    Restore {
+        #[clap(long, env = FM_DBTOOL_CONFIG_DIR_ENV)]
+        cfg_dir: PathBuf,
+        /// Path of the archive to restore from
+        #[arg(long)]
+        archive: PathBuf,
+    },
 }
 
 fn hex_parser(hex: &str) -> Result<Bytes> {
@@ -146,10 +180,11 @@ pub fn with_default_modules_inits(self) -> Self {
             .with_client_module_init(MintClientInit)
             .with_client_module_init(LightningClientInit::default())
             .with_client_module_init(fedimint_lnv2_client::LightningClientInit::default())
-            .with_client_module_init(fedimint_walletv2_client::WalletClientInit)
+            .with_client_module_init(fedimint_walletv2_client::WalletClientInit::default())
             .with_client_module_init(MetaClientInit)
     }
 
+    #[allow(clippy::too_many_lines)]
     pub async fn run(&self) -> anyhow::Result<()> {
         let options = &self.cli_args;
         match &options.command {
@@ -203,17 +238,7 @@ pub async fn run(&self) -> anyhow::Result<()> {
                     None => Vec::new(),
                 };
 
-                let (module_inits, client_module_inits) = if options.no_modules {
-                    (
-                        ServerModuleInitRegistry::new(),
-                        ClientModuleInitRegistry::new(),
-                    )
-                } else {
-                    (
-                        self.server_module_inits.clone(),
-                        self.client_module_inits.clone(),
-                    )
-                };
+                let (module_inits, client_module_inits) = self.module_inits();
 
                 let mut dbdump = DatabaseDump::new(
                     cfg_dir.clone(),
@@ -226,6 +251,41 @@ pub async fn run(&self) -> anyhow::Result<()> {
                 .await?;
                 dbdump.dump_database().await?;
             }
+            DbCommand::Check { cfg_dir } => {
+                let (module_inits, client_module_inits) = self.module_inits();
+
+                DatabaseCheck::new(
+                    cfg_dir.clone(),
+                    options.database_dir.clone(),
+                    module_inits,
+                    client_module_inits,
+                )
+                .await?
+                .check_database()
+                .await?;
+            }
+            DbCommand::Snapshot { cfg_dir, out } => {
+                let (module_inits, _) = self.module_inits();
+
+                snapshot_database(
+                    cfg_dir,
+                    Path::new(&options.database_dir),
+                    out,
+                    &module_inits,
+                )
+                .await?;
+            }
+            DbCommand::Restore { cfg_dir, archive } => {
+                let (module_inits, _) = self.module_inits();
+
+                restore_database(
+                    cfg_dir,
+                    Path::new(&options.database_dir),
+                    archive,
+                    &module_inits,
+                )
+                .await?;
+            }
             DbCommand::DeletePrefix { prefix } => {
                 let rocksdb = open_db(options).await;
                 let mut dbtx = rocksdb.begin_transaction().await;
@@ -236,6 +296,20 @@ pub async fn run(&self) -> anyhow::Result<()> {
 
         Ok(())
     }
+
+    fn module_inits(&self) -> (ServerModuleInitRegistry, ClientModuleInitRegistry) {
+        if self.cli_args.no_modules {
+            (
+                ServerModuleInitRegistry::new(),
+                ClientModuleInitRegistry::new(),
+            )
+        } else {
+            (
+                self.server_module_inits.clone(),
+                self.client_module_inits.clone(),
+            )
+        }
+    }
 }
 
 async fn open_db(options: &Options) -> fedimint_core::db::Database {
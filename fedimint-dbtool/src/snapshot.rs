@@ -0,0 +1,352 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path};
+
+use anyhow::{Context, bail, ensure};
+use fedimint_core::BitcoinHash;
+use fedimint_core::bitcoin::hashes::sha256;
+use fedimint_core::db::{Database, IDatabaseTransactionOpsCore as _};
+use fedimint_core::encoding::Encodable;
+use fedimint_core::module::registry::ModuleRegistry;
+use fedimint_core::time::duration_since_epoch;
+use fedimint_rocksdb::{RocksDb, RocksDbReadOnly};
+use fedimint_server::config::ServerConfig;
+use fedimint_server::config::io::read_server_config;
+use fedimint_server::consensus::engine::get_finished_session_count_static;
+use fedimint_server::core::ServerModuleInitRegistry;
+use futures::StreamExt as _;
+use serde::{Deserialize, Serialize};
+
+/// Version of the snapshot archive layout, bumped on incompatible changes
+pub const SNAPSHOT_VERSION: u64 = 1;
+
+/// Name of the manifest, which is always the first entry of the archive
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Directory of the archive containing the database files
+const DATABASE_DIR: &str = "database";
+
+/// Number of entries copied into the snapshot database per transaction
+const COPY_BATCH_SIZE: usize = 10_000;
+
+/// Describes the database contained in a snapshot archive
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotManifest {
+    version: u64,
+    /// Unix timestamp of the snapshot in seconds
+    created_at: u64,
+    /// Hash of the consensus config of the guardian the database belongs to
+    config_hash: sha256::Hash,
+    /// Number of finished sessions in the database
+    session_count: u64,
+    /// Checksums of all database files by file name
+    files: BTreeMap<String, sha256::Hash>,
+}
+
+/// Creates a consistent snapshot of a guardian database and writes it as a
+/// checksummed tar archive to `archive`.
+///
+/// The guardian may keep running: the database is read via a RocksDB
+/// secondary instance, so the snapshot contains all sessions committed before
+/// it was started. The archive is removed again if the snapshot fails.
+pub async fn snapshot_database(
+    cfg_dir: &Path,
+    database_dir: &Path,
+    archive: &Path,
+    module_inits: &ServerModuleInitRegistry,
+) -> anyhow::Result<()> {
+    let cfg = read_server_config(cfg_dir)
+        .context("Snapshots require the config of the guardian owning the database")?;
+
+    // Create the archive first to not copy the database for nothing
+    let archive_file = File::create_new(archive)
+        .with_context(|| format!("Failed to create archive {}", archive.display()))?;
+
+    let manifest = create_snapshot(database_dir, archive_file, &cfg, module_inits)
+        .await
+        .inspect_err(|_| {
+            // A partial archive must not be mistaken for a snapshot
+            fs::remove_file(archive).ok();
+        })?;
+
+    println!("{}", serde_json::to_string_pretty(&manifest)?);
+
+    Ok(())
+}
+
+async fn create_snapshot(
+    database_dir: &Path,
+    archive: File,
+    cfg: &ServerConfig,
+    module_inits: &ServerModuleInitRegistry,
+) -> anyhow::Result<SnapshotManifest> {
+    let staging_dir = tempfile::tempdir()?;
+    let checkpoint_dir = staging_dir.path().join(DATABASE_DIR);
+
+    checkpoint_database(database_dir, &checkpoint_dir).await?;
+
+    let session_count = session_count(&checkpoint_dir, cfg, module_inits).await?;
+
+    write_archive(
+        &checkpoint_dir,
+        archive,
+        cfg.consensus.consensus_hash_sha256(),
+        session_count,
+    )
+}
+
+/// Copies all entries of the database at `database_dir`, which may be in use
+/// by a running guardian, into a new database at `checkpoint_dir`.
+///
+/// The entries are copied instead of hard linking the table files like a
+/// RocksDB checkpoint, since the running guardian may delete those files at
+/// any time. The secondary instance is never caught up with the primary again
+/// after it was opened, so the copy is a consistent view of the database.
+async fn checkpoint_database(database_dir: &Path, checkpoint_dir: &Path) -> anyhow::Result<()> {
+    let secondary_dir = tempfile::tempdir()?;
+
+    let source = Database::new(
+        RocksDbReadOnly::open_secondary(database_dir, secondary_dir.path())
+            .await
+            .context("Failed to open the database")?,
+        ModuleRegistry::default(),
+    );
+
+    let target = Database::new(
+        RocksDb::build(checkpoint_dir).open().await?,
+        ModuleRegistry::default(),
+    );
+
+    let mut source_dbtx = source.begin_transaction_nc().await;
+    let mut entries = source_dbtx.raw_find_by_prefix(&[]).await?;
+
+    let mut target_dbtx = target.begin_transaction().await;
+    let mut batch_size = 0;
+
+    while let Some((key, value)) = entries.next().await {
+        target_dbtx.raw_insert_bytes(&key, &value).await?;
+
+        batch_size += 1;
+
+        if batch_size == COPY_BATCH_SIZE {
+            target_dbtx.commit_tx_result().await?;
+            target_dbtx = target.begin_transaction().await;
+            batch_size = 0;
+        }
+    }
+
+    target_dbtx.commit_tx_result().await?;
+
+    Ok(())
+}
+
+/// Writes the database files in `checkpoint_dir` together with their
+/// manifest to `archive`
+fn write_archive(
+    checkpoint_dir: &Path,
+    archive: File,
+    config_hash: sha256::Hash,
+    session_count: u64,
+) -> anyhow::Result<SnapshotManifest> {
+    let mut files = BTreeMap::new();
+
+    for entry in fs::read_dir(&checkpoint_dir)? {
+        let entry = entry?;
+
+        ensure!(
+            entry.file_type()?.is_file(),
+            "Unexpected directory in database checkpoint: {}",
+            entry.path().display()
+        );
+
+        let name = entry.file_name().into_string().map_err(|name| {
+            anyhow::format_err!("Invalid file name in checkpoint: {}", name.display())
+        })?;
+
+        files.insert(name, hash_file(&entry.path())?);
+    }
+
+    let manifest = SnapshotManifest {
+        version: SNAPSHOT_VERSION,
+        created_at: duration_since_epoch().as_secs(),
+        config_hash,
+        session_count,
+        files,
+    };
+
+    let mut builder = tar::Builder::new(archive);
+
+    let manifest_bytes = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_bytes.len() as u64);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, MANIFEST_FILE, manifest_bytes.as_slice())?;
+
+    for name in manifest.files.keys() {
+        builder.append_path_with_name(
+            checkpoint_dir.join(name),
+            Path::new(DATABASE_DIR).join(name),
+        )?;
+    }
+
+    builder.into_inner()?.sync_all()?;
+
+    Ok(manifest)
+}
+
+/// Restores a snapshot archive created by [`snapshot_database`] to
+/// `database_dir`, which must not exist yet.
+///
+/// The archive is only moved into place after all file checksums matched and
+/// both the config hash and the session count of the restored database agree
+/// with the manifest.
+pub async fn restore_database(
+    cfg_dir: &Path,
+    database_dir: &Path,
+    archive: &Path,
+    module_inits: &ServerModuleInitRegistry,
+) -> anyhow::Result<()> {
+    let cfg = read_server_config(cfg_dir)
+        .context("Restoring requires the config of the guardian owning the database")?;
+
+    ensure!(
+        !database_dir.exists(),
+        "Database directory {} already exists, move it away before restoring",
+        database_dir.display()
+    );
+
+    // Stage next to the target so the final rename does not cross file systems
+    let parent_dir = database_dir
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let staging_dir = tempfile::tempdir_in(parent_dir)?;
+    let restored_dir = staging_dir.path().join(DATABASE_DIR);
+
+    let manifest = read_archive(
+        archive,
+        &restored_dir,
+        cfg.consensus.consensus_hash_sha256(),
+    )?;
+
+    let session_count = session_count(&restored_dir, &cfg, module_inits).await?;
+
+    ensure!(
+        session_count == manifest.session_count,
+        "Restored database has {session_count} sessions, but the manifest expects {}",
+        manifest.session_count
+    );
+
+    fs::rename(&restored_dir, database_dir)?;
+
+    println!("{}", serde_json::to_string_pretty(&manifest)?);
+
+    Ok(())
+}
+
+/// Extracts the database files of `archive` to `restored_dir` and verifies
+/// them against the checksums and the config hash of the manifest
+fn read_archive(
+    archive: &Path,
+    restored_dir: &Path,
+    config_hash: sha256::Hash,
+) -> anyhow::Result<SnapshotManifest> {
+    fs::create_dir(restored_dir)?;
+
+    let mut archive = tar::Archive::new(
+        File::open(archive)
+            .with_context(|| format!("Failed to open archive {}", archive.display()))?,
+    );
+    let mut entries = archive.entries()?;
+
+    let manifest: SnapshotManifest = {
+        let entry = entries.next().context("Archive is empty")??;
+
+        ensure!(
+            entry.path()? == Path::new(MANIFEST_FILE),
+            "Archive does not start with a manifest"
+        );
+
+        serde_json::from_reader(entry).context("Invalid manifest")?
+    };
+
+    ensure!(
+        manifest.version == SNAPSHOT_VERSION,
+        "Unsupported snapshot version {}, expected {SNAPSHOT_VERSION}",
+        manifest.version
+    );
+
+    ensure!(
+        manifest.config_hash == config_hash,
+        "Snapshot was taken with a different config, expected config hash {config_hash} but got {}",
+        manifest.config_hash
+    );
+
+    let mut files = BTreeMap::new();
+
+    for entry in entries {
+        let mut entry = entry?;
+        let name = database_file_name(&entry.path()?)?;
+
+        ensure!(
+            manifest.files.contains_key(&name),
+            "Archive contains file {name} missing from the manifest"
+        );
+
+        let path = restored_dir.join(&name);
+        io::copy(&mut entry, &mut File::create_new(&path)?)?;
+
+        files.insert(name, hash_file(&path)?);
+    }
+
+    ensure!(
+        files == manifest.files,
+        "Database files do not match the checksums of the manifest"
+    );
+
+    Ok(manifest)
+}
+
+/// Returns the file name of a database file in the archive, rejecting any
+/// other path
+fn database_file_name(path: &Path) -> anyhow::Result<String> {
+    let mut components = path.components();
+
+    match (components.next(), components.next(), components.next()) {
+        (Some(Component::Normal(dir)), Some(Component::Normal(name)), None)
+            if dir == DATABASE_DIR =>
+        {
+            name.to_str()
+                .map(ToString::to_string)
+                .context("Invalid file name in archive")
+        }
+        _ => bail!("Unexpected path in archive: {}", path.display()),
+    }
+}
+
+fn hash_file(path: &Path) -> anyhow::Result<sha256::Hash> {
+    let mut engine = sha256::Hash::engine();
+    io::copy(&mut File::open(path)?, &mut engine)?;
+    Ok(sha256::Hash::from_engine(engine))
+}
+
+async fn session_count(
+    database_dir: &Path,
+    cfg: &ServerConfig,
+    module_inits: &ServerModuleInitRegistry,
+) -> anyhow::Result<u64> {
+    let decoders = module_inits
+        .available_decoders(cfg.iter_module_instances())?
+        .with_fallback();
+
+    let db = Database::new(
+        RocksDbReadOnly::open_read_only(database_dir).await?,
+        decoders,
+    );
+
+    Ok(get_finished_session_count_static(&mut db.begin_transaction_nc().await).await)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,121 @@
+use std::fs::File;
+use std::path::Path;
+
+use fedimint_core::BitcoinHash;
+use fedimint_core::bitcoin::hashes::sha256;
+use fedimint_core::db::{Database, IDatabaseTransactionOpsCore as _};
+use fedimint_core::module::registry::ModuleRegistry;
+use fedimint_rocksdb::RocksDb;
+use futures::StreamExt as _;
+
+use super::{checkpoint_database, read_archive, write_archive};
+
+async fn read_entries(db_path: &Path) -> anyhow::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let db = Database::new(
+        RocksDb::build(db_path).open().await?,
+        ModuleRegistry::default(),
+    );
+    let mut dbtx = db.begin_transaction_nc().await;
+
+    Ok(dbtx.raw_find_by_prefix(&[]).await?.collect().await)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn snapshot_restore_roundtrip() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let db_path = dir.path().join("database");
+
+    {
+        let db = Database::new(
+            RocksDb::build(&db_path).open().await?,
+            ModuleRegistry::default(),
+        );
+        let mut dbtx = db.begin_transaction().await;
+
+        for i in 0..100u8 {
+            dbtx.raw_insert_bytes(&[0x42, i], &[i; 32]).await?;
+        }
+
+        dbtx.commit_tx().await;
+    }
+
+    let config_hash = sha256::Hash::hash(b"config");
+
+    let checkpoint_dir = dir.path().join("checkpoint");
+    checkpoint_database(&db_path, &checkpoint_dir).await?;
+
+    let archive_path = dir.path().join("snapshot.tar");
+    let manifest = write_archive(
+        &checkpoint_dir,
+        File::create_new(&archive_path)?,
+        config_hash,
+        7,
+    )?;
+
+    assert_eq!(manifest.session_count, 7);
+    assert!(!manifest.files.is_empty());
+
+    // The config hash has to match the one recorded in the manifest
+    assert!(
+        read_archive(
+            &archive_path,
+            &dir.path().join("rejected"),
+            sha256::Hash::hash(b"other config"),
+        )
+        .is_err()
+    );
+
+    let restored_path = dir.path().join("restored");
+    let restored = read_archive(&archive_path, &restored_path, config_hash)?;
+
+    assert_eq!(restored.files, manifest.files);
+
+    let entries = read_entries(&db_path).await?;
+
+    assert_eq!(entries.len(), 100);
+    assert_eq!(entries, read_entries(&restored_path).await?);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn snapshot_of_running_guardian() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let db_path = dir.path().join("database");
+
+    let db = Database::new(
+        RocksDb::build(&db_path).open().await?,
+        ModuleRegistry::default(),
+    );
+
+    let mut dbtx = db.begin_transaction().await;
+
+    for i in 0..100u8 {
+        dbtx.raw_insert_bytes(&[0x42, i], &[i; 32]).await?;
+    }
+
+    dbtx.commit_tx().await;
+
+    let checkpoint_dir = dir.path().join("checkpoint");
+    checkpoint_database(&db_path, &checkpoint_dir).await?;
+
+    // Writes after the snapshot started are not part of it
+    let mut dbtx = db.begin_transaction().await;
+    dbtx.raw_insert_bytes(&[0x43], &[0; 32]).await?;
+    dbtx.commit_tx().await;
+
+    let entries = read_entries(&checkpoint_dir).await?;
+
+    assert_eq!(entries.len(), 100);
+
+    let mut dbtx = db.begin_transaction_nc().await;
+    let live_entries = dbtx
+        .raw_find_by_prefix(&[0x42])
+        .await?
+        .collect::<Vec<_>>()
+        .await;
+
+    assert_eq!(entries, live_entries);
+
+    Ok(())
+}
@@ -0,0 +1,297 @@
+//! Export of the event log to external consumers
+//!
+//! An [`EventLogExporter`] follows the non-trimable event log from a
+//! persisted cursor and hands batches of matching entries to an
+//! [`EventLogSink`], e.g. [`JsonLinesSink`] writing newline-delimited JSON.
+//! The cursor is advanced in the same database transaction after the sink
+//! accepted a batch, so no entry is ever skipped, but after a crash the last
+//! batch might be delivered again. Consumers should deduplicate by
+//! [`PersistedLogEntry::id`].
+
+use fedimint_core::core::ModuleKind;
+use fedimint_core::db::Database;
+use fedimint_core::{apply, async_trait_maybe_send};
+use fedimint_logging::LOG_CLIENT_EVENT_LOG;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncWrite, AsyncWriteExt as _};
+use tokio::sync::watch;
+use tracing::{debug, trace};
+
+use crate::{
+    DBTransactionEventLogExt as _, DynEventLogTracker, EventKind, EventLogEntry, EventLogId,
+    PersistedLogEntry,
+};
+
+/// Default number of entries read from the log per database transaction
+pub const DEFAULT_EXPORT_BATCH_SIZE: u64 = 1_000;
+
+/// Destination for exported event log entries
+#[apply(async_trait_maybe_send!)]
+pub trait EventLogSink {
+    /// Write a batch of entries, ordered by their id
+    ///
+    /// The exporter cursor only moves past the batch once this returns
+    /// successfully, so on error the same entries will be offered again.
+    async fn write_batch(&mut self, entries: &[PersistedLogEntry]) -> anyhow::Result<()>;
+}
+
+/// [`EventLogSink`] writing every entry as a single line of JSON
+pub struct JsonLinesSink<W> {
+    writer: W,
+}
+
+impl<W> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<W> EventLogSink for JsonLinesSink<W>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    async fn write_batch(&mut self, entries: &[PersistedLogEntry]) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+
+        for entry in entries {
+            serde_json::to_writer(&mut buf, entry)?;
+            buf.push(b'\n');
+        }
+
+        self.writer.write_all(&buf).await?;
+        self.writer.flush().await?;
+
+        Ok(())
+    }
+}
+
+/// Collects the exported entries in memory, e.g. to return them from an API
+#[apply(async_trait_maybe_send!)]
+impl EventLogSink for Vec<PersistedLogEntry> {
+    async fn write_batch(&mut self, entries: &[PersistedLogEntry]) -> anyhow::Result<()> {
+        self.extend_from_slice(entries);
+
+        Ok(())
+    }
+}
+
+/// Selects which entries get exported
+///
+/// An empty list matches everything, so the default filter exports the whole
+/// log.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventLogFilter {
+    /// Only export entries of these kinds
+    #[serde(default)]
+    pub event_kinds: Vec<EventKind>,
+    /// Only export entries emitted by modules of these kinds
+    #[serde(default)]
+    pub module_kinds: Vec<ModuleKind>,
+}
+
+impl EventLogFilter {
+    pub fn with_event_kinds(mut self, event_kinds: impl IntoIterator<Item = EventKind>) -> Self {
+        self.event_kinds.extend(event_kinds);
+        self
+    }
+
+    pub fn with_module_kinds(mut self, module_kinds: impl IntoIterator<Item = ModuleKind>) -> Self {
+        self.module_kinds.extend(module_kinds);
+        self
+    }
+
+    pub fn matches(&self, entry: &EventLogEntry) -> bool {
+        let kind_matches = self.event_kinds.is_empty() || self.event_kinds.contains(&entry.kind);
+
+        let module_matches = self.module_kinds.is_empty()
+            || entry
+                .module_kind()
+                .is_some_and(|kind| self.module_kinds.contains(kind));
+
+        kind_matches && module_matches
+    }
+}
+
+/// Follows the non-trimable event log and exports it into an
+/// [`EventLogSink`]
+pub struct EventLogExporter {
+    db: Database,
+    tracker: DynEventLogTracker,
+    filter: EventLogFilter,
+    batch_size: u64,
+}
+
+impl EventLogExporter {
+    /// Create an exporter resuming from the cursor persisted by `tracker`, or
+    /// from the start of the log if there is none yet
+    pub fn new(db: Database, tracker: DynEventLogTracker) -> Self {
+        Self {
+            db,
+            tracker,
+            filter: EventLogFilter::default(),
+            batch_size: DEFAULT_EXPORT_BATCH_SIZE,
+        }
+    }
+
+    pub fn with_filter(mut self, filter: EventLogFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: u64) -> Self {
+        assert!(0 < batch_size, "Batch size must be positive");
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Position of the next entry that will be considered for export
+    pub async fn cursor(&mut self) -> anyhow::Result<EventLogId> {
+        Ok(self
+            .tracker
+            .load(&mut self.db.begin_transaction_nc().await)
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Read up to `limit` entries matching the filter past the cursor without
+    /// moving it
+    ///
+    /// Returns the entries together with the position to [`Self::ack`] once
+    /// the consumer has processed them. This is meant for consumers on the
+    /// other side of a network connection, where the entries might get lost
+    /// after they were read.
+    pub async fn read_pending(
+        &mut self,
+        limit: usize,
+    ) -> anyhow::Result<(Vec<PersistedLogEntry>, EventLogId)> {
+        let mut dbtx = self.db.begin_transaction_nc().await;
+
+        let mut pos = self.tracker.load(&mut dbtx).await?.unwrap_or_default();
+        let mut entries = Vec::new();
+
+        while entries.len() < limit {
+            let batch = dbtx.get_event_log(Some(pos), self.batch_size).await;
+
+            if batch.is_empty() {
+                break;
+            }
+
+            for entry in batch {
+                pos = entry.id().next();
+
+                if self.filter.matches(&entry) {
+                    entries.push(entry);
+
+                    if entries.len() == limit {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok((entries, pos))
+    }
+
+    /// Move the cursor to `pos` after the entries returned by
+    /// [`Self::read_pending`] were processed
+    ///
+    /// Acknowledging a position behind the cursor is a no-op, so a retried
+    /// acknowledgement never causes entries to be exported twice.
+    pub async fn ack(&mut self, pos: EventLogId) -> anyhow::Result<()> {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        let cursor = self
+            .tracker
+            .load(&mut dbtx.to_ref_nc())
+            .await?
+            .unwrap_or_default();
+
+        if cursor < pos {
+            self.tracker.store(&mut dbtx.to_ref_nc(), pos).await?;
+            dbtx.commit_tx_result().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Export all entries currently in the log past the cursor
+    ///
+    /// Returns the number of entries written to the sink.
+    pub async fn export_pending<S>(&mut self, sink: &mut S) -> anyhow::Result<usize>
+    where
+        S: EventLogSink + ?Sized,
+    {
+        let mut exported = 0;
+
+        loop {
+            let mut dbtx = self.db.begin_transaction().await;
+
+            let pos = self
+                .tracker
+                .load(&mut dbtx.to_ref_nc())
+                .await?
+                .unwrap_or_default();
+
+            let batch = dbtx.get_event_log(Some(pos), self.batch_size).await;
+
+            let Some(next_pos) = batch.last().map(|entry| entry.id().next()) else {
+                return Ok(exported);
+            };
+
+            let entries = batch
+                .into_iter()
+                .filter(|entry| self.filter.matches(entry))
+                .collect::<Vec<_>>();
+
+            trace!(
+                target: LOG_CLIENT_EVENT_LOG,
+                %pos,
+                %next_pos,
+                num = entries.len(),
+                "Exporting event log batch"
+            );
+
+            if !entries.is_empty() {
+                sink.write_batch(&entries).await?;
+            }
+
+            self.tracker.store(&mut dbtx.to_ref_nc(), next_pos).await?;
+
+            dbtx.commit_tx_result().await?;
+
+            exported += entries.len();
+        }
+    }
+
+    /// Keep exporting new entries as they get added to the log
+    ///
+    /// Returns only when `log_event_added` gets closed, which happens on
+    /// client shutdown, or on error.
+    pub async fn run<S>(
+        mut self,
+        sink: &mut S,
+        mut log_event_added: watch::Receiver<()>,
+    ) -> anyhow::Result<()>
+    where
+        S: EventLogSink + ?Sized,
+    {
+        loop {
+            // Mark the current notification as seen before reading the log,
+            // so entries added while we export are not missed.
+            log_event_added.mark_unchanged();
+
+            let exported = self.export_pending(sink).await?;
+
+            debug!(target: LOG_CLIENT_EVENT_LOG, exported, "Exported event log entries");
+
+            if log_event_added.changed().await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
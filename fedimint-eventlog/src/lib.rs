@@ -15,6 +15,8 @@
 #[cfg(feature = "uniffi")]
 ::uniffi::setup_scaffolding!();
 
+pub mod export;
+
 use std::borrow::Cow;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -287,6 +289,18 @@ pub fn to_event<E>(&self) -> Option<E>
     {
         serde_json::from_slice(&self.payload).ok()
     }
+
+    /// Like [`Self::to_event`], but only if the entry is of the kind of `E`
+    pub fn to_event_of_kind<E>(&self) -> Option<E>
+    where
+        E: Event,
+    {
+        if E::MODULE.as_ref() == self.module_kind() && E::KIND == self.kind {
+            self.to_event()
+        } else {
+            None
+        }
+    }
 }
 
 /// An `EventLogEntry` that was already persisted (so has an id)
@@ -1,10 +1,12 @@
+use std::ops::Range;
 use std::sync::Arc;
 use std::sync::atomic::AtomicU8;
 
 use anyhow::bail;
+use fedimint_core::core::ModuleKind;
 use fedimint_core::db::mem_impl::MemDatabase;
 use fedimint_core::db::{
-    DatabaseTransaction, IDatabaseTransactionOpsCoreTyped as _, IRawDatabaseExt as _,
+    Database, DatabaseTransaction, IDatabaseTransactionOpsCoreTyped as _, IRawDatabaseExt as _,
     NonCommittable,
 };
 use fedimint_core::encoding::{Decodable, Encodable};
@@ -20,7 +22,8 @@
     EventLogTrimableIdPrefixAll, TRIMABLE_EVENTLOG_MIN_ID_AGE, TRIMABLE_EVENTLOG_MIN_TS_AGE,
     handle_events, run_event_log_ordering_task, trim_trimable_log,
 };
-use crate::EventLogNonTrimableTracker;
+use crate::export::{EventLogExporter, EventLogFilter, JsonLinesSink};
+use crate::{EventLogModule, EventLogNonTrimableTracker, PersistedLogEntry};
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Encodable, Decodable)]
 pub struct TestEventLogIdKey;
@@ -198,3 +201,105 @@ async fn test_trim_trimable_log() {
         assert_eq!(remaining_ids.len(), expected_remaining);
     }
 }
+
+async fn append_export_test_entries(db: &Database, ids: Range<u64>) {
+    let mut dbtx = db.begin_transaction().await;
+
+    for i in ids {
+        let entry = EventLogEntry {
+            kind: EventKind::from(if i % 2 == 0 { "even" } else { "odd" }),
+            module: (i % 3 == 0).then(|| EventLogModule {
+                kind: ModuleKind::from_static_str("mint"),
+                id: 1,
+            }),
+            ts_usecs: i,
+            payload: serde_json::to_vec(&i).unwrap(),
+        };
+
+        dbtx.insert_entry(&EventLogId(i), &entry).await;
+    }
+
+    dbtx.commit_tx().await;
+}
+
+fn exported_ids(sink: JsonLinesSink<Vec<u8>>) -> Vec<u64> {
+    String::from_utf8(sink.into_inner())
+        .unwrap()
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<PersistedLogEntry>(line)
+                .unwrap()
+                .id()
+                .into()
+        })
+        .collect()
+}
+
+#[test_log::test(tokio::test)]
+async fn test_export_event_log() {
+    let db = MemDatabase::new().into_database();
+
+    append_export_test_entries(&db, 0..10).await;
+
+    let filter = EventLogFilter::default()
+        .with_event_kinds([EventKind::from("even")])
+        .with_module_kinds([ModuleKind::from_static_str("mint")]);
+
+    let mut exporter = EventLogExporter::new(db.clone(), Box::new(TestEventLogTracker))
+        .with_filter(filter.clone())
+        .with_batch_size(3);
+
+    let mut sink = JsonLinesSink::new(Vec::new());
+    assert_eq!(exporter.export_pending(&mut sink).await.unwrap(), 2);
+    assert_eq!(exported_ids(sink), vec![0, 6]);
+    assert_eq!(exporter.cursor().await.unwrap(), EventLogId(10));
+
+    append_export_test_entries(&db, 10..13).await;
+
+    // A new exporter resumes from the persisted cursor
+    let mut exporter =
+        EventLogExporter::new(db.clone(), Box::new(TestEventLogTracker)).with_filter(filter);
+
+    let mut sink = JsonLinesSink::new(Vec::new());
+    assert_eq!(exporter.export_pending(&mut sink).await.unwrap(), 1);
+    assert_eq!(exported_ids(sink), vec![12]);
+    assert_eq!(exporter.cursor().await.unwrap(), EventLogId(13));
+}
+
+#[test_log::test(tokio::test)]
+async fn test_read_pending_event_log() {
+    let db = MemDatabase::new().into_database();
+
+    append_export_test_entries(&db, 0..10).await;
+
+    let ids = |entries: Vec<PersistedLogEntry>| {
+        entries
+            .iter()
+            .map(|entry| u64::from(entry.id()))
+            .collect::<Vec<_>>()
+    };
+
+    let mut exporter = EventLogExporter::new(db.clone(), Box::new(TestEventLogTracker))
+        .with_filter(EventLogFilter::default().with_event_kinds([EventKind::from("even")]))
+        .with_batch_size(3);
+
+    let (entries, next_pos) = exporter.read_pending(2).await.unwrap();
+    assert_eq!(ids(entries), vec![0, 2]);
+    assert_eq!(next_pos, EventLogId(3));
+
+    // Without an acknowledgement the same entries are returned again
+    let (entries, _) = exporter.read_pending(2).await.unwrap();
+    assert_eq!(ids(entries), vec![0, 2]);
+    assert_eq!(exporter.cursor().await.unwrap(), EventLogId(0));
+
+    exporter.ack(next_pos).await.unwrap();
+
+    let (entries, next_pos) = exporter.read_pending(10).await.unwrap();
+    assert_eq!(ids(entries), vec![4, 6, 8]);
+    assert_eq!(next_pos, EventLogId(10));
+
+    // A stale acknowledgement never moves the cursor backwards
+    exporter.ack(next_pos).await.unwrap();
+    exporter.ack(EventLogId(3)).await.unwrap();
+    assert_eq!(exporter.cursor().await.unwrap(), EventLogId(10));
+}
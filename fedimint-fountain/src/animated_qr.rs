@@ -0,0 +1,299 @@
+//! Framing of fountain-encoded fragments as short strings that can be
+//! displayed as an animated sequence of QR codes.
+//!
+//! Every frame has the form `fedimint:<kind>/<seq>-<count>/<body>` where
+//! `kind` tags the type of the transported payload, `seq` is the one-based
+//! sequence number of the fragment, `count` is the number of segments the
+//! payload was split into and `body` is the base32 encoded fragment followed
+//! by a checksum over the kind and the fragment. Since fountain fragments
+//! never repeat, `seq` keeps growing past `count`.
+//!
+//! Frames are parsed case-insensitively, so they can be uppercased in order to
+//! use the more compact alphanumeric mode of QR codes.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Context, bail, ensure};
+use bitcoin_hashes::Hash;
+use fedimint_core::base32;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+
+use crate::{FountainDecoder, FountainEncoder, Fragment};
+
+/// Prefix of every animated QR frame
+pub const FRAME_PREFIX: &str = "fedimint:";
+
+/// Maximum fragment length that keeps a frame small enough to be scanned
+/// reliably from a phone screen.
+pub const DEFAULT_MAX_FRAGMENT_LENGTH: usize = 200;
+
+/// Type tag identifying the payload carried by a stream of frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PayloadKind {
+    /// Mint v2 `ECash`
+    ECash,
+    /// Legacy mint `OOBNotes`
+    OOBNotes,
+    /// A federation's `ClientConfig`
+    ClientConfig,
+    /// A `ClientBackup`
+    ClientBackup,
+}
+
+impl PayloadKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::ECash => "ecash",
+            Self::OOBNotes => "oob-notes",
+            Self::ClientConfig => "client-config",
+            Self::ClientBackup => "client-backup",
+        }
+    }
+}
+
+impl fmt::Display for PayloadKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for PayloadKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            Self::ECash,
+            Self::OOBNotes,
+            Self::ClientConfig,
+            Self::ClientBackup,
+        ]
+        .into_iter()
+        .find(|kind| kind.as_str() == s.to_lowercase())
+        .with_context(|| format!("Unknown payload kind: {s}"))
+    }
+}
+
+/// A single frame of an animated QR transmission
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    kind: PayloadKind,
+    fragment: Fragment,
+}
+
+impl Frame {
+    pub fn kind(&self) -> PayloadKind {
+        self.kind
+    }
+
+    pub fn fragment(&self) -> &Fragment {
+        &self.fragment
+    }
+
+    /// One-based sequence number of the frame within the stream
+    pub fn sequence_number(&self) -> u64 {
+        u64::from(self.fragment.index()) + 1
+    }
+
+    fn checksum(kind: PayloadKind, fragment: &Fragment) -> [u8; 4] {
+        (kind.as_str().to_owned(), fragment.clone())
+            .consensus_hash_sha256()
+            .to_byte_array()[..4]
+            .try_into()
+            .expect("Slice has length four")
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let body = (
+            self.fragment.clone(),
+            Self::checksum(self.kind, &self.fragment),
+        )
+            .consensus_encode_to_vec();
+
+        write!(
+            f,
+            "{FRAME_PREFIX}{}/{}-{}/{}",
+            self.kind,
+            self.sequence_number(),
+            self.fragment.fragment_count(),
+            base32::encode(&body)
+        )
+    }
+}
+
+impl FromStr for Frame {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+
+        let Some(s) = s.strip_prefix(FRAME_PREFIX) else {
+            bail!("Invalid frame prefix");
+        };
+
+        let [kind, sequence, body] = s.splitn(3, '/').collect::<Vec<_>>()[..] else {
+            bail!("Invalid frame structure");
+        };
+
+        let kind = kind.parse::<PayloadKind>()?;
+
+        let (sequence_number, fragment_count) =
+            sequence.split_once('-').context("Invalid frame sequence")?;
+
+        let sequence_number = sequence_number
+            .parse::<u64>()
+            .context("Invalid frame sequence number")?;
+
+        let fragment_count = fragment_count
+            .parse::<usize>()
+            .context("Invalid frame fragment count")?;
+
+        let (fragment, checksum) = <(Fragment, [u8; 4])>::consensus_decode_whole(
+            &base32::decode(body)?,
+            &ModuleDecoderRegistry::default(),
+        )
+        .context("Invalid frame body")?;
+
+        ensure!(
+            checksum == Self::checksum(kind, &fragment),
+            "Frame checksum mismatch"
+        );
+
+        let frame = Self { kind, fragment };
+
+        ensure!(
+            frame.sequence_number() == sequence_number
+                && frame.fragment.fragment_count() == fragment_count,
+            "Frame sequence does not match its body"
+        );
+
+        Ok(frame)
+    }
+}
+
+/// Encoder emitting an unbounded stream of frames for a payload
+pub struct AnimatedQrEncoder {
+    kind: PayloadKind,
+    encoder: FountainEncoder,
+}
+
+impl AnimatedQrEncoder {
+    pub fn new(kind: PayloadKind, encodable: impl Encodable, max_fragment_length: usize) -> Self {
+        Self {
+            kind,
+            encoder: FountainEncoder::new(encodable, max_fragment_length),
+        }
+    }
+
+    /// Returns the number of frames a decoder needs to receive at minimum.
+    pub fn fragment_count(&self) -> usize {
+        self.encoder.fragment_count()
+    }
+
+    /// Frames never repeat, so this can be called indefinitely
+    pub fn next_frame(&mut self) -> Frame {
+        Frame {
+            kind: self.kind,
+            fragment: self.encoder.next_fragment(),
+        }
+    }
+}
+
+/// Decoder reassembling a payload of a given kind from scanned frames
+pub struct AnimatedQrDecoder<E: Decodable> {
+    kind: PayloadKind,
+    decoder: FountainDecoder<E>,
+}
+
+impl<E: Decodable> AnimatedQrDecoder<E> {
+    pub fn new(kind: PayloadKind, decoders: ModuleDecoderRegistry) -> Self {
+        Self {
+            kind,
+            decoder: FountainDecoder::new(decoders),
+        }
+    }
+
+    /// Add a scanned frame. Returns Some(E) when decoding is complete. Frames
+    /// tagged with a different payload kind are rejected without affecting
+    /// the progress made so far.
+    pub fn add_frame(&mut self, frame: &Frame) -> anyhow::Result<Option<E>> {
+        ensure!(
+            frame.kind == self.kind,
+            "Expected a frame of kind {} but received {}",
+            self.kind,
+            frame.kind
+        );
+
+        Ok(self.decoder.add_fragment(&frame.fragment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_string_roundtrip() {
+        let payload = (0..1000).map(|i| i as u8).collect::<Vec<u8>>();
+
+        let mut encoder = AnimatedQrEncoder::new(PayloadKind::ECash, &payload, 100);
+        let mut decoder =
+            AnimatedQrDecoder::<Vec<u8>>::new(PayloadKind::ECash, ModuleDecoderRegistry::default());
+
+        for _ in 0..100 {
+            let frame = encoder.next_frame();
+
+            assert!(frame.to_string().starts_with("fedimint:ecash/"));
+
+            let parsed = frame.to_string().to_uppercase().parse::<Frame>().unwrap();
+
+            assert_eq!(parsed, frame);
+
+            if let Some(decoded) = decoder.add_frame(&parsed).unwrap() {
+                assert_eq!(decoded, payload);
+                return;
+            }
+        }
+
+        panic!("Decoder did not decode the payload within 100 frames");
+    }
+
+    #[test]
+    fn test_frame_kind_mismatch() {
+        let mut encoder = AnimatedQrEncoder::new(PayloadKind::OOBNotes, b"foo".to_vec(), 2);
+        let mut decoder = AnimatedQrDecoder::<Vec<u8>>::new(
+            PayloadKind::ClientConfig,
+            ModuleDecoderRegistry::default(),
+        );
+
+        assert!(decoder.add_frame(&encoder.next_frame()).is_err());
+    }
+
+    #[test]
+    fn test_frame_corruption() {
+        let mut encoder = AnimatedQrEncoder::new(PayloadKind::ClientBackup, b"foo".to_vec(), 2);
+
+        let frame = encoder.next_frame().to_string();
+
+        let (header, body) = frame.rsplit_once('/').unwrap();
+        let flipped = if body.starts_with('0') { '1' } else { '0' };
+
+        assert!(
+            format!("{header}/{flipped}{}", &body[1..])
+                .parse::<Frame>()
+                .is_err()
+        );
+
+        assert!(
+            frame
+                .replace("client-backup", "client-config")
+                .parse::<Frame>()
+                .is_err()
+        );
+
+        assert!(frame.replace("/1-", "/2-").parse::<Frame>().is_err());
+    }
+}
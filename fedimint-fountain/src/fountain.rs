@@ -56,6 +56,11 @@ pub fn new(message: &[u8], max_fragment_length: usize) -> Self {
         }
     }
 
+    /// Returns the number of segments the message was partitioned into.
+    pub fn fragment_count(&self) -> usize {
+        self.fragments.len()
+    }
+
     /// Returns the next fragment to be emitted by the fountain encoder.
     /// After all fragments of the original message have been emitted once,
     /// the fountain encoder will emit the result of xoring together the
@@ -168,6 +173,16 @@ pub struct Fragment {
 }
 
 impl Fragment {
+    /// Returns the position of this fragment in the emitted stream.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Returns the number of segments the message was partitioned into.
+    pub fn fragment_count(&self) -> usize {
+        self.meta.simple_fragments()
+    }
+
     /// Returns the indexes of the message segments that were combined.
     pub fn indexes(&self) -> Vec<usize> {
         choose_fragments(
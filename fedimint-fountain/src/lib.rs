@@ -4,11 +4,13 @@
 //! payload segments, or constructed by xor-ing a certain set of payload
 //! segments.
 
+pub mod animated_qr;
 mod fountain;
 
 use std::marker::PhantomData;
 
 use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
 pub use fountain::Fragment;
 
 pub struct FountainEncoder {
@@ -25,6 +27,12 @@ pub fn new(encodable: impl Encodable, max_fragment_length: usize) -> Self {
         }
     }
 
+    /// Returns the number of fragments the payload was split into, which is
+    /// the minimum number of fragments a decoder needs to receive.
+    pub fn fragment_count(&self) -> usize {
+        self.encoder.fragment_count()
+    }
+
     /// Fragments never repeat, so this can be called indefinitely
     pub fn next_fragment(&mut self) -> Fragment {
         self.encoder.next_fragment()
@@ -34,19 +42,27 @@ pub fn next_fragment(&mut self) -> Fragment {
 /// Decoder for fountain-encoded encodable types
 pub struct FountainDecoder<E: Decodable> {
     decoder: fountain::Decoder,
+    decoders: ModuleDecoderRegistry,
     _pd: PhantomData<E>,
 }
 
 impl<E: Decodable> Default for FountainDecoder<E> {
     fn default() -> Self {
+        Self::new(ModuleDecoderRegistry::default())
+    }
+}
+
+impl<E: Decodable> FountainDecoder<E> {
+    /// Creates a decoder using `decoders` to decode the reassembled payload,
+    /// which is required for payloads containing module specific types.
+    pub fn new(decoders: ModuleDecoderRegistry) -> Self {
         Self {
             decoder: fountain::Decoder::default(),
+            decoders,
             _pd: PhantomData,
         }
     }
-}
 
-impl<E: Decodable> FountainDecoder<E> {
     /// Add a scanned fragment. Returns Some(E) when decoding is complete. If we
     /// receive an invalid fragment, possibly belonging to a different fountain
     /// encoding, the decoder is reset.
@@ -56,7 +72,7 @@ pub fn add_fragment(&mut self, fragment: &Fragment) -> Option<E> {
             .receive(fragment.clone())
             .transpose()? // The fragment is valid but the decoding is not yet complete
             .ok()
-            .map(|b| Decodable::consensus_decode_whole(&b, &Default::default()).ok())
+            .map(|b| Decodable::consensus_decode_whole(&b, &self.decoders).ok())
         {
             return Some(d);
         }
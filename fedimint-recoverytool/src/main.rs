@@ -1,16 +1,20 @@
 #![deny(clippy::pedantic)]
 
 mod key;
+mod psbt;
+mod walletv2;
 
 use std::collections::BTreeSet;
 use std::path::{Path, PathBuf};
 
-use anyhow::{Context, anyhow};
-use bitcoin::OutPoint;
+use anyhow::{Context, anyhow, bail};
+use bitcoin::address::NetworkUnchecked;
 use bitcoin::network::Network;
+use bitcoin::psbt::Psbt;
 use bitcoin::secp256k1::{PublicKey, SECP256K1, SecretKey};
+use bitcoin::{Address, OutPoint};
 use clap::{ArgGroup, Parser, Subcommand};
-use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::core::{ModuleInstanceId, ModuleKind};
 use fedimint_core::db::{Database, IDatabaseTransactionOpsCoreTyped};
 use fedimint_core::epoch::ConsensusItem;
 use fedimint_core::fedimint_build_code_version_env;
@@ -40,13 +44,16 @@
 use tracing::info;
 
 use crate::key::Key;
+use crate::psbt::{
+    SweepInput, SweepPsbt, SweepScript, combine_sweep_psbts, create_sweep_psbt, psbt_parser,
+    sign_sweep_psbt,
+};
 
 /// Tool to recover the on-chain wallet of a Fedimint federation
 #[derive(Debug, Parser)]
 #[command(version)]
 #[command(group(
     ArgGroup::new("keysource")
-        .required(true)
         .args(["config", "descriptor"]),
 ))]
 struct RecoveryTool {
@@ -92,6 +99,40 @@ enum TweakSource {
         #[arg(long)]
         db: PathBuf,
     },
+    /// Create an unsigned PSBT sweeping all confirmed UTXOs of the on-chain
+    /// wallet to a single address, to be signed by the guardians with `sign`
+    Sweep {
+        /// Extract UTXOs from a database without module partitioning
+        #[arg(long)]
+        legacy: bool,
+        /// Path to database
+        #[arg(long)]
+        db: PathBuf,
+        /// Address receiving the swept funds
+        #[arg(long)]
+        address: Address<NetworkUnchecked>,
+        /// Fee rate of the sweep transaction in sat/vB
+        #[arg(long)]
+        fee_rate: u64,
+    },
+    /// Sign a sweep PSBT with the key of this guardian
+    Sign {
+        /// Base64 encoded PSBT
+        #[arg(long, value_parser = psbt_parser)]
+        psbt: Psbt,
+    },
+    /// Combine sweep PSBTs signed by different guardians, the final transaction
+    /// is included once enough guardians signed. Requires no keys.
+    Combine {
+        /// Base64 encoded PSBT, repeat for every signed PSBT
+        #[arg(long = "psbt", value_parser = psbt_parser, required = true)]
+        psbts: Vec<Psbt>,
+    },
+    /// Recover the on-chain wallet of the walletv2 module, requires --cfg
+    Walletv2 {
+        #[command(subcommand)]
+        command: walletv2::Walletv2Command,
+    },
 }
 
 fn tweak_parser(hex: &str) -> anyhow::Result<[u8; 33]> {
@@ -100,20 +141,20 @@ enum TweakSource {
         .map_err(|_| anyhow!("tweaks have to be 33 bytes long"))
 }
 
-/// Get the wallet module instance ID from a server config by looking up the
+/// Get the instance ID of a module from a server config by looking up the
 /// module kind
-fn get_wallet_module_id(cfg: &ServerConfig) -> anyhow::Result<ModuleInstanceId> {
+fn get_module_id(cfg: &ServerConfig, kind: &ModuleKind) -> anyhow::Result<ModuleInstanceId> {
     cfg.consensus
         .modules
         .iter()
         .find_map(|(id, module_cfg)| {
-            if module_cfg.kind == WalletCommonInit::KIND {
+            if &module_cfg.kind == kind {
                 Some(*id)
             } else {
                 None
             }
         })
-        .context("Wallet module not found in config")
+        .with_context(|| format!("Module {kind} not found in config"))
 }
 
 async fn get_db(path: &Path, module_decoders: ModuleDecoderRegistry) -> Database {
@@ -133,10 +174,28 @@ async fn main() -> anyhow::Result<()> {
 
     let opts: RecoveryTool = RecoveryTool::parse();
 
+    if let TweakSource::Combine { psbts } = &opts.strategy {
+        let mut psbt = combine_sweep_psbts(psbts.clone())?;
+
+        walletv2::aggregate_signature_shares(&mut psbt)?;
+
+        return SweepPsbt::new(&psbt)?.print();
+    }
+
+    if let TweakSource::Walletv2 { command } = &opts.strategy {
+        let config = opts
+            .config
+            .as_ref()
+            .context("Recovering walletv2 requires --cfg")?;
+        let cfg = read_server_config(config).context("Could not read config file")?;
+
+        return walletv2::run(command, &cfg).await;
+    }
+
     let (base_descriptor, base_key, network, wallet_module_id) = if let Some(config) = opts.config {
         let cfg = read_server_config(&config).expect("Could not read config file");
-        let wallet_module_id =
-            get_wallet_module_id(&cfg).expect("Wallet module not found in config");
+        let wallet_module_id = get_module_id(&cfg, &WalletCommonInit::KIND)
+            .expect("Wallet module not found in config");
         let wallet_cfg: WalletConfig = cfg
             .get_module_config_typed(wallet_module_id)
             .expect("Malformed wallet config");
@@ -151,7 +210,7 @@ async fn main() -> anyhow::Result<()> {
         // prefix/decoder matching which isn't needed with direct descriptor usage.
         (descriptor, key, opts.network, 0)
     } else {
-        panic!("Either config or descriptor need to be provided by clap");
+        bail!("Either --cfg or --descriptor together with --key is required");
     };
 
     process_and_print_tweak_source(
@@ -161,18 +220,17 @@ async fn main() -> anyhow::Result<()> {
         network,
         wallet_module_id,
     )
-    .await;
-
-    Ok(())
+    .await
 }
 
+#[allow(clippy::too_many_lines)]
 async fn process_and_print_tweak_source(
     tweak_source: &TweakSource,
     base_descriptor: &Descriptor<CompressedPublicKey>,
     base_key: &SecretKey,
     network: Network,
     wallet_module_id: ModuleInstanceId,
-) {
+) -> anyhow::Result<()> {
     match tweak_source {
         TweakSource::Direct { tweak } => {
             let descriptor = tweak_descriptor(base_descriptor, base_key, tweak, network);
@@ -182,20 +240,10 @@ async fn process_and_print_tweak_source(
                 .expect("Could not encode to stdout");
         }
         TweakSource::Utxos { legacy, db } => {
-            let db = get_db(db, ModuleRegistry::default()).await;
-
-            let db = if *legacy {
-                db
-            } else {
-                db.with_prefix_module_id(wallet_module_id).0
-            };
-
-            let utxos: Vec<ImportableWallet> = db
-                .begin_transaction_nc()
-                .await
-                .find_by_prefix(&UTXOPrefixKey)
+            let utxos: Vec<ImportableWallet> = read_utxos(db, *legacy, wallet_module_id)
                 .await
-                .map(|(UTXOKey(outpoint), SpendableUTXO { tweak, amount })| {
+                .into_iter()
+                .map(|(outpoint, SpendableUTXO { tweak, amount })| {
                     let descriptor = tweak_descriptor(base_descriptor, base_key, &tweak, network);
 
                     ImportableWallet {
@@ -204,12 +252,41 @@ async fn process_and_print_tweak_source(
                         amount_sat: amount,
                     }
                 })
-                .collect()
-                .await;
+                .collect();
 
             serde_json::to_writer(std::io::stdout().lock(), &utxos)
                 .expect("Could not encode to stdout");
         }
+        TweakSource::Sweep {
+            legacy,
+            db,
+            address,
+            fee_rate,
+        } => {
+            let address = address
+                .clone()
+                .require_network(network)
+                .context("Address does not match the network of the federation")?;
+
+            let inputs = read_utxos(db, *legacy, wallet_module_id)
+                .await
+                .into_iter()
+                .map(|(outpoint, utxo)| sweep_input(base_descriptor, outpoint, &utxo))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            let psbt = create_sweep_psbt(inputs, address.script_pubkey(), *fee_rate)?;
+
+            SweepPsbt::new(&psbt)?.print()?;
+        }
+        TweakSource::Sign { psbt } => {
+            let mut psbt = psbt.clone();
+
+            sign_sweep_psbt(&mut psbt, |tweak| {
+                signing_key(base_descriptor, base_key, tweak)
+            })?;
+
+            SweepPsbt::new(&psbt)?.print()?;
+        }
         TweakSource::Epochs { db } => {
             let decoders = ModuleDecoderRegistry::from_iter([(
                 wallet_module_id,
@@ -271,7 +348,35 @@ async fn process_and_print_tweak_source(
             serde_json::to_writer(std::io::stdout().lock(), &wallets)
                 .expect("Could not encode to stdout");
         }
+        TweakSource::Combine { .. } | TweakSource::Walletv2 { .. } => {
+            unreachable!("Handled before reading the legacy wallet config")
+        }
     }
+
+    Ok(())
+}
+
+/// Returns the confirmed UTXOs of the on-chain wallet
+async fn read_utxos(
+    db: &Path,
+    legacy: bool,
+    wallet_module_id: ModuleInstanceId,
+) -> Vec<(OutPoint, SpendableUTXO)> {
+    let db = get_db(db, ModuleRegistry::default()).await;
+
+    let db = if legacy {
+        db
+    } else {
+        db.with_prefix_module_id(wallet_module_id).0
+    };
+
+    db.begin_transaction_nc()
+        .await
+        .find_by_prefix(&UTXOPrefixKey)
+        .await
+        .map(|(UTXOKey(outpoint), utxo)| (outpoint, utxo))
+        .collect()
+        .await
 }
 
 fn input_tweaks_and_peg_out_count(
@@ -333,6 +438,46 @@ fn tweak_descriptor(
         .expect("can't fail")
 }
 
+/// Single guardian federations use a P2WPKH descriptor, all others a P2WSH
+/// multisig descriptor
+fn sweep_script(descriptor: &PegInDescriptor) -> anyhow::Result<SweepScript> {
+    match descriptor {
+        Descriptor::Wpkh(..) => Ok(SweepScript::Wpkh(descriptor.script_pubkey())),
+        Descriptor::Wsh(wsh) => Ok(SweepScript::Wsh(wsh.inner_script())),
+        _ => bail!("Unsupported wallet descriptor {descriptor}"),
+    }
+}
+
+fn sweep_input(
+    base_descriptor: &PegInDescriptor,
+    outpoint: OutPoint,
+    utxo: &SpendableUTXO,
+) -> anyhow::Result<SweepInput> {
+    let descriptor = base_descriptor.tweak(&utxo.tweak, SECP256K1);
+
+    Ok(SweepInput {
+        outpoint,
+        value: utxo.amount,
+        script: sweep_script(&descriptor)?,
+        max_weight_to_satisfy: descriptor
+            .max_weight_to_satisfy()
+            .context("Wallet descriptor is not satisfiable")?,
+        tweak: utxo.tweak.to_vec(),
+    })
+}
+
+fn signing_key(
+    base_descriptor: &PegInDescriptor,
+    base_sk: &SecretKey,
+    tweak: &[u8],
+) -> anyhow::Result<(SweepScript, SecretKey)> {
+    let tweak: [u8; 33] = tweak.try_into().context("Malformed wallet tweak")?;
+
+    let script = sweep_script(&base_descriptor.tweak(&tweak, SECP256K1))?;
+
+    Ok((script, base_sk.tweak(&tweak, SECP256K1)))
+}
+
 /// A UTXO with its Bitcoin Core importable descriptor
 #[derive(Debug, Serialize)]
 struct ImportableWallet {
@@ -408,3 +553,46 @@ fn parses_valid_length_tweaks() {
     );
     assert!(tweak_parser(bad_length_tweak_hex.as_str()).is_err());
 }
+
+#[test]
+fn sweeps_single_guardian_wallet() {
+    use bitcoin::hashes::Hash as _;
+    use miniscript::descriptor::Wpkh;
+    use miniscript::psbt::PsbtExt;
+
+    let sk = SecretKey::from_slice(&rand::random::<[u8; 32]>()).expect("Valid secret key");
+
+    let descriptor = PegInDescriptor::Wpkh(
+        Wpkh::new(CompressedPublicKey::new(sk.public_key(SECP256K1)))
+            .expect("Our key type is always compressed"),
+    );
+
+    let utxo = SpendableUTXO {
+        tweak: SecretKey::from_slice(&rand::random::<[u8; 32]>())
+            .expect("Valid secret key")
+            .public_key(SECP256K1)
+            .serialize(),
+        amount: bitcoin::Amount::from_sat(100_000),
+    };
+
+    let outpoint = OutPoint {
+        txid: bitcoin::Txid::all_zeros(),
+        vout: 0,
+    };
+
+    let input = sweep_input(&descriptor, outpoint, &utxo).expect("Descriptor is supported");
+
+    let destination = descriptor.script_pubkey();
+
+    let mut psbt = create_sweep_psbt(vec![input], destination, 10).expect("Failed to create sweep");
+
+    sign_sweep_psbt(&mut psbt, |tweak| signing_key(&descriptor, &sk, tweak))
+        .expect("Failed to sign");
+
+    let tx = psbt
+        .finalize(SECP256K1)
+        .expect("Single signature is sufficient")
+        .extract_tx_unchecked_fee_rate();
+
+    assert_eq!(tx.input.len(), 1);
+}
@@ -0,0 +1,301 @@
+//! Threshold signed sweep of the federation's on-chain funds
+//!
+//! One guardian creates an unsigned sweep PSBT from the UTXO set, every
+//! guardian signs it offline with the key from their own config and the PSBTs
+//! are combined once enough of them were signed. The tweak of every input is
+//! part of the PSBT, so signing requires neither the database nor any other
+//! guardian's key and no secret key ever has to be exported. Walletv2
+//! federations using the taproot descriptor sign in two rounds instead, see
+//! [`crate::walletv2`].
+
+use anyhow::{Context, bail, ensure};
+use base64::Engine as _;
+use bitcoin::hashes::Hash as _;
+use bitcoin::psbt::{Psbt, raw};
+use bitcoin::secp256k1::{SECP256K1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use bitcoin::transaction::Version;
+use bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Weight};
+use fedimint_core::weight_to_vbytes;
+use miniscript::psbt::PsbtExt;
+use serde::Serialize;
+use tracing::info;
+
+/// The script a federation UTXO is locked to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SweepScript {
+    /// The P2WPKH script pubkey of a single guardian federation
+    Wpkh(ScriptBuf),
+    /// The P2WSH witness script of a multisig federation
+    Wsh(ScriptBuf),
+    /// The P2TR script pubkey of a walletv2 federation using the taproot
+    /// descriptor, which is spent via the key path
+    Tr(ScriptBuf),
+}
+
+impl SweepScript {
+    fn script_pubkey(&self) -> ScriptBuf {
+        match self {
+            SweepScript::Wpkh(script_pubkey) | SweepScript::Tr(script_pubkey) => {
+                script_pubkey.clone()
+            }
+            SweepScript::Wsh(witness_script) => {
+                ScriptBuf::new_p2wsh(&witness_script.wscript_hash())
+            }
+        }
+    }
+
+    fn witness_script(&self) -> Option<ScriptBuf> {
+        match self {
+            SweepScript::Wpkh(..) | SweepScript::Tr(..) => None,
+            SweepScript::Wsh(witness_script) => Some(witness_script.clone()),
+        }
+    }
+}
+
+/// A federation UTXO to be swept
+#[derive(Debug, Clone)]
+pub struct SweepInput {
+    pub outpoint: OutPoint,
+    pub value: Amount,
+    /// The script of the tweaked federation descriptor
+    pub script: SweepScript,
+    /// Upper bound of the witness weight once the input is signed
+    pub max_weight_to_satisfy: Weight,
+    /// The tweak the guardians need to derive their key for this input
+    pub tweak: Vec<u8>,
+}
+
+/// A sweep PSBT as printed by all PSBT commands
+#[derive(Debug, Serialize)]
+pub struct SweepPsbt {
+    /// Base64 encoded PSBT
+    psbt: String,
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    fee_sat: Amount,
+    /// Hex encoded final transaction, present once enough guardians signed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tx: Option<String>,
+}
+
+impl SweepPsbt {
+    pub fn new(psbt: &Psbt) -> anyhow::Result<Self> {
+        let tx = psbt
+            .clone()
+            .finalize(SECP256K1)
+            .ok()
+            .map(Psbt::extract_tx_unchecked_fee_rate)
+            .map(|tx| bitcoin::consensus::encode::serialize_hex(&tx));
+
+        Ok(SweepPsbt {
+            psbt: base64::engine::general_purpose::STANDARD.encode(psbt.serialize()),
+            fee_sat: psbt.fee().context("PSBT is missing UTXO information")?,
+            tx,
+        })
+    }
+
+    pub fn print(&self) -> anyhow::Result<()> {
+        serde_json::to_writer(std::io::stdout().lock(), self)?;
+
+        Ok(())
+    }
+}
+
+/// Parses a base64 encoded PSBT
+pub fn psbt_parser(psbt: &str) -> anyhow::Result<Psbt> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(psbt.trim())?;
+
+    Ok(Psbt::deserialize(&bytes)?)
+}
+
+/// Key of a proprietary PSBT field of the recovery tool
+pub fn proprietary_key(subtype: u8, key: Vec<u8>) -> raw::ProprietaryKey {
+    raw::ProprietaryKey {
+        prefix: b"fedimint".to_vec(),
+        subtype,
+        key,
+    }
+}
+
+/// Key of the proprietary PSBT input field holding the tweak of the input
+pub fn proprietary_tweak_key() -> raw::ProprietaryKey {
+    proprietary_key(0x00, vec![])
+}
+
+/// Returns the UTXOs spent by the inputs of the PSBT
+fn prevouts(psbt: &Psbt) -> anyhow::Result<Vec<TxOut>> {
+    psbt.inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            input
+                .witness_utxo
+                .clone()
+                .with_context(|| format!("Input {index} is missing its UTXO"))
+        })
+        .collect()
+}
+
+/// Returns the taproot key path sighashes of all inputs, which commit to the
+/// UTXOs of all inputs
+pub fn taproot_sighashes(psbt: &Psbt) -> anyhow::Result<Vec<[u8; 32]>> {
+    let prevouts = prevouts(psbt)?;
+
+    let mut sighash_cache = SighashCache::new(&psbt.unsigned_tx);
+
+    (0..psbt.inputs.len())
+        .map(|index| {
+            Ok(sighash_cache
+                .taproot_key_spend_signature_hash(
+                    index,
+                    &Prevouts::All(&prevouts),
+                    TapSighashType::Default,
+                )
+                .context("Failed to compute taproot sighash")?
+                .to_byte_array())
+        })
+        .collect()
+}
+
+/// Creates an unsigned PSBT spending all `inputs` to `destination`
+pub fn create_sweep_psbt(
+    inputs: Vec<SweepInput>,
+    destination: ScriptBuf,
+    fee_rate: u64,
+) -> anyhow::Result<Psbt> {
+    ensure!(!inputs.is_empty(), "There are no UTXOs to sweep");
+
+    let mut tx = Transaction {
+        version: Version(2),
+        lock_time: bitcoin::absolute::LockTime::ZERO,
+        input: inputs
+            .iter()
+            .map(|input| TxIn {
+                previous_output: input.outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: bitcoin::Witness::new(),
+            })
+            .collect(),
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: destination,
+        }],
+    };
+
+    let satisfaction_weight = inputs
+        .iter()
+        .map(|input| input.max_weight_to_satisfy.to_wu())
+        .sum::<u64>();
+
+    // The segwit marker and flag are not part of the unsigned weight
+    let vbytes = weight_to_vbytes(tx.weight().to_wu() + 2 + satisfaction_weight);
+
+    let fee = fee_rate
+        .checked_mul(vbytes)
+        .map(Amount::from_sat)
+        .context("Fee rate is too high")?;
+
+    let total = inputs.iter().map(|input| input.value).sum::<Amount>();
+
+    tx.output[0].value = total
+        .checked_sub(fee)
+        .context("Fee exceeds the value of all UTXOs")?;
+
+    ensure!(
+        tx.output[0].value >= tx.output[0].script_pubkey.minimal_non_dust(),
+        "Swept value of {} is below the dust limit",
+        tx.output[0].value
+    );
+
+    let mut psbt = Psbt::from_unsigned_tx(tx)?;
+
+    for (psbt_input, input) in psbt.inputs.iter_mut().zip(inputs) {
+        psbt_input.witness_utxo = Some(TxOut {
+            value: input.value,
+            script_pubkey: input.script.script_pubkey(),
+        });
+        psbt_input.witness_script = input.script.witness_script();
+        psbt_input
+            .proprietary
+            .insert(proprietary_tweak_key(), input.tweak);
+    }
+
+    info!(%fee, vbytes, "Created sweep transaction");
+
+    Ok(psbt)
+}
+
+/// Adds the signatures of this guardian to all inputs of the PSBT
+///
+/// `derive_key` returns the expected script and the secret key of this guardian
+/// for the tweak of an input. Signing fails if any input does not belong to the
+/// federation wallet.
+pub fn sign_sweep_psbt(
+    psbt: &mut Psbt,
+    derive_key: impl Fn(&[u8]) -> anyhow::Result<(SweepScript, SecretKey)>,
+) -> anyhow::Result<()> {
+    for output in &psbt.unsigned_tx.output {
+        info!(
+            script_pubkey = %output.script_pubkey,
+            value = %output.value,
+            "Signing sweep output"
+        );
+    }
+
+    let prevouts = prevouts(psbt)?;
+
+    let mut sighash_cache = SighashCache::new(&psbt.unsigned_tx);
+
+    for (index, input) in psbt.inputs.iter_mut().enumerate() {
+        let Some(tweak) = input.proprietary.get(&proprietary_tweak_key()) else {
+            bail!("Input {index} is missing its tweak");
+        };
+
+        let (script, sk) = derive_key(tweak)?;
+
+        let utxo = &prevouts[index];
+
+        ensure!(
+            input.witness_script == script.witness_script()
+                && utxo.script_pubkey == script.script_pubkey(),
+            "Input {index} does not belong to the federation wallet"
+        );
+
+        let sighash = match &script {
+            SweepScript::Wpkh(script_pubkey) => sighash_cache
+                .p2wpkh_signature_hash(index, script_pubkey, utxo.value, EcdsaSighashType::All)
+                .context("Failed to compute P2WPKH segwit sighash")?,
+            SweepScript::Wsh(witness_script) => sighash_cache
+                .p2wsh_signature_hash(index, witness_script, utxo.value, EcdsaSighashType::All)
+                .context("Failed to compute P2WSH segwit sighash")?,
+            SweepScript::Tr(..) => {
+                bail!(
+                    "Input {index} is spent via the taproot key path, which requires FROST signing"
+                )
+            }
+        };
+
+        input.partial_sigs.insert(
+            bitcoin::PublicKey::new(sk.public_key(SECP256K1)),
+            bitcoin::ecdsa::Signature::sighash_all(SECP256K1.sign_ecdsa(&sighash.into(), &sk)),
+        );
+    }
+
+    Ok(())
+}
+
+/// Merges the signatures of PSBTs signed by different guardians
+pub fn combine_sweep_psbts(psbts: Vec<Psbt>) -> anyhow::Result<Psbt> {
+    let mut psbts = psbts.into_iter();
+
+    let mut combined = psbts.next().context("No PSBT to combine")?;
+
+    for psbt in psbts {
+        combined
+            .combine(psbt)
+            .context("PSBTs do not spend the same transaction")?;
+    }
+
+    Ok(combined)
+}
@@ -0,0 +1,964 @@
+//! Recovery of the on-chain wallet of the walletv2 module
+//!
+//! Unlike the legacy wallet, walletv2 keeps its funds in a single federation
+//! UTXO which every peg-in and peg-out spends into a chain of transactions.
+//! The tweaks of all UTXOs are stored in the database, so we can derive the
+//! spending keys directly from the guardian config and either export them as
+//! descriptors or sign a sweep transaction right away.
+//!
+//! The guardians of a federation using the taproot descriptor hold shares of a
+//! single key instead, which can not be exported as a multisig descriptor. Its
+//! funds are swept with the two rounds of FROST signing the federation uses in
+//! consensus, carried out offline: every guardian of the signing set adds its
+//! nonce commitments to the sweep PSBT with `commit`, and once the commitments
+//! are combined into a single PSBT every guardian adds its signature shares
+//! with `sign`. Both are stored in proprietary PSBT fields, and `combine`
+//! aggregates the shares into the final signatures. No secret key share ever
+//! leaves a guardian's machine.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::psbt::{self, Psbt};
+use bitcoin::secp256k1::{Message, PublicKey, SECP256K1, Scalar, SecretKey};
+use bitcoin::sighash::TapSighashType;
+use bitcoin::{Address, Network, OutPoint, ScriptBuf, Txid, Weight};
+use clap::Subcommand;
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::db::{Database, DatabaseTransaction, IDatabaseTransactionOpsCoreTyped};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::epoch::ConsensusItem;
+use fedimint_core::module::CommonModuleInit;
+use fedimint_core::module::registry::{ModuleDecoderRegistry, ModuleRegistry};
+use fedimint_core::session_outcome::SignedSessionOutcome;
+use fedimint_core::{NumPeersExt, PeerId};
+use fedimint_server::config::ServerConfig;
+use fedimint_server::consensus::db::SignedSessionOutcomePrefix;
+use fedimint_server::core::ServerModule;
+use fedimint_wallet_server::common::keys::CompressedPublicKey;
+use fedimint_walletv2_server::common::config::{WalletConfig, WalletDescriptor};
+use fedimint_walletv2_server::common::frost::{
+    NonceCommitment, SignatureShare, SigningNonces, TaprootKey, group_public_key,
+};
+use fedimint_walletv2_server::common::{
+    WalletCommonInit, WalletInput, descriptor, tweak_public_key,
+};
+use fedimint_walletv2_server::db::{
+    FederationWalletKey, OutputPrefix, SpentOutputPrefix, UnconfirmedTxPrefix, UnsignedTxPrefix,
+};
+use fedimint_walletv2_server::{FederationTx, SpentTxOut, Wallet};
+use futures::StreamExt;
+use miniscript::Descriptor;
+use miniscript::descriptor::Wsh;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::key::Key;
+use crate::psbt::{
+    SweepInput, SweepPsbt, SweepScript, create_sweep_psbt, proprietary_key, proprietary_tweak_key,
+    psbt_parser, sign_sweep_psbt, taproot_sighashes,
+};
+use crate::{ImportableWallet, ImportableWalletMin, get_db, get_module_id};
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Walletv2Command {
+    /// Derive the wallet descriptors of all UTXOs of the federation that are
+    /// not spent by a transaction the federation already broadcast
+    Utxos {
+        /// Path to database
+        #[arg(long)]
+        db: PathBuf,
+    },
+    /// Derive the wallet descriptors of all tweaks used by peg-ins according
+    /// to the session log and by pending federation transactions. Most of
+    /// these descriptors will be empty.
+    Epochs {
+        /// Path to database
+        #[arg(long)]
+        db: PathBuf,
+    },
+    /// Create an unsigned PSBT sweeping all UTXOs of `utxos` to a single
+    /// address, to be signed by the guardians with `sign`
+    Sweep {
+        /// Path to database
+        #[arg(long)]
+        db: PathBuf,
+        /// Address receiving the swept funds
+        #[arg(long)]
+        address: Address<NetworkUnchecked>,
+        /// Fee rate of the sweep transaction in sat/vB
+        #[arg(long)]
+        fee_rate: u64,
+    },
+    /// Sign a sweep PSBT with the key of this guardian. For the taproot
+    /// descriptor this adds the signature shares of this guardian, which
+    /// requires the nonce commitments of the signing set combined into the PSBT
+    Sign {
+        /// Base64 encoded PSBT
+        #[arg(long, value_parser = psbt_parser)]
+        psbt: Psbt,
+        /// Session file written by `commit`, only used by the taproot
+        /// descriptor. It is deleted before the signature shares are printed,
+        /// such that its nonces are never used twice.
+        #[arg(long)]
+        session: Option<PathBuf>,
+    },
+    /// Add the nonce commitments of this guardian to a sweep PSBT of a
+    /// federation using the taproot descriptor, which makes this guardian part
+    /// of the signing set. The nonces are derived from a new session written to
+    /// `--session`, which `sign` requires.
+    Commit {
+        /// Base64 encoded PSBT
+        #[arg(long, value_parser = psbt_parser)]
+        psbt: Psbt,
+        /// Path the session file is written to, must not exist yet
+        #[arg(long)]
+        session: PathBuf,
+    },
+}
+
+/// Subtype of the proprietary PSBT field holding the group key of a taproot
+/// federation
+const GROUP_KEY_SUBTYPE: u8 = 0x01;
+
+/// Subtype of the proprietary PSBT input fields holding the nonce commitments
+/// of the signing set, keyed by peer id
+const NONCE_COMMITMENT_SUBTYPE: u8 = 0x02;
+
+/// Subtype of the proprietary PSBT input fields holding the signature shares
+/// of the signing set, keyed by peer id
+const SIGNATURE_SHARE_SUBTYPE: u8 = 0x03;
+
+/// The state a guardian keeps between adding its nonce commitments to a sweep
+/// PSBT and signing it
+#[derive(Debug, Serialize, Deserialize)]
+struct SigningSession {
+    txid: Txid,
+    /// Random attempt the nonces are derived from, such that no two sessions
+    /// share their nonces
+    attempt: u64,
+}
+
+fn peer_key(subtype: u8, peer: PeerId) -> psbt::raw::ProprietaryKey {
+    proprietary_key(subtype, u16::from(peer).to_be_bytes().to_vec())
+}
+
+/// Returns the values of the proprietary input fields of a subtype by peer id
+fn peer_values(input: &psbt::Input, subtype: u8) -> anyhow::Result<BTreeMap<PeerId, &Vec<u8>>> {
+    input
+        .proprietary
+        .iter()
+        .filter(|(key, _)| key.prefix == b"fedimint" && key.subtype == subtype)
+        .map(|(key, value)| {
+            let peer = <[u8; 2]>::try_from(key.key.as_slice())
+                .context("Malformed peer id in proprietary PSBT field")?;
+
+            Ok((PeerId::from(u16::from_be_bytes(peer)), value))
+        })
+        .collect()
+}
+
+fn nonce_commitments(input: &psbt::Input) -> anyhow::Result<BTreeMap<PeerId, NonceCommitment>> {
+    peer_values(input, NONCE_COMMITMENT_SUBTYPE)?
+        .into_iter()
+        .map(|(peer, value)| {
+            let commitment =
+                NonceCommitment::consensus_decode_whole(value, &ModuleRegistry::default())
+                    .with_context(|| format!("Malformed nonce commitment of guardian {peer}"))?;
+
+            Ok((peer, commitment))
+        })
+        .collect()
+}
+
+fn signature_shares(input: &psbt::Input) -> anyhow::Result<BTreeMap<PeerId, SignatureShare>> {
+    peer_values(input, SIGNATURE_SHARE_SUBTYPE)?
+        .into_iter()
+        .map(|(peer, value)| {
+            let share = <[u8; 32]>::try_from(value.as_slice())
+                .ok()
+                .filter(|share| SecretKey::from_slice(share).is_ok())
+                .with_context(|| format!("Malformed signature share of guardian {peer}"))?;
+
+            Ok((peer, SignatureShare(share)))
+        })
+        .collect()
+}
+
+/// Returns the taproot key of an input of a sweep PSBT and ensures the input
+/// is locked to it
+fn input_taproot_key(
+    group_pk: &PublicKey,
+    input: &psbt::Input,
+    index: usize,
+) -> anyhow::Result<TaprootKey> {
+    let tweak = input
+        .proprietary
+        .get(&proprietary_tweak_key())
+        .with_context(|| format!("Input {index} is missing its tweak"))?;
+
+    let tweak = sha256::Hash::from_slice(tweak).context("Malformed walletv2 tweak")?;
+
+    let taproot_key = TaprootKey::new(group_pk, &tweak);
+
+    anyhow::ensure!(
+        input.witness_utxo.as_ref().map(|utxo| &utxo.script_pubkey)
+            == Some(&ScriptBuf::new_p2tr_tweaked(taproot_key.output_key)),
+        "Input {index} does not belong to the federation wallet"
+    );
+
+    Ok(taproot_key)
+}
+
+/// Aggregates the signature shares of every input of a walletv2 taproot sweep
+/// for which the entire signing set submitted its share. PSBTs of other
+/// federations are left unchanged.
+pub fn aggregate_signature_shares(psbt: &mut Psbt) -> anyhow::Result<()> {
+    let Some(group_pk) = psbt
+        .proprietary
+        .get(&proprietary_key(GROUP_KEY_SUBTYPE, vec![]))
+    else {
+        return Ok(());
+    };
+
+    let group_pk = PublicKey::from_slice(group_pk).context("Malformed group key")?;
+
+    let sighashes = taproot_sighashes(psbt)?;
+
+    for (index, input) in psbt.inputs.iter_mut().enumerate() {
+        let commitments = nonce_commitments(input)?;
+        let shares = signature_shares(input)?;
+
+        if shares.is_empty() || !shares.keys().eq(commitments.keys()) {
+            info!(
+                index,
+                signing_set = commitments.len(),
+                shares = shares.len(),
+                "Input is missing signature shares"
+            );
+
+            continue;
+        }
+
+        let taproot_key = input_taproot_key(&group_pk, input, index)?;
+
+        let signature = taproot_key.aggregate(&commitments, &shares, &sighashes[index]);
+
+        SECP256K1
+            .verify_schnorr(
+                &signature,
+                &Message::from_digest(sighashes[index]),
+                &taproot_key.output_key.to_x_only_public_key(),
+            )
+            .with_context(|| {
+                format!("Signature shares of input {index} do not aggregate to a valid signature")
+            })?;
+
+        input.tap_key_sig = Some(bitcoin::taproot::Signature {
+            signature,
+            sighash_type: TapSighashType::Default,
+        });
+    }
+
+    Ok(())
+}
+
+pub async fn run(command: &Walletv2Command, cfg: &ServerConfig) -> anyhow::Result<()> {
+    let module_id = get_module_id(cfg, &WalletCommonInit::KIND)?;
+    let wallet_cfg: WalletConfig = cfg
+        .get_module_config_typed(module_id)
+        .context("Malformed walletv2 config")?;
+
+    let keys = WalletKeys {
+        pks: wallet_cfg.consensus.bitcoin_pks,
+        sk: wallet_cfg.private.bitcoin_sk,
+        network: wallet_cfg.consensus.network,
+        descriptor: wallet_cfg.consensus.descriptor,
+    };
+
+    match command {
+        Walletv2Command::Utxos { db } => {
+            keys.ensure_multisig()?;
+
+            let db = get_module_db(db, module_id).await;
+
+            let wallets = federation_utxos(&mut db.begin_transaction_nc().await)
+                .await
+                .into_iter()
+                .map(|(outpoint, utxo)| ImportableWallet {
+                    outpoint,
+                    descriptor: keys.importable_descriptor(&utxo.tweak),
+                    amount_sat: utxo.value,
+                })
+                .collect::<Vec<_>>();
+
+            serde_json::to_writer(std::io::stdout().lock(), &wallets)?;
+        }
+        Walletv2Command::Epochs { db } => {
+            keys.ensure_multisig()?;
+
+            let decoders = ModuleDecoderRegistry::from_iter([(
+                module_id,
+                WalletCommonInit::KIND,
+                <Wallet as ServerModule>::decoder(),
+            )])
+            .with_fallback();
+
+            let db = get_db(db, decoders).await;
+
+            let mut tweaks = peg_in_tweaks(&db, module_id).await;
+
+            let module_db = db.with_prefix_module_id(module_id).0;
+
+            tweaks.extend(pending_tweaks(&mut module_db.begin_transaction_nc().await).await);
+
+            let wallets = tweaks
+                .iter()
+                .map(|tweak| ImportableWalletMin {
+                    descriptor: keys.importable_descriptor(tweak),
+                })
+                .collect::<Vec<_>>();
+
+            serde_json::to_writer(std::io::stdout().lock(), &wallets)?;
+        }
+        Walletv2Command::Sweep {
+            db,
+            address,
+            fee_rate,
+        } => {
+            let address = address
+                .clone()
+                .require_network(keys.network)
+                .context("Address does not match the network of the federation")?;
+
+            let db = get_module_db(db, module_id).await;
+
+            let utxos = federation_utxos(&mut db.begin_transaction_nc().await).await;
+
+            let inputs = utxos
+                .iter()
+                .map(|(outpoint, utxo)| keys.sweep_input(*outpoint, utxo))
+                .collect();
+
+            let mut psbt = create_sweep_psbt(inputs, address.script_pubkey(), *fee_rate)?;
+
+            keys.add_group_key(&mut psbt);
+
+            SweepPsbt::new(&psbt)?.print()?;
+        }
+        Walletv2Command::Sign { psbt, session } => {
+            let mut psbt = psbt.clone();
+
+            if keys.descriptor == WalletDescriptor::Tr {
+                let session_path = session.as_ref().context(
+                    "Signing for the taproot descriptor requires the session of `commit`",
+                )?;
+
+                let session: SigningSession = serde_json::from_slice(
+                    &std::fs::read(session_path).context("Failed to read the session file")?,
+                )
+                .context("Malformed session file")?;
+
+                anyhow::ensure!(
+                    session.txid == psbt.unsigned_tx.compute_txid(),
+                    "The session belongs to another sweep transaction"
+                );
+
+                keys.sign_shares(&mut psbt, session.attempt)?;
+
+                // Signing twice with the same nonces but another signing set
+                // would leak our key share
+                std::fs::remove_file(session_path).context("Failed to delete the session file")?;
+            } else {
+                anyhow::ensure!(
+                    session.is_none(),
+                    "Sessions are only used by the taproot descriptor"
+                );
+
+                sign_sweep_psbt(&mut psbt, |tweak| keys.signing_key(tweak))?;
+            }
+
+            SweepPsbt::new(&psbt)?.print()?;
+        }
+        Walletv2Command::Commit { psbt, session } => {
+            anyhow::ensure!(
+                keys.descriptor == WalletDescriptor::Tr,
+                "Only the taproot descriptor commits to nonces, use `sign` instead"
+            );
+
+            let mut psbt = psbt.clone();
+
+            let signing_session = SigningSession {
+                txid: psbt.unsigned_tx.compute_txid(),
+                attempt: rand::random(),
+            };
+
+            keys.commit(&mut psbt, signing_session.attempt)?;
+
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(session)
+                .and_then(|mut file| {
+                    file.write_all(&serde_json::to_vec(&signing_session)?)?;
+                    file.sync_all()
+                })
+                .context("Failed to write the session file")?;
+
+            SweepPsbt::new(&psbt)?.print()?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn get_module_db(path: &Path, module_id: ModuleInstanceId) -> Database {
+    get_db(path, ModuleRegistry::default())
+        .await
+        .with_prefix_module_id(module_id)
+        .0
+}
+
+/// Returns the UTXOs of the federation by outpoint
+///
+/// The federation UTXO at the tip of the transaction chain might be the output
+/// of a broadcast but still unconfirmed transaction, in which case spending it
+/// requires that transaction to confirm first. Transactions the federation
+/// never broadcast are skipped and their inputs returned instead.
+async fn federation_utxos(dbtx: &mut DatabaseTransaction<'_>) -> BTreeMap<OutPoint, SpentTxOut> {
+    let Some(wallet) = dbtx.get_value(&FederationWalletKey).await else {
+        info!("The federation wallet has not received any funds yet");
+        return BTreeMap::new();
+    };
+
+    let unsigned_txs = dbtx
+        .find_by_prefix(&UnsignedTxPrefix)
+        .await
+        .map(|(_, tx)| tx)
+        .collect::<Vec<FederationTx>>()
+        .await;
+
+    let unsigned_txids = unsigned_txs
+        .iter()
+        .map(|tx| tx.tx.compute_txid())
+        .collect::<BTreeSet<Txid>>();
+
+    let mut utxos = BTreeMap::from([(
+        wallet.outpoint,
+        SpentTxOut {
+            value: wallet.value,
+            tweak: wallet.tweak,
+        },
+    )]);
+
+    for tx in unsigned_txs {
+        for (input, utxo) in tx.tx.input.iter().zip(tx.spent_tx_outs) {
+            utxos.insert(input.previous_output, utxo);
+        }
+    }
+
+    utxos.retain(|outpoint, _| !unsigned_txids.contains(&outpoint.txid));
+
+    let spent_outputs = dbtx
+        .find_by_prefix(&SpentOutputPrefix)
+        .await
+        .map(|(key, ())| key.0)
+        .collect::<BTreeSet<u64>>()
+        .await;
+
+    let unclaimed_outputs = dbtx
+        .find_by_prefix(&OutputPrefix)
+        .await
+        .filter(|(key, _)| std::future::ready(!spent_outputs.contains(&key.0)))
+        .map(|(_, output)| output.0)
+        .collect::<Vec<OutPoint>>()
+        .await;
+
+    if !unclaimed_outputs.is_empty() {
+        warn!(
+            ?unclaimed_outputs,
+            "Potential deposits were never claimed, they can only be recovered with the tweak of the depositing user"
+        );
+    }
+
+    utxos
+}
+
+/// Returns the tweaks of all peg-ins in the session log
+async fn peg_in_tweaks(db: &Database, module_id: ModuleInstanceId) -> BTreeSet<sha256::Hash> {
+    db.begin_transaction_nc()
+        .await
+        .find_by_prefix(&SignedSessionOutcomePrefix)
+        .await
+        .flat_map(
+            |(
+                _,
+                SignedSessionOutcome {
+                    session_outcome, ..
+                },
+            )| {
+                let tweaks = session_outcome
+                    .items
+                    .into_iter()
+                    .filter_map(|item| match item.item {
+                        ConsensusItem::Transaction(tx) => Some(tx),
+                        ConsensusItem::Module(_) | ConsensusItem::Default { .. } => None,
+                    })
+                    .flat_map(|tx| tx.inputs)
+                    .filter(|input| input.module_instance_id() == module_id)
+                    .filter_map(|input| {
+                        match input
+                            .as_any()
+                            .downcast_ref::<WalletInput>()
+                            .expect("Instance id mapping incorrect")
+                        {
+                            WalletInput::V0(input) => {
+                                Some(input.tweak.consensus_hash::<sha256::Hash>())
+                            }
+                            WalletInput::Default { .. } => {
+                                warn!("Skipping walletv2 input of unknown variant");
+                                None
+                            }
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                futures::stream::iter(tweaks)
+            },
+        )
+        .collect()
+        .await
+}
+
+/// Returns the tweaks of the federation UTXO and all UTXOs spent by pending
+/// federation transactions
+async fn pending_tweaks(dbtx: &mut DatabaseTransaction<'_>) -> BTreeSet<sha256::Hash> {
+    let mut tweaks = dbtx
+        .get_value(&FederationWalletKey)
+        .await
+        .map(|wallet| wallet.tweak)
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+
+    for prefix_txs in [
+        dbtx.find_by_prefix(&UnsignedTxPrefix)
+            .await
+            .map(|(_, tx)| tx)
+            .collect::<Vec<FederationTx>>()
+            .await,
+        dbtx.find_by_prefix(&UnconfirmedTxPrefix)
+            .await
+            .map(|(_, tx)| tx)
+            .collect::<Vec<FederationTx>>()
+            .await,
+    ] {
+        tweaks.extend(
+            prefix_txs
+                .into_iter()
+                .flat_map(|tx| tx.spent_tx_outs)
+                .map(|utxo| utxo.tweak),
+        );
+    }
+
+    tweaks
+}
+
+/// The multisig keys of the federation together with the secret key of this
+/// guardian
+struct WalletKeys {
+    pks: BTreeMap<PeerId, PublicKey>,
+    sk: SecretKey,
+    network: Network,
+    descriptor: WalletDescriptor,
+}
+
+impl WalletKeys {
+    /// The guardians of a taproot federation hold shares of a single key, which
+    /// can not be exported as a multisig descriptor
+    fn ensure_multisig(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.descriptor == WalletDescriptor::Wsh,
+            "The taproot descriptor can not be exported, use `sweep` instead"
+        );
+
+        Ok(())
+    }
+
+    fn our_peer_id(&self) -> anyhow::Result<PeerId> {
+        let pk = self.sk.public_key(SECP256K1);
+
+        self.pks
+            .iter()
+            .find(|(_, peer_pk)| **peer_pk == pk)
+            .map(|(peer, _)| *peer)
+            .context("Our key is not part of the federation")
+    }
+
+    /// Adds the group key of a taproot federation to a sweep PSBT, which
+    /// `combine` requires to aggregate the signature shares
+    fn add_group_key(&self, psbt: &mut Psbt) {
+        if self.descriptor == WalletDescriptor::Tr {
+            psbt.proprietary.insert(
+                proprietary_key(GROUP_KEY_SUBTYPE, vec![]),
+                group_public_key(&self.pks).serialize().to_vec(),
+            );
+        }
+    }
+
+    /// Adds the nonce commitments of this guardian for every input, which are
+    /// derived from the transaction and the attempt of the signing session
+    fn commit(&self, psbt: &mut Psbt, attempt: u64) -> anyhow::Result<()> {
+        let peer = self.our_peer_id()?;
+        let group_pk = group_public_key(&self.pks);
+        let txid = psbt.unsigned_tx.compute_txid();
+
+        for (index, input) in psbt.inputs.iter_mut().enumerate() {
+            input_taproot_key(&group_pk, input, index)?;
+
+            let nonces = SigningNonces::new(&self.sk, &txid, attempt, index as u64);
+
+            input.proprietary.insert(
+                peer_key(NONCE_COMMITMENT_SUBTYPE, peer),
+                nonces.commitment().consensus_encode_to_vec(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Adds the signature shares of this guardian for every input. The signing
+    /// set of an input are the guardians that committed to their nonces.
+    fn sign_shares(&self, psbt: &mut Psbt, attempt: u64) -> anyhow::Result<()> {
+        for output in &psbt.unsigned_tx.output {
+            info!(
+                script_pubkey = %output.script_pubkey,
+                value = %output.value,
+                "Signing sweep output"
+            );
+        }
+
+        let peer = self.our_peer_id()?;
+        let group_pk = group_public_key(&self.pks);
+        let txid = psbt.unsigned_tx.compute_txid();
+        let threshold = self.pks.to_num_peers().threshold();
+        let sighashes = taproot_sighashes(psbt)?;
+
+        for (index, input) in psbt.inputs.iter_mut().enumerate() {
+            let taproot_key = input_taproot_key(&group_pk, input, index)?;
+
+            let commitments = nonce_commitments(input)?;
+
+            anyhow::ensure!(
+                commitments.keys().all(|peer| self.pks.contains_key(peer)),
+                "Input {index} has nonce commitments of unknown guardians"
+            );
+
+            anyhow::ensure!(
+                threshold <= commitments.len(),
+                "Signing requires the nonce commitments of {threshold} guardians, input {index} has {}",
+                commitments.len()
+            );
+
+            let nonces = SigningNonces::new(&self.sk, &txid, attempt, index as u64);
+
+            anyhow::ensure!(
+                commitments.get(&peer) == Some(&nonces.commitment()),
+                "Our nonce commitment of input {index} does not belong to this session"
+            );
+
+            let share =
+                taproot_key.sign_share(&self.sk, &nonces, peer, &commitments, &sighashes[index]);
+
+            anyhow::ensure!(
+                taproot_key.verify_share(
+                    &share,
+                    &self.pks[&peer],
+                    peer,
+                    &commitments,
+                    &sighashes[index],
+                ),
+                "Our signature share of input {index} is invalid"
+            );
+
+            input
+                .proprietary
+                .insert(peer_key(SIGNATURE_SHARE_SUBTYPE, peer), share.0.to_vec());
+        }
+
+        Ok(())
+    }
+
+    fn descriptor(&self, tweak: &sha256::Hash) -> Wsh<PublicKey> {
+        descriptor(&self.pks, tweak)
+    }
+
+    fn tweaked_secret_key(&self, tweak: &sha256::Hash) -> SecretKey {
+        let scalar =
+            Scalar::from_be_bytes(tweak.to_byte_array()).expect("Hash is within field order");
+
+        self.sk
+            .add_tweak(&scalar)
+            .expect("Failed to tweak bitcoin secret key")
+    }
+
+    /// Returns a Bitcoin Core importable descriptor containing the tweaked
+    /// secret key of this guardian
+    fn importable_descriptor(&self, tweak: &sha256::Hash) -> Descriptor<Key> {
+        let sk = self.tweaked_secret_key(tweak);
+        let pk = sk.public_key(SECP256K1);
+
+        let keys = self
+            .pks
+            .values()
+            .map(|pk| tweak_public_key(pk, tweak))
+            .map(|tweaked_pk| {
+                if tweaked_pk == pk {
+                    Key::Private(bitcoin::PrivateKey::new(sk, self.network))
+                } else {
+                    Key::Public(CompressedPublicKey::new(tweaked_pk))
+                }
+            })
+            .collect();
+
+        Descriptor::new_wsh_sortedmulti(self.pks.to_num_peers().threshold(), keys)
+            .expect("Failed to construct descriptor")
+    }
+    fn sweep_input(&self, outpoint: OutPoint, utxo: &SpentTxOut) -> SweepInput {
+        if self.descriptor == WalletDescriptor::Tr {
+            return SweepInput {
+                outpoint,
+                value: utxo.value,
+                script: SweepScript::Tr(self.descriptor.script_pubkey(&self.pks, &utxo.tweak)),
+                max_weight_to_satisfy: Weight::from_wu(
+                    self.descriptor.max_witness_weight(&self.pks),
+                ),
+                tweak: utxo.tweak.to_byte_array().to_vec(),
+            };
+        }
+
+        let descriptor = self.descriptor(&utxo.tweak);
+
+        SweepInput {
+            outpoint,
+            value: utxo.value,
+            script: SweepScript::Wsh(descriptor.inner_script()),
+            max_weight_to_satisfy: descriptor
+                .max_weight_to_satisfy()
+                .expect("Multisig descriptor is satisfiable"),
+            tweak: utxo.tweak.to_byte_array().to_vec(),
+        }
+    }
+
+    fn signing_key(&self, tweak: &[u8]) -> anyhow::Result<(SweepScript, SecretKey)> {
+        self.ensure_multisig()?;
+
+        let tweak = sha256::Hash::from_slice(tweak).context("Malformed walletv2 tweak")?;
+
+        Ok((
+            SweepScript::Wsh(self.descriptor(&tweak).inner_script()),
+            self.tweaked_secret_key(&tweak),
+        ))
+    }
+}
+
+#[test]
+fn sweep_is_final_once_threshold_signed() {
+    use bitcoin::Amount;
+    use miniscript::psbt::PsbtExt;
+
+    use crate::psbt::combine_sweep_psbts;
+
+    let sks = (0..4)
+        .map(|_| SecretKey::from_slice(&rand::random::<[u8; 32]>()).expect("Valid secret key"))
+        .collect::<Vec<_>>();
+
+    let pks = sks
+        .iter()
+        .zip(0u16..)
+        .map(|(sk, peer)| (PeerId::from(peer), sk.public_key(SECP256K1)))
+        .collect::<BTreeMap<_, _>>();
+
+    let keys = sks
+        .into_iter()
+        .map(|sk| WalletKeys {
+            pks: pks.clone(),
+            sk,
+            network: Network::Regtest,
+            descriptor: WalletDescriptor::Wsh,
+        })
+        .collect::<Vec<_>>();
+
+    let inputs = (0..3)
+        .map(|vout| {
+            let outpoint = OutPoint {
+                txid: Txid::all_zeros(),
+                vout,
+            };
+
+            let utxo = SpentTxOut {
+                value: Amount::from_sat(100_000),
+                tweak: sha256::Hash::hash(&vout.to_be_bytes()),
+            };
+
+            keys[0].sweep_input(outpoint, &utxo)
+        })
+        .collect();
+
+    let destination = keys[0]
+        .descriptor(&sha256::Hash::all_zeros())
+        .script_pubkey();
+
+    let unsigned = create_sweep_psbt(inputs, destination, 10).expect("Failed to create sweep");
+
+    let fee = unsigned.fee().expect("PSBT contains all UTXOs");
+
+    assert_eq!(
+        unsigned.unsigned_tx.output[0].value + fee,
+        Amount::from_sat(300_000)
+    );
+
+    // Every guardian signs their own copy of the unsigned PSBT
+    let signed = keys
+        .iter()
+        .map(|key| {
+            let mut psbt = unsigned.clone();
+
+            sign_sweep_psbt(&mut psbt, |tweak| key.signing_key(tweak)).expect("Failed to sign");
+
+            psbt
+        })
+        .collect::<Vec<_>>();
+
+    // Three out of four guardians are required to sign
+    let partial = combine_sweep_psbts(signed[..2].to_vec()).expect("Failed to combine");
+
+    assert!(partial.finalize(SECP256K1).is_err());
+
+    let tx = combine_sweep_psbts(signed[1..].to_vec())
+        .expect("Failed to combine")
+        .finalize(SECP256K1)
+        .expect("Threshold of signatures is present")
+        .extract_tx_unchecked_fee_rate();
+
+    // The fee estimate is an upper bound of the final transaction size
+    assert!(tx.vsize() as u64 * 10 <= fee.to_sat());
+}
+
+#[test]
+fn taproot_sweep_is_signed_with_frost() {
+    use bitcoin::Amount;
+    use fedimint_walletv2_server::common::frost::evaluate_polynomial;
+    use miniscript::psbt::PsbtExt;
+
+    use crate::psbt::combine_sweep_psbts;
+
+    let coefficients = (0..3)
+        .map(|_| SecretKey::from_slice(&rand::random::<[u8; 32]>()).expect("Valid secret key"))
+        .collect::<Vec<_>>();
+
+    let sks = (0u16..4)
+        .map(|peer| evaluate_polynomial(&coefficients, PeerId::from(peer)))
+        .collect::<Vec<_>>();
+
+    let pks = sks
+        .iter()
+        .zip(0u16..)
+        .map(|(sk, peer)| (PeerId::from(peer), sk.public_key(SECP256K1)))
+        .collect::<BTreeMap<_, _>>();
+
+    let keys = sks
+        .into_iter()
+        .map(|sk| WalletKeys {
+            pks: pks.clone(),
+            sk,
+            network: Network::Regtest,
+            descriptor: WalletDescriptor::Tr,
+        })
+        .collect::<Vec<_>>();
+
+    let inputs = (0..3)
+        .map(|vout| {
+            let outpoint = OutPoint {
+                txid: Txid::all_zeros(),
+                vout,
+            };
+
+            let utxo = SpentTxOut {
+                value: Amount::from_sat(100_000),
+                tweak: sha256::Hash::hash(&vout.to_be_bytes()),
+            };
+
+            keys[0].sweep_input(outpoint, &utxo)
+        })
+        .collect();
+
+    let destination = keys[0]
+        .descriptor
+        .script_pubkey(&pks, &sha256::Hash::all_zeros());
+
+    let mut unsigned = create_sweep_psbt(inputs, destination, 10).expect("Failed to create sweep");
+
+    keys[0].add_group_key(&mut unsigned);
+
+    let fee = unsigned.fee().expect("PSBT contains all UTXOs");
+
+    // Three out of four guardians commit to their nonces in their own session
+    let committed = keys[1..]
+        .iter()
+        .zip(1..)
+        .map(|(key, attempt)| {
+            let mut psbt = unsigned.clone();
+
+            key.commit(&mut psbt, attempt).expect("Failed to commit");
+
+            psbt
+        })
+        .collect::<Vec<_>>();
+
+    // Two nonce commitments are below the threshold
+    let mut psbt = combine_sweep_psbts(committed[1..].to_vec()).expect("Failed to combine");
+
+    assert!(keys[2].sign_shares(&mut psbt, 2).is_err());
+
+    let committed = combine_sweep_psbts(committed).expect("Failed to combine");
+
+    // The nonces of another session do not match our commitment
+    let mut psbt = committed.clone();
+
+    assert!(keys[1].sign_shares(&mut psbt, 2).is_err());
+
+    let signed = keys[1..]
+        .iter()
+        .zip(1..)
+        .map(|(key, attempt)| {
+            let mut psbt = committed.clone();
+
+            key.sign_shares(&mut psbt, attempt).expect("Failed to sign");
+
+            psbt
+        })
+        .collect::<Vec<_>>();
+
+    // Every guardian of the signing set has to submit its signature share
+    let mut partial = combine_sweep_psbts(signed[..2].to_vec()).expect("Failed to combine");
+
+    aggregate_signature_shares(&mut partial).expect("Failed to aggregate");
+
+    assert!(partial.finalize(SECP256K1).is_err());
+
+    let mut psbt = combine_sweep_psbts(signed).expect("Failed to combine");
+
+    aggregate_signature_shares(&mut psbt).expect("Failed to aggregate");
+
+    let tx = psbt
+        .finalize(SECP256K1)
+        .expect("Sweep is signed with the key of the federation")
+        .extract_tx_unchecked_fee_rate();
+
+    // The fee estimate is an upper bound of the final transaction size
+    assert!(tx.vsize() as u64 * 10 <= fee.to_sat());
+}
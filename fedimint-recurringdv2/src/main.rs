@@ -208,14 +208,14 @@ async fn create_contract_and_fetch_invoice(
 
     let (routing_info, gateway) = select_gateway(gateways, federation_id, gateway_conn).await?;
 
+    let receive_fee = routing_info.receive_fee_for(amount);
+
     ensure!(
-        routing_info
-            .receive_fee
-            .is_within(&PaymentFee::RECEIVE_FEE_LIMIT),
+        receive_fee.is_within(&PaymentFee::RECEIVE_FEE_LIMIT),
         "Payment fee exceeds limit"
     );
 
-    let contract_amount = routing_info.receive_fee.subtract_from(amount);
+    let contract_amount = receive_fee.subtract_from(amount);
 
     ensure!(
         contract_amount >= MINIMUM_INCOMING_CONTRACT_AMOUNT,
@@ -227,7 +227,7 @@ async fn create_contract_and_fetch_invoice(
     // contracts are discovered via the contract stream rather than awaited
     // per-invoice, so a real expiration is not needed here; the bolt11 invoice
     // still carries the real expiry of `expiry_secs`.
-    let expiration = fee_encoded_expiration(routing_info.receive_fee.fee(amount).msats);
+    let expiration = fee_encoded_expiration(receive_fee.fee(amount).msats);
 
     let contract = IncomingContract::new(
         aggregate_pk,
@@ -206,6 +206,32 @@ pub fn open_read_only_blocking(db_path: &Path) -> anyhow::Result<RocksDbReadOnly
         let db = rocksdb::DB::open_for_read_only(&opts, db_path, false)?;
         Ok(RocksDbReadOnly(db))
     }
+
+    /// Opens the database as a RocksDB secondary instance, which unlike a
+    /// read-only instance also replays the write-ahead log of a primary
+    /// instance that is still running. It sees all writes committed before it
+    /// was opened. `secondary_path` holds the info logs of the instance.
+    #[allow(clippy::unused_async)]
+    pub async fn open_secondary(
+        db_path: impl AsRef<Path>,
+        secondary_path: impl AsRef<Path>,
+    ) -> anyhow::Result<RocksDbReadOnly> {
+        let (db_path, secondary_path) = (db_path.as_ref(), secondary_path.as_ref());
+        block_in_place(|| Self::open_secondary_blocking(db_path, secondary_path))
+    }
+
+    pub fn open_secondary_blocking(
+        db_path: &Path,
+        secondary_path: &Path,
+    ) -> anyhow::Result<RocksDbReadOnly> {
+        let mut opts = get_default_options()?;
+        // Keep all table files open, since the primary may delete files after a
+        // compaction that this instance still reads from
+        opts.set_max_open_files(-1);
+        let db = rocksdb::DB::open_as_secondary(&opts, db_path, secondary_path)?;
+        db.try_catch_up_with_primary()?;
+        Ok(RocksDbReadOnly(db))
+    }
 }
 
 impl From<rocksdb::OptimisticTransactionDB> for RocksDb {
@@ -0,0 +1,321 @@
+//! Bitcoin backend serving a recorded chain segment from a local directory
+//!
+//! The directory contains one raw block per file named `<height>.block`, in
+//! the format served by Esplora's `/block/:hash/raw` endpoint, and an optional
+//! `config.json` deserializing into a [`BlockFileConfig`]. Transactions are
+//! never broadcast but written to the `outbox` subdirectory instead, which
+//! allows deterministic, network-free replay tests against recorded chains.
+//!
+//! Block files added to the directory while the client is running are picked
+//! up by the next call to `get_block_count`, so tests can extend the chain.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::Read as _;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, bail, ensure};
+use bitcoin::block::Header;
+use bitcoin::consensus::{Decodable, deserialize, serialize};
+use bitcoin::{Block, BlockHash, Transaction};
+use fedimint_core::envs::BitcoinRpcConfig;
+use fedimint_core::runtime::block_in_place;
+use fedimint_core::util::SafeUrl;
+use fedimint_core::{ChainId, Feerate};
+use fedimint_logging::LOG_SERVER;
+use fedimint_server_core::bitcoin_rpc::IServerBitcoinRpc;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+/// Name of the optional [`BlockFileConfig`] in the block directory
+pub const BLOCK_FILE_CONFIG: &str = "config.json";
+
+/// Extension of the raw block files in the block directory
+pub const BLOCK_FILE_EXTENSION: &str = "block";
+
+/// Subdirectory submitted transactions are written to
+pub const OUTBOX_DIR: &str = "outbox";
+
+/// Size of a consensus encoded block header
+const HEADER_SIZE: usize = 80;
+
+/// Configuration of a block file directory
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockFileConfig {
+    /// Chain id to report, required if the recorded segment does not contain
+    /// the block at height 1
+    #[serde(default)]
+    pub chain_id: Option<ChainId>,
+    /// Fee rates by the block height from which on they are reported. No fee
+    /// rate is reported while the chain tip is below the first entry.
+    #[serde(default)]
+    pub feerates: BTreeMap<u64, Feerate>,
+}
+
+impl BlockFileConfig {
+    /// Fee rate to report for a chain with tip at `height`
+    pub fn feerate(&self, height: u64) -> Option<Feerate> {
+        self.feerates
+            .range(..=height)
+            .next_back()
+            .map(|(_, feerate)| *feerate)
+    }
+}
+
+/// Hashes of the indexed blocks, which form a contiguous chain
+#[derive(Debug, Default)]
+struct BlockIndex {
+    hashes: BTreeMap<u64, BlockHash>,
+    heights: HashMap<BlockHash, u64>,
+}
+
+#[derive(Debug)]
+pub struct BlockFileClient {
+    dir: PathBuf,
+    url: SafeUrl,
+    config: BlockFileConfig,
+    index: Mutex<BlockIndex>,
+}
+
+impl BlockFileClient {
+    /// Opens a block directory, reading its config from [`BLOCK_FILE_CONFIG`]
+    /// if present
+    pub fn new(dir: &Path) -> anyhow::Result<Self> {
+        let config_path = dir.join(BLOCK_FILE_CONFIG);
+
+        let config = if config_path.exists() {
+            serde_json::from_reader(File::open(&config_path)?)
+                .with_context(|| format!("Invalid block file config {}", config_path.display()))?
+        } else {
+            BlockFileConfig::default()
+        };
+
+        Self::with_config(dir, config)
+    }
+
+    pub fn with_config(dir: &Path, config: BlockFileConfig) -> anyhow::Result<Self> {
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("Block directory {} does not exist", dir.display()))?;
+
+        let url = SafeUrl::parse(&format!("file://{}", dir.display()))?;
+
+        info!(
+            target: LOG_SERVER,
+            %url,
+            "Initializing bitcoin block file backend"
+        );
+
+        let client = Self {
+            dir,
+            url,
+            config,
+            index: Mutex::new(BlockIndex::default()),
+        };
+
+        client.update_index()?;
+
+        Ok(client)
+    }
+
+    /// Returns all transactions submitted so far by txid
+    pub fn outbox(&self) -> anyhow::Result<BTreeMap<bitcoin::Txid, Transaction>> {
+        let outbox_dir = self.dir.join(OUTBOX_DIR);
+
+        if !outbox_dir.exists() {
+            return Ok(BTreeMap::new());
+        }
+
+        let mut transactions = BTreeMap::new();
+
+        for entry in fs::read_dir(outbox_dir)? {
+            let transaction: Transaction = deserialize(&fs::read(entry?.path())?)?;
+
+            transactions.insert(transaction.compute_txid(), transaction);
+        }
+
+        Ok(transactions)
+    }
+
+    fn block_path(&self, height: u64) -> PathBuf {
+        self.dir.join(format!("{height}.{BLOCK_FILE_EXTENSION}"))
+    }
+
+    /// Indexes block files added since the last call and returns the height of
+    /// the chain tip
+    fn update_index(&self) -> anyhow::Result<u64> {
+        let mut heights = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+
+            if path
+                .extension()
+                .is_some_and(|ext| ext == BLOCK_FILE_EXTENSION)
+            {
+                let height = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse::<u64>().ok())
+                    .with_context(|| format!("Invalid block file name {}", path.display()))?;
+
+                heights.push(height);
+            }
+        }
+
+        heights.sort_unstable();
+
+        let (Some(&first), Some(&last)) = (heights.first(), heights.last()) else {
+            bail!("Block directory {} contains no blocks", self.dir.display());
+        };
+
+        ensure!(
+            last - first + 1 == heights.len() as u64,
+            "Block files do not form a contiguous range of heights"
+        );
+
+        let mut index = self.index.lock().expect("poisoned");
+
+        if let Some((indexed_first, _)) = index.hashes.first_key_value() {
+            ensure!(
+                *indexed_first == first,
+                "Block file at height {indexed_first} was removed"
+            );
+        }
+
+        for height in heights {
+            if index.hashes.contains_key(&height) {
+                continue;
+            }
+
+            let header = self.read_header(height)?;
+
+            if let Some(prev_hash) = height
+                .checked_sub(1)
+                .and_then(|prev_height| index.hashes.get(&prev_height))
+            {
+                ensure!(
+                    header.prev_blockhash == *prev_hash,
+                    "Block at height {height} does not build on the previous block"
+                );
+            }
+
+            debug!(target: LOG_SERVER, height, hash = %header.block_hash(), "Indexed block file");
+
+            index.hashes.insert(height, header.block_hash());
+            index.heights.insert(header.block_hash(), height);
+        }
+
+        Ok(last)
+    }
+
+    fn read_header(&self, height: u64) -> anyhow::Result<Header> {
+        let mut bytes = [0; HEADER_SIZE];
+
+        File::open(self.block_path(height))?
+            .read_exact(&mut bytes)
+            .with_context(|| format!("Block file at height {height} is truncated"))?;
+
+        Ok(Header::consensus_decode(&mut bytes.as_slice())?)
+    }
+
+    fn tip_height(&self) -> u64 {
+        *self
+            .index
+            .lock()
+            .expect("poisoned")
+            .hashes
+            .last_key_value()
+            .expect("Index contains at least one block")
+            .0
+    }
+}
+
+#[async_trait::async_trait]
+impl IServerBitcoinRpc for BlockFileClient {
+    fn get_bitcoin_rpc_config(&self) -> BitcoinRpcConfig {
+        BitcoinRpcConfig {
+            kind: "blockfile".to_string(),
+            url: self.url.clone(),
+        }
+    }
+
+    fn get_url(&self) -> SafeUrl {
+        self.url.clone()
+    }
+
+    async fn get_block_count(&self) -> anyhow::Result<u64> {
+        block_in_place(|| self.update_index()).map(|height| height + 1)
+    }
+
+    async fn get_block_hash(&self, height: u64) -> anyhow::Result<BlockHash> {
+        self.index
+            .lock()
+            .expect("poisoned")
+            .hashes
+            .get(&height)
+            .copied()
+            .with_context(|| format!("Block at height {height} is not available"))
+    }
+
+    async fn get_block(&self, block_hash: &BlockHash) -> anyhow::Result<Block> {
+        let height = self
+            .index
+            .lock()
+            .expect("poisoned")
+            .heights
+            .get(block_hash)
+            .copied()
+            .context("Block with this hash is not available")?;
+
+        let block: Block = deserialize(&block_in_place(|| fs::read(self.block_path(height)))?)?;
+
+        ensure!(
+            block.block_hash() == *block_hash,
+            "Block file at height {height} was modified"
+        );
+
+        ensure!(
+            block.check_merkle_root(),
+            "Block at height {height} has an invalid merkle root"
+        );
+
+        Ok(block)
+    }
+
+    async fn get_feerate(&self) -> anyhow::Result<Option<Feerate>> {
+        Ok(self.config.feerate(self.tip_height()))
+    }
+
+    async fn submit_transaction(&self, transaction: Transaction) -> anyhow::Result<()> {
+        let outbox_dir = self.dir.join(OUTBOX_DIR);
+
+        let path = outbox_dir.join(format!("{}.tx", transaction.compute_txid()));
+
+        block_in_place(|| {
+            fs::create_dir_all(&outbox_dir)?;
+            fs::write(path, serialize(&transaction))
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_sync_progress(&self) -> anyhow::Result<Option<f64>> {
+        Ok(None)
+    }
+
+    async fn get_chain_id(&self) -> anyhow::Result<ChainId> {
+        if let Some(chain_id) = self.config.chain_id {
+            return Ok(chain_id);
+        }
+
+        self.get_block_hash(1)
+            .await
+            .map(ChainId::new)
+            .context("Block at height 1 is not recorded, configure the chain id instead")
+    }
+}
+
+#[cfg(test)]
+mod tests;
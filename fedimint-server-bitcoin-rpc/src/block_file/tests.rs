@@ -0,0 +1,164 @@
+use std::path::Path;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::consensus::serialize;
+use bitcoin::hashes::Hash;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, Block, BlockHash, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+    Witness,
+};
+use fedimint_core::{ChainId, Feerate};
+use fedimint_server_core::bitcoin_rpc::IServerBitcoinRpc;
+
+use super::{BLOCK_FILE_CONFIG, BlockFileClient, BlockFileConfig};
+
+fn transaction(tag: u64) -> Transaction {
+    Transaction {
+        version: Version(2),
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: ScriptBuf::builder()
+                .push_slice(tag.to_be_bytes())
+                .into_script(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(50_000),
+            script_pubkey: ScriptBuf::new(),
+        }],
+    }
+}
+
+/// Returns a block building on `prev_blockhash`, made unique by `tag`
+fn block(prev_blockhash: BlockHash, tag: u64) -> Block {
+    let mut block = genesis_block(Network::Regtest);
+
+    block.header.prev_blockhash = prev_blockhash;
+    block.txdata = vec![transaction(tag)];
+    block.header.merkle_root = block.compute_merkle_root().expect("Block has transactions");
+
+    block
+}
+
+/// Writes blocks building on `prev_blockhash` at `heights` and returns them
+fn write_blocks(
+    dir: &Path,
+    mut prev_blockhash: BlockHash,
+    heights: impl IntoIterator<Item = u64>,
+) -> Vec<Block> {
+    heights
+        .into_iter()
+        .map(|height| {
+            let block = block(prev_blockhash, height);
+
+            std::fs::write(dir.join(format!("{height}.block")), serialize(&block))
+                .expect("Failed to write block");
+
+            prev_blockhash = block.block_hash();
+
+            block
+        })
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn serves_recorded_chain_segment() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let blocks = write_blocks(dir.path(), BlockHash::all_zeros(), 100..104);
+
+    let config = BlockFileConfig {
+        chain_id: Some(ChainId::new(BlockHash::all_zeros())),
+        feerates: [
+            (0, Feerate { sats_per_kvb: 1000 }),
+            (103, Feerate { sats_per_kvb: 2000 }),
+        ]
+        .into(),
+    };
+
+    std::fs::write(
+        dir.path().join(BLOCK_FILE_CONFIG),
+        serde_json::to_vec(&config).unwrap(),
+    )
+    .unwrap();
+
+    let client = BlockFileClient::new(dir.path()).unwrap();
+
+    assert_eq!(client.get_block_count().await.unwrap(), 104);
+
+    for (height, block) in (100..).zip(&blocks) {
+        let hash = client.get_block_hash(height).await.unwrap();
+
+        assert_eq!(hash, block.block_hash());
+        assert_eq!(client.get_block(&hash).await.unwrap(), *block);
+    }
+
+    assert!(client.get_block_hash(99).await.is_err());
+    assert!(client.get_block_hash(104).await.is_err());
+
+    assert_eq!(
+        client.get_feerate().await.unwrap(),
+        Some(Feerate { sats_per_kvb: 2000 })
+    );
+
+    assert_eq!(
+        client.get_chain_id().await.unwrap(),
+        ChainId::new(BlockHash::all_zeros())
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn extends_chain_with_new_block_files() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let blocks = write_blocks(dir.path(), BlockHash::all_zeros(), 0..2);
+
+    let client = BlockFileClient::new(dir.path()).unwrap();
+
+    assert_eq!(client.get_block_count().await.unwrap(), 2);
+    assert_eq!(client.get_feerate().await.unwrap(), None);
+    assert_eq!(
+        client.get_chain_id().await.unwrap(),
+        ChainId::new(blocks[1].block_hash())
+    );
+
+    write_blocks(dir.path(), blocks[1].block_hash(), 2..4);
+
+    assert_eq!(client.get_block_count().await.unwrap(), 4);
+
+    // A block that does not build on the tip is rejected
+    write_blocks(dir.path(), BlockHash::all_zeros(), 4..5);
+
+    assert!(client.get_block_count().await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writes_submitted_transactions_to_outbox() {
+    let dir = tempfile::tempdir().unwrap();
+
+    write_blocks(dir.path(), BlockHash::all_zeros(), 0..1);
+
+    let client = BlockFileClient::new(dir.path()).unwrap();
+
+    assert!(client.outbox().unwrap().is_empty());
+
+    let transaction = transaction(42);
+
+    client
+        .submit_transaction(transaction.clone())
+        .await
+        .unwrap();
+    client
+        .submit_transaction(transaction.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        client.outbox().unwrap(),
+        [(transaction.compute_txid(), transaction)].into()
+    );
+}
@@ -1,6 +1,8 @@
 pub mod bitcoind;
+pub mod block_file;
 pub mod esplora;
 pub mod metrics;
+pub mod quorum;
 pub mod tracked;
 
 use anyhow::Result;
@@ -27,14 +29,23 @@ pub fn new(
         password: String,
         bitcoind_url: &SafeUrl,
         esplora_url: &SafeUrl,
+    ) -> Result<Self> {
+        let bitcoind_client = BitcoindClient::new(username, password, bitcoind_url)?;
+
+        Self::with_bitcoind_client(bitcoind_client, esplora_url)
+    }
+
+    /// Falls back to esplora for an already constructed bitcoind client
+    pub fn with_bitcoind_client(
+        bitcoind_client: BitcoindClient,
+        esplora_url: &SafeUrl,
     ) -> Result<Self> {
         warn!(
             target: LOG_SERVER,
-            %bitcoind_url,
+            bitcoind_url = %bitcoind_client.get_url(),
             %esplora_url,
             "Initializing bitcoin bitcoind backend with esplora fallback"
         );
-        let bitcoind_client = BitcoindClient::new(username, password, bitcoind_url)?;
         let esplora_client = EsploraClient::new(esplora_url)?;
 
         Ok(Self {
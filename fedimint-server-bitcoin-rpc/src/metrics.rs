@@ -31,3 +31,33 @@
     )
     .expect("metric registration should not fail")
 });
+
+/// Counter of server bitcoin RPC quorum queries the backends disagreed on,
+/// labeled by method
+pub static SERVER_BITCOIN_RPC_QUORUM_DISAGREEMENTS_TOTAL: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        register_int_counter_vec_with_registry!(
+            opts!(
+                "server_bitcoin_rpc_quorum_disagreements_total",
+                "Total number of server bitcoin RPC quorum queries the backends disagreed on",
+            ),
+            &["method"],
+            REGISTRY
+        )
+        .expect("metric registration should not fail")
+    });
+
+/// Counter of server bitcoin RPC quorum queries that did not reach the quorum,
+/// labeled by method
+pub static SERVER_BITCOIN_RPC_QUORUM_FAILURES_TOTAL: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        register_int_counter_vec_with_registry!(
+            opts!(
+                "server_bitcoin_rpc_quorum_failures_total",
+                "Total number of server bitcoin RPC quorum queries that did not reach the quorum",
+            ),
+            &["method"],
+            REGISTRY
+        )
+        .expect("metric registration should not fail")
+    });
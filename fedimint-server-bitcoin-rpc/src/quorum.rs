@@ -0,0 +1,267 @@
+//! Bitcoin backend cross-checking the chain state reported by several backends
+//!
+//! Block hashes and the chain id are only reported if at least `quorum`
+//! backends agree on them, such that a single lying or misconfigured backend
+//! cannot make the guardian vote for a wrong chain. The block count is the
+//! highest count reached by at least `quorum` backends, so lagging backends
+//! delay but never block progress as long as a quorum is in sync. Blocks are
+//! verified against their hash and everything else fails over to the next
+//! backend on error.
+
+use std::fmt::Debug;
+
+use anyhow::{Context, bail, ensure};
+use bitcoin::{Block, BlockHash, Transaction};
+use fedimint_core::envs::BitcoinRpcConfig;
+use fedimint_core::util::{FmtCompactAnyhow as _, SafeUrl};
+use fedimint_core::{ChainId, Feerate};
+use fedimint_logging::LOG_SERVER;
+use fedimint_server_core::bitcoin_rpc::{DynServerBitcoinRpc, IServerBitcoinRpc};
+use futures::future::join_all;
+use tracing::{info, warn};
+
+use crate::metrics::{
+    SERVER_BITCOIN_RPC_QUORUM_DISAGREEMENTS_TOTAL, SERVER_BITCOIN_RPC_QUORUM_FAILURES_TOTAL,
+};
+
+#[derive(Debug)]
+pub struct ServerBitcoinRpcQuorum {
+    backends: Vec<DynServerBitcoinRpc>,
+    quorum: usize,
+}
+
+impl ServerBitcoinRpcQuorum {
+    /// Creates a client requiring `quorum` of the `backends` to agree, the
+    /// backends are failed over to in the given order
+    pub fn new(backends: Vec<DynServerBitcoinRpc>, quorum: usize) -> anyhow::Result<Self> {
+        ensure!(quorum > 0, "The bitcoin rpc quorum has to be at least one");
+
+        ensure!(
+            quorum <= backends.len(),
+            "The bitcoin rpc quorum of {quorum} exceeds the number of {} backends",
+            backends.len()
+        );
+
+        info!(
+            target: LOG_SERVER,
+            backends = ?backends.iter().map(|backend| backend.get_url()).collect::<Vec<_>>(),
+            quorum,
+            "Initializing bitcoin quorum backend"
+        );
+
+        Ok(Self { backends, quorum })
+    }
+
+    /// Queries all backends concurrently, logging and dropping all errors
+    async fn query_all<T, F>(
+        &self,
+        method: &'static str,
+        call: impl Fn(DynServerBitcoinRpc) -> F,
+    ) -> Vec<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        join_all(self.backends.iter().cloned().map(call))
+            .await
+            .into_iter()
+            .zip(&self.backends)
+            .filter_map(|(result, backend)| {
+                result
+                    .inspect_err(|e| {
+                        warn!(
+                            target: LOG_SERVER,
+                            method,
+                            url = %backend.get_url(),
+                            error = %e.fmt_compact_anyhow(),
+                            "Bitcoin rpc backend failed"
+                        );
+                    })
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// Queries the backends in order until the first one succeeds
+    async fn query_first<T, F>(
+        &self,
+        method: &'static str,
+        call: impl Fn(DynServerBitcoinRpc) -> F,
+    ) -> anyhow::Result<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        for backend in &self.backends {
+            match call(backend.clone()).await {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    warn!(
+                        target: LOG_SERVER,
+                        method,
+                        url = %backend.get_url(),
+                        error = %e.fmt_compact_anyhow(),
+                        "Bitcoin rpc backend failed, failing over to next backend"
+                    );
+                }
+            }
+        }
+
+        bail!("All bitcoin rpc backends failed for {method}")
+    }
+
+    /// Returns the value reported by at least `quorum` backends
+    async fn query_quorum<T, F>(
+        &self,
+        method: &'static str,
+        call: impl Fn(DynServerBitcoinRpc) -> F,
+    ) -> anyhow::Result<T>
+    where
+        T: PartialEq + Debug,
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        let mut votes: Vec<(T, usize)> = vec![];
+
+        for value in self.query_all(method, call).await {
+            match votes.iter_mut().find(|(voted, _)| *voted == value) {
+                Some((_, count)) => *count += 1,
+                None => votes.push((value, 1)),
+            }
+        }
+
+        if votes.len() > 1 {
+            warn!(
+                target: LOG_SERVER,
+                method,
+                ?votes,
+                "Bitcoin rpc backends disagree"
+            );
+
+            SERVER_BITCOIN_RPC_QUORUM_DISAGREEMENTS_TOTAL
+                .with_label_values(&[method])
+                .inc();
+        }
+
+        match votes.into_iter().find(|(_, count)| *count >= self.quorum) {
+            Some((value, _)) => Ok(value),
+            None => {
+                SERVER_BITCOIN_RPC_QUORUM_FAILURES_TOTAL
+                    .with_label_values(&[method])
+                    .inc();
+
+                bail!(
+                    "Less than {} bitcoin rpc backends agree on {method}",
+                    self.quorum
+                )
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl IServerBitcoinRpc for ServerBitcoinRpcQuorum {
+    fn get_bitcoin_rpc_config(&self) -> BitcoinRpcConfig {
+        self.backends[0].get_bitcoin_rpc_config()
+    }
+
+    fn get_url(&self) -> SafeUrl {
+        self.backends[0].get_url()
+    }
+
+    async fn get_block_count(&self) -> anyhow::Result<u64> {
+        let mut counts = self
+            .query_all("get_block_count", |backend| async move {
+                backend.get_block_count().await
+            })
+            .await;
+
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+
+        if counts.first() != counts.last() {
+            SERVER_BITCOIN_RPC_QUORUM_DISAGREEMENTS_TOTAL
+                .with_label_values(&["get_block_count"])
+                .inc();
+        }
+
+        // Every backend that reported a higher count has to know this one too
+        match counts.get(self.quorum - 1) {
+            Some(count) => Ok(*count),
+            None => {
+                SERVER_BITCOIN_RPC_QUORUM_FAILURES_TOTAL
+                    .with_label_values(&["get_block_count"])
+                    .inc();
+
+                bail!(
+                    "Less than {} bitcoin rpc backends reported a block count",
+                    self.quorum
+                )
+            }
+        }
+    }
+
+    async fn get_block_hash(&self, height: u64) -> anyhow::Result<BlockHash> {
+        self.query_quorum("get_block_hash", |backend| async move {
+            backend.get_block_hash(height).await
+        })
+        .await
+    }
+
+    async fn get_block(&self, block_hash: &BlockHash) -> anyhow::Result<Block> {
+        self.query_first("get_block", |backend| async move {
+            let block = backend.get_block(block_hash).await?;
+
+            ensure!(
+                block.block_hash() == *block_hash,
+                "Backend returned a block with a different hash"
+            );
+
+            ensure!(
+                block.check_merkle_root(),
+                "Backend returned a block with an invalid merkle root"
+            );
+
+            Ok(block)
+        })
+        .await
+    }
+
+    async fn get_feerate(&self) -> anyhow::Result<Option<Feerate>> {
+        self.query_first("get_feerate", |backend| async move {
+            backend.get_feerate().await
+        })
+        .await
+    }
+
+    async fn submit_transaction(&self, transaction: Transaction) -> anyhow::Result<()> {
+        let submitted = self
+            .query_all("submit_transaction", |backend| {
+                let transaction = transaction.clone();
+
+                async move { backend.submit_transaction(transaction).await }
+            })
+            .await;
+
+        ensure!(
+            !submitted.is_empty(),
+            "All bitcoin rpc backends failed for submit_transaction"
+        );
+
+        Ok(())
+    }
+
+    async fn get_sync_progress(&self) -> anyhow::Result<Option<f64>> {
+        self.query_first("get_sync_progress", |backend| async move {
+            backend.get_sync_progress().await
+        })
+        .await
+    }
+
+    async fn get_chain_id(&self) -> anyhow::Result<ChainId> {
+        self.query_quorum("get_chain_id", |backend| async move {
+            backend.get_chain_id().await
+        })
+        .await
+        .context("Bitcoin rpc backends do not agree on the chain")
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,171 @@
+use anyhow::{Context, anyhow};
+use bitcoin::hashes::Hash;
+use bitcoin::{Block, BlockHash, Transaction};
+use fedimint_core::envs::BitcoinRpcConfig;
+use fedimint_core::util::SafeUrl;
+use fedimint_core::{ChainId, Feerate};
+use fedimint_server_core::bitcoin_rpc::{DynServerBitcoinRpc, IServerBitcoinRpc};
+
+use super::ServerBitcoinRpcQuorum;
+
+/// Backend serving the chain of block hashes `chain`, or failing if it is
+/// `None`
+#[derive(Debug)]
+struct FakeBackend {
+    chain: Option<Vec<BlockHash>>,
+}
+
+fn backend(chain: Option<&[u8]>) -> DynServerBitcoinRpc {
+    FakeBackend {
+        chain: chain.map(|chain| {
+            chain
+                .iter()
+                .map(|byte| BlockHash::from_byte_array([*byte; 32]))
+                .collect()
+        }),
+    }
+    .into_dyn()
+}
+
+impl FakeBackend {
+    fn chain(&self) -> anyhow::Result<&Vec<BlockHash>> {
+        self.chain.as_ref().context("Backend is offline")
+    }
+}
+
+#[async_trait::async_trait]
+impl IServerBitcoinRpc for FakeBackend {
+    fn get_bitcoin_rpc_config(&self) -> BitcoinRpcConfig {
+        BitcoinRpcConfig {
+            kind: "fake".to_string(),
+            url: self.get_url(),
+        }
+    }
+
+    fn get_url(&self) -> SafeUrl {
+        "http://fake".parse().unwrap()
+    }
+
+    async fn get_block_count(&self) -> anyhow::Result<u64> {
+        Ok(self.chain()?.len() as u64)
+    }
+
+    async fn get_block_hash(&self, height: u64) -> anyhow::Result<BlockHash> {
+        self.chain()?
+            .get(height as usize)
+            .copied()
+            .context("Block is not available")
+    }
+
+    async fn get_block(&self, _block_hash: &BlockHash) -> anyhow::Result<Block> {
+        Err(anyhow!("not supported by the fake backend"))
+    }
+
+    async fn get_feerate(&self) -> anyhow::Result<Option<Feerate>> {
+        self.chain()?;
+
+        Ok(Some(Feerate { sats_per_kvb: 1000 }))
+    }
+
+    async fn submit_transaction(&self, _transaction: Transaction) -> anyhow::Result<()> {
+        self.chain().map(|_| ())
+    }
+
+    async fn get_sync_progress(&self) -> anyhow::Result<Option<f64>> {
+        Ok(None)
+    }
+
+    async fn get_chain_id(&self) -> anyhow::Result<ChainId> {
+        self.get_block_hash(1).await.map(ChainId::new)
+    }
+}
+
+#[test]
+fn rejects_unreachable_quorum() {
+    assert!(ServerBitcoinRpcQuorum::new(vec![backend(None)], 0).is_err());
+    assert!(ServerBitcoinRpcQuorum::new(vec![backend(None)], 2).is_err());
+}
+
+#[tokio::test]
+async fn reports_block_count_reached_by_quorum() {
+    let quorum = ServerBitcoinRpcQuorum::new(
+        vec![
+            backend(Some(&[0, 1, 2])),
+            backend(Some(&[0, 1, 2, 3, 4, 5, 6, 7])),
+            backend(None),
+            backend(Some(&[0, 1, 2, 3, 4])),
+        ],
+        2,
+    )
+    .unwrap();
+
+    assert_eq!(quorum.get_block_count().await.unwrap(), 5);
+    assert_eq!(
+        quorum.get_feerate().await.unwrap().unwrap().sats_per_kvb,
+        1000
+    );
+
+    let quorum = ServerBitcoinRpcQuorum::new(
+        vec![backend(Some(&[0, 1, 2])), backend(None), backend(None)],
+        2,
+    )
+    .unwrap();
+
+    assert!(quorum.get_block_count().await.is_err());
+}
+
+#[tokio::test]
+async fn reports_block_hash_agreed_on_by_quorum() {
+    let quorum = ServerBitcoinRpcQuorum::new(
+        vec![
+            backend(Some(&[0, 1, 2])),
+            backend(Some(&[0, 1, 3])),
+            backend(Some(&[0, 1, 3])),
+        ],
+        2,
+    )
+    .unwrap();
+
+    assert_eq!(
+        quorum.get_block_hash(2).await.unwrap(),
+        BlockHash::from_byte_array([3; 32])
+    );
+
+    assert_eq!(
+        quorum.get_chain_id().await.unwrap(),
+        ChainId::new(BlockHash::from_byte_array([1; 32]))
+    );
+
+    let quorum = ServerBitcoinRpcQuorum::new(
+        vec![
+            backend(Some(&[0, 1, 2])),
+            backend(Some(&[0, 1, 3])),
+            backend(None),
+        ],
+        2,
+    )
+    .unwrap();
+
+    assert!(quorum.get_block_hash(2).await.is_err());
+}
+
+#[tokio::test]
+async fn submits_transaction_to_all_backends() {
+    let transaction = Transaction {
+        version: bitcoin::transaction::Version(2),
+        lock_time: bitcoin::absolute::LockTime::ZERO,
+        input: vec![],
+        output: vec![],
+    };
+
+    let quorum = ServerBitcoinRpcQuorum::new(vec![backend(None), backend(Some(&[0]))], 1).unwrap();
+
+    quorum
+        .submit_transaction(transaction.clone())
+        .await
+        .unwrap();
+
+    let quorum = ServerBitcoinRpcQuorum::new(vec![backend(None), backend(None)], 1).unwrap();
+
+    assert!(quorum.submit_transaction(transaction).await.is_err());
+}
@@ -9,6 +9,9 @@ pub async fn render(wallet: &fedimint_walletv2_server::Wallet) -> Markup {
     let send_fee = wallet.send_fee_ui().await;
     let receive_fee = wallet.receive_fee_ui().await;
     let pending_tx_chain = wallet.pending_tx_chain_ui().await;
+    let pending_tx_chain_stuck = wallet.pending_tx_chain_stuck_ui().await;
+    let fee_budget = wallet.fee_budget_ui().await;
+    let pegout_batch = wallet.pegout_batch_ui().await;
     let tx_chain = wallet.tx_chain_ui().await;
     let recovery_keys = wallet.recovery_keys_ui().await;
 
@@ -89,6 +92,18 @@ pub async fn render(wallet: &fedimint_walletv2_server::Wallet) -> Markup {
                                         }
                                     }
                                 }
+                                tr {
+                                    th { "Fee Bump Budget" }
+                                    td { (fee_budget.sats_round_down()) " sats" }
+                                }
+                                tr {
+                                    th { "Open Peg-Out Batch" }
+                                    td {
+                                        (pegout_batch.len()) " peg-outs worth "
+                                        (pegout_batch.iter().map(|pegout| pegout.tx_out.value.to_sat()).sum::<u64>())
+                                        " sats"
+                                    }
+                                }
                             }
                         }
 
@@ -101,6 +116,11 @@ pub async fn render(wallet: &fedimint_walletv2_server::Wallet) -> Markup {
                                         "Warning: Transaction has been pending for more than 18 blocks!"
                                     }
                                 }
+                                @if pending_tx_chain_stuck {
+                                    div class="alert alert-warning" role="alert" {
+                                        "The feerate of the pending chain is less than 1/" (fedimint_walletv2_server::FEE_BUMP_FEERATE_RATIO) " of the consensus fee rate. The federation accelerates the chain via CPFP once its tip has been pending for " (fedimint_walletv2_server::FEE_BUMP_DELAY) " blocks, if the fee bump budget allows."
+                                    }
+                                }
 
                                 table class="table" {
                                     thead {
@@ -1,5 +1,6 @@
 use std::env;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -28,6 +29,7 @@
 use fedimint_logging::TracingSetup;
 use fedimint_server::core::{DynServerModuleInit, IServerModuleInit, ServerModuleInitRegistry};
 use fedimint_server_bitcoin_rpc::bitcoind::BitcoindClient;
+use fedimint_server_bitcoin_rpc::block_file::BlockFileClient;
 use fedimint_server_bitcoin_rpc::esplora::EsploraClient;
 use fedimint_server_core::bitcoin_rpc::{DynServerBitcoinRpc, IServerBitcoinRpc};
 use fedimint_testing_core::test_dir;
@@ -106,6 +108,9 @@ pub fn new_primary(
                     .into_dyn()
                 }
                 "esplora" => EsploraClient::new(&rpc_config.url).unwrap().into_dyn(),
+                "blockfile" => BlockFileClient::new(Path::new(rpc_config.url.path()))
+                    .unwrap()
+                    .into_dyn(),
                 kind => panic!("Unknown bitcoin rpc kind {kind}"),
             };
 
@@ -165,6 +170,20 @@ pub fn with_module(
         self
     }
 
+    /// Serves the federation's bitcoin backend from the recorded chain segment
+    /// in `dir` instead of the test bitcoin backend, see
+    /// [`fedimint_server_bitcoin_rpc::block_file`]. Blocks are added by writing
+    /// further block files, the test bitcoin backend does not affect the
+    /// federation anymore.
+    pub fn with_block_file_rpc(mut self, dir: &Path) -> Self {
+        let block_file_client =
+            BlockFileClient::new(dir).expect("Failed to open the block directory");
+
+        self.bitcoin_rpc = block_file_client.get_bitcoin_rpc_config();
+        self.server_bitcoin_rpc = block_file_client.into_dyn();
+        self
+    }
+
     pub fn with_server_only_module(
         mut self,
         server: impl IServerModuleInit + MaybeSend + MaybeSync + 'static,
@@ -197,6 +216,13 @@ pub fn new_fed_builder(&self, num_offline: u16) -> FederationTestBuilder {
 
     /// Creates a new Gateway that can be used for module tests.
     pub async fn new_gateway(&self) -> Gateway {
+        self.new_gateway_with_lightning(Arc::new(FakeLightningTest::new()))
+            .await
+    }
+
+    /// Creates a new Gateway connected to the given Lightning node, e.g. the
+    /// gateway node of a [`fedimint_lightning::mock::MockLightningNetwork`].
+    pub async fn new_gateway_with_lightning(&self, ln_client: Arc<dyn ILnRpcClient>) -> Gateway {
         // Use server_gens.iter() to match the alphabetical order used by the server
         // when assigning module instance IDs (BTreeMap iteration order)
         let module_kinds: Vec<_> = self
@@ -238,8 +264,6 @@ pub async fn new_gateway(&self) -> Gateway {
                 .await
                 .expect("Failed to initialize gateway");
 
-        let ln_client: Arc<dyn ILnRpcClient> = Arc::new(FakeLightningTest::new());
-
         let LightningInfo::Connected {
             public_key: lightning_public_key,
             alias: lightning_alias,
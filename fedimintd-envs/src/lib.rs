@@ -32,6 +32,12 @@
 
 pub const FM_ESPLORA_URL_ENV: &str = "FM_ESPLORA_URL";
 
+pub const FM_ADDITIONAL_ESPLORA_URLS_ENV: &str = "FM_ADDITIONAL_ESPLORA_URLS";
+
+pub const FM_BITCOIN_RPC_QUORUM_ENV: &str = "FM_BITCOIN_RPC_QUORUM";
+
+pub const FM_BITCOIN_BLOCK_DIR_ENV: &str = "FM_BITCOIN_BLOCK_DIR";
+
 pub const FM_ENABLE_IROH_ENV: &str = "FM_ENABLE_IROH";
 
 pub const FM_IROH_P2P_RELAY_ENV: &str = "FM_IROH_P2P_RELAY";
@@ -42,18 +42,21 @@
 use fedimint_server::net::api::ApiSecrets;
 use fedimint_server_bitcoin_rpc::BitcoindClientWithFallback;
 use fedimint_server_bitcoin_rpc::bitcoind::BitcoindClient;
+use fedimint_server_bitcoin_rpc::block_file::BlockFileClient;
 use fedimint_server_bitcoin_rpc::esplora::EsploraClient;
+use fedimint_server_bitcoin_rpc::quorum::ServerBitcoinRpcQuorum;
 use fedimint_server_bitcoin_rpc::tracked::ServerBitcoinRpcTracked;
 use fedimint_server_core::ServerModuleInitRegistryExt;
 use fedimint_server_core::bitcoin_rpc::IServerBitcoinRpc;
 use fedimint_unknown_server::UnknownInit;
 use fedimint_wallet_server::WalletInit;
 use fedimintd_envs::{
-    FM_API_URL_ENV, FM_BIND_API_ENV, FM_BIND_API_NEXT_ENV, FM_BIND_METRICS_ENV, FM_BIND_P2P_ENV,
-    FM_BIND_TOKIO_CONSOLE_ENV, FM_BIND_UI_ENV, FM_BITCOIN_NETWORK_ENV, FM_BITCOIND_PASSWORD_ENV,
-    FM_BITCOIND_URL_ENV, FM_BITCOIND_URL_PASSWORD_FILE_ENV, FM_BITCOIND_USERNAME_ENV,
-    FM_DATA_DIR_ENV, FM_DB_CHECKPOINT_RETENTION_ENV, FM_DISABLE_META_MODULE_ENV,
-    FM_ENABLE_IROH_ENV, FM_ESPLORA_URL_ENV, FM_FORCE_API_SECRETS_ENV,
+    FM_ADDITIONAL_ESPLORA_URLS_ENV, FM_API_URL_ENV, FM_BIND_API_ENV, FM_BIND_API_NEXT_ENV,
+    FM_BIND_METRICS_ENV, FM_BIND_P2P_ENV, FM_BIND_TOKIO_CONSOLE_ENV, FM_BIND_UI_ENV,
+    FM_BITCOIN_BLOCK_DIR_ENV, FM_BITCOIN_NETWORK_ENV, FM_BITCOIN_RPC_QUORUM_ENV,
+    FM_BITCOIND_PASSWORD_ENV, FM_BITCOIND_URL_ENV, FM_BITCOIND_URL_PASSWORD_FILE_ENV,
+    FM_BITCOIND_USERNAME_ENV, FM_DATA_DIR_ENV, FM_DB_CHECKPOINT_RETENTION_ENV,
+    FM_DISABLE_META_MODULE_ENV, FM_ENABLE_IROH_ENV, FM_ESPLORA_URL_ENV, FM_FORCE_API_SECRETS_ENV,
     FM_IROH_API_MAX_CONNECTIONS_ENV, FM_IROH_API_MAX_REQUESTS_PER_CONNECTION_ENV,
     FM_IROH_NEXT_ENABLE_ENV, FM_IROH_P2P_RELAY_ENV, FM_P2P_MAX_CONNECTION_AGE_SECS_ENV,
     FM_P2P_URL_ENV, FM_PASSWORD_API_ENV, FM_PASSWORD_UI_ENV, FM_SESSION_TIMEOUT_SECS_ENV,
@@ -89,7 +92,7 @@
         ArgGroup::new("bitcoin_rpc")
             .required(true)
             .multiple(true)
-            .args(["bitcoind_url", "esplora_url"])
+            .args(["bitcoind_url", "esplora_url", "bitcoin_block_dir"])
     )
 )]
 struct ServerOpts {
@@ -143,6 +146,33 @@ struct ServerOpts {
     #[arg(long, env = FM_ESPLORA_URL_ENV)]
     esplora_url: Option<SafeUrl>,
 
+    /// Further Esplora HTTP base URLs to cross-check the chain against, only
+    /// used together with `--bitcoin-rpc-quorum`
+    #[arg(
+        long,
+        env = FM_ADDITIONAL_ESPLORA_URLS_ENV,
+        value_delimiter = ',',
+        requires = "bitcoin_rpc_quorum"
+    )]
+    additional_esplora_urls: Vec<SafeUrl>,
+
+    /// If set, all configured bitcoin backends are queried and block hashes
+    /// are only accepted once this many of them agree. Otherwise esplora is
+    /// only used as a fallback if bitcoind is configured as well.
+    #[arg(long, env = FM_BITCOIN_RPC_QUORUM_ENV)]
+    bitcoin_rpc_quorum: Option<usize>,
+
+    /// Directory with a recorded chain segment to serve instead of a live
+    /// bitcoin backend, one raw block per `<height>.block` file. Meant for
+    /// replay tests: transactions are written to its `outbox` subdirectory
+    /// instead of being broadcast.
+    #[arg(
+        long,
+        env = FM_BITCOIN_BLOCK_DIR_ENV,
+        conflicts_with_all = ["bitcoind_url", "esplora_url", "bitcoin_rpc_quorum"]
+    )]
+    bitcoin_block_dir: Option<PathBuf>,
+
     /// Address we bind to for p2p consensus communication
     ///
     /// Should be `0.0.0.0:8173` most of the time, as p2p connectivity is public
@@ -297,6 +327,21 @@ pub async fn get_bitcoind_url_and_password(&self) -> anyhow::Result<(SafeUrl, St
             Ok((url, password))
         }
     }
+
+    /// Builds the bitcoind client from the configured url and credentials
+    async fn bitcoind_client(&self) -> anyhow::Result<BitcoindClient> {
+        let username = self
+            .bitcoind_username
+            .clone()
+            .context("FM_BITCOIND_URL is set but FM_BITCOIND_USERNAME is not")?;
+
+        let (url, password) = self
+            .get_bitcoind_url_and_password()
+            .await
+            .context("Failed to get bitcoind url")?;
+
+        BitcoindClient::new(username, password, &url)
+    }
 }
 
 /// Block the thread and run a Fedimintd server
@@ -424,43 +469,56 @@ pub async fn run(
         ModuleRegistry::default(),
     );
 
-    let dyn_server_bitcoin_rpc = match (
-        server_opts.bitcoind_url.as_ref(),
-        server_opts.esplora_url.as_ref(),
-    ) {
-        (Some(_), None) => {
-            let bitcoind_username = server_opts
-                .bitcoind_username
-                .clone()
-                .expect("FM_BITCOIND_URL is set but FM_BITCOIND_USERNAME is not");
-            let (bitcoind_url, bitcoind_password) = server_opts
-                .get_bitcoind_url_and_password()
-                .await
-                .expect("Failed to get bitcoind url");
-            BitcoindClient::new(bitcoind_username, bitcoind_password, &bitcoind_url)
-                .unwrap()
-                .into_dyn()
+    let dyn_server_bitcoin_rpc = if let Some(dir) = server_opts.bitcoin_block_dir.as_ref() {
+        BlockFileClient::new(dir)
+            .expect("Failed to open bitcoin block directory")
+            .into_dyn()
+    } else if let Some(quorum) = server_opts.bitcoin_rpc_quorum {
+        let mut backends = vec![];
+
+        if server_opts.bitcoind_url.is_some() {
+            backends.push(
+                server_opts
+                    .bitcoind_client()
+                    .await
+                    .expect("Failed to create bitcoind client")
+                    .into_dyn(),
+            );
         }
-        (None, Some(url)) => EsploraClient::new(url).unwrap().into_dyn(),
-        (Some(_), Some(esplora_url)) => {
-            let bitcoind_username = server_opts
-                .bitcoind_username
-                .clone()
-                .expect("FM_BITCOIND_URL is set but FM_BITCOIND_USERNAME is not");
-            let (bitcoind_url, bitcoind_password) = server_opts
-                .get_bitcoind_url_and_password()
+
+        for url in server_opts
+            .esplora_url
+            .iter()
+            .chain(&server_opts.additional_esplora_urls)
+        {
+            backends.push(EsploraClient::new(url).unwrap().into_dyn());
+        }
+
+        ServerBitcoinRpcQuorum::new(backends, quorum)
+            .expect("Invalid bitcoin rpc quorum")
+            .into_dyn()
+    } else {
+        match (
+            server_opts.bitcoind_url.as_ref(),
+            server_opts.esplora_url.as_ref(),
+        ) {
+            (Some(_), None) => server_opts
+                .bitcoind_client()
                 .await
-                .expect("Failed to get bitcoind url");
-            BitcoindClientWithFallback::new(
-                bitcoind_username,
-                bitcoind_password,
-                &bitcoind_url,
+                .expect("Failed to create bitcoind client")
+                .into_dyn(),
+            (None, Some(url)) => EsploraClient::new(url).unwrap().into_dyn(),
+            (Some(_), Some(esplora_url)) => BitcoindClientWithFallback::with_bitcoind_client(
+                server_opts
+                    .bitcoind_client()
+                    .await
+                    .expect("Failed to create bitcoind client"),
                 esplora_url,
             )
             .unwrap()
-            .into_dyn()
+            .into_dyn(),
+            _ => unreachable!("ArgGroup already enforced XOR relation"),
         }
-        _ => unreachable!("ArgGroup already enforced XOR relation"),
     };
     let dyn_server_bitcoin_rpc =
         ServerBitcoinRpcTracked::new(dyn_server_bitcoin_rpc, "server").into_dyn();
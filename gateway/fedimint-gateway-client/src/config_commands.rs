@@ -2,8 +2,13 @@
 use fedimint_core::Amount;
 use fedimint_core::config::FederationId;
 use fedimint_core::util::SafeUrl;
-use fedimint_gateway_client::{get_config, get_info, set_fees, set_mnemonic};
-use fedimint_gateway_common::{ConfigPayload, SetFeesPayload, SetMnemonicPayload};
+use fedimint_gateway_client::{
+    get_config, get_info, set_exposure_limits, set_fee_policy, set_fees, set_mnemonic, set_webhook,
+};
+use fedimint_gateway_common::{
+    ConfigPayload, ExposureLimits, FeePolicy, SetExposureLimitsPayload, SetFeePolicyPayload,
+    SetFeesPayload, SetMnemonicPayload, SetWebhookPayload, WebhookSettings,
+};
 use fedimint_ln_common::client::GatewayApi;
 
 use crate::{CliOutput, CliOutputResult};
@@ -39,12 +44,49 @@ pub enum ConfigCommands {
         #[clap(long)]
         tx_ppm: Option<u64>,
     },
+    /// Set the limits on the gateway's exposure to a federation. Limits that
+    /// are not provided are removed.
+    SetExposureLimits {
+        #[clap(long)]
+        federation_id: FederationId,
+
+        /// Maximum ecash balance the gateway holds in the federation
+        #[clap(long)]
+        max_balance: Option<Amount>,
+
+        /// Maximum sum of the payments to and from the federation that can be
+        /// in flight at the same time
+        #[clap(long)]
+        max_in_flight: Option<Amount>,
+    },
+    /// Set the policy the gateway derives a federation's LNv2 fees from. The
+    /// federation goes back to its static fees if no policy is provided.
+    SetFeePolicy {
+        #[clap(long)]
+        federation_id: FederationId,
+
+        /// Fee policy as JSON, e.g. `{"send_curve":[{"ecash_share_ppm":0,
+        /// "multiplier_percent":50}],"receive_curve":[],"amount_tiers":[]}`
+        #[clap(long, value_parser = parse_fee_policy)]
+        policy: Option<FeePolicy>,
+    },
     /// Instructs the gateway to create a new mnemonic or set it to the provided
     /// mnemonic
     SetMnemonic {
         #[clap(long)]
         words: Option<String>,
     },
+    /// Set the webhook the gateway pushes payment, federation, liquidity and
+    /// state notifications to. Notifications stop and undelivered ones are
+    /// dropped if no url is provided.
+    SetWebhook {
+        #[clap(long, requires = "secret")]
+        url: Option<SafeUrl>,
+
+        /// Secret the notifications are signed with using HMAC-SHA256
+        #[clap(long, requires = "url")]
+        secret: Option<String>,
+    },
 }
 
 impl ConfigCommands {
@@ -90,10 +132,55 @@ pub async fn handle(self, client: &GatewayApi, base_url: &SafeUrl) -> CliOutputR
                 .await?;
                 Ok(CliOutput::Empty)
             }
+            Self::SetExposureLimits {
+                federation_id,
+                max_balance,
+                max_in_flight,
+            } => {
+                set_exposure_limits(
+                    client,
+                    base_url,
+                    SetExposureLimitsPayload {
+                        federation_id,
+                        limits: ExposureLimits {
+                            max_balance,
+                            max_in_flight,
+                        },
+                    },
+                )
+                .await?;
+                Ok(CliOutput::Empty)
+            }
+            Self::SetFeePolicy {
+                federation_id,
+                policy,
+            } => {
+                set_fee_policy(
+                    client,
+                    base_url,
+                    SetFeePolicyPayload {
+                        federation_id,
+                        policy,
+                    },
+                )
+                .await?;
+                Ok(CliOutput::Empty)
+            }
             Self::SetMnemonic { words } => {
                 set_mnemonic(client, base_url, SetMnemonicPayload { words }).await?;
                 Ok(CliOutput::Empty)
             }
+            Self::SetWebhook { url, secret } => {
+                let webhook = url
+                    .zip(secret)
+                    .map(|(url, secret)| WebhookSettings { url, secret });
+                set_webhook(client, base_url, SetWebhookPayload { webhook }).await?;
+                Ok(CliOutput::Empty)
+            }
         }
     }
 }
+
+fn parse_fee_policy(s: &str) -> Result<FeePolicy, serde_json::Error> {
+    serde_json::from_str(s)
+}
@@ -1,18 +1,23 @@
+use std::io::Write as _;
+use std::path::PathBuf;
 use std::time::{Duration, UNIX_EPOCH};
 
 use clap::Subcommand;
 use fedimint_connectors::error::ServerError;
 use fedimint_core::config::FederationId;
+use fedimint_core::core::ModuleKind;
 use fedimint_core::fedimint_build_code_version_env;
 use fedimint_core::time::now;
 use fedimint_core::util::SafeUrl;
+use fedimint_eventlog::export::EventLogFilter;
 use fedimint_eventlog::{EventKind, EventLogId};
 use fedimint_gateway_client::{
-    connect_federation, get_balances, get_info, get_invite_codes, get_mnemonic, leave_federation,
-    payment_log, payment_summary, stop,
+    connect_federation, export_event_log, get_balances, get_info, get_invite_codes, get_mnemonic,
+    leave_federation, payment_log, payment_summary, stop,
 };
 use fedimint_gateway_common::{
-    ConnectFedPayload, LeaveFedPayload, PaymentLogPayload, PaymentSummaryPayload,
+    ConnectFedPayload, ExportEventLogPayload, LeaveFedPayload, PaymentLogPayload,
+    PaymentSummaryPayload,
 };
 use fedimint_ln_common::client::GatewayApi;
 
@@ -63,6 +68,36 @@ pub enum GeneralCommands {
         #[clap(long)]
         event_kinds: Vec<EventKind>,
     },
+    /// Export a federation client's event log as newline-delimited JSON
+    ///
+    /// Resumes after the last entry exported under the same `--name`. Every
+    /// page is acknowledged only after it was written out.
+    ExportEventLog {
+        #[clap(long)]
+        federation_id: FederationId,
+
+        /// Name of the export, each name keeps its own cursor
+        #[clap(long, default_value = "gateway-cli")]
+        name: String,
+
+        /// Only export events of these kinds (can be specified multiple times
+        /// or comma-separated)
+        #[clap(long, value_delimiter = ',')]
+        event_kind: Vec<EventKind>,
+
+        /// Only export events of these module kinds (can be specified
+        /// multiple times or comma-separated)
+        #[clap(long, value_delimiter = ',')]
+        module: Vec<String>,
+
+        /// Maximum number of entries to request per page
+        #[clap(long)]
+        limit: Option<usize>,
+
+        /// Append to this file instead of writing to stdout
+        #[clap(long)]
+        out_file: Option<PathBuf>,
+    },
     /// Create a bcrypt hash of a password, for use in gateway deployment
     CreatePasswordHash {
         password: String,
@@ -154,6 +189,81 @@ pub async fn handle(self, client: &GatewayApi, base_url: &SafeUrl) -> CliOutputR
                 .await?;
                 Ok(CliOutput::PaymentLog(payment_log))
             }
+            Self::ExportEventLog {
+                federation_id,
+                name,
+                event_kind,
+                module,
+                limit,
+                out_file,
+            } => {
+                let filter = EventLogFilter::default()
+                    .with_event_kinds(event_kind)
+                    .with_module_kinds(
+                        module
+                            .iter()
+                            .map(String::as_str)
+                            .map(ModuleKind::clone_from_str),
+                    );
+
+                let mut ack = None;
+                let mut exported = 0;
+
+                let next_pos = loop {
+                    let export = export_event_log(
+                        client,
+                        base_url,
+                        ExportEventLogPayload {
+                            federation_id,
+                            name: name.clone(),
+                            filter: filter.clone(),
+                            ack,
+                            limit,
+                        },
+                    )
+                    .await?;
+
+                    // The request acknowledged the previous page, so there is
+                    // nothing left to acknowledge once a page comes back empty
+                    if export.entries.is_empty() {
+                        break ack.unwrap_or(export.next_pos);
+                    }
+
+                    let mut buf = Vec::new();
+
+                    for entry in &export.entries {
+                        serde_json::to_writer(&mut buf, entry)
+                            .expect("Event log entries serialize to JSON");
+                        buf.push(b'\n');
+                    }
+
+                    match &out_file {
+                        Some(out_file) => std::fs::OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(out_file)
+                            .and_then(|mut file| file.write_all(&buf)),
+                        None => std::io::stdout().write_all(&buf),
+                    }
+                    .map_err(|e| ServerError::InternalClientError(e.into()))?;
+
+                    exported += export.entries.len();
+                    ack = Some(export.next_pos);
+                };
+
+                let summary = CliOutput::EventLogExport { exported, next_pos };
+
+                // Keep stdout pure newline-delimited JSON if the entries went there
+                if out_file.is_none() {
+                    eprintln!(
+                        "{}",
+                        serde_json::to_string(&summary).expect("CliOutput serializes to JSON")
+                    );
+                    return Ok(CliOutput::Empty);
+                }
+
+                Ok(summary)
+            }
             Self::CreatePasswordHash { password, cost } => {
                 let hash = bcrypt::hash(password, cost.unwrap_or(bcrypt::DEFAULT_COST))
                     .expect("Unable to create bcrypt hash");
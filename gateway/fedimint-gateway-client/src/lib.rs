@@ -14,7 +14,8 @@
     CREATE_BOLT12_OFFER_FOR_OPERATOR_ENDPOINT, ChannelInfo, CloseChannelsWithPeerRequest,
     CloseChannelsWithPeerResponse, ConfigPayload, ConnectFedPayload, ConnectPeerRequest,
     CreateInvoiceForOperatorPayload, CreateOfferPayload, CreateOfferResponse,
-    DepositAddressPayload, DepositAddressRecheckPayload, FederationInfo, GATEWAY_INFO_ENDPOINT,
+    DepositAddressPayload, DepositAddressRecheckPayload, EXPORT_EVENT_LOG_ENDPOINT,
+    ExportEventLogPayload, ExportEventLogResponse, FederationInfo, GATEWAY_INFO_ENDPOINT,
     GET_BALANCES_ENDPOINT, GET_INVOICE_ENDPOINT, GET_LN_ONCHAIN_ADDRESS_ENDPOINT, GatewayBalances,
     GatewayFedConfig, GatewayInfo, GetInvoiceRequest, GetInvoiceResponse, INVITE_CODES_ENDPOINT,
     LEAVE_FED_ENDPOINT, LIST_CHANNELS_ENDPOINT, LIST_TRANSACTIONS_ENDPOINT, LeaveFedPayload,
@@ -23,12 +24,16 @@
     PAY_INVOICE_FOR_OPERATOR_ENDPOINT, PAY_OFFER_FOR_OPERATOR_ENDPOINT, PAYMENT_LOG_ENDPOINT,
     PAYMENT_SUMMARY_ENDPOINT, PEGIN_FROM_ONCHAIN_ENDPOINT, PayInvoiceForOperatorPayload,
     PayOfferPayload, PayOfferResponse, PaymentLogPayload, PaymentLogResponse,
-    PaymentSummaryPayload, PaymentSummaryResponse, PeginFromOnchainPayload, RECEIVE_ECASH_ENDPOINT,
-    ReceiveEcashPayload, ReceiveEcashResponse, SEND_ONCHAIN_ENDPOINT, SET_CHANNEL_FEES_ENDPOINT,
-    SET_FEES_ENDPOINT, SPEND_ECASH_ENDPOINT, STOP_ENDPOINT, SendOnchainRequest,
-    SetChannelFeesRequest, SetFeesPayload, SetMnemonicPayload, SpendEcashPayload,
-    SpendEcashResponse, WITHDRAW_ENDPOINT, WITHDRAW_TO_ONCHAIN_ENDPOINT, WithdrawPayload,
-    WithdrawResponse, WithdrawToOnchainPayload,
+    PaymentSummaryPayload, PaymentSummaryResponse, PeginFromOnchainPayload,
+    REBALANCE_CONFIG_ENDPOINT, REBALANCE_ENDPOINT, RECEIVE_ECASH_ENDPOINT, RebalanceConfigResponse,
+    RebalanceResponse, RebalanceSettings, ReceiveEcashPayload, ReceiveEcashResponse,
+    SEND_ONCHAIN_ENDPOINT, SET_CHANNEL_FEES_ENDPOINT, SET_EXPOSURE_LIMITS_ENDPOINT,
+    SET_FEE_POLICY_ENDPOINT, SET_FEES_ENDPOINT, SET_LIQUIDITY_TARGET_ENDPOINT,
+    SET_REBALANCE_SETTINGS_ENDPOINT, SET_WEBHOOK_ENDPOINT, SPEND_ECASH_ENDPOINT, STOP_ENDPOINT,
+    SendOnchainRequest, SetChannelFeesRequest, SetExposureLimitsPayload, SetFeePolicyPayload,
+    SetFeesPayload, SetLiquidityTargetPayload, SetMnemonicPayload, SetWebhookPayload,
+    SpendEcashPayload, SpendEcashResponse, WITHDRAW_ENDPOINT, WITHDRAW_TO_ONCHAIN_ENDPOINT,
+    WithdrawPayload, WithdrawResponse, WithdrawToOnchainPayload,
 };
 use fedimint_ln_common::Method;
 use fedimint_ln_common::client::GatewayApi;
@@ -145,6 +150,46 @@ pub async fn set_fees(
         .await
 }
 
+pub async fn set_exposure_limits(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: SetExposureLimitsPayload,
+) -> ServerResult<()> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            SET_EXPOSURE_LIMITS_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
+pub async fn set_fee_policy(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: SetFeePolicyPayload,
+) -> ServerResult<()> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            SET_FEE_POLICY_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
+pub async fn set_webhook(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: SetWebhookPayload,
+) -> ServerResult<()> {
+    client
+        .request(base_url, Method::POST, SET_WEBHOOK_ENDPOINT, Some(payload))
+        .await
+}
+
 pub async fn create_invoice_for_self(
     client: &GatewayApi,
     base_url: &SafeUrl,
@@ -347,6 +392,21 @@ pub async fn payment_log(
         .await
 }
 
+pub async fn export_event_log(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: ExportEventLogPayload,
+) -> ServerResult<ExportEventLogResponse> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            EXPORT_EVENT_LOG_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
 pub async fn payment_summary(
     client: &GatewayApi,
     base_url: &SafeUrl,
@@ -440,3 +500,53 @@ pub async fn get_invite_codes(
         )
         .await
 }
+
+pub async fn get_rebalance_config(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+) -> ServerResult<RebalanceConfigResponse> {
+    client
+        .request::<(), RebalanceConfigResponse>(
+            base_url,
+            Method::GET,
+            REBALANCE_CONFIG_ENDPOINT,
+            None,
+        )
+        .await
+}
+
+pub async fn set_rebalance_settings(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: RebalanceSettings,
+) -> ServerResult<()> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            SET_REBALANCE_SETTINGS_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
+pub async fn set_liquidity_target(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: SetLiquidityTargetPayload,
+) -> ServerResult<()> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            SET_LIQUIDITY_TARGET_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
+pub async fn rebalance(client: &GatewayApi, base_url: &SafeUrl) -> ServerResult<RebalanceResponse> {
+    client
+        .request::<(), RebalanceResponse>(base_url, Method::GET, REBALANCE_ENDPOINT, None)
+        .await
+}
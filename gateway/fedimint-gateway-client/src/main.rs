@@ -5,6 +5,7 @@
 mod general_commands;
 mod lightning_commands;
 mod onchain_commands;
+mod rebalance_commands;
 
 use std::collections::BTreeMap;
 
@@ -19,17 +20,20 @@
 use fedimint_core::config::FederationId;
 use fedimint_core::invite_code::InviteCode;
 use fedimint_core::util::SafeUrl;
+use fedimint_eventlog::EventLogId;
 use fedimint_gateway_common::{
     ChannelInfo, CloseChannelsWithPeerResponse, CreateOfferResponse, FederationConfig,
     FederationInfo, GatewayBalances, GatewayFedConfig, GatewayInfo, GetInvoiceResponse,
     ListTransactionsResponse, MnemonicResponse, PayOfferResponse, PaymentLogResponse,
-    PaymentSummaryResponse, ReceiveEcashResponse, SpendEcashResponse, WithdrawResponse,
+    PaymentSummaryResponse, RebalanceConfigResponse, RebalanceResponse, ReceiveEcashResponse,
+    SpendEcashResponse, WithdrawResponse,
 };
 use fedimint_ln_common::client::GatewayApi;
 use fedimint_logging::TracingSetup;
 use general_commands::GeneralCommands;
 use lightning_commands::LightningCommands;
 use onchain_commands::OnchainCommands;
+use rebalance_commands::RebalanceCommands;
 use serde::Serialize;
 
 /// Unified output type for all gateway-cli commands.
@@ -46,6 +50,10 @@ pub enum CliOutput {
     Federation(FederationInfo),
     Mnemonic(MnemonicResponse),
     PaymentLog(PaymentLogResponse),
+    EventLogExport {
+        exported: usize,
+        next_pos: EventLogId,
+    },
     PaymentSummary(PaymentSummaryResponse),
     InviteCodes(BTreeMap<FederationId, BTreeMap<PeerId, (String, InviteCode)>>),
     PasswordHash(String),
@@ -91,6 +99,10 @@ pub enum CliOutput {
     Config(GatewayFedConfig),
     FederationConfigs(Vec<FederationConfig>),
 
+    // Rebalance commands
+    RebalanceConfig(RebalanceConfigResponse),
+    Rebalance(RebalanceResponse),
+
     // No output (for commands that succeed silently)
     #[serde(skip)]
     Empty,
@@ -242,6 +254,8 @@ enum Commands {
     Onchain(OnchainCommands),
     #[command(subcommand)]
     Cfg(ConfigCommands),
+    #[command(subcommand)]
+    Rebalance(RebalanceCommands),
     Completion {
         shell: clap_complete::Shell,
     },
@@ -285,6 +299,9 @@ async fn run() -> CliOutputResult {
         Commands::Ecash(ecash_command) => ecash_command.handle(&client, &cli.address).await?,
         Commands::Onchain(onchain_command) => onchain_command.handle(&client, &cli.address).await?,
         Commands::Cfg(config_commands) => config_commands.handle(&client, &cli.address).await?,
+        Commands::Rebalance(rebalance_commands) => {
+            rebalance_commands.handle(&client, &cli.address).await?
+        }
         Commands::Completion { shell } => {
             clap_complete::generate(
                 shell,
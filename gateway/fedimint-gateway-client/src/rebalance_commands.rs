@@ -0,0 +1,151 @@
+use clap::Subcommand;
+use fedimint_core::Amount;
+use fedimint_core::config::FederationId;
+use fedimint_core::util::SafeUrl;
+use fedimint_gateway_client::{
+    get_rebalance_config, rebalance, set_liquidity_target, set_rebalance_settings,
+};
+use fedimint_gateway_common::{LiquidityTarget, NodeAddress, SetLiquidityTargetPayload};
+use fedimint_ln_common::client::GatewayApi;
+
+use crate::{CliOutput, CliOutputResult};
+
+/// Commands for configuring and running the automated rebalancing of the
+/// gateway's liquidity between its federations and its lightning node.
+#[derive(Subcommand)]
+pub enum RebalanceCommands {
+    /// Display the rebalancer settings and the liquidity target of each
+    /// federation
+    Config,
+    /// Update the rebalancer settings. Settings that are not provided are left
+    /// unchanged.
+    SetSettings {
+        /// Whether rebalancing runs on a schedule
+        #[clap(long)]
+        enabled: Option<bool>,
+
+        /// Seconds between two scheduled rebalancing passes
+        #[clap(long)]
+        interval_secs: Option<u64>,
+
+        /// Fee rate used for peg-ins and channel opens from the lightning
+        /// node's onchain wallet
+        #[clap(long)]
+        fee_rate_sats_per_vbyte: Option<u64>,
+
+        /// Onchain balance that is never used for peg-ins
+        #[clap(long)]
+        onchain_reserve_sats: Option<u64>,
+
+        /// Outbound liquidity the lightning node should keep
+        #[clap(long)]
+        min_outbound_msats: Option<u64>,
+
+        /// Inbound liquidity the lightning node should keep
+        #[clap(long)]
+        min_inbound_msats: Option<u64>,
+
+        /// Peer in `pubkey@host[:port]` format that channels are opened to
+        /// when the outbound liquidity is too low
+        #[clap(long, conflicts_with = "clear_channel_peer")]
+        channel_peer: Option<NodeAddress>,
+
+        /// Stop opening channels, only report low outbound liquidity
+        #[clap(long)]
+        clear_channel_peer: bool,
+    },
+    /// Set the range of ecash the gateway keeps in a federation
+    SetTarget {
+        #[clap(long)]
+        federation_id: FederationId,
+
+        #[clap(long)]
+        min_ecash: Amount,
+
+        #[clap(long)]
+        max_ecash: Amount,
+    },
+    /// Stop rebalancing a federation
+    RemoveTarget {
+        #[clap(long)]
+        federation_id: FederationId,
+    },
+    /// Run a rebalancing pass immediately
+    Run,
+}
+
+impl RebalanceCommands {
+    pub async fn handle(self, client: &GatewayApi, base_url: &SafeUrl) -> CliOutputResult {
+        match self {
+            Self::Config => {
+                let config = get_rebalance_config(client, base_url).await?;
+                Ok(CliOutput::RebalanceConfig(config))
+            }
+            Self::SetSettings {
+                enabled,
+                interval_secs,
+                fee_rate_sats_per_vbyte,
+                onchain_reserve_sats,
+                min_outbound_msats,
+                min_inbound_msats,
+                channel_peer,
+                clear_channel_peer,
+            } => {
+                let mut settings = get_rebalance_config(client, base_url).await?.settings;
+                settings.enabled = enabled.unwrap_or(settings.enabled);
+                settings.interval_secs = interval_secs.unwrap_or(settings.interval_secs);
+                settings.fee_rate_sats_per_vbyte =
+                    fee_rate_sats_per_vbyte.unwrap_or(settings.fee_rate_sats_per_vbyte);
+                settings.onchain_reserve_sats =
+                    onchain_reserve_sats.unwrap_or(settings.onchain_reserve_sats);
+                settings.min_outbound_msats =
+                    min_outbound_msats.unwrap_or(settings.min_outbound_msats);
+                settings.min_inbound_msats =
+                    min_inbound_msats.unwrap_or(settings.min_inbound_msats);
+                if clear_channel_peer {
+                    settings.channel_peer = None;
+                } else if channel_peer.is_some() {
+                    settings.channel_peer = channel_peer;
+                }
+
+                set_rebalance_settings(client, base_url, settings).await?;
+                Ok(CliOutput::Empty)
+            }
+            Self::SetTarget {
+                federation_id,
+                min_ecash,
+                max_ecash,
+            } => {
+                set_liquidity_target(
+                    client,
+                    base_url,
+                    SetLiquidityTargetPayload {
+                        federation_id,
+                        target: Some(LiquidityTarget {
+                            min_ecash,
+                            max_ecash,
+                        }),
+                    },
+                )
+                .await?;
+                Ok(CliOutput::Empty)
+            }
+            Self::RemoveTarget { federation_id } => {
+                set_liquidity_target(
+                    client,
+                    base_url,
+                    SetLiquidityTargetPayload {
+                        federation_id,
+                        target: None,
+                    },
+                )
+                .await?;
+                Ok(CliOutput::Empty)
+            }
+            Self::Run => {
+                let response = rebalance(client, base_url).await?;
+                Ok(CliOutput::Rebalance(response))
+            }
+        }
+    }
+}
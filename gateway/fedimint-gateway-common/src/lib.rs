@@ -4,7 +4,8 @@
 use std::time::{Duration, SystemTime};
 
 use bitcoin::address::NetworkUnchecked;
-use bitcoin::hashes::sha256;
+use bitcoin::hashes::hex::DisplayHex as _;
+use bitcoin::hashes::{Hash as _, HashEngine as _, Hmac, HmacEngine, sha256};
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::{Address, Network, OutPoint};
 use clap::Subcommand;
@@ -13,10 +14,12 @@
     FM_LND_TIME_PREF_ENV, FM_LND_TLS_CERT_ENV, FM_PORT_LDK,
 };
 use fedimint_core::config::{FederationId, JsonClientConfig};
-use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::encoding::{Decodable, DecodeError, Encodable};
 use fedimint_core::invite_code::InviteCode;
+use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::util::{SafeUrl, get_average, get_median};
 use fedimint_core::{Amount, BitcoinAmountOrAll, secp256k1};
+use fedimint_eventlog::export::EventLogFilter;
 use fedimint_eventlog::{EventKind, EventLogId, PersistedLogEntry, StructuredPaymentEvents};
 use fedimint_lnv2_common::gateway_api::PaymentFee;
 use fedimint_wallet_client::PegOutFees;
@@ -36,6 +39,7 @@
 pub const CONNECT_FED_ENDPOINT: &str = "/connect_fed";
 pub const CREATE_BOLT11_INVOICE_FOR_OPERATOR_ENDPOINT: &str = "/create_bolt11_invoice_for_operator";
 pub const CREATE_BOLT12_OFFER_FOR_OPERATOR_ENDPOINT: &str = "/create_bolt12_offer_for_operator";
+pub const EXPORT_EVENT_LOG_ENDPOINT: &str = "/export_event_log";
 pub const GATEWAY_INFO_ENDPOINT: &str = "/info";
 pub const INVITE_CODES_ENDPOINT: &str = "/invite_codes";
 pub const GET_BALANCES_ENDPOINT: &str = "/balances";
@@ -54,9 +58,16 @@
 pub const PAYMENT_LOG_ENDPOINT: &str = "/payment_log";
 pub const PAYMENT_SUMMARY_ENDPOINT: &str = "/payment_summary";
 pub const PEGIN_FROM_ONCHAIN_ENDPOINT: &str = "/pegin_from_onchain";
+pub const REBALANCE_ENDPOINT: &str = "/rebalance";
+pub const REBALANCE_CONFIG_ENDPOINT: &str = "/rebalance_config";
 pub const RECEIVE_ECASH_ENDPOINT: &str = "/receive_ecash";
 pub const SET_CHANNEL_FEES_ENDPOINT: &str = "/set_channel_fees";
+pub const SET_EXPOSURE_LIMITS_ENDPOINT: &str = "/set_exposure_limits";
 pub const SET_FEES_ENDPOINT: &str = "/set_fees";
+pub const SET_FEE_POLICY_ENDPOINT: &str = "/set_fee_policy";
+pub const SET_LIQUIDITY_TARGET_ENDPOINT: &str = "/set_liquidity_target";
+pub const SET_REBALANCE_SETTINGS_ENDPOINT: &str = "/set_rebalance_settings";
+pub const SET_WEBHOOK_ENDPOINT: &str = "/set_webhook";
 pub const STOP_ENDPOINT: &str = "/stop";
 pub const SEND_ONCHAIN_ENDPOINT: &str = "/send_onchain";
 pub const SPEND_ECASH_ENDPOINT: &str = "/spend_ecash";
@@ -65,6 +76,10 @@
 
 pub const DEFAULT_LIGHTNING_PORT: u16 = 9735;
 
+/// Maximum number of entries returned by a single event log export if the
+/// request does not set a `limit`
+pub const DEFAULT_EXPORT_EVENT_LOG_LIMIT: usize = 1_000;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ConnectFedPayload {
     pub invite_code: String,
@@ -180,6 +195,16 @@ pub struct FederationInfo {
     pub balance_msat: Amount,
     pub config: FederationConfig,
     pub last_backup_time: Option<SystemTime>,
+    #[serde(default)]
+    pub exposure_limits: ExposureLimits,
+    /// Sum of the payments to and from the federation that are currently in
+    /// flight.
+    #[serde(default)]
+    pub in_flight_msat: Amount,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerState,
+    #[serde(default)]
+    pub fee_policy: Option<FeePolicy>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -295,6 +320,40 @@ pub struct PaymentLogPayload {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PaymentLogResponse(pub Vec<PersistedLogEntry>);
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportEventLogPayload {
+    pub federation_id: FederationId,
+
+    /// Name of the export. Every name keeps its own cursor in the
+    /// federation's client database, so independent consumers must use
+    /// different names.
+    pub name: String,
+
+    #[serde(default)]
+    pub filter: EventLogFilter,
+
+    /// `next_pos` of a previous response whose entries the consumer has
+    /// processed. The export's cursor only moves once it gets acknowledged,
+    /// so entries lost on the way to the consumer are returned again.
+    #[serde(default)]
+    pub ack: Option<EventLogId>,
+
+    /// Maximum number of entries to return, defaults to
+    /// [`DEFAULT_EXPORT_EVENT_LOG_LIMIT`]
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportEventLogResponse {
+    /// Entries matching the filter past the export's acknowledged cursor, at
+    /// most `limit` of them
+    pub entries: Vec<PersistedLogEntry>,
+
+    /// Position to acknowledge once the entries have been processed
+    pub next_pos: EventLogId,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PaymentSummaryResponse {
     pub outgoing: PaymentStats,
@@ -460,6 +519,23 @@ fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     }
 }
 
+impl Encodable for NodeAddress {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        self.to_string().consensus_encode(writer)
+    }
+}
+
+impl Decodable for NodeAddress {
+    fn consensus_decode_partial_from_finite_reader<R: std::io::Read>(
+        r: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        String::consensus_decode_partial_from_finite_reader(r, modules)?
+            .parse()
+            .map_err(|err: String| DecodeError::new_custom(anyhow::format_err!(err)))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SetChannelFeesRequest {
     /// Funding outpoint identifying the channel whose advertised routing fees
@@ -769,6 +845,409 @@ pub struct SetMnemonicPayload {
     pub words: Option<String>,
 }
 
+/// Global settings of the gateway's liquidity rebalancer.
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct RebalanceSettings {
+    /// Whether the rebalancer runs on its own schedule. A pass can always be
+    /// triggered manually, regardless of this flag.
+    pub enabled: bool,
+    /// Time between two scheduled rebalancing passes.
+    pub interval_secs: u64,
+    /// Fee rate used when pegging in or opening channels from the lightning
+    /// node's onchain wallet.
+    pub fee_rate_sats_per_vbyte: u64,
+    /// Onchain balance that peg-ins never touch, e.g. to keep funds for opening
+    /// channels.
+    pub onchain_reserve_sats: u64,
+    /// Outbound liquidity the lightning node should keep across its channels.
+    pub min_outbound_msats: u64,
+    /// Inbound liquidity the lightning node should keep across its channels.
+    pub min_inbound_msats: u64,
+    /// Peer the rebalancer opens a channel to from the onchain wallet when the
+    /// outbound liquidity is below `min_outbound_msats`. Without a peer, low
+    /// outbound liquidity is only reported.
+    pub channel_peer: Option<NodeAddress>,
+}
+
+impl Default for RebalanceSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: 3600,
+            fee_rate_sats_per_vbyte: 10,
+            onchain_reserve_sats: 0,
+            min_outbound_msats: 0,
+            min_inbound_msats: 0,
+            channel_peer: None,
+        }
+    }
+}
+
+/// The range of ecash the rebalancer keeps the gateway's balance in for a
+/// single federation.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct LiquidityTarget {
+    pub min_ecash: Amount,
+    pub max_ecash: Amount,
+}
+
+impl LiquidityTarget {
+    /// The balance a rebalancing move aims for, leaving room for payments in
+    /// both directions before the next move becomes necessary.
+    pub fn midpoint(&self) -> Amount {
+        Amount::from_msats(
+            self.min_ecash.msats + (self.max_ecash.msats.saturating_sub(self.min_ecash.msats)) / 2,
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SetLiquidityTargetPayload {
+    pub federation_id: FederationId,
+    /// The new target, or `None` to stop rebalancing this federation.
+    pub target: Option<LiquidityTarget>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RebalanceConfigResponse {
+    pub settings: RebalanceSettings,
+    pub targets: BTreeMap<FederationId, LiquidityTarget>,
+}
+
+/// A single move of funds between the federations, the lightning node's
+/// channels and its onchain wallet.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RebalanceAction {
+    /// Peg-in from the lightning node's onchain wallet into the federation.
+    PegIn {
+        federation_id: FederationId,
+        amount: bitcoin::Amount,
+    },
+    /// Peg-out from the federation into the lightning node's onchain wallet.
+    PegOut {
+        federation_id: FederationId,
+        amount: bitcoin::Amount,
+    },
+    /// Channel to [`RebalanceSettings::channel_peer`] funded from the
+    /// lightning node's onchain wallet, adding outbound liquidity.
+    OpenChannel { amount: bitcoin::Amount },
+    /// Payment of an invoice of the lightning node with the federation's ecash
+    /// through another gateway of the federation, turning inbound into
+    /// outbound liquidity.
+    SelfPayToChannels {
+        federation_id: FederationId,
+        amount: Amount,
+    },
+    /// Payment from the lightning node into the federation's ecash through
+    /// another gateway of the federation, turning outbound into inbound
+    /// liquidity.
+    SelfPayFromChannels {
+        federation_id: FederationId,
+        amount: Amount,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RebalanceActionOutcome {
+    pub action: RebalanceAction,
+    /// The reason the action failed, `None` if it was submitted successfully.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RebalanceResponse {
+    pub actions: Vec<RebalanceActionOutcome>,
+    /// Outbound liquidity missing to reach `min_outbound_msats` at the start of
+    /// the pass. It is added by self-payments from the federations' ecash and,
+    /// beyond that, by opening a channel to the `channel_peer` once the onchain
+    /// wallet can fund it.
+    pub outbound_deficit_msats: u64,
+    /// Inbound liquidity missing to reach `min_inbound_msats` at the start of
+    /// the pass. It is added by self-payments into the federations' ecash as
+    /// far as the outbound liquidity allows.
+    pub inbound_deficit_msats: u64,
+}
+
+/// Limits on how much the gateway is exposed to a single federation. Payments
+/// that would exceed a limit are rejected.
+#[derive(
+    Debug, Clone, Copy, Default, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize,
+)]
+pub struct ExposureLimits {
+    /// Maximum ecash balance the gateway holds in the federation. Checked for
+    /// payments that increase the gateway's balance.
+    pub max_balance: Option<Amount>,
+    /// Maximum sum of the payments to and from the federation that can be in
+    /// flight at the same time.
+    pub max_in_flight: Option<Amount>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SetExposureLimitsPayload {
+    pub federation_id: FederationId,
+    pub limits: ExposureLimits,
+}
+
+/// State of the circuit breaker the gateway keeps for every federation based on
+/// the health of the federation's API.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitBreakerState {
+    /// The federation is healthy and payments are routed normally.
+    #[default]
+    Closed,
+    /// The federation's API failed repeatedly and no payments are routed until
+    /// it responds again.
+    Open {
+        /// When the circuit breaker tripped.
+        since: SystemTime,
+    },
+}
+
+impl CircuitBreakerState {
+    pub fn is_open(&self) -> bool {
+        matches!(self, Self::Open { .. })
+    }
+}
+
+/// Largest multiplier a fee curve can apply to the lightning fee.
+pub const MAX_FEE_MULTIPLIER_PERCENT: u64 = 10_000;
+
+/// A point of a [`FeePolicy`] curve. While the share of the gateway's liquidity
+/// for a federation that is held as ecash equals `ecash_share_ppm`, the
+/// lightning fee is scaled to `multiplier_percent` percent.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct FeeCurvePoint {
+    pub ecash_share_ppm: u64,
+    pub multiplier_percent: u64,
+}
+
+/// Discount on the lightning fee for payments of at least `min_amount`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct FeeAmountTier {
+    pub min_amount: Amount,
+    pub discount_percent: u64,
+}
+
+/// Policy the gateway uses to derive the LNv2 fees of a federation from its
+/// current liquidity instead of charging the static lightning fee.
+///
+/// The ecash share is the federation's ecash balance divided by the sum of that
+/// balance and the outbound liquidity of the lightning node's channels. Sending
+/// a payment moves liquidity from the channels into ecash and receiving one
+/// moves it the other way, so the curves typically make sending more expensive
+/// as the share grows and receiving more expensive as it shrinks. Between two
+/// points the multiplier is interpolated linearly.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct FeePolicy {
+    /// Multiplier of the lightning fee charged for outgoing payments. Without
+    /// points the full lightning fee is charged.
+    pub send_curve: Vec<FeeCurvePoint>,
+    /// Multiplier of the lightning fee charged on top of the transaction fee
+    /// for incoming payments. Without points no lightning fee is charged.
+    pub receive_curve: Vec<FeeCurvePoint>,
+    pub amount_tiers: Vec<FeeAmountTier>,
+}
+
+impl FeePolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, curve) in [("send", &self.send_curve), ("receive", &self.receive_curve)] {
+            if curve.iter().any(|point| point.ecash_share_ppm > 1_000_000) {
+                return Err(format!(
+                    "The {name} curve has an ecash share above 1000000 ppm"
+                ));
+            }
+
+            if curve
+                .iter()
+                .any(|point| point.multiplier_percent > MAX_FEE_MULTIPLIER_PERCENT)
+            {
+                return Err(format!(
+                    "The {name} curve has a multiplier above {MAX_FEE_MULTIPLIER_PERCENT} percent"
+                ));
+            }
+
+            if !curve
+                .windows(2)
+                .all(|pair| pair[0].ecash_share_ppm < pair[1].ecash_share_ppm)
+            {
+                return Err(format!(
+                    "The points of the {name} curve have to be sorted by a strictly increasing ecash share"
+                ));
+            }
+        }
+
+        if self
+            .amount_tiers
+            .iter()
+            .any(|tier| tier.discount_percent > 100)
+        {
+            return Err("Amount tiers cannot discount more than 100 percent".to_string());
+        }
+
+        if !self
+            .amount_tiers
+            .windows(2)
+            .all(|pair| pair[0].min_amount < pair[1].min_amount)
+        {
+            return Err(
+                "Amount tiers have to be sorted by a strictly increasing minimum amount"
+                    .to_string(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns the percentage of the lightning fee charged for outgoing
+    /// payments at the given ecash share.
+    pub fn send_multiplier_percent(&self, ecash_share_ppm: u64) -> u64 {
+        interpolate_fee_curve(&self.send_curve, ecash_share_ppm).unwrap_or(100)
+    }
+
+    /// Returns the percentage of the lightning fee charged for incoming
+    /// payments at the given ecash share.
+    pub fn receive_multiplier_percent(&self, ecash_share_ppm: u64) -> u64 {
+        interpolate_fee_curve(&self.receive_curve, ecash_share_ppm).unwrap_or(0)
+    }
+}
+
+fn interpolate_fee_curve(curve: &[FeeCurvePoint], ecash_share_ppm: u64) -> Option<u64> {
+    let first = curve.first()?;
+    let last = curve.last()?;
+
+    if ecash_share_ppm <= first.ecash_share_ppm {
+        return Some(first.multiplier_percent);
+    }
+
+    if ecash_share_ppm >= last.ecash_share_ppm {
+        return Some(last.multiplier_percent);
+    }
+
+    let pair = curve
+        .windows(2)
+        .find(|pair| ecash_share_ppm <= pair[1].ecash_share_ppm)?;
+    let (low, high) = (pair[0], pair[1]);
+
+    let span = u128::from(high.ecash_share_ppm.saturating_sub(low.ecash_share_ppm)).max(1);
+    let offset = u128::from(ecash_share_ppm - low.ecash_share_ppm);
+    let interpolate = |from: u64, to: u64| {
+        u64::try_from(u128::from(to - from) * offset / span).expect("Bounded by to - from")
+    };
+
+    Some(if low.multiplier_percent <= high.multiplier_percent {
+        low.multiplier_percent + interpolate(low.multiplier_percent, high.multiplier_percent)
+    } else {
+        low.multiplier_percent - interpolate(high.multiplier_percent, low.multiplier_percent)
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SetFeePolicyPayload {
+    pub federation_id: FederationId,
+    /// The policy to use for the federation, or `None` to go back to the
+    /// static fees.
+    pub policy: Option<FeePolicy>,
+}
+
+/// HTTP header carrying the hex encoded HMAC-SHA256 of a webhook request's
+/// body, keyed with [`WebhookSettings::secret`].
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Fedimint-Gateway-Signature";
+
+/// Endpoint the gateway pushes [`WebhookMessage`]s to.
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct WebhookSettings {
+    pub url: SafeUrl,
+    /// Shared secret the receiver uses to verify that a request was sent by
+    /// this gateway.
+    pub secret: String,
+}
+
+impl WebhookSettings {
+    /// Returns the value of the [`WEBHOOK_SIGNATURE_HEADER`] for a request
+    /// with the given body.
+    pub fn signature(&self, body: &[u8]) -> String {
+        let mut engine = HmacEngine::<sha256::Hash>::new(self.secret.as_bytes());
+        engine.input(body);
+        Hmac::from_engine(engine)
+            .to_byte_array()
+            .as_hex()
+            .to_string()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SetWebhookPayload {
+    /// The webhook to notify, or `None` to stop sending notifications and drop
+    /// the ones that were not delivered yet.
+    pub webhook: Option<WebhookSettings>,
+}
+
+/// Body of a webhook request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookMessage {
+    /// Unique identifier of the message. A message is delivered at least
+    /// once, so receivers should use it to ignore repeated deliveries.
+    pub id: u64,
+    pub timestamp_secs: u64,
+    pub notification: WebhookNotification,
+}
+
+/// Something that happened at the gateway that its operator wants to be told
+/// about.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WebhookNotification {
+    /// A payment through the gateway completed. `event` is the payload of
+    /// the event in the federation client's event log.
+    PaymentSucceeded {
+        federation_id: FederationId,
+        direction: PaymentDirection,
+        event: serde_json::Value,
+    },
+    /// A payment through the gateway failed. `event` is the payload of the
+    /// event in the federation client's event log.
+    PaymentFailed {
+        federation_id: FederationId,
+        direction: PaymentDirection,
+        event: serde_json::Value,
+    },
+    FederationConnected {
+        federation_id: FederationId,
+    },
+    FederationLeft {
+        federation_id: FederationId,
+    },
+    /// A balance dropped below its liquidity target.
+    LowLiquidity {
+        alert: LiquidityAlert,
+    },
+    GatewayStateChanged {
+        state: String,
+    },
+}
+
+/// A balance of the gateway that is below the minimum configured in the
+/// rebalancer's [`LiquidityTarget`]s and [`RebalanceSettings`].
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LiquidityAlert {
+    Ecash {
+        federation_id: FederationId,
+        balance: Amount,
+        minimum: Amount,
+    },
+    Outbound {
+        balance_msats: u64,
+        minimum_msats: u64,
+    },
+    Inbound {
+        balance_msats: u64,
+        minimum_msats: u64,
+    },
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -848,4 +1327,75 @@ fn node_address_serde_uses_display_format() {
             serde_json::from_str(&json).expect("can deserialize request");
         assert_eq!(request.node_address.host_with_port(), "127.0.0.1:9735");
     }
+
+    fn point(ecash_share_ppm: u64, multiplier_percent: u64) -> FeeCurvePoint {
+        FeeCurvePoint {
+            ecash_share_ppm,
+            multiplier_percent,
+        }
+    }
+
+    #[test]
+    fn fee_policy_interpolates_between_curve_points() {
+        let policy = FeePolicy {
+            send_curve: vec![point(200_000, 50), point(800_000, 200)],
+            receive_curve: vec![point(0, 300), point(500_000, 0)],
+            amount_tiers: vec![],
+        };
+        policy.validate().expect("valid policy");
+
+        assert_eq!(policy.send_multiplier_percent(0), 50);
+        assert_eq!(policy.send_multiplier_percent(500_000), 125);
+        assert_eq!(policy.send_multiplier_percent(1_000_000), 200);
+
+        assert_eq!(policy.receive_multiplier_percent(0), 300);
+        assert_eq!(policy.receive_multiplier_percent(250_000), 150);
+        assert_eq!(policy.receive_multiplier_percent(750_000), 0);
+    }
+
+    #[test]
+    fn fee_policy_without_curves_keeps_static_fees() {
+        let policy = FeePolicy::default();
+
+        assert_eq!(policy.send_multiplier_percent(500_000), 100);
+        assert_eq!(policy.receive_multiplier_percent(500_000), 0);
+    }
+
+    #[test]
+    fn fee_policy_rejects_unsorted_curves_and_tiers() {
+        let unsorted_curve = FeePolicy {
+            send_curve: vec![point(500_000, 100), point(500_000, 200)],
+            ..FeePolicy::default()
+        };
+        assert!(unsorted_curve.validate().is_err());
+
+        let unsorted_tiers = FeePolicy {
+            amount_tiers: vec![
+                FeeAmountTier {
+                    min_amount: Amount::from_sats(10_000),
+                    discount_percent: 10,
+                },
+                FeeAmountTier {
+                    min_amount: Amount::from_sats(1_000),
+                    discount_percent: 20,
+                },
+            ],
+            ..FeePolicy::default()
+        };
+        assert!(unsorted_tiers.validate().is_err());
+    }
+
+    #[test]
+    fn webhook_signature_is_hmac_sha256_of_body() {
+        // Test case 2 of RFC 4231
+        let webhook = WebhookSettings {
+            url: SafeUrl::parse("https://example.com/webhook").expect("valid url"),
+            secret: "Jefe".to_string(),
+        };
+
+        assert_eq!(
+            webhook.signature(b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
 }
@@ -17,11 +17,15 @@
 use fedimint_core::invite_code::InviteCode;
 use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::{Amount, impl_db_lookup, impl_db_record, push_db_pair_items, secp256k1};
+use fedimint_eventlog::EventLogId;
 use fedimint_gateway_common::envs::FM_GATEWAY_IROH_SECRET_KEY_OVERRIDE_ENV;
-use fedimint_gateway_common::{ConnectorType, FederationConfig, RegisteredProtocol};
+use fedimint_gateway_common::{
+    ConnectorType, ExposureLimits, FederationConfig, FeePolicy, LiquidityTarget, RebalanceSettings,
+    RegisteredProtocol, WebhookSettings,
+};
 use fedimint_ln_common::serde_routing_fees;
 use fedimint_lnv2_common::contracts::{IncomingContract, PaymentImage};
-use fedimint_lnv2_common::gateway_api::PaymentFee;
+use fedimint_lnv2_common::gateway_api::{HoldInvoiceStatus, PaymentFee};
 use futures::{FutureExt, StreamExt};
 use lightning_invoice::RoutingFees;
 use rand::Rng;
@@ -121,6 +125,95 @@ async fn save_federation_backup_record(
         federation_id: FederationId,
         backup_time: Option<SystemTime>,
     );
+
+    /// Returns the rebalancer settings, or the defaults if they were never
+    /// saved.
+    async fn load_rebalance_settings(&mut self) -> RebalanceSettings;
+
+    async fn save_rebalance_settings(&mut self, settings: &RebalanceSettings);
+
+    async fn load_liquidity_targets(&mut self) -> BTreeMap<FederationId, LiquidityTarget>;
+
+    async fn save_liquidity_target(&mut self, federation_id: FederationId, target: LiquidityTarget);
+
+    /// Removes the liquidity target and the rebalancing history of a
+    /// federation.
+    async fn remove_liquidity_target(&mut self, federation_id: FederationId);
+
+    /// Returns when the rebalancer last moved funds in or out of a federation,
+    /// in seconds since the unix epoch.
+    async fn load_last_rebalance(&mut self, federation_id: FederationId) -> Option<u64>;
+
+    async fn save_last_rebalance(&mut self, federation_id: FederationId, time_secs: u64);
+
+    /// Returns when the rebalancer last opened a channel, in seconds since the
+    /// unix epoch.
+    async fn load_last_channel_open(&mut self) -> Option<u64>;
+
+    async fn save_last_channel_open(&mut self, time_secs: u64);
+
+    /// Returns the exposure limits of a federation, or no limits if they were
+    /// never saved.
+    async fn load_exposure_limits(&mut self, federation_id: FederationId) -> ExposureLimits;
+
+    async fn save_exposure_limits(&mut self, federation_id: FederationId, limits: ExposureLimits);
+
+    async fn remove_exposure_limits(&mut self, federation_id: FederationId);
+
+    /// Returns the fee policy of a federation, or `None` if the federation
+    /// uses its static fees.
+    async fn load_fee_policy(&mut self, federation_id: FederationId) -> Option<FeePolicy>;
+
+    async fn save_fee_policy(&mut self, federation_id: FederationId, policy: &FeePolicy);
+
+    async fn remove_fee_policy(&mut self, federation_id: FederationId);
+
+    async fn load_webhook_settings(&mut self) -> Option<WebhookSettings>;
+
+    async fn save_webhook_settings(&mut self, settings: &WebhookSettings);
+
+    /// Removes the webhook settings together with all deliveries that are
+    /// still queued.
+    async fn remove_webhook_settings(&mut self);
+
+    /// Allocates the id of a new webhook delivery. Ids are never reused, not
+    /// even after the webhook settings were removed.
+    async fn next_webhook_delivery_id(&mut self) -> u64;
+
+    async fn save_webhook_delivery(&mut self, id: u64, delivery: &WebhookDelivery);
+
+    /// Returns the queued webhook deliveries, oldest first.
+    async fn load_webhook_deliveries(&mut self) -> Vec<(u64, WebhookDelivery)>;
+
+    async fn remove_webhook_delivery(&mut self, id: u64);
+
+    /// Returns the position in a federation's event log up to which events
+    /// have been turned into webhook notifications.
+    async fn load_webhook_event_log_position(
+        &mut self,
+        federation_id: FederationId,
+    ) -> Option<EventLogId>;
+
+    async fn save_webhook_event_log_position(
+        &mut self,
+        federation_id: FederationId,
+        position: EventLogId,
+    );
+
+    async fn remove_webhook_event_log_position(&mut self, federation_id: FederationId);
+
+    /// Returns the status of the hold invoice for `payment_image`, or `None`
+    /// if the invoice for `payment_image` is not a hold invoice.
+    async fn load_hold_invoice_status(
+        &mut self,
+        payment_image: PaymentImage,
+    ) -> Option<HoldInvoiceStatus>;
+
+    async fn save_hold_invoice_status(
+        &mut self,
+        payment_image: PaymentImage,
+        status: HoldInvoiceStatus,
+    );
 }
 
 impl<Cap: Send> GatewayDbtxNcExt for DatabaseTransaction<'_, Cap> {
@@ -271,6 +364,66 @@ async fn dump_database(
                         "Gateway Public Keys"
                     );
                 }
+                DbKeyPrefix::LiquidityTarget => {
+                    push_db_pair_items!(
+                        self,
+                        LiquidityTargetPrefix,
+                        LiquidityTargetKey,
+                        LiquidityTarget,
+                        gateway_items,
+                        "Liquidity Targets"
+                    );
+                }
+                DbKeyPrefix::ExposureLimits => {
+                    push_db_pair_items!(
+                        self,
+                        ExposureLimitsPrefix,
+                        ExposureLimitsKey,
+                        ExposureLimits,
+                        gateway_items,
+                        "Exposure Limits"
+                    );
+                }
+                DbKeyPrefix::FeePolicy => {
+                    push_db_pair_items!(
+                        self,
+                        FeePolicyPrefix,
+                        FeePolicyKey,
+                        FeePolicy,
+                        gateway_items,
+                        "Fee Policies"
+                    );
+                }
+                DbKeyPrefix::WebhookDelivery => {
+                    push_db_pair_items!(
+                        self,
+                        WebhookDeliveryPrefix,
+                        WebhookDeliveryKey,
+                        WebhookDelivery,
+                        gateway_items,
+                        "Webhook Deliveries"
+                    );
+                }
+                DbKeyPrefix::WebhookEventLogPosition => {
+                    push_db_pair_items!(
+                        self,
+                        WebhookEventLogPositionPrefix,
+                        WebhookEventLogPositionKey,
+                        EventLogId,
+                        gateway_items,
+                        "Webhook Event Log Positions"
+                    );
+                }
+                DbKeyPrefix::HoldInvoice => {
+                    push_db_pair_items!(
+                        self,
+                        HoldInvoicePrefix,
+                        HoldInvoiceKey,
+                        HoldInvoiceStatus,
+                        gateway_items,
+                        "Hold Invoices"
+                    );
+                }
                 _ => {}
             }
         }
@@ -323,6 +476,163 @@ async fn save_federation_backup_record(
         )
         .await;
     }
+
+    async fn load_rebalance_settings(&mut self) -> RebalanceSettings {
+        self.get_value(&RebalanceSettingsKey)
+            .await
+            .unwrap_or_default()
+    }
+
+    async fn save_rebalance_settings(&mut self, settings: &RebalanceSettings) {
+        self.insert_entry(&RebalanceSettingsKey, settings).await;
+    }
+
+    async fn load_liquidity_targets(&mut self) -> BTreeMap<FederationId, LiquidityTarget> {
+        self.find_by_prefix(&LiquidityTargetPrefix)
+            .await
+            .map(|(key, target): (LiquidityTargetKey, LiquidityTarget)| (key.federation_id, target))
+            .collect::<BTreeMap<FederationId, LiquidityTarget>>()
+            .await
+    }
+
+    async fn save_liquidity_target(
+        &mut self,
+        federation_id: FederationId,
+        target: LiquidityTarget,
+    ) {
+        self.insert_entry(&LiquidityTargetKey { federation_id }, &target)
+            .await;
+    }
+
+    async fn remove_liquidity_target(&mut self, federation_id: FederationId) {
+        self.remove_entry(&LiquidityTargetKey { federation_id })
+            .await;
+        self.remove_entry(&LastRebalanceKey { federation_id }).await;
+    }
+
+    async fn load_last_rebalance(&mut self, federation_id: FederationId) -> Option<u64> {
+        self.get_value(&LastRebalanceKey { federation_id }).await
+    }
+
+    async fn save_last_rebalance(&mut self, federation_id: FederationId, time_secs: u64) {
+        self.insert_entry(&LastRebalanceKey { federation_id }, &time_secs)
+            .await;
+    }
+
+    async fn load_last_channel_open(&mut self) -> Option<u64> {
+        self.get_value(&LastChannelOpenKey).await
+    }
+
+    async fn save_last_channel_open(&mut self, time_secs: u64) {
+        self.insert_entry(&LastChannelOpenKey, &time_secs).await;
+    }
+
+    async fn load_exposure_limits(&mut self, federation_id: FederationId) -> ExposureLimits {
+        self.get_value(&ExposureLimitsKey { federation_id })
+            .await
+            .unwrap_or_default()
+    }
+
+    async fn save_exposure_limits(&mut self, federation_id: FederationId, limits: ExposureLimits) {
+        self.insert_entry(&ExposureLimitsKey { federation_id }, &limits)
+            .await;
+    }
+
+    async fn remove_exposure_limits(&mut self, federation_id: FederationId) {
+        self.remove_entry(&ExposureLimitsKey { federation_id })
+            .await;
+    }
+
+    async fn load_fee_policy(&mut self, federation_id: FederationId) -> Option<FeePolicy> {
+        self.get_value(&FeePolicyKey { federation_id }).await
+    }
+
+    async fn save_fee_policy(&mut self, federation_id: FederationId, policy: &FeePolicy) {
+        self.insert_entry(&FeePolicyKey { federation_id }, policy)
+            .await;
+    }
+
+    async fn remove_fee_policy(&mut self, federation_id: FederationId) {
+        self.remove_entry(&FeePolicyKey { federation_id }).await;
+    }
+
+    async fn load_webhook_settings(&mut self) -> Option<WebhookSettings> {
+        self.get_value(&WebhookSettingsKey).await
+    }
+
+    async fn save_webhook_settings(&mut self, settings: &WebhookSettings) {
+        self.insert_entry(&WebhookSettingsKey, settings).await;
+    }
+
+    async fn remove_webhook_settings(&mut self) {
+        self.remove_entry(&WebhookSettingsKey).await;
+        self.remove_by_prefix(&WebhookDeliveryPrefix).await;
+    }
+
+    async fn next_webhook_delivery_id(&mut self) -> u64 {
+        let id = self
+            .get_value(&NextWebhookDeliveryIdKey)
+            .await
+            .unwrap_or_default();
+        self.insert_entry(&NextWebhookDeliveryIdKey, &(id + 1))
+            .await;
+        id
+    }
+
+    async fn save_webhook_delivery(&mut self, id: u64, delivery: &WebhookDelivery) {
+        self.insert_entry(&WebhookDeliveryKey { id }, delivery)
+            .await;
+    }
+
+    async fn load_webhook_deliveries(&mut self) -> Vec<(u64, WebhookDelivery)> {
+        self.find_by_prefix(&WebhookDeliveryPrefix)
+            .await
+            .map(|(key, delivery)| (key.id, delivery))
+            .collect()
+            .await
+    }
+
+    async fn remove_webhook_delivery(&mut self, id: u64) {
+        self.remove_entry(&WebhookDeliveryKey { id }).await;
+    }
+
+    async fn load_webhook_event_log_position(
+        &mut self,
+        federation_id: FederationId,
+    ) -> Option<EventLogId> {
+        self.get_value(&WebhookEventLogPositionKey { federation_id })
+            .await
+    }
+
+    async fn save_webhook_event_log_position(
+        &mut self,
+        federation_id: FederationId,
+        position: EventLogId,
+    ) {
+        self.insert_entry(&WebhookEventLogPositionKey { federation_id }, &position)
+            .await;
+    }
+
+    async fn remove_webhook_event_log_position(&mut self, federation_id: FederationId) {
+        self.remove_entry(&WebhookEventLogPositionKey { federation_id })
+            .await;
+    }
+
+    async fn load_hold_invoice_status(
+        &mut self,
+        payment_image: PaymentImage,
+    ) -> Option<HoldInvoiceStatus> {
+        self.get_value(&HoldInvoiceKey(payment_image)).await
+    }
+
+    async fn save_hold_invoice_status(
+        &mut self,
+        payment_image: PaymentImage,
+        status: HoldInvoiceStatus,
+    ) {
+        self.insert_entry(&HoldInvoiceKey(payment_image), &status)
+            .await;
+    }
 }
 
 #[repr(u8)]
@@ -337,6 +647,17 @@ enum DbKeyPrefix {
     Iroh = 0x11,
     FederationBackup = 0x12,
     ClaimedOutgoingPaymentImage = 0x13,
+    RebalanceSettings = 0x14,
+    LiquidityTarget = 0x15,
+    LastRebalance = 0x16,
+    ExposureLimits = 0x17,
+    FeePolicy = 0x18,
+    WebhookSettings = 0x19,
+    WebhookDelivery = 0x1a,
+    WebhookEventLogPosition = 0x1b,
+    NextWebhookDeliveryId = 0x1c,
+    HoldInvoice = 0x1d,
+    LastChannelOpen = 0x1e,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -847,5 +1168,172 @@ pub struct RegisteredIncomingContract {
     db_prefix = DbKeyPrefix::ClaimedOutgoingPaymentImage,
 );
 
+#[derive(Debug, Encodable, Decodable)]
+struct RebalanceSettingsKey;
+
+impl_db_record!(
+    key = RebalanceSettingsKey,
+    value = RebalanceSettings,
+    db_prefix = DbKeyPrefix::RebalanceSettings,
+);
+
+#[derive(Debug, Encodable, Decodable)]
+struct LiquidityTargetKey {
+    federation_id: FederationId,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+struct LiquidityTargetPrefix;
+
+impl_db_record!(
+    key = LiquidityTargetKey,
+    value = LiquidityTarget,
+    db_prefix = DbKeyPrefix::LiquidityTarget,
+);
+
+impl_db_lookup!(
+    key = LiquidityTargetKey,
+    query_prefix = LiquidityTargetPrefix
+);
+
+/// Time of the last rebalancing move of a federation, in seconds since the unix
+/// epoch. Peg-ins and peg-outs only settle after confirmations, so the
+/// rebalancer waits before touching the same federation again.
+#[derive(Debug, Encodable, Decodable)]
+struct LastRebalanceKey {
+    federation_id: FederationId,
+}
+
+impl_db_record!(
+    key = LastRebalanceKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::LastRebalance,
+);
+
+/// Time the rebalancer last opened a channel, in seconds since the unix epoch.
+/// The new channel only counts towards the outbound liquidity once its funding
+/// transaction confirmed, so the rebalancer waits before opening another one.
+#[derive(Debug, Encodable, Decodable)]
+struct LastChannelOpenKey;
+
+impl_db_record!(
+    key = LastChannelOpenKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::LastChannelOpen,
+);
+
+#[derive(Debug, Encodable, Decodable)]
+struct ExposureLimitsKey {
+    federation_id: FederationId,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+struct ExposureLimitsPrefix;
+
+impl_db_record!(
+    key = ExposureLimitsKey,
+    value = ExposureLimits,
+    db_prefix = DbKeyPrefix::ExposureLimits,
+);
+
+impl_db_lookup!(key = ExposureLimitsKey, query_prefix = ExposureLimitsPrefix);
+
+#[derive(Debug, Encodable, Decodable)]
+struct FeePolicyKey {
+    federation_id: FederationId,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+struct FeePolicyPrefix;
+
+impl_db_record!(
+    key = FeePolicyKey,
+    value = FeePolicy,
+    db_prefix = DbKeyPrefix::FeePolicy,
+);
+
+impl_db_lookup!(key = FeePolicyKey, query_prefix = FeePolicyPrefix);
+
+#[derive(Debug, Encodable, Decodable)]
+struct WebhookSettingsKey;
+
+impl_db_record!(
+    key = WebhookSettingsKey,
+    value = WebhookSettings,
+    db_prefix = DbKeyPrefix::WebhookSettings,
+);
+
+/// A webhook message that has not been delivered yet.
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    /// The JSON encoded message, stored as sent so that retries carry the same
+    /// body and signature.
+    pub body: String,
+    pub attempts: u32,
+    /// Earliest time of the next attempt in seconds since the unix epoch.
+    pub next_attempt_secs: u64,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+struct WebhookDeliveryKey {
+    id: u64,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+struct WebhookDeliveryPrefix;
+
+impl_db_record!(
+    key = WebhookDeliveryKey,
+    value = WebhookDelivery,
+    db_prefix = DbKeyPrefix::WebhookDelivery,
+);
+
+impl_db_lookup!(
+    key = WebhookDeliveryKey,
+    query_prefix = WebhookDeliveryPrefix
+);
+
+#[derive(Debug, Encodable, Decodable)]
+struct NextWebhookDeliveryIdKey;
+
+impl_db_record!(
+    key = NextWebhookDeliveryIdKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::NextWebhookDeliveryId,
+);
+
+#[derive(Debug, Encodable, Decodable)]
+struct WebhookEventLogPositionKey {
+    federation_id: FederationId,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+struct WebhookEventLogPositionPrefix;
+
+impl_db_record!(
+    key = WebhookEventLogPositionKey,
+    value = EventLogId,
+    db_prefix = DbKeyPrefix::WebhookEventLogPosition,
+);
+
+impl_db_lookup!(
+    key = WebhookEventLogPositionKey,
+    query_prefix = WebhookEventLogPositionPrefix
+);
+
+#[derive(Debug, Encodable, Decodable)]
+struct HoldInvoiceKey(pub PaymentImage);
+
+#[derive(Debug, Encodable, Decodable)]
+struct HoldInvoicePrefix;
+
+impl_db_record!(
+    key = HoldInvoiceKey,
+    value = HoldInvoiceStatus,
+    db_prefix = DbKeyPrefix::HoldInvoice,
+);
+
+impl_db_lookup!(key = HoldInvoiceKey, query_prefix = HoldInvoicePrefix);
+
 #[cfg(test)]
 mod migration_tests;
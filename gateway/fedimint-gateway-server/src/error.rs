@@ -50,6 +50,8 @@ pub enum PublicGatewayError {
     LNv2(#[from] LNv2Error),
     #[error("{}", .0)]
     FederationNotConnected(#[from] FederationNotConnected),
+    #[error("{}", .0)]
+    Exposure(#[from] ExposureError),
     #[error("Failed to receive ecash: {failure_reason}")]
     ReceiveEcashError { failure_reason: String },
     #[error("Unexpected Error: {}", OptStacktrace(.0))]
@@ -66,6 +68,10 @@ fn into_response(self) -> Response {
             PublicGatewayError::FederationNotConnected(e) => {
                 (e.to_string(), StatusCode::BAD_REQUEST)
             }
+            PublicGatewayError::Exposure(_) => (
+                "Federation is temporarily not served by this gateway".to_string(),
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
             PublicGatewayError::ReceiveEcashError { .. } => (
                 "Failed to receive ecash".to_string(),
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -179,6 +185,26 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     }
 }
 
+/// Public error that indicates a payment was rejected to limit the gateway's
+/// exposure to a federation.
+#[derive(Debug, Error)]
+pub enum ExposureError {
+    #[error("Circuit breaker of federation {federation_id_prefix} is open")]
+    CircuitBreakerOpen {
+        federation_id_prefix: FederationIdPrefix,
+    },
+    #[error("Payment would exceed the maximum balance in federation {federation_id_prefix}")]
+    MaxBalanceExceeded {
+        federation_id_prefix: FederationIdPrefix,
+    },
+    #[error(
+        "Payment would exceed the maximum amount in flight in federation {federation_id_prefix}"
+    )]
+    MaxInFlightExceeded {
+        federation_id_prefix: FederationIdPrefix,
+    },
+}
+
 /// LNURL-compliant error response for verify endpoints
 #[derive(Debug, Error)]
 pub(crate) struct LnurlError {
@@ -2,16 +2,21 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use fedimint_client::ClientHandle;
+use fedimint_core::Amount;
+use fedimint_core::core::ModuleKind;
 use fedimint_eventlog::{
-    DBTransactionEventLogExt, Event, EventKind, EventLogId, PersistedLogEntry,
+    DBTransactionEventLogExt, Event, EventKind, EventLogId, EventPersistence, PersistedLogEntry,
 };
+use fedimint_gateway_common::PaymentDirection;
 use fedimint_gwv2_client::events::{
     CompleteLightningPaymentSucceeded, IncomingPaymentFailed, IncomingPaymentStarted,
     IncomingPaymentSucceeded, OutgoingPaymentFailed, OutgoingPaymentStarted,
     OutgoingPaymentSucceeded,
 };
+use fedimint_lnv2_common::contracts::PaymentImage;
 use fedimint_mint_client::events::{OOBNotesReissued, OOBNotesSpent};
 use fedimint_wallet_client::events::{DepositConfirmed, WithdrawRequest};
+use serde::{Deserialize, Serialize};
 
 /// The set of gateway payment-related event kinds used as the default filter
 /// for the `payment_log` API when no explicit `event_kinds` are provided.
@@ -19,7 +24,7 @@
 /// This does not include all events in the log (e.g. `tx-created`,
 /// `tx-accepted`, `NoteCreated`, `NoteSpent` are excluded), so paginated
 /// results filtered to these kinds will have non-contiguous event IDs.
-pub const ALL_GATEWAY_EVENTS: [EventKind; 11] = [
+pub const ALL_GATEWAY_EVENTS: [EventKind; 12] = [
     OutgoingPaymentStarted::KIND,
     OutgoingPaymentSucceeded::KIND,
     OutgoingPaymentFailed::KIND,
@@ -31,8 +36,37 @@
     OOBNotesReissued::KIND,
     WithdrawRequest::KIND,
     DepositConfirmed::KIND,
+    PaymentFeeApplied::KIND,
 ];
 
+/// Event that is emitted when the gateway accepts an incoming LNv2 contract or
+/// has paid the invoice of an outgoing one, recording the fee the contract
+/// pays the gateway.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PaymentFeeApplied {
+    /// The payment image of the contract.
+    pub payment_image: PaymentImage,
+
+    pub direction: PaymentDirection,
+
+    /// The amount of the invoice, or of the part of it the contract pays for a
+    /// multi-path payment.
+    pub amount: Amount,
+
+    /// The fee the contract pays the gateway.
+    pub fee: Amount,
+
+    /// The ecash share the federation's fee policy was evaluated at, or `None`
+    /// if the federation uses static fees.
+    pub ecash_share_ppm: Option<u64>,
+}
+
+impl Event for PaymentFeeApplied {
+    const MODULE: Option<ModuleKind> = None;
+    const KIND: EventKind = EventKind::from_static("payment-fee-applied");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
+
 /// Searches through the event log for all events that occurred within the
 /// specified time bounds.
 ///
@@ -0,0 +1,377 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use fedimint_client::ClientHandleArc;
+use fedimint_core::Amount;
+use fedimint_core::config::FederationId;
+use fedimint_core::task::{sleep, timeout};
+use fedimint_core::time::now;
+use fedimint_gateway_common::{CircuitBreakerState, ExposureLimits};
+use fedimint_gateway_server_db::GatewayDbtxNcExt as _;
+use fedimint_lightning::{InterceptPaymentRequest, InterceptPaymentResponse};
+use fedimint_logging::LOG_GATEWAY;
+use tracing::{info, warn};
+
+use crate::Gateway;
+use crate::error::ExposureError;
+
+/// Number of consecutive failed health checks after which the circuit breaker
+/// of a federation trips.
+const CIRCUIT_BREAKER_FAILURE_THRESHOLD: u32 = 3;
+
+/// Time between two health checks of the connected federations.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Time a federation's API has to answer a health check.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default)]
+struct FederationExposure {
+    in_flight: Amount,
+    consecutive_failures: u32,
+    circuit_breaker: CircuitBreakerState,
+}
+
+/// Tracks the payments in flight and the circuit breaker of every federation
+/// the gateway is connected to. The state is kept in memory only, after a
+/// restart all circuit breakers start out closed.
+#[derive(Debug, Default)]
+pub struct ExposureTracker {
+    federations: Mutex<BTreeMap<FederationId, FederationExposure>>,
+
+    /// Amounts of incoming HTLCs that stay in flight until the HTLC is settled
+    /// or failed back, keyed by incoming channel id and HTLC id.
+    htlcs: Mutex<BTreeMap<(u64, u64), (FederationId, Amount)>>,
+}
+
+impl ExposureTracker {
+    pub fn in_flight(&self, federation_id: &FederationId) -> Amount {
+        self.federations
+            .lock()
+            .expect("poisoned")
+            .get(federation_id)
+            .map(|exposure| exposure.in_flight)
+            .unwrap_or_default()
+    }
+
+    pub fn circuit_breaker(&self, federation_id: &FederationId) -> CircuitBreakerState {
+        self.federations
+            .lock()
+            .expect("poisoned")
+            .get(federation_id)
+            .map(|exposure| exposure.circuit_breaker)
+            .unwrap_or_default()
+    }
+
+    /// Reserves `amount` as in flight for a federation until the returned
+    /// guard is dropped. Fails if the federation's circuit breaker is open or
+    /// the reservation would exceed `ExposureLimits::max_in_flight`.
+    pub fn reserve(
+        self: &Arc<Self>,
+        federation_id: FederationId,
+        amount: Amount,
+        limits: &ExposureLimits,
+    ) -> Result<InFlightGuard, ExposureError> {
+        let mut federations = self.federations.lock().expect("poisoned");
+        let exposure = federations.entry(federation_id).or_default();
+
+        if exposure.circuit_breaker.is_open() {
+            return Err(ExposureError::CircuitBreakerOpen {
+                federation_id_prefix: federation_id.to_prefix(),
+            });
+        }
+
+        let in_flight = exposure.in_flight.checked_add(amount);
+        if let Some(max_in_flight) = limits.max_in_flight
+            && in_flight.is_none_or(|in_flight| in_flight > max_in_flight)
+        {
+            return Err(ExposureError::MaxInFlightExceeded {
+                federation_id_prefix: federation_id.to_prefix(),
+            });
+        }
+
+        exposure.in_flight = in_flight.unwrap_or(Amount::from_msats(u64::MAX));
+
+        Ok(InFlightGuard {
+            tracker: self.clone(),
+            federation_id,
+            amount,
+        })
+    }
+
+    /// Keeps the amount reserved by `guard` in flight until
+    /// [`Self::release_htlc`] is called for the incoming HTLC.
+    pub fn hold_for_htlc(&self, mut guard: InFlightGuard, incoming_chan_id: u64, htlc_id: u64) {
+        // Taking the amount disarms the guard
+        let amount = std::mem::take(&mut guard.amount);
+        self.htlcs
+            .lock()
+            .expect("poisoned")
+            .insert((incoming_chan_id, htlc_id), (guard.federation_id, amount));
+    }
+
+    /// Releases the amount held for an incoming HTLC that was settled or
+    /// failed back.
+    pub fn release_htlc(&self, incoming_chan_id: u64, htlc_id: u64) {
+        let Some((federation_id, amount)) = self
+            .htlcs
+            .lock()
+            .expect("poisoned")
+            .remove(&(incoming_chan_id, htlc_id))
+        else {
+            return;
+        };
+
+        if let Some(exposure) = self
+            .federations
+            .lock()
+            .expect("poisoned")
+            .get_mut(&federation_id)
+        {
+            exposure.in_flight = exposure.in_flight.saturating_sub(amount);
+        }
+    }
+
+    /// Records the outcome of a health check of a federation's API and
+    /// returns the new state of its circuit breaker if it changed.
+    fn record_health_check(
+        &self,
+        federation_id: FederationId,
+        healthy: bool,
+    ) -> Option<CircuitBreakerState> {
+        let mut federations = self.federations.lock().expect("poisoned");
+        let exposure = federations.entry(federation_id).or_default();
+
+        if healthy {
+            exposure.consecutive_failures = 0;
+            if exposure.circuit_breaker.is_open() {
+                exposure.circuit_breaker = CircuitBreakerState::Closed;
+                return Some(exposure.circuit_breaker);
+            }
+            return None;
+        }
+
+        exposure.consecutive_failures = exposure.consecutive_failures.saturating_add(1);
+        if !exposure.circuit_breaker.is_open()
+            && CIRCUIT_BREAKER_FAILURE_THRESHOLD <= exposure.consecutive_failures
+        {
+            exposure.circuit_breaker = CircuitBreakerState::Open { since: now() };
+            return Some(exposure.circuit_breaker);
+        }
+
+        None
+    }
+
+    /// Forgets the state of a federation the gateway left.
+    pub fn remove(&self, federation_id: &FederationId) {
+        self.federations
+            .lock()
+            .expect("poisoned")
+            .remove(federation_id);
+        self.htlcs
+            .lock()
+            .expect("poisoned")
+            .retain(|_, (htlc_federation_id, _)| htlc_federation_id != federation_id);
+    }
+}
+
+/// Releases an amount reserved with [`ExposureTracker::reserve`] once the
+/// payment it belongs to is no longer in flight.
+#[derive(Debug)]
+pub struct InFlightGuard {
+    tracker: Arc<ExposureTracker>,
+    federation_id: FederationId,
+    amount: Amount,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if let Some(exposure) = self
+            .tracker
+            .federations
+            .lock()
+            .expect("poisoned")
+            .get_mut(&self.federation_id)
+        {
+            exposure.in_flight = exposure.in_flight.saturating_sub(self.amount);
+        }
+    }
+}
+
+impl Gateway {
+    /// Reserves a payment of `amount` routed through `client` against the
+    /// exposure limits and the circuit breaker of the client's federation.
+    /// Payments that leave the gateway with more ecash in the federation set
+    /// `increases_balance` and are also checked against
+    /// `ExposureLimits::max_balance`.
+    pub(crate) async fn reserve_exposure(
+        &self,
+        client: &ClientHandleArc,
+        amount: Amount,
+        increases_balance: bool,
+    ) -> Result<InFlightGuard, ExposureError> {
+        let federation_id = client.federation_id();
+        let limits = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_exposure_limits(federation_id)
+            .await;
+
+        if increases_balance && let Some(max_balance) = limits.max_balance {
+            let balance = client.get_balance_for_btc().await.unwrap_or_default();
+            if balance
+                .checked_add(amount)
+                .is_none_or(|balance| balance > max_balance)
+            {
+                return Err(ExposureError::MaxBalanceExceeded {
+                    federation_id_prefix: federation_id.to_prefix(),
+                });
+            }
+        }
+
+        self.exposure.reserve(federation_id, amount, &limits)
+    }
+
+    /// Keeps an incoming payment in flight until its HTLC is completed.
+    pub(crate) fn hold_exposure_for_htlc(
+        &self,
+        in_flight: InFlightGuard,
+        htlc_request: &InterceptPaymentRequest,
+    ) {
+        self.exposure.hold_for_htlc(
+            in_flight,
+            htlc_request.incoming_chan_id,
+            htlc_request.htlc_id,
+        );
+    }
+
+    /// Releases the exposure held for an incoming payment whose HTLC is being
+    /// settled or failed back.
+    pub(crate) fn release_exposure_for_htlc(&self, htlc_response: &InterceptPaymentResponse) {
+        self.exposure
+            .release_htlc(htlc_response.incoming_chan_id, htlc_response.htlc_id);
+    }
+
+    /// Spawns a background task that periodically checks the API of every
+    /// connected federation and trips the federation's circuit breaker after
+    /// repeated failures. The circuit breaker closes again on the first
+    /// successful check.
+    pub(crate) fn spawn_federation_health_task(&self) {
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("federation health check", async move {
+                loop {
+                    sleep(HEALTH_CHECK_INTERVAL).await;
+
+                    let clients = self_copy
+                        .federation_manager
+                        .read()
+                        .await
+                        .clients()
+                        .map(|(federation_id, client)| (*federation_id, client.value().clone()))
+                        .collect::<Vec<_>>();
+
+                    let checks = clients.into_iter().map(|(federation_id, client)| async move {
+                        let healthy = matches!(
+                            timeout(HEALTH_CHECK_TIMEOUT, client.api().session_count()).await,
+                            Ok(Ok(_))
+                        );
+                        (federation_id, healthy)
+                    });
+
+                    for (federation_id, healthy) in futures::future::join_all(checks).await {
+                        match self_copy.exposure.record_health_check(federation_id, healthy) {
+                            Some(CircuitBreakerState::Open { .. }) => {
+                                warn!(target: LOG_GATEWAY, %federation_id, "Federation API is unhealthy, opening circuit breaker");
+                            }
+                            Some(CircuitBreakerState::Closed) => {
+                                info!(target: LOG_GATEWAY, %federation_id, "Federation API recovered, closing circuit breaker");
+                            }
+                            None => {}
+                        }
+                    }
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::{Hash as _, sha256};
+
+    use super::*;
+
+    fn federation_id() -> FederationId {
+        FederationId(sha256::Hash::from_byte_array([1; 32]))
+    }
+
+    #[test]
+    fn in_flight_is_limited_and_released_on_drop() {
+        let tracker = Arc::new(ExposureTracker::default());
+        let limits = ExposureLimits {
+            max_balance: None,
+            max_in_flight: Some(Amount::from_sats(1_000)),
+        };
+
+        let first = tracker
+            .reserve(federation_id(), Amount::from_sats(600), &limits)
+            .expect("within limit");
+        assert!(matches!(
+            tracker.reserve(federation_id(), Amount::from_sats(600), &limits),
+            Err(ExposureError::MaxInFlightExceeded { .. })
+        ));
+        assert_eq!(tracker.in_flight(&federation_id()), Amount::from_sats(600));
+
+        drop(first);
+        assert_eq!(tracker.in_flight(&federation_id()), Amount::ZERO);
+        tracker
+            .reserve(federation_id(), Amount::from_sats(600), &limits)
+            .expect("released");
+    }
+
+    #[test]
+    fn htlc_stays_in_flight_until_released() {
+        let tracker = Arc::new(ExposureTracker::default());
+        let guard = tracker
+            .reserve(
+                federation_id(),
+                Amount::from_sats(100),
+                &ExposureLimits::default(),
+            )
+            .expect("no limits");
+
+        tracker.hold_for_htlc(guard, 1, 2);
+        assert_eq!(tracker.in_flight(&federation_id()), Amount::from_sats(100));
+
+        tracker.release_htlc(1, 2);
+        assert_eq!(tracker.in_flight(&federation_id()), Amount::ZERO);
+    }
+
+    #[test]
+    fn circuit_breaker_trips_after_repeated_failures() {
+        let tracker = Arc::new(ExposureTracker::default());
+        let limits = ExposureLimits::default();
+
+        for _ in 1..CIRCUIT_BREAKER_FAILURE_THRESHOLD {
+            assert_eq!(tracker.record_health_check(federation_id(), false), None);
+        }
+        assert!(matches!(
+            tracker.record_health_check(federation_id(), false),
+            Some(CircuitBreakerState::Open { .. })
+        ));
+        assert!(matches!(
+            tracker.reserve(federation_id(), Amount::from_sats(1), &limits),
+            Err(ExposureError::CircuitBreakerOpen { .. })
+        ));
+
+        assert_eq!(
+            tracker.record_health_check(federation_id(), true),
+            Some(CircuitBreakerState::Closed)
+        );
+        tracker
+            .reserve(federation_id(), Amount::from_sats(1), &limits)
+            .expect("circuit breaker closed");
+    }
+}
@@ -19,6 +19,7 @@
 use tracing::{info, warn};
 
 use crate::error::{AdminGatewayError, FederationNotConnected};
+use crate::exposure::ExposureTracker;
 use crate::{AdminResult, Registration};
 
 /// The first index that the gateway will assign to a federation.
@@ -42,6 +43,9 @@ pub struct FederationManager {
     /// federation, this value is incremented and assigned to the federation
     /// as the `federation_index`
     next_index: AtomicU64,
+
+    /// Payments in flight and circuit breakers of the connected federations.
+    exposure: Arc<ExposureTracker>,
 }
 
 impl FederationManager {
@@ -50,6 +54,7 @@ pub fn new() -> Self {
             clients: BTreeMap::new(),
             index_to_federation: BTreeMap::new(),
             next_index: AtomicU64::new(INITIAL_INDEX),
+            exposure: Arc::new(ExposureTracker::default()),
         }
     }
 
@@ -88,6 +93,7 @@ async fn remove_client(&mut self, federation_id: FederationId) -> AdminResult<()
 
         self.index_to_federation
             .retain(|_, fid| *fid != federation_id);
+        self.exposure.remove(&federation_id);
 
         match Arc::into_inner(client) {
             Some(client) => {
@@ -213,6 +219,14 @@ pub fn client(&self, federation_id: &FederationId) -> Option<&Spanned<ClientHand
         self.clients.get(federation_id)
     }
 
+    pub fn clients(&self) -> impl Iterator<Item = (&FederationId, &Spanned<ClientHandleArc>)> {
+        self.clients.iter()
+    }
+
+    pub fn exposure(&self) -> Arc<ExposureTracker> {
+        self.exposure.clone()
+    }
+
     pub async fn federation_info(
         &self,
         federation_id: FederationId,
@@ -251,6 +265,10 @@ pub async fn federation_info(
                     balance_msat,
                     config,
                     last_backup_time,
+                    exposure_limits: dbtx.load_exposure_limits(federation_id).await,
+                    in_flight_msat: self.exposure.in_flight(&federation_id),
+                    circuit_breaker: self.exposure.circuit_breaker(&federation_id),
+                    fee_policy: dbtx.load_fee_policy(federation_id).await,
                 })
             })
             .await
@@ -296,6 +314,10 @@ pub async fn federation_info_all_federations(
                     balance_msat,
                     config,
                     last_backup_time,
+                    exposure_limits: dbtx.load_exposure_limits(*federation_id).await,
+                    in_flight_msat: self.exposure.in_flight(federation_id),
+                    circuit_breaker: self.exposure.circuit_breaker(federation_id),
+                    fee_policy: dbtx.load_fee_policy(*federation_id).await,
                 });
             }
         }
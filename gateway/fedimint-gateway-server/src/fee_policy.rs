@@ -0,0 +1,262 @@
+use fedimint_core::Amount;
+use fedimint_core::config::FederationId;
+use fedimint_gateway_common::{FederationConfig, FeePolicy};
+use fedimint_gateway_server_db::GatewayDbtxNcExt as _;
+use fedimint_lightning::LightningContext;
+use fedimint_lnv2_common::gateway_api::{PaymentFee, RoutingFeeTier};
+use fedimint_logging::LOG_GATEWAY;
+use tracing::debug;
+
+use crate::error::{FederationNotConnected, PublicGatewayError};
+use crate::{Gateway, Result};
+
+/// Ecash share used when the gateway has neither ecash in a federation nor
+/// outbound liquidity in its channels.
+const BALANCED_ECASH_SHARE_PPM: u64 = 500_000;
+
+/// The LNv2 fees the gateway currently charges for a federation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Lnv2Fees {
+    pub(crate) send_fee_default: PaymentFee,
+    pub(crate) receive_fee: PaymentFee,
+    pub(crate) fee_tiers: Vec<RoutingFeeTier>,
+    /// The ecash share the federation's fee policy was evaluated at, or `None`
+    /// if the federation uses static fees.
+    pub(crate) ecash_share_ppm: Option<u64>,
+}
+
+impl Gateway {
+    /// Computes the LNv2 fees of a federation. Without a fee policy these are
+    /// the static fees of the federation's config, otherwise the policy is
+    /// evaluated at the gateway's current liquidity.
+    pub(crate) async fn routing_fees_v2(
+        &self,
+        federation_id: &FederationId,
+        fed_config: &FederationConfig,
+        context: &LightningContext,
+    ) -> Result<Lnv2Fees> {
+        let policy = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_fee_policy(*federation_id)
+            .await;
+
+        let Some(policy) = policy else {
+            // This route is public and unauthenticated, so the sum of two fees stored
+            // before the fee limits applied must not be able to panic here.
+            let send_fee_default = fed_config
+                .lightning_fee
+                .checked_add(fed_config.transaction_fee)
+                .ok_or_else(|| {
+                    PublicGatewayError::Unexpected(anyhow::anyhow!(
+                        "The configured fees of federation {federation_id} cannot be added"
+                    ))
+                })?;
+
+            return Ok(Lnv2Fees {
+                send_fee_default,
+                receive_fee: fed_config.transaction_fee,
+                fee_tiers: Vec::new(),
+                ecash_share_ppm: None,
+            });
+        };
+
+        let ecash_balance = self
+            .federation_manager
+            .read()
+            .await
+            .client(federation_id)
+            .ok_or(FederationNotConnected {
+                federation_id_prefix: federation_id.to_prefix(),
+            })?
+            .value()
+            .get_balance_for_btc()
+            .await
+            .unwrap_or_default();
+        let outbound_msats = context.lnrpc.get_balances().await?.lightning_balance_msats;
+        let ecash_share_ppm = ecash_share_ppm(ecash_balance, outbound_msats);
+
+        debug!(
+            target: LOG_GATEWAY,
+            %federation_id,
+            ecash_share_ppm,
+            "Evaluating fee policy"
+        );
+
+        Ok(policy_fees(
+            &policy,
+            fed_config.lightning_fee,
+            fed_config.transaction_fee,
+            ecash_share_ppm,
+        ))
+    }
+}
+
+/// Returns the share of the gateway's liquidity for a federation that is held
+/// as ecash, in parts per million.
+fn ecash_share_ppm(ecash_balance: Amount, outbound_msats: u64) -> u64 {
+    let ecash_msats = u128::from(ecash_balance.msats);
+    let total_msats = ecash_msats + u128::from(outbound_msats);
+
+    if total_msats == 0 {
+        return BALANCED_ECASH_SHARE_PPM;
+    }
+
+    u64::try_from(ecash_msats * 1_000_000 / total_msats).expect("At most one million")
+}
+
+/// Evaluates a fee policy at the given ecash share. The scaled lightning fee
+/// is charged on top of the transaction fee and the totals are capped at the
+/// fee limits LNv2 clients accept.
+fn policy_fees(
+    policy: &FeePolicy,
+    lightning_fee: PaymentFee,
+    transaction_fee: PaymentFee,
+    ecash_share_ppm: u64,
+) -> Lnv2Fees {
+    let send_lightning_fee = scale_fee(
+        lightning_fee,
+        policy.send_multiplier_percent(ecash_share_ppm),
+    );
+    let receive_lightning_fee = scale_fee(
+        lightning_fee,
+        policy.receive_multiplier_percent(ecash_share_ppm),
+    );
+
+    let fee_tiers = policy
+        .amount_tiers
+        .iter()
+        .map(|tier| {
+            let remaining_percent = 100 - tier.discount_percent.min(100);
+
+            RoutingFeeTier {
+                min_amount: tier.min_amount,
+                send_fee_default: add_capped(
+                    transaction_fee,
+                    scale_fee(send_lightning_fee, remaining_percent),
+                    PaymentFee::SEND_FEE_LIMIT,
+                ),
+                receive_fee: add_capped(
+                    transaction_fee,
+                    scale_fee(receive_lightning_fee, remaining_percent),
+                    PaymentFee::RECEIVE_FEE_LIMIT,
+                ),
+            }
+        })
+        .collect();
+
+    Lnv2Fees {
+        send_fee_default: add_capped(
+            transaction_fee,
+            send_lightning_fee,
+            PaymentFee::SEND_FEE_LIMIT,
+        ),
+        receive_fee: add_capped(
+            transaction_fee,
+            receive_lightning_fee,
+            PaymentFee::RECEIVE_FEE_LIMIT,
+        ),
+        fee_tiers,
+        ecash_share_ppm: Some(ecash_share_ppm),
+    }
+}
+
+fn scale_fee(fee: PaymentFee, percent: u64) -> PaymentFee {
+    PaymentFee {
+        base: Amount::from_msats(fee.base.msats.saturating_mul(percent) / 100),
+        parts_per_million: fee.parts_per_million.saturating_mul(percent) / 100,
+    }
+}
+
+fn add_capped(fee: PaymentFee, other: PaymentFee, limit: PaymentFee) -> PaymentFee {
+    let sum = fee.checked_add(other).unwrap_or(limit);
+
+    PaymentFee {
+        base: sum.base.min(limit.base),
+        parts_per_million: sum.parts_per_million.min(limit.parts_per_million),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_gateway_common::{FeeAmountTier, FeeCurvePoint};
+
+    use super::*;
+
+    fn fee(base_sats: u64, parts_per_million: u64) -> PaymentFee {
+        PaymentFee {
+            base: Amount::from_sats(base_sats),
+            parts_per_million,
+        }
+    }
+
+    fn point(ecash_share_ppm: u64, multiplier_percent: u64) -> FeeCurvePoint {
+        FeeCurvePoint {
+            ecash_share_ppm,
+            multiplier_percent,
+        }
+    }
+
+    #[test]
+    fn ecash_share_is_relative_to_outbound_liquidity() {
+        assert_eq!(ecash_share_ppm(Amount::from_msats(250), 750), 250_000);
+        assert_eq!(ecash_share_ppm(Amount::ZERO, 1_000), 0);
+        assert_eq!(ecash_share_ppm(Amount::ZERO, 0), BALANCED_ECASH_SHARE_PPM);
+    }
+
+    #[test]
+    fn policy_scales_lightning_fee_and_caps_at_limits() {
+        let policy = FeePolicy {
+            send_curve: vec![point(0, 50), point(1_000_000, 1_000)],
+            receive_curve: vec![point(0, 100), point(1_000_000, 0)],
+            amount_tiers: vec![],
+        };
+
+        let drained_channels = policy_fees(&policy, fee(10, 1_000), fee(2, 3_000), 1_000_000);
+        assert_eq!(drained_channels.send_fee_default, fee(100, 13_000));
+        assert_eq!(drained_channels.receive_fee, fee(2, 3_000));
+
+        let drained_ecash = policy_fees(&policy, fee(10, 1_000), fee(2, 3_000), 0);
+        assert_eq!(drained_ecash.send_fee_default, fee(7, 3_500));
+        assert_eq!(drained_ecash.receive_fee, fee(12, 4_000));
+        assert_eq!(drained_ecash.ecash_share_ppm, Some(0));
+    }
+
+    #[test]
+    fn amount_tiers_discount_the_lightning_fee() {
+        let policy = FeePolicy {
+            send_curve: vec![],
+            receive_curve: vec![point(0, 100)],
+            amount_tiers: vec![
+                FeeAmountTier {
+                    min_amount: Amount::from_sats(100_000),
+                    discount_percent: 50,
+                },
+                FeeAmountTier {
+                    min_amount: Amount::from_sats(1_000_000),
+                    discount_percent: 100,
+                },
+            ],
+        };
+
+        let fees = policy_fees(&policy, fee(10, 1_000), fee(2, 3_000), 500_000);
+
+        assert_eq!(fees.send_fee_default, fee(12, 4_000));
+        assert_eq!(
+            fees.fee_tiers,
+            vec![
+                RoutingFeeTier {
+                    min_amount: Amount::from_sats(100_000),
+                    send_fee_default: fee(7, 3_500),
+                    receive_fee: fee(7, 3_500),
+                },
+                RoutingFeeTier {
+                    min_amount: Amount::from_sats(1_000_000),
+                    send_fee_default: fee(2, 3_000),
+                    receive_fee: fee(2, 3_000),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use std::time::Duration;
+
+use fedimint_core::task::sleep;
+use fedimint_core::time::duration_since_epoch;
+use fedimint_core::util::FmtCompact as _;
+use fedimint_gateway_server_db::GatewayDbtxNcExt as _;
+use fedimint_lightning::{
+    InterceptPaymentRequest, InterceptPaymentResponse, LightningContext, PaymentAction,
+};
+use fedimint_lnv2_common::contracts::{IncomingContract, PaymentImage};
+use fedimint_lnv2_common::gateway_api::{
+    CreateBolt11InvoicePayload, HoldInvoiceStatus, ResolveHoldInvoicePayload,
+};
+use fedimint_logging::LOG_GATEWAY;
+use lightning_invoice::Bolt11Invoice;
+use tracing::{info, warn};
+
+use crate::error::{LNv2Error, PublicGatewayError};
+use crate::{Gateway, Result};
+
+/// Maximum time the gateway holds a payment for a hold invoice. The sender's
+/// HTLC is locked up for as long as the payment is held, so we do not let the
+/// recipient hold it for longer than the final hop's CLTV delta allows.
+const MAX_HOLD_DURATION: Duration = Duration::from_secs(60 * 60);
+
+impl Gateway {
+    /// Creates a hold invoice for an incoming contract. Unlike for an invoice
+    /// created by [`Self::create_bolt11_invoice_v2`] the gateway holds incoming
+    /// payments until the recipient settles or cancels them, and cancels them
+    /// itself once the incoming contract expires.
+    pub(crate) async fn create_hold_invoice_v2(
+        &self,
+        payload: CreateBolt11InvoicePayload,
+    ) -> Result<Bolt11Invoice> {
+        let invoice_expiration = duration_since_epoch().as_secs() + u64::from(payload.expiry_secs);
+        let contract_expiration = payload.contract.commitment.expiration_or_fee;
+
+        if contract_expiration > invoice_expiration + MAX_HOLD_DURATION.as_secs() {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                format!(
+                    "Payments can not be held for longer than {} seconds",
+                    MAX_HOLD_DURATION.as_secs()
+                ),
+            )));
+        }
+
+        let payment_image = payload.contract.commitment.payment_image.clone();
+
+        let invoice = self.create_bolt11_invoice_v2(payload).await?;
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        dbtx.save_hold_invoice_status(payment_image, HoldInvoiceStatus::Open)
+            .await;
+
+        dbtx.commit_tx().await;
+
+        Ok(invoice)
+    }
+
+    /// Returns the status of a hold invoice, or `None` if the gateway has not
+    /// created a hold invoice for the payment image.
+    pub(crate) async fn hold_invoice_status_v2(
+        &self,
+        payment_image: PaymentImage,
+    ) -> Result<Option<HoldInvoiceStatus>> {
+        Ok(self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_hold_invoice_status(payment_image)
+            .await)
+    }
+
+    /// Settles or cancels the payment for a hold invoice on behalf of the
+    /// recipient, who proves control of the incoming contract by signing the
+    /// resolution with the contract's claim key. A payment may be resolved
+    /// before it arrives, in which case the gateway settles or cancels it right
+    /// away. Returns the status of the hold invoice after the resolution, which
+    /// differs from the requested one if the payment was already resolved.
+    pub(crate) async fn resolve_hold_invoice_v2(
+        &self,
+        payload: ResolveHoldInvoicePayload,
+    ) -> Result<HoldInvoiceStatus> {
+        let registered_contract = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_registered_incoming_contract(payload.payment_image.clone())
+            .await
+            .ok_or(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "No corresponding incoming contract available".to_string(),
+            )))?;
+
+        if !registered_contract
+            .contract
+            .verify_hold_resolution(payload.settle, &payload.signature)
+        {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "Invalid signature for the hold invoice resolution".to_string(),
+            )));
+        }
+
+        if registered_contract.contract.commitment.expiration_or_fee
+            <= duration_since_epoch().as_secs()
+        {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "The incoming contract has already expired".to_string(),
+            )));
+        }
+
+        let target_status = if payload.settle {
+            HoldInvoiceStatus::Settled
+        } else {
+            HoldInvoiceStatus::Cancelled
+        };
+
+        let Some(status) = self
+            .transition_hold_invoice_v2(payload.payment_image.clone(), target_status)
+            .await
+        else {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "The invoice is not a hold invoice".to_string(),
+            )));
+        };
+
+        if status != target_status {
+            return Ok(status);
+        }
+
+        let PaymentImage::Hash(payment_hash) = payload.payment_image else {
+            return Ok(status);
+        };
+
+        // The payment has not arrived yet or the gateway restarted while holding
+        // it, in which case the lightning node delivers it again and we resolve it
+        // according to the status then.
+        let Some(htlc_request) = self
+            .held_htlcs
+            .lock()
+            .expect("poisoned")
+            .remove(&payment_hash)
+        else {
+            return Ok(status);
+        };
+
+        let lightning_context = self.get_lightning_context().await?;
+
+        if payload.settle {
+            info!(target: LOG_GATEWAY, %payment_hash, "Settling held payment for hold invoice");
+
+            let (contract, client) = self
+                .get_registered_incoming_contract_and_client_v2(
+                    PaymentImage::Hash(payment_hash),
+                    htlc_request.amount_msat,
+                )
+                .await?;
+
+            self.relay_incoming_htlc_v2(&htlc_request, contract, client, &lightning_context)
+                .await;
+        } else {
+            info!(target: LOG_GATEWAY, %payment_hash, "Cancelling held payment for hold invoice");
+
+            cancel_htlc(&htlc_request, &lightning_context).await;
+        }
+
+        Ok(status)
+    }
+
+    /// Holds an intercepted HTLC paying a hold invoice until the recipient
+    /// settles it, or cancels it if the recipient already cancelled the
+    /// payment. Returns `false` if the HTLC should be relayed right away since
+    /// it does not pay a hold invoice or the recipient already settled it.
+    pub(crate) async fn hold_incoming_htlc_v2(
+        &self,
+        htlc_request: &InterceptPaymentRequest,
+        contract: &IncomingContract,
+        lightning_context: &LightningContext,
+    ) -> bool {
+        let payment_image = PaymentImage::Hash(htlc_request.payment_hash);
+
+        // We make the HTLC available to a concurrent resolution before the held
+        // status is committed, since the resolution only looks for the HTLC once
+        // it has observed the status.
+        self.held_htlcs
+            .lock()
+            .expect("poisoned")
+            .insert(htlc_request.payment_hash, htlc_request.clone());
+
+        let status = self
+            .transition_hold_invoice_v2(payment_image.clone(), HoldInvoiceStatus::Held)
+            .await;
+
+        if status == Some(HoldInvoiceStatus::Held) {
+            info!(target: LOG_GATEWAY, payment_hash = %htlc_request.payment_hash, "Holding payment for hold invoice");
+
+            let deadline = Duration::from_secs(contract.commitment.expiration_or_fee)
+                .min(duration_since_epoch() + MAX_HOLD_DURATION);
+
+            self.spawn_hold_deadline_task(payment_image, deadline);
+
+            return true;
+        }
+
+        // A resolution which raced with us has already taken over the HTLC
+        if self
+            .held_htlcs
+            .lock()
+            .expect("poisoned")
+            .remove(&htlc_request.payment_hash)
+            .is_none()
+        {
+            return true;
+        }
+
+        match status {
+            None | Some(HoldInvoiceStatus::Settled) => false,
+            Some(HoldInvoiceStatus::Cancelled) => {
+                cancel_htlc(htlc_request, lightning_context).await;
+
+                true
+            }
+            Some(HoldInvoiceStatus::Open | HoldInvoiceStatus::Held) => {
+                unreachable!("The status has just been updated")
+            }
+        }
+    }
+
+    /// Rejects the payment image of a hold invoice unless its recipient has
+    /// already settled the payment.
+    pub(crate) async fn ensure_not_held_v2(&self, payment_image: PaymentImage) -> Result<()> {
+        match self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_hold_invoice_status(payment_image)
+            .await
+        {
+            None | Some(HoldInvoiceStatus::Settled) => Ok(()),
+            Some(..) => Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "The payment for the hold invoice has not been settled".to_string(),
+            ))),
+        }
+    }
+
+    /// Moves an open or held hold invoice to `target_status` and returns the
+    /// resulting status. Hold invoices that were already settled or cancelled
+    /// keep their status. Returns `None` if the payment image does not belong
+    /// to a hold invoice.
+    async fn transition_hold_invoice_v2(
+        &self,
+        payment_image: PaymentImage,
+        target_status: HoldInvoiceStatus,
+    ) -> Option<HoldInvoiceStatus> {
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        let status = match dbtx.load_hold_invoice_status(payment_image.clone()).await? {
+            HoldInvoiceStatus::Open | HoldInvoiceStatus::Held => target_status,
+            status @ (HoldInvoiceStatus::Settled | HoldInvoiceStatus::Cancelled) => {
+                return Some(status);
+            }
+        };
+
+        dbtx.save_hold_invoice_status(payment_image, status).await;
+
+        dbtx.commit_tx().await;
+
+        Some(status)
+    }
+
+    /// Spawns a task that cancels a held payment once the deadline, in seconds
+    /// since the unix epoch, has passed without the recipient resolving it.
+    fn spawn_hold_deadline_task(&self, payment_image: PaymentImage, deadline: Duration) {
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("hold invoice deadline", async move {
+                sleep(deadline.saturating_sub(duration_since_epoch())).await;
+
+                let PaymentImage::Hash(payment_hash) = payment_image else {
+                    return;
+                };
+
+                if self_copy
+                    .transition_hold_invoice_v2(payment_image, HoldInvoiceStatus::Cancelled)
+                    .await
+                    != Some(HoldInvoiceStatus::Cancelled)
+                {
+                    return;
+                }
+
+                let Some(htlc_request) = self_copy
+                    .held_htlcs
+                    .lock()
+                    .expect("poisoned")
+                    .remove(&payment_hash)
+                else {
+                    return;
+                };
+
+                warn!(target: LOG_GATEWAY, %payment_hash, "Cancelling held payment for hold invoice after deadline");
+
+                match self_copy.get_lightning_context().await {
+                    Ok(lightning_context) => cancel_htlc(&htlc_request, &lightning_context).await,
+                    Err(err) => {
+                        warn!(target: LOG_GATEWAY, err = %err.fmt_compact(), "Could not cancel held payment");
+                    }
+                }
+            });
+    }
+}
+
+/// Fails an intercepted HTLC back to the sender.
+async fn cancel_htlc(htlc_request: &InterceptPaymentRequest, lightning_context: &LightningContext) {
+    let outcome = InterceptPaymentResponse {
+        action: PaymentAction::Cancel,
+        payment_hash: htlc_request.payment_hash,
+        incoming_chan_id: htlc_request.incoming_chan_id,
+        htlc_id: htlc_request.htlc_id,
+    };
+
+    if let Err(err) = lightning_context.lnrpc.complete_htlc(outcome).await {
+        warn!(target: LOG_GATEWAY, err = %err.fmt_compact(), "Error sending HTLC response to lightning node");
+    }
+}
@@ -19,11 +19,19 @@
 pub mod envs;
 mod error;
 mod events;
+mod exposure;
 mod federation_manager;
+mod fee_policy;
+mod hold_invoice;
 mod iroh_server;
 mod metrics;
+mod rebalance;
 pub mod rpc_server;
+mod swap;
+#[cfg(feature = "testing")]
+pub mod testing;
 mod types;
+mod webhook;
 
 use std::collections::{BTreeMap, BTreeSet};
 use std::env;
@@ -43,7 +51,7 @@
 use config::{DatabaseBackend, GatewayOpts};
 use envs::FM_GATEWAY_SKIP_WAIT_FOR_SYNC_ENV;
 use error::FederationNotConnected;
-use events::ALL_GATEWAY_EVENTS;
+use events::{ALL_GATEWAY_EVENTS, PaymentFeeApplied};
 use federation_manager::FederationManager;
 use fedimint_bip39::{Bip39RootSecretStrategy, Language, Mnemonic};
 use fedimint_bitcoind::bitcoincore::BitcoindClient;
@@ -70,19 +78,24 @@
     Amount, BitcoinAmountOrAll, PeerId, TieredCounts, crit, fedimint_build_code_version_env,
     get_network_for_address,
 };
+use fedimint_eventlog::export::EventLogExporter;
 use fedimint_eventlog::{DBTransactionEventLogExt, EventLogId, StructuredPaymentEvents};
 use fedimint_gateway_common::{
-    BackupPayload, ChainSource, CloseChannelsWithPeerRequest, CloseChannelsWithPeerResponse,
-    ConnectFedPayload, ConnectPeerRequest, ConnectorType, CreateInvoiceForOperatorPayload,
-    CreateOfferPayload, CreateOfferResponse, DepositAddressPayload, DepositAddressRecheckPayload,
-    FederationBalanceInfo, FederationConfig, FederationInfo, GatewayBalances, GatewayFedConfig,
-    GatewayInfo, GetInvoiceRequest, GetInvoiceResponse, LeaveFedPayload, LightningInfo,
-    LightningMode, ListTransactionsPayload, ListTransactionsResponse, MnemonicResponse,
-    OpenChannelRequest, PayInvoiceForOperatorPayload, PayOfferPayload, PayOfferResponse,
+    BackupPayload, ChainSource, CircuitBreakerState, CloseChannelsWithPeerRequest,
+    CloseChannelsWithPeerResponse, ConnectFedPayload, ConnectPeerRequest, ConnectorType,
+    CreateInvoiceForOperatorPayload, CreateOfferPayload, CreateOfferResponse,
+    DEFAULT_EXPORT_EVENT_LOG_LIMIT, DepositAddressPayload, DepositAddressRecheckPayload,
+    ExportEventLogPayload, ExportEventLogResponse, ExposureLimits, FederationBalanceInfo,
+    FederationConfig, FederationInfo, GatewayBalances, GatewayFedConfig, GatewayInfo,
+    GetInvoiceRequest, GetInvoiceResponse, LeaveFedPayload, LightningInfo, LightningMode,
+    ListTransactionsPayload, ListTransactionsResponse, MnemonicResponse, OpenChannelRequest,
+    PayInvoiceForOperatorPayload, PayOfferPayload, PayOfferResponse, PaymentDirection,
     PaymentLogPayload, PaymentLogResponse, PaymentStats, PaymentSummaryPayload,
-    PaymentSummaryResponse, PeginFromOnchainPayload, ReceiveEcashPayload, ReceiveEcashResponse,
-    RegisteredProtocol, SendOnchainRequest, SetChannelFeesRequest, SetFeesPayload,
-    SetMnemonicPayload, SpendEcashPayload, SpendEcashResponse, V1_API_ENDPOINT, WithdrawPayload,
+    PaymentSummaryResponse, PeginFromOnchainPayload, RebalanceConfigResponse, RebalanceResponse,
+    RebalanceSettings, ReceiveEcashPayload, ReceiveEcashResponse, RegisteredProtocol,
+    SendOnchainRequest, SetChannelFeesRequest, SetExposureLimitsPayload, SetFeePolicyPayload,
+    SetFeesPayload, SetLiquidityTargetPayload, SetMnemonicPayload, SetWebhookPayload,
+    SpendEcashPayload, SpendEcashResponse, V1_API_ENDPOINT, WebhookNotification, WithdrawPayload,
     WithdrawPreviewPayload, WithdrawPreviewResponse, WithdrawResponse, WithdrawToOnchainPayload,
 };
 use fedimint_gateway_server_db::{GatewayDbtxNcExt as _, get_gatewayd_database_migrations};
@@ -103,17 +116,19 @@
     InvoiceDescription, LightningContext, LightningRpcError, LnRpcTracked, Lnv2HoldInvoiceFilter,
     PayInvoiceResponse, PaymentAction, RouteHtlcStream, ldk,
 };
+use fedimint_ln_client::api::LnFederationApi as _;
 use fedimint_ln_client::pay::PaymentData;
 use fedimint_ln_common::LightningCommonInit;
 use fedimint_ln_common::config::LightningClientConfig;
 use fedimint_ln_common::contracts::outgoing::OutgoingContractAccount;
 use fedimint_ln_common::contracts::{IdentifiableContract, Preimage};
 use fedimint_lnurl::VerifyResponse;
-use fedimint_lnv2_common::Bolt11InvoiceDescription;
 use fedimint_lnv2_common::contracts::{IncomingContract, PaymentImage};
 use fedimint_lnv2_common::gateway_api::{
-    CreateBolt11InvoicePayload, PaymentFee, RoutingInfo, SendPaymentPayload,
+    CreateBolt11InvoicePayload, CreateBolt12OfferPayload, FetchBolt12InvoicePayload, PaymentFee,
+    RoutingInfo, SendPaymentPayload,
 };
+use fedimint_lnv2_common::{Bolt11InvoiceDescription, LightningInvoice};
 use fedimint_logging::LOG_GATEWAY;
 use fedimint_mint_client::{MintClientInit, MintClientModule, OOBNotes, ReissueExternalNotesState};
 use fedimint_mintv2_client::{
@@ -121,14 +136,19 @@
 };
 use fedimint_wallet_client::{PegOutFees, WalletClientInit, WalletClientModule, WithdrawState};
 use futures::stream::StreamExt;
+use lightning::offers::invoice::Bolt12Invoice;
+use lightning::offers::offer::Offer;
 use lightning_invoice::{Bolt11Invoice, RoutingFees};
 use rand::rngs::OsRng;
-use tokio::sync::RwLock;
+use tokio::sync::{Notify, RwLock};
 use tracing::{debug, info, info_span, warn};
 
 use crate::envs::FM_GATEWAY_MNEMONIC_ENV;
-use crate::error::{AdminGatewayError, LNv1Error, LNv2Error, PublicGatewayError};
+use crate::error::{AdminGatewayError, ExposureError, LNv1Error, LNv2Error, PublicGatewayError};
 use crate::events::get_events_for_duration;
+use crate::exposure::ExposureTracker;
+use crate::fee_policy::Lnv2Fees;
+use crate::rebalance::MIN_REBALANCE_INTERVAL;
 use crate::rpc_server::run_webserver;
 use crate::types::PrettyInterceptPaymentRequest;
 
@@ -317,6 +337,18 @@ pub struct Gateway {
     /// The gateway's federation manager.
     federation_manager: Arc<RwLock<FederationManager>>,
 
+    /// Payments in flight and circuit breakers of the connected federations,
+    /// shared with the federation manager. Kept here so payment paths do not
+    /// have to take the federation manager's lock.
+    exposure: Arc<ExposureTracker>,
+
+    /// Wakes up the webhook dispatcher when a notification was queued.
+    webhook_notify: Arc<Notify>,
+
+    /// HTLCs paying hold invoices that wait for the recipient to settle or
+    /// cancel them, by payment hash.
+    held_htlcs: Arc<std::sync::Mutex<BTreeMap<sha256::Hash, InterceptPaymentRequest>>>,
+
     /// The mode that specifies the lightning connection parameters
     lightning_mode: LightningMode,
 
@@ -653,7 +685,7 @@ pub async fn new_with_default_modules(
         registry.attach(MintClientInit);
         registry.attach(MintV2ClientInit);
         registry.attach(WalletClientInit::new(dyn_bitcoin_rpc));
-        registry.attach(fedimint_walletv2_client::WalletClientInit);
+        registry.attach(fedimint_walletv2_client::WalletClientInit::default());
 
         let client_builder =
             GatewayClientBuilder::new(opts.data_dir.clone(), registry, opts.db_backend).await?;
@@ -737,8 +769,14 @@ async fn new(
             );
         }
 
+        let federation_manager = FederationManager::new();
+        let exposure = federation_manager.exposure();
+
         Ok(Self {
-            federation_manager: Arc::new(RwLock::new(FederationManager::new())),
+            federation_manager: Arc::new(RwLock::new(federation_manager)),
+            exposure,
+            webhook_notify: Arc::new(Notify::new()),
+            held_htlcs: Arc::new(std::sync::Mutex::new(BTreeMap::new())),
             lightning_mode,
             state: Arc::new(RwLock::new(gateway_state)),
             client_builder,
@@ -815,6 +853,9 @@ pub async fn run(
         self.load_clients().await?;
         self.start_gateway(runtime, mnemonic_receiver.resubscribe());
         self.spawn_backup_task();
+        self.spawn_rebalance_task();
+        self.spawn_federation_health_task();
+        self.spawn_webhook_tasks();
         // start metrics server
         fedimint_metrics::spawn_api_server(self.metrics_listen, self.task_group.clone()).await?;
         // start webserver last to avoid handling requests before fully initialized
@@ -1228,6 +1269,55 @@ async fn try_handle_lightning_payment_lnv2(
             )
             .await?;
 
+        // Payments for hold invoices are held until the recipient settles them.
+        if self
+            .hold_incoming_htlc_v2(htlc_request, &contract, lightning_context)
+            .await
+        {
+            return Ok(());
+        }
+
+        self.relay_incoming_htlc_v2(htlc_request, contract, client, lightning_context)
+            .await;
+
+        Ok(())
+    }
+
+    /// Funds the incoming contract for an intercepted HTLC such that the
+    /// recipient can claim the payment, and cancels the HTLC if the gateway
+    /// cannot fund the contract.
+    async fn relay_incoming_htlc_v2(
+        &self,
+        htlc_request: &InterceptPaymentRequest,
+        contract: IncomingContract,
+        client: ClientHandleArc,
+        lightning_context: &LightningContext,
+    ) {
+        // The gateway funds the incoming contract with its own ecash, so only the
+        // amount in flight is limited.
+        let in_flight = match self
+            .reserve_exposure(&client, Amount::from_msats(htlc_request.amount_msat), false)
+            .await
+        {
+            Ok(in_flight) => in_flight,
+            Err(err) => {
+                warn!(target: LOG_GATEWAY, err = %err.fmt_compact(), "Rejecting incoming lightning payment");
+
+                let outcome = InterceptPaymentResponse {
+                    action: PaymentAction::Cancel,
+                    payment_hash: htlc_request.payment_hash,
+                    incoming_chan_id: htlc_request.incoming_chan_id,
+                    htlc_id: htlc_request.htlc_id,
+                };
+
+                if let Err(err) = lightning_context.lnrpc.complete_htlc(outcome).await {
+                    warn!(target: LOG_GATEWAY, err = %err.fmt_compact(), "Error sending HTLC response to lightning node");
+                }
+
+                return;
+            }
+        };
+
         if let Err(err) = client
             .get_first_module::<GatewayClientModuleV2>()
             .expect("Must have client module")
@@ -1240,6 +1330,7 @@ async fn try_handle_lightning_payment_lnv2(
             )
             .await
         {
+            drop(in_flight);
             warn!(target: LOG_GATEWAY, err = %err.fmt_compact_anyhow(), "Error relaying incoming lightning payment");
 
             let outcome = InterceptPaymentResponse {
@@ -1252,9 +1343,11 @@ async fn try_handle_lightning_payment_lnv2(
             if let Err(err) = lightning_context.lnrpc.complete_htlc(outcome).await {
                 warn!(target: LOG_GATEWAY, err = %err.fmt_compact(), "Error sending HTLC response to lightning node");
             }
+
+            return;
         }
 
-        Ok(())
+        self.hold_exposure_for_htlc(in_flight, htlc_request);
     }
 
     /// Tries to handle a lightning payment using the legacy lightning protocol.
@@ -1298,13 +1391,25 @@ async fn try_handle_lightning_payment_ln_legacy(
                                         "Federation does not have LNv1 module".to_string(),
                                     ))
                                 })?;
+                        // Rejecting here lets the caller cancel the HTLC since it was
+                        // sent to a federation scid
+                        let in_flight = self
+                            .reserve_exposure(
+                                client,
+                                Amount::from_msats(htlc_request.amount_msat),
+                                false,
+                            )
+                            .await?;
                         match lnv1
                             .gateway_handle_intercepted_htlc(htlc, async {
                                 Ok(lightning_context.lnrpc.info().await?.block_height)
                             })
                             .await
                         {
-                            Ok(_) => Ok(()),
+                            Ok(_) => {
+                                self.hold_exposure_for_htlc(in_flight, htlc_request);
+                                Ok(())
+                            }
                             Err(e) => Err(PublicGatewayError::LNv1(LNv1Error::IncomingPayment(
                                 format!("Error intercepting lightning payment {e:?}"),
                             ))),
@@ -1379,6 +1484,7 @@ async fn forward_lightning_payment(
     /// requested one if the gateway is shutting down.
     async fn set_gateway_state(&self, state: GatewayState) -> GatewayState {
         let mut lock = self.state.write().await;
+        let previous_state = lock.to_string();
 
         if let GatewayState::ShuttingDown { .. } = *lock {
             match state {
@@ -1397,7 +1503,17 @@ async fn set_gateway_state(&self, state: GatewayState) -> GatewayState {
             *lock = state;
         }
 
-        lock.clone()
+        let state = lock.clone();
+        drop(lock);
+
+        if state.to_string() != previous_state {
+            self.notify_webhook(WebhookNotification::GatewayStateChanged {
+                state: state.to_string(),
+            })
+            .await;
+        }
+
+        state
     }
 
     /// Drives the gateway's state directly, bypassing the lightning connection
@@ -1449,7 +1565,7 @@ pub async fn handle_get_federation_config(
     /// Returns a Bitcoin deposit on-chain address for pegging in Bitcoin for a
     /// specific connected federation.
     pub async fn handle_address_msg(&self, payload: DepositAddressPayload) -> AdminResult<Address> {
-        let client = self.select_client(payload.federation_id).await?;
+        let client = self.connected_client(payload.federation_id).await?;
 
         if let Ok(wallet_module) = client.value().get_first_module::<WalletClientModule>() {
             let address = wallet_module
@@ -1489,6 +1605,23 @@ async fn handle_pay_invoice_msg(
             .get_first_module::<GatewayClientModule>()
             .map_err(LNv1Error::OutgoingPayment)
             .map_err(PublicGatewayError::LNv1)?;
+        // Amountless invoices are paid with the amount of the outgoing contract,
+        // so it has to be fetched from the federation to check the limits.
+        let amount = match payload.payment_data.amount() {
+            Some(amount) => amount,
+            None => {
+                gateway_module
+                    .api
+                    .fetch_contract(contract_id)
+                    .await
+                    .map_err(|e| LNv1Error::OutgoingPayment(e.into()))?
+                    .ok_or(LNv1Error::OutgoingPayment(anyhow!(
+                        "Outgoing contract {contract_id} does not exist"
+                    )))?
+                    .amount
+            }
+        };
+        let _in_flight = self.reserve_exposure(client.value(), amount, true).await?;
         let operation_id = gateway_module
             .gateway_pay_bolt11_invoice(payload)
             .await
@@ -1567,7 +1700,7 @@ pub async fn handle_recheck_address_msg(
         &self,
         payload: DepositAddressRecheckPayload,
     ) -> AdminResult<()> {
-        let client = self.select_client(payload.federation_id).await?;
+        let client = self.connected_client(payload.federation_id).await?;
 
         if let Ok(wallet_module) = client.value().get_first_module::<WalletClientModule>() {
             wallet_module
@@ -1835,11 +1968,28 @@ async fn register_federations(
         }
     }
 
-    /// Retrieves a `ClientHandleArc` from the Gateway's in memory structures
-    /// that keep track of available clients, given a `federation_id`.
+    /// Retrieves the `ClientHandleArc` of a federation payments are routed
+    /// through, failing if the federation's circuit breaker is open.
     pub async fn select_client(
         &self,
         federation_id: FederationId,
+    ) -> Result<Spanned<fedimint_client::ClientHandleArc>> {
+        if self.exposure.circuit_breaker(&federation_id).is_open() {
+            return Err(ExposureError::CircuitBreakerOpen {
+                federation_id_prefix: federation_id.to_prefix(),
+            }
+            .into());
+        }
+
+        Ok(self.connected_client(federation_id).await?)
+    }
+
+    /// Retrieves a `ClientHandleArc` from the Gateway's in memory structures
+    /// that keep track of available clients, given a `federation_id`,
+    /// regardless of the federation's circuit breaker.
+    pub async fn connected_client(
+        &self,
+        federation_id: FederationId,
     ) -> std::result::Result<Spanned<fedimint_client::ClientHandleArc>, FederationNotConnected>
     {
         self.federation_manager
@@ -2276,7 +2426,19 @@ async fn handle_leave_federation(
             .await?;
 
         dbtx.remove_federation_config(payload.federation_id).await;
+        dbtx.remove_liquidity_target(payload.federation_id).await;
+        dbtx.remove_exposure_limits(payload.federation_id).await;
+        dbtx.remove_fee_policy(payload.federation_id).await;
+        dbtx.remove_webhook_event_log_position(payload.federation_id)
+            .await;
         dbtx.commit_tx().await;
+        drop(federation_manager);
+
+        self.notify_webhook(WebhookNotification::FederationLeft {
+            federation_id: payload.federation_id,
+        })
+        .await;
+
         Ok(federation_info)
     }
 
@@ -2365,6 +2527,10 @@ async fn handle_connect_federation(
             }),
             config: federation_config.clone(),
             last_backup_time: None,
+            exposure_limits: ExposureLimits::default(),
+            in_flight_msat: Amount::ZERO,
+            circuit_breaker: CircuitBreakerState::Closed,
+            fee_policy: None,
         };
 
         Self::check_federation_network(&client, self.network).await?;
@@ -2406,6 +2572,10 @@ async fn handle_connect_federation(
             federation_index = %federation_index,
             "Federation connected"
         );
+        drop(federation_manager);
+
+        self.notify_webhook(WebhookNotification::FederationConnected { federation_id })
+            .await;
 
         Ok(federation_info)
     }
@@ -2756,7 +2926,7 @@ async fn handle_spend_ecash_msg(
         payload: SpendEcashPayload,
     ) -> AdminResult<SpendEcashResponse> {
         let client = self
-            .select_client(payload.federation_id)
+            .connected_client(payload.federation_id)
             .await?
             .into_value();
 
@@ -2806,6 +2976,11 @@ async fn handle_shutdown_msg(&self, task_group: TaskGroup) -> AdminResult<()> {
         // gets refunded. `get_lightning_context` accepts `ShuttingDown`, so the
         // in-flight payments can complete while the gateway drains.
         if was_running {
+            self.notify_webhook(WebhookNotification::GatewayStateChanged {
+                state: "ShuttingDown".to_string(),
+            })
+            .await;
+
             self.federation_manager
                 .read()
                 .await
@@ -2846,7 +3021,7 @@ async fn handle_withdraw_msg(&self, payload: WithdrawPayload) -> AdminResult<Wit
             });
         };
 
-        let client = self.select_client(federation_id).await?;
+        let client = self.connected_client(federation_id).await?;
 
         if let Ok(wallet_module) = client
             .value()
@@ -2947,7 +3122,7 @@ async fn handle_withdraw_preview_msg(
                 failure_reason: "Address network mismatch".to_string(),
             })?;
 
-        let client = self.select_client(payload.federation_id).await?;
+        let client = self.connected_client(payload.federation_id).await?;
 
         let WithdrawDetails {
             amount,
@@ -3074,6 +3249,47 @@ async fn handle_payment_log_msg(
         Ok(PaymentLogResponse(payment_log))
     }
 
+    /// Acknowledges the entries a consumer has processed and returns the next
+    /// page of the federation client's event log matching the filter.
+    ///
+    /// The export's cursor is only advanced by an acknowledgement, never by
+    /// reading, so a response that does not reach the consumer is returned
+    /// again by the next request. Unlike `handle_payment_log_msg` this walks
+    /// the log forwards, so it can be polled to stream the full event log
+    /// into external systems.
+    async fn handle_export_event_log_msg(
+        &self,
+        ExportEventLogPayload {
+            federation_id,
+            name,
+            filter,
+            ack,
+            limit,
+        }: ExportEventLogPayload,
+    ) -> AdminResult<ExportEventLogResponse> {
+        let federation_manager = self.federation_manager.read().await;
+        let client = federation_manager
+            .client(&federation_id)
+            .ok_or(FederationNotConnected {
+                federation_id_prefix: federation_id.to_prefix(),
+            })?
+            .value();
+
+        let mut exporter =
+            EventLogExporter::new(client.db().clone(), client.event_log_export_tracker(name))
+                .with_filter(filter);
+
+        if let Some(ack) = ack {
+            exporter.ack(ack).await?;
+        }
+
+        let (entries, next_pos) = exporter
+            .read_pending(limit.unwrap_or(DEFAULT_EXPORT_EVENT_LOG_LIMIT))
+            .await?;
+
+        Ok(ExportEventLogResponse { entries, next_pos })
+    }
+
     /// Set the gateway's root mnemonic by generating a new one or using the
     /// words provided in `SetMnemonicPayload`.
     async fn handle_set_mnemonic_msg(&self, payload: SetMnemonicPayload) -> AdminResult<()> {
@@ -3169,6 +3385,145 @@ async fn handle_get_note_summary_msg(
         fed_manager.get_note_summary(federation_id).await
     }
 
+    async fn handle_get_rebalance_config_msg(&self) -> AdminResult<RebalanceConfigResponse> {
+        let mut dbtx = self.gateway_db.begin_transaction_nc().await;
+        Ok(RebalanceConfigResponse {
+            settings: dbtx.load_rebalance_settings().await,
+            targets: dbtx.load_liquidity_targets().await,
+        })
+    }
+
+    async fn handle_set_rebalance_settings_msg(
+        &self,
+        payload: RebalanceSettings,
+    ) -> AdminResult<()> {
+        if payload.interval_secs < MIN_REBALANCE_INTERVAL.as_secs() {
+            return Err(AdminGatewayError::GatewayConfigurationError(format!(
+                "Rebalancing interval must be at least {} seconds",
+                MIN_REBALANCE_INTERVAL.as_secs()
+            )));
+        }
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        dbtx.save_rebalance_settings(&payload).await;
+        dbtx.commit_tx().await;
+        info!(target: LOG_GATEWAY, settings = ?payload, "Updated rebalance settings");
+        Ok(())
+    }
+
+    async fn handle_set_liquidity_target_msg(
+        &self,
+        SetLiquidityTargetPayload {
+            federation_id,
+            target,
+        }: SetLiquidityTargetPayload,
+    ) -> AdminResult<()> {
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        if let Some(target) = target {
+            if target.max_ecash < target.min_ecash {
+                return Err(AdminGatewayError::GatewayConfigurationError(
+                    "Maximum ecash balance must not be below the minimum".to_string(),
+                ));
+            }
+
+            self.federation_manager
+                .read()
+                .await
+                .client(&federation_id)
+                .ok_or(FederationNotConnected {
+                    federation_id_prefix: federation_id.to_prefix(),
+                })?;
+
+            dbtx.save_liquidity_target(federation_id, target).await;
+        } else {
+            dbtx.remove_liquidity_target(federation_id).await;
+        }
+        dbtx.commit_tx().await;
+        info!(target: LOG_GATEWAY, %federation_id, ?target, "Updated liquidity target");
+        Ok(())
+    }
+
+    async fn handle_rebalance_msg(&self) -> AdminResult<RebalanceResponse> {
+        self.rebalance().await
+    }
+
+    async fn handle_set_exposure_limits_msg(
+        &self,
+        SetExposureLimitsPayload {
+            federation_id,
+            limits,
+        }: SetExposureLimitsPayload,
+    ) -> AdminResult<()> {
+        self.federation_manager
+            .read()
+            .await
+            .client(&federation_id)
+            .ok_or(FederationNotConnected {
+                federation_id_prefix: federation_id.to_prefix(),
+            })?;
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        dbtx.save_exposure_limits(federation_id, limits).await;
+        dbtx.commit_tx().await;
+        info!(target: LOG_GATEWAY, %federation_id, ?limits, "Updated exposure limits");
+        Ok(())
+    }
+
+    async fn handle_set_fee_policy_msg(
+        &self,
+        SetFeePolicyPayload {
+            federation_id,
+            policy,
+        }: SetFeePolicyPayload,
+    ) -> AdminResult<()> {
+        self.federation_manager
+            .read()
+            .await
+            .client(&federation_id)
+            .ok_or(FederationNotConnected {
+                federation_id_prefix: federation_id.to_prefix(),
+            })?;
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        match &policy {
+            Some(policy) => {
+                policy
+                    .validate()
+                    .map_err(AdminGatewayError::GatewayConfigurationError)?;
+                dbtx.save_fee_policy(federation_id, policy).await;
+            }
+            None => dbtx.remove_fee_policy(federation_id).await,
+        }
+        dbtx.commit_tx().await;
+        info!(target: LOG_GATEWAY, %federation_id, ?policy, "Updated fee policy");
+        Ok(())
+    }
+
+    async fn handle_set_webhook_msg(
+        &self,
+        SetWebhookPayload { webhook }: SetWebhookPayload,
+    ) -> AdminResult<()> {
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        match &webhook {
+            Some(webhook) => {
+                if webhook.secret.is_empty() {
+                    return Err(AdminGatewayError::GatewayConfigurationError(
+                        "The webhook secret must not be empty".to_string(),
+                    ));
+                }
+                dbtx.save_webhook_settings(webhook).await;
+            }
+            None => dbtx.remove_webhook_settings().await,
+        }
+        dbtx.commit_tx().await;
+        info!(
+            target: LOG_GATEWAY,
+            url = ?webhook.as_ref().map(|webhook| &webhook.url),
+            "Updated webhook"
+        );
+        Ok(())
+    }
+
     fn get_password_hash(&self) -> String {
         self.bcrypt_password_hash.clone()
     }
@@ -3213,13 +3568,31 @@ async fn public_key_v2(&self, federation_id: &FederationId) -> Option<PublicKey>
     }
 
     /// Returns payment information that LNv2 clients can use to instruct this
-    /// Gateway to pay an invoice or receive a payment.
+    /// Gateway to pay an invoice or receive a payment. Returns `None` while
+    /// the federation's circuit breaker is open so clients pick another
+    /// gateway.
     pub async fn routing_info_v2(
         &self,
         federation_id: &FederationId,
     ) -> Result<Option<RoutingInfo>> {
+        Ok(self
+            .routing_info_with_fees_v2(federation_id)
+            .await?
+            .map(|(routing_info, _)| routing_info))
+    }
+
+    /// Like [`Self::routing_info_v2`], but also returns the fees the routing
+    /// info was derived from.
+    async fn routing_info_with_fees_v2(
+        &self,
+        federation_id: &FederationId,
+    ) -> Result<Option<(RoutingInfo, Lnv2Fees)>> {
         let context = self.get_lightning_context().await?;
 
+        if self.exposure.circuit_breaker(federation_id).is_open() {
+            return Ok(None);
+        }
+
         let mut dbtx = self.gateway_db.begin_transaction_nc().await;
         let fed_config = dbtx.load_federation_config(*federation_id).await.ok_or(
             PublicGatewayError::FederationNotConnected(FederationNotConnected {
@@ -3227,35 +3600,34 @@ pub async fn routing_info_v2(
             }),
         )?;
 
-        let lightning_fee = fed_config.lightning_fee;
-        let transaction_fee = fed_config.transaction_fee;
+        let Some(module_public_key) = self.public_key_v2(federation_id).await else {
+            return Ok(None);
+        };
 
-        // This route is public and unauthenticated, so the sum of two fees stored
-        // before the fee limits applied must not be able to panic here.
-        let send_fee_default = lightning_fee.checked_add(transaction_fee).ok_or_else(|| {
-            PublicGatewayError::Unexpected(anyhow!(
-                "The configured fees of federation {federation_id} cannot be added"
-            ))
-        })?;
+        let fees = self
+            .routing_fees_v2(federation_id, &fed_config, &context)
+            .await?;
 
-        Ok(self
-            .public_key_v2(federation_id)
-            .await
-            .map(|module_public_key| RoutingInfo {
-                lightning_public_key: context.lightning_public_key,
-                lightning_alias: Some(context.lightning_alias.clone()),
-                module_public_key,
-                send_fee_default,
-                // The base fee ensures that the gateway does not loose sats sending the payment due
-                // to fees paid on the transaction claiming the outgoing contract or
-                // subsequent transactions spending the newly issued ecash
-                send_fee_minimum: transaction_fee,
-                expiration_delta_default: 1440,
-                expiration_delta_minimum: EXPIRATION_DELTA_MINIMUM_V2,
-                // The base fee ensures that the gateway does not loose sats receiving the payment
-                // due to fees paid on the transaction funding the incoming contract
-                receive_fee: transaction_fee,
-            }))
+        let routing_info = RoutingInfo {
+            lightning_public_key: context.lightning_public_key,
+            lightning_alias: Some(context.lightning_alias.clone()),
+            module_public_key,
+            send_fee_default: fees.send_fee_default,
+            // The base fee ensures that the gateway does not loose sats sending the payment due
+            // to fees paid on the transaction claiming the outgoing contract or
+            // subsequent transactions spending the newly issued ecash
+            send_fee_minimum: fed_config.transaction_fee,
+            expiration_delta_default: 1440,
+            expiration_delta_minimum: EXPIRATION_DELTA_MINIMUM_V2,
+            // The base fee ensures that the gateway does not loose sats receiving the payment
+            // due to fees paid on the transaction funding the incoming contract
+            receive_fee: fees.receive_fee,
+            fee_tiers: fees.fee_tiers.clone(),
+            supports_partial_payments: context.lnrpc.supports_partial_payments(),
+            supports_bolt12: context.lnrpc.supports_bolt12_payments(),
+        };
+
+        Ok(Some((routing_info, fees)))
     }
 
     /// Instructs this gateway to pay a Lightning network invoice via the LNv2
@@ -3271,12 +3643,63 @@ pub async fn send_payment_v2(
             .value()
             .get_first_module::<GatewayClientModuleV2>()
             .map_err(|err| PublicGatewayError::LNv2(LNv2Error::OutgoingPayment(err)))?;
+        let _in_flight = self
+            .reserve_exposure(client.value(), payload.contract.amount, true)
+            .await?;
 
-        module
+        // The amount of the invoice this contract pays, which is only a part of
+        // it for a multi-path payment. Amountless invoices are rejected by the
+        // gateway module, so they never reach the fee audit below.
+        let amount = payload.part_amount.or(payload
+            .invoice
+            .amount_milli_satoshis()
+            .map(Amount::from_msats));
+        let contract_amount = payload.contract.amount;
+        let payment_image = payload.contract.payment_image.clone();
+        let federation_id = payload.federation_id;
+
+        // Repeated requests for the same contract only subscribe to the payment,
+        // so its fee must only be recorded for the first one.
+        let is_new = !client
+            .value()
+            .operation_exists(OperationId::from_encodable(&payload.contract))
+            .await;
+
+        let result = module
             .send_payment(payload)
             .await
             .map_err(LNv2Error::OutgoingPayment)
-            .map_err(PublicGatewayError::LNv2)
+            .map_err(PublicGatewayError::LNv2)?;
+
+        if is_new
+            && result.is_ok()
+            && let Some(amount) = amount
+        {
+            // The audit must not fail the payment, so the ecash share is left out
+            // if the fees cannot be evaluated.
+            let ecash_share_ppm = self
+                .routing_info_with_fees_v2(&federation_id)
+                .await
+                .ok()
+                .flatten()
+                .and_then(|(_, fees)| fees.ecash_share_ppm);
+
+            client
+                .value()
+                .log_event(
+                    None,
+                    PaymentFeeApplied {
+                        payment_image,
+                        direction: PaymentDirection::Outbound,
+                        amount,
+                        fee: contract_amount.saturating_sub(amount),
+                        ecash_share_ppm,
+                    },
+                )
+                .await;
+        }
+
+        Ok(result)
     }
 
     /// For the LNv2 protocol, this will create an invoice by fetching it from
@@ -3287,26 +3710,91 @@ async fn create_bolt11_invoice_v2(
         &self,
         payload: CreateBolt11InvoicePayload,
     ) -> Result<Bolt11Invoice> {
-        if !payload.contract.verify() {
+        let (payment_hash, ecash_share_ppm) = self
+            .verify_incoming_contract_v2(&payload.federation_id, &payload.contract, payload.amount)
+            .await?;
+
+        let invoice = self
+            .create_invoice_via_lnrpc_v2(
+                payment_hash,
+                payload.amount,
+                payload.description.clone(),
+                payload.expiry_secs,
+            )
+            .await?;
+
+        self.register_incoming_contract_v2(
+            payload.federation_id,
+            payload.amount,
+            payload.contract,
+            ecash_share_ppm,
+        )
+        .await?;
+
+        Ok(invoice)
+    }
+
+    /// For the LNv2 protocol, this will create a single-use offer via the
+    /// connected Lightning node whose invoices are bound to the payment hash of
+    /// the incoming contract, then save the payment hash such that the
+    /// payment can be matched to a specific federation like a payment for an
+    /// invoice created by [`Self::create_bolt11_invoice_v2`].
+    async fn create_bolt12_offer_v2(&self, payload: CreateBolt12OfferPayload) -> Result<String> {
+        let (payment_hash, ecash_share_ppm) = self
+            .verify_incoming_contract_v2(&payload.federation_id, &payload.contract, payload.amount)
+            .await?;
+
+        let offer = self
+            .get_lightning_context()
+            .await?
+            .lnrpc
+            .create_offer_for_hash(
+                payment_hash,
+                payload.amount,
+                payload.description,
+                payload.expiry_secs,
+            )?;
+
+        self.register_incoming_contract_v2(
+            payload.federation_id,
+            payload.amount,
+            payload.contract,
+            ecash_share_ppm,
+        )
+        .await?;
+
+        Ok(offer)
+    }
+
+    /// Verifies that an incoming contract submitted by a client is keyed to
+    /// this gateway and pays at least the gateway's receive fee, returning the
+    /// payment hash the contract is bound to and the ecash share the fee
+    /// policy of the federation was evaluated at.
+    async fn verify_incoming_contract_v2(
+        &self,
+        federation_id: &FederationId,
+        contract: &IncomingContract,
+        amount: Amount,
+    ) -> Result<(sha256::Hash, Option<u64>)> {
+        if !contract.verify() {
             return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
                 "The contract is invalid".to_string(),
             )));
         }
 
-        let payment_info = self.routing_info_v2(&payload.federation_id).await?.ok_or(
-            LNv2Error::IncomingPayment(format!(
-                "Federation {} does not exist",
-                payload.federation_id
-            )),
+        let (payment_info, fees) = self.routing_info_with_fees_v2(federation_id).await?.ok_or(
+            LNv2Error::IncomingPayment(format!("Federation {federation_id} does not exist")),
         )?;
 
-        if payload.contract.commitment.refund_pk != payment_info.module_public_key {
+        if contract.commitment.refund_pk != payment_info.module_public_key {
             return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
                 "The incoming contract is keyed to another gateway".to_string(),
             )));
         }
 
-        let contract_amount = payment_info.receive_fee.subtract_from(payload.amount.msats);
+        let contract_amount = payment_info
+            .receive_fee_for(amount.msats)
+            .subtract_from(amount.msats);
 
         if contract_amount == Amount::ZERO {
             return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
@@ -3314,44 +3802,53 @@ async fn create_bolt11_invoice_v2(
             )));
         }
 
-        if contract_amount != payload.contract.commitment.amount {
+        // Clients that ignore our fee tiers, or fetched our routing info while
+        // the fee policy asked for more, pay more than the current fee.
+        if contract.commitment.amount > contract_amount {
             return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
                 "The contract amount does not pay the correct amount of fees".to_string(),
             )));
         }
 
-        if payload.contract.commitment.expiration_or_fee <= duration_since_epoch().as_secs() {
+        if contract.commitment.expiration_or_fee <= duration_since_epoch().as_secs() {
             return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
                 "The contract has already expired".to_string(),
             )));
         }
 
-        let payment_hash = match payload.contract.commitment.payment_image {
-            PaymentImage::Hash(payment_hash) => payment_hash,
-            PaymentImage::Point(..) => {
-                return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
-                    "PaymentImage is not a payment hash".to_string(),
-                )));
-            }
+        match contract.commitment.payment_image {
+            PaymentImage::Hash(payment_hash) => Ok((payment_hash, fees.ecash_share_ppm)),
+            PaymentImage::Point(..) => Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "PaymentImage is not a payment hash".to_string(),
+            ))),
+        }
+    }
+
+    /// Saves the incoming contract such that incoming lightning payments for
+    /// its payment hash can be matched to the federation.
+    async fn register_incoming_contract_v2(
+        &self,
+        federation_id: FederationId,
+        amount: Amount,
+        contract: IncomingContract,
+        ecash_share_ppm: Option<u64>,
+    ) -> Result<()> {
+        let fee_applied = PaymentFeeApplied {
+            payment_image: contract.commitment.payment_image.clone(),
+            direction: PaymentDirection::Inbound,
+            amount,
+            fee: amount.saturating_sub(contract.commitment.amount),
+            ecash_share_ppm,
         };
 
-        let invoice = self
-            .create_invoice_via_lnrpc_v2(
-                payment_hash,
-                payload.amount,
-                payload.description.clone(),
-                payload.expiry_secs,
-            )
-            .await?;
+        // The circuit breaker is checked before the contract is persisted, such
+        // that a rejected registration does not leave an orphaned contract.
+        let client = self.select_client(federation_id).await?;
 
         let mut dbtx = self.gateway_db.begin_transaction().await;
 
         if dbtx
-            .save_registered_incoming_contract(
-                payload.federation_id,
-                payload.amount,
-                payload.contract,
-            )
+            .save_registered_incoming_contract(federation_id, amount, contract)
             .await
             .is_some()
         {
@@ -3366,7 +3863,39 @@ async fn create_bolt11_invoice_v2(
             ))
         })?;
 
-        Ok(invoice)
+        client.value().log_event(None, fee_applied).await;
+
+        Ok(())
+    }
+
+    /// For the LNv2 protocol, this will request an invoice for the offer from
+    /// its issuer via the connected Lightning node on behalf of a client, such
+    /// that the client can bind an outgoing contract to its payment hash.
+    async fn fetch_bolt12_invoice_v2(
+        &self,
+        payload: FetchBolt12InvoicePayload,
+    ) -> Result<LightningInvoice> {
+        self.routing_info_v2(&payload.federation_id)
+            .await?
+            .ok_or(LNv2Error::OutgoingPayment(anyhow!(
+                "Federation {} does not exist",
+                payload.federation_id
+            )))?;
+
+        let offer = Offer::from_str(&payload.offer).map_err(|e| {
+            PublicGatewayError::LNv2(LNv2Error::OutgoingPayment(anyhow!(
+                "Failed to parse offer: {e:?}"
+            )))
+        })?;
+
+        let invoice = self
+            .get_lightning_context()
+            .await?
+            .lnrpc
+            .fetch_bolt12_invoice(offer, payload.amount, payload.payer_note)
+            .await?;
+
+        Ok(LightningInvoice::Bolt12(invoice))
     }
 
     /// Retrieves a BOLT11 invoice from the connected Lightning node with a
@@ -3424,7 +3953,7 @@ pub async fn verify_bolt11_preimage_v2(
             .ok_or("Unknown payment hash".to_string())?;
 
         let client = self
-            .select_client(registered_contract.federation_id)
+            .connected_client(registered_contract.federation_id)
             .await
             .map_err(|_| "Not connected to federation".to_string())?
             .into_value();
@@ -3503,6 +4032,8 @@ async fn complete_htlc(
         &self,
         htlc_response: InterceptPaymentResponse,
     ) -> std::result::Result<(), LightningRpcError> {
+        self.release_exposure_for_htlc(&htlc_response);
+
         loop {
             let lightning_context = self.await_lightning_context().await;
 
@@ -3539,6 +4070,11 @@ async fn is_direct_swap(
         // may already be committed to. Ask once we can actually answer.
         let lightning_context = self.await_lightning_context().await;
         if lightning_context.lightning_public_key == invoice.get_payee_pub_key() {
+            // A direct swap funds the incoming contract right away, so it cannot
+            // wait for the recipient to settle a hold invoice.
+            self.ensure_not_held_v2(PaymentImage::Hash(*invoice.payment_hash()))
+                .await?;
+
             let (contract, client) = self
                 .get_registered_incoming_contract_and_client_v2(
                     PaymentImage::Hash(*invoice.payment_hash()),
@@ -3569,6 +4105,37 @@ async fn pay(
             .map(|response| response.preimage.0)
     }
 
+    async fn pay_bolt12(
+        &self,
+        invoice: Bolt12Invoice,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> std::result::Result<[u8; 32], LightningRpcError> {
+        // The send state machine forfeits the outgoing contract on any error from
+        // here, so only the lightning node gets to say this payment failed.
+        let lightning_context = self.await_lightning_context().await;
+        lightning_context
+            .lnrpc
+            .pay_bolt12_invoice(invoice, max_delay, max_fee)
+            .await
+            .map(|response| response.preimage.0)
+    }
+
+    async fn pay_part(
+        &self,
+        invoice: Bolt11Invoice,
+        amount: Amount,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> std::result::Result<[u8; 32], LightningRpcError> {
+        let lightning_context = self.await_lightning_context().await;
+        lightning_context
+            .lnrpc
+            .pay_part(invoice, amount, max_delay, max_fee)
+            .await
+            .map(|response| response.preimage.0)
+    }
+
     async fn min_contract_amount(
         &self,
         federation_id: &FederationId,
@@ -3802,6 +4369,8 @@ async fn complete_htlc(
         &self,
         htlc: InterceptPaymentResponse,
     ) -> std::result::Result<(), LightningRpcError> {
+        self.release_exposure_for_htlc(&htlc);
+
         // Wait until the lightning node is online to complete the HTLC.
         let lightning_context = self.await_lightning_context().await;
 
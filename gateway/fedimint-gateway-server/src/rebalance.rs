@@ -0,0 +1,710 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use fedimint_core::config::FederationId;
+use fedimint_core::task::{Elapsed, sleep, timeout};
+use fedimint_core::time::duration_since_epoch;
+use fedimint_core::util::FmtCompact as _;
+use fedimint_core::{Amount, BitcoinAmountOrAll};
+use fedimint_gateway_common::{
+    CreateInvoiceForOperatorPayload, GatewayBalances, LiquidityTarget, OpenChannelRequest,
+    PeginFromOnchainPayload, RebalanceAction, RebalanceActionOutcome, RebalanceResponse,
+    RebalanceSettings, WithdrawToOnchainPayload,
+};
+use fedimint_gateway_server_db::GatewayDbtxNcExt as _;
+use fedimint_gateway_ui::IAdminGateway as _;
+use fedimint_gwv2_client::GatewayClientModuleV2;
+use fedimint_lnv2_common::gateway_api::PaymentFee;
+use fedimint_logging::LOG_GATEWAY;
+use tracing::{debug, info, warn};
+
+use crate::{AdminResult, Gateway};
+
+/// Smallest amount the rebalancer moves. Anything below is not worth the
+/// onchain fees of a peg-in or peg-out.
+const MIN_REBALANCE_AMOUNT: bitcoin::Amount = bitcoin::Amount::from_sat(10_000);
+
+/// How long the rebalancer leaves a federation alone after moving funds in or
+/// out of it. Peg-ins and peg-outs only show up in the balances once they are
+/// confirmed, so acting again earlier would move the same deficit twice.
+const REBALANCE_COOLDOWN: Duration = Duration::from_hours(6);
+
+/// Smallest channel the rebalancer opens. Most lightning implementations
+/// reject smaller channels.
+const MIN_CHANNEL_SIZE: bitcoin::Amount = bitcoin::Amount::from_sat(20_000);
+
+/// Smallest amount the rebalancer moves between a federation and the channels
+/// with a self-payment through another gateway.
+const MIN_SELF_PAY_AMOUNT: Amount = Amount::from_sats(1_000);
+
+/// Fee budget of the lightning node for routing a self-payment to another
+/// gateway. Outbound liquidity is only spent as far as this budget still keeps
+/// the outbound target.
+const SELF_PAY_ROUTING_FEE_LIMIT: PaymentFee = PaymentFee {
+    base: Amount::from_sats(1),
+    parts_per_million: 10_000,
+};
+
+/// Largest CLTV delta the lightning node accepts for routing a self-payment.
+const SELF_PAY_MAX_DELAY: u64 = 1008;
+
+/// Expiry of the invoices of a self-payment.
+const SELF_PAY_EXPIRY_SECS: u32 = 3600;
+
+/// How long a rebalancing pass waits for a self-payment to complete. A payment
+/// that takes longer keeps going in the background, and the federation is left
+/// alone like after a peg-in or peg-out.
+const SELF_PAY_TIMEOUT: Duration = Duration::from_mins(10);
+
+/// Shortest interval the rebalancer can be scheduled with.
+pub(crate) const MIN_REBALANCE_INTERVAL: Duration = Duration::from_mins(1);
+
+impl Gateway {
+    /// Spawns a background task that runs a rebalancing pass every
+    /// `RebalanceSettings::interval_secs` while the rebalancer is enabled.
+    pub(crate) fn spawn_rebalance_task(&self) {
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("rebalance liquidity", async move {
+                loop {
+                    let interval = self_copy
+                        .gateway_db
+                        .begin_transaction_nc()
+                        .await
+                        .load_rebalance_settings()
+                        .await
+                        .interval_secs;
+                    sleep(Duration::from_secs(interval).max(MIN_REBALANCE_INTERVAL)).await;
+
+                    // The settings may have changed while sleeping
+                    let enabled = self_copy
+                        .gateway_db
+                        .begin_transaction_nc()
+                        .await
+                        .load_rebalance_settings()
+                        .await
+                        .enabled;
+                    if !enabled {
+                        continue;
+                    }
+
+                    if let Err(err) = self_copy.rebalance().await {
+                        warn!(target: LOG_GATEWAY, err = %err.fmt_compact(), "Scheduled rebalancing failed");
+                    }
+                }
+            });
+    }
+
+    /// Runs a single rebalancing pass: moves ecash between the federations and
+    /// the channels with self-payments through other gateways while the
+    /// channels are below their liquidity targets, moves funds between the
+    /// federations that are outside of their liquidity target and the
+    /// lightning node's onchain wallet, opens a channel to the configured peer
+    /// if the outbound liquidity is still too low, and reports how far the
+    /// channels were from their liquidity targets.
+    pub(crate) async fn rebalance(&self) -> AdminResult<RebalanceResponse> {
+        let mut dbtx = self.gateway_db.begin_transaction_nc().await;
+        let settings = dbtx.load_rebalance_settings().await;
+        let targets = dbtx.load_liquidity_targets().await;
+
+        let now = duration_since_epoch().as_secs();
+        let mut cooling_down = BTreeSet::new();
+        for federation_id in targets.keys() {
+            if let Some(last_rebalance) = dbtx.load_last_rebalance(*federation_id).await
+                && now.saturating_sub(last_rebalance) < REBALANCE_COOLDOWN.as_secs()
+            {
+                cooling_down.insert(*federation_id);
+            }
+        }
+        let channel_cooling_down = dbtx
+            .load_last_channel_open()
+            .await
+            .is_some_and(|last_open| now.saturating_sub(last_open) < REBALANCE_COOLDOWN.as_secs());
+        drop(dbtx);
+
+        let balances = self.handle_get_balances_msg().await?;
+        let actions = plan_rebalance(
+            &settings,
+            &targets,
+            &balances,
+            &cooling_down,
+            channel_cooling_down,
+        );
+
+        let mut outcomes = Vec::with_capacity(actions.len());
+        for action in actions {
+            let error = match self.execute_rebalance_action(&settings, &action, now).await {
+                Ok(()) => None,
+                Err(err) => {
+                    warn!(target: LOG_GATEWAY, ?action, err = %err.fmt_compact(), "Rebalancing action failed");
+                    Some(err.to_string())
+                }
+            };
+
+            outcomes.push(RebalanceActionOutcome { action, error });
+        }
+
+        let (outbound_deficit_msats, inbound_deficit_msats) =
+            channel_deficits(&settings, &balances);
+        if outbound_deficit_msats > 0 || inbound_deficit_msats > 0 {
+            warn!(
+                target: LOG_GATEWAY,
+                outbound_deficit_msats,
+                inbound_deficit_msats,
+                "Lightning channels are below their liquidity targets"
+            );
+        }
+
+        debug!(target: LOG_GATEWAY, num_actions = outcomes.len(), "Finished rebalancing pass");
+
+        Ok(RebalanceResponse {
+            actions: outcomes,
+            outbound_deficit_msats,
+            inbound_deficit_msats,
+        })
+    }
+
+    /// Executes a single planned action and records when it succeeded, so the
+    /// next passes leave the federation or the channels alone until the move
+    /// confirmed.
+    async fn execute_rebalance_action(
+        &self,
+        settings: &RebalanceSettings,
+        action: &RebalanceAction,
+        now: u64,
+    ) -> AdminResult<()> {
+        match *action {
+            RebalanceAction::PegIn {
+                federation_id,
+                amount,
+            } => {
+                let txid = self
+                    .handle_pegin_from_onchain_msg(PeginFromOnchainPayload {
+                        federation_id,
+                        amount: BitcoinAmountOrAll::Amount(amount),
+                        fee_rate_sats_per_vbyte: settings.fee_rate_sats_per_vbyte,
+                    })
+                    .await?;
+                info!(target: LOG_GATEWAY, %federation_id, %amount, %txid, "Rebalancer pegged in");
+
+                let mut dbtx = self.gateway_db.begin_transaction().await;
+                dbtx.save_last_rebalance(federation_id, now).await;
+                dbtx.commit_tx().await;
+            }
+            RebalanceAction::PegOut {
+                federation_id,
+                amount,
+            } => {
+                let response = self
+                    .handle_withdraw_to_onchain_msg(WithdrawToOnchainPayload {
+                        federation_id,
+                        amount: BitcoinAmountOrAll::Amount(amount),
+                    })
+                    .await?;
+                info!(target: LOG_GATEWAY, %federation_id, %amount, txid = %response.txid, "Rebalancer pegged out");
+
+                let mut dbtx = self.gateway_db.begin_transaction().await;
+                dbtx.save_last_rebalance(federation_id, now).await;
+                dbtx.commit_tx().await;
+            }
+            RebalanceAction::OpenChannel { amount } => {
+                let peer = settings
+                    .channel_peer
+                    .as_ref()
+                    .expect("Channels are only planned with a channel peer");
+                let txid = self
+                    .handle_open_channel_msg(OpenChannelRequest {
+                        pubkey: peer.pubkey,
+                        host: peer.host_with_port(),
+                        channel_size_sats: amount.to_sat(),
+                        push_amount_sats: 0,
+                        fee_rate_sats_per_vbyte: Some(settings.fee_rate_sats_per_vbyte),
+                        base_fee_msat: None,
+                        parts_per_million: None,
+                    })
+                    .await?;
+                info!(target: LOG_GATEWAY, %peer, %amount, %txid, "Rebalancer opened channel");
+
+                let mut dbtx = self.gateway_db.begin_transaction().await;
+                dbtx.save_last_channel_open(now).await;
+                dbtx.commit_tx().await;
+            }
+            RebalanceAction::SelfPayToChannels {
+                federation_id,
+                amount,
+            } => {
+                let result = timeout(
+                    SELF_PAY_TIMEOUT,
+                    self.self_pay_to_channels(federation_id, amount),
+                )
+                .await;
+                self.finish_self_payment(federation_id, result, now).await?;
+                info!(target: LOG_GATEWAY, %federation_id, %amount, "Rebalancer paid ecash into the channels");
+            }
+            RebalanceAction::SelfPayFromChannels {
+                federation_id,
+                amount,
+            } => {
+                let result = timeout(
+                    SELF_PAY_TIMEOUT,
+                    self.self_pay_from_channels(federation_id, amount),
+                )
+                .await;
+                self.finish_self_payment(federation_id, result, now).await?;
+                info!(target: LOG_GATEWAY, %federation_id, %amount, "Rebalancer paid the channels into ecash");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pays an invoice of the lightning node with ecash of the federation
+    /// through another gateway of the federation.
+    async fn self_pay_to_channels(
+        &self,
+        federation_id: FederationId,
+        amount: Amount,
+    ) -> AdminResult<()> {
+        let client = self.connected_client(federation_id).await?;
+        let module = client.value().get_first_module::<GatewayClientModuleV2>()?;
+        let lightning_context = self.get_lightning_context().await?;
+
+        let (gateway_api, routing_info) = module
+            .select_self_pay_gateway(lightning_context.lightning_public_key)
+            .await?;
+
+        let invoice = self
+            .handle_create_invoice_for_operator_msg(CreateInvoiceForOperatorPayload {
+                amount_msats: amount.msats,
+                expiry_secs: Some(SELF_PAY_EXPIRY_SECS),
+                description: Some("Gateway liquidity rebalancing".to_string()),
+            })
+            .await?;
+
+        let operation_id = module
+            .send_self_payment(gateway_api, &routing_info, invoice)
+            .await?;
+        module.await_self_send(operation_id).await?;
+
+        Ok(())
+    }
+
+    /// Pays an invoice for an incoming contract of the federation through
+    /// another gateway of the federation from the lightning node.
+    async fn self_pay_from_channels(
+        &self,
+        federation_id: FederationId,
+        amount: Amount,
+    ) -> AdminResult<()> {
+        let client = self.connected_client(federation_id).await?;
+        let module = client.value().get_first_module::<GatewayClientModuleV2>()?;
+        let lightning_context = self.get_lightning_context().await?;
+
+        let (gateway_api, routing_info) = module
+            .select_self_pay_gateway(lightning_context.lightning_public_key)
+            .await?;
+
+        let (operation_id, invoice) = module
+            .receive_self_payment(gateway_api, &routing_info, amount, SELF_PAY_EXPIRY_SECS)
+            .await?;
+
+        lightning_context
+            .lnrpc
+            .pay(
+                invoice,
+                SELF_PAY_MAX_DELAY,
+                SELF_PAY_ROUTING_FEE_LIMIT.fee(amount.msats),
+            )
+            .await?;
+        module.await_self_receive(operation_id).await?;
+
+        Ok(())
+    }
+
+    /// Leaves the federation alone for the next passes if a self-payment did
+    /// not complete in time, since it may still move the funds.
+    async fn finish_self_payment(
+        &self,
+        federation_id: FederationId,
+        result: Result<AdminResult<()>, Elapsed>,
+        now: u64,
+    ) -> AdminResult<()> {
+        match result {
+            Ok(result) => result,
+            Err(_) => {
+                let mut dbtx = self.gateway_db.begin_transaction().await;
+                dbtx.save_last_rebalance(federation_id, now).await;
+                dbtx.commit_tx().await;
+
+                Err(anyhow::anyhow!("The self-payment is still pending").into())
+            }
+        }
+    }
+}
+
+/// Plans the moves that bring the channels up to their liquidity targets and
+/// every federation with a liquidity target back to the middle of its range.
+///
+/// Channels lacking outbound liquidity are paid into from the ecash a
+/// federation holds above the bottom of its range, channels lacking inbound
+/// liquidity pay into federations below the top of their range. These
+/// self-payments only spend the liquidity of the other direction that exceeds
+/// its target, so they never push the channels below a target themselves.
+///
+/// Afterwards, surplus ecash is pegged out to the lightning node's onchain
+/// wallet, missing ecash is pegged in from it. Peg-ins are funded in federation
+/// order and never touch `RebalanceSettings::onchain_reserve_sats`. Peg-outs
+/// only become spendable once confirmed, so they do not fund peg-ins of the
+/// same pass.
+///
+/// If the channels still lack outbound liquidity and a channel peer is
+/// configured, whatever the peg-ins left of the onchain funds opens a channel
+/// covering the deficit.
+fn plan_rebalance(
+    settings: &RebalanceSettings,
+    targets: &BTreeMap<FederationId, LiquidityTarget>,
+    balances: &GatewayBalances,
+    cooling_down: &BTreeSet<FederationId>,
+    channel_cooling_down: bool,
+) -> Vec<RebalanceAction> {
+    let mut ecash_balances = balances
+        .ecash_balances
+        .iter()
+        .map(|info| (info.federation_id, info.ecash_balance_msats))
+        .collect::<BTreeMap<FederationId, Amount>>();
+
+    let mut available_onchain = bitcoin::Amount::from_sat(
+        balances
+            .onchain_balance_sats
+            .saturating_sub(settings.onchain_reserve_sats),
+    );
+
+    let (mut outbound_deficit_msats, mut inbound_deficit_msats) =
+        channel_deficits(settings, balances);
+    let mut spare_outbound_msats = balances
+        .lightning_balance_msats
+        .saturating_sub(settings.min_outbound_msats);
+    let mut spare_inbound_msats = balances
+        .inbound_lightning_liquidity_msats
+        .saturating_sub(settings.min_inbound_msats);
+
+    let mut actions = Vec::new();
+    for (federation_id, target) in targets {
+        if cooling_down.contains(federation_id) {
+            continue;
+        }
+
+        let Some(balance) = ecash_balances.get_mut(federation_id) else {
+            continue;
+        };
+
+        if outbound_deficit_msats > 0 {
+            // The ecash spent on the payment includes the fee of the other gateway
+            let amount = PaymentFee::SEND_FEE_LIMIT
+                .subtract_from(balance.saturating_sub(target.min_ecash).msats)
+                .msats
+                .min(outbound_deficit_msats)
+                .min(spare_inbound_msats);
+            if amount >= MIN_SELF_PAY_AMOUNT.msats {
+                *balance = balance.saturating_sub(PaymentFee::SEND_FEE_LIMIT.add_to(amount));
+                outbound_deficit_msats -= amount;
+                spare_inbound_msats -= amount;
+                actions.push(RebalanceAction::SelfPayToChannels {
+                    federation_id: *federation_id,
+                    amount: Amount::from_msats(amount),
+                });
+            }
+        } else if inbound_deficit_msats > 0 {
+            // The outbound liquidity spent on the payment includes the routing fee
+            let amount = target
+                .max_ecash
+                .saturating_sub(*balance)
+                .msats
+                .min(inbound_deficit_msats)
+                .min(
+                    SELF_PAY_ROUTING_FEE_LIMIT
+                        .subtract_from(spare_outbound_msats)
+                        .msats,
+                );
+            if amount >= MIN_SELF_PAY_AMOUNT.msats {
+                *balance += Amount::from_msats(amount);
+                inbound_deficit_msats -= amount;
+                spare_outbound_msats -= SELF_PAY_ROUTING_FEE_LIMIT.add_to(amount).msats;
+                actions.push(RebalanceAction::SelfPayFromChannels {
+                    federation_id: *federation_id,
+                    amount: Amount::from_msats(amount),
+                });
+            }
+        }
+    }
+
+    for (federation_id, target) in targets {
+        if cooling_down.contains(federation_id) {
+            continue;
+        }
+
+        // Targets of federations the gateway is no longer connected to are ignored
+        let Some(balance) = ecash_balances.get(federation_id) else {
+            continue;
+        };
+
+        if *balance > target.max_ecash {
+            let amount =
+                bitcoin::Amount::from_sat(balance.saturating_sub(target.midpoint()).msats / 1000);
+            if amount >= MIN_REBALANCE_AMOUNT {
+                actions.push(RebalanceAction::PegOut {
+                    federation_id: *federation_id,
+                    amount,
+                });
+            }
+        } else if *balance < target.min_ecash {
+            let amount = bitcoin::Amount::from_sat(
+                target
+                    .midpoint()
+                    .saturating_sub(*balance)
+                    .msats
+                    .div_ceil(1000),
+            )
+            .min(available_onchain);
+            if amount >= MIN_REBALANCE_AMOUNT {
+                available_onchain -= amount;
+                actions.push(RebalanceAction::PegIn {
+                    federation_id: *federation_id,
+                    amount,
+                });
+            }
+        }
+    }
+
+    if settings.channel_peer.is_some() && outbound_deficit_msats > 0 && !channel_cooling_down {
+        let amount = bitcoin::Amount::from_sat(outbound_deficit_msats.div_ceil(1000))
+            .max(MIN_CHANNEL_SIZE)
+            .min(available_onchain);
+        if amount >= MIN_CHANNEL_SIZE {
+            actions.push(RebalanceAction::OpenChannel { amount });
+        }
+    }
+
+    actions
+}
+
+/// Returns how much outbound and inbound liquidity the lightning node is
+/// missing to reach the channel targets.
+fn channel_deficits(settings: &RebalanceSettings, balances: &GatewayBalances) -> (u64, u64) {
+    (
+        settings
+            .min_outbound_msats
+            .saturating_sub(balances.lightning_balance_msats),
+        settings
+            .min_inbound_msats
+            .saturating_sub(balances.inbound_lightning_liquidity_msats),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::{Hash as _, sha256};
+    use fedimint_gateway_common::FederationBalanceInfo;
+
+    use super::*;
+
+    fn federation_id(byte: u8) -> FederationId {
+        FederationId(sha256::Hash::from_byte_array([byte; 32]))
+    }
+
+    fn target(min_sats: u64, max_sats: u64) -> LiquidityTarget {
+        LiquidityTarget {
+            min_ecash: Amount::from_sats(min_sats),
+            max_ecash: Amount::from_sats(max_sats),
+        }
+    }
+
+    fn balances(onchain_sats: u64, ecash: &[(FederationId, u64)]) -> GatewayBalances {
+        GatewayBalances {
+            onchain_balance_sats: onchain_sats,
+            lightning_balance_msats: 0,
+            ecash_balances: ecash
+                .iter()
+                .map(|(federation_id, sats)| FederationBalanceInfo {
+                    federation_id: *federation_id,
+                    ecash_balance_msats: Amount::from_sats(*sats),
+                })
+                .collect(),
+            inbound_lightning_liquidity_msats: 0,
+        }
+    }
+
+    #[test]
+    fn moves_federations_to_the_middle_of_their_range() {
+        let targets = BTreeMap::from([
+            (federation_id(1), target(100_000, 300_000)),
+            (federation_id(2), target(100_000, 300_000)),
+            (federation_id(3), target(100_000, 300_000)),
+        ]);
+        let balances = balances(
+            1_000_000,
+            &[
+                (federation_id(1), 500_000),
+                (federation_id(2), 50_000),
+                (federation_id(3), 200_000),
+            ],
+        );
+
+        let actions = plan_rebalance(
+            &RebalanceSettings::default(),
+            &targets,
+            &balances,
+            &BTreeSet::new(),
+            false,
+        );
+
+        assert_eq!(
+            actions,
+            vec![
+                RebalanceAction::PegOut {
+                    federation_id: federation_id(1),
+                    amount: bitcoin::Amount::from_sat(300_000),
+                },
+                RebalanceAction::PegIn {
+                    federation_id: federation_id(2),
+                    amount: bitcoin::Amount::from_sat(150_000),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn peg_ins_keep_the_onchain_reserve() {
+        let targets = BTreeMap::from([
+            (federation_id(1), target(100_000, 300_000)),
+            (federation_id(2), target(100_000, 300_000)),
+        ]);
+        let balances = balances(250_000, &[(federation_id(1), 0), (federation_id(2), 0)]);
+        let settings = RebalanceSettings {
+            onchain_reserve_sats: 50_000,
+            ..RebalanceSettings::default()
+        };
+
+        let actions = plan_rebalance(&settings, &targets, &balances, &BTreeSet::new(), false);
+
+        assert_eq!(
+            actions,
+            vec![RebalanceAction::PegIn {
+                federation_id: federation_id(1),
+                amount: bitcoin::Amount::from_sat(200_000),
+            }]
+        );
+    }
+
+    #[test]
+    fn skips_cooling_down_disconnected_and_dust() {
+        let targets = BTreeMap::from([
+            (federation_id(1), target(100_000, 300_000)),
+            (federation_id(2), target(100_000, 300_000)),
+            (federation_id(3), target(100_000, 110_000)),
+        ]);
+        let balances = balances(
+            1_000_000,
+            &[(federation_id(1), 0), (federation_id(3), 111_000)],
+        );
+
+        let actions = plan_rebalance(
+            &RebalanceSettings::default(),
+            &targets,
+            &balances,
+            &BTreeSet::from([federation_id(1)]),
+            false,
+        );
+
+        assert!(actions.is_empty());
+    }
+
+    #[test]
+    fn opens_channel_with_onchain_funds_left_after_peg_ins() {
+        let targets = BTreeMap::from([(federation_id(1), target(100_000, 300_000))]);
+        let balances = balances(500_000, &[(federation_id(1), 0)]);
+        let settings = RebalanceSettings {
+            min_outbound_msats: 1_000_000_000,
+            channel_peer: Some(
+                "02eadbd9e7557375161df8b646776a547c5cbc2e95b3071ec81553f8ec2cea3b8c@127.0.0.1:9735"
+                    .parse()
+                    .expect("valid node address"),
+            ),
+            ..RebalanceSettings::default()
+        };
+
+        let actions = plan_rebalance(&settings, &targets, &balances, &BTreeSet::new(), false);
+
+        assert_eq!(
+            actions,
+            vec![
+                RebalanceAction::PegIn {
+                    federation_id: federation_id(1),
+                    amount: bitcoin::Amount::from_sat(200_000),
+                },
+                RebalanceAction::OpenChannel {
+                    amount: bitcoin::Amount::from_sat(300_000),
+                },
+            ]
+        );
+
+        let actions = plan_rebalance(&settings, &targets, &balances, &BTreeSet::new(), true);
+
+        assert_eq!(
+            actions,
+            vec![RebalanceAction::PegIn {
+                federation_id: federation_id(1),
+                amount: bitcoin::Amount::from_sat(200_000),
+            }]
+        );
+    }
+
+    #[test]
+    fn self_pays_ecash_into_channels_lacking_outbound_liquidity() {
+        let targets = BTreeMap::from([(federation_id(1), target(100_000, 300_000))]);
+        let balances = GatewayBalances {
+            lightning_balance_msats: 0,
+            inbound_lightning_liquidity_msats: Amount::from_sats(1_000_000).msats,
+            ..balances(0, &[(federation_id(1), 200_000)])
+        };
+        let settings = RebalanceSettings {
+            min_outbound_msats: Amount::from_sats(50_000).msats,
+            ..RebalanceSettings::default()
+        };
+
+        let actions = plan_rebalance(&settings, &targets, &balances, &BTreeSet::new(), false);
+
+        assert_eq!(
+            actions,
+            vec![RebalanceAction::SelfPayToChannels {
+                federation_id: federation_id(1),
+                amount: Amount::from_sats(50_000),
+            }]
+        );
+    }
+
+    #[test]
+    fn self_pays_channels_lacking_inbound_liquidity_into_ecash() {
+        let targets = BTreeMap::from([(federation_id(1), target(100_000, 300_000))]);
+        let balances = GatewayBalances {
+            lightning_balance_msats: Amount::from_sats(1_000_000).msats,
+            inbound_lightning_liquidity_msats: 0,
+            ..balances(0, &[(federation_id(1), 200_000)])
+        };
+        let settings = RebalanceSettings {
+            min_inbound_msats: Amount::from_sats(500_000).msats,
+            ..RebalanceSettings::default()
+        };
+
+        let actions = plan_rebalance(&settings, &targets, &balances, &BTreeSet::new(), false);
+
+        // The federation only takes ecash up to the top of its range
+        assert_eq!(
+            actions,
+            vec![RebalanceAction::SelfPayFromChannels {
+                federation_id: federation_id(1),
+                amount: Amount::from_sats(100_000),
+            }]
+        );
+    }
+}
@@ -18,28 +18,39 @@
     CONNECT_PEER_ENDPOINT, CREATE_BOLT11_INVOICE_FOR_OPERATOR_ENDPOINT,
     CREATE_BOLT12_OFFER_FOR_OPERATOR_ENDPOINT, CloseChannelsWithPeerRequest, ConfigPayload,
     ConnectFedPayload, ConnectPeerRequest, CreateInvoiceForOperatorPayload, CreateOfferPayload,
-    DepositAddressPayload, DepositAddressRecheckPayload, GATEWAY_INFO_ENDPOINT,
-    GET_BALANCES_ENDPOINT, GET_INVOICE_ENDPOINT, GET_LN_ONCHAIN_ADDRESS_ENDPOINT,
-    GetInvoiceRequest, INVITE_CODES_ENDPOINT, LEAVE_FED_ENDPOINT, LIST_CHANNELS_ENDPOINT,
-    LIST_TRANSACTIONS_ENDPOINT, LeaveFedPayload, ListTransactionsPayload, MNEMONIC_ENDPOINT,
-    OPEN_CHANNEL_ENDPOINT, OPEN_CHANNEL_WITH_PUSH_ENDPOINT, OpenChannelRequest,
+    DepositAddressPayload, DepositAddressRecheckPayload, EXPORT_EVENT_LOG_ENDPOINT,
+    ExportEventLogPayload, GATEWAY_INFO_ENDPOINT, GET_BALANCES_ENDPOINT, GET_INVOICE_ENDPOINT,
+    GET_LN_ONCHAIN_ADDRESS_ENDPOINT, GetInvoiceRequest, INVITE_CODES_ENDPOINT, LEAVE_FED_ENDPOINT,
+    LIST_CHANNELS_ENDPOINT, LIST_TRANSACTIONS_ENDPOINT, LeaveFedPayload, ListTransactionsPayload,
+    MNEMONIC_ENDPOINT, OPEN_CHANNEL_ENDPOINT, OPEN_CHANNEL_WITH_PUSH_ENDPOINT, OpenChannelRequest,
     PAY_INVOICE_FOR_OPERATOR_ENDPOINT, PAY_OFFER_FOR_OPERATOR_ENDPOINT, PAYMENT_LOG_ENDPOINT,
     PAYMENT_SUMMARY_ENDPOINT, PEGIN_FROM_ONCHAIN_ENDPOINT, PayInvoiceForOperatorPayload,
     PayOfferPayload, PaymentLogPayload, PaymentSummaryPayload, PeginFromOnchainPayload,
-    RECEIVE_ECASH_ENDPOINT, ReceiveEcashPayload, SEND_ONCHAIN_ENDPOINT, SET_CHANNEL_FEES_ENDPOINT,
-    SET_FEES_ENDPOINT, SPEND_ECASH_ENDPOINT, STOP_ENDPOINT, SendOnchainRequest,
-    SetChannelFeesRequest, SetFeesPayload, SetMnemonicPayload, SpendEcashPayload, V1_API_ENDPOINT,
-    WITHDRAW_ENDPOINT, WITHDRAW_TO_ONCHAIN_ENDPOINT, WithdrawPayload, WithdrawToOnchainPayload,
+    REBALANCE_CONFIG_ENDPOINT, REBALANCE_ENDPOINT, RECEIVE_ECASH_ENDPOINT, RebalanceSettings,
+    ReceiveEcashPayload, SEND_ONCHAIN_ENDPOINT, SET_CHANNEL_FEES_ENDPOINT,
+    SET_EXPOSURE_LIMITS_ENDPOINT, SET_FEE_POLICY_ENDPOINT, SET_FEES_ENDPOINT,
+    SET_LIQUIDITY_TARGET_ENDPOINT, SET_REBALANCE_SETTINGS_ENDPOINT, SET_WEBHOOK_ENDPOINT,
+    SPEND_ECASH_ENDPOINT, STOP_ENDPOINT, SendOnchainRequest, SetChannelFeesRequest,
+    SetExposureLimitsPayload, SetFeePolicyPayload, SetFeesPayload, SetLiquidityTargetPayload,
+    SetMnemonicPayload, SetWebhookPayload, SpendEcashPayload, V1_API_ENDPOINT, WITHDRAW_ENDPOINT,
+    WITHDRAW_TO_ONCHAIN_ENDPOINT, WithdrawPayload, WithdrawToOnchainPayload,
 };
 use fedimint_gateway_ui::IAdminGateway;
 use fedimint_ln_common::gateway_endpoint_constants::{
     GET_GATEWAY_ID_ENDPOINT, PAY_INVOICE_ENDPOINT,
 };
 use fedimint_lnurl::LnurlResponse;
+use fedimint_lnv2_common::contracts::PaymentImage;
 use fedimint_lnv2_common::endpoint_constants::{
-    CREATE_BOLT11_INVOICE_ENDPOINT, ROUTING_INFO_ENDPOINT, SEND_PAYMENT_ENDPOINT,
+    CREATE_BOLT11_INVOICE_ENDPOINT, CREATE_BOLT12_OFFER_ENDPOINT, CREATE_HOLD_INVOICE_ENDPOINT,
+    FETCH_BOLT12_INVOICE_ENDPOINT, HOLD_INVOICE_STATUS_ENDPOINT, RESOLVE_HOLD_INVOICE_ENDPOINT,
+    ROUTING_INFO_ENDPOINT, SEND_PAYMENT_ENDPOINT, SWAP_ENDPOINT, SWAP_QUOTE_ENDPOINT,
+    SWAP_STATUS_ENDPOINT,
+};
+use fedimint_lnv2_common::gateway_api::{
+    CreateBolt11InvoicePayload, CreateBolt12OfferPayload, FetchBolt12InvoicePayload,
+    ResolveHoldInvoicePayload, SendPaymentPayload, SwapPayload, SwapQuoteRequest,
 };
-use fedimint_lnv2_common::gateway_api::{CreateBolt11InvoicePayload, SendPaymentPayload};
 use fedimint_logging::LOG_GATEWAY;
 use hex::ToHex;
 use serde::de::DeserializeOwned;
@@ -54,7 +65,7 @@
 
 // Routes that the liquidity manager is allowed to access. Any authenticated
 // route NOT in this list requires the admin password.
-const LIQUIDITY_MANAGER_ROUTES: [&str; 21] = [
+const LIQUIDITY_MANAGER_ROUTES: [&str; 26] = [
     ADDRESS_ENDPOINT,
     ADDRESS_RECHECK_ENDPOINT,
     CLOSE_CHANNELS_WITH_PEER_ENDPOINT,
@@ -73,8 +84,13 @@
     PAYMENT_LOG_ENDPOINT,
     PAYMENT_SUMMARY_ENDPOINT,
     PEGIN_FROM_ONCHAIN_ENDPOINT,
+    REBALANCE_CONFIG_ENDPOINT,
+    REBALANCE_ENDPOINT,
     SET_CHANNEL_FEES_ENDPOINT,
+    SET_FEE_POLICY_ENDPOINT,
     SET_FEES_ENDPOINT,
+    SET_LIQUIDITY_TARGET_ENDPOINT,
+    SET_REBALANCE_SETTINGS_ENDPOINT,
     WITHDRAW_TO_ONCHAIN_ENDPOINT,
 ];
 
@@ -288,6 +304,50 @@ fn lnv2_routes(handlers: &mut Handlers) -> Router {
         false,
         router,
     );
+    let router = register_post_handler(
+        handlers,
+        CREATE_BOLT12_OFFER_ENDPOINT,
+        create_bolt12_offer_v2,
+        false,
+        router,
+    );
+    let router = register_post_handler(
+        handlers,
+        FETCH_BOLT12_INVOICE_ENDPOINT,
+        fetch_bolt12_invoice_v2,
+        false,
+        router,
+    );
+    let router = register_post_handler(handlers, SWAP_QUOTE_ENDPOINT, swap_quote_v2, false, router);
+    let router = register_post_handler(handlers, SWAP_ENDPOINT, swap_v2, false, router);
+    let router = register_post_handler(
+        handlers,
+        SWAP_STATUS_ENDPOINT,
+        swap_status_v2,
+        false,
+        router,
+    );
+    let router = register_post_handler(
+        handlers,
+        CREATE_HOLD_INVOICE_ENDPOINT,
+        create_hold_invoice_v2,
+        false,
+        router,
+    );
+    let router = register_post_handler(
+        handlers,
+        HOLD_INVOICE_STATUS_ENDPOINT,
+        hold_invoice_status_v2,
+        false,
+        router,
+    );
+    let router = register_post_handler(
+        handlers,
+        RESOLVE_HOLD_INVOICE_ENDPOINT,
+        resolve_hold_invoice_v2,
+        false,
+        router,
+    );
     // Verify endpoint does not have the same signature, it is handled separately
     router.route("/verify/{payment_hash}", get(verify_bolt11_preimage_v2_get))
 }
@@ -496,6 +556,13 @@ fn routes(gateway: Arc<Gateway>, task_group: TaskGroup, handlers: &mut Handlers)
         is_authenticated,
         authenticated_routes,
     );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        EXPORT_EVENT_LOG_ENDPOINT,
+        export_event_log,
+        is_authenticated,
+        authenticated_routes,
+    );
     let authenticated_routes = register_post_handler(
         handlers,
         PAYMENT_SUMMARY_ENDPOINT,
@@ -510,6 +577,20 @@ fn routes(gateway: Arc<Gateway>, task_group: TaskGroup, handlers: &mut Handlers)
         is_authenticated,
         authenticated_routes,
     );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        SET_FEE_POLICY_ENDPOINT,
+        set_fee_policy,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        SET_WEBHOOK_ENDPOINT,
+        set_webhook,
+        is_authenticated,
+        authenticated_routes,
+    );
     let authenticated_routes = register_post_handler(
         handlers,
         CONFIGURATION_ENDPOINT,
@@ -538,6 +619,41 @@ fn routes(gateway: Arc<Gateway>, task_group: TaskGroup, handlers: &mut Handlers)
         is_authenticated,
         authenticated_routes,
     );
+    let authenticated_routes = register_get_handler(
+        handlers,
+        REBALANCE_CONFIG_ENDPOINT,
+        rebalance_config,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        SET_REBALANCE_SETTINGS_ENDPOINT,
+        set_rebalance_settings,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        SET_LIQUIDITY_TARGET_ENDPOINT,
+        set_liquidity_target,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_get_handler(
+        handlers,
+        REBALANCE_ENDPOINT,
+        rebalance,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        SET_EXPOSURE_LIMITS_ENDPOINT,
+        set_exposure_limits,
+        is_authenticated,
+        authenticated_routes,
+    );
     let authenticated_routes = authenticated_routes.layer(middleware::from_fn(auth_middleware));
 
     Router::new()
@@ -678,6 +794,25 @@ async fn set_fees(
     Ok(Json(json!(())))
 }
 
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn set_fee_policy(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<SetFeePolicyPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    gateway.handle_set_fee_policy_msg(payload).await?;
+    Ok(Json(json!(())))
+}
+
+/// Not logging the payload, it contains the webhook secret.
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn set_webhook(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<SetWebhookPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    gateway.handle_set_webhook_msg(payload).await?;
+    Ok(Json(json!(())))
+}
+
 #[instrument(target = LOG_GATEWAY, skip_all, err)]
 async fn get_ln_onchain_address(
     Extension(gateway): Extension<Arc<Gateway>>,
@@ -766,6 +901,50 @@ async fn get_balances(
     Ok(Json(json!(balances)))
 }
 
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn rebalance_config(
+    Extension(gateway): Extension<Arc<Gateway>>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let config = gateway.handle_get_rebalance_config_msg().await?;
+    Ok(Json(json!(config)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn set_rebalance_settings(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<RebalanceSettings>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    gateway.handle_set_rebalance_settings_msg(payload).await?;
+    Ok(Json(json!(())))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn set_liquidity_target(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<SetLiquidityTargetPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    gateway.handle_set_liquidity_target_msg(payload).await?;
+    Ok(Json(json!(())))
+}
+
+/// Runs a rebalancing pass immediately, independent of the schedule
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn rebalance(
+    Extension(gateway): Extension<Arc<Gateway>>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let response = gateway.handle_rebalance_msg().await?;
+    Ok(Json(json!(response)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn set_exposure_limits(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<SetExposureLimitsPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    gateway.handle_set_exposure_limits_msg(payload).await?;
+    Ok(Json(json!(())))
+}
+
 #[instrument(target = LOG_GATEWAY, skip_all, err)]
 async fn get_gateway_id(
     Extension(gateway): Extension<Arc<Gateway>>,
@@ -800,6 +979,78 @@ async fn create_bolt11_invoice_v2(
     Ok(Json(json!(invoice)))
 }
 
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn create_bolt12_offer_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<CreateBolt12OfferPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let offer = gateway.create_bolt12_offer_v2(payload).await?;
+    Ok(Json(json!(offer)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn fetch_bolt12_invoice_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<FetchBolt12InvoicePayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let invoice = gateway.fetch_bolt12_invoice_v2(payload).await?;
+    Ok(Json(json!(invoice)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn swap_quote_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<SwapQuoteRequest>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let quote = gateway.swap_quote_v2(payload).await?;
+    Ok(Json(json!(quote)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn swap_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<SwapPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let invoice = gateway.swap_v2(payload).await?;
+    Ok(Json(json!(invoice)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn swap_status_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payment_image): Json<PaymentImage>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let status = gateway.swap_status_v2(payment_image).await?;
+    Ok(Json(json!(status)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn create_hold_invoice_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<CreateBolt11InvoicePayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let invoice = gateway.create_hold_invoice_v2(payload).await?;
+    Ok(Json(json!(invoice)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn hold_invoice_status_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payment_image): Json<PaymentImage>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let status = gateway.hold_invoice_status_v2(payment_image).await?;
+    Ok(Json(json!(status)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn resolve_hold_invoice_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<ResolveHoldInvoicePayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let status = gateway.resolve_hold_invoice_v2(payload).await?;
+    Ok(Json(json!(status)))
+}
+
 pub(crate) async fn verify_bolt11_preimage_v2_get(
     Extension(gateway): Extension<Arc<Gateway>>,
     Path(payment_hash): Path<sha256::Hash>,
@@ -872,6 +1123,17 @@ async fn payment_log(
     Ok(Json(json!(payment_log)))
 }
 
+/// `POST /export_event_log` — acknowledges the previously returned page and
+/// returns the next page of the federation client's event log entries.
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn export_event_log(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<ExportEventLogPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let export = gateway.handle_export_event_log_msg(payload).await?;
+    Ok(Json(json!(export)))
+}
+
 #[instrument(target = LOG_GATEWAY, skip_all, err)]
 async fn payment_summary(
     Extension(gateway): Extension<Arc<Gateway>>,
@@ -0,0 +1,122 @@
+use fedimint_core::core::OperationId;
+use fedimint_gateway_server_db::GatewayDbtxNcExt as _;
+use fedimint_gwv2_client::{FinalReceiveState, GatewayClientModuleV2};
+use fedimint_lnv2_common::Bolt11InvoiceDescription;
+use fedimint_lnv2_common::contracts::PaymentImage;
+use fedimint_lnv2_common::gateway_api::{
+    CreateBolt11InvoicePayload, SwapPayload, SwapQuote, SwapQuoteRequest, SwapStatus,
+};
+use lightning_invoice::Bolt11Invoice;
+
+use crate::error::{LNv2Error, PublicGatewayError};
+use crate::{Gateway, Result};
+
+impl Gateway {
+    /// Quotes a swap of ecash from the source to the target federation. The
+    /// quote is `None` if the gateway is currently not routing payments for
+    /// either of the two federations.
+    pub(crate) async fn swap_quote_v2(
+        &self,
+        request: SwapQuoteRequest,
+    ) -> Result<Option<SwapQuote>> {
+        if request.source_federation_id == request.target_federation_id {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "Source and target federation of a swap must differ".to_string(),
+            )));
+        }
+
+        let Some((source_routing_info, _)) = self
+            .routing_info_with_fees_v2(&request.source_federation_id)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let Some((target_routing_info, _)) = self
+            .routing_info_with_fees_v2(&request.target_federation_id)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(SwapQuote::new(
+            request,
+            &source_routing_info,
+            &target_routing_info,
+        )))
+    }
+
+    /// Registers the incoming contract of a swap in the target federation and
+    /// returns an invoice of the gateway's own node for it. Once the client
+    /// funds an outgoing contract for this invoice in the source federation,
+    /// the gateway settles both contracts as a direct swap without routing
+    /// the payment over the Lightning network. The swap is rejected if the
+    /// client's quote no longer matches the gateway's current fees.
+    pub(crate) async fn swap_v2(&self, payload: SwapPayload) -> Result<Bolt11Invoice> {
+        if self.swap_quote_v2(payload.quote.request()).await? != Some(payload.quote.clone()) {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "The swap quote is no longer valid".to_string(),
+            )));
+        }
+
+        let description = Bolt11InvoiceDescription::Direct(format!(
+            "Swap from federation {}",
+            payload.quote.source_federation_id
+        ));
+
+        self.create_bolt11_invoice_v2(CreateBolt11InvoicePayload {
+            federation_id: payload.quote.target_federation_id,
+            contract: payload.contract,
+            amount: payload.quote.amount,
+            description,
+            expiry_secs: payload.expiry_secs,
+        })
+        .await
+    }
+
+    /// Returns the status of the incoming side of a swap, or `None` if the
+    /// gateway has no incoming contract registered for the payment image.
+    pub(crate) async fn swap_status_v2(
+        &self,
+        payment_image: PaymentImage,
+    ) -> Result<Option<SwapStatus>> {
+        let Some(registered_contract) = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_registered_incoming_contract(payment_image)
+            .await
+        else {
+            return Ok(None);
+        };
+
+        let client = self
+            .connected_client(registered_contract.federation_id)
+            .await?
+            .into_value();
+
+        let operation_id = OperationId::from_encodable(&registered_contract.contract);
+
+        if !client.operation_exists(operation_id).await {
+            return Ok(Some(SwapStatus::AwaitingPayment));
+        }
+
+        if client.has_active_states(operation_id).await {
+            return Ok(Some(SwapStatus::Funding));
+        }
+
+        let status = match client
+            .get_first_module::<GatewayClientModuleV2>()
+            .expect("Must have client module")
+            .await_receive(operation_id)
+            .await
+        {
+            FinalReceiveState::Success(..) => SwapStatus::Completed,
+            FinalReceiveState::Rejected
+            | FinalReceiveState::Refunded
+            | FinalReceiveState::Failure => SwapStatus::Failed,
+        };
+
+        Ok(Some(status))
+    }
+}
@@ -0,0 +1,55 @@
+//! Entry points for tests of other crates, which drive the gateway without the
+//! webserver and the HTLC interception loop that reach them in production.
+
+use fedimint_lightning::{InterceptPaymentRequest, LightningContext};
+use fedimint_lnv2_common::contracts::PaymentImage;
+use fedimint_lnv2_common::gateway_api::{
+    CreateBolt11InvoicePayload, HoldInvoiceStatus, ResolveHoldInvoicePayload,
+};
+use lightning_invoice::Bolt11Invoice;
+
+use crate::{Gateway, Result};
+
+/// A view of the gateway exposing the handlers of its public API.
+pub struct TestingGateway<'a>(&'a Gateway);
+
+impl Gateway {
+    pub fn testing(&self) -> TestingGateway<'_> {
+        TestingGateway(self)
+    }
+}
+
+impl TestingGateway<'_> {
+    pub async fn create_hold_invoice_v2(
+        &self,
+        payload: CreateBolt11InvoicePayload,
+    ) -> Result<Bolt11Invoice> {
+        self.0.create_hold_invoice_v2(payload).await
+    }
+
+    pub async fn hold_invoice_status_v2(
+        &self,
+        payment_image: PaymentImage,
+    ) -> Result<Option<HoldInvoiceStatus>> {
+        self.0.hold_invoice_status_v2(payment_image).await
+    }
+
+    pub async fn resolve_hold_invoice_v2(
+        &self,
+        payload: ResolveHoldInvoicePayload,
+    ) -> Result<HoldInvoiceStatus> {
+        self.0.resolve_hold_invoice_v2(payload).await
+    }
+
+    /// Handles a payment as if the gateway had intercepted it from its
+    /// lightning node.
+    pub async fn intercept_htlc(
+        &self,
+        payment_request: InterceptPaymentRequest,
+        lightning_context: &LightningContext,
+    ) {
+        self.0
+            .handle_lightning_payment(payment_request, lightning_context)
+            .await;
+    }
+}
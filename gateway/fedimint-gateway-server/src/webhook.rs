@@ -0,0 +1,612 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
+use std::time::Duration;
+
+use fedimint_core::config::FederationId;
+use fedimint_core::db::DatabaseTransaction;
+use fedimint_core::task::sleep;
+use fedimint_core::time::duration_since_epoch;
+use fedimint_core::util::FmtCompact as _;
+use fedimint_eventlog::{Event as _, EventKind, EventLogEntry, EventLogId};
+use fedimint_gateway_common::{
+    GatewayBalances, LiquidityAlert, LiquidityTarget, PaymentDirection, RebalanceSettings,
+    WEBHOOK_SIGNATURE_HEADER, WebhookMessage, WebhookNotification, WebhookSettings,
+};
+use fedimint_gateway_server_db::{GatewayDbtxNcExt as _, WebhookDelivery};
+use fedimint_gateway_ui::IAdminGateway as _;
+use fedimint_gwv2_client::events::{
+    IncomingPaymentFailed, IncomingPaymentSucceeded, OutgoingPaymentFailed,
+    OutgoingPaymentSucceeded,
+};
+use fedimint_logging::LOG_GATEWAY;
+use reqwest::header::CONTENT_TYPE;
+use tracing::{debug, info, warn};
+
+use crate::Gateway;
+
+/// Longest the dispatcher waits before looking at the delivery queue again
+/// when it is not woken up by a new notification.
+const WEBHOOK_DISPATCH_INTERVAL: Duration = Duration::from_mins(1);
+
+/// Timeout of a single webhook request.
+const WEBHOOK_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay before the first retry of a failed delivery, doubled on every
+/// further attempt up to `WEBHOOK_MAX_RETRY_DELAY`.
+const WEBHOOK_INITIAL_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+const WEBHOOK_MAX_RETRY_DELAY: Duration = Duration::from_hours(1);
+
+/// Number of failed attempts after which a delivery is dropped.
+const WEBHOOK_MAX_ATTEMPTS: u32 = 12;
+
+/// How often the federation clients' event logs are checked for payment
+/// events.
+const EVENT_LOG_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+const EVENT_LOG_BATCH_SIZE: u64 = 1_000;
+
+/// How often the balances are compared against the liquidity targets.
+const LIQUIDITY_CHECK_INTERVAL: Duration = Duration::from_mins(1);
+
+/// Event kinds of the LNv1 and LNv2 gateway modules that complete a payment.
+/// Both modules use the same kind names.
+const PAYMENT_OUTCOME_EVENTS: [(EventKind, bool, PaymentDirection); 4] = [
+    (
+        OutgoingPaymentSucceeded::KIND,
+        true,
+        PaymentDirection::Outbound,
+    ),
+    (
+        OutgoingPaymentFailed::KIND,
+        false,
+        PaymentDirection::Outbound,
+    ),
+    (
+        IncomingPaymentSucceeded::KIND,
+        true,
+        PaymentDirection::Inbound,
+    ),
+    (
+        IncomingPaymentFailed::KIND,
+        false,
+        PaymentDirection::Inbound,
+    ),
+];
+
+/// The balance a [`LiquidityAlert`] is about, used to alert only once per
+/// drop below a minimum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LiquiditySource {
+    Ecash(FederationId),
+    Outbound,
+    Inbound,
+}
+
+impl Gateway {
+    /// Queues a webhook notification for delivery. Does nothing if no webhook
+    /// is configured.
+    pub(crate) async fn notify_webhook(&self, notification: WebhookNotification) {
+        let queued = self
+            .gateway_db
+            .autocommit(
+                |dbtx, _| {
+                    let notification = notification.clone();
+                    Box::pin(async move {
+                        Ok::<_, Infallible>(queue_webhook_messages(dbtx, vec![notification]).await)
+                    })
+                },
+                None,
+            )
+            .await
+            .expect("Retries until the transaction commits");
+
+        if queued {
+            self.webhook_notify.notify_one();
+        }
+    }
+
+    /// Spawns the background tasks that turn payment events and liquidity
+    /// changes into webhook notifications and deliver the queued ones.
+    pub(crate) fn spawn_webhook_tasks(&self) {
+        self.spawn_webhook_dispatch_task();
+        self.spawn_webhook_event_log_task();
+        self.spawn_low_liquidity_task();
+    }
+
+    fn spawn_webhook_dispatch_task(&self) {
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("webhook dispatch", async move {
+                let http_client = reqwest::Client::builder()
+                    .timeout(WEBHOOK_REQUEST_TIMEOUT)
+                    .build()
+                    .expect("Failed to build webhook http client");
+
+                loop {
+                    let next_attempt = self_copy.dispatch_webhook_deliveries(&http_client).await;
+                    let delay = next_attempt.map_or(WEBHOOK_DISPATCH_INTERVAL, |next_attempt| {
+                        next_attempt.min(WEBHOOK_DISPATCH_INTERVAL)
+                    });
+
+                    tokio::select! {
+                        () = self_copy.webhook_notify.notified() => {}
+                        () = sleep(delay) => {}
+                    }
+                }
+            });
+    }
+
+    /// Sends every queued delivery that is due and returns how long to wait
+    /// for the next delivery that is not.
+    async fn dispatch_webhook_deliveries(&self, http_client: &reqwest::Client) -> Option<Duration> {
+        let mut dbtx = self.gateway_db.begin_transaction_nc().await;
+        let webhook = dbtx.load_webhook_settings().await?;
+        let deliveries = dbtx.load_webhook_deliveries().await;
+        drop(dbtx);
+
+        let mut next_attempt_secs: Option<u64> = None;
+        for (id, mut delivery) in deliveries {
+            let now = duration_since_epoch().as_secs();
+            if now < delivery.next_attempt_secs {
+                next_attempt_secs = earliest(next_attempt_secs, delivery.next_attempt_secs);
+                continue;
+            }
+
+            let result = send_webhook(http_client, &webhook, &delivery).await;
+
+            let mut dbtx = self.gateway_db.begin_transaction().await;
+            match result {
+                Ok(()) => {
+                    debug!(target: LOG_GATEWAY, id, "Delivered webhook notification");
+                    dbtx.remove_webhook_delivery(id).await;
+                }
+                Err(err) if delivery.attempts + 1 >= WEBHOOK_MAX_ATTEMPTS => {
+                    warn!(
+                        target: LOG_GATEWAY,
+                        id,
+                        err = %err.fmt_compact(),
+                        "Dropping webhook notification after too many failed attempts"
+                    );
+                    dbtx.remove_webhook_delivery(id).await;
+                }
+                Err(err) => {
+                    delivery.attempts += 1;
+                    delivery.next_attempt_secs = now + retry_delay(delivery.attempts).as_secs();
+                    warn!(
+                        target: LOG_GATEWAY,
+                        id,
+                        attempts = delivery.attempts,
+                        err = %err.fmt_compact(),
+                        "Failed to deliver webhook notification, retrying later"
+                    );
+                    // Removing the webhook drops the queue, a failed delivery must not bring
+                    // itself back
+                    if dbtx.load_webhook_settings().await.is_some() {
+                        dbtx.save_webhook_delivery(id, &delivery).await;
+                    }
+                    next_attempt_secs = earliest(next_attempt_secs, delivery.next_attempt_secs);
+                }
+            }
+
+            if dbtx.commit_tx_result().await.is_err() {
+                debug!(target: LOG_GATEWAY, id, "Webhook queue changed while delivering");
+            }
+        }
+
+        next_attempt_secs.map(|next_attempt_secs| {
+            Duration::from_secs(next_attempt_secs.saturating_sub(duration_since_epoch().as_secs()))
+        })
+    }
+
+    /// Spawns a background task that follows the event logs of the federation
+    /// clients and queues a notification for every payment that succeeded or
+    /// failed.
+    fn spawn_webhook_event_log_task(&self) {
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("webhook event log", async move {
+                loop {
+                    sleep(EVENT_LOG_POLL_INTERVAL).await;
+
+                    let clients = self_copy
+                        .federation_manager
+                        .read()
+                        .await
+                        .clients()
+                        .map(|(federation_id, client)| (*federation_id, client.value().clone()))
+                        .collect::<Vec<_>>();
+
+                    let mut queued = false;
+                    for (federation_id, client) in clients {
+                        let position = self_copy
+                            .gateway_db
+                            .begin_transaction_nc()
+                            .await
+                            .load_webhook_event_log_position(federation_id)
+                            .await;
+
+                        // Payments that completed before the gateway started following the log
+                        // are not reported
+                        let Some(position) = position else {
+                            let position = client.get_next_event_log_id().await;
+                            self_copy
+                                .save_webhook_event_log_position(
+                                    federation_id,
+                                    position,
+                                    Vec::new(),
+                                )
+                                .await;
+                            continue;
+                        };
+
+                        let events = client
+                            .get_event_log(Some(position), EVENT_LOG_BATCH_SIZE)
+                            .await;
+                        let Some(last_event) = events.last() else {
+                            continue;
+                        };
+
+                        let notifications = events
+                            .iter()
+                            .filter_map(|event| payment_notification(federation_id, event.as_raw()))
+                            .collect::<Vec<_>>();
+                        queued |= self_copy
+                            .save_webhook_event_log_position(
+                                federation_id,
+                                last_event.id().next(),
+                                notifications,
+                            )
+                            .await;
+                    }
+
+                    if queued {
+                        self_copy.webhook_notify.notify_one();
+                    }
+                }
+            });
+    }
+
+    /// Queues the notifications and advances the federation's event log
+    /// position atomically, so every payment is reported exactly once. Returns
+    /// whether any notification was queued.
+    async fn save_webhook_event_log_position(
+        &self,
+        federation_id: FederationId,
+        position: EventLogId,
+        notifications: Vec<WebhookNotification>,
+    ) -> bool {
+        self.gateway_db
+            .autocommit(
+                |dbtx, _| {
+                    let notifications = notifications.clone();
+                    Box::pin(async move {
+                        // The federation may have been left while its log was read
+                        if dbtx.load_federation_config(federation_id).await.is_none() {
+                            return Ok::<_, Infallible>(false);
+                        }
+
+                        dbtx.save_webhook_event_log_position(federation_id, position)
+                            .await;
+                        Ok(queue_webhook_messages(dbtx, notifications).await)
+                    })
+                },
+                None,
+            )
+            .await
+            .expect("Retries until the transaction commits")
+    }
+
+    /// Spawns a background task that notifies the webhook whenever a balance
+    /// drops below the minimum of its liquidity target.
+    fn spawn_low_liquidity_task(&self) {
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("webhook low liquidity", async move {
+                let mut low = BTreeSet::new();
+
+                loop {
+                    sleep(LIQUIDITY_CHECK_INTERVAL).await;
+
+                    let mut dbtx = self_copy.gateway_db.begin_transaction_nc().await;
+                    if dbtx.load_webhook_settings().await.is_none() {
+                        low.clear();
+                        continue;
+                    }
+                    let settings = dbtx.load_rebalance_settings().await;
+                    let targets = dbtx.load_liquidity_targets().await;
+                    drop(dbtx);
+
+                    let balances = match self_copy.handle_get_balances_msg().await {
+                        Ok(balances) => balances,
+                        Err(err) => {
+                            debug!(target: LOG_GATEWAY, err = %err.fmt_compact(), "Skipping liquidity check");
+                            continue;
+                        }
+                    };
+
+                    let alerts = liquidity_alerts(&settings, &targets, &balances);
+                    let sources = alerts.iter().map(liquidity_source).collect::<BTreeSet<_>>();
+
+                    for alert in alerts {
+                        if !low.contains(&liquidity_source(&alert)) {
+                            info!(target: LOG_GATEWAY, ?alert, "Liquidity dropped below its target");
+                            self_copy
+                                .notify_webhook(WebhookNotification::LowLiquidity { alert })
+                                .await;
+                        }
+                    }
+
+                    low = sources;
+                }
+            });
+    }
+}
+
+/// Queues a webhook message for each notification. Returns whether anything
+/// was queued, which is not the case if no webhook is configured.
+async fn queue_webhook_messages(
+    dbtx: &mut DatabaseTransaction<'_>,
+    notifications: Vec<WebhookNotification>,
+) -> bool {
+    if notifications.is_empty() || dbtx.load_webhook_settings().await.is_none() {
+        return false;
+    }
+
+    let now = duration_since_epoch().as_secs();
+    for notification in notifications {
+        let id = dbtx.next_webhook_delivery_id().await;
+        let body = serde_json::to_string(&WebhookMessage {
+            id,
+            timestamp_secs: now,
+            notification,
+        })
+        .expect("Webhook messages serialize to json");
+
+        dbtx.save_webhook_delivery(
+            id,
+            &WebhookDelivery {
+                body,
+                attempts: 0,
+                next_attempt_secs: now,
+            },
+        )
+        .await;
+    }
+
+    true
+}
+
+async fn send_webhook(
+    http_client: &reqwest::Client,
+    webhook: &WebhookSettings,
+    delivery: &WebhookDelivery,
+) -> reqwest::Result<()> {
+    http_client
+        .post(webhook.url.clone().to_unsafe())
+        .header(CONTENT_TYPE, "application/json")
+        .header(
+            WEBHOOK_SIGNATURE_HEADER,
+            webhook.signature(delivery.body.as_bytes()),
+        )
+        .body(delivery.body.clone())
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+fn earliest(next_attempt_secs: Option<u64>, attempt_secs: u64) -> Option<u64> {
+    Some(next_attempt_secs.map_or(attempt_secs, |next| next.min(attempt_secs)))
+}
+
+/// Delay before the next attempt of a delivery that failed `attempts` times.
+fn retry_delay(attempts: u32) -> Duration {
+    WEBHOOK_INITIAL_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .min(WEBHOOK_MAX_RETRY_DELAY)
+}
+
+/// Returns the notification for an event of the LNv1 or LNv2 gateway module
+/// that completes a payment.
+fn payment_notification(
+    federation_id: FederationId,
+    event: &EventLogEntry,
+) -> Option<WebhookNotification> {
+    let module_kind = event.module_kind()?;
+    if *module_kind != fedimint_ln_common::KIND && *module_kind != fedimint_lnv2_common::KIND {
+        return None;
+    }
+
+    let (_, succeeded, direction) = PAYMENT_OUTCOME_EVENTS
+        .iter()
+        .find(|(kind, _, _)| *kind == event.kind)?;
+    let event = serde_json::from_slice(&event.payload).ok()?;
+
+    Some(if *succeeded {
+        WebhookNotification::PaymentSucceeded {
+            federation_id,
+            direction: direction.clone(),
+            event,
+        }
+    } else {
+        WebhookNotification::PaymentFailed {
+            federation_id,
+            direction: direction.clone(),
+            event,
+        }
+    })
+}
+
+/// Returns an alert for every balance below the minimum of its liquidity
+/// target. A minimum of zero disables the alert.
+fn liquidity_alerts(
+    settings: &RebalanceSettings,
+    targets: &BTreeMap<FederationId, LiquidityTarget>,
+    balances: &GatewayBalances,
+) -> Vec<LiquidityAlert> {
+    let mut alerts = balances
+        .ecash_balances
+        .iter()
+        .filter_map(|balance| {
+            let target = targets.get(&balance.federation_id)?;
+            (balance.ecash_balance_msats < target.min_ecash).then_some(LiquidityAlert::Ecash {
+                federation_id: balance.federation_id,
+                balance: balance.ecash_balance_msats,
+                minimum: target.min_ecash,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if balances.lightning_balance_msats < settings.min_outbound_msats {
+        alerts.push(LiquidityAlert::Outbound {
+            balance_msats: balances.lightning_balance_msats,
+            minimum_msats: settings.min_outbound_msats,
+        });
+    }
+
+    if balances.inbound_lightning_liquidity_msats < settings.min_inbound_msats {
+        alerts.push(LiquidityAlert::Inbound {
+            balance_msats: balances.inbound_lightning_liquidity_msats,
+            minimum_msats: settings.min_inbound_msats,
+        });
+    }
+
+    alerts
+}
+
+fn liquidity_source(alert: &LiquidityAlert) -> LiquiditySource {
+    match alert {
+        LiquidityAlert::Ecash { federation_id, .. } => LiquiditySource::Ecash(*federation_id),
+        LiquidityAlert::Outbound { .. } => LiquiditySource::Outbound,
+        LiquidityAlert::Inbound { .. } => LiquiditySource::Inbound,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::Amount;
+    use fedimint_core::core::ModuleKind;
+    use fedimint_eventlog::EventLogModule;
+    use fedimint_gateway_common::FederationBalanceInfo;
+
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles_up_to_an_hour() {
+        assert_eq!(retry_delay(1), Duration::from_secs(5));
+        assert_eq!(retry_delay(2), Duration::from_secs(10));
+        assert_eq!(retry_delay(5), Duration::from_secs(80));
+        assert_eq!(retry_delay(11), WEBHOOK_MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX), WEBHOOK_MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn only_completed_gateway_payments_are_notified() {
+        let federation_id = FederationId::dummy();
+        let event = |module: ModuleKind, kind: EventKind| EventLogEntry {
+            kind,
+            module: Some(EventLogModule {
+                kind: module,
+                id: 0,
+            }),
+            ts_usecs: 0,
+            payload: br#"{"error":"timeout"}"#.to_vec(),
+        };
+
+        assert_eq!(
+            payment_notification(
+                federation_id,
+                &event(fedimint_lnv2_common::KIND, OutgoingPaymentFailed::KIND)
+            ),
+            Some(WebhookNotification::PaymentFailed {
+                federation_id,
+                direction: PaymentDirection::Outbound,
+                event: serde_json::json!({ "error": "timeout" }),
+            })
+        );
+        assert!(matches!(
+            payment_notification(
+                federation_id,
+                &event(fedimint_ln_common::KIND, IncomingPaymentSucceeded::KIND)
+            ),
+            Some(WebhookNotification::PaymentSucceeded {
+                direction: PaymentDirection::Inbound,
+                ..
+            })
+        ));
+        assert_eq!(
+            payment_notification(
+                federation_id,
+                &event(
+                    fedimint_ln_common::KIND,
+                    EventKind::from_static("outgoing-payment-started")
+                )
+            ),
+            None
+        );
+        assert_eq!(
+            payment_notification(
+                federation_id,
+                &event(
+                    ModuleKind::from_static_str("mint"),
+                    OutgoingPaymentFailed::KIND
+                )
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn liquidity_alerts_respect_targets_and_disabled_minimums() {
+        let federation_id = FederationId::dummy();
+        let settings = RebalanceSettings {
+            min_outbound_msats: 1_000,
+            min_inbound_msats: 0,
+            ..RebalanceSettings::default()
+        };
+        let targets = BTreeMap::from([(
+            federation_id,
+            LiquidityTarget {
+                min_ecash: Amount::from_msats(500),
+                max_ecash: Amount::from_msats(5_000),
+            },
+        )]);
+        let balances = GatewayBalances {
+            onchain_balance_sats: 0,
+            lightning_balance_msats: 999,
+            ecash_balances: vec![FederationBalanceInfo {
+                federation_id,
+                ecash_balance_msats: Amount::from_msats(400),
+            }],
+            inbound_lightning_liquidity_msats: 0,
+        };
+
+        assert_eq!(
+            liquidity_alerts(&settings, &targets, &balances),
+            vec![
+                LiquidityAlert::Ecash {
+                    federation_id,
+                    balance: Amount::from_msats(400),
+                    minimum: Amount::from_msats(500),
+                },
+                LiquidityAlert::Outbound {
+                    balance_msats: 999,
+                    minimum_msats: 1_000,
+                },
+            ]
+        );
+        assert!(
+            liquidity_alerts(
+                &settings,
+                &BTreeMap::new(),
+                &GatewayBalances {
+                    lightning_balance_msats: 1_000,
+                    ..balances
+                }
+            )
+            .is_empty()
+        );
+    }
+}
@@ -41,6 +41,8 @@
     FinalReceiveState, GatewayClientModuleV2, GatewayClientStateMachinesV2, GatewayOperationMetaV2,
     IncomingCircuitKey,
 };
+use fedimint_lightning::ILnRpcClient as _;
+use fedimint_lightning::mock::{HtlcResolution, MockLightningNetwork, PaymentBehaviour};
 use fedimint_ln_client::api::LnFederationApi;
 use fedimint_ln_client::pay::{PayInvoicePayload, PaymentData};
 use fedimint_ln_client::{
@@ -57,7 +59,10 @@
 use fedimint_ln_server::LightningInit;
 use fedimint_lnv2_common::LightningInvoice;
 use fedimint_lnv2_common::contracts::{IncomingContract, OutgoingContract, PaymentImage};
-use fedimint_lnv2_common::gateway_api::{PaymentFee, SendPaymentPayload};
+use fedimint_lnv2_common::gateway_api::{
+    CreateBolt11InvoicePayload, HoldInvoiceStatus, PaymentFee, ResolveHoldInvoicePayload,
+    SendPaymentPayload,
+};
 use fedimint_logging::LOG_TEST;
 use fedimint_testing::btc::BitcoinTest;
 use fedimint_testing::db::BYTE_33;
@@ -510,6 +515,82 @@ async fn test_gateway_client_pay_unpayable_invoice() -> anyhow::Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_gateway_client_pay_invoice_rejected_by_mock_network() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+
+    // The payee is a peer of the gateway that no payment reaches
+    let network = MockLightningNetwork::new();
+    let payee = network.add_peer("payee");
+    network.open_channel(payee, sats(1000), sats(0));
+    network.set_payee_behaviour(payee, PaymentBehaviour::fail("no route to payee"));
+
+    let gateway = fixtures
+        .new_gateway_with_lightning(Arc::new(network.gateway_node()))
+        .await;
+    fed.connect_gateway(&gateway).await;
+    let user_client = fed.new_client().await;
+
+    let gateway_id = gateway.http_gateway_id().await;
+    let gateway_client = gateway.select_client(fed.id()).await?.into_value();
+    // Give user client initial balance
+    let dummy_module = user_client.get_first_module::<DummyClientModule>()?;
+    let lightning_module = user_client.get_first_module::<LightningClientModule>()?;
+    dummy_module
+        .mock_receive(sats(1000), AmountUnit::BITCOIN)
+        .await?;
+    assert_eq!(user_client.get_balance_for_btc().await?, sats(1000));
+
+    // Create invoice that cannot be paid
+    let invoice = network.peer_invoice(payee, sats(250));
+    let payment_hash = *invoice.payment_hash();
+
+    let gateway = lightning_module.select_gateway(&gateway_id).await;
+
+    // User client pays test invoice
+    let OutgoingLightningPayment {
+        payment_type,
+        contract_id,
+        fee: _,
+    } = user_pay_invoice(&lightning_module, invoice.clone(), &gateway_id).await?;
+    match payment_type {
+        PayType::Lightning(pay_op) => {
+            let mut pay_sub = lightning_module
+                .subscribe_ln_pay(pay_op)
+                .await?
+                .into_stream();
+            assert_eq!(pay_sub.ok().await?, LnPayState::Created);
+            let funded = pay_sub.ok().await?;
+            assert_matches!(funded, LnPayState::Funded { .. });
+
+            let payload = PayInvoicePayload {
+                federation_id: user_client.federation_id(),
+                contract_id,
+                payment_data: get_payment_data(gateway, invoice),
+                preimage_auth: Hash::hash(&[0; 32]),
+            };
+
+            let gw_pay_op = gateway_client
+                .get_first_module::<GatewayClientModule>()?
+                .gateway_pay_bolt11_invoice(payload)
+                .await?;
+            let mut gw_pay_sub = gateway_client
+                .get_first_module::<GatewayClientModule>()?
+                .gateway_subscribe_ln_pay(gw_pay_op)
+                .await?
+                .into_stream();
+            assert_eq!(gw_pay_sub.ok().await?, GatewayExtPayStates::Created);
+            assert_matches!(gw_pay_sub.ok().await?, GatewayExtPayStates::Canceled { .. });
+        }
+        _ => panic!("Expected Lightning payment!"),
+    }
+
+    assert!(network.payment_preimage(payment_hash).is_none());
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_gateway_client_intercept_valid_htlc() -> anyhow::Result<()> {
     single_federation_test(|gateway, _, fed, user_client, _| async move {
@@ -1596,6 +1677,141 @@ async fn lnv2_expired_incoming_contract_is_rejected() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn lnv2_hold_invoice_payment_is_held_until_settled() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+
+    let network = MockLightningNetwork::new();
+    let gateway = fixtures
+        .new_gateway_with_lightning(Arc::new(network.gateway_node()))
+        .await;
+
+    fed.connect_gateway(&gateway).await;
+
+    send_msats_to_gateway(&gateway, fed.id(), 1_000_000_000).await;
+
+    let client = gateway.select_client(fed.id()).await?.into_value();
+    let module = client.get_first_module::<GatewayClientModuleV2>()?;
+
+    let invoice_amount = Amount::from_sats(1000);
+    let contract_amount = gateway
+        .routing_info_v2(&fed.id())
+        .await?
+        .expect("Gateway is connected to the federation")
+        .receive_fee_for(invoice_amount.msats)
+        .subtract_from(invoice_amount.msats);
+
+    let recipient = Keypair::new(secp256k1::SECP256K1, &mut rand::thread_rng());
+    let preimage = [23; 32];
+    let payment_image = PaymentImage::Hash(preimage.consensus_hash());
+
+    let contract = IncomingContract::new(
+        module.cfg.tpe_agg_pk,
+        [42; 32],
+        preimage,
+        payment_image.clone(),
+        contract_amount,
+        fedimint_core::time::duration_since_epoch().as_secs() + 3600,
+        recipient.public_key(),
+        module.keypair.public_key(),
+        Keypair::new(secp256k1::SECP256K1, &mut rand::thread_rng()).public_key(),
+    );
+
+    let invoice = gateway
+        .testing()
+        .create_hold_invoice_v2(CreateBolt11InvoicePayload {
+            federation_id: fed.id(),
+            contract: contract.clone(),
+            amount: invoice_amount,
+            description: fedimint_lnv2_common::Bolt11InvoiceDescription::Direct(String::new()),
+            expiry_secs: 3600,
+        })
+        .await?;
+
+    assert_eq!(
+        gateway
+            .testing()
+            .hold_invoice_status_v2(payment_image.clone())
+            .await?,
+        Some(HoldInvoiceStatus::Open)
+    );
+
+    let task_group = TaskGroup::new();
+    let (mut htlc_stream, _) = Box::new(network.gateway_node())
+        .route_htlcs(&task_group)
+        .await?;
+
+    let htlc = network.pay_gateway_invoice(&invoice)?;
+
+    let htlc_request = futures::StreamExt::next(&mut htlc_stream)
+        .await
+        .expect("HTLC is intercepted");
+
+    gateway
+        .testing()
+        .intercept_htlc(htlc_request, &gateway.get_lightning_context().await?)
+        .await;
+
+    // The gateway holds the payment until the recipient settles it
+    assert_eq!(
+        gateway
+            .testing()
+            .hold_invoice_status_v2(payment_image.clone())
+            .await?,
+        Some(HoldInvoiceStatus::Held)
+    );
+    assert!(
+        !client
+            .operation_exists(OperationId::from_encodable(&contract))
+            .await
+    );
+
+    // Only the recipient can settle the payment
+    assert!(
+        gateway
+            .testing()
+            .resolve_hold_invoice_v2(ResolveHoldInvoicePayload {
+                payment_image: payment_image.clone(),
+                settle: true,
+                signature: module
+                    .keypair
+                    .sign_schnorr(contract.hold_resolution_message(true)),
+            })
+            .await
+            .is_err()
+    );
+
+    assert_eq!(
+        gateway
+            .testing()
+            .resolve_hold_invoice_v2(ResolveHoldInvoicePayload {
+                payment_image: payment_image.clone(),
+                settle: true,
+                signature: recipient.sign_schnorr(contract.hold_resolution_message(true)),
+            })
+            .await?,
+        HoldInvoiceStatus::Settled
+    );
+
+    assert_eq!(
+        htlc.resolution().await,
+        HtlcResolution::Settled(Preimage(preimage))
+    );
+
+    assert_eq!(
+        gateway
+            .testing()
+            .hold_invoice_status_v2(payment_image)
+            .await?,
+        Some(HoldInvoiceStatus::Settled)
+    );
+
+    task_group.shutdown_join_all(None).await?;
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn lnv2_malleated_incoming_contract_is_rejected() -> anyhow::Result<()> {
     let fixtures = fixtures();
@@ -1901,6 +2117,7 @@ async fn lnv2_routes_reject_a_federation_without_an_lnv2_module() -> anyhow::Res
             ephemeral_pk: keypair.public_key(),
         },
         invoice: LightningInvoice::Bolt11(FakeLightningTest::new().invoice(sats(1), None)?),
+        part_amount: None,
         auth: secp256k1::SECP256K1
             .sign_schnorr(&secp256k1::Message::from_digest([0; 32]), &keypair),
     };
@@ -13,7 +13,8 @@
 use fedimint_core::invite_code::InviteCode;
 use fedimint_core::{Amount, BitcoinAmountOrAll, PeerId, TieredCounts};
 use fedimint_gateway_common::{
-    DepositAddressPayload, FederationInfo, LeaveFedPayload, ReceiveEcashPayload, SetFeesPayload,
+    CircuitBreakerState, DepositAddressPayload, ExposureLimits, FederationInfo, LeaveFedPayload,
+    ReceiveEcashPayload, SetExposureLimitsPayload, SetFeePolicyPayload, SetFeesPayload,
     SpendEcashPayload, WithdrawPayload, WithdrawPreviewPayload,
 };
 use fedimint_mint_client::OOBNotes;
@@ -25,9 +26,11 @@
 use qrcode::render::svg;
 use serde::Deserialize;
 
+use crate::lightning::empty_string_as_none;
 use crate::{
-    DEPOSIT_ADDRESS_ROUTE, DynGatewayApi, RECEIVE_ECASH_ROUTE, SET_FEES_ROUTE, SPEND_ECASH_ROUTE,
-    WITHDRAW_CONFIRM_ROUTE, WITHDRAW_PREVIEW_ROUTE, redirect_error, redirect_success,
+    DEPOSIT_ADDRESS_ROUTE, DynGatewayApi, RECEIVE_ECASH_ROUTE, REMOVE_FEE_POLICY_ROUTE,
+    SET_EXPOSURE_LIMITS_ROUTE, SET_FEES_ROUTE, SPEND_ECASH_ROUTE, WITHDRAW_CONFIRM_ROUTE,
+    WITHDRAW_PREVIEW_ROUTE, redirect_error, redirect_success,
     redirect_success_with_export_reminder,
 };
 
@@ -36,6 +39,21 @@ pub struct ReceiveEcashForm {
     pub notes: String,
 }
 
+/// Exposure limits in sats, empty fields remove the limit
+#[derive(Deserialize)]
+pub struct ExposureLimitsForm {
+    pub federation_id: FederationId,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub max_balance_sats: Option<u64>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub max_in_flight_sats: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct RemoveFeePolicyForm {
+    pub federation_id: FederationId,
+}
+
 pub fn scripts() -> Markup {
     html!(
         script {
@@ -198,6 +216,11 @@ pub fn render<E: Display>(
                         div class="alert alert-secondary py-1 px-2 small" {
                             "Last Backup: " strong { (last_backup_str) }
                         }
+                        @if let CircuitBreakerState::Open { since } = fed.circuit_breaker {
+                            div class="alert alert-danger py-1 px-2 small" {
+                                "Circuit breaker open, payments are not routed. Federation API unreachable, tripped " strong { (time_ago(since)) }
+                            }
+                        }
 
                         // --- TABS ---
                         ul class="nav nav-tabs" role="tablist" {
@@ -210,6 +233,15 @@ pub fn render<E: Display>(
                                     role="tab"
                                 { "Fees" }
                             }
+                            li class="nav-item" role="presentation" {
+                                button class="nav-link"
+                                    id={(format!("limits-tab-{}", fed.federation_id))}
+                                    data-bs-toggle="tab"
+                                    data-bs-target={(format!("#limits-tab-pane-{}", fed.federation_id))}
+                                    type="button"
+                                    role="tab"
+                                { "Limits" }
+                            }
                             li class="nav-item" role="presentation" {
                                 button class="nav-link"
                                     id={(format!("deposit-tab-{}", fed.federation_id))}
@@ -311,6 +343,21 @@ pub fn render<E: Display>(
                                         }
                                     }
 
+                                    @if let Some(policy) = &fed.fee_policy {
+                                        div class="alert alert-info small d-flex justify-content-between align-items-center" {
+                                            span {
+                                                "A fee policy scales the lightning fee of LNv2 payments with the gateway's liquidity ("
+                                                (policy.send_curve.len()) " send points, "
+                                                (policy.receive_curve.len()) " receive points, "
+                                                (policy.amount_tiers.len()) " amount tiers)."
+                                            }
+                                            form method="post" action=(REMOVE_FEE_POLICY_ROUTE) style="display: inline;" {
+                                                input type="hidden" name="federation_id" value=(fed.federation_id.to_string());
+                                                button type="submit" class="btn btn-sm btn-outline-danger ms-2" { "Use Static Fees" }
+                                            }
+                                        }
+                                    }
+
                                     button
                                         class="btn btn-sm btn-outline-primary"
                                         type="button"
@@ -392,6 +439,53 @@ pub fn render<E: Display>(
                                 }
                             }
 
+                            // ──────────────────────────────────────────
+                            //   TAB: LIMITS
+                            // ──────────────────────────────────────────
+                            div class="tab-pane fade"
+                                id={(format!("limits-tab-pane-{}", fed.federation_id))}
+                                role="tabpanel"
+                                aria-labelledby={(format!("limits-tab-{}", fed.federation_id))} {
+
+                                p class="small mb-2" {
+                                    "In Flight: " strong { (fed.in_flight_msat) }
+                                }
+
+                                form method="post" action={(SET_EXPOSURE_LIMITS_ROUTE)} {
+                                    input type="hidden" name="federation_id" value=(fed.federation_id.to_string());
+                                    table class="table table-sm mb-2" {
+                                        tbody {
+                                            tr {
+                                                th {
+                                                    "Max Balance (sats) "
+                                                    span class="text-muted" data-bs-toggle="tooltip" title="Payments that would raise the gateway's balance in this federation above this amount are rejected. Leave empty for no limit." { "ⓘ" }
+                                                }
+                                                td {
+                                                    input type="number" min="0"
+                                                        class="form-control form-control-sm"
+                                                        name="max_balance_sats"
+                                                        value=[fed.exposure_limits.max_balance.map(|amount| amount.sats_round_down())];
+                                                }
+                                            }
+                                            tr {
+                                                th {
+                                                    "Max In Flight (sats) "
+                                                    span class="text-muted" data-bs-toggle="tooltip" title="Maximum sum of the payments to and from this federation that can be pending at the same time. Leave empty for no limit." { "ⓘ" }
+                                                }
+                                                td {
+                                                    input type="number" min="0"
+                                                        class="form-control form-control-sm"
+                                                        name="max_in_flight_sats"
+                                                        value=[fed.exposure_limits.max_in_flight.map(|amount| amount.sats_round_down())];
+                                                }
+                                            }
+                                        }
+                                    }
+
+                                    button type="submit" class="btn btn-sm btn-primary" { "Save Limits" }
+                                }
+                            }
+
                             // ──────────────────────────────────────────
                             //   TAB: DEPOSIT
                             // ──────────────────────────────────────────
@@ -720,6 +814,43 @@ pub async fn set_fees_handler<E: Display>(
     }
 }
 
+pub async fn set_exposure_limits_handler<E: Display>(
+    State(state): State<UiState<DynGatewayApi<E>>>,
+    _auth: UserAuth,
+    Form(form): Form<ExposureLimitsForm>,
+) -> impl IntoResponse {
+    let payload = SetExposureLimitsPayload {
+        federation_id: form.federation_id,
+        limits: ExposureLimits {
+            max_balance: form.max_balance_sats.map(Amount::from_sats),
+            max_in_flight: form.max_in_flight_sats.map(Amount::from_sats),
+        },
+    };
+
+    match state.api.handle_set_exposure_limits_msg(payload).await {
+        Ok(()) => redirect_success("Saved exposure limits".to_string()).into_response(),
+        Err(err) => {
+            redirect_error(format!("Failed to save exposure limits: {err}")).into_response()
+        }
+    }
+}
+
+pub async fn remove_fee_policy_handler<E: Display>(
+    State(state): State<UiState<DynGatewayApi<E>>>,
+    _auth: UserAuth,
+    Form(form): Form<RemoveFeePolicyForm>,
+) -> impl IntoResponse {
+    let payload = SetFeePolicyPayload {
+        federation_id: form.federation_id,
+        policy: None,
+    };
+
+    match state.api.handle_set_fee_policy_msg(payload).await {
+        Ok(()) => redirect_success("Removed fee policy".to_string()).into_response(),
+        Err(err) => redirect_error(format!("Failed to remove fee policy: {err}")).into_response(),
+    }
+}
+
 pub async fn deposit_address_handler<E: Display>(
     State(state): State<UiState<DynGatewayApi<E>>>,
     _auth: UserAuth,
@@ -5,6 +5,7 @@
 mod lightning;
 mod mnemonic;
 mod payment_summary;
+mod rebalance;
 mod setup;
 
 use std::collections::BTreeMap;
@@ -30,12 +31,14 @@
 use fedimint_gateway_common::{
     ChainSource, CloseChannelsWithPeerRequest, CloseChannelsWithPeerResponse, ConnectFedPayload,
     ConnectPeerRequest, CreateInvoiceForOperatorPayload, CreateOfferPayload, CreateOfferResponse,
-    DepositAddressPayload, FederationInfo, GatewayBalances, GatewayInfo, LeaveFedPayload,
-    LightningMode, ListTransactionsPayload, ListTransactionsResponse, MnemonicResponse,
-    OpenChannelRequest, PayInvoiceForOperatorPayload, PayOfferPayload, PayOfferResponse,
-    PaymentLogPayload, PaymentLogResponse, PaymentSummaryPayload, PaymentSummaryResponse,
-    ReceiveEcashPayload, ReceiveEcashResponse, SendOnchainRequest, SetFeesPayload,
-    SetMnemonicPayload, SpendEcashPayload, SpendEcashResponse, WithdrawPayload,
+    DepositAddressPayload, ExportEventLogPayload, ExportEventLogResponse, FederationInfo,
+    GatewayBalances, GatewayInfo, LeaveFedPayload, LightningMode, ListTransactionsPayload,
+    ListTransactionsResponse, MnemonicResponse, OpenChannelRequest, PayInvoiceForOperatorPayload,
+    PayOfferPayload, PayOfferResponse, PaymentLogPayload, PaymentLogResponse,
+    PaymentSummaryPayload, PaymentSummaryResponse, RebalanceConfigResponse, RebalanceResponse,
+    RebalanceSettings, ReceiveEcashPayload, ReceiveEcashResponse, SendOnchainRequest,
+    SetExposureLimitsPayload, SetFeePolicyPayload, SetFeesPayload, SetLiquidityTargetPayload,
+    SetMnemonicPayload, SetWebhookPayload, SpendEcashPayload, SpendEcashResponse, WithdrawPayload,
     WithdrawPreviewPayload, WithdrawPreviewResponse, WithdrawResponse,
 };
 use fedimint_ln_common::contracts::Preimage;
@@ -52,8 +55,9 @@
 
 use crate::connect_fed::connect_federation_handler;
 use crate::federation::{
-    deposit_address_handler, leave_federation_handler, receive_ecash_handler, set_fees_handler,
-    spend_ecash_handler, withdraw_confirm_handler, withdraw_preview_handler,
+    deposit_address_handler, leave_federation_handler, receive_ecash_handler,
+    remove_fee_policy_handler, set_exposure_limits_handler, set_fees_handler, spend_ecash_handler,
+    withdraw_confirm_handler, withdraw_preview_handler,
 };
 use crate::lightning::{
     channels_fragment_handler, close_channel_handler, connect_peer_handler,
@@ -64,6 +68,10 @@
 };
 use crate::mnemonic::{mnemonic_iframe_handler, mnemonic_reveal_handler};
 use crate::payment_summary::payment_log_fragment_handler;
+use crate::rebalance::{
+    rebalance_now_handler, remove_liquidity_target_handler, set_liquidity_target_handler,
+    set_rebalance_settings_handler,
+};
 use crate::setup::{create_wallet_handler, recover_wallet_form, recover_wallet_handler};
 pub type DynGatewayApi<E> = Arc<dyn IAdminGateway<Error = E> + Send + Sync + 'static>;
 
@@ -75,6 +83,8 @@
 pub(crate) const LEAVE_FEDERATION_ROUTE: &str = "/ui/federations/{id}/leave";
 pub(crate) const CONNECT_FEDERATION_ROUTE: &str = "/ui/federations/join";
 pub(crate) const SET_FEES_ROUTE: &str = "/ui/federation/set-fees";
+pub(crate) const SET_EXPOSURE_LIMITS_ROUTE: &str = "/ui/federation/set-exposure-limits";
+pub(crate) const REMOVE_FEE_POLICY_ROUTE: &str = "/ui/federation/remove-fee-policy";
 pub(crate) const SEND_ONCHAIN_ROUTE: &str = "/ui/wallet/send";
 pub(crate) const WALLET_FRAGMENT_ROUTE: &str = "/ui/wallet/fragment";
 pub(crate) const LN_ONCHAIN_ADDRESS_ROUTE: &str = "/ui/wallet/receive";
@@ -96,6 +106,10 @@
 pub(crate) const RECOVER_WALLET_ROUTE: &str = "/ui/wallet/recover";
 pub(crate) const MNEMONIC_IFRAME_ROUTE: &str = "/ui/mnemonic/iframe";
 pub(crate) const EXPORT_INVITE_CODES_ROUTE: &str = "/ui/export-invite-codes";
+pub(crate) const SET_REBALANCE_SETTINGS_ROUTE: &str = "/ui/rebalance/settings";
+pub(crate) const SET_LIQUIDITY_TARGET_ROUTE: &str = "/ui/rebalance/target";
+pub(crate) const REMOVE_LIQUIDITY_TARGET_ROUTE: &str = "/ui/rebalance/target/remove";
+pub(crate) const REBALANCE_NOW_ROUTE: &str = "/ui/rebalance/run";
 
 #[derive(Default, Deserialize)]
 pub struct DashboardQuery {
@@ -235,6 +249,11 @@ async fn handle_payment_log_msg(
         payload: PaymentLogPayload,
     ) -> Result<PaymentLogResponse, Self::Error>;
 
+    async fn handle_export_event_log_msg(
+        &self,
+        payload: ExportEventLogPayload,
+    ) -> Result<ExportEventLogResponse, Self::Error>;
+
     async fn handle_export_invite_codes(
         &self,
     ) -> BTreeMap<FederationId, BTreeMap<PeerId, (String, InviteCode)>>;
@@ -266,6 +285,33 @@ async fn handle_get_note_summary_msg(
         &self,
         federation_id: &FederationId,
     ) -> Result<TieredCounts, Self::Error>;
+
+    async fn handle_get_rebalance_config_msg(&self)
+    -> Result<RebalanceConfigResponse, Self::Error>;
+
+    async fn handle_set_rebalance_settings_msg(
+        &self,
+        payload: RebalanceSettings,
+    ) -> Result<(), Self::Error>;
+
+    async fn handle_set_liquidity_target_msg(
+        &self,
+        payload: SetLiquidityTargetPayload,
+    ) -> Result<(), Self::Error>;
+
+    async fn handle_rebalance_msg(&self) -> Result<RebalanceResponse, Self::Error>;
+
+    async fn handle_set_exposure_limits_msg(
+        &self,
+        payload: SetExposureLimitsPayload,
+    ) -> Result<(), Self::Error>;
+
+    async fn handle_set_fee_policy_msg(
+        &self,
+        payload: SetFeePolicyPayload,
+    ) -> Result<(), Self::Error>;
+
+    async fn handle_set_webhook_msg(&self, payload: SetWebhookPayload) -> Result<(), Self::Error>;
 }
 
 async fn login_form_handler<E>(
@@ -395,6 +441,12 @@ async fn dashboard_view<E>(
             }
         }
 
+        div class="row gy-4 mt-2" {
+            div class="col-md-12" {
+                (rebalance::render(&state.api, &gateway_info.federations).await)
+            }
+        }
+
         div class="row gy-4 mt-2" {
             div class="col-md-12" {
                 (connect_fed::render(&gateway_info.gateway_state))
@@ -515,6 +567,21 @@ pub fn router<E: Display + Send + Sync + std::fmt::Debug + 'static>(
         .route(WITHDRAW_PREVIEW_ROUTE, post(withdraw_preview_handler))
         .route(WITHDRAW_CONFIRM_ROUTE, post(withdraw_confirm_handler))
         .route(PAYMENT_LOG_ROUTE, get(payment_log_fragment_handler))
+        .route(
+            SET_REBALANCE_SETTINGS_ROUTE,
+            post(set_rebalance_settings_handler),
+        )
+        .route(
+            SET_LIQUIDITY_TARGET_ROUTE,
+            post(set_liquidity_target_handler),
+        )
+        .route(
+            REMOVE_LIQUIDITY_TARGET_ROUTE,
+            post(remove_liquidity_target_handler),
+        )
+        .route(REBALANCE_NOW_ROUTE, post(rebalance_now_handler))
+        .route(SET_EXPOSURE_LIMITS_ROUTE, post(set_exposure_limits_handler))
+        .route(REMOVE_FEE_POLICY_ROUTE, post(remove_fee_policy_handler))
         .route(CREATE_WALLET_ROUTE, post(create_wallet_handler))
         .route(
             RECOVER_WALLET_ROUTE,
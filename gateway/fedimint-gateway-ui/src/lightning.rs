@@ -575,7 +575,7 @@ fn detect_payment_type(payment_string: &str) -> PaymentStringType {
 }
 
 /// Helper to deserialize empty strings as None
-fn empty_string_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+pub(crate) fn empty_string_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
 where
     D: serde::Deserializer<'de>,
     T: std::str::FromStr,
@@ -0,0 +1,311 @@
+use std::fmt::Display;
+
+use axum::Form;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use fedimint_core::Amount;
+use fedimint_core::config::FederationId;
+use fedimint_gateway_common::{
+    FederationInfo, LiquidityTarget, NodeAddress, RebalanceSettings, SetLiquidityTargetPayload,
+};
+use fedimint_logging::LOG_GATEWAY_UI;
+use fedimint_ui_common::UiState;
+use fedimint_ui_common::auth::UserAuth;
+use maud::{Markup, html};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::{
+    DynGatewayApi, REBALANCE_NOW_ROUTE, REMOVE_LIQUIDITY_TARGET_ROUTE, SET_LIQUIDITY_TARGET_ROUTE,
+    SET_REBALANCE_SETTINGS_ROUTE, redirect_error, redirect_success,
+};
+
+#[derive(Deserialize)]
+pub struct RebalanceSettingsForm {
+    /// Checkboxes are only submitted when checked
+    pub enabled: Option<String>,
+    pub interval_secs: u64,
+    pub fee_rate_sats_per_vbyte: u64,
+    pub onchain_reserve_sats: u64,
+    pub min_outbound_sats: u64,
+    pub min_inbound_sats: u64,
+    /// Empty to only report low outbound liquidity
+    pub channel_peer: String,
+}
+
+#[derive(Deserialize)]
+pub struct LiquidityTargetForm {
+    pub federation_id: FederationId,
+    pub min_ecash_sats: u64,
+    pub max_ecash_sats: u64,
+}
+
+#[derive(Deserialize)]
+pub struct RemoveLiquidityTargetForm {
+    pub federation_id: FederationId,
+}
+
+pub async fn render<E>(api: &DynGatewayApi<E>, federations: &[FederationInfo]) -> Markup
+where
+    E: std::fmt::Display,
+{
+    debug!(target: LOG_GATEWAY_UI, "Getting rebalance config...");
+    let config = match api.handle_get_rebalance_config_msg().await {
+        Ok(config) => config,
+        Err(err) => {
+            return html! {
+                div class="alert alert-danger" {
+                    "Failed to load rebalancing config: " (err)
+                }
+            };
+        }
+    };
+    let settings = &config.settings;
+
+    html! {
+        div class="card h-100" {
+            div class="card-header dashboard-header d-flex justify-content-between align-items-center" {
+                span { "Liquidity Rebalancing" }
+                form action=(REBALANCE_NOW_ROUTE) method="post" style="display: inline;" {
+                    button class="btn btn-sm btn-outline-primary" type="submit"
+                        onclick="return confirm('Move funds between the federations, the onchain wallet and the channels now?');"
+                    {
+                        "Rebalance Now"
+                    }
+                }
+            }
+
+            div class="card-body" {
+                p class="text-muted small" {
+                    "Channels below their liquidity targets are rebalanced with self-payments through other gateways of the federations: ecash above a federation's range minimum pays into the channels for outbound liquidity, and channels pay into federations below their range maximum for inbound liquidity. "
+                    "Federations outside of their ecash range are moved back to the middle of it by pegging out to, or pegging in from, the lightning node's onchain wallet. "
+                    "If a channel peer is set, remaining low outbound liquidity is topped up by opening a channel to it from the onchain wallet."
+                }
+
+                form method="post" action=(SET_REBALANCE_SETTINGS_ROUTE) {
+                    table class="table table-sm mb-2" {
+                        tbody {
+                            tr {
+                                th { "Scheduled" }
+                                td {
+                                    input type="checkbox"
+                                        class="form-check-input"
+                                        name="enabled"
+                                        value="true"
+                                        checked[settings.enabled];
+                                }
+                            }
+                            tr {
+                                th { "Interval (seconds)" }
+                                td {
+                                    input type="number" min="60"
+                                        class="form-control form-control-sm"
+                                        name="interval_secs"
+                                        value=(settings.interval_secs);
+                                }
+                            }
+                            tr {
+                                th { "Onchain Fee Rate (sats/vB)" }
+                                td {
+                                    input type="number" min="1"
+                                        class="form-control form-control-sm"
+                                        name="fee_rate_sats_per_vbyte"
+                                        value=(settings.fee_rate_sats_per_vbyte);
+                                }
+                            }
+                            tr {
+                                th { "Onchain Reserve (sats)" }
+                                td {
+                                    input type="number" min="0"
+                                        class="form-control form-control-sm"
+                                        name="onchain_reserve_sats"
+                                        value=(settings.onchain_reserve_sats);
+                                }
+                            }
+                            tr {
+                                th { "Minimum Outbound (sats)" }
+                                td {
+                                    input type="number" min="0"
+                                        class="form-control form-control-sm"
+                                        name="min_outbound_sats"
+                                        value=(settings.min_outbound_msats / 1000);
+                                }
+                            }
+                            tr {
+                                th { "Minimum Inbound (sats)" }
+                                td {
+                                    input type="number" min="0"
+                                        class="form-control form-control-sm"
+                                        name="min_inbound_sats"
+                                        value=(settings.min_inbound_msats / 1000);
+                                }
+                            }
+                            tr {
+                                th { "Channel Peer" }
+                                td {
+                                    input type="text"
+                                        class="form-control form-control-sm"
+                                        name="channel_peer"
+                                        placeholder="pubkey@host:port"
+                                        value=[settings.channel_peer.as_ref().map(ToString::to_string)];
+                                }
+                            }
+                        }
+                    }
+                    button type="submit" class="btn btn-sm btn-primary" { "Save Settings" }
+                }
+
+                @if !federations.is_empty() {
+                    table class="table table-sm mt-4 mb-0" {
+                        thead {
+                            tr {
+                                th { "Federation" }
+                                th { "Balance" }
+                                th { "Min Ecash (sats)" }
+                                th { "Max Ecash (sats)" }
+                                th {}
+                            }
+                        }
+                        tbody {
+                            @for fed in federations {
+                                @let target = config.targets.get(&fed.federation_id);
+                                @let form_id = format!("liquidity-target-{}", fed.federation_id);
+                                tr {
+                                    td { (fed.federation_name.clone().unwrap_or_else(|| fed.federation_id.to_string())) }
+                                    td { (fed.balance_msat) }
+                                    td {
+                                        input type="number" min="0" required
+                                            form=(form_id)
+                                            class="form-control form-control-sm"
+                                            name="min_ecash_sats"
+                                            value=[target.map(|t| t.min_ecash.sats_round_down())];
+                                    }
+                                    td {
+                                        input type="number" min="0" required
+                                            form=(form_id)
+                                            class="form-control form-control-sm"
+                                            name="max_ecash_sats"
+                                            value=[target.map(|t| t.max_ecash.sats_round_down())];
+                                    }
+                                    td class="text-nowrap" {
+                                        form id=(form_id) method="post" action=(SET_LIQUIDITY_TARGET_ROUTE) style="display: inline;" {
+                                            input type="hidden" name="federation_id" value=(fed.federation_id.to_string());
+                                            button type="submit" class="btn btn-sm btn-outline-primary me-1" { "Set" }
+                                        }
+                                        @if target.is_some() {
+                                            form method="post" action=(REMOVE_LIQUIDITY_TARGET_ROUTE) style="display: inline;" {
+                                                input type="hidden" name="federation_id" value=(fed.federation_id.to_string());
+                                                button type="submit" class="btn btn-sm btn-outline-danger" { "Remove" }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub async fn set_rebalance_settings_handler<E: Display>(
+    State(state): State<UiState<DynGatewayApi<E>>>,
+    _auth: UserAuth,
+    Form(form): Form<RebalanceSettingsForm>,
+) -> impl IntoResponse {
+    let channel_peer = match form.channel_peer.trim() {
+        "" => None,
+        channel_peer => match channel_peer.parse::<NodeAddress>() {
+            Ok(node_address) => Some(node_address),
+            Err(err) => {
+                return redirect_error(format!("Invalid channel peer: {err}")).into_response();
+            }
+        },
+    };
+
+    let settings = RebalanceSettings {
+        enabled: form.enabled.is_some(),
+        interval_secs: form.interval_secs,
+        fee_rate_sats_per_vbyte: form.fee_rate_sats_per_vbyte,
+        onchain_reserve_sats: form.onchain_reserve_sats,
+        min_outbound_msats: form.min_outbound_sats.saturating_mul(1000),
+        min_inbound_msats: form.min_inbound_sats.saturating_mul(1000),
+        channel_peer,
+    };
+
+    match state.api.handle_set_rebalance_settings_msg(settings).await {
+        Ok(()) => redirect_success("Saved rebalancing settings".to_string()).into_response(),
+        Err(err) => {
+            redirect_error(format!("Failed to save rebalancing settings: {err}")).into_response()
+        }
+    }
+}
+
+pub async fn set_liquidity_target_handler<E: Display>(
+    State(state): State<UiState<DynGatewayApi<E>>>,
+    _auth: UserAuth,
+    Form(form): Form<LiquidityTargetForm>,
+) -> impl IntoResponse {
+    let payload = SetLiquidityTargetPayload {
+        federation_id: form.federation_id,
+        target: Some(LiquidityTarget {
+            min_ecash: Amount::from_sats(form.min_ecash_sats),
+            max_ecash: Amount::from_sats(form.max_ecash_sats),
+        }),
+    };
+
+    match state.api.handle_set_liquidity_target_msg(payload).await {
+        Ok(()) => redirect_success("Saved liquidity target".to_string()).into_response(),
+        Err(err) => {
+            redirect_error(format!("Failed to save liquidity target: {err}")).into_response()
+        }
+    }
+}
+
+pub async fn remove_liquidity_target_handler<E: Display>(
+    State(state): State<UiState<DynGatewayApi<E>>>,
+    _auth: UserAuth,
+    Form(form): Form<RemoveLiquidityTargetForm>,
+) -> impl IntoResponse {
+    let payload = SetLiquidityTargetPayload {
+        federation_id: form.federation_id,
+        target: None,
+    };
+
+    match state.api.handle_set_liquidity_target_msg(payload).await {
+        Ok(()) => redirect_success("Removed liquidity target".to_string()).into_response(),
+        Err(err) => {
+            redirect_error(format!("Failed to remove liquidity target: {err}")).into_response()
+        }
+    }
+}
+
+pub async fn rebalance_now_handler<E: Display>(
+    State(state): State<UiState<DynGatewayApi<E>>>,
+    _auth: UserAuth,
+) -> impl IntoResponse {
+    match state.api.handle_rebalance_msg().await {
+        Ok(response) => {
+            let failed = response
+                .actions
+                .iter()
+                .filter(|outcome| outcome.error.is_some())
+                .count();
+            let mut msg = format!(
+                "Rebalancing submitted {} of {} moves.",
+                response.actions.len() - failed,
+                response.actions.len()
+            );
+            if response.outbound_deficit_msats > 0 || response.inbound_deficit_msats > 0 {
+                msg.push_str(&format!(
+                    " Channels are missing {} outbound and {} inbound liquidity.",
+                    Amount::from_msats(response.outbound_deficit_msats),
+                    Amount::from_msats(response.inbound_deficit_msats)
+                ));
+            }
+            redirect_success(msg).into_response()
+        }
+        Err(err) => redirect_error(format!("Failed to rebalance: {err}")).into_response(),
+    }
+}
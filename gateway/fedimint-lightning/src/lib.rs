@@ -1,6 +1,8 @@
 pub mod ldk;
 pub mod lnd;
 pub mod metrics;
+#[cfg(any(test, feature = "testing"))]
+pub mod mock;
 
 use std::fmt::Debug;
 use std::str::FromStr;
@@ -28,6 +30,8 @@
 use fedimint_metrics::HistogramExt as _;
 use futures::future::BoxFuture;
 use futures::stream::BoxStream;
+use lightning::offers::invoice::Bolt12Invoice;
+use lightning::offers::offer::Offer;
 use lightning_invoice::Bolt11Invoice;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -151,6 +155,26 @@ async fn pay(
         .await
     }
 
+    /// Attempts to pay the given part of an invoice's amount as one shard of a
+    /// multi-path payment, waiting for the payment to complete and returning
+    /// the preimage. The payee only releases the preimage once shards for the
+    /// full invoice amount have arrived, possibly from other nodes. If this is
+    /// implemented, [`ILnRpcClient::supports_partial_payments`] must return
+    /// true.
+    ///
+    /// The same idempotency requirements as for [`ILnRpcClient::pay`] apply.
+    async fn pay_part(
+        &self,
+        _invoice: Bolt11Invoice,
+        _amount: Amount,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        Err(LightningRpcError::FailedPayment {
+            failure_reason: "Paying parts of an invoice is not supported".to_string(),
+        })
+    }
+
     /// Attempts to pay an invoice using the lightning node, waiting for the
     /// payment to complete and returning the preimage.
     ///
@@ -175,6 +199,25 @@ fn supports_private_payments(&self) -> bool {
         false
     }
 
+    /// Returns true if the lightning backend can pay a part of an invoice. If
+    /// this returns true, [`ILnRpcClient::pay_part`] must be implemented. LDK
+    /// Node only sends payments for at least the full invoice amount, so only
+    /// LND gateways pay parts of multi-path payments.
+    fn supports_partial_payments(&self) -> bool {
+        false
+    }
+
+    /// Returns true if the lightning backend can pay invoices fetched from
+    /// BOLT12 offers and create offers for a payment hash. If this returns
+    /// true, [`ILnRpcClient::fetch_bolt12_invoice`],
+    /// [`ILnRpcClient::pay_bolt12_invoice`] and
+    /// [`ILnRpcClient::create_offer_for_hash`] must be implemented. LND has no
+    /// native BOLT12 support and LDK Node only pays offers end to end and
+    /// derives the payment hashes of its offers itself, so neither does.
+    fn supports_bolt12_payments(&self) -> bool {
+        false
+    }
+
     /// Consumes the current client and returns a stream of intercepted HTLCs
     /// and a new client. `complete_htlc` must be called for all successfully
     /// intercepted HTLCs sent to the returned stream.
@@ -274,6 +317,61 @@ async fn pay_offer(
         payer_note: Option<String>,
     ) -> Result<Preimage, LightningRpcError>;
 
+    /// Requests an invoice for the offer from its issuer without paying it.
+    ///
+    /// This allows the gateway to secure the payment for the invoice's payment
+    /// hash, e.g. by an LNv2 outgoing contract, before it pays the invoice via
+    /// [`ILnRpcClient::pay_bolt12_invoice`].
+    ///
+    /// LNv2 BOLT12 payments are currently limited to backends implementing
+    /// this method, [`ILnRpcClient::pay_bolt12_invoice`] and
+    /// [`ILnRpcClient::create_offer_for_hash`], which report this via
+    /// [`ILnRpcClient::supports_bolt12_payments`].
+    async fn fetch_bolt12_invoice(
+        &self,
+        _offer: Offer,
+        _amount: Option<Amount>,
+        _payer_note: Option<String>,
+    ) -> Result<Bolt12Invoice, LightningRpcError> {
+        Err(LightningRpcError::Bolt12Error {
+            failure_reason: "Fetching BOLT12 invoices is not supported".to_string(),
+        })
+    }
+
+    /// Attempts to pay an invoice previously requested via
+    /// [`ILnRpcClient::fetch_bolt12_invoice`], waiting for the payment to
+    /// complete and returning the preimage.
+    ///
+    /// The same idempotency requirements as for [`ILnRpcClient::pay`] apply.
+    async fn pay_bolt12_invoice(
+        &self,
+        _invoice: Bolt12Invoice,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        Err(LightningRpcError::Bolt12Error {
+            failure_reason: "Paying BOLT12 invoices is not supported".to_string(),
+        })
+    }
+
+    /// Creates a single-use offer for a fixed amount, such that the node
+    /// responds to invoice requests with an invoice for the given payment
+    /// hash. The incoming payment is then intercepted like a payment for an
+    /// invoice created via [`ILnRpcClient::create_invoice`] with a payment
+    /// hash.
+    fn create_offer_for_hash(
+        &self,
+        _payment_hash: sha256::Hash,
+        _amount: Amount,
+        _description: String,
+        _expiry_secs: u32,
+    ) -> Result<String, LightningRpcError> {
+        Err(LightningRpcError::Bolt12Error {
+            failure_reason: "Creating BOLT12 offers for a payment hash is not supported"
+                .to_string(),
+        })
+    }
+
     fn sync_wallet(&self) -> Result<(), LightningRpcError>;
 }
 
@@ -572,6 +670,22 @@ async fn pay(
         )
     }
 
+    async fn pay_part(
+        &self,
+        invoice: Bolt11Invoice,
+        amount: Amount,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        tracked_call!(
+            self,
+            "pay_part",
+            self.inner
+                .pay_part(invoice, amount, max_delay, max_fee)
+                .await
+        )
+    }
+
     async fn pay_private(
         &self,
         invoice: PrunedInvoice,
@@ -589,6 +703,14 @@ fn supports_private_payments(&self) -> bool {
         self.inner.supports_private_payments()
     }
 
+    fn supports_partial_payments(&self) -> bool {
+        self.inner.supports_partial_payments()
+    }
+
+    fn supports_bolt12_payments(&self) -> bool {
+        self.inner.supports_bolt12_payments()
+    }
+
     async fn route_htlcs<'a>(
         self: Box<Self>,
         _task_group: &TaskGroup,
@@ -729,6 +851,51 @@ async fn pay_offer(
         )
     }
 
+    async fn fetch_bolt12_invoice(
+        &self,
+        offer: Offer,
+        amount: Option<Amount>,
+        payer_note: Option<String>,
+    ) -> Result<Bolt12Invoice, LightningRpcError> {
+        tracked_call!(
+            self,
+            "fetch_bolt12_invoice",
+            self.inner
+                .fetch_bolt12_invoice(offer, amount, payer_note)
+                .await
+        )
+    }
+
+    async fn pay_bolt12_invoice(
+        &self,
+        invoice: Bolt12Invoice,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        tracked_call!(
+            self,
+            "pay_bolt12_invoice",
+            self.inner
+                .pay_bolt12_invoice(invoice, max_delay, max_fee)
+                .await
+        )
+    }
+
+    fn create_offer_for_hash(
+        &self,
+        payment_hash: sha256::Hash,
+        amount: Amount,
+        description: String,
+        expiry_secs: u32,
+    ) -> Result<String, LightningRpcError> {
+        tracked_call!(
+            self,
+            "create_offer_for_hash",
+            self.inner
+                .create_offer_for_hash(payment_hash, amount, description, expiry_secs)
+        )
+    }
+
     fn sync_wallet(&self) -> Result<(), LightningRpcError> {
         tracked_call!(self, "sync_wallet", self.inner.sync_wallet())
     }
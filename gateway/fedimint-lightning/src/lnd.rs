@@ -20,6 +20,7 @@
 use fedimint_ln_common::route_hints::{RouteHint, RouteHintHop};
 use fedimint_logging::LOG_LIGHTNING;
 use hex::ToHex;
+use lightning_invoice::Bolt11Invoice;
 use secp256k1::PublicKey;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
@@ -30,19 +31,21 @@
 };
 use tonic_lnd::lnrpc::channel_point::FundingTxid;
 use tonic_lnd::lnrpc::failure::FailureCode;
+use tonic_lnd::lnrpc::fee_limit;
+use tonic_lnd::lnrpc::htlc_attempt::HtlcStatus;
 use tonic_lnd::lnrpc::invoice::InvoiceState;
 use tonic_lnd::lnrpc::payment::PaymentStatus;
 use tonic_lnd::lnrpc::policy_update_request::Scope as PolicyUpdateScope;
 use tonic_lnd::lnrpc::{
     ChanInfoRequest, ChannelBalanceRequest, ChannelPoint, CloseChannelRequest,
-    ConnectPeerRequest as LndConnectPeerRequest, FeeReportRequest, GetInfoRequest, Invoice,
-    InvoiceSubscription, LightningAddress, ListChannelsRequest, ListInvoiceRequest,
-    ListPaymentsRequest, ListPeersRequest, OpenChannelRequest, PolicyUpdateRequest,
-    SendCoinsRequest, UpdateFailure, WalletBalanceRequest,
+    ConnectPeerRequest as LndConnectPeerRequest, FeeLimit, FeeReportRequest, GetInfoRequest,
+    Invoice, InvoiceSubscription, LightningAddress, ListChannelsRequest, ListInvoiceRequest,
+    ListPaymentsRequest, ListPeersRequest, MppRecord, NodePair, OpenChannelRequest,
+    PolicyUpdateRequest, QueryRoutesRequest, SendCoinsRequest, UpdateFailure, WalletBalanceRequest,
 };
 use tonic_lnd::routerrpc::{
     CircuitKey, ForwardHtlcInterceptResponse, ResolveHoldForwardAction, SendPaymentRequest,
-    TrackPaymentRequest,
+    SendToRouteRequest, TrackPaymentRequest,
 };
 use tonic_lnd::tonic::Code;
 use tonic_lnd::walletrpc::AddrRequest;
@@ -64,6 +67,9 @@
 
 type HtlcSubscriptionSender = mpsc::Sender<InterceptPaymentRequest>;
 
+/// How many routes we try for a part of a multi-path payment before giving up.
+const MAX_PART_ROUTE_ATTEMPTS: usize = 3;
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 enum HoldInvoiceAction {
     Complete,
@@ -1233,12 +1239,236 @@ async fn pay_private(
         })
     }
 
+    async fn pay_part(
+        &self,
+        invoice: Bolt11Invoice,
+        amount: Amount,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        let invoice =
+            PrunedInvoice::try_from(invoice).map_err(|_| LightningRpcError::FailedPayment {
+                failure_reason: "Invoice has no amount".to_string(),
+            })?;
+
+        let payment_hash = invoice.payment_hash.to_byte_array().to_vec();
+        info!(
+            target: LOG_LIGHTNING,
+            payment_hash = %PrettyPaymentHash(&payment_hash),
+            %amount,
+            "LND Paying part of invoice",
+        );
+        let mut client = self.connect().await?;
+
+        // If the payment exists, that means we've already tried to pay our part
+        if let Some(preimage) = self
+            .lookup_payment(payment_hash.clone(), &mut client)
+            .await?
+        {
+            info!(
+                target: LOG_LIGHTNING,
+                payment_hash = %PrettyPaymentHash(&payment_hash),
+                "LND payment already exists for invoice",
+            );
+            let preimage: Vec<u8> = hex::FromHex::from_hex(preimage.as_str()).map_err(|error| {
+                LightningRpcError::FailedPayment {
+                    failure_reason: format!("Failed to convert preimage {error:?}"),
+                }
+            })?;
+
+            return Ok(PayInvoiceResponse {
+                preimage: Preimage(preimage.try_into().expect("Failed to create preimage")),
+            });
+        }
+
+        let to_lnd_range = |value: u64, name: &str| {
+            i64::try_from(value).map_err(|error| LightningRpcError::FailedPayment {
+                failure_reason: format!("{name} exceeds valid LND range {error:?}"),
+            })
+        };
+
+        let amt_msat = to_lnd_range(amount.msats, "amount")?;
+        let total_amt_msat = to_lnd_range(invoice.amount.msats, "invoice amount")?;
+        let fee_limit_msat = to_lnd_range(max_fee.msats, "max_fee_msat")?;
+
+        let final_cltv_delta = invoice.min_final_cltv_delta.try_into().map_err(|error| {
+            LightningRpcError::FailedPayment {
+                failure_reason: format!("final cltv delta exceeds valid LND range {error:?}"),
+            }
+        })?;
+        let cltv_limit =
+            max_delay
+                .try_into()
+                .map_err(|error| LightningRpcError::FailedPayment {
+                    failure_reason: format!("max delay exceeds valid LND range {error:?}"),
+                })?;
+
+        let dest_features = wire_features_to_lnd_feature_vec(&invoice.destination_features)
+            .map_err(|e| LightningRpcError::FailedPayment {
+                failure_reason: e.to_string(),
+            })?;
+
+        // Node pairs whose channel failed to forward a previous attempt
+        let mut ignored_pairs = Vec::new();
+
+        let mut attempt_number = 0;
+
+        let attempt = loop {
+            attempt_number += 1;
+
+            // LND only sends the full amount of an invoice via `send_payment_v2`, so
+            // we find a route for our part ourselves and mark it as a shard of the
+            // full amount with an MPP record on the final hop.
+            let mut route = client
+                .lightning()
+                .query_routes(QueryRoutesRequest {
+                    pub_key: invoice.destination.serialize().encode_hex::<String>(),
+                    amt_msat,
+                    final_cltv_delta,
+                    fee_limit: Some(FeeLimit {
+                        limit: Some(fee_limit::Limit::FixedMsat(fee_limit_msat)),
+                    }),
+                    ignored_pairs: ignored_pairs.clone(),
+                    cltv_limit,
+                    route_hints: route_hints_to_lnd(&invoice.route_hints),
+                    dest_features: dest_features.clone(),
+                    use_mission_control: true,
+                    ..Default::default()
+                })
+                .await
+                .map_err(|status| LightningRpcError::FailedPayment {
+                    failure_reason: format!("Failed to find a route for the payment {status:?}"),
+                })?
+                .into_inner()
+                .routes
+                .into_iter()
+                .next()
+                .ok_or(LightningRpcError::FailedPayment {
+                    failure_reason: "No route found for the payment".to_string(),
+                })?;
+
+            route
+                .hops
+                .last_mut()
+                .ok_or(LightningRpcError::FailedPayment {
+                    failure_reason: "Route has no hops".to_string(),
+                })?
+                .mpp_record = Some(MppRecord {
+                payment_addr: invoice.payment_secret.to_vec(),
+                total_amt_msat,
+            });
+
+            let hops = route
+                .hops
+                .iter()
+                .map(|hop| hop.pub_key.clone())
+                .collect::<Vec<_>>();
+
+            debug!(
+                target: LOG_LIGHTNING,
+                payment_hash = %PrettyPaymentHash(&payment_hash),
+                attempt_number,
+                "LND sending part of payment, waiting for all parts to arrive...",
+            );
+
+            // The payee only settles our part once all parts have arrived
+            let attempt = client
+                .router()
+                .send_to_route_v2(SendToRouteRequest {
+                    payment_hash: payment_hash.clone(),
+                    route: Some(route),
+                    skip_temp_err: false,
+                })
+                .await
+                .map_err(|status| LightningRpcError::FailedPayment {
+                    failure_reason: format!("Failed to make outgoing payment {status:?}"),
+                })?
+                .into_inner();
+
+            if attempt.status() == HtlcStatus::Succeeded {
+                break attempt;
+            }
+
+            warn!(
+                target: LOG_LIGHTNING,
+                payment_hash = %PrettyPaymentHash(&payment_hash),
+                attempt_number,
+                failure = ?attempt.failure,
+                "LND payment of part failed",
+            );
+
+            // A failure reported by the payee is final, while a failure of an
+            // intermediate channel is retried over a route avoiding it.
+            let failure_source_index = attempt
+                .failure
+                .as_ref()
+                .map(|failure| failure.failure_source_index as usize)
+                .filter(|index| *index < hops.len());
+
+            let Some(failure_source_index) = failure_source_index else {
+                return Err(LightningRpcError::FailedPayment {
+                    failure_reason: format!("{:?}", attempt.failure),
+                });
+            };
+
+            if attempt_number >= MAX_PART_ROUTE_ATTEMPTS {
+                return Err(LightningRpcError::FailedPayment {
+                    failure_reason: format!(
+                        "No route succeeded after {attempt_number} attempts: {:?}",
+                        attempt.failure
+                    ),
+                });
+            }
+
+            let from = match failure_source_index {
+                0 => self
+                    .info()
+                    .await?
+                    .pub_key
+                    .serialize()
+                    .encode_hex::<String>(),
+                index => hops[index - 1].clone(),
+            };
+
+            ignored_pairs.push(NodePair {
+                from: hex::FromHex::from_hex(&from).map_err(|error| {
+                    LightningRpcError::FailedPayment {
+                        failure_reason: format!("Invalid node public key {error:?}"),
+                    }
+                })?,
+                to: hex::FromHex::from_hex(&hops[failure_source_index]).map_err(|error| {
+                    LightningRpcError::FailedPayment {
+                        failure_reason: format!("Invalid node public key {error:?}"),
+                    }
+                })?,
+            });
+        };
+
+        info!(
+            target: LOG_LIGHTNING,
+            payment_hash = %PrettyPaymentHash(&payment_hash),
+            "LND payment of part succeeded for invoice",
+        );
+
+        Ok(PayInvoiceResponse {
+            preimage: Preimage(attempt.preimage.try_into().map_err(|_| {
+                LightningRpcError::FailedPayment {
+                    failure_reason: "Invalid preimage length".to_string(),
+                }
+            })?),
+        })
+    }
+
     /// Returns true if the lightning backend supports payments without full
     /// invoices
     fn supports_private_payments(&self) -> bool {
         true
     }
 
+    fn supports_partial_payments(&self) -> bool {
+        true
+    }
+
     async fn route_htlcs<'a>(
         self: Box<Self>,
         task_group: &TaskGroup,
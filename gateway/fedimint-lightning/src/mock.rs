@@ -0,0 +1,1451 @@
+//! An in-process [`ILnRpcClient`] that simulates a small Lightning network
+//! around the gateway's node.
+//!
+//! [`MockLightningNetwork`] holds the simulated network: the gateway's node,
+//! its peers and channels, the invoices and offers issued on either side and
+//! the block height. Tests script the network's behaviour through it, e.g.
+//! which outgoing payments fail or stay in flight, and drive incoming payments
+//! through the gateway's HTLC interception. [`MockLightningNode`] is the
+//! gateway's view of the network and is handed to the gateway in place of an
+//! LND or LDK client.
+//!
+//! Keys and preimages are derived deterministically, so a scripted test
+//! produces the same network on every run.
+
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::{Address, CompressedPublicKey, Network, OutPoint, Txid};
+use fedimint_core::task::TaskGroup;
+use fedimint_core::time::{duration_since_epoch, now};
+use fedimint_core::{Amount, BitcoinAmountOrAll};
+use fedimint_gateway_common::{
+    ChannelInfo, CloseChannelsWithPeerRequest, CloseChannelsWithPeerResponse, ConnectPeerRequest,
+    GetInvoiceRequest, GetInvoiceResponse, ListTransactionsResponse, OpenChannelRequest,
+    PaymentDetails, PaymentDirection, PaymentKind, PaymentStatus, SendOnchainRequest,
+    SetChannelFeesRequest,
+};
+use fedimint_ln_common::PrunedInvoice;
+use fedimint_ln_common::route_hints::{RouteHint, RouteHintHop};
+use lightning::offers::offer::{Offer, OfferBuilder, OfferId};
+use lightning_invoice::{Bolt11Invoice, Currency, InvoiceBuilder, PaymentSecret};
+use tokio::sync::{Notify, mpsc, oneshot};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+use crate::{
+    CreateInvoiceRequest, CreateInvoiceResponse, GetBalancesResponse, GetLnOnchainAddressResponse,
+    GetNodeInfoResponse, GetRouteHintsResponse, ILnRpcClient, InterceptPaymentRequest,
+    InterceptPaymentResponse, InvoiceDescription, LightningRpcError, ListChannelsResponse,
+    NO_INCOMING_CIRCUIT, OpenChannelResponse, PayInvoiceResponse, PaymentAction, Preimage,
+    RouteHtlcStream, SendOnchainResponse,
+};
+
+/// The alias of the gateway's node in the simulated network.
+pub const MOCK_GATEWAY_ALIAS: &str = "mock-gateway";
+
+/// The CLTV expiry delta the simulated channels advertise in route hints.
+const MOCK_CLTV_EXPIRY_DELTA: u16 = 40;
+
+/// How an outgoing payment of the gateway behaves in the simulated network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentBehaviour {
+    /// The payment reaches the payee over a route with the given fee and CLTV
+    /// delta, provided both are within the limits of the payment and the
+    /// gateway has enough outbound liquidity.
+    Succeed { fee: Amount, cltv_delta: u64 },
+    /// No route to the payee is found.
+    Fail { failure_reason: String },
+    /// The payment stays in flight until it is released via
+    /// [`MockLightningNetwork::release_payment`].
+    Hold,
+}
+
+impl PaymentBehaviour {
+    /// A payment that succeeds without routing fees.
+    pub fn succeed() -> Self {
+        PaymentBehaviour::Succeed {
+            fee: Amount::ZERO,
+            cltv_delta: 0,
+        }
+    }
+
+    pub fn fail(failure_reason: impl Into<String>) -> Self {
+        PaymentBehaviour::Fail {
+            failure_reason: failure_reason.into(),
+        }
+    }
+}
+
+/// How the gateway resolved an HTLC that was sent to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HtlcResolution {
+    Settled(Preimage),
+    Cancelled,
+    Forwarded,
+    /// The HTLC expired before the gateway completed it.
+    TimedOut,
+}
+
+/// An HTLC sent to the gateway's node, which resolves once the gateway
+/// completes it or it times out.
+#[derive(Debug)]
+pub struct MockHtlc {
+    resolution: oneshot::Receiver<HtlcResolution>,
+}
+
+impl MockHtlc {
+    /// Waits for the HTLC to be resolved.
+    pub async fn resolution(self) -> HtlcResolution {
+        self.resolution.await.unwrap_or(HtlcResolution::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MockChannel {
+    short_channel_id: u64,
+    peer: PublicKey,
+    funding_outpoint: OutPoint,
+    local_msats: u64,
+    remote_msats: u64,
+    base_fee_msat: u64,
+    parts_per_million: u64,
+}
+
+#[derive(Debug, Clone)]
+struct MockInvoice {
+    amount: Amount,
+    /// `None` for a hold invoice, whose preimage the gateway only learns once
+    /// it settles the payment.
+    preimage: Option<Preimage>,
+    status: PaymentStatus,
+    created_at: SystemTime,
+}
+
+#[derive(Debug, Clone)]
+struct MockOffer {
+    issuer: PublicKey,
+    amount: Option<Amount>,
+    /// The payment hash a single-use offer of the gateway is bound to.
+    payment_hash: Option<sha256::Hash>,
+}
+
+#[derive(Debug, Clone)]
+enum OutgoingStatus {
+    InFlight,
+    Succeeded(Preimage),
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+struct OutgoingPayment {
+    amount: Amount,
+    max_delay: u64,
+    max_fee: Amount,
+    kind: PaymentKind,
+    status: OutgoingStatus,
+}
+
+#[derive(Debug)]
+struct PendingHtlc {
+    request: InterceptPaymentRequest,
+    incoming_short_channel_id: u64,
+    resolution: oneshot::Sender<HtlcResolution>,
+}
+
+#[derive(Debug)]
+struct MockNetworkState {
+    gateway_secret_key: SecretKey,
+    gateway_public_key: PublicKey,
+    block_height: u32,
+    onchain_balance_sats: u64,
+    peers: BTreeMap<PublicKey, Option<String>>,
+    channels: Vec<MockChannel>,
+    /// Preimages of the invoices and offers issued by peers.
+    peer_preimages: BTreeMap<sha256::Hash, Preimage>,
+    payee_behaviours: BTreeMap<PublicKey, PaymentBehaviour>,
+    payment_behaviours: BTreeMap<sha256::Hash, PaymentBehaviour>,
+    outgoing: BTreeMap<sha256::Hash, OutgoingPayment>,
+    invoices: BTreeMap<sha256::Hash, MockInvoice>,
+    offers: HashMap<OfferId, MockOffer>,
+    /// HTLCs intercepted as forwards, keyed by their incoming circuit.
+    pending_htlcs: BTreeMap<(u64, u64), PendingHtlc>,
+    /// HTLCs held by hold invoices of the gateway, keyed by payment hash.
+    held_htlcs: BTreeMap<sha256::Hash, PendingHtlc>,
+    htlc_sender: Option<mpsc::UnboundedSender<InterceptPaymentRequest>>,
+    transactions: Vec<PaymentDetails>,
+    next_htlc_id: u64,
+    next_nonce: u64,
+}
+
+/// A simulated Lightning network around the gateway's node.
+#[derive(Debug, Clone)]
+pub struct MockLightningNetwork {
+    state: Arc<Mutex<MockNetworkState>>,
+    /// Notified whenever an outgoing payment of the gateway is resolved.
+    payment_updates: Arc<Notify>,
+}
+
+impl Default for MockLightningNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockLightningNetwork {
+    pub fn new() -> Self {
+        let gateway_secret_key = derive_secret_key(MOCK_GATEWAY_ALIAS);
+
+        MockLightningNetwork {
+            state: Arc::new(Mutex::new(MockNetworkState {
+                gateway_secret_key,
+                gateway_public_key: gateway_secret_key.public_key(&Secp256k1::new()),
+                block_height: 0,
+                onchain_balance_sats: 0,
+                peers: BTreeMap::new(),
+                channels: Vec::new(),
+                peer_preimages: BTreeMap::new(),
+                payee_behaviours: BTreeMap::new(),
+                payment_behaviours: BTreeMap::new(),
+                outgoing: BTreeMap::new(),
+                invoices: BTreeMap::new(),
+                offers: HashMap::new(),
+                pending_htlcs: BTreeMap::new(),
+                held_htlcs: BTreeMap::new(),
+                htlc_sender: None,
+                transactions: Vec::new(),
+                next_htlc_id: 0,
+                next_nonce: 0,
+            })),
+            payment_updates: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Returns the gateway's node, which implements [`ILnRpcClient`].
+    pub fn gateway_node(&self) -> MockLightningNode {
+        MockLightningNode {
+            network: self.clone(),
+        }
+    }
+
+    pub fn gateway_public_key(&self) -> PublicKey {
+        self.state().gateway_public_key
+    }
+
+    pub fn block_height(&self) -> u32 {
+        self.state().block_height
+    }
+
+    /// Adds a peer node to the network whose key is derived from its alias.
+    pub fn add_peer(&self, alias: &str) -> PublicKey {
+        let public_key = derive_secret_key(alias).public_key(&Secp256k1::new());
+        self.state()
+            .peers
+            .insert(public_key, Some(alias.to_string()));
+        public_key
+    }
+
+    /// Opens a channel between the gateway and a peer with the given local and
+    /// remote balance, returning its short channel id.
+    pub fn open_channel(&self, peer: PublicKey, local: Amount, remote: Amount) -> u64 {
+        self.state().open_channel(peer, local.msats, remote.msats)
+    }
+
+    /// Credits the on-chain wallet of the gateway's node.
+    pub fn fund_onchain(&self, amount_sats: u64) {
+        self.state().onchain_balance_sats += amount_sats;
+    }
+
+    /// Sets how outgoing payments to the given payee behave. Payments to
+    /// known peers succeed without fees by default, while payments to
+    /// unknown nodes fail.
+    pub fn set_payee_behaviour(&self, payee: PublicKey, behaviour: PaymentBehaviour) {
+        self.state().payee_behaviours.insert(payee, behaviour);
+    }
+
+    /// Sets how the outgoing payment with the given payment hash behaves,
+    /// taking precedence over the behaviour of its payee.
+    pub fn set_payment_behaviour(&self, payment_hash: sha256::Hash, behaviour: PaymentBehaviour) {
+        self.state()
+            .payment_behaviours
+            .insert(payment_hash, behaviour);
+    }
+
+    /// Resolves an outgoing payment held in flight with the given behaviour.
+    /// Returns `false` if no payment with this payment hash is in flight.
+    pub fn release_payment(&self, payment_hash: sha256::Hash, behaviour: PaymentBehaviour) -> bool {
+        let released = {
+            let mut state = self.state();
+            match state.outgoing.get(&payment_hash) {
+                Some(payment) if matches!(payment.status, OutgoingStatus::InFlight) => {
+                    state.attempt_payment(payment_hash, &behaviour);
+                    true
+                }
+                _ => false,
+            }
+        };
+
+        self.payment_updates.notify_waiters();
+
+        released
+    }
+
+    /// Returns the preimage of an outgoing payment of the gateway if it has
+    /// succeeded.
+    pub fn payment_preimage(&self, payment_hash: sha256::Hash) -> Option<Preimage> {
+        match &self.state().outgoing.get(&payment_hash)?.status {
+            OutgoingStatus::Succeeded(preimage) => Some(preimage.clone()),
+            _ => None,
+        }
+    }
+
+    /// Creates an invoice of a peer that the gateway can pay. The peer must
+    /// have been added via [`Self::add_peer`].
+    pub fn peer_invoice(&self, peer: PublicKey, amount: Amount) -> Bolt11Invoice {
+        let mut state = self.state();
+        let preimage = state.next_preimage();
+        let payment_hash = sha256::Hash::hash(&preimage.0);
+        state.peer_preimages.insert(payment_hash, preimage);
+
+        let peer_alias = state
+            .peers
+            .get(&peer)
+            .cloned()
+            .flatten()
+            .expect("Peer must have been added by alias");
+
+        build_invoice(
+            &derive_secret_key(&peer_alias),
+            payment_hash,
+            amount.msats,
+            InvoiceDescription::Direct(String::new()),
+            3600,
+        )
+    }
+
+    /// Creates an offer of a peer that the gateway can pay.
+    pub fn peer_offer(&self, peer: PublicKey, amount: Option<Amount>) -> String {
+        let offer = build_offer(peer, amount, Some("Mock peer offer".to_string()), None)
+            .expect("Offers of peers are valid");
+
+        self.state().offers.insert(
+            offer.id(),
+            MockOffer {
+                issuer: peer,
+                amount,
+                payment_hash: None,
+            },
+        );
+
+        offer.to_string()
+    }
+
+    /// Pays an invoice created by the gateway's node. Payments for hold
+    /// invoices are intercepted by the gateway, while payments for regular
+    /// invoices settle immediately.
+    pub fn pay_gateway_invoice(
+        &self,
+        invoice: &Bolt11Invoice,
+    ) -> Result<MockHtlc, LightningRpcError> {
+        let amount = invoice
+            .amount_milli_satoshis()
+            .ok_or(LightningRpcError::FailedPayment {
+                failure_reason: "Invoice has no amount".to_string(),
+            })?;
+
+        self.state().receive(
+            *invoice.payment_hash(),
+            Amount::from_msats(amount),
+            invoice.min_final_cltv_expiry_delta(),
+        )
+    }
+
+    /// Pays an offer created by the gateway's node like
+    /// [`Self::pay_gateway_invoice`] pays an invoice.
+    pub fn pay_gateway_offer(
+        &self,
+        offer: &str,
+        amount: Option<Amount>,
+    ) -> Result<MockHtlc, LightningRpcError> {
+        let offer = parse_offer(offer)?;
+        let mut state = self.state();
+
+        let mock_offer = state
+            .offers
+            .get(&offer.id())
+            .filter(|mock_offer| mock_offer.issuer == state.gateway_public_key)
+            .cloned()
+            .ok_or(LightningRpcError::Bolt12Error {
+                failure_reason: "Unknown offer".to_string(),
+            })?;
+
+        let amount = mock_offer
+            .amount
+            .or(amount)
+            .ok_or(LightningRpcError::Bolt12Error {
+                failure_reason: "The offer requires an amount".to_string(),
+            })?;
+
+        let payment_hash = match mock_offer.payment_hash {
+            Some(payment_hash) => payment_hash,
+            None => {
+                let preimage = state.next_preimage();
+                let payment_hash = sha256::Hash::hash(&preimage.0);
+                state.invoices.insert(
+                    payment_hash,
+                    MockInvoice {
+                        amount,
+                        preimage: Some(preimage),
+                        status: PaymentStatus::Pending,
+                        created_at: now(),
+                    },
+                );
+                payment_hash
+            }
+        };
+
+        state.receive(payment_hash, amount, u64::from(MOCK_CLTV_EXPIRY_DELTA))
+    }
+
+    /// Sends an HTLC over an inbound channel of the gateway that the gateway
+    /// intercepts as a forward to the given short channel id.
+    pub fn forward_htlc(
+        &self,
+        payment_hash: sha256::Hash,
+        amount: Amount,
+        short_channel_id: u64,
+        expiry_delta: u32,
+    ) -> Result<MockHtlc, LightningRpcError> {
+        let mut state = self.state();
+        let incoming_short_channel_id = state.inbound_channel(amount)?;
+        let htlc_id = state.next_htlc_id;
+        state.next_htlc_id += 1;
+
+        let request = InterceptPaymentRequest {
+            payment_hash,
+            amount_msat: amount.msats,
+            expiry: state.block_height + expiry_delta,
+            incoming_chan_id: incoming_short_channel_id,
+            short_channel_id: Some(short_channel_id),
+            htlc_id,
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        state.intercept(request.clone())?;
+        state.pending_htlcs.insert(
+            (incoming_short_channel_id, htlc_id),
+            PendingHtlc {
+                request,
+                incoming_short_channel_id,
+                resolution: sender,
+            },
+        );
+
+        Ok(MockHtlc {
+            resolution: receiver,
+        })
+    }
+
+    /// Mines blocks, timing out every HTLC whose expiry has been reached
+    /// before the gateway completed it.
+    pub fn mine_blocks(&self, num_blocks: u32) {
+        let mut state = self.state();
+        state.block_height += num_blocks;
+        let block_height = state.block_height;
+
+        let expired_circuits = state
+            .pending_htlcs
+            .iter()
+            .filter(|(_, htlc)| htlc.request.expiry <= block_height)
+            .map(|(circuit, _)| *circuit)
+            .collect::<Vec<_>>();
+
+        for circuit in expired_circuits {
+            if let Some(htlc) = state.pending_htlcs.remove(&circuit) {
+                let _ = htlc.resolution.send(HtlcResolution::TimedOut);
+            }
+        }
+
+        let expired_hashes = state
+            .held_htlcs
+            .iter()
+            .filter(|(_, htlc)| htlc.request.expiry <= block_height)
+            .map(|(payment_hash, _)| *payment_hash)
+            .collect::<Vec<_>>();
+
+        for payment_hash in expired_hashes {
+            if let Some(htlc) = state.held_htlcs.remove(&payment_hash) {
+                if let Some(invoice) = state.invoices.get_mut(&payment_hash) {
+                    invoice.status = PaymentStatus::Failed;
+                }
+                let _ = htlc.resolution.send(HtlcResolution::TimedOut);
+            }
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockNetworkState> {
+        self.state.lock().expect("Mock network lock poisoned")
+    }
+
+    async fn send_payment(
+        &self,
+        payee: PublicKey,
+        payment_hash: sha256::Hash,
+        amount: Amount,
+        max_delay: u64,
+        max_fee: Amount,
+        kind: PaymentKind,
+    ) -> Result<Preimage, LightningRpcError> {
+        {
+            let mut state = self.state();
+
+            // Payments are idempotent per payment hash, a repeated call waits
+            // for the outcome of the first one.
+            if !state.outgoing.contains_key(&payment_hash) {
+                let behaviour = state.behaviour_for(payee, payment_hash);
+                state.outgoing.insert(
+                    payment_hash,
+                    OutgoingPayment {
+                        amount,
+                        max_delay,
+                        max_fee,
+                        kind,
+                        status: OutgoingStatus::InFlight,
+                    },
+                );
+                state.attempt_payment(payment_hash, &behaviour);
+            }
+        }
+
+        self.payment_updates.notify_waiters();
+
+        loop {
+            let mut notified = std::pin::pin!(self.payment_updates.notified());
+            notified.as_mut().enable();
+
+            let status = self
+                .state()
+                .outgoing
+                .get(&payment_hash)
+                .map(|payment| payment.status.clone());
+
+            match status {
+                Some(OutgoingStatus::Succeeded(preimage)) => return Ok(preimage),
+                Some(OutgoingStatus::Failed(failure_reason)) => {
+                    return Err(LightningRpcError::FailedPayment { failure_reason });
+                }
+                Some(OutgoingStatus::InFlight) => notified.await,
+                None => {
+                    return Err(LightningRpcError::FailedPayment {
+                        failure_reason: "Payment is unknown".to_string(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl MockNetworkState {
+    fn next_nonce(&mut self) -> u64 {
+        let nonce = self.next_nonce;
+        self.next_nonce += 1;
+        nonce
+    }
+
+    fn next_preimage(&mut self) -> Preimage {
+        let nonce = self.next_nonce();
+        Preimage(derive_bytes("preimage", nonce))
+    }
+
+    fn open_channel(&mut self, peer: PublicKey, local_msats: u64, remote_msats: u64) -> u64 {
+        self.peers.entry(peer).or_insert(None);
+
+        let nonce = self.next_nonce();
+        // Short channel ids are never zero, see `NO_INCOMING_CIRCUIT`.
+        let short_channel_id = nonce + 1;
+
+        self.channels.push(MockChannel {
+            short_channel_id,
+            peer,
+            funding_outpoint: OutPoint {
+                txid: Txid::from_byte_array(derive_bytes("funding", nonce)),
+                vout: 0,
+            },
+            local_msats,
+            remote_msats,
+            base_fee_msat: 0,
+            parts_per_million: 0,
+        });
+
+        short_channel_id
+    }
+
+    fn behaviour_for(&self, payee: PublicKey, payment_hash: sha256::Hash) -> PaymentBehaviour {
+        if let Some(behaviour) = self.payment_behaviours.get(&payment_hash) {
+            return behaviour.clone();
+        }
+
+        if let Some(behaviour) = self.payee_behaviours.get(&payee) {
+            return behaviour.clone();
+        }
+
+        if self.peers.contains_key(&payee) {
+            PaymentBehaviour::succeed()
+        } else {
+            PaymentBehaviour::fail(format!("No route to {payee}"))
+        }
+    }
+
+    /// Resolves an outgoing payment that is in flight according to the given
+    /// behaviour, leaving it in flight if the behaviour is to hold it.
+    fn attempt_payment(&mut self, payment_hash: sha256::Hash, behaviour: &PaymentBehaviour) {
+        let payment = self
+            .outgoing
+            .get(&payment_hash)
+            .cloned()
+            .expect("Payment must be in flight");
+
+        let status = match behaviour {
+            PaymentBehaviour::Hold => return,
+            PaymentBehaviour::Fail { failure_reason } => {
+                OutgoingStatus::Failed(failure_reason.clone())
+            }
+            PaymentBehaviour::Succeed { fee, cltv_delta } => {
+                match self.route_payment(payment_hash, &payment, *fee, *cltv_delta) {
+                    Ok(preimage) => OutgoingStatus::Succeeded(preimage),
+                    Err(failure_reason) => OutgoingStatus::Failed(failure_reason),
+                }
+            }
+        };
+
+        let (preimage, transaction_status) = match &status {
+            OutgoingStatus::Succeeded(preimage) => {
+                (Some(preimage.clone()), PaymentStatus::Succeeded)
+            }
+            _ => (None, PaymentStatus::Failed),
+        };
+
+        self.transactions.push(PaymentDetails {
+            payment_hash: Some(payment_hash),
+            preimage: preimage.map(|preimage| hex::encode(preimage.0)),
+            payment_kind: payment.kind.clone(),
+            amount: payment.amount,
+            direction: PaymentDirection::Outbound,
+            status: transaction_status,
+            timestamp_secs: duration_since_epoch().as_secs(),
+        });
+
+        self.outgoing
+            .get_mut(&payment_hash)
+            .expect("Payment must be in flight")
+            .status = status;
+    }
+
+    fn route_payment(
+        &mut self,
+        payment_hash: sha256::Hash,
+        payment: &OutgoingPayment,
+        fee: Amount,
+        cltv_delta: u64,
+    ) -> Result<Preimage, String> {
+        if cltv_delta > payment.max_delay {
+            return Err(format!(
+                "Route requires a delay of {cltv_delta} blocks, exceeding the limit of {}",
+                payment.max_delay
+            ));
+        }
+
+        if fee > payment.max_fee {
+            return Err(format!(
+                "Route requires a fee of {fee}, exceeding the limit of {}",
+                payment.max_fee
+            ));
+        }
+
+        let preimage = self
+            .peer_preimages
+            .get(&payment_hash)
+            .cloned()
+            .ok_or("Payee rejected the payment: unknown payment hash".to_string())?;
+
+        let total_msats = payment.amount.msats + fee.msats;
+        let channel = self
+            .channels
+            .iter_mut()
+            .find(|channel| total_msats <= channel.local_msats)
+            .ok_or("Insufficient outbound liquidity".to_string())?;
+
+        channel.local_msats -= total_msats;
+        channel.remote_msats += total_msats;
+
+        Ok(preimage)
+    }
+
+    /// Returns the short channel id of a channel with enough inbound liquidity
+    /// to receive the amount.
+    fn inbound_channel(&self, amount: Amount) -> Result<u64, LightningRpcError> {
+        self.channels
+            .iter()
+            .find(|channel| amount.msats <= channel.remote_msats)
+            .map(|channel| channel.short_channel_id)
+            .ok_or(LightningRpcError::FailedPayment {
+                failure_reason: "Insufficient inbound liquidity".to_string(),
+            })
+    }
+
+    fn intercept(&self, request: InterceptPaymentRequest) -> Result<(), LightningRpcError> {
+        self.htlc_sender
+            .as_ref()
+            .and_then(|sender| sender.send(request).ok())
+            .ok_or(LightningRpcError::FailedToRouteHtlcs {
+                failure_reason: "The gateway is not intercepting HTLCs".to_string(),
+            })
+    }
+
+    fn receive(
+        &mut self,
+        payment_hash: sha256::Hash,
+        amount: Amount,
+        expiry_delta: u64,
+    ) -> Result<MockHtlc, LightningRpcError> {
+        let invoice =
+            self.invoices
+                .get(&payment_hash)
+                .cloned()
+                .ok_or(LightningRpcError::FailedPayment {
+                    failure_reason: "Unknown invoice".to_string(),
+                })?;
+
+        if invoice.status != PaymentStatus::Pending || self.held_htlcs.contains_key(&payment_hash) {
+            return Err(LightningRpcError::FailedPayment {
+                failure_reason: "Invoice has already been paid".to_string(),
+            });
+        }
+
+        if amount < invoice.amount {
+            return Err(LightningRpcError::FailedPayment {
+                failure_reason: "Amount is less than the invoice amount".to_string(),
+            });
+        }
+
+        let incoming_short_channel_id = self.inbound_channel(amount)?;
+        let (sender, receiver) = oneshot::channel();
+
+        match invoice.preimage {
+            Some(preimage) => {
+                self.settle(
+                    payment_hash,
+                    incoming_short_channel_id,
+                    amount,
+                    preimage.clone(),
+                );
+                let _ = sender.send(HtlcResolution::Settled(preimage));
+            }
+            None => {
+                let (incoming_chan_id, htlc_id) = NO_INCOMING_CIRCUIT;
+                let request = InterceptPaymentRequest {
+                    payment_hash,
+                    amount_msat: amount.msats,
+                    expiry: self.block_height
+                        + u32::try_from(expiry_delta).expect("Expiry delta fits into u32"),
+                    incoming_chan_id,
+                    short_channel_id: None,
+                    htlc_id,
+                };
+
+                self.intercept(request.clone())?;
+                self.held_htlcs.insert(
+                    payment_hash,
+                    PendingHtlc {
+                        request,
+                        incoming_short_channel_id,
+                        resolution: sender,
+                    },
+                );
+            }
+        }
+
+        Ok(MockHtlc {
+            resolution: receiver,
+        })
+    }
+
+    fn settle(
+        &mut self,
+        payment_hash: sha256::Hash,
+        incoming_short_channel_id: u64,
+        amount: Amount,
+        preimage: Preimage,
+    ) {
+        if let Some(channel) = self
+            .channels
+            .iter_mut()
+            .find(|channel| channel.short_channel_id == incoming_short_channel_id)
+        {
+            channel.remote_msats -= amount.msats;
+            channel.local_msats += amount.msats;
+        }
+
+        if let Some(invoice) = self.invoices.get_mut(&payment_hash) {
+            invoice.preimage = Some(preimage.clone());
+            invoice.status = PaymentStatus::Succeeded;
+        }
+
+        self.transactions.push(PaymentDetails {
+            payment_hash: Some(payment_hash),
+            preimage: Some(hex::encode(preimage.0)),
+            payment_kind: PaymentKind::Bolt11,
+            amount,
+            direction: PaymentDirection::Inbound,
+            status: PaymentStatus::Succeeded,
+            timestamp_secs: duration_since_epoch().as_secs(),
+        });
+    }
+}
+
+/// The gateway's node in a [`MockLightningNetwork`].
+#[derive(Debug, Clone)]
+pub struct MockLightningNode {
+    network: MockLightningNetwork,
+}
+
+impl MockLightningNode {
+    pub fn network(&self) -> &MockLightningNetwork {
+        &self.network
+    }
+}
+
+#[async_trait]
+impl ILnRpcClient for MockLightningNode {
+    async fn info(&self) -> Result<GetNodeInfoResponse, LightningRpcError> {
+        let state = self.network.state();
+
+        Ok(GetNodeInfoResponse {
+            pub_key: state.gateway_public_key,
+            alias: MOCK_GATEWAY_ALIAS.to_string(),
+            network: Network::Regtest.to_string(),
+            block_height: state.block_height,
+            synced_to_chain: true,
+        })
+    }
+
+    async fn routehints(
+        &self,
+        num_route_hints: usize,
+    ) -> Result<GetRouteHintsResponse, LightningRpcError> {
+        let mut channels = self.network.state().channels.clone();
+        channels.sort_by_key(|channel| std::cmp::Reverse(channel.remote_msats));
+
+        let route_hints = channels
+            .into_iter()
+            .take(num_route_hints)
+            .map(|channel| {
+                RouteHint(vec![RouteHintHop {
+                    src_node_id: channel.peer,
+                    short_channel_id: channel.short_channel_id,
+                    base_msat: u32::try_from(channel.base_fee_msat).unwrap_or(u32::MAX),
+                    proportional_millionths: u32::try_from(channel.parts_per_million)
+                        .unwrap_or(u32::MAX),
+                    cltv_expiry_delta: MOCK_CLTV_EXPIRY_DELTA,
+                    htlc_minimum_msat: None,
+                    htlc_maximum_msat: None,
+                }])
+            })
+            .collect();
+
+        Ok(GetRouteHintsResponse { route_hints })
+    }
+
+    async fn pay_private(
+        &self,
+        invoice: PrunedInvoice,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        if invoice.expiry_timestamp < duration_since_epoch().as_secs() {
+            return Err(LightningRpcError::FailedPayment {
+                failure_reason: "Invoice has expired".to_string(),
+            });
+        }
+
+        let preimage = self
+            .network
+            .send_payment(
+                invoice.destination,
+                invoice.payment_hash,
+                invoice.amount,
+                max_delay,
+                max_fee,
+                PaymentKind::Bolt11,
+            )
+            .await?;
+
+        Ok(PayInvoiceResponse { preimage })
+    }
+
+    async fn pay_part(
+        &self,
+        invoice: Bolt11Invoice,
+        amount: Amount,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        if invoice.is_expired() {
+            return Err(LightningRpcError::FailedPayment {
+                failure_reason: "Invoice has expired".to_string(),
+            });
+        }
+
+        // The mock network does not model multi-path payments, every node pays its
+        // part as an individual payment to the payee.
+        let preimage = self
+            .network
+            .send_payment(
+                invoice.recover_payee_pub_key(),
+                *invoice.payment_hash(),
+                amount,
+                max_delay,
+                max_fee,
+                PaymentKind::Bolt11,
+            )
+            .await?;
+
+        Ok(PayInvoiceResponse { preimage })
+    }
+
+    fn supports_private_payments(&self) -> bool {
+        true
+    }
+
+    fn supports_partial_payments(&self) -> bool {
+        true
+    }
+
+    fn supports_bolt12_payments(&self) -> bool {
+        true
+    }
+
+    async fn route_htlcs<'a>(
+        self: Box<Self>,
+        _task_group: &TaskGroup,
+    ) -> Result<(RouteHtlcStream<'a>, Arc<dyn ILnRpcClient>), LightningRpcError> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.network.state().htlc_sender = Some(sender);
+
+        Ok((
+            Box::pin(UnboundedReceiverStream::new(receiver)),
+            Arc::new(*self),
+        ))
+    }
+
+    async fn complete_htlc(&self, htlc: InterceptPaymentResponse) -> Result<(), LightningRpcError> {
+        let mut state = self.network.state();
+
+        let pending = match htlc.incoming_circuit() {
+            Some(circuit) => state.pending_htlcs.remove(&circuit),
+            None => state.held_htlcs.remove(&htlc.payment_hash),
+        }
+        .ok_or(LightningRpcError::FailedToCompleteHtlc {
+            failure_reason: "No pending HTLC, it may have timed out".to_string(),
+        })?;
+
+        let resolution = match &htlc.action {
+            _ if pending.request.payment_hash != htlc.payment_hash => {
+                Err("Payment hash does not match the pending HTLC")
+            }
+            PaymentAction::Settle(preimage)
+                if sha256::Hash::hash(&preimage.0) == htlc.payment_hash =>
+            {
+                Ok(HtlcResolution::Settled(preimage.clone()))
+            }
+            PaymentAction::Settle(_) => Err("Preimage does not match the payment hash"),
+            PaymentAction::Cancel => Ok(HtlcResolution::Cancelled),
+            PaymentAction::Forward if htlc.incoming_circuit().is_some() => {
+                Ok(HtlcResolution::Forwarded)
+            }
+            PaymentAction::Forward => Err("HTLCs held by a hold invoice cannot be forwarded"),
+        };
+
+        let resolution = match resolution {
+            Ok(resolution) => resolution,
+            Err(failure_reason) => {
+                // The HTLC stays pending, as it would on a real node.
+                match htlc.incoming_circuit() {
+                    Some(circuit) => state.pending_htlcs.insert(circuit, pending),
+                    None => state.held_htlcs.insert(htlc.payment_hash, pending),
+                };
+
+                return Err(LightningRpcError::FailedToCompleteHtlc {
+                    failure_reason: failure_reason.to_string(),
+                });
+            }
+        };
+
+        match &resolution {
+            HtlcResolution::Settled(preimage) => state.settle(
+                htlc.payment_hash,
+                pending.incoming_short_channel_id,
+                Amount::from_msats(pending.request.amount_msat),
+                preimage.clone(),
+            ),
+            HtlcResolution::Cancelled => {
+                if let Some(invoice) = state.invoices.get_mut(&htlc.payment_hash) {
+                    invoice.status = PaymentStatus::Failed;
+                }
+            }
+            HtlcResolution::Forwarded | HtlcResolution::TimedOut => {}
+        }
+
+        let _ = pending.resolution.send(resolution);
+
+        Ok(())
+    }
+
+    async fn create_invoice(
+        &self,
+        create_invoice_request: CreateInvoiceRequest,
+    ) -> Result<CreateInvoiceResponse, LightningRpcError> {
+        let mut state = self.network.state();
+
+        // An invoice for a given payment hash is a hold invoice, since only the
+        // federation knows its preimage.
+        let (payment_hash, preimage) = match create_invoice_request.payment_hash {
+            Some(payment_hash) => (payment_hash, None),
+            None => {
+                let preimage = state.next_preimage();
+                (sha256::Hash::hash(&preimage.0), Some(preimage))
+            }
+        };
+
+        if state.invoices.contains_key(&payment_hash) {
+            return Err(LightningRpcError::FailedToGetInvoice {
+                failure_reason: "An invoice for this payment hash already exists".to_string(),
+            });
+        }
+
+        let invoice = build_invoice(
+            &state.gateway_secret_key,
+            payment_hash,
+            create_invoice_request.amount_msat,
+            create_invoice_request
+                .description
+                .unwrap_or(InvoiceDescription::Direct(String::new())),
+            create_invoice_request.expiry_secs,
+        );
+
+        state.invoices.insert(
+            payment_hash,
+            MockInvoice {
+                amount: Amount::from_msats(create_invoice_request.amount_msat),
+                preimage,
+                status: PaymentStatus::Pending,
+                created_at: now(),
+            },
+        );
+
+        Ok(CreateInvoiceResponse {
+            invoice: invoice.to_string(),
+        })
+    }
+
+    async fn get_ln_onchain_address(
+        &self,
+    ) -> Result<GetLnOnchainAddressResponse, LightningRpcError> {
+        let public_key = self.network.state().gateway_public_key;
+
+        Ok(GetLnOnchainAddressResponse {
+            address: Address::p2wpkh(&CompressedPublicKey(public_key), Network::Regtest)
+                .to_string(),
+        })
+    }
+
+    async fn send_onchain(
+        &self,
+        payload: SendOnchainRequest,
+    ) -> Result<SendOnchainResponse, LightningRpcError> {
+        let mut state = self.network.state();
+
+        let amount_sats = match payload.amount {
+            BitcoinAmountOrAll::All => state.onchain_balance_sats,
+            BitcoinAmountOrAll::Amount(amount) => amount.to_sat(),
+        };
+
+        if state.onchain_balance_sats < amount_sats {
+            return Err(LightningRpcError::FailedToWithdrawOnchain {
+                failure_reason: "Insufficient on-chain funds".to_string(),
+            });
+        }
+
+        state.onchain_balance_sats -= amount_sats;
+
+        let nonce = state.next_nonce();
+        let txid = Txid::from_byte_array(derive_bytes("withdrawal", nonce));
+
+        state.transactions.push(PaymentDetails {
+            payment_hash: None,
+            preimage: None,
+            payment_kind: PaymentKind::Onchain,
+            amount: Amount::from_sats(amount_sats),
+            direction: PaymentDirection::Outbound,
+            status: PaymentStatus::Succeeded,
+            timestamp_secs: duration_since_epoch().as_secs(),
+        });
+
+        Ok(SendOnchainResponse {
+            txid: txid.to_string(),
+        })
+    }
+
+    async fn open_channel(
+        &self,
+        payload: OpenChannelRequest,
+    ) -> Result<OpenChannelResponse, LightningRpcError> {
+        let mut state = self.network.state();
+
+        if state.onchain_balance_sats < payload.channel_size_sats
+            || payload.channel_size_sats < payload.push_amount_sats
+        {
+            return Err(LightningRpcError::FailedToOpenChannel {
+                failure_reason: "Insufficient on-chain funds".to_string(),
+            });
+        }
+
+        state.onchain_balance_sats -= payload.channel_size_sats;
+
+        let short_channel_id = state.open_channel(
+            payload.pubkey,
+            (payload.channel_size_sats - payload.push_amount_sats) * 1000,
+            payload.push_amount_sats * 1000,
+        );
+
+        let channel = state
+            .channels
+            .iter_mut()
+            .find(|channel| channel.short_channel_id == short_channel_id)
+            .expect("Channel was just opened");
+
+        channel.base_fee_msat = payload.base_fee_msat.unwrap_or_default();
+        channel.parts_per_million = payload.parts_per_million.unwrap_or_default();
+
+        Ok(OpenChannelResponse {
+            funding_txid: channel.funding_outpoint.txid.to_string(),
+        })
+    }
+
+    async fn connect_peer(&self, payload: ConnectPeerRequest) -> Result<(), LightningRpcError> {
+        self.network
+            .state()
+            .peers
+            .entry(payload.node_address.pubkey)
+            .or_insert(None);
+
+        Ok(())
+    }
+
+    async fn close_channels_with_peer(
+        &self,
+        payload: CloseChannelsWithPeerRequest,
+    ) -> Result<CloseChannelsWithPeerResponse, LightningRpcError> {
+        let mut state = self.network.state();
+
+        let (closed, open): (Vec<_>, Vec<_>) = std::mem::take(&mut state.channels)
+            .into_iter()
+            .partition(|channel| channel.peer == payload.pubkey);
+
+        state.channels = open;
+        state.onchain_balance_sats += closed
+            .iter()
+            .map(|channel| channel.local_msats / 1000)
+            .sum::<u64>();
+
+        Ok(CloseChannelsWithPeerResponse {
+            num_channels_closed: u32::try_from(closed.len()).unwrap_or(u32::MAX),
+        })
+    }
+
+    async fn list_channels(&self) -> Result<ListChannelsResponse, LightningRpcError> {
+        let state = self.network.state();
+
+        let channels = state
+            .channels
+            .iter()
+            .map(|channel| ChannelInfo {
+                remote_pubkey: channel.peer,
+                channel_size_sats: (channel.local_msats + channel.remote_msats) / 1000,
+                outbound_liquidity_sats: channel.local_msats / 1000,
+                inbound_liquidity_sats: channel.remote_msats / 1000,
+                is_active: true,
+                funding_outpoint: Some(channel.funding_outpoint),
+                remote_node_alias: state.peers.get(&channel.peer).cloned().flatten(),
+                remote_address: None,
+                base_fee_msat: Some(channel.base_fee_msat),
+                parts_per_million: Some(channel.parts_per_million),
+            })
+            .collect();
+
+        Ok(ListChannelsResponse { channels })
+    }
+
+    async fn set_channel_fees(
+        &self,
+        payload: SetChannelFeesRequest,
+    ) -> Result<(), LightningRpcError> {
+        let mut state = self.network.state();
+
+        let channel = state
+            .channels
+            .iter_mut()
+            .find(|channel| channel.funding_outpoint == payload.funding_outpoint)
+            .ok_or(LightningRpcError::FailedToSetChannelFees {
+                failure_reason: "Unknown channel".to_string(),
+            })?;
+
+        channel.base_fee_msat = payload.base_fee_msat;
+        channel.parts_per_million = payload.parts_per_million;
+
+        Ok(())
+    }
+
+    async fn get_balances(&self) -> Result<GetBalancesResponse, LightningRpcError> {
+        let state = self.network.state();
+
+        Ok(GetBalancesResponse {
+            onchain_balance_sats: state.onchain_balance_sats,
+            lightning_balance_msats: state.channels.iter().map(|c| c.local_msats).sum(),
+            inbound_lightning_liquidity_msats: state.channels.iter().map(|c| c.remote_msats).sum(),
+        })
+    }
+
+    async fn get_invoice(
+        &self,
+        get_invoice_request: GetInvoiceRequest,
+    ) -> Result<Option<GetInvoiceResponse>, LightningRpcError> {
+        let state = self.network.state();
+
+        Ok(state
+            .invoices
+            .get(&get_invoice_request.payment_hash)
+            .map(|invoice| GetInvoiceResponse {
+                preimage: invoice
+                    .preimage
+                    .as_ref()
+                    .map(|preimage| hex::encode(preimage.0)),
+                payment_hash: Some(get_invoice_request.payment_hash),
+                amount: invoice.amount,
+                created_at: invoice.created_at,
+                status: invoice.status.clone(),
+            }))
+    }
+
+    async fn list_transactions(
+        &self,
+        start_secs: u64,
+        end_secs: u64,
+    ) -> Result<ListTransactionsResponse, LightningRpcError> {
+        let transactions = self
+            .network
+            .state()
+            .transactions
+            .iter()
+            .filter(|transaction| (start_secs..end_secs).contains(&transaction.timestamp_secs))
+            .cloned()
+            .collect();
+
+        Ok(ListTransactionsResponse { transactions })
+    }
+
+    fn create_offer(
+        &self,
+        amount: Option<Amount>,
+        description: Option<String>,
+        expiry_secs: Option<u32>,
+        _quantity: Option<u64>,
+    ) -> Result<String, LightningRpcError> {
+        let mut state = self.network.state();
+        let offer = build_offer(state.gateway_public_key, amount, description, expiry_secs)?;
+
+        state.offers.insert(
+            offer.id(),
+            MockOffer {
+                issuer: state.gateway_public_key,
+                amount,
+                payment_hash: None,
+            },
+        );
+
+        Ok(offer.to_string())
+    }
+
+    async fn pay_offer(
+        &self,
+        offer: String,
+        _quantity: Option<u64>,
+        amount: Option<Amount>,
+        _payer_note: Option<String>,
+    ) -> Result<Preimage, LightningRpcError> {
+        let offer = parse_offer(&offer)?;
+
+        let (issuer, payment_hash, amount) =
+            {
+                let mut state = self.network.state();
+
+                let mock_offer = state.offers.get(&offer.id()).cloned().ok_or(
+                    LightningRpcError::Bolt12Error {
+                        failure_reason: "The offer's issuer did not respond".to_string(),
+                    },
+                )?;
+
+                let amount =
+                    mock_offer
+                        .amount
+                        .or(amount)
+                        .ok_or(LightningRpcError::Bolt12Error {
+                            failure_reason: "The offer requires an amount".to_string(),
+                        })?;
+
+                let preimage = state.next_preimage();
+                let payment_hash = sha256::Hash::hash(&preimage.0);
+                state.peer_preimages.insert(payment_hash, preimage);
+
+                (mock_offer.issuer, payment_hash, amount)
+            };
+
+        self.network
+            .send_payment(
+                issuer,
+                payment_hash,
+                amount,
+                u64::MAX,
+                Amount::from_msats(u64::MAX),
+                PaymentKind::Bolt12Offer,
+            )
+            .await
+    }
+
+    fn create_offer_for_hash(
+        &self,
+        payment_hash: sha256::Hash,
+        amount: Amount,
+        description: String,
+        expiry_secs: u32,
+    ) -> Result<String, LightningRpcError> {
+        let mut state = self.network.state();
+
+        if state.invoices.contains_key(&payment_hash) {
+            return Err(LightningRpcError::Bolt12Error {
+                failure_reason: "An offer for this payment hash already exists".to_string(),
+            });
+        }
+
+        let offer = build_offer(
+            state.gateway_public_key,
+            Some(amount),
+            Some(description),
+            Some(expiry_secs),
+        )?;
+
+        state.offers.insert(
+            offer.id(),
+            MockOffer {
+                issuer: state.gateway_public_key,
+                amount: Some(amount),
+                payment_hash: Some(payment_hash),
+            },
+        );
+        state.invoices.insert(
+            payment_hash,
+            MockInvoice {
+                amount,
+                preimage: None,
+                status: PaymentStatus::Pending,
+                created_at: now(),
+            },
+        );
+
+        Ok(offer.to_string())
+    }
+
+    fn sync_wallet(&self) -> Result<(), LightningRpcError> {
+        Ok(())
+    }
+}
+
+fn derive_bytes(domain: &str, nonce: u64) -> [u8; 32] {
+    sha256::Hash::hash(format!("fedimint-lightning-mock/{domain}/{nonce}").as_bytes())
+        .to_byte_array()
+}
+
+fn derive_secret_key(alias: &str) -> SecretKey {
+    SecretKey::from_slice(
+        &sha256::Hash::hash(format!("fedimint-lightning-mock/node/{alias}").as_bytes())
+            .to_byte_array(),
+    )
+    .expect("Hash is a valid secret key")
+}
+
+fn build_invoice(
+    secret_key: &SecretKey,
+    payment_hash: sha256::Hash,
+    amount_msat: u64,
+    description: InvoiceDescription,
+    expiry_secs: u32,
+) -> Bolt11Invoice {
+    let builder = InvoiceBuilder::new(Currency::Regtest);
+
+    let builder = match description {
+        InvoiceDescription::Direct(description) => builder.description(description),
+        InvoiceDescription::Hash(hash) => builder.description_hash(hash),
+    };
+
+    builder
+        .payment_hash(payment_hash)
+        .current_timestamp()
+        .min_final_cltv_expiry_delta(u64::from(MOCK_CLTV_EXPIRY_DELTA))
+        .payment_secret(PaymentSecret(derive_bytes(
+            "payment-secret",
+            u64::from_be_bytes(
+                payment_hash.to_byte_array()[..8]
+                    .try_into()
+                    .expect("Slice has eight bytes"),
+            ),
+        )))
+        .amount_milli_satoshis(amount_msat)
+        .expiry_time(Duration::from_secs(u64::from(expiry_secs)))
+        .build_signed(|message| Secp256k1::new().sign_ecdsa_recoverable(message, secret_key))
+        .expect("Invoice is valid")
+}
+
+fn build_offer(
+    issuer: PublicKey,
+    amount: Option<Amount>,
+    description: Option<String>,
+    expiry_secs: Option<u32>,
+) -> Result<Offer, LightningRpcError> {
+    let mut builder = OfferBuilder::new(issuer).description(description.unwrap_or_default());
+
+    if let Some(amount) = amount {
+        builder = builder.amount_msats(amount.msats);
+    }
+
+    if let Some(expiry_secs) = expiry_secs {
+        builder = builder
+            .absolute_expiry(duration_since_epoch() + Duration::from_secs(u64::from(expiry_secs)));
+    }
+
+    builder.build().map_err(|e| LightningRpcError::Bolt12Error {
+        failure_reason: format!("Failed to build offer: {e:?}"),
+    })
+}
+
+fn parse_offer(offer: &str) -> Result<Offer, LightningRpcError> {
+    Offer::from_str(offer).map_err(|_| LightningRpcError::Bolt12Error {
+        failure_reason: "Failed to parse Bolt12 Offer".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests;
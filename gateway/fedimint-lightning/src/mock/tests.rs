@@ -0,0 +1,209 @@
+use bitcoin::hashes::{Hash as _, sha256};
+use fedimint_core::Amount;
+use fedimint_core::task::TaskGroup;
+use futures::StreamExt as _;
+
+use super::{HtlcResolution, MockLightningNetwork, PaymentBehaviour};
+use crate::{
+    CreateInvoiceRequest, ILnRpcClient, InterceptPaymentResponse, LightningRpcError, PaymentAction,
+    Preimage,
+};
+
+const MAX_DELAY: u64 = 1000;
+
+fn max_fee() -> Amount {
+    Amount::from_sats(10)
+}
+
+/// A network with a peer the gateway has a balanced channel with.
+fn network_with_peer() -> (MockLightningNetwork, bitcoin::secp256k1::PublicKey) {
+    let network = MockLightningNetwork::new();
+    let peer = network.add_peer("peer");
+    network.open_channel(peer, Amount::from_sats(100_000), Amount::from_sats(100_000));
+    (network, peer)
+}
+
+#[tokio::test]
+async fn payment_moves_liquidity_and_is_idempotent() {
+    let (network, peer) = network_with_peer();
+    let node = network.gateway_node();
+    let invoice = network.peer_invoice(peer, Amount::from_sats(1_000));
+
+    let first = node
+        .pay(invoice.clone(), MAX_DELAY, max_fee())
+        .await
+        .expect("Payment succeeds");
+    let second = node
+        .pay(invoice.clone(), MAX_DELAY, max_fee())
+        .await
+        .expect("Repeated payment succeeds");
+
+    assert_eq!(first.preimage, second.preimage);
+    assert_eq!(
+        sha256::Hash::hash(&first.preimage.0),
+        *invoice.payment_hash()
+    );
+
+    let balances = node.get_balances().await.expect("Balances are available");
+    assert_eq!(balances.lightning_balance_msats, 99_000_000);
+    assert_eq!(balances.inbound_lightning_liquidity_msats, 101_000_000);
+}
+
+#[tokio::test]
+async fn payment_fails_on_route_limits_and_liquidity() {
+    let (network, peer) = network_with_peer();
+    let node = network.gateway_node();
+
+    let invoice = network.peer_invoice(peer, Amount::from_sats(1_000));
+    network.set_payee_behaviour(
+        peer,
+        PaymentBehaviour::Succeed {
+            fee: Amount::from_sats(20),
+            cltv_delta: 0,
+        },
+    );
+    assert!(matches!(
+        node.pay(invoice, MAX_DELAY, max_fee()).await,
+        Err(LightningRpcError::FailedPayment { .. })
+    ));
+
+    let invoice = network.peer_invoice(peer, Amount::from_sats(1_000));
+    network.set_payment_behaviour(
+        *invoice.payment_hash(),
+        PaymentBehaviour::Succeed {
+            fee: Amount::ZERO,
+            cltv_delta: MAX_DELAY + 1,
+        },
+    );
+    assert!(node.pay(invoice, MAX_DELAY, max_fee()).await.is_err());
+
+    network.set_payee_behaviour(peer, PaymentBehaviour::succeed());
+    let invoice = network.peer_invoice(peer, Amount::from_sats(200_000));
+    assert!(node.pay(invoice, MAX_DELAY, max_fee()).await.is_err());
+
+    let stranger = MockLightningNetwork::new();
+    let stranger_peer = stranger.add_peer("stranger");
+    let invoice = stranger.peer_invoice(stranger_peer, Amount::from_sats(1_000));
+    assert!(node.pay(invoice, MAX_DELAY, max_fee()).await.is_err());
+}
+
+#[tokio::test]
+async fn held_payment_completes_once_released() {
+    let (network, peer) = network_with_peer();
+    let node = network.gateway_node();
+    let invoice = network.peer_invoice(peer, Amount::from_sats(1_000));
+    let payment_hash = *invoice.payment_hash();
+
+    network.set_payment_behaviour(payment_hash, PaymentBehaviour::Hold);
+
+    let payment = tokio::spawn(async move { node.pay(invoice, MAX_DELAY, max_fee()).await });
+
+    while !network.release_payment(payment_hash, PaymentBehaviour::fail("Peer went offline")) {
+        tokio::task::yield_now().await;
+    }
+
+    assert!(payment.await.expect("Task completes").is_err());
+    assert_eq!(network.payment_preimage(payment_hash), None);
+}
+
+#[tokio::test]
+async fn hold_invoice_is_intercepted_and_settled() {
+    let (network, _) = network_with_peer();
+    let task_group = TaskGroup::new();
+    let (mut stream, node) = Box::new(network.gateway_node())
+        .route_htlcs(&task_group)
+        .await
+        .expect("Routes HTLCs");
+
+    let preimage = Preimage([7; 32]);
+    let payment_hash = sha256::Hash::hash(&preimage.0);
+    let invoice = node
+        .create_invoice(CreateInvoiceRequest {
+            payment_hash: Some(payment_hash),
+            amount_msat: 50_000,
+            expiry_secs: 3600,
+            description: None,
+        })
+        .await
+        .expect("Creates invoice")
+        .invoice
+        .parse()
+        .expect("Invoice is valid");
+
+    let htlc = network
+        .pay_gateway_invoice(&invoice)
+        .expect("Payment is sent");
+    let request = stream.next().await.expect("HTLC is intercepted");
+    assert_eq!(request.payment_hash, payment_hash);
+
+    let response = |action| InterceptPaymentResponse {
+        incoming_chan_id: request.incoming_chan_id,
+        htlc_id: request.htlc_id,
+        payment_hash,
+        action,
+    };
+
+    assert!(
+        node.complete_htlc(response(PaymentAction::Settle(Preimage([8; 32]))))
+            .await
+            .is_err()
+    );
+    node.complete_htlc(response(PaymentAction::Settle(preimage.clone())))
+        .await
+        .expect("Settles the HTLC");
+
+    assert_eq!(htlc.resolution().await, HtlcResolution::Settled(preimage));
+}
+
+#[tokio::test]
+async fn intercepted_forward_times_out() {
+    let (network, _) = network_with_peer();
+    let task_group = TaskGroup::new();
+    let (mut stream, node) = Box::new(network.gateway_node())
+        .route_htlcs(&task_group)
+        .await
+        .expect("Routes HTLCs");
+
+    let payment_hash = sha256::Hash::hash(b"forward");
+    let htlc = network
+        .forward_htlc(payment_hash, Amount::from_sats(1_000), 42, 10)
+        .expect("HTLC is sent");
+    let request = stream.next().await.expect("HTLC is intercepted");
+    assert_eq!(request.short_channel_id, Some(42));
+
+    network.mine_blocks(10);
+    assert_eq!(htlc.resolution().await, HtlcResolution::TimedOut);
+
+    assert!(
+        node.complete_htlc(InterceptPaymentResponse {
+            incoming_chan_id: request.incoming_chan_id,
+            htlc_id: request.htlc_id,
+            payment_hash,
+            action: PaymentAction::Cancel,
+        })
+        .await
+        .is_err()
+    );
+}
+
+#[tokio::test]
+async fn offers_are_paid_in_both_directions() {
+    let (network, peer) = network_with_peer();
+    let node = network.gateway_node();
+
+    let offer = network.peer_offer(peer, Some(Amount::from_sats(1_000)));
+    node.pay_offer(offer, None, None, None)
+        .await
+        .expect("Pays the peer's offer");
+
+    let offer = node
+        .create_offer(None, Some("Tip".to_string()), None, None)
+        .expect("Creates offer");
+    let htlc = network
+        .pay_gateway_offer(&offer, Some(Amount::from_sats(500)))
+        .expect("Pays the gateway's offer");
+    assert!(matches!(
+        htlc.resolution().await,
+        HtlcResolution::Settled(_)
+    ));
+}
@@ -1,9 +1,16 @@
+use std::collections::{BTreeMap, BTreeSet};
+
 use fedimint_api_client::api::{FederationApiExt, FederationResult, IModuleFederationApi};
+use fedimint_api_client::query::FilterMapThreshold;
 use fedimint_core::module::ApiRequestErased;
 use fedimint_core::task::{MaybeSend, MaybeSync};
-use fedimint_core::{OutPoint, apply, async_trait_maybe_send};
+use fedimint_core::util::SafeUrl;
+use fedimint_core::{NumPeersExt, OutPoint, PeerId, apply, async_trait_maybe_send};
 use fedimint_lnv2_common::ContractId;
-use fedimint_lnv2_common::endpoint_constants::OUTGOING_CONTRACT_EXPIRATION_ENDPOINT;
+use fedimint_lnv2_common::endpoint_constants::{
+    AWAIT_INCOMING_CONTRACT_ENDPOINT, AWAIT_PREIMAGE_ENDPOINT, CONSENSUS_BLOCK_COUNT_ENDPOINT,
+    GATEWAYS_ENDPOINT, OUTGOING_CONTRACT_EXPIRATION_ENDPOINT,
+};
 
 #[apply(async_trait_maybe_send!)]
 pub trait GatewayFederationApi {
@@ -11,6 +18,18 @@ async fn outgoing_contract_expiration(
         &self,
         outpoint: OutPoint,
     ) -> FederationResult<Option<(ContractId, u64)>>;
+
+    async fn consensus_block_count(&self) -> FederationResult<u64>;
+
+    async fn await_incoming_contract(
+        &self,
+        contract_id: &ContractId,
+        expiration: u64,
+    ) -> Option<OutPoint>;
+
+    async fn await_preimage(&self, outpoint: OutPoint, expiration: u64) -> Option<[u8; 32]>;
+
+    async fn gateways(&self) -> FederationResult<Vec<SafeUrl>>;
 }
 
 #[apply(async_trait_maybe_send!)]
@@ -28,4 +47,52 @@ async fn outgoing_contract_expiration(
         )
         .await
     }
+
+    async fn consensus_block_count(&self) -> FederationResult<u64> {
+        self.request_current_consensus(
+            CONSENSUS_BLOCK_COUNT_ENDPOINT.to_string(),
+            ApiRequestErased::new(()),
+        )
+        .await
+    }
+
+    async fn await_incoming_contract(
+        &self,
+        contract_id: &ContractId,
+        expiration: u64,
+    ) -> Option<OutPoint> {
+        self.request_current_consensus_retry::<Option<OutPoint>>(
+            AWAIT_INCOMING_CONTRACT_ENDPOINT.to_string(),
+            ApiRequestErased::new((contract_id, expiration)),
+        )
+        .await
+    }
+
+    async fn await_preimage(&self, outpoint: OutPoint, expiration: u64) -> Option<[u8; 32]> {
+        self.request_current_consensus_retry(
+            AWAIT_PREIMAGE_ENDPOINT.to_string(),
+            ApiRequestErased::new((outpoint, expiration)),
+        )
+        .await
+    }
+
+    async fn gateways(&self) -> FederationResult<Vec<SafeUrl>> {
+        let gateways: BTreeMap<PeerId, Vec<SafeUrl>> = self
+            .request_with_strategy(
+                FilterMapThreshold::new(
+                    |_, gateways| Ok(gateways),
+                    self.all_peers().to_num_peers(),
+                ),
+                GATEWAYS_ENDPOINT.to_string(),
+                ApiRequestErased::default(),
+            )
+            .await?;
+
+        Ok(gateways
+            .into_values()
+            .flatten()
+            .collect::<BTreeSet<SafeUrl>>()
+            .into_iter()
+            .collect())
+    }
 }
@@ -2,6 +2,8 @@
 mod complete_sm;
 pub mod events;
 mod receive_sm;
+mod self_receive_sm;
+mod self_send_sm;
 mod send_sm;
 
 use std::collections::BTreeMap;
@@ -9,10 +11,9 @@
 use std::fmt::Debug;
 use std::sync::Arc;
 
-use anyhow::{anyhow, ensure};
+use anyhow::{anyhow, bail, ensure};
 use async_trait::async_trait;
-use bitcoin::hashes::sha256;
-use bitcoin::secp256k1::Message;
+use bitcoin::hashes::{Hash as _, sha256};
 use events::{IncomingPaymentStarted, OutgoingPaymentStarted};
 use fedimint_api_client::api::DynModuleApi;
 use fedimint_client::ClientHandleArc;
@@ -31,24 +32,31 @@
 use fedimint_core::module::{
     Amounts, ApiVersion, CommonModuleInit, ModuleCommon, ModuleInit, MultiApiVersion,
 };
-use fedimint_core::secp256k1::Keypair;
-use fedimint_core::time::now;
-use fedimint_core::util::Spanned;
+use fedimint_core::secp256k1::{Keypair, PublicKey, Scalar, SecretKey};
+use fedimint_core::time::{duration_since_epoch, now};
+use fedimint_core::util::{SafeUrl, Spanned};
 use fedimint_core::{Amount, PeerId, apply, async_trait_maybe_send, secp256k1};
 use fedimint_lightning::{InterceptPaymentResponse, LightningRpcError};
 use fedimint_lnv2_common::config::LightningClientConfig;
-use fedimint_lnv2_common::contracts::{IncomingContract, PaymentImage};
-use fedimint_lnv2_common::gateway_api::SendPaymentPayload;
+use fedimint_lnv2_common::contracts::{IncomingContract, OutgoingContract, PaymentImage};
+use fedimint_lnv2_common::gateway_api::{
+    GatewayConnection, PaymentFee, RealGatewayConnection, RoutingInfo, SendPaymentPayload,
+};
 use fedimint_lnv2_common::{
-    LightningCommonInit, LightningInvoice, LightningModuleTypes, LightningOutput, LightningOutputV0,
+    Bolt11InvoiceDescription, GatewayApi, LightningCommonInit, LightningInvoice,
+    LightningModuleTypes, LightningOutput, LightningOutputV0, MINIMUM_INCOMING_CONTRACT_AMOUNT,
+    tweak,
 };
 use futures::StreamExt;
+use lightning::offers::invoice::Bolt12Invoice;
 use lightning_invoice::Bolt11Invoice;
 use receive_sm::{ReceiveSMState, ReceiveStateMachine};
 use secp256k1::schnorr::Signature;
+use self_receive_sm::{SelfReceiveSMCommon, SelfReceiveSMState, SelfReceiveStateMachine};
+use self_send_sm::{SelfSendSMCommon, SelfSendSMState, SelfSendStateMachine};
 use send_sm::{SendSMState, SendStateMachine};
 use serde::{Deserialize, Serialize};
-use tpe::{AggregatePublicKey, PublicKeyShare};
+use tpe::{AggregatePublicKey, PublicKeyShare, derive_agg_dk};
 use tracing::{info, warn};
 
 use crate::api::GatewayFederationApi;
@@ -62,6 +70,14 @@
 /// LNv2 CLTV Delta in blocks
 pub const EXPIRATION_DELTA_MINIMUM_V2: u64 = 144;
 
+/// Largest expiration delta in blocks another gateway may request for a self
+/// payment, after which the contract can be refunded unilaterally.
+const SELF_PAY_EXPIRATION_DELTA_LIMIT: u64 = 1440;
+
+/// Blocks added to the expiration of a self payment's outgoing contract in
+/// case the other gateway only sees the contract confirmed with a delay.
+const SELF_PAY_CONFIRMATION_BUFFER: u64 = 12;
+
 fn incoming_circuit_operation_id(
     receive_operation_id: OperationId,
     circuit: IncomingCircuitKey,
@@ -152,6 +168,12 @@ pub enum GatewayOperationRoleV2 {
     Receive,
     /// An incoming Lightning circuit completion operation.
     CircuitCompletion,
+    /// A payment of the gateway's own Lightning node from ecash through
+    /// another gateway of the federation.
+    SelfSend,
+    /// A payment from the gateway's own Lightning node into ecash through
+    /// another gateway of the federation.
+    SelfReceive,
 }
 
 impl GatewayOperationMetaV2 {
@@ -199,6 +221,7 @@ fn supported_api_versions(&self) -> MultiApiVersion {
     }
 
     async fn init(&self, args: &ClientModuleInitArgs<Self>) -> anyhow::Result<Self::Module> {
+        let api = GatewayApi::new(None, args.connector_registry.clone());
         Ok(GatewayClientModuleV2 {
             federation_id: *args.federation_id(),
             cfg: args.cfg().clone(),
@@ -210,6 +233,7 @@ async fn init(&self, args: &ClientModuleInitArgs<Self>) -> anyhow::Result<Self::
                 .clone()
                 .to_secp_key(fedimint_core::secp256k1::SECP256K1),
             gateway: self.gateway.clone(),
+            gateway_conn: Arc::new(RealGatewayConnection { api }),
         })
     }
 }
@@ -223,6 +247,9 @@ pub struct GatewayClientModuleV2 {
     pub module_api: DynModuleApi,
     pub keypair: Keypair,
     pub gateway: Arc<dyn IGatewayClientV2>,
+    /// Connection to the other gateways of the federation, used to move the
+    /// gateway's own liquidity through them.
+    pub gateway_conn: Arc<dyn GatewayConnection + Send + Sync>,
 }
 
 #[derive(Debug, Clone)]
@@ -288,6 +315,8 @@ pub enum GatewayClientStateMachinesV2 {
     /// Completes one incoming circuit independently of other circuits carrying
     /// the same payment hash and amount.
     CircuitComplete(CircuitCompleteStateMachine),
+    SelfSend(SelfSendStateMachine),
+    SelfReceive(SelfReceiveStateMachine),
 }
 
 impl fmt::Display for GatewayClientStateMachinesV2 {
@@ -305,6 +334,12 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             GatewayClientStateMachinesV2::CircuitComplete(complete) => {
                 write!(f, "{complete}")
             }
+            GatewayClientStateMachinesV2::SelfSend(send) => {
+                write!(f, "{send}")
+            }
+            GatewayClientStateMachinesV2::SelfReceive(receive) => {
+                write!(f, "{receive}")
+            }
         }
     }
 }
@@ -350,6 +385,18 @@ fn transitions(
                     GatewayClientStateMachinesV2::CircuitComplete
                 )
             }
+            GatewayClientStateMachinesV2::SelfSend(state) => {
+                sm_enum_variant_translation!(
+                    state.transitions(context, global_context),
+                    GatewayClientStateMachinesV2::SelfSend
+                )
+            }
+            GatewayClientStateMachinesV2::SelfReceive(state) => {
+                sm_enum_variant_translation!(
+                    state.transitions(context, global_context),
+                    GatewayClientStateMachinesV2::SelfReceive
+                )
+            }
         }
     }
 
@@ -359,6 +406,8 @@ fn operation_id(&self) -> OperationId {
             GatewayClientStateMachinesV2::Receive(state) => state.operation_id(),
             GatewayClientStateMachinesV2::Complete(state) => state.operation_id(),
             GatewayClientStateMachinesV2::CircuitComplete(state) => state.operation_id(),
+            GatewayClientStateMachinesV2::SelfSend(state) => state.operation_id(),
+            GatewayClientStateMachinesV2::SelfReceive(state) => state.operation_id(),
         }
     }
 }
@@ -402,9 +451,7 @@ pub async fn send_payment(
             secp256k1::SECP256K1
                 .verify_schnorr(
                     &payload.auth,
-                    &Message::from_digest(
-                        *payload.invoice.consensus_hash::<sha256::Hash>().as_ref()
-                    ),
+                    &SendPaymentPayload::auth_message(&payload.invoice, payload.part_amount),
                     &payload.contract.refund_pk.x_only_public_key().0,
                 )
                 .is_ok(),
@@ -425,20 +472,37 @@ pub async fn send_payment(
             "Contract Id returned by the federation does not match contract in request"
         );
 
-        let (payment_hash, amount) = match &payload.invoice {
-            LightningInvoice::Bolt11(invoice) => (
-                invoice.payment_hash(),
-                invoice
-                    .amount_milli_satoshis()
-                    .ok_or(anyhow!("Invoice is missing amount"))?,
-            ),
-        };
+        let payment_hash = payload.invoice.payment_hash();
+
+        let invoice_amount = payload
+            .invoice
+            .amount_milli_satoshis()
+            .ok_or(anyhow!("Invoice is missing amount"))?;
 
         ensure!(
-            PaymentImage::Hash(*payment_hash) == payload.contract.payment_image,
+            PaymentImage::Hash(payment_hash) == payload.contract.payment_image,
             "The invoices payment hash does not match the contracts payment hash"
         );
 
+        // A part of a multi-path payment only obliges us to pay its amount of the
+        // invoice, the remaining amount is paid by other gateways.
+        let (amount, state) = match payload.part_amount {
+            Some(part_amount) => {
+                ensure!(
+                    matches!(payload.invoice, LightningInvoice::Bolt11(..)),
+                    "Only bolt11 invoices can be paid in multiple parts"
+                );
+
+                ensure!(
+                    part_amount.msats != 0 && part_amount.msats <= invoice_amount,
+                    "Invalid part amount for the invoice"
+                );
+
+                (part_amount.msats, SendSMState::SendingPart(part_amount))
+            }
+            None => (invoice_amount, SendSMState::Sending),
+        };
+
         let min_contract_amount = self
             .gateway
             .min_contract_amount(&payload.federation_id, amount)
@@ -454,7 +518,7 @@ pub async fn send_payment(
                 invoice: payload.invoice,
                 claim_keypair: self.keypair,
             },
-            state: SendSMState::Sending,
+            state,
         });
 
         let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
@@ -492,7 +556,7 @@ pub async fn send_payment(
         loop {
             if let Some(GatewayClientStateMachinesV2::Send(state)) = stream.next().await {
                 match state.state {
-                    SendSMState::Sending => {}
+                    SendSMState::Sending | SendSMState::SendingPart(..) => {}
                     SendSMState::Claiming(claiming) => {
                         // The preimage is proof the payment succeeded, so return it to
                         // the sender as soon as it is available rather than waiting for
@@ -803,6 +867,283 @@ pub async fn await_completion(&self, operation_id: OperationId) {
             }
         }
     }
+
+    /// Selects another gateway of the federation to move the gateway's own
+    /// liquidity through. Gateways sharing the Lightning node
+    /// `node_public_key` are skipped, since paying between ecash and the same
+    /// node does not move any liquidity.
+    pub async fn select_self_pay_gateway(
+        &self,
+        node_public_key: PublicKey,
+    ) -> anyhow::Result<(SafeUrl, RoutingInfo)> {
+        let gateways = self
+            .module_api
+            .gateways()
+            .await
+            .map_err(|_| anyhow!("The gateway can not reach the federation"))?;
+
+        for gateway_api in gateways {
+            let Ok(Some(routing_info)) = self
+                .gateway_conn
+                .routing_info(gateway_api.clone(), &self.federation_id)
+                .await
+            else {
+                continue;
+            };
+
+            if routing_info.module_public_key == self.keypair.public_key()
+                || routing_info.lightning_public_key == node_public_key
+            {
+                continue;
+            }
+
+            return Ok((gateway_api, routing_info));
+        }
+
+        bail!("The federation has no other gateway to move liquidity through")
+    }
+
+    /// Funds an outgoing contract for an invoice of the gateway's own Lightning
+    /// node and requests the other gateway to pay it, moving liquidity from
+    /// the federation into the gateway's channels.
+    pub async fn send_self_payment(
+        &self,
+        gateway_api: SafeUrl,
+        routing_info: &RoutingInfo,
+        invoice: Bolt11Invoice,
+    ) -> anyhow::Result<OperationId> {
+        let amount = invoice
+            .amount_milli_satoshis()
+            .ok_or(anyhow!("Invoice is missing amount"))?;
+
+        let invoice = LightningInvoice::Bolt11(invoice);
+
+        let (send_fee, expiration_delta) = routing_info.send_parameters(&invoice);
+
+        ensure!(
+            send_fee.is_within(&PaymentFee::SEND_FEE_LIMIT),
+            "The fee of the other gateway exceeds the limit"
+        );
+
+        ensure!(
+            expiration_delta <= SELF_PAY_EXPIRATION_DELTA_LIMIT,
+            "The expiration delta of the other gateway exceeds the limit"
+        );
+
+        let consensus_block_count = self
+            .module_api
+            .consensus_block_count()
+            .await
+            .map_err(|_| anyhow!("The gateway can not reach the federation"))?;
+
+        let (ephemeral_tweak, ephemeral_pk) = tweak::generate(self.keypair.public_key());
+
+        let refund_keypair = SecretKey::from_slice(&ephemeral_tweak)
+            .expect("32 bytes, within curve order")
+            .keypair(secp256k1::SECP256K1);
+
+        let contract = OutgoingContract {
+            payment_image: PaymentImage::Hash(invoice.payment_hash()),
+            amount: send_fee.add_to(amount),
+            expiration: consensus_block_count + expiration_delta + SELF_PAY_CONFIRMATION_BUFFER,
+            claim_pk: routing_info.module_public_key,
+            refund_pk: refund_keypair.public_key(),
+            ephemeral_pk,
+        };
+
+        let operation_id = OperationId::from_encodable(&contract);
+
+        let client_output = ClientOutput::<LightningOutput> {
+            output: LightningOutput::V0(LightningOutputV0::Outgoing(contract.clone())),
+            amounts: Amounts::new_bitcoin(contract.amount),
+        };
+        let client_output_sm = ClientOutputSM::<GatewayClientStateMachinesV2> {
+            state_machines: Arc::new(move |range: OutPointRange| {
+                assert_eq!(range.count(), 1);
+
+                vec![GatewayClientStateMachinesV2::SelfSend(
+                    SelfSendStateMachine {
+                        common: SelfSendSMCommon {
+                            operation_id,
+                            outpoint: range.into_iter().next().unwrap(),
+                            contract: contract.clone(),
+                            gateway_api: gateway_api.clone(),
+                            invoice: invoice.clone(),
+                            refund_keypair,
+                        },
+                        state: SelfSendSMState::Funding,
+                    },
+                )]
+            }),
+        };
+
+        let client_output = self.client_ctx.make_client_outputs(ClientOutputBundle::new(
+            vec![client_output],
+            vec![client_output_sm],
+        ));
+
+        self.client_ctx
+            .finalize_and_submit_transaction(
+                operation_id,
+                LightningCommonInit::KIND.as_str(),
+                |_| GatewayOperationMetaV2::role(GatewayOperationRoleV2::SelfSend),
+                TransactionBuilder::new().with_outputs(client_output),
+            )
+            .await?;
+
+        Ok(operation_id)
+    }
+
+    /// Waits for the other gateway to pay the invoice of a self payment,
+    /// failing once the contract has been refunded instead.
+    pub async fn await_self_send(&self, operation_id: OperationId) -> anyhow::Result<()> {
+        let mut stream = self.notifier.subscribe(operation_id).await;
+
+        loop {
+            if let Some(GatewayClientStateMachinesV2::SelfSend(state)) = stream.next().await {
+                match state.state {
+                    SelfSendSMState::Funding | SelfSendSMState::Funded => {}
+                    SelfSendSMState::Success(..) => return Ok(()),
+                    SelfSendSMState::Rejected(error) => {
+                        bail!("The funding transaction was rejected: {error}")
+                    }
+                    SelfSendSMState::Refunding(out_points) => {
+                        self.client_ctx
+                            .await_primary_module_outputs(operation_id, out_points)
+                            .await?;
+
+                        bail!("The other gateway did not pay the invoice")
+                    }
+                }
+            }
+        }
+    }
+
+    /// Creates an incoming contract keyed to the other gateway and requests an
+    /// invoice for it. Once the gateway's own Lightning node pays the invoice,
+    /// the contract is claimed, moving liquidity from the gateway's channels
+    /// into the federation.
+    pub async fn receive_self_payment(
+        &self,
+        gateway_api: SafeUrl,
+        routing_info: &RoutingInfo,
+        amount: Amount,
+        expiry_secs: u32,
+    ) -> anyhow::Result<(OperationId, Bolt11Invoice)> {
+        let receive_fee = routing_info.receive_fee_for(amount.msats);
+
+        ensure!(
+            receive_fee.is_within(&PaymentFee::RECEIVE_FEE_LIMIT),
+            "The fee of the other gateway exceeds the limit"
+        );
+
+        let contract_amount = receive_fee.subtract_from(amount.msats);
+
+        ensure!(
+            MINIMUM_INCOMING_CONTRACT_AMOUNT <= contract_amount,
+            "The amount is too small to cover the fee of the other gateway"
+        );
+
+        let (ephemeral_tweak, ephemeral_pk) = tweak::generate(self.keypair.public_key());
+
+        let encryption_seed = ephemeral_tweak
+            .consensus_hash::<sha256::Hash>()
+            .to_byte_array();
+
+        let preimage = encryption_seed
+            .consensus_hash::<sha256::Hash>()
+            .to_byte_array();
+
+        let claim_keypair = self
+            .keypair
+            .secret_key()
+            .mul_tweak(&Scalar::from_be_bytes(ephemeral_tweak).expect("Within curve order"))
+            .expect("Tweak is valid")
+            .keypair(secp256k1::SECP256K1);
+
+        let contract = IncomingContract::new(
+            self.cfg.tpe_agg_pk,
+            encryption_seed,
+            preimage,
+            PaymentImage::Hash(preimage.consensus_hash()),
+            contract_amount,
+            duration_since_epoch()
+                .as_secs()
+                .saturating_add(u64::from(expiry_secs)),
+            claim_keypair.public_key(),
+            routing_info.module_public_key,
+            ephemeral_pk,
+        );
+
+        let invoice = self
+            .gateway_conn
+            .bolt11_invoice(
+                gateway_api,
+                self.federation_id,
+                contract.clone(),
+                amount,
+                Bolt11InvoiceDescription::Direct("Gateway liquidity rebalancing".to_string()),
+                expiry_secs,
+            )
+            .await?;
+
+        ensure!(
+            PaymentImage::Hash(*invoice.payment_hash()) == contract.commitment.payment_image,
+            "The invoice of the other gateway does not match the contract"
+        );
+
+        ensure!(
+            invoice.amount_milli_satoshis() == Some(amount.msats),
+            "The invoice of the other gateway has an incorrect amount"
+        );
+
+        let operation_id = OperationId::from_encodable(&contract);
+
+        let receive_sm = GatewayClientStateMachinesV2::SelfReceive(SelfReceiveStateMachine {
+            common: SelfReceiveSMCommon {
+                operation_id,
+                agg_decryption_key: derive_agg_dk(&self.cfg.tpe_agg_pk, &encryption_seed),
+                contract,
+                claim_keypair,
+            },
+            state: SelfReceiveSMState::Pending,
+        });
+
+        self.client_ctx
+            .manual_operation_start(
+                operation_id,
+                LightningCommonInit::KIND.as_str(),
+                GatewayOperationMetaV2::role(GatewayOperationRoleV2::SelfReceive),
+                vec![self.client_ctx.make_dyn_state(receive_sm)],
+            )
+            .await?;
+
+        Ok((operation_id, invoice))
+    }
+
+    /// Waits for the incoming contract of a self payment to be claimed,
+    /// failing once the contract expired unpaid instead.
+    pub async fn await_self_receive(&self, operation_id: OperationId) -> anyhow::Result<()> {
+        let mut stream = self.notifier.subscribe(operation_id).await;
+
+        loop {
+            if let Some(GatewayClientStateMachinesV2::SelfReceive(state)) = stream.next().await {
+                match state.state {
+                    SelfReceiveSMState::Pending => {}
+                    SelfReceiveSMState::Claiming(out_points) => {
+                        self.client_ctx
+                            .await_primary_module_outputs(operation_id, out_points)
+                            .await?;
+
+                        return Ok(());
+                    }
+                    SelfReceiveSMState::Expired => {
+                        bail!("The incoming contract expired without being funded")
+                    }
+                }
+            }
+        }
+    }
 }
 
 /// An interface between module implementation and the general `Gateway`
@@ -846,6 +1187,25 @@ async fn pay(
         max_fee: Amount,
     ) -> Result<[u8; 32], LightningRpcError>;
 
+    /// Initiates a payment of an invoice the gateway has fetched from a
+    /// BOLT12 offer over the Lightning network.
+    async fn pay_bolt12(
+        &self,
+        invoice: Bolt12Invoice,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<[u8; 32], LightningRpcError>;
+
+    /// Initiates a payment of the given part of an invoice's amount over the
+    /// Lightning network as one shard of a multi-path payment.
+    async fn pay_part(
+        &self,
+        invoice: Bolt11Invoice,
+        amount: Amount,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<[u8; 32], LightningRpcError>;
+
     /// Computes the minimum contract amount necessary for making an outgoing
     /// payment.
     ///
@@ -0,0 +1,161 @@
+use core::fmt;
+
+use fedimint_client_module::DynGlobalClientContext;
+use fedimint_client_module::sm::{ClientSMDatabaseTransaction, State, StateTransition};
+use fedimint_client_module::transaction::{ClientInput, ClientInputBundle};
+use fedimint_core::OutPoint;
+use fedimint_core::core::OperationId;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::Amounts;
+use fedimint_core::secp256k1::Keypair;
+use fedimint_lnv2_common::contracts::IncomingContract;
+use fedimint_lnv2_common::{LightningInput, LightningInputV0};
+use tpe::AggregateDecryptionKey;
+
+use crate::GatewayClientContextV2;
+use crate::api::GatewayFederationApi;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
+pub struct SelfReceiveStateMachine {
+    pub common: SelfReceiveSMCommon,
+    pub state: SelfReceiveSMState,
+}
+
+impl SelfReceiveStateMachine {
+    pub fn update(&self, state: SelfReceiveSMState) -> Self {
+        Self {
+            common: self.common.clone(),
+            state,
+        }
+    }
+}
+
+impl fmt::Display for SelfReceiveStateMachine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Self Receive State Machine Operation ID: {:?} State: {}",
+            self.common.operation_id, self.state
+        )
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
+pub struct SelfReceiveSMCommon {
+    pub operation_id: OperationId,
+    pub contract: IncomingContract,
+    pub claim_keypair: Keypair,
+    pub agg_decryption_key: AggregateDecryptionKey,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
+pub enum SelfReceiveSMState {
+    Pending,
+    Claiming(Vec<OutPoint>),
+    Expired,
+}
+
+impl fmt::Display for SelfReceiveSMState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SelfReceiveSMState::Pending => write!(f, "Pending"),
+            SelfReceiveSMState::Claiming(_) => write!(f, "Claiming"),
+            SelfReceiveSMState::Expired => write!(f, "Expired"),
+        }
+    }
+}
+
+#[cfg_attr(doc, aquamarine::aquamarine)]
+/// State machine that claims an incoming contract funded by another gateway of
+/// the federation once the gateway's own Lightning node paid the other
+/// gateway's invoice, moving liquidity from the gateway's channels into the
+/// federation.
+///
+/// ```mermaid
+/// graph LR
+/// classDef virtual fill:#fff,stroke-dasharray: 5 5
+///
+///     Pending -- incoming contract is confirmed --> Claiming
+///     Pending -- decryption contract expires --> Expired
+/// ```
+impl State for SelfReceiveStateMachine {
+    type ModuleContext = GatewayClientContextV2;
+
+    fn transitions(
+        &self,
+        _context: &Self::ModuleContext,
+        global_context: &DynGlobalClientContext,
+    ) -> Vec<StateTransition<Self>> {
+        let gc = global_context.clone();
+
+        match &self.state {
+            SelfReceiveSMState::Pending => {
+                vec![StateTransition::new(
+                    Self::await_incoming_contract(
+                        self.common.contract.clone(),
+                        global_context.clone(),
+                    ),
+                    move |dbtx, outpoint, old_state| {
+                        Box::pin(Self::transition_incoming_contract(
+                            dbtx,
+                            old_state,
+                            gc.clone(),
+                            outpoint,
+                        ))
+                    },
+                )]
+            }
+            SelfReceiveSMState::Claiming(..) | SelfReceiveSMState::Expired => {
+                vec![]
+            }
+        }
+    }
+
+    fn operation_id(&self) -> OperationId {
+        self.common.operation_id
+    }
+}
+
+impl SelfReceiveStateMachine {
+    async fn await_incoming_contract(
+        contract: IncomingContract,
+        global_context: DynGlobalClientContext,
+    ) -> Option<OutPoint> {
+        global_context
+            .module_api()
+            .await_incoming_contract(
+                &contract.contract_id(),
+                contract.commitment.expiration_or_fee,
+            )
+            .await
+    }
+
+    async fn transition_incoming_contract(
+        dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
+        old_state: SelfReceiveStateMachine,
+        global_context: DynGlobalClientContext,
+        outpoint: Option<OutPoint>,
+    ) -> SelfReceiveStateMachine {
+        let Some(outpoint) = outpoint else {
+            return old_state.update(SelfReceiveSMState::Expired);
+        };
+
+        let client_input = ClientInput::<LightningInput> {
+            input: LightningInput::V0(LightningInputV0::Incoming(
+                outpoint,
+                old_state.common.agg_decryption_key,
+            )),
+            amounts: Amounts::new_bitcoin(old_state.common.contract.commitment.amount),
+            keys: vec![old_state.common.claim_keypair],
+        };
+
+        let change_range = global_context
+            .claim_inputs(dbtx, ClientInputBundle::new_no_sm(vec![client_input]))
+            .await
+            .expect("Cannot claim input, additional funding needed");
+
+        old_state.update(SelfReceiveSMState::Claiming(
+            change_range.into_iter().collect(),
+        ))
+    }
+}
@@ -0,0 +1,289 @@
+use core::fmt;
+use std::sync::Arc;
+
+use anyhow::ensure;
+use fedimint_client_module::DynGlobalClientContext;
+use fedimint_client_module::sm::{ClientSMDatabaseTransaction, State, StateTransition};
+use fedimint_client_module::transaction::{ClientInput, ClientInputBundle};
+use fedimint_core::config::FederationId;
+use fedimint_core::core::OperationId;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::Amounts;
+use fedimint_core::secp256k1::Keypair;
+use fedimint_core::secp256k1::schnorr::Signature;
+use fedimint_core::util::SafeUrl;
+use fedimint_core::util::backoff_util::api_networking_backoff;
+use fedimint_core::{OutPoint, TransactionId, crit, util};
+use fedimint_lnv2_common::contracts::OutgoingContract;
+use fedimint_lnv2_common::gateway_api::{GatewayConnection, SendPaymentPayload};
+use fedimint_lnv2_common::{LightningInput, LightningInputV0, LightningInvoice, OutgoingWitness};
+use fedimint_logging::LOG_CLIENT_MODULE_GW;
+use futures::future::pending;
+
+use crate::GatewayClientContextV2;
+use crate::api::GatewayFederationApi;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
+pub struct SelfSendStateMachine {
+    pub common: SelfSendSMCommon,
+    pub state: SelfSendSMState,
+}
+
+impl SelfSendStateMachine {
+    pub fn update(&self, state: SelfSendSMState) -> Self {
+        Self {
+            common: self.common.clone(),
+            state,
+        }
+    }
+}
+
+impl fmt::Display for SelfSendStateMachine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Self Send State Machine Operation ID: {:?} State: {}",
+            self.common.operation_id, self.state
+        )
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
+pub struct SelfSendSMCommon {
+    pub operation_id: OperationId,
+    pub outpoint: OutPoint,
+    pub contract: OutgoingContract,
+    pub gateway_api: SafeUrl,
+    pub invoice: LightningInvoice,
+    pub refund_keypair: Keypair,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
+pub enum SelfSendSMState {
+    Funding,
+    Funded,
+    Rejected(String),
+    Success([u8; 32]),
+    Refunding(Vec<OutPoint>),
+}
+
+impl fmt::Display for SelfSendSMState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SelfSendSMState::Funding => write!(f, "Funding"),
+            SelfSendSMState::Funded => write!(f, "Funded"),
+            SelfSendSMState::Rejected(_) => write!(f, "Rejected"),
+            SelfSendSMState::Success(_) => write!(f, "Success"),
+            SelfSendSMState::Refunding(_) => write!(f, "Refunding"),
+        }
+    }
+}
+
+#[cfg_attr(doc, aquamarine::aquamarine)]
+/// State machine that pays an invoice of the gateway's own Lightning node from
+/// the gateway's ecash through another gateway of the federation, moving
+/// liquidity from the federation into the gateway's channels.
+///
+/// ```mermaid
+/// graph LR
+/// classDef virtual fill:#fff,stroke-dasharray: 5 5
+///
+///     Funding -- funding tx is rejected --> Rejected
+///     Funding -- funding tx is accepted --> Funded
+///     Funded -- other gateway returns preimage --> Success
+///     Funded -- other gateway returns forfeit signature --> Refunding
+///     Funded -- await_preimage returns preimage --> Success
+///     Funded -- await_preimage expires --> Refunding
+/// ```
+impl State for SelfSendStateMachine {
+    type ModuleContext = GatewayClientContextV2;
+
+    fn transitions(
+        &self,
+        context: &Self::ModuleContext,
+        global_context: &DynGlobalClientContext,
+    ) -> Vec<StateTransition<Self>> {
+        let gc_pay = global_context.clone();
+        let gc_preimage = global_context.clone();
+
+        match &self.state {
+            SelfSendSMState::Funding => {
+                vec![StateTransition::new(
+                    Self::await_funding(global_context.clone(), self.common.outpoint.txid),
+                    |_, result, old_state| {
+                        Box::pin(async move { Self::transition_funding(result, &old_state) })
+                    },
+                )]
+            }
+            SelfSendSMState::Funded => {
+                vec![
+                    StateTransition::new(
+                        Self::gateway_send_payment(
+                            context.module.gateway_conn.clone(),
+                            self.common.clone(),
+                            context.module.federation_id,
+                        ),
+                        move |dbtx, response, old_state| {
+                            Box::pin(Self::transition_gateway_send_payment(
+                                gc_pay.clone(),
+                                dbtx,
+                                response,
+                                old_state,
+                            ))
+                        },
+                    ),
+                    StateTransition::new(
+                        Self::await_preimage(
+                            self.common.outpoint,
+                            self.common.contract.clone(),
+                            gc_preimage.clone(),
+                        ),
+                        move |dbtx, preimage, old_state| {
+                            Box::pin(Self::transition_preimage(
+                                dbtx,
+                                gc_preimage.clone(),
+                                old_state,
+                                preimage,
+                            ))
+                        },
+                    ),
+                ]
+            }
+            SelfSendSMState::Rejected(..)
+            | SelfSendSMState::Success(..)
+            | SelfSendSMState::Refunding(..) => {
+                vec![]
+            }
+        }
+    }
+
+    fn operation_id(&self) -> OperationId {
+        self.common.operation_id
+    }
+}
+
+impl SelfSendStateMachine {
+    async fn await_funding(
+        global_context: DynGlobalClientContext,
+        txid: TransactionId,
+    ) -> Result<(), String> {
+        global_context.await_tx_accepted(txid).await
+    }
+
+    fn transition_funding(
+        result: Result<(), String>,
+        old_state: &SelfSendStateMachine,
+    ) -> SelfSendStateMachine {
+        match result {
+            Ok(()) => old_state.update(SelfSendSMState::Funded),
+            Err(error) => old_state.update(SelfSendSMState::Rejected(error)),
+        }
+    }
+
+    async fn gateway_send_payment(
+        gateway_conn: Arc<dyn GatewayConnection + Send + Sync>,
+        common: SelfSendSMCommon,
+        federation_id: FederationId,
+    ) -> Result<[u8; 32], Signature> {
+        util::retry("gateway-send-payment", api_networking_backoff(), || async {
+            let payment_result = gateway_conn
+                .send_payment(
+                    common.gateway_api.clone(),
+                    federation_id,
+                    common.outpoint,
+                    common.contract.clone(),
+                    common.invoice.clone(),
+                    None,
+                    common
+                        .refund_keypair
+                        .sign_schnorr(SendPaymentPayload::auth_message(&common.invoice, None)),
+                )
+                .await?;
+
+            ensure!(
+                common.contract.verify_gateway_response(&payment_result),
+                "Invalid gateway response: {payment_result:?}"
+            );
+
+            Ok(payment_result)
+        })
+        .await
+        .expect("Number of retries has no limit")
+    }
+
+    async fn transition_gateway_send_payment(
+        global_context: DynGlobalClientContext,
+        dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
+        gateway_response: Result<[u8; 32], Signature>,
+        old_state: SelfSendStateMachine,
+    ) -> SelfSendStateMachine {
+        match gateway_response {
+            Ok(preimage) => old_state.update(SelfSendSMState::Success(preimage)),
+            Err(signature) => {
+                Self::refund(
+                    global_context,
+                    dbtx,
+                    old_state,
+                    OutgoingWitness::Cancel(signature),
+                )
+                .await
+            }
+        }
+    }
+
+    async fn await_preimage(
+        outpoint: OutPoint,
+        contract: OutgoingContract,
+        global_context: DynGlobalClientContext,
+    ) -> Option<[u8; 32]> {
+        let preimage = global_context
+            .module_api()
+            .await_preimage(outpoint, contract.expiration)
+            .await?;
+
+        if contract.verify_preimage(&preimage) {
+            return Some(preimage);
+        }
+
+        crit!(target: LOG_CLIENT_MODULE_GW, "Federation returned invalid preimage {:?}", preimage);
+
+        pending().await
+    }
+
+    async fn transition_preimage(
+        dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
+        global_context: DynGlobalClientContext,
+        old_state: SelfSendStateMachine,
+        preimage: Option<[u8; 32]>,
+    ) -> SelfSendStateMachine {
+        match preimage {
+            Some(preimage) => old_state.update(SelfSendSMState::Success(preimage)),
+            None => Self::refund(global_context, dbtx, old_state, OutgoingWitness::Refund).await,
+        }
+    }
+
+    async fn refund(
+        global_context: DynGlobalClientContext,
+        dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
+        old_state: SelfSendStateMachine,
+        witness: OutgoingWitness,
+    ) -> SelfSendStateMachine {
+        let client_input = ClientInput::<LightningInput> {
+            input: LightningInput::V0(LightningInputV0::Outgoing(
+                old_state.common.outpoint,
+                witness,
+            )),
+            amounts: Amounts::new_bitcoin(old_state.common.contract.amount),
+            keys: vec![old_state.common.refund_keypair],
+        };
+
+        let change_range = global_context
+            .claim_inputs(dbtx, ClientInputBundle::new_no_sm(vec![client_input]))
+            .await
+            .expect("Cannot claim input, additional funding needed");
+
+        old_state.update(SelfSendSMState::Refunding(
+            change_range.into_iter().collect(),
+        ))
+    }
+}
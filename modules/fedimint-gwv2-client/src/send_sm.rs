@@ -11,6 +11,7 @@
 use fedimint_core::{Amount, OutPoint};
 use fedimint_lnv2_common::contracts::OutgoingContract;
 use fedimint_lnv2_common::{LightningInput, LightningInputV0, LightningInvoice, OutgoingWitness};
+use lightning::offers::invoice::Bolt12Invoice;
 use serde::{Deserialize, Serialize};
 
 use super::FinalReceiveState;
@@ -58,6 +59,9 @@ pub enum SendSMState {
     Sending,
     Claiming(Claiming),
     Cancelled(Cancelled),
+    /// Like [`SendSMState::Sending`] for a contract that only obliges us to pay
+    /// the given amount of the invoice as a part of a multi-path payment.
+    SendingPart(Amount),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -72,6 +76,7 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             SendSMState::Sending => write!(f, "Sending"),
             SendSMState::Claiming(_) => write!(f, "Claiming"),
             SendSMState::Cancelled(_) => write!(f, "Cancelled"),
+            SendSMState::SendingPart(_) => write!(f, "SendingPart"),
         }
     }
 }
@@ -105,6 +110,8 @@ pub enum Cancelled {
 ///
 ///     Sending -- payment is successful --> Claiming
 ///     Sending -- payment fails --> Cancelled
+///     SendingPart -- payment is successful --> Claiming
+///     SendingPart -- payment fails --> Cancelled
 /// ```
 impl State for SendStateMachine {
     type ModuleContext = GatewayClientContextV2;
@@ -138,6 +145,27 @@ fn transitions(
                     },
                 )]
             }
+            SendSMState::SendingPart(part_amount) => {
+                vec![StateTransition::new(
+                    Self::send_part_payment(
+                        context.clone(),
+                        self.common.max_delay,
+                        self.common.min_contract_amount,
+                        self.common.invoice.clone(),
+                        self.common.contract.clone(),
+                        *part_amount,
+                    ),
+                    move |dbtx, result, old_state| {
+                        Box::pin(Self::transition_send_payment(
+                            dbtx,
+                            old_state,
+                            gc.clone(),
+                            result,
+                            gateway_context.clone(),
+                        ))
+                    },
+                )]
+            }
             SendSMState::Claiming(..) | SendSMState::Cancelled(..) => {
                 vec![]
             }
@@ -157,7 +185,19 @@ async fn send_payment(
         invoice: LightningInvoice,
         contract: OutgoingContract,
     ) -> Result<PaymentResponse, Cancelled> {
-        let LightningInvoice::Bolt11(invoice) = invoice;
+        let invoice = match invoice {
+            LightningInvoice::Bolt11(invoice) => invoice,
+            LightningInvoice::Bolt12(invoice) => {
+                return Self::send_bolt12_payment(
+                    context,
+                    max_delay,
+                    min_contract_amount,
+                    invoice,
+                    contract,
+                )
+                .await;
+            }
+        };
 
         // The following two checks may fail in edge cases since they have inherent
         // timing assumptions. Therefore, they may only be checked after we have created
@@ -241,6 +281,80 @@ async fn send_payment(
         }
     }
 
+    /// Pays an invoice the gateway has previously fetched from a BOLT12 offer
+    /// on behalf of the sender. Since offers do not carry route hints, such a
+    /// payment can be neither an LNv1 nor a direct swap and always goes over
+    /// the Lightning network.
+    async fn send_bolt12_payment(
+        context: GatewayClientContextV2,
+        max_delay: u64,
+        min_contract_amount: Amount,
+        invoice: Bolt12Invoice,
+        contract: OutgoingContract,
+    ) -> Result<PaymentResponse, Cancelled> {
+        if invoice.is_expired() {
+            return Err(Cancelled::InvoiceExpired);
+        }
+
+        if max_delay == 0 {
+            return Err(Cancelled::TimeoutTooClose);
+        }
+
+        let Some(max_fee) = contract.amount.checked_sub(min_contract_amount) else {
+            return Err(Cancelled::Underfunded);
+        };
+
+        let preimage = context
+            .gateway
+            .pay_bolt12(invoice, max_delay, max_fee)
+            .await
+            .map_err(|e| Cancelled::LightningRpcError(e.to_string()))?;
+
+        Ok(PaymentResponse {
+            preimage,
+            target_federation: None,
+        })
+    }
+
+    /// Pays our part of a multi-path payment. The payee only settles the parts
+    /// once the full invoice amount has arrived, so this may not be an LNv1 or
+    /// direct swap and always goes over the Lightning network.
+    async fn send_part_payment(
+        context: GatewayClientContextV2,
+        max_delay: u64,
+        min_contract_amount: Amount,
+        invoice: LightningInvoice,
+        contract: OutgoingContract,
+        part_amount: Amount,
+    ) -> Result<PaymentResponse, Cancelled> {
+        let LightningInvoice::Bolt11(invoice) = invoice else {
+            return Err(Cancelled::Failure);
+        };
+
+        if invoice.is_expired() {
+            return Err(Cancelled::InvoiceExpired);
+        }
+
+        if max_delay == 0 {
+            return Err(Cancelled::TimeoutTooClose);
+        }
+
+        let Some(max_fee) = contract.amount.checked_sub(min_contract_amount) else {
+            return Err(Cancelled::Underfunded);
+        };
+
+        let preimage = context
+            .gateway
+            .pay_part(invoice, part_amount, max_delay, max_fee)
+            .await
+            .map_err(|e| Cancelled::LightningRpcError(e.to_string()))?;
+
+        Ok(PaymentResponse {
+            preimage,
+            target_federation: None,
+        })
+    }
+
     async fn transition_send_payment(
         dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
         old_state: SendStateMachine,
@@ -45,6 +45,9 @@
 use fedimint_client_module::module::recovery::NoModuleBackup;
 use fedimint_client_module::module::{ClientContext, ClientModule, IClientModule, OutPointRange};
 use fedimint_client_module::oplog::UpdateStreamOrOutcome;
+use fedimint_client_module::payment_history::{
+    self, PaymentDirection, PaymentHistoryUpdate, PaymentStatus,
+};
 use fedimint_client_module::sm::{DynState, ModuleNotifier, State, StateTransition};
 use fedimint_client_module::transaction::{
     ClientInput, ClientInputBundle, ClientOutput, ClientOutputBundle, ClientOutputSM, FeeQuote,
@@ -68,6 +71,7 @@
     Amount, OutPoint, apply, async_trait_maybe_send, push_db_pair_items, runtime, secp256k1,
 };
 use fedimint_derive_secret::{ChildId, DerivableSecret};
+use fedimint_eventlog::EventLogEntry;
 use fedimint_ln_common::client::GatewayApi;
 use fedimint_ln_common::config::{FeeToAmount, LightningClientConfig};
 use fedimint_ln_common::contracts::incoming::{IncomingContract, IncomingContractOffer};
@@ -104,6 +108,9 @@
 use tracing::{debug, error, info, warn};
 
 use crate::db::PaymentResultPrefix;
+use crate::events::{
+    ReceivePaymentEvent, SendPaymentEvent, SendPaymentStatus, SendPaymentUpdateEvent,
+};
 use crate::incoming::{
     FundingOfferState, IncomingSmCommon, IncomingSmStates, IncomingStateMachine,
 };
@@ -116,7 +123,7 @@
     LightningReceiveConfirmedInvoice, LightningReceiveError, LightningReceiveStateMachine,
     LightningReceiveStates, LightningReceiveSubmittedOffer, get_incoming_contract,
 };
-use crate::recurring::RecurringPaymentCodeEntry;
+use crate::recurring::{RecurringPaymentCodeEntry, ReurringPaymentReceiveMeta};
 
 /// Number of blocks until outgoing lightning contracts times out and user
 /// client can get refund
@@ -511,6 +518,67 @@ fn output_fee(
         }
     }
 
+    async fn payment_history_update(&self, event: &EventLogEntry) -> Option<PaymentHistoryUpdate> {
+        if let Some(event) = event.to_event_of_kind::<SendPaymentEvent>() {
+            let invoice = match self.operation_meta(event.operation_id).await {
+                Some(LightningOperationMetaVariant::Pay(meta)) => Some(meta.invoice),
+                _ => None,
+            };
+
+            return Some(PaymentHistoryUpdate::Created(
+                payment_history::PaymentInfo {
+                    operation_id: event.operation_id,
+                    direction: PaymentDirection::Send,
+                    amount: event.amount,
+                    fee: event.fee,
+                    status: PaymentStatus::Pending,
+                    counterparty: invoice
+                        .as_ref()
+                        .map(|invoice| invoice.recover_payee_pub_key().to_string()),
+                    payment_hash: invoice.as_ref().map(|invoice| *invoice.payment_hash()),
+                },
+            ));
+        }
+
+        if let Some(event) = event.to_event_of_kind::<SendPaymentUpdateEvent>() {
+            return Some(PaymentHistoryUpdate::Status {
+                operation_id: event.operation_id,
+                status: match event.status {
+                    SendPaymentStatus::Success(..) => PaymentStatus::Success,
+                    SendPaymentStatus::Refunded => PaymentStatus::Failed,
+                },
+            });
+        }
+
+        if let Some(event) = event.to_event_of_kind::<ReceivePaymentEvent>() {
+            let invoice = match self.operation_meta(event.operation_id).await {
+                Some(
+                    LightningOperationMetaVariant::Receive { invoice, .. }
+                    | LightningOperationMetaVariant::ReceiveReclaim { invoice, .. }
+                    | LightningOperationMetaVariant::RecurringPaymentReceive(
+                        ReurringPaymentReceiveMeta { invoice, .. },
+                    ),
+                ) => Some(invoice),
+                _ => None,
+            };
+
+            // The event is only logged once the incoming contract is funded
+            return Some(PaymentHistoryUpdate::Created(
+                payment_history::PaymentInfo {
+                    operation_id: event.operation_id,
+                    direction: PaymentDirection::Receive,
+                    amount: event.amount,
+                    fee: Amount::ZERO,
+                    status: PaymentStatus::Success,
+                    counterparty: None,
+                    payment_hash: invoice.as_ref().map(|invoice| *invoice.payment_hash()),
+                },
+            ));
+        }
+
+        None
+    }
+
     #[cfg(feature = "cli")]
     async fn handle_cli_command(
         &self,
@@ -741,6 +809,18 @@ pub enum PayBolt11InvoiceError {
 }
 
 impl LightningClientModule {
+    async fn operation_meta(
+        &self,
+        operation_id: OperationId,
+    ) -> Option<LightningOperationMetaVariant> {
+        self.client_ctx
+            .get_operation(operation_id)
+            .await
+            .ok()
+            .and_then(|operation| operation.try_meta::<LightningOperationMeta>().ok())
+            .map(|meta| meta.variant)
+    }
+
     fn new(
         args: &ClientModuleInitArgs<LightningClientInit>,
         gateway_conn: Arc<dyn GatewayConnection + Send + Sync>,
@@ -1,9 +1,11 @@
+use std::str::FromStr;
 use std::{ffi, iter};
 
 use clap::{Parser, Subcommand};
 use fedimint_core::core::OperationId;
 use fedimint_core::util::SafeUrl;
 use fedimint_core::{Amount, PeerId};
+use lightning::offers::offer::Offer;
 use lightning_invoice::Bolt11Invoice;
 use serde::Serialize;
 use serde_json::Value;
@@ -20,6 +22,27 @@ enum Opts {
         #[arg(long)]
         gateway: Option<SafeUrl>,
     },
+    /// Pay an invoice in several parts routed through different gateways. For
+    /// testing you can optionally specify the gateways to choose from,
+    /// otherwise they are selected from the vetted gateways.
+    SendMultiPath {
+        invoice: Bolt11Invoice,
+        #[arg(long, default_value_t = 2)]
+        parts: usize,
+        #[arg(long)]
+        gateway: Vec<SafeUrl>,
+    },
+    /// Pay a BOLT12 offer. The amount is required if and only if the offer
+    /// does not specify one.
+    SendOffer {
+        offer: String,
+        #[arg(long)]
+        amount: Option<Amount>,
+        #[arg(long)]
+        payer_note: Option<String>,
+        #[arg(long)]
+        gateway: Option<SafeUrl>,
+    },
     /// Await the final state of the send operation.
     AwaitSend { operation_id: OperationId },
     /// Request an invoice. For testing you can optionally specify a gateway to
@@ -30,6 +53,29 @@ enum Opts {
         #[arg(long)]
         gateway: Option<SafeUrl>,
     },
+    /// Request a single-use BOLT12 offer. For testing you can optionally
+    /// specify a gateway to issue the offer, otherwise a gateway will be
+    /// selected automatically.
+    ReceiveOffer {
+        amount: Amount,
+        #[arg(long)]
+        gateway: Option<SafeUrl>,
+    },
+    /// Request a hold invoice, such that incoming payments are held until
+    /// they are settled or cancelled. For testing you can optionally specify a
+    /// gateway to generate the invoice, otherwise a gateway will be selected
+    /// automatically.
+    ReceiveHold {
+        amount: Amount,
+        #[arg(long, default_value_t = 600)]
+        hold_secs: u32,
+        #[arg(long)]
+        gateway: Option<SafeUrl>,
+    },
+    /// Settle the held payment of a hold invoice.
+    SettleHeld { operation_id: OperationId },
+    /// Cancel the held payment of a hold invoice.
+    CancelHeld { operation_id: OperationId },
     /// Await the final state of the receive operation.
     AwaitReceive { operation_id: OperationId },
     /// Lnurl subcommands
@@ -70,6 +116,15 @@ enum GatewaysOpts {
     Add { gateway: SafeUrl },
     /// Remove a vetted gateway.
     Remove { gateway: SafeUrl },
+    /// List the scores of the gateways used for gateway selection.
+    Scores,
+    /// Override the score of a gateway, or remove the override if no score is
+    /// given. A score of zero excludes the gateway from gateway selection.
+    SetScore {
+        gateway: SafeUrl,
+        #[arg(long)]
+        score: Option<u64>,
+    },
 }
 
 pub(crate) async fn handle_cli_command(
@@ -82,6 +137,35 @@ pub(crate) async fn handle_cli_command(
         Opts::Send { gateway, invoice } => {
             json(lightning.send(invoice, gateway, Value::Null).await?)
         }
+        Opts::SendMultiPath {
+            invoice,
+            parts,
+            gateway,
+        } => json(
+            lightning
+                .send_multi_path(
+                    invoice,
+                    parts,
+                    (!gateway.is_empty()).then_some(gateway),
+                    Value::Null,
+                )
+                .await?,
+        ),
+        Opts::SendOffer {
+            offer,
+            amount,
+            payer_note,
+            gateway,
+        } => {
+            let offer = Offer::from_str(&offer)
+                .map_err(|e| anyhow::anyhow!("Failed to parse offer: {e:?}"))?;
+
+            json(
+                lightning
+                    .send_offer(offer, amount, payer_note, gateway, Value::Null)
+                    .await?,
+            )
+        }
         Opts::AwaitSend { operation_id } => json(
             lightning
                 .await_final_send_operation_state(operation_id)
@@ -98,6 +182,35 @@ pub(crate) async fn handle_cli_command(
                 )
                 .await?,
         ),
+        Opts::ReceiveOffer { amount, gateway } => {
+            let (offer, operation_id) = lightning
+                .receive_offer(amount, 3600, String::new(), gateway, Value::Null)
+                .await?;
+
+            json((offer.to_string(), operation_id))
+        }
+        Opts::ReceiveHold {
+            amount,
+            hold_secs,
+            gateway,
+        } => json(
+            lightning
+                .receive_hold(
+                    amount,
+                    3600,
+                    hold_secs,
+                    Bolt11InvoiceDescription::Direct(String::new()),
+                    gateway,
+                    Value::Null,
+                )
+                .await?,
+        ),
+        Opts::SettleHeld { operation_id } => {
+            json(lightning.settle_held_payment(operation_id).await?)
+        }
+        Opts::CancelHeld { operation_id } => {
+            json(lightning.cancel_held_payment(operation_id).await?)
+        }
         Opts::AwaitReceive { operation_id } => json(
             lightning
                 .await_final_receive_operation_state(operation_id)
@@ -130,6 +243,10 @@ pub(crate) async fn handle_cli_command(
 
                 json(lightning.module_api.remove_gateway(auth, gateway).await?)
             }
+            GatewaysOpts::Scores => json(lightning.gateway_scores().await),
+            GatewaysOpts::SetScore { gateway, score } => {
+                json(lightning.set_gateway_score_override(gateway, score).await)
+            }
         },
     };
 
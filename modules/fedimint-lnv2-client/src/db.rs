@@ -1,14 +1,18 @@
 use fedimint_core::encoding::{Decodable, Encodable};
-use fedimint_core::impl_db_record;
 use fedimint_core::secp256k1::PublicKey;
 use fedimint_core::util::SafeUrl;
+use fedimint_core::{impl_db_lookup, impl_db_record};
 use strum::EnumIter;
 
+use crate::GatewayStats;
+
 #[repr(u8)]
 #[derive(Clone, EnumIter, Debug)]
 pub enum DbKeyPrefix {
     Gateway = 0x41,
     IncomingContractStreamIndex = 0x42,
+    GatewayStats = 0x43,
+    GatewayScoreOverride = 0x44,
     /// Prefixes between 0xb0..=0xcf shall all be considered allocated for
     /// historical and future external use
     ExternalReservedStart = 0xb0,
@@ -35,3 +39,37 @@ pub enum DbKeyPrefix {
     value = u64,
     db_prefix = DbKeyPrefix::IncomingContractStreamIndex
 );
+
+/// Outcomes of the payments we sent through the gateway at this url.
+#[derive(Debug, Encodable, Decodable)]
+pub struct GatewayStatsKey(pub SafeUrl);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct GatewayStatsPrefix;
+
+impl_db_record!(
+    key = GatewayStatsKey,
+    value = GatewayStats,
+    db_prefix = DbKeyPrefix::GatewayStats,
+);
+
+impl_db_lookup!(key = GatewayStatsKey, query_prefix = GatewayStatsPrefix);
+
+/// A score set by the integrator that replaces the score we compute for the
+/// gateway at this url.
+#[derive(Debug, Encodable, Decodable)]
+pub struct GatewayScoreOverrideKey(pub SafeUrl);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct GatewayScoreOverridePrefix;
+
+impl_db_record!(
+    key = GatewayScoreOverrideKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::GatewayScoreOverride,
+);
+
+impl_db_lookup!(
+    key = GatewayScoreOverrideKey,
+    query_prefix = GatewayScoreOverridePrefix
+);
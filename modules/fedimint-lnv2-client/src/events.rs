@@ -56,3 +56,16 @@ impl Event for ReceivePaymentEvent {
     const KIND: EventKind = EventKind::from_static("payment-receive");
     const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
 }
+
+/// Event emitted when the gateway holds a payment for a hold invoice until the
+/// recipient settles or cancels it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReceivePaymentHeldEvent {
+    pub operation_id: OperationId,
+}
+
+impl Event for ReceivePaymentHeldEvent {
+    const MODULE: Option<ModuleKind> = Some(fedimint_lnv2_common::KIND);
+    const KIND: EventKind = EventKind::from_static("payment-receive-held");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
@@ -0,0 +1,227 @@
+use std::time::Duration;
+
+use fedimint_core::Amount;
+use fedimint_core::db::{DatabaseTransaction, IDatabaseTransactionOpsCoreTyped};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::util::SafeUrl;
+use serde::{Deserialize, Serialize};
+
+use crate::db::GatewayStatsKey;
+
+/// The highest score of a gateway. Gateways with an override score of zero are
+/// never selected automatically.
+pub const MAX_GATEWAY_SCORE: u64 = 1000;
+
+/// Number of recorded payments and unresponsive requests at which all stats of
+/// a gateway are halved, such that its score follows its recent behaviour and
+/// a gateway recovers from past downtime.
+const STATS_WINDOW: u64 = 64;
+
+/// The average latency at which a gateway's score is halved.
+const LATENCY_HALVING_MS: u64 = 30_000;
+
+/// The average fee in parts per million at which a gateway's score is halved.
+const FEE_HALVING_PPM: u64 = 50_000;
+
+/// Outcomes of the payments we sent through a gateway, recorded by the send
+/// state machine once a payment succeeds or is refunded.
+///
+/// The fee of an outgoing contract is fixed by the gateway's quote when we
+/// fund it, so the fee the gateway charges always equals the quoted fee and we
+/// only track the fees we paid.
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, Hash, Encodable, Decodable, Serialize, Deserialize,
+)]
+pub struct GatewayStats {
+    /// Payments the gateway completed.
+    pub successes: u64,
+    /// Payments the gateway did not complete, such that we refunded the
+    /// contract.
+    pub refunds: u64,
+    /// Times the gateway did not respond to a request for its routing info
+    /// while we selected a gateway.
+    pub unresponsive: u64,
+    /// Number of completed payments for which we measured the latency. The
+    /// gateway's response is lost if we restart while awaiting it.
+    pub latency_samples: u64,
+    /// Sum of the time from requesting the payment to the gateway's response
+    /// over all latency samples.
+    pub total_latency_ms: u64,
+    /// Sum of the amounts of all completed payments, excluding fees.
+    pub amount_sent: Amount,
+    /// Sum of the fees we paid for all completed payments.
+    pub fees_paid: Amount,
+}
+
+impl GatewayStats {
+    pub(crate) fn record_success(
+        &mut self,
+        amount: Amount,
+        fee: Amount,
+        latency: Option<Duration>,
+    ) {
+        self.successes = self.successes.saturating_add(1);
+        self.amount_sent += amount;
+        self.fees_paid += fee;
+
+        if let Some(latency) = latency {
+            self.latency_samples = self.latency_samples.saturating_add(1);
+            self.total_latency_ms = self
+                .total_latency_ms
+                .saturating_add(u64::try_from(latency.as_millis()).unwrap_or(u64::MAX));
+        }
+    }
+
+    pub(crate) fn record_refund(&mut self) {
+        self.refunds = self.refunds.saturating_add(1);
+    }
+
+    pub(crate) fn record_unresponsive(&mut self) {
+        self.unresponsive = self.unresponsive.saturating_add(1);
+    }
+
+    /// Halves all stats once the gateway's recorded outcomes reach
+    /// [`STATS_WINDOW`], which preserves its averages.
+    fn decay(&mut self) {
+        let outcomes = self
+            .successes
+            .saturating_add(self.refunds)
+            .saturating_add(self.unresponsive);
+
+        if outcomes < STATS_WINDOW {
+            return;
+        }
+
+        self.successes /= 2;
+        self.refunds /= 2;
+        self.unresponsive /= 2;
+        self.latency_samples /= 2;
+        self.total_latency_ms /= 2;
+        self.amount_sent = Amount::from_msats(self.amount_sent.msats / 2);
+        self.fees_paid = Amount::from_msats(self.fees_paid.msats / 2);
+    }
+
+    /// The average time the gateway took to complete a payment.
+    pub fn average_latency(&self) -> Option<Duration> {
+        self.total_latency_ms
+            .checked_div(self.latency_samples)
+            .map(Duration::from_millis)
+    }
+
+    /// The average fee we paid relative to the amount sent in parts per
+    /// million.
+    pub fn average_fee_ppm(&self) -> Option<u64> {
+        self.fees_paid
+            .msats
+            .saturating_mul(1_000_000)
+            .checked_div(self.amount_sent.msats)
+    }
+
+    /// Scores the gateway between zero and [`MAX_GATEWAY_SCORE`] as the
+    /// product of its success rate, its availability and penalties for high
+    /// latency and fees. The rates are smoothed such that a gateway we have
+    /// not used yet scores half of the maximum and a handful of failures
+    /// rank a gateway below untested ones.
+    pub fn score(&self) -> u64 {
+        let attempts = self.successes.saturating_add(self.refunds);
+
+        let success_rate = ratio(self.successes.saturating_add(1), attempts.saturating_add(2));
+
+        let availability = ratio(
+            attempts.saturating_add(1),
+            attempts.saturating_add(self.unresponsive).saturating_add(1),
+        );
+
+        let latency_penalty = self.average_latency().map_or(MAX_GATEWAY_SCORE, |latency| {
+            ratio(
+                LATENCY_HALVING_MS,
+                LATENCY_HALVING_MS
+                    .saturating_add(u64::try_from(latency.as_millis()).unwrap_or(u64::MAX)),
+            )
+        });
+
+        let fee_penalty = self.average_fee_ppm().map_or(MAX_GATEWAY_SCORE, |fee_ppm| {
+            ratio(FEE_HALVING_PPM, FEE_HALVING_PPM.saturating_add(fee_ppm))
+        });
+
+        [availability, latency_penalty, fee_penalty]
+            .into_iter()
+            .fold(success_rate, |score, factor| {
+                score * factor / MAX_GATEWAY_SCORE
+            })
+    }
+}
+
+/// The ratio of two numbers scaled to [`MAX_GATEWAY_SCORE`].
+fn ratio(numerator: u64, denominator: u64) -> u64 {
+    numerator.saturating_mul(MAX_GATEWAY_SCORE) / denominator.max(1)
+}
+
+/// The score of a gateway as used for gateway selection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GatewayScore {
+    pub stats: GatewayStats,
+    /// The score computed from the gateway's stats.
+    pub computed_score: u64,
+    /// The score set via
+    /// [`LightningClientModule::set_gateway_score_override`](crate::LightningClientModule::set_gateway_score_override),
+    /// which takes precedence over the computed score.
+    pub override_score: Option<u64>,
+}
+
+impl GatewayScore {
+    /// The score used for gateway selection.
+    pub fn score(&self) -> u64 {
+        self.override_score.unwrap_or(self.computed_score)
+    }
+}
+
+/// Updates the stats of the gateway at the url with the outcome of a payment.
+pub(crate) async fn update_gateway_stats(
+    dbtx: &mut DatabaseTransaction<'_>,
+    gateway: &SafeUrl,
+    update: impl FnOnce(&mut GatewayStats),
+) {
+    let mut stats = dbtx
+        .get_value(&GatewayStatsKey(gateway.clone()))
+        .await
+        .unwrap_or_default();
+
+    update(&mut stats);
+
+    stats.decay();
+
+    dbtx.insert_entry(&GatewayStatsKey(gateway.clone()), &stats)
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GatewayStats, STATS_WINDOW};
+
+    #[test]
+    fn unresponsive_gateway_recovers_once_it_completes_payments() {
+        let mut stats = GatewayStats::default();
+
+        for _ in 0..10_000 {
+            stats.record_unresponsive();
+            stats.decay();
+        }
+
+        assert!(stats.unresponsive < STATS_WINDOW);
+
+        let degraded = stats.score();
+
+        for _ in 0..STATS_WINDOW {
+            stats.record_success(
+                fedimint_core::Amount::from_sats(1000),
+                fedimint_core::Amount::ZERO,
+                None,
+            );
+            stats.decay();
+        }
+
+        assert!(degraded < stats.score());
+        assert!(GatewayStats::default().score() < stats.score());
+    }
+}
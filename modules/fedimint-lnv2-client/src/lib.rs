@@ -5,12 +5,14 @@
 #![allow(clippy::must_use_candidate)]
 
 pub use fedimint_lnv2_common as common;
+pub use gateway_score::{GatewayScore, GatewayStats, MAX_GATEWAY_SCORE};
 
 mod api;
 #[cfg(feature = "cli")]
 mod cli;
 pub mod db;
 pub mod events;
+mod gateway_score;
 mod receive_sm;
 mod send_sm;
 
@@ -18,14 +20,21 @@
 use std::sync::Arc;
 
 use async_stream::stream;
+use bitcoin::constants::ChainHash;
 use bitcoin::hashes::{Hash, sha256};
-use bitcoin::secp256k1;
-use db::{DbKeyPrefix, GatewayKey, IncomingContractStreamIndexKey};
+use bitcoin::{Network, secp256k1};
+use db::{
+    DbKeyPrefix, GatewayKey, GatewayScoreOverrideKey, GatewayScoreOverridePrefix,
+    GatewayStatsPrefix, IncomingContractStreamIndexKey,
+};
 use fedimint_api_client::api::DynModuleApi;
 use fedimint_client_module::module::init::{ClientModuleInit, ClientModuleInitArgs};
 use fedimint_client_module::module::recovery::NoModuleBackup;
 use fedimint_client_module::module::{ClientContext, ClientModule, OutPointRange};
 use fedimint_client_module::oplog::UpdateStreamOrOutcome;
+use fedimint_client_module::payment_history::{
+    PaymentDirection, PaymentHistoryUpdate, PaymentInfo, PaymentStatus,
+};
 use fedimint_client_module::sm::{Context, DynState, ModuleNotifier, State, StateTransition};
 use fedimint_client_module::transaction::{
     ClientOutput, ClientOutputBundle, ClientOutputSM, FeeQuote, FeeQuoteRequest,
@@ -45,10 +54,12 @@
 use fedimint_core::util::SafeUrl;
 use fedimint_core::{Amount, PeerId, apply, async_trait_maybe_send};
 use fedimint_derive_secret::{ChildId, DerivableSecret};
+use fedimint_eventlog::EventLogEntry;
 use fedimint_lnv2_common::config::LightningClientConfig;
 use fedimint_lnv2_common::contracts::{IncomingContract, OutgoingContract, PaymentImage};
 use fedimint_lnv2_common::gateway_api::{
-    GatewayConnection, PaymentFee, RealGatewayConnection, RoutingInfo,
+    GatewayConnection, HoldInvoiceStatus, PaymentFee, RealGatewayConnection,
+    ResolveHoldInvoicePayload, RoutingInfo, SwapQuote, SwapStatus,
 };
 use fedimint_lnv2_common::{
     Bolt11InvoiceDescription, GatewayApi, KIND, LightningCommonInit, LightningInvoice,
@@ -56,6 +67,8 @@
     lnurl, tweak,
 };
 use futures::StreamExt;
+use lightning::offers::invoice::Bolt12Invoice;
+use lightning::offers::offer::{Amount as OfferAmount, Offer};
 use lightning_invoice::{Bolt11Invoice, Currency};
 use secp256k1::{Keypair, PublicKey, Scalar, SecretKey, ecdh};
 use serde::{Deserialize, Serialize};
@@ -66,7 +79,9 @@
 use tracing::warn;
 
 use crate::api::LightningFederationApi;
-use crate::events::SendPaymentEvent;
+use crate::events::{
+    ReceivePaymentEvent, SendPaymentEvent, SendPaymentStatus, SendPaymentUpdateEvent,
+};
 use crate::receive_sm::{ReceiveSMCommon, ReceiveSMState, ReceiveStateMachine};
 use crate::send_sm::{SendSMCommon, SendSMState, SendStateMachine};
 
@@ -77,12 +92,21 @@
 /// A two hour buffer in case either the client or gateway go offline
 const CONTRACT_CONFIRMATION_BUFFER: u64 = 12;
 
+/// Expiry of the incoming contract of a swap. The gateway funds it as soon as
+/// the outgoing contract is confirmed, so it only has to outlast the funding.
+const SWAP_EXPIRY_SECS: u32 = 60 * 60;
+
+/// Maximum number of gateways a multi-path payment is split across.
+const MAX_MULTI_PATH_PARTS: usize = 8;
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum LightningOperationMeta {
     Send(SendOperationMeta),
     Receive(ReceiveOperationMeta),
     LnurlReceive(LnurlReceiveOperationMeta),
+    OfferReceive(OfferReceiveOperationMeta),
+    MultiPathSend(MultiPathSendOperationMeta),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,31 +121,80 @@ pub struct SendOperationMeta {
 impl SendOperationMeta {
     /// Calculate the absolute fee paid to the gateway on success.
     pub fn gateway_fee(&self) -> Amount {
-        match &self.invoice {
-            LightningInvoice::Bolt11(invoice) => self.contract.amount.saturating_sub(
-                Amount::from_msats(invoice.amount_milli_satoshis().expect("Invoice has amount")),
-            ),
-        }
+        self.contract.amount.saturating_sub(Amount::from_msats(
+            self.invoice
+                .amount_milli_satoshis()
+                .expect("Invoice has amount"),
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiPathSendOperationMeta {
+    pub change_outpoint_range: OutPointRange,
+    pub invoice: LightningInvoice,
+    pub parts: Vec<SendPartMeta>,
+    pub custom_meta: Value,
+}
+
+impl MultiPathSendOperationMeta {
+    /// Calculate the absolute fee paid to all gateways on success.
+    pub fn gateway_fee(&self) -> Amount {
+        self.parts
+            .iter()
+            .map(|part| part.contract.amount.saturating_sub(part.amount))
+            .sum()
     }
 }
 
+/// A part of a multi-path payment, paid by a single gateway in exchange for
+/// its outgoing contract.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendPartMeta {
+    pub gateway: SafeUrl,
+    pub contract: OutgoingContract,
+    /// The amount of the invoice paid by this part.
+    pub amount: Amount,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReceiveOperationMeta {
     pub gateway: SafeUrl,
     pub contract: IncomingContract,
     pub invoice: LightningInvoice,
     pub custom_meta: Value,
+    /// Whether the gateway holds payments for the invoice until we settle or
+    /// cancel them, see [`LightningClientModule::receive_hold`].
+    #[serde(default)]
+    pub hold: bool,
 }
 
 impl ReceiveOperationMeta {
     /// Calculate the absolute fee paid to the gateway on success.
     pub fn gateway_fee(&self) -> Amount {
-        match &self.invoice {
-            LightningInvoice::Bolt11(invoice) => {
-                Amount::from_msats(invoice.amount_milli_satoshis().expect("Invoice has amount"))
-                    .saturating_sub(self.contract.commitment.amount)
-            }
-        }
+        Amount::from_msats(
+            self.invoice
+                .amount_milli_satoshis()
+                .expect("Invoice has amount"),
+        )
+        .saturating_sub(self.contract.commitment.amount)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OfferReceiveOperationMeta {
+    pub gateway: SafeUrl,
+    pub contract: IncomingContract,
+    /// The bech32 encoded offer
+    pub offer: String,
+    pub amount: Amount,
+    pub custom_meta: Value,
+}
+
+impl OfferReceiveOperationMeta {
+    /// Calculate the absolute fee paid to the gateway on success.
+    pub fn gateway_fee(&self) -> Amount {
+        self.amount.saturating_sub(self.contract.commitment.amount)
     }
 }
 
@@ -188,6 +261,9 @@ pub enum FinalSendOperationState {
 ///
 ///     Pending -- payment is confirmed --> Claiming
 ///     Pending -- invoice expires --> Expired
+///     Pending -- gateway holds payment --> Held
+///     Held -- payment is settled and confirmed --> Claiming
+///     Held -- payment is cancelled or expires --> Expired
 ///     Claiming -- ecash is minted --> Claimed
 ///     Claiming -- minting ecash fails --> Failure
 /// ```
@@ -195,6 +271,9 @@ pub enum FinalSendOperationState {
 pub enum ReceiveOperationState {
     /// We are waiting for the payment.
     Pending,
+    /// The gateway holds the payment for a hold invoice until we settle or
+    /// cancel it.
+    Held,
     /// The payment request has expired.
     Expired,
     /// The payment has been confirmed and we are issuing the ecash.
@@ -218,6 +297,17 @@ pub enum FinalReceiveOperationState {
 
 pub type ReceiveResult = Result<(Bolt11Invoice, OperationId), ReceiveError>;
 
+/// The operations of a swap, in the source and the target federation.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SwapOperationIds {
+    /// The send operation in the source federation, see
+    /// [`LightningClientModule::await_final_send_operation_state`].
+    pub send_operation_id: OperationId,
+    /// The receive operation in the target federation, see
+    /// [`LightningClientModule::await_final_receive_operation_state`].
+    pub receive_operation_id: OperationId,
+}
+
 #[derive(Clone)]
 pub struct LightningClientInit {
     pub gateway_conn: Option<Arc<dyn GatewayConnection + Send + Sync>>,
@@ -366,9 +456,110 @@ async fn handle_cli_command(
     ) -> anyhow::Result<serde_json::Value> {
         cli::handle_cli_command(self, args).await
     }
+
+    async fn payment_history_update(&self, event: &EventLogEntry) -> Option<PaymentHistoryUpdate> {
+        if let Some(event) = event.to_event_of_kind::<SendPaymentEvent>() {
+            let invoice = match self.operation_meta(event.operation_id).await {
+                Some(LightningOperationMeta::Send(meta)) => Some(meta.invoice),
+                Some(LightningOperationMeta::MultiPathSend(meta)) => Some(meta.invoice),
+                _ => None,
+            };
+
+            return Some(PaymentHistoryUpdate::Created(PaymentInfo {
+                operation_id: event.operation_id,
+                direction: PaymentDirection::Send,
+                amount: event.amount,
+                fee: event.fee,
+                status: PaymentStatus::Pending,
+                counterparty: invoice.as_ref().map(invoice_payee),
+                payment_hash: invoice.as_ref().map(invoice_payment_hash),
+            }));
+        }
+
+        if let Some(event) = event.to_event_of_kind::<SendPaymentUpdateEvent>() {
+            return Some(PaymentHistoryUpdate::Status {
+                operation_id: event.operation_id,
+                status: match event.status {
+                    SendPaymentStatus::Success(..) => PaymentStatus::Success,
+                    SendPaymentStatus::Refunded => PaymentStatus::Failed,
+                },
+            });
+        }
+
+        if let Some(event) = event.to_event_of_kind::<ReceivePaymentEvent>() {
+            let payment_hash = match self.operation_meta(event.operation_id).await {
+                Some(LightningOperationMeta::Receive(meta)) => {
+                    Some(invoice_payment_hash(&meta.invoice))
+                }
+                Some(LightningOperationMeta::OfferReceive(meta)) => {
+                    match meta.contract.commitment.payment_image {
+                        PaymentImage::Hash(payment_hash) => Some(payment_hash),
+                        PaymentImage::Point(..) => None,
+                    }
+                }
+                _ => None,
+            };
+
+            // The event is only logged once the contract is claimed
+            return Some(PaymentHistoryUpdate::Created(PaymentInfo {
+                operation_id: event.operation_id,
+                direction: PaymentDirection::Receive,
+                amount: event.amount,
+                fee: event.fee,
+                status: PaymentStatus::Success,
+                counterparty: None,
+                payment_hash,
+            }));
+        }
+
+        None
+    }
+}
+
+fn invoice_payee(invoice: &LightningInvoice) -> String {
+    invoice.payee_pub_key().to_string()
+}
+
+fn invoice_payment_hash(invoice: &LightningInvoice) -> sha256::Hash {
+    invoice.payment_hash()
+}
+
+/// Checks that the invoice echoes the offer it has been requested for and has
+/// been signed by the offer's issuer, such that the gateway cannot substitute
+/// an invoice issued by another recipient.
+fn invoice_matches_offer(invoice: &Bolt12Invoice, offer: &Offer, network: Network) -> bool {
+    if invoice.chain() != ChainHash::using_genesis_block(network)
+        || invoice.issuer_signing_pubkey() != offer.issuer_signing_pubkey()
+        || invoice.message_paths() != offer.paths()
+        || invoice.metadata() != offer.metadata()
+        || invoice.amount() != offer.amount()
+        || invoice.description() != offer.description()
+    {
+        return false;
+    }
+
+    // Offers without a signing key are only reachable via blinded paths, in
+    // which case the invoice is signed with the blinded id of a path's
+    // recipient. If the offer has neither, we cannot verify the invoice.
+    match offer.issuer_signing_pubkey() {
+        Some(pubkey) => invoice.signing_pubkey() == pubkey,
+        None => offer.paths().iter().any(|path| {
+            path.blinded_hops()
+                .last()
+                .is_some_and(|hop| hop.blinded_node_id == invoice.signing_pubkey())
+        }),
+    }
 }
 
 impl LightningClientModule {
+    async fn operation_meta(&self, operation_id: OperationId) -> Option<LightningOperationMeta> {
+        self.client_ctx
+            .get_operation(operation_id)
+            .await
+            .ok()
+            .and_then(|operation| operation.try_meta().ok())
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn new(
         federation_id: FederationId,
@@ -450,10 +641,22 @@ async fn update_gateway_map(&self) {
     /// Selects an available gateway by querying the federation's registered
     /// gateways, checking if one of them match the invoice's payee public
     /// key, then queries the gateway for `RoutingInfo` to determine if it is
-    /// online.
+    /// online. Otherwise, we try the gateways in the order of their score,
+    /// skipping the ones with an override score of zero.
     pub async fn select_gateway(
         &self,
         invoice: Option<Bolt11Invoice>,
+    ) -> Result<(SafeUrl, RoutingInfo), SelectGatewayError> {
+        self.select_gateway_with_capabilities(invoice, false).await
+    }
+
+    /// Selects an available gateway like [`Self::select_gateway`], skipping
+    /// the gateways whose lightning node can not send or receive BOLT12
+    /// payments if `requires_bolt12` is set.
+    async fn select_gateway_with_capabilities(
+        &self,
+        invoice: Option<Bolt11Invoice>,
+        requires_bolt12: bool,
     ) -> Result<(SafeUrl, RoutingInfo), SelectGatewayError> {
         let gateways = self
             .module_api
@@ -465,6 +668,8 @@ pub async fn select_gateway(
             return Err(SelectGatewayError::NoGatewaysAvailable);
         }
 
+        let gateways = self.rank_gateways(gateways).await;
+
         if let Some(invoice) = invoice
             && let Some(gateway) = self
                 .client_ctx
@@ -475,19 +680,139 @@ pub async fn select_gateway(
                 .await
                 .filter(|gateway| gateways.contains(gateway))
             && let Ok(Some(routing_info)) = self.routing_info(&gateway).await
+            && (routing_info.supports_bolt12 || !requires_bolt12)
         {
             return Ok((gateway, routing_info));
         }
 
+        let mut incapable = false;
+
         for gateway in gateways {
-            if let Ok(Some(routing_info)) = self.routing_info(&gateway).await {
-                return Ok((gateway, routing_info));
+            match self.routing_info(&gateway).await {
+                Ok(Some(routing_info)) if requires_bolt12 && !routing_info.supports_bolt12 => {
+                    incapable = true;
+                }
+                Ok(Some(routing_info)) => return Ok((gateway, routing_info)),
+                Ok(None) => {}
+                Err(..) => self.record_unresponsive_gateway(&gateway).await,
             }
         }
 
+        if incapable {
+            return Err(SelectGatewayError::NoBolt12GatewaysAvailable);
+        }
+
         Err(SelectGatewayError::GatewaysUnresponsive)
     }
 
+    /// Orders the gateways by descending score and drops the ones excluded by
+    /// an override score of zero. A gateway whose computed score dropped to
+    /// zero is still tried last, such that it can recover. Gateways with the
+    /// same score keep their relative order.
+    async fn rank_gateways(&self, gateways: Vec<SafeUrl>) -> Vec<SafeUrl> {
+        let scores = self.gateway_scores().await;
+
+        let unused_score = GatewayStats::default().score();
+
+        let score = |gateway: &SafeUrl| {
+            scores
+                .get(gateway)
+                .map_or(unused_score, GatewayScore::score)
+        };
+
+        let mut gateways = gateways
+            .into_iter()
+            .filter(|gateway| {
+                scores
+                    .get(gateway)
+                    .is_none_or(|score| score.override_score != Some(0))
+            })
+            .collect::<Vec<_>>();
+
+        gateways.sort_by_key(|gateway| std::cmp::Reverse(score(gateway)));
+
+        gateways
+    }
+
+    async fn record_unresponsive_gateway(&self, gateway: &SafeUrl) {
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+        gateway_score::update_gateway_stats(
+            &mut dbtx.to_ref_nc(),
+            gateway,
+            GatewayStats::record_unresponsive,
+        )
+        .await;
+
+        if let Err(e) = dbtx.commit_tx_result().await {
+            warn!("Failed to commit the updated gateway stats to the database: {e}");
+        }
+    }
+
+    /// Returns the score of every gateway we have sent a payment through or
+    /// assigned a score to. Gateways without an entry are scored as
+    /// `GatewayStats::default().score()`.
+    pub async fn gateway_scores(&self) -> BTreeMap<SafeUrl, GatewayScore> {
+        let mut dbtx = self.client_ctx.module_db().begin_transaction_nc().await;
+
+        let mut scores = dbtx
+            .find_by_prefix(&GatewayStatsPrefix)
+            .await
+            .map(|(key, stats)| {
+                let score = GatewayScore {
+                    computed_score: stats.score(),
+                    stats,
+                    override_score: None,
+                };
+
+                (key.0, score)
+            })
+            .collect::<BTreeMap<_, _>>()
+            .await;
+
+        let overrides = dbtx
+            .find_by_prefix(&GatewayScoreOverridePrefix)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        for (key, override_score) in overrides {
+            scores
+                .entry(key.0)
+                .or_insert_with(|| GatewayScore {
+                    stats: GatewayStats::default(),
+                    computed_score: GatewayStats::default().score(),
+                    override_score: None,
+                })
+                .override_score = Some(override_score);
+        }
+
+        scores
+    }
+
+    /// Replaces the score we compute for the gateway with a fixed score up to
+    /// [`MAX_GATEWAY_SCORE`], or removes a previous override if the score is
+    /// `None`. A score of zero excludes the gateway from automatic gateway
+    /// selection, while it can still be chosen explicitly.
+    pub async fn set_gateway_score_override(&self, gateway: SafeUrl, score: Option<u64>) {
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+        match score {
+            Some(score) => {
+                dbtx.insert_entry(
+                    &GatewayScoreOverrideKey(gateway),
+                    &score.min(MAX_GATEWAY_SCORE),
+                )
+                .await;
+            }
+            None => {
+                dbtx.remove_entry(&GatewayScoreOverrideKey(gateway)).await;
+            }
+        }
+
+        dbtx.commit_tx().await;
+    }
+
     /// Sends a request to each peer for their registered gateway list and
     /// returns a `Vec<SafeUrl` of all registered gateways to the client.
     pub async fn list_gateways(
@@ -535,13 +860,116 @@ pub async fn routing_info(
     ///
     /// The absolute fee for a payment can be calculated from the operation meta
     /// to be shown to the user in the transaction history.
-    #[allow(clippy::too_many_lines)]
     pub async fn send(
         &self,
         invoice: Bolt11Invoice,
         gateway: Option<SafeUrl>,
         custom_meta: Value,
     ) -> Result<OperationId, SendPaymentError> {
+        let amount = self.validate_invoice(&invoice)?;
+
+        // The attempt index is fixed at `0` so the operation id matches the one
+        // older clients derived for the first payment attempt, ensuring an
+        // already-paid or in-flight invoice is still detected after an upgrade.
+        let operation_id = OperationId::from_encodable(&(invoice.clone(), 0u64));
+
+        if self.client_ctx.operation_exists(operation_id).await {
+            return Err(SendPaymentError::DuplicatePaymentAttempt(operation_id));
+        }
+
+        let (gateway_api, routing_info) = match gateway {
+            Some(gateway_api) => (
+                gateway_api.clone(),
+                self.routing_info(&gateway_api)
+                    .await
+                    .map_err(|e| SendPaymentError::FailedToConnectToGateway(e.to_string()))?
+                    .ok_or(SendPaymentError::FederationNotSupported)?,
+            ),
+            None => self
+                .select_gateway(Some(invoice.clone()))
+                .await
+                .map_err(SendPaymentError::SelectGateway)?,
+        };
+
+        self.fund_outgoing_contract(
+            operation_id,
+            LightningInvoice::Bolt11(invoice),
+            amount,
+            gateway_api,
+            routing_info,
+            custom_meta,
+        )
+        .await
+    }
+
+    /// Pay an invoice in several parts, each routed through a different gateway,
+    /// for payments that exceed the outbound liquidity of a single gateway. The
+    /// invoice amount is split evenly across `num_parts` online gateways. For
+    /// testing you can optionally specify the gateways to choose from,
+    /// otherwise they are chosen from the federation's vetted gateways.
+    ///
+    /// Every part is secured by its own outgoing contract and all contracts
+    /// are funded in a single transaction. The gateways pay their parts as a
+    /// multi-path payment over lightning, so the payee only releases the
+    /// preimage once all parts have arrived: either the payment succeeds as a
+    /// whole or every contract is refunded. The operation reports the combined
+    /// state of all parts.
+    ///
+    /// The fee limits of [`Self::send`] apply to every part.
+    pub async fn send_multi_path(
+        &self,
+        invoice: Bolt11Invoice,
+        num_parts: usize,
+        gateways: Option<Vec<SafeUrl>>,
+        custom_meta: Value,
+    ) -> Result<OperationId, SendPaymentError> {
+        let amount = self.validate_invoice(&invoice)?;
+
+        if !(2..=MAX_MULTI_PATH_PARTS).contains(&num_parts) || amount < num_parts as u64 {
+            return Err(SendPaymentError::InvalidNumberOfParts);
+        }
+
+        // Multi-path payments share the operation id with single-path payments
+        // of the same invoice, such that neither can pay an invoice twice.
+        let operation_id = OperationId::from_encodable(&(invoice.clone(), 0u64));
+
+        if self.client_ctx.operation_exists(operation_id).await {
+            return Err(SendPaymentError::DuplicatePaymentAttempt(operation_id));
+        }
+
+        let gateways = self
+            .select_part_gateways(&invoice, num_parts, gateways)
+            .await?;
+
+        let part_amount = amount / num_parts as u64;
+
+        let parts = gateways
+            .into_iter()
+            .enumerate()
+            .map(|(index, (gateway_api, routing_info))| {
+                // The first part pays the remainder of the division.
+                let amount = if index == 0 {
+                    amount - part_amount * (num_parts as u64 - 1)
+                } else {
+                    part_amount
+                };
+
+                (gateway_api, routing_info, Amount::from_msats(amount))
+            })
+            .collect();
+
+        self.fund_part_contracts(
+            operation_id,
+            LightningInvoice::Bolt11(invoice),
+            amount,
+            parts,
+            custom_meta,
+        )
+        .await
+    }
+
+    /// Checks that we can pay the invoice and returns its amount.
+    fn validate_invoice(&self, invoice: &Bolt11Invoice) -> Result<u64, SendPaymentError> {
         let amount = invoice
             .amount_milli_satoshis()
             .ok_or(SendPaymentError::InvoiceMissingAmount)?;
@@ -557,20 +985,102 @@ pub async fn send(
             });
         }
 
-        // The attempt index is fixed at `0` so the operation id matches the one
-        // older clients derived for the first payment attempt, ensuring an
-        // already-paid or in-flight invoice is still detected after an upgrade.
-        let operation_id = OperationId::from_encodable(&(invoice.clone(), 0u64));
+        Ok(amount)
+    }
 
-        if self.client_ctx.operation_exists(operation_id).await {
-            return Err(SendPaymentError::DuplicatePaymentAttempt(operation_id));
+    /// Selects `num_parts` online gateways with distinct lightning nodes to
+    /// pay the parts of a multi-path payment. Gateways whose node is the payee
+    /// of the invoice are skipped, since they could only settle the whole
+    /// payment as a direct swap, as are gateways whose node cannot pay a part
+    /// of an invoice. The federation's vetted gateways are tried in
+    /// the order of their score.
+    async fn select_part_gateways(
+        &self,
+        invoice: &Bolt11Invoice,
+        num_parts: usize,
+        gateways: Option<Vec<SafeUrl>>,
+    ) -> Result<Vec<(SafeUrl, RoutingInfo)>, SendPaymentError> {
+        let candidates = match gateways {
+            Some(gateways) => gateways,
+            None => {
+                let gateways = self.module_api.gateways().await.map_err(|e| {
+                    SendPaymentError::SelectGateway(SelectGatewayError::FailedToRequestGateways(
+                        e.to_string(),
+                    ))
+                })?;
+
+                self.rank_gateways(gateways).await
+            }
+        };
+
+        let payee = invoice.recover_payee_pub_key();
+
+        let mut selected: Vec<(SafeUrl, RoutingInfo)> = Vec::new();
+
+        for gateway in candidates {
+            if selected.len() == num_parts {
+                break;
+            }
+
+            let Ok(Some(routing_info)) = self.routing_info(&gateway).await else {
+                continue;
+            };
+
+            if routing_info.lightning_public_key == payee || !routing_info.supports_partial_payments
+            {
+                continue;
+            }
+
+            if selected.iter().any(|(_, selected)| {
+                selected.lightning_public_key == routing_info.lightning_public_key
+            }) {
+                continue;
+            }
+
+            selected.push((gateway, routing_info));
         }
 
-        let (ephemeral_tweak, ephemeral_pk) = tweak::generate(self.keypair.public_key());
+        if selected.len() < num_parts {
+            return Err(SendPaymentError::NotEnoughGateways);
+        }
 
-        let refund_keypair = SecretKey::from_slice(&ephemeral_tweak)
-            .expect("32 bytes, within curve order")
-            .keypair(secp256k1::SECP256K1);
+        Ok(selected)
+    }
+
+    /// Pay a BOLT12 offer. Since the payment hash of an offer is only known
+    /// once its issuer has responded with an invoice, the gateway is selected
+    /// first and then requested to fetch the invoice for us. The outgoing
+    /// contract is bound to the payment hash of this invoice.
+    ///
+    /// The amount has to be specified if and only if the offer does not
+    /// specify one itself. The same fee limits as for [`Self::send`] apply.
+    pub async fn send_offer(
+        &self,
+        offer: Offer,
+        amount: Option<Amount>,
+        payer_note: Option<String>,
+        gateway: Option<SafeUrl>,
+        custom_meta: Value,
+    ) -> Result<OperationId, SendPaymentError> {
+        if offer.is_expired() {
+            return Err(SendPaymentError::InvoiceExpired);
+        }
+
+        if !offer.supports_chain(ChainHash::using_genesis_block(self.cfg.network)) {
+            return Err(SendPaymentError::WrongChain);
+        }
+
+        let amount = match (offer.amount(), amount) {
+            (Some(OfferAmount::Bitcoin { amount_msats }), None) => amount_msats,
+            (None, Some(amount)) => amount.msats,
+            (Some(OfferAmount::Currency { .. }), _) => {
+                return Err(SendPaymentError::UnsupportedOfferCurrency);
+            }
+            (None, None) => return Err(SendPaymentError::InvoiceMissingAmount),
+            (Some(OfferAmount::Bitcoin { .. }), Some(_)) => {
+                return Err(SendPaymentError::OfferAmountAlreadySpecified);
+            }
+        };
 
         let (gateway_api, routing_info) = match gateway {
             Some(gateway_api) => (
@@ -581,11 +1091,80 @@ pub async fn send(
                     .ok_or(SendPaymentError::FederationNotSupported)?,
             ),
             None => self
-                .select_gateway(Some(invoice.clone()))
+                .select_gateway_with_capabilities(None, true)
                 .await
                 .map_err(SendPaymentError::SelectGateway)?,
         };
 
+        if !routing_info.supports_bolt12 {
+            return Err(SendPaymentError::Bolt12NotSupported);
+        }
+
+        let invoice = self
+            .gateway_conn
+            .fetch_bolt12_invoice(
+                gateway_api.clone(),
+                self.federation_id,
+                offer.clone(),
+                offer
+                    .amount()
+                    .is_none()
+                    .then_some(Amount::from_msats(amount)),
+                payer_note,
+            )
+            .await
+            .map_err(|e| SendPaymentError::FailedToFetchInvoice(e.to_string()))?;
+
+        // The gateway may not be trusted to have requested the invoice from the
+        // offer's issuer for the requested amount, so we verify both ourselves.
+        if !invoice_matches_offer(&invoice, &offer, self.cfg.network) {
+            return Err(SendPaymentError::InvalidInvoice);
+        }
+
+        if invoice.amount_msats() != amount {
+            return Err(SendPaymentError::InvalidInvoice);
+        }
+
+        if invoice.is_expired() {
+            return Err(SendPaymentError::InvoiceExpired);
+        }
+
+        let invoice = LightningInvoice::Bolt12(invoice);
+
+        let operation_id = OperationId::from_encodable(&(invoice.clone(), 0u64));
+
+        if self.client_ctx.operation_exists(operation_id).await {
+            return Err(SendPaymentError::DuplicatePaymentAttempt(operation_id));
+        }
+
+        self.fund_outgoing_contract(
+            operation_id,
+            invoice,
+            amount,
+            gateway_api,
+            routing_info,
+            custom_meta,
+        )
+        .await
+    }
+
+    /// Funds an outgoing contract for the invoice which incentivizes the
+    /// gateway to pay it, and starts the send state machine.
+    async fn fund_outgoing_contract(
+        &self,
+        operation_id: OperationId,
+        invoice: LightningInvoice,
+        amount: u64,
+        gateway_api: SafeUrl,
+        routing_info: RoutingInfo,
+        custom_meta: Value,
+    ) -> Result<OperationId, SendPaymentError> {
+        let (ephemeral_tweak, ephemeral_pk) = tweak::generate(self.keypair.public_key());
+
+        let refund_keypair = SecretKey::from_slice(&ephemeral_tweak)
+            .expect("32 bytes, within curve order")
+            .keypair(secp256k1::SECP256K1);
+
         let (send_fee, expiration_delta) = routing_info.send_parameters(&invoice);
 
         if !send_fee.is_within(&PaymentFee::SEND_FEE_LIMIT) {
@@ -603,7 +1182,7 @@ pub async fn send(
             .map_err(|e| SendPaymentError::FailedToRequestBlockCount(e.to_string()))?;
 
         let contract = OutgoingContract {
-            payment_image: PaymentImage::Hash(*invoice.payment_hash()),
+            payment_image: PaymentImage::Hash(invoice.payment_hash()),
             amount: send_fee.add_to(amount),
             expiration: consensus_block_count + expiration_delta + CONTRACT_CONFIRMATION_BUFFER,
             claim_pk: routing_info.module_public_key,
@@ -628,7 +1207,7 @@ pub async fn send(
                         outpoint: range.into_iter().next().unwrap(),
                         contract: contract_clone.clone(),
                         gateway_api: Some(gateway_api_clone.clone()),
-                        invoice: Some(LightningInvoice::Bolt11(invoice_clone.clone())),
+                        invoice: Some(invoice_clone.clone()),
                         refund_keypair,
                     },
                     state: SendSMState::Funding,
@@ -652,7 +1231,7 @@ pub async fn send(
                         change_outpoint_range,
                         gateway: gateway_api.clone(),
                         contract: contract.clone(),
-                        invoice: LightningInvoice::Bolt11(invoice.clone()),
+                        invoice: invoice.clone(),
                         custom_meta: custom_meta.clone(),
                     })
                 },
@@ -679,20 +1258,153 @@ pub async fn send(
         Ok(operation_id)
     }
 
-    /// The status of a previous [`Self::send`] for this invoice.
-    ///
-    /// Callers deciding whether an invoice is safe to pay through another
-    /// mechanism must treat anything but [`InvoiceSendStatus::NotAttempted`]
-    /// as a potential double payment.
-    pub async fn get_invoice_send_status(
+    /// Funds an outgoing contract for every part of a multi-path payment in a
+    /// single transaction, such that either all or none of the parts are
+    /// funded, and starts a send state machine for each of them.
+    async fn fund_part_contracts(
         &self,
-        invoice: &Bolt11Invoice,
-    ) -> anyhow::Result<InvoiceSendStatus> {
-        // Send only creates attempt index 0 nowadays, but older clients
-        // allocated a fresh index per retry, so scan for the latest attempt.
-        // No new attempt was ever allocated after a success, so the latest
-        // attempt alone determines the status.
-        let mut last_op = None;
+        operation_id: OperationId,
+        invoice: LightningInvoice,
+        amount: u64,
+        parts: Vec<(SafeUrl, RoutingInfo, Amount)>,
+        custom_meta: Value,
+    ) -> Result<OperationId, SendPaymentError> {
+        let consensus_block_count = self
+            .module_api
+            .consensus_block_count()
+            .await
+            .map_err(|e| SendPaymentError::FailedToRequestBlockCount(e.to_string()))?;
+
+        let mut part_metas = Vec::new();
+        let mut refund_keypairs = Vec::new();
+
+        for (gateway_api, routing_info, part_amount) in parts {
+            let (ephemeral_tweak, ephemeral_pk) = tweak::generate(self.keypair.public_key());
+
+            let refund_keypair = SecretKey::from_slice(&ephemeral_tweak)
+                .expect("32 bytes, within curve order")
+                .keypair(secp256k1::SECP256K1);
+
+            let (send_fee, expiration_delta) = routing_info.send_part_parameters(part_amount.msats);
+
+            if !send_fee.is_within(&PaymentFee::SEND_FEE_LIMIT) {
+                return Err(SendPaymentError::GatewayFeeExceedsLimit);
+            }
+
+            if EXPIRATION_DELTA_LIMIT < expiration_delta {
+                return Err(SendPaymentError::GatewayExpirationExceedsLimit);
+            }
+
+            part_metas.push(SendPartMeta {
+                gateway: gateway_api,
+                contract: OutgoingContract {
+                    payment_image: PaymentImage::Hash(invoice.payment_hash()),
+                    amount: send_fee.add_to(part_amount.msats),
+                    expiration: consensus_block_count
+                        + expiration_delta
+                        + CONTRACT_CONFIRMATION_BUFFER,
+                    claim_pk: routing_info.module_public_key,
+                    refund_pk: refund_keypair.public_key(),
+                    ephemeral_pk,
+                },
+                amount: part_amount,
+            });
+
+            refund_keypairs.push(refund_keypair);
+        }
+
+        let client_outputs = part_metas
+            .iter()
+            .map(|part| ClientOutput::<LightningOutput> {
+                output: LightningOutput::V0(LightningOutputV0::Outgoing(part.contract.clone())),
+                amounts: Amounts::new_bitcoin(part.contract.amount),
+            })
+            .collect();
+
+        let part_metas_clone = part_metas.clone();
+        let invoice_clone = invoice.clone();
+
+        let client_output_sm = ClientOutputSM::<LightningClientStateMachines> {
+            state_machines: Arc::new(move |range: OutPointRange| {
+                range
+                    .into_iter()
+                    .zip(part_metas_clone.iter().zip(refund_keypairs.iter()))
+                    .map(|(outpoint, (part, refund_keypair))| {
+                        LightningClientStateMachines::Send(SendStateMachine {
+                            common: SendSMCommon {
+                                operation_id,
+                                outpoint,
+                                contract: part.contract.clone(),
+                                gateway_api: Some(part.gateway.clone()),
+                                invoice: Some(invoice_clone.clone()),
+                                refund_keypair: *refund_keypair,
+                            },
+                            state: SendSMState::FundingPart(part.amount),
+                        })
+                    })
+                    .collect()
+            }),
+        };
+
+        let client_output = self.client_ctx.make_client_outputs(ClientOutputBundle::new(
+            client_outputs,
+            vec![client_output_sm],
+        ));
+
+        let transaction = TransactionBuilder::new().with_outputs(client_output);
+
+        let total_contract_amount: Amount =
+            part_metas.iter().map(|part| part.contract.amount).sum();
+
+        self.client_ctx
+            .finalize_and_submit_transaction(
+                operation_id,
+                LightningCommonInit::KIND.as_str(),
+                move |change_outpoint_range| {
+                    LightningOperationMeta::MultiPathSend(MultiPathSendOperationMeta {
+                        change_outpoint_range,
+                        invoice: invoice.clone(),
+                        parts: part_metas.clone(),
+                        custom_meta: custom_meta.clone(),
+                    })
+                },
+                transaction,
+            )
+            .await
+            .map_err(|e| SendPaymentError::FailedToFundPayment(e.to_string()))?;
+
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+        self.client_ctx
+            .log_event(
+                &mut dbtx,
+                SendPaymentEvent {
+                    operation_id,
+                    amount: Amount::from_msats(amount),
+                    fee: total_contract_amount.saturating_sub(Amount::from_msats(amount)),
+                },
+            )
+            .await;
+
+        dbtx.commit_tx().await;
+
+        Ok(operation_id)
+    }
+
+    /// The status of a previous [`Self::send`] for this invoice.
+    ///
+    /// Callers deciding whether an invoice is safe to pay through another
+    /// mechanism must treat anything but [`InvoiceSendStatus::NotAttempted`]
+    /// as a potential double payment.
+    pub async fn get_invoice_send_status(
+        &self,
+        invoice: &Bolt11Invoice,
+    ) -> anyhow::Result<InvoiceSendStatus> {
+        // Send only creates attempt index 0 nowadays, but older clients
+        // allocated a fresh index per retry, so scan for the latest attempt.
+        // No new attempt was ever allocated after a success, so the latest
+        // attempt alone determines the status.
+        let mut last_op = None;
 
         for attempt in 0_u64.. {
             let operation_id = OperationId::from_encodable(&(invoice.clone(), attempt));
@@ -738,6 +1450,11 @@ pub async fn subscribe_send_operation_state_updates(
         let client_ctx = self.client_ctx.clone();
         let module_api = self.module_api.clone();
 
+        let num_parts = match operation.meta::<LightningOperationMeta>() {
+            LightningOperationMeta::MultiPathSend(meta) => meta.parts.len(),
+            _ => 1,
+        };
+
         Ok(self.client_ctx.outcome_or_updates(&operation, operation_id, |state| match state {
                 SendOperationState::Funding
                 | SendOperationState::Funded
@@ -747,11 +1464,102 @@ pub async fn subscribe_send_operation_state_updates(
                 | SendOperationState::Failure => true,
             }, move || {
             stream! {
+                if num_parts > 1 {
+                    // The parts of a multi-path payment either all succeed or are all
+                    // refunded, hence we report their combined state.
+                    let mut parts = BTreeMap::new();
+                    let mut last_state = None;
+
+                    let parts = loop {
+                        let Some(LightningClientStateMachines::Send(state)) = stream.next().await else {
+                            continue;
+                        };
+
+                        match state.state {
+                            SendSMState::Success(preimage) => {
+                                // the preimage has been verified by the state machine previously
+                                assert!(state.common.contract.verify_preimage(&preimage));
+
+                                yield SendOperationState::Success(preimage);
+                                return;
+                            },
+                            SendSMState::Rejected(..) => {
+                                // All parts are funded by the same transaction
+                                yield SendOperationState::Failure;
+                                return;
+                            },
+                            _ => {}
+                        }
+
+                        parts.insert(state.common.outpoint, state);
+
+                        let is_refunding = |part: &SendStateMachine| {
+                            matches!(part.state, SendSMState::Refunding(..))
+                        };
+
+                        if parts.len() == num_parts && parts.values().all(is_refunding) {
+                            break parts;
+                        }
+
+                        let combined_state = if parts.values().any(is_refunding) {
+                            SendOperationState::Refunding
+                        } else if parts
+                            .values()
+                            .all(|part| matches!(part.state, SendSMState::Funding | SendSMState::FundingPart(..)))
+                        {
+                            SendOperationState::Funding
+                        } else {
+                            SendOperationState::Funded
+                        };
+
+                        if last_state.as_ref() != Some(&combined_state) {
+                            last_state = Some(combined_state.clone());
+
+                            yield combined_state;
+                        }
+                    };
+
+                    if last_state != Some(SendOperationState::Refunding) {
+                        yield SendOperationState::Refunding;
+                    }
+
+                    for part in parts.into_values() {
+                        let SendSMState::Refunding(out_points) = part.state else {
+                            unreachable!("All parts are refunding");
+                        };
+
+                        if client_ctx.await_primary_module_outputs(operation_id, out_points).await.is_ok() {
+                            continue;
+                        }
+
+                        // A gateway may have claimed its outgoing contract after all, in
+                        // which case the payee has released the preimage.
+                        if let Some(preimage) = module_api.await_preimage(
+                            part.common.outpoint,
+                            0
+                        ).await
+                            && part.common.contract.verify_preimage(&preimage) {
+                                yield SendOperationState::Success(preimage);
+                                return;
+                            }
+
+                        yield SendOperationState::Failure;
+                        return;
+                    }
+
+                    yield SendOperationState::Refunded;
+                    return;
+                }
+
                 loop {
                     if let Some(LightningClientStateMachines::Send(state)) = stream.next().await {
                         match state.state {
-                            SendSMState::Funding => yield SendOperationState::Funding,
-                            SendSMState::Funded => yield SendOperationState::Funded,
+                            SendSMState::Funding | SendSMState::FundingPart(..) => {
+                                yield SendOperationState::Funding;
+                            },
+                            SendSMState::Funded | SendSMState::FundedPart(..) => {
+                                yield SendOperationState::Funded;
+                            },
                             SendSMState::Success(preimage) => {
                                 // the preimage has been verified by the state machine previously
                                 assert!(state.common.contract.verify_preimage(&preimage));
@@ -845,6 +1653,7 @@ pub async fn receive(
                 self.keypair.public_key(),
                 amount,
                 expiry_secs,
+                None,
                 description,
                 gateway,
             )
@@ -859,6 +1668,7 @@ pub async fn receive(
                     contract,
                     invoice: LightningInvoice::Bolt11(invoice.clone()),
                     custom_meta,
+                    hold: false,
                 }),
             )
             .await
@@ -867,6 +1677,358 @@ pub async fn receive(
         Ok((invoice, operation_id))
     }
 
+    /// Request a hold invoice. Unlike for an invoice requested via
+    /// [`Self::receive`] the gateway does not settle a payment right away, but
+    /// holds it until we settle it via [`Self::settle_held_payment`] or cancel
+    /// it via [`Self::cancel_held_payment`]. The receive operation reports
+    /// [`ReceiveOperationState::Held`] once the gateway holds a payment.
+    ///
+    /// A payment that has been neither settled nor cancelled `hold_secs` after
+    /// the invoice has expired is cancelled by the gateway, in which case the
+    /// receive operation expires. Gateways hold a payment for at most an hour.
+    pub async fn receive_hold(
+        &self,
+        amount: Amount,
+        expiry_secs: u32,
+        hold_secs: u32,
+        description: Bolt11InvoiceDescription,
+        gateway: Option<SafeUrl>,
+        custom_meta: Value,
+    ) -> Result<(Bolt11Invoice, OperationId), ReceiveError> {
+        let (gateway, contract, invoice) = self
+            .create_contract_and_fetch_invoice(
+                self.keypair.public_key(),
+                amount,
+                expiry_secs,
+                Some(hold_secs),
+                description,
+                gateway,
+            )
+            .await?;
+
+        let operation_id = self
+            .receive_incoming_contract(
+                self.keypair.secret_key(),
+                contract.clone(),
+                LightningOperationMeta::Receive(ReceiveOperationMeta {
+                    gateway,
+                    contract,
+                    invoice: LightningInvoice::Bolt11(invoice.clone()),
+                    custom_meta,
+                    hold: true,
+                }),
+            )
+            .await
+            .expect("The contract has been generated with our public key");
+
+        Ok((invoice, operation_id))
+    }
+
+    /// Settle the payment the gateway holds for a hold invoice requested via
+    /// [`Self::receive_hold`], such that the gateway funds the incoming
+    /// contract and the receive operation completes.
+    pub async fn settle_held_payment(
+        &self,
+        operation_id: OperationId,
+    ) -> Result<(), ResolveHeldPaymentError> {
+        self.resolve_held_payment(operation_id, true).await
+    }
+
+    /// Cancel the payment the gateway holds for a hold invoice requested via
+    /// [`Self::receive_hold`], such that the payment fails for the sender and
+    /// the receive operation expires.
+    pub async fn cancel_held_payment(
+        &self,
+        operation_id: OperationId,
+    ) -> Result<(), ResolveHeldPaymentError> {
+        self.resolve_held_payment(operation_id, false).await
+    }
+
+    async fn resolve_held_payment(
+        &self,
+        operation_id: OperationId,
+        settle: bool,
+    ) -> Result<(), ResolveHeldPaymentError> {
+        let meta = match self
+            .client_ctx
+            .get_operation(operation_id)
+            .await
+            .map(|operation| operation.meta::<LightningOperationMeta>())
+        {
+            Ok(LightningOperationMeta::Receive(meta)) if meta.hold => meta,
+            _ => return Err(ResolveHeldPaymentError::UnknownHoldReceive),
+        };
+
+        let (claim_keypair, _) = self
+            .recover_contract_keys(self.keypair.secret_key(), &meta.contract)
+            .ok_or(ResolveHeldPaymentError::UnknownHoldReceive)?;
+
+        let status = self
+            .gateway_conn
+            .resolve_hold_invoice(
+                meta.gateway,
+                ResolveHoldInvoicePayload {
+                    payment_image: meta.contract.commitment.payment_image.clone(),
+                    settle,
+                    signature: claim_keypair
+                        .sign_schnorr(meta.contract.hold_resolution_message(settle)),
+                },
+            )
+            .await
+            .map_err(|e| ResolveHeldPaymentError::FailedToConnectToGateway(e.to_string()))?;
+
+        let expected_status = if settle {
+            HoldInvoiceStatus::Settled
+        } else {
+            HoldInvoiceStatus::Cancelled
+        };
+
+        if status != expected_status {
+            return Err(ResolveHeldPaymentError::AlreadyResolved(status));
+        }
+
+        Ok(())
+    }
+
+    /// Request a single-use BOLT12 offer. Like [`Self::receive`] this creates
+    /// an incoming contract and requests the gateway to issue an offer for
+    /// it, such that any invoice the gateway responds with to an invoice
+    /// request for this offer is bound to the contract's payment hash.
+    ///
+    /// The same fee limit as for [`Self::receive`] applies.
+    pub async fn receive_offer(
+        &self,
+        amount: Amount,
+        expiry_secs: u32,
+        description: String,
+        gateway: Option<SafeUrl>,
+        custom_meta: Value,
+    ) -> Result<(Offer, OperationId), ReceiveError> {
+        let (gateway, contract, offer) = self
+            .create_contract_and_fetch_offer(
+                self.keypair.public_key(),
+                amount,
+                expiry_secs,
+                description,
+                gateway,
+            )
+            .await?;
+
+        let operation_id = self
+            .receive_incoming_contract(
+                self.keypair.secret_key(),
+                contract.clone(),
+                LightningOperationMeta::OfferReceive(OfferReceiveOperationMeta {
+                    gateway,
+                    contract,
+                    offer: offer.to_string(),
+                    amount,
+                    custom_meta,
+                }),
+            )
+            .await
+            .expect("The contract has been generated with our public key");
+
+        Ok((offer, operation_id))
+    }
+
+    /// Requests the fees for moving `amount` from this federation to the
+    /// target federation with [`Self::swap`]. For testing you can optionally
+    /// specify the gateway, otherwise the first vetted gateway that is
+    /// connected to both federations is selected.
+    pub async fn swap_quote(
+        &self,
+        target_federation_id: FederationId,
+        amount: Amount,
+        gateway: Option<SafeUrl>,
+    ) -> Result<(SafeUrl, SwapQuote), SwapError> {
+        if self.federation_id == target_federation_id {
+            return Err(SwapError::SameFederation);
+        }
+
+        if let Some(gateway) = gateway {
+            let quote = self
+                .gateway_conn
+                .swap_quote(
+                    gateway.clone(),
+                    self.federation_id,
+                    target_federation_id,
+                    amount,
+                )
+                .await
+                .map_err(|e| SwapError::FailedToConnectToGateway(e.to_string()))?
+                .ok_or(SwapError::FederationNotSupported)?;
+
+            return Ok((gateway, quote));
+        }
+
+        let gateways = self.module_api.gateways().await.map_err(|e| {
+            SwapError::SelectGateway(SelectGatewayError::FailedToRequestGateways(e.to_string()))
+        })?;
+
+        if gateways.is_empty() {
+            return Err(SwapError::SelectGateway(
+                SelectGatewayError::NoGatewaysAvailable,
+            ));
+        }
+
+        for gateway in self.rank_gateways(gateways).await {
+            match self
+                .gateway_conn
+                .swap_quote(
+                    gateway.clone(),
+                    self.federation_id,
+                    target_federation_id,
+                    amount,
+                )
+                .await
+            {
+                Ok(Some(quote)) => return Ok((gateway, quote)),
+                Ok(None) => {}
+                Err(..) => self.record_unresponsive_gateway(&gateway).await,
+            }
+        }
+
+        Err(SwapError::FederationNotSupported)
+    }
+
+    /// Move the amount of `quote` from this federation to the federation of
+    /// `target` through the gateway that issued the quote, without a payment
+    /// over the Lightning network.
+    ///
+    /// The swap is settled like a payment from this federation of an invoice
+    /// the gateway created for the target federation. The gateway can only
+    /// claim the outgoing contract in this federation by funding the incoming
+    /// contract in the target federation, otherwise the outgoing contract is
+    /// refunded. The fees are fixed by the quote: the gateway rejects the swap
+    /// if its fees have changed, and we never lock more than
+    /// [`SwapQuote::send_amount`] or accept less than
+    /// [`SwapQuote::receive_amount`]. The progress of the swap is reported by
+    /// [`Self::swap_status`] of the target module.
+    pub async fn swap(
+        &self,
+        target: &LightningClientModule,
+        gateway: SafeUrl,
+        quote: SwapQuote,
+        custom_meta: Value,
+    ) -> Result<SwapOperationIds, SwapError> {
+        if self.federation_id == target.federation_id {
+            return Err(SwapError::SameFederation);
+        }
+
+        if quote.source_federation_id != self.federation_id
+            || quote.target_federation_id != target.federation_id
+        {
+            return Err(SwapError::InvalidQuote);
+        }
+
+        if !quote.send_fee.is_within(&PaymentFee::SEND_FEE_LIMIT)
+            || !quote.receive_fee.is_within(&PaymentFee::RECEIVE_FEE_LIMIT)
+        {
+            return Err(SwapError::GatewayFeeExceedsLimit);
+        }
+
+        let mut routing_info = self
+            .routing_info(&gateway)
+            .await
+            .map_err(|e| SwapError::FailedToConnectToGateway(e.to_string()))?
+            .ok_or(SwapError::FederationNotSupported)?;
+
+        // The outgoing contract of a direct swap is funded with the quoted fee
+        routing_info.send_fee_minimum = quote.send_fee;
+        routing_info.expiration_delta_minimum = quote.expiration_delta;
+
+        let (gateway_api, contract) = target
+            .create_incoming_contract(
+                target.keypair.public_key(),
+                quote.amount,
+                SWAP_EXPIRY_SECS,
+                Some(gateway),
+                false,
+            )
+            .await
+            .map_err(SwapError::Receive)?;
+
+        if contract.commitment.amount < quote.receive_amount() {
+            return Err(SwapError::InvalidQuote);
+        }
+
+        let invoice = target
+            .gateway_conn
+            .swap(
+                gateway_api.clone(),
+                quote.clone(),
+                contract.clone(),
+                SWAP_EXPIRY_SECS,
+            )
+            .await
+            .map_err(|e| SwapError::FailedToConnectToGateway(e.to_string()))?;
+
+        // Only an invoice of the gateway's own node is settled as a direct swap
+        if PaymentImage::Hash(*invoice.payment_hash()) != contract.commitment.payment_image
+            || invoice.amount_milli_satoshis() != Some(quote.amount.msats)
+            || invoice.recover_payee_pub_key() != routing_info.lightning_public_key
+        {
+            return Err(SwapError::InvalidInvoice);
+        }
+
+        let receive_operation_id = target
+            .receive_incoming_contract(
+                target.keypair.secret_key(),
+                contract.clone(),
+                LightningOperationMeta::Receive(ReceiveOperationMeta {
+                    gateway: gateway_api.clone(),
+                    contract,
+                    invoice: LightningInvoice::Bolt11(invoice.clone()),
+                    custom_meta: custom_meta.clone(),
+                    hold: false,
+                }),
+            )
+            .await
+            .expect("The contract has been generated with our public key");
+
+        let send_operation_id = OperationId::from_encodable(&(invoice.clone(), 0u64));
+
+        self.fund_outgoing_contract(
+            send_operation_id,
+            LightningInvoice::Bolt11(invoice),
+            quote.amount.msats,
+            gateway_api,
+            routing_info,
+            custom_meta,
+        )
+        .await
+        .map_err(SwapError::Send)?;
+
+        Ok(SwapOperationIds {
+            send_operation_id,
+            receive_operation_id,
+        })
+    }
+
+    /// Requests the status of a swap from the gateway settling it, given the
+    /// receive operation of the swap in this federation. Returns `None` if the
+    /// gateway does not know the swap.
+    pub async fn swap_status(
+        &self,
+        receive_operation_id: OperationId,
+    ) -> Result<Option<SwapStatus>, SwapError> {
+        let meta = match self
+            .client_ctx
+            .get_operation(receive_operation_id)
+            .await
+            .map(|operation| operation.meta::<LightningOperationMeta>())
+        {
+            Ok(LightningOperationMeta::Receive(meta)) => meta,
+            _ => return Err(SwapError::UnknownSwap),
+        };
+
+        self.gateway_conn
+            .swap_status(meta.gateway, meta.contract.commitment.payment_image)
+            .await
+            .map_err(|e| SwapError::FailedToConnectToGateway(e.to_string()))
+    }
+
     /// Computes the federation fee a `receive` of `amount` would incur, without
     /// submitting anything.
     ///
@@ -993,15 +2155,120 @@ pub async fn spendable_amount(
 
     /// Create an incoming contract locked to a public key derived from the
     /// recipient's static module public key and fetches the corresponding
-    /// invoice.
+    /// invoice. If `hold_secs` is set, the invoice is a hold invoice and the
+    /// contract expires `hold_secs` after the invoice.
     async fn create_contract_and_fetch_invoice(
         &self,
         recipient_static_pk: PublicKey,
         amount: Amount,
         expiry_secs: u32,
+        hold_secs: Option<u32>,
         description: Bolt11InvoiceDescription,
         gateway: Option<SafeUrl>,
     ) -> Result<(SafeUrl, IncomingContract, Bolt11Invoice), ReceiveError> {
+        let contract_expiry_secs = expiry_secs.saturating_add(hold_secs.unwrap_or(0));
+
+        let (gateway, contract) = self
+            .create_incoming_contract(
+                recipient_static_pk,
+                amount,
+                contract_expiry_secs,
+                gateway,
+                false,
+            )
+            .await?;
+
+        let invoice = if hold_secs.is_some() {
+            self.gateway_conn
+                .hold_invoice(
+                    gateway.clone(),
+                    self.federation_id,
+                    contract.clone(),
+                    amount,
+                    description,
+                    expiry_secs,
+                )
+                .await
+        } else {
+            self.gateway_conn
+                .bolt11_invoice(
+                    gateway.clone(),
+                    self.federation_id,
+                    contract.clone(),
+                    amount,
+                    description,
+                    expiry_secs,
+                )
+                .await
+        }
+        .map_err(|e| ReceiveError::FailedToConnectToGateway(e.to_string()))?;
+
+        if PaymentImage::Hash(*invoice.payment_hash()) != contract.commitment.payment_image {
+            return Err(ReceiveError::InvalidInvoice);
+        }
+
+        if invoice.amount_milli_satoshis() != Some(amount.msats) {
+            return Err(ReceiveError::IncorrectInvoiceAmount);
+        }
+
+        Ok((gateway, contract, invoice))
+    }
+
+    /// Create an incoming contract locked to a public key derived from the
+    /// recipient's static module public key and fetches a single-use offer
+    /// whose invoices are bound to the contract's payment hash.
+    async fn create_contract_and_fetch_offer(
+        &self,
+        recipient_static_pk: PublicKey,
+        amount: Amount,
+        expiry_secs: u32,
+        description: String,
+        gateway: Option<SafeUrl>,
+    ) -> Result<(SafeUrl, IncomingContract, Offer), ReceiveError> {
+        let (gateway, contract) = self
+            .create_incoming_contract(recipient_static_pk, amount, expiry_secs, gateway, true)
+            .await?;
+
+        let offer = self
+            .gateway_conn
+            .bolt12_offer(
+                gateway.clone(),
+                self.federation_id,
+                contract.clone(),
+                amount,
+                description,
+                expiry_secs,
+            )
+            .await
+            .map_err(|e| ReceiveError::FailedToConnectToGateway(e.to_string()))?;
+
+        if !offer.supports_chain(ChainHash::using_genesis_block(self.cfg.network)) {
+            return Err(ReceiveError::InvalidInvoice);
+        }
+
+        if offer.amount()
+            != Some(OfferAmount::Bitcoin {
+                amount_msats: amount.msats,
+            })
+        {
+            return Err(ReceiveError::IncorrectInvoiceAmount);
+        }
+
+        Ok((gateway, contract, offer))
+    }
+
+    /// Create an incoming contract locked to a public key derived from the
+    /// recipient's static module public key and keyed to the selected
+    /// gateway. If `requires_bolt12` is set, the gateway has to be able to
+    /// receive the payment via a BOLT12 offer.
+    async fn create_incoming_contract(
+        &self,
+        recipient_static_pk: PublicKey,
+        amount: Amount,
+        expiry_secs: u32,
+        gateway: Option<SafeUrl>,
+        requires_bolt12: bool,
+    ) -> Result<(SafeUrl, IncomingContract), ReceiveError> {
         let (ephemeral_tweak, ephemeral_pk) = tweak::generate(recipient_static_pk);
 
         let encryption_seed = ephemeral_tweak
@@ -1021,19 +2288,22 @@ async fn create_contract_and_fetch_invoice(
                     .ok_or(ReceiveError::FederationNotSupported)?,
             ),
             None => self
-                .select_gateway(None)
+                .select_gateway_with_capabilities(None, requires_bolt12)
                 .await
                 .map_err(ReceiveError::SelectGateway)?,
         };
 
-        if !routing_info
-            .receive_fee
-            .is_within(&PaymentFee::RECEIVE_FEE_LIMIT)
-        {
+        if requires_bolt12 && !routing_info.supports_bolt12 {
+            return Err(ReceiveError::Bolt12NotSupported);
+        }
+
+        let receive_fee = routing_info.receive_fee_for(amount.msats);
+
+        if !receive_fee.is_within(&PaymentFee::RECEIVE_FEE_LIMIT) {
             return Err(ReceiveError::GatewayFeeExceedsLimit);
         }
 
-        let contract_amount = routing_info.receive_fee.subtract_from(amount.msats);
+        let contract_amount = receive_fee.subtract_from(amount.msats);
 
         if contract_amount < MINIMUM_INCOMING_CONTRACT_AMOUNT {
             return Err(ReceiveError::AmountTooSmall);
@@ -1062,28 +2332,7 @@ async fn create_contract_and_fetch_invoice(
             ephemeral_pk,
         );
 
-        let invoice = self
-            .gateway_conn
-            .bolt11_invoice(
-                gateway.clone(),
-                self.federation_id,
-                contract.clone(),
-                amount,
-                description,
-                expiry_secs,
-            )
-            .await
-            .map_err(|e| ReceiveError::FailedToConnectToGateway(e.to_string()))?;
-
-        if invoice.payment_hash() != &preimage.consensus_hash() {
-            return Err(ReceiveError::InvalidInvoice);
-        }
-
-        if invoice.amount_milli_satoshis() != Some(amount.msats) {
-            return Err(ReceiveError::IncorrectInvoiceAmount);
-        }
-
-        Ok((gateway, contract, invoice))
+        Ok((gateway, contract))
     }
 
     // Receive an incoming contract locked to a public key derived from our
@@ -1098,6 +2347,13 @@ async fn receive_incoming_contract(
 
         let (claim_keypair, agg_decryption_key) = self.recover_contract_keys(sk, &contract)?;
 
+        let state = match &operation_meta {
+            LightningOperationMeta::Receive(meta) if meta.hold => {
+                ReceiveSMState::AwaitingHold(meta.gateway.clone())
+            }
+            _ => ReceiveSMState::Pending,
+        };
+
         let receive_sm = LightningClientStateMachines::Receive(ReceiveStateMachine {
             common: ReceiveSMCommon {
                 operation_id,
@@ -1105,7 +2361,7 @@ async fn receive_incoming_contract(
                 claim_keypair,
                 agg_decryption_key,
             },
-            state: ReceiveSMState::Pending,
+            state,
         });
 
         // this may only fail if the operation id is already in use, in which case we
@@ -1165,7 +2421,9 @@ pub async fn subscribe_receive_operation_state_updates(
         let client_ctx = self.client_ctx.clone();
 
         Ok(self.client_ctx.outcome_or_updates(&operation, operation_id, |state| match state {
-                ReceiveOperationState::Pending | ReceiveOperationState::Claiming => false,
+                ReceiveOperationState::Pending
+                | ReceiveOperationState::Held
+                | ReceiveOperationState::Claiming => false,
                 ReceiveOperationState::Expired
                 | ReceiveOperationState::Claimed
                 | ReceiveOperationState::Failure => true,
@@ -1174,7 +2432,10 @@ pub async fn subscribe_receive_operation_state_updates(
                 loop {
                     if let Some(LightningClientStateMachines::Receive(state)) = stream.next().await {
                         match state.state {
-                            ReceiveSMState::Pending => yield ReceiveOperationState::Pending,
+                            ReceiveSMState::Pending | ReceiveSMState::AwaitingHold(..) => {
+                                yield ReceiveOperationState::Pending;
+                            }
+                            ReceiveSMState::Held => yield ReceiveOperationState::Held,
                             ReceiveSMState::Claiming(out_points) => {
                                 yield ReceiveOperationState::Claiming;
 
@@ -1351,6 +2612,8 @@ pub enum SelectGatewayError {
     NoGatewaysAvailable,
     #[error("All gateways failed to respond")]
     GatewaysUnresponsive,
+    #[error("No gateway supports BOLT12")]
+    NoBolt12GatewaysAvailable,
 }
 
 /// The status of the latest send attempt for an invoice, derived from the
@@ -1395,6 +2658,22 @@ pub enum SendPaymentError {
         invoice_currency: Currency,
         federation_currency: Currency,
     },
+    #[error("Offer is for a different chain")]
+    WrongChain,
+    #[error("Offer is denominated in a fiat currency")]
+    UnsupportedOfferCurrency,
+    #[error("Offer already specifies an amount")]
+    OfferAmountAlreadySpecified,
+    #[error("Failed to fetch an invoice for the offer")]
+    FailedToFetchInvoice(String),
+    #[error("Gateway returned an invalid invoice")]
+    InvalidInvoice,
+    #[error("Invalid number of parts for the invoice amount")]
+    InvalidNumberOfParts,
+    #[error("Not enough gateways available to pay all parts")]
+    NotEnoughGateways,
+    #[error("Gateway does not support BOLT12")]
+    Bolt12NotSupported,
 }
 
 #[derive(Error, Debug, Clone, Eq, PartialEq)]
@@ -1413,6 +2692,42 @@ pub enum ReceiveError {
     InvalidInvoice,
     #[error("Gateway returned an invoice with incorrect amount")]
     IncorrectInvoiceAmount,
+    #[error("Gateway does not support BOLT12")]
+    Bolt12NotSupported,
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum ResolveHeldPaymentError {
+    #[error("The operation does not receive a payment for a hold invoice")]
+    UnknownHoldReceive,
+    #[error("Failed to connect to gateway")]
+    FailedToConnectToGateway(String),
+    #[error("The held payment has already been resolved: {0:?}")]
+    AlreadyResolved(HoldInvoiceStatus),
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum SwapError {
+    #[error("Cannot swap within the same federation")]
+    SameFederation,
+    #[error(transparent)]
+    SelectGateway(SelectGatewayError),
+    #[error("Failed to connect to gateway")]
+    FailedToConnectToGateway(String),
+    #[error("Gateway does not support swaps between these federations")]
+    FederationNotSupported,
+    #[error("Gateway fee exceeds the allowed limit")]
+    GatewayFeeExceedsLimit,
+    #[error("The swap quote does not match the swap")]
+    InvalidQuote,
+    #[error("Gateway returned an invalid invoice")]
+    InvalidInvoice,
+    #[error("The operation is not the receive operation of a swap")]
+    UnknownSwap,
+    #[error(transparent)]
+    Receive(ReceiveError),
+    #[error(transparent)]
+    Send(SendPaymentError),
 }
 
 #[derive(Error, Debug, Clone, Eq, PartialEq)]
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use fedimint_client_module::DynGlobalClientContext;
 use fedimint_client_module::sm::{ClientSMDatabaseTransaction, State, StateTransition};
 use fedimint_client_module::transaction::{ClientInput, ClientInputBundle};
@@ -5,17 +7,23 @@
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::module::Amounts;
 use fedimint_core::secp256k1::Keypair;
+use fedimint_core::task::sleep;
+use fedimint_core::util::SafeUrl;
 use fedimint_core::{Amount, OutPoint};
-use fedimint_lnv2_common::contracts::{IncomingContract, fee_from_expiration};
+use fedimint_lnv2_common::contracts::{IncomingContract, PaymentImage, fee_from_expiration};
+use fedimint_lnv2_common::gateway_api::HoldInvoiceStatus;
 use fedimint_lnv2_common::{LightningInput, LightningInputV0};
 use fedimint_logging::LOG_CLIENT_MODULE_LNV2;
 use tpe::AggregateDecryptionKey;
 use tracing::instrument;
 
 use crate::api::LightningFederationApi;
-use crate::events::ReceivePaymentEvent;
+use crate::events::{ReceivePaymentEvent, ReceivePaymentHeldEvent};
 use crate::{LightningClientContext, LightningOperationMeta};
 
+/// How often we ask the gateway whether it holds a payment for a hold invoice.
+const HOLD_STATUS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
 pub struct ReceiveStateMachine {
     pub common: ReceiveSMCommon,
@@ -44,6 +52,11 @@ pub enum ReceiveSMState {
     Pending,
     Claiming(Vec<OutPoint>),
     Expired,
+    /// The gateway holds payments for the contract until we settle or cancel
+    /// them, and we wait for it to receive one.
+    AwaitingHold(SafeUrl),
+    /// The gateway holds a payment until we settle or cancel it.
+    Held,
 }
 
 #[cfg_attr(doc, aquamarine::aquamarine)]
@@ -55,6 +68,11 @@ pub enum ReceiveSMState {
 ///
 ///     Pending -- incoming contract is confirmed --> Claiming
 ///     Pending -- decryption contract expires --> Expired
+///     AwaitingHold -- gateway holds a payment --> Held
+///     AwaitingHold -- incoming contract is confirmed --> Claiming
+///     AwaitingHold -- decryption contract expires --> Expired
+///     Held -- payment is settled and incoming contract is confirmed --> Claiming
+///     Held -- payment is cancelled and decryption contract expires --> Expired
 /// ```
 impl State for ReceiveStateMachine {
     type ModuleContext = LightningClientContext;
@@ -67,20 +85,39 @@ fn transitions(
         let gc = global_context.clone();
         let ctx = context.clone();
 
+        let await_incoming_contract = StateTransition::new(
+            Self::await_incoming_contract(self.common.contract.clone(), gc.clone()),
+            move |dbtx, contract_confirmed, old_state| {
+                Box::pin(Self::transition_incoming_contract(
+                    dbtx,
+                    old_state,
+                    ctx.clone(),
+                    gc.clone(),
+                    contract_confirmed,
+                ))
+            },
+        );
+
         match &self.state {
-            ReceiveSMState::Pending => {
-                vec![StateTransition::new(
-                    Self::await_incoming_contract(self.common.contract.clone(), gc.clone()),
-                    move |dbtx, contract_confirmed, old_state| {
-                        Box::pin(Self::transition_incoming_contract(
-                            dbtx,
-                            old_state,
-                            ctx.clone(),
-                            gc.clone(),
-                            contract_confirmed,
-                        ))
-                    },
-                )]
+            ReceiveSMState::Pending | ReceiveSMState::Held => {
+                vec![await_incoming_contract]
+            }
+            ReceiveSMState::AwaitingHold(gateway_api) => {
+                let ctx = context.clone();
+
+                vec![
+                    await_incoming_contract,
+                    StateTransition::new(
+                        Self::await_held_payment(
+                            gateway_api.clone(),
+                            self.common.contract.commitment.payment_image.clone(),
+                            context.clone(),
+                        ),
+                        move |dbtx, (), old_state| {
+                            Box::pin(Self::transition_held_payment(dbtx, old_state, ctx.clone()))
+                        },
+                    ),
+                ]
             }
             ReceiveSMState::Claiming(..) | ReceiveSMState::Expired => {
                 vec![]
@@ -108,6 +145,43 @@ async fn await_incoming_contract(
             .await
     }
 
+    #[instrument(target = LOG_CLIENT_MODULE_LNV2, skip(context))]
+    async fn await_held_payment(
+        gateway_api: SafeUrl,
+        payment_image: PaymentImage,
+        context: LightningClientContext,
+    ) {
+        loop {
+            if let Ok(Some(HoldInvoiceStatus::Held | HoldInvoiceStatus::Settled)) = context
+                .gateway_conn
+                .hold_invoice_status(gateway_api.clone(), payment_image.clone())
+                .await
+            {
+                return;
+            }
+
+            sleep(HOLD_STATUS_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn transition_held_payment(
+        dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
+        old_state: ReceiveStateMachine,
+        context: LightningClientContext,
+    ) -> ReceiveStateMachine {
+        context
+            .client_ctx
+            .log_event(
+                &mut dbtx.module_tx(),
+                ReceivePaymentHeldEvent {
+                    operation_id: old_state.common.operation_id,
+                },
+            )
+            .await;
+
+        old_state.update(ReceiveSMState::Held)
+    }
+
     async fn transition_incoming_contract(
         dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
         old_state: ReceiveStateMachine,
@@ -145,9 +219,10 @@ async fn transition_incoming_contract(
             .map(|operation| operation.meta::<LightningOperationMeta>())
         {
             // A receive operation meta is only recorded for manually created
-            // invoices; lnurl receives have no invoice on the client (and no
+            // invoices and offers; lnurl receives have no invoice on the client (and no
             // operation), so the fee is recovered from the fee-encoded expiration.
             Ok(LightningOperationMeta::Receive(meta)) => meta.gateway_fee(),
+            Ok(LightningOperationMeta::OfferReceive(meta)) => meta.gateway_fee(),
             _ => Amount::from_msats(fee_from_expiration(
                 old_state.common.contract.commitment.expiration_or_fee,
             )),
@@ -1,5 +1,6 @@
+use std::time::Duration;
+
 use anyhow::ensure;
-use bitcoin::hashes::sha256;
 use fedimint_client_module::DynGlobalClientContext;
 use fedimint_client_module::sm::{ClientSMDatabaseTransaction, State, StateTransition};
 use fedimint_client_module::transaction::{ClientInput, ClientInputBundle};
@@ -7,10 +8,12 @@
 use fedimint_core::core::OperationId;
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::module::Amounts;
+use fedimint_core::time::now;
 use fedimint_core::util::SafeUrl;
 use fedimint_core::util::backoff_util::api_networking_backoff;
-use fedimint_core::{OutPoint, TransactionId, crit, secp256k1, util};
+use fedimint_core::{Amount, OutPoint, TransactionId, crit, secp256k1, util};
 use fedimint_lnv2_common::contracts::OutgoingContract;
+use fedimint_lnv2_common::gateway_api::SendPaymentPayload;
 use fedimint_lnv2_common::{LightningInput, LightningInputV0, OutgoingWitness};
 use fedimint_logging::LOG_CLIENT_MODULE_LNV2;
 use futures::future::pending;
@@ -20,6 +23,7 @@
 
 use crate::api::LightningFederationApi;
 use crate::events::{SendPaymentStatus, SendPaymentUpdateEvent};
+use crate::gateway_score::update_gateway_stats;
 use crate::{LightningClientContext, LightningInvoice};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
@@ -54,6 +58,23 @@ pub enum SendSMState {
     Rejected(String),
     Success([u8; 32]),
     Refunding(Vec<OutPoint>),
+    /// Like [`SendSMState::Funding`] for a contract that funds the given
+    /// amount of the invoice as a part of a multi-path payment.
+    FundingPart(Amount),
+    /// Like [`SendSMState::Funded`] for a contract that funds the given amount
+    /// of the invoice as a part of a multi-path payment.
+    FundedPart(Amount),
+}
+
+impl SendSMState {
+    /// The amount of the invoice this contract funds if it is a part of a
+    /// multi-path payment.
+    fn part_amount(&self) -> Option<Amount> {
+        match self {
+            SendSMState::FundingPart(amount) | SendSMState::FundedPart(amount) => Some(*amount),
+            _ => None,
+        }
+    }
 }
 
 async fn send_update_event(
@@ -74,6 +95,41 @@ async fn send_update_event(
         .await;
 }
 
+/// Records the outcome of the payment in the stats of the gateway we sent it
+/// through, with the time the gateway took to respond if we know it.
+async fn record_gateway_outcome(
+    dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
+    state: &SendStateMachine,
+    success: bool,
+    latency: Option<Duration>,
+) {
+    let Some(gateway_api) = &state.common.gateway_api else {
+        return;
+    };
+
+    let amount = state.state.part_amount().or_else(|| {
+        state
+            .common
+            .invoice
+            .as_ref()
+            .and_then(LightningInvoice::amount_milli_satoshis)
+            .map(Amount::from_msats)
+    });
+
+    update_gateway_stats(&mut dbtx.module_tx(), gateway_api, |stats| {
+        match (success, amount) {
+            (true, Some(amount)) => stats.record_success(
+                amount,
+                state.common.contract.amount.saturating_sub(amount),
+                latency,
+            ),
+            (true, None) => stats.record_success(Amount::ZERO, Amount::ZERO, latency),
+            (false, _) => stats.record_refund(),
+        }
+    })
+    .await;
+}
+
 #[cfg_attr(doc, aquamarine::aquamarine)]
 /// State machine that requests the lightning gateway to pay an invoice on
 /// behalf of a federation client.
@@ -89,6 +145,9 @@ async fn send_update_event(
 ///     Funded -- await_preimage returns preimage --> Success
 ///     Funded -- await_preimage expires --> Refunding
 /// ```
+///
+/// The contracts of a multi-path payment pass through `FundingPart` and
+/// `FundedPart` instead of `Funding` and `Funded` with the same transitions.
 impl State for SendStateMachine {
     type ModuleContext = LightningClientContext;
 
@@ -103,7 +162,7 @@ fn transitions(
         let gc_preimage = global_context.clone();
 
         match &self.state {
-            SendSMState::Funding => {
+            SendSMState::Funding | SendSMState::FundingPart(..) => {
                 vec![StateTransition::new(
                     Self::await_funding(global_context.clone(), self.common.outpoint.txid),
                     move |_, error, old_state| {
@@ -111,7 +170,7 @@ fn transitions(
                     },
                 )]
             }
-            SendSMState::Funded => {
+            SendSMState::Funded | SendSMState::FundedPart(..) => {
                 vec![
                     StateTransition::new(
                         Self::gateway_send_payment(
@@ -120,6 +179,7 @@ fn transitions(
                             self.common.outpoint,
                             self.common.contract.clone(),
                             self.common.invoice.clone().unwrap(),
+                            self.state.part_amount(),
                             self.common.refund_keypair,
                             context.clone(),
                         ),
@@ -174,12 +234,14 @@ fn transition_funding(
         result: Result<(), String>,
         old_state: &SendStateMachine,
     ) -> SendStateMachine {
-        match result {
-            Ok(()) => old_state.update(SendSMState::Funded),
-            Err(error) => old_state.update(SendSMState::Rejected(error)),
+        match (result, old_state.state.part_amount()) {
+            (Ok(()), None) => old_state.update(SendSMState::Funded),
+            (Ok(()), Some(part_amount)) => old_state.update(SendSMState::FundedPart(part_amount)),
+            (Err(error), _) => old_state.update(SendSMState::Rejected(error)),
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     #[instrument(target = LOG_CLIENT_MODULE_LNV2, skip(refund_keypair, context))]
     async fn gateway_send_payment(
         gateway_api: SafeUrl,
@@ -187,10 +249,13 @@ async fn gateway_send_payment(
         outpoint: OutPoint,
         contract: OutgoingContract,
         invoice: LightningInvoice,
+        part_amount: Option<Amount>,
         refund_keypair: Keypair,
         context: LightningClientContext,
-    ) -> Result<[u8; 32], Signature> {
-        util::retry("gateway-send-payment", api_networking_backoff(), || async {
+    ) -> (Result<[u8; 32], Signature>, Duration) {
+        let start = now();
+
+        let response = util::retry("gateway-send-payment", api_networking_backoff(), || async {
             let payment_result = context
                 .gateway_conn
                 .send_payment(
@@ -199,9 +264,9 @@ async fn gateway_send_payment(
                     outpoint,
                     contract.clone(),
                     invoice.clone(),
-                    refund_keypair.sign_schnorr(secp256k1::Message::from_digest(
-                        *invoice.consensus_hash::<sha256::Hash>().as_ref(),
-                    )),
+                    part_amount,
+                    refund_keypair
+                        .sign_schnorr(SendPaymentPayload::auth_message(&invoice, part_amount)),
                 )
                 .await?;
 
@@ -213,16 +278,20 @@ async fn gateway_send_payment(
             Ok(payment_result)
         })
         .await
-        .expect("Number of retries has no limit")
+        .expect("Number of retries has no limit");
+
+        (response, now().duration_since(start).unwrap_or_default())
     }
 
     async fn transition_gateway_send_payment(
         context: LightningClientContext,
         global_context: DynGlobalClientContext,
         dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
-        gateway_response: Result<[u8; 32], Signature>,
+        (gateway_response, latency): (Result<[u8; 32], Signature>, Duration),
         old_state: SendStateMachine,
     ) -> SendStateMachine {
+        record_gateway_outcome(dbtx, &old_state, gateway_response.is_ok(), Some(latency)).await;
+
         match gateway_response {
             Ok(preimage) => {
                 send_update_event(
@@ -294,6 +363,10 @@ async fn transition_preimage(
         old_state: SendStateMachine,
         preimage: Option<[u8; 32]>,
     ) -> SendStateMachine {
+        // The gateway did not respond to our payment request, so we do not know
+        // how long it took to complete the payment, if at all.
+        record_gateway_outcome(dbtx, &old_state, preimage.is_some(), None).await;
+
         if let Some(preimage) = preimage {
             send_update_event(
                 context,
@@ -148,6 +148,26 @@ pub fn decrypt_preimage(
     pub fn create_decryption_key_share(&self, sk: &SecretKeyShare) -> DecryptionKeyShare {
         create_dk_share(sk, &self.ciphertext)
     }
+
+    /// The message the recipient signs with the claim key to settle or cancel
+    /// a payment the gateway holds for this contract.
+    pub fn hold_resolution_message(&self, settle: bool) -> Message {
+        Message::from_digest(
+            *(self.contract_id(), settle)
+                .consensus_hash::<sha256::Hash>()
+                .as_ref(),
+        )
+    }
+
+    pub fn verify_hold_resolution(&self, settle: bool, signature: &Signature) -> bool {
+        secp256k1::global::SECP256K1
+            .verify_schnorr(
+                signature,
+                &self.hold_resolution_message(settle),
+                &self.commitment.claim_pk.x_only_public_key().0,
+            )
+            .is_ok()
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
@@ -11,6 +11,14 @@
 
 // Gateway endpoints
 pub const CREATE_BOLT11_INVOICE_ENDPOINT: &str = "/create_bolt11_invoice";
+pub const CREATE_BOLT12_OFFER_ENDPOINT: &str = "/create_bolt12_offer";
+pub const CREATE_HOLD_INVOICE_ENDPOINT: &str = "/create_hold_invoice";
+pub const FETCH_BOLT12_INVOICE_ENDPOINT: &str = "/fetch_bolt12_invoice";
+pub const HOLD_INVOICE_STATUS_ENDPOINT: &str = "/hold_invoice_status";
+pub const RESOLVE_HOLD_INVOICE_ENDPOINT: &str = "/resolve_hold_invoice";
 pub const VERIFY_BOLT11_PREIMAGE_ENDPOINT: &str = "/verify_bolt11_preimage";
 pub const ROUTING_INFO_ENDPOINT: &str = "/routing_info";
 pub const SEND_PAYMENT_ENDPOINT: &str = "/send_payment";
+pub const SWAP_ENDPOINT: &str = "/swap";
+pub const SWAP_QUOTE_ENDPOINT: &str = "/swap_quote";
+pub const SWAP_STATUS_ENDPOINT: &str = "/swap_status";
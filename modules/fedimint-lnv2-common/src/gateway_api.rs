@@ -1,21 +1,27 @@
 use std::str::FromStr;
 
-use bitcoin::secp256k1::PublicKey;
+use bitcoin::hashes::sha256;
 use bitcoin::secp256k1::schnorr::Signature;
+use bitcoin::secp256k1::{Message, PublicKey};
 use fedimint_connectors::error::ServerError;
 use fedimint_core::config::FederationId;
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::util::SafeUrl;
 use fedimint_core::{Amount, OutPoint, apply, async_trait_maybe_send};
 use fedimint_ln_common::client::GatewayApi;
+use lightning::offers::invoice::Bolt12Invoice;
+use lightning::offers::offer::Offer;
 use lightning_invoice::{Bolt11Invoice, RoutingFees};
 use reqwest::Method;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::contracts::{IncomingContract, OutgoingContract};
+use crate::contracts::{IncomingContract, OutgoingContract, PaymentImage};
 use crate::endpoint_constants::{
-    CREATE_BOLT11_INVOICE_ENDPOINT, ROUTING_INFO_ENDPOINT, SEND_PAYMENT_ENDPOINT,
+    CREATE_BOLT11_INVOICE_ENDPOINT, CREATE_BOLT12_OFFER_ENDPOINT, CREATE_HOLD_INVOICE_ENDPOINT,
+    FETCH_BOLT12_INVOICE_ENDPOINT, HOLD_INVOICE_STATUS_ENDPOINT, RESOLVE_HOLD_INVOICE_ENDPOINT,
+    ROUTING_INFO_ENDPOINT, SEND_PAYMENT_ENDPOINT, SWAP_ENDPOINT, SWAP_QUOTE_ENDPOINT,
+    SWAP_STATUS_ENDPOINT,
 };
 use crate::{Bolt11InvoiceDescription, LightningInvoice};
 
@@ -37,6 +43,64 @@ async fn bolt11_invoice(
         expiry_secs: u32,
     ) -> Result<Bolt11Invoice, ServerError>;
 
+    /// Requests an invoice like [`Self::bolt11_invoice`], but the gateway
+    /// holds the incoming payment until the recipient settles or cancels it
+    /// via [`Self::resolve_hold_invoice`] and cancels it once the contract
+    /// expires.
+    async fn hold_invoice(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        contract: IncomingContract,
+        amount: Amount,
+        description: Bolt11InvoiceDescription,
+        expiry_secs: u32,
+    ) -> Result<Bolt11Invoice, ServerError>;
+
+    /// Requests the status of the hold invoice for the payment image, or
+    /// `None` if the gateway does not know the hold invoice.
+    async fn hold_invoice_status(
+        &self,
+        gateway_api: SafeUrl,
+        payment_image: PaymentImage,
+    ) -> Result<Option<HoldInvoiceStatus>, ServerError>;
+
+    /// Requests the gateway to settle or cancel the payment it holds for a
+    /// hold invoice.
+    async fn resolve_hold_invoice(
+        &self,
+        gateway_api: SafeUrl,
+        payload: ResolveHoldInvoicePayload,
+    ) -> Result<HoldInvoiceStatus, ServerError>;
+
+    /// Requests a single-use offer from the gateway whose invoices are bound
+    /// to the payment hash of the incoming contract.
+    async fn bolt12_offer(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        contract: IncomingContract,
+        amount: Amount,
+        description: String,
+        expiry_secs: u32,
+    ) -> Result<Offer, ServerError>;
+
+    /// Requests the gateway to fetch an invoice for the offer from the
+    /// offer's issuer, such that the client can bind an outgoing contract to
+    /// its payment hash.
+    async fn fetch_bolt12_invoice(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        offer: Offer,
+        amount: Option<Amount>,
+        payer_note: Option<String>,
+    ) -> Result<Bolt12Invoice, ServerError>;
+
+    /// Requests the gateway to pay the invoice, or only `part_amount` of it as
+    /// one part of a multi-path payment whose other parts are paid by other
+    /// gateways, in exchange for the outgoing contract.
+    #[allow(clippy::too_many_arguments)]
     async fn send_payment(
         &self,
         gateway_api: SafeUrl,
@@ -44,8 +108,39 @@ async fn send_payment(
         outpoint: OutPoint,
         contract: OutgoingContract,
         invoice: LightningInvoice,
+        part_amount: Option<Amount>,
         auth: Signature,
     ) -> Result<Result<[u8; 32], Signature>, ServerError>;
+
+    /// Requests the fees of moving `amount` from the source to the target
+    /// federation, or `None` if the gateway cannot swap between the two.
+    async fn swap_quote(
+        &self,
+        gateway_api: SafeUrl,
+        source_federation_id: FederationId,
+        target_federation_id: FederationId,
+        amount: Amount,
+    ) -> Result<Option<SwapQuote>, ServerError>;
+
+    /// Requests an invoice for an incoming contract in the target federation
+    /// that the gateway settles with an outgoing contract in the source
+    /// federation instead of a payment over the Lightning network. The gateway
+    /// rejects the request if its fees have changed since it issued the quote.
+    async fn swap(
+        &self,
+        gateway_api: SafeUrl,
+        quote: SwapQuote,
+        contract: IncomingContract,
+        expiry_secs: u32,
+    ) -> Result<Bolt11Invoice, ServerError>;
+
+    /// Requests the status of the swap settled with the payment image, or
+    /// `None` if the gateway does not know the swap.
+    async fn swap_status(
+        &self,
+        gateway_api: SafeUrl,
+        payment_image: PaymentImage,
+    ) -> Result<Option<SwapStatus>, ServerError>;
 }
 
 #[derive(Debug, Clone)]
@@ -95,6 +190,122 @@ async fn bolt11_invoice(
             .await
     }
 
+    async fn hold_invoice(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        contract: IncomingContract,
+        amount: Amount,
+        description: Bolt11InvoiceDescription,
+        expiry_secs: u32,
+    ) -> Result<Bolt11Invoice, ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                CREATE_HOLD_INVOICE_ENDPOINT,
+                Some(CreateBolt11InvoicePayload {
+                    federation_id,
+                    contract,
+                    amount,
+                    description,
+                    expiry_secs,
+                }),
+            )
+            .await
+    }
+
+    async fn hold_invoice_status(
+        &self,
+        gateway_api: SafeUrl,
+        payment_image: PaymentImage,
+    ) -> Result<Option<HoldInvoiceStatus>, ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                HOLD_INVOICE_STATUS_ENDPOINT,
+                Some(payment_image),
+            )
+            .await
+    }
+
+    async fn resolve_hold_invoice(
+        &self,
+        gateway_api: SafeUrl,
+        payload: ResolveHoldInvoicePayload,
+    ) -> Result<HoldInvoiceStatus, ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                RESOLVE_HOLD_INVOICE_ENDPOINT,
+                Some(payload),
+            )
+            .await
+    }
+
+    async fn bolt12_offer(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        contract: IncomingContract,
+        amount: Amount,
+        description: String,
+        expiry_secs: u32,
+    ) -> Result<Offer, ServerError> {
+        let offer: String = self
+            .api
+            .request(
+                &gateway_api,
+                Method::POST,
+                CREATE_BOLT12_OFFER_ENDPOINT,
+                Some(CreateBolt12OfferPayload {
+                    federation_id,
+                    contract,
+                    amount,
+                    description,
+                    expiry_secs,
+                }),
+            )
+            .await?;
+
+        Offer::from_str(&offer).map_err(|e| {
+            ServerError::InvalidResponse(anyhow::anyhow!("Received invalid offer: {e:?}"))
+        })
+    }
+
+    async fn fetch_bolt12_invoice(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        offer: Offer,
+        amount: Option<Amount>,
+        payer_note: Option<String>,
+    ) -> Result<Bolt12Invoice, ServerError> {
+        let invoice: LightningInvoice = self
+            .api
+            .request(
+                &gateway_api,
+                Method::POST,
+                FETCH_BOLT12_INVOICE_ENDPOINT,
+                Some(FetchBolt12InvoicePayload {
+                    federation_id,
+                    offer: offer.to_string(),
+                    amount,
+                    payer_note,
+                }),
+            )
+            .await?;
+
+        match invoice {
+            LightningInvoice::Bolt12(invoice) => Ok(invoice),
+            LightningInvoice::Bolt11(..) => Err(ServerError::InvalidResponse(anyhow::anyhow!(
+                "Received a BOLT11 invoice for a BOLT12 offer"
+            ))),
+        }
+    }
+
     async fn send_payment(
         &self,
         gateway_api: SafeUrl,
@@ -102,6 +313,7 @@ async fn send_payment(
         outpoint: OutPoint,
         contract: OutgoingContract,
         invoice: LightningInvoice,
+        part_amount: Option<Amount>,
         auth: Signature,
     ) -> Result<Result<[u8; 32], Signature>, ServerError> {
         self.api
@@ -114,11 +326,69 @@ async fn send_payment(
                     outpoint,
                     contract,
                     invoice,
+                    part_amount,
                     auth,
                 }),
             )
             .await
     }
+
+    async fn swap_quote(
+        &self,
+        gateway_api: SafeUrl,
+        source_federation_id: FederationId,
+        target_federation_id: FederationId,
+        amount: Amount,
+    ) -> Result<Option<SwapQuote>, ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                SWAP_QUOTE_ENDPOINT,
+                Some(SwapQuoteRequest {
+                    source_federation_id,
+                    target_federation_id,
+                    amount,
+                }),
+            )
+            .await
+    }
+
+    async fn swap(
+        &self,
+        gateway_api: SafeUrl,
+        quote: SwapQuote,
+        contract: IncomingContract,
+        expiry_secs: u32,
+    ) -> Result<Bolt11Invoice, ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                SWAP_ENDPOINT,
+                Some(SwapPayload {
+                    quote,
+                    contract,
+                    expiry_secs,
+                }),
+            )
+            .await
+    }
+
+    async fn swap_status(
+        &self,
+        gateway_api: SafeUrl,
+        payment_image: PaymentImage,
+    ) -> Result<Option<SwapStatus>, ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                SWAP_STATUS_ENDPOINT,
+                Some(payment_image),
+            )
+            .await
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -130,15 +400,159 @@ pub struct CreateBolt11InvoicePayload {
     pub expiry_secs: u32,
 }
 
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ResolveHoldInvoicePayload {
+    pub payment_image: PaymentImage,
+    /// Settles the held payment if `true`, cancels it otherwise.
+    pub settle: bool,
+    /// The signature of [`IncomingContract::hold_resolution_message`] by the
+    /// contract's claim key.
+    pub signature: Signature,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub enum HoldInvoiceStatus {
+    /// The gateway waits for the payment.
+    Open,
+    /// The gateway holds the payment until the recipient settles or cancels
+    /// it.
+    Held,
+    /// The recipient has settled the payment, so the gateway funds the
+    /// incoming contract.
+    Settled,
+    /// The recipient has cancelled the payment or the contract has expired.
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CreateBolt12OfferPayload {
+    pub federation_id: FederationId,
+    pub contract: IncomingContract,
+    pub amount: Amount,
+    pub description: String,
+    pub expiry_secs: u32,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FetchBolt12InvoicePayload {
+    pub federation_id: FederationId,
+    /// The bech32 encoded offer
+    pub offer: String,
+    /// The amount to request, required if the offer does not specify one
+    pub amount: Option<Amount>,
+    pub payer_note: Option<String>,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct SendPaymentPayload {
     pub federation_id: FederationId,
     pub outpoint: OutPoint,
     pub contract: OutgoingContract,
     pub invoice: LightningInvoice,
+    /// The amount to pay if the contract only funds a part of a multi-path
+    /// payment of the invoice.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub part_amount: Option<Amount>,
     pub auth: Signature,
 }
 
+impl SendPaymentPayload {
+    /// The message the sender signs with the refund key of the outgoing
+    /// contract to authorize the gateway to pay the invoice, or the given part
+    /// of it, such that nobody else can submit the contract with a different
+    /// invoice or part amount.
+    pub fn auth_message(invoice: &LightningInvoice, part_amount: Option<Amount>) -> Message {
+        let hash = match part_amount {
+            Some(part_amount) => (invoice.clone(), part_amount).consensus_hash::<sha256::Hash>(),
+            None => invoice.consensus_hash::<sha256::Hash>(),
+        };
+
+        Message::from_digest(*hash.as_ref())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SwapQuoteRequest {
+    pub source_federation_id: FederationId,
+    pub target_federation_id: FederationId,
+    /// The amount of the invoice the swap is settled with.
+    pub amount: Amount,
+}
+
+/// The fees a gateway charges for moving ecash from one of its federations to
+/// another. A swap is settled like a payment of an invoice created by the
+/// gateway for the target federation, so it is charged the send fee of a
+/// direct swap in the source federation and the receive fee in the target
+/// federation.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SwapQuote {
+    pub source_federation_id: FederationId,
+    pub target_federation_id: FederationId,
+    pub amount: Amount,
+    /// The fee of the outgoing contract in the source federation.
+    pub send_fee: PaymentFee,
+    /// The expiration delta of the outgoing contract in the source federation.
+    pub expiration_delta: u64,
+    /// The fee deducted from the incoming contract in the target federation.
+    pub receive_fee: PaymentFee,
+}
+
+impl SwapQuote {
+    /// The request this quote answers.
+    pub fn request(&self) -> SwapQuoteRequest {
+        SwapQuoteRequest {
+            source_federation_id: self.source_federation_id,
+            target_federation_id: self.target_federation_id,
+            amount: self.amount,
+        }
+    }
+
+    /// Quotes a swap from the routing info of the gateway for the source and
+    /// the target federation.
+    pub fn new(request: SwapQuoteRequest, source: &RoutingInfo, target: &RoutingInfo) -> Self {
+        SwapQuote {
+            source_federation_id: request.source_federation_id,
+            target_federation_id: request.target_federation_id,
+            amount: request.amount,
+            send_fee: source.send_fee_minimum,
+            expiration_delta: source.expiration_delta_minimum,
+            receive_fee: target.receive_fee_for(request.amount.msats),
+        }
+    }
+
+    /// The amount the sender locks in the source federation.
+    pub fn send_amount(&self) -> Amount {
+        self.send_fee.add_to(self.amount.msats)
+    }
+
+    /// The amount the recipient receives in the target federation.
+    pub fn receive_amount(&self) -> Amount {
+        self.receive_fee.subtract_from(self.amount.msats)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SwapPayload {
+    /// The quote the client accepted, which caps the fees of the swap.
+    pub quote: SwapQuote,
+    /// The incoming contract in the target federation.
+    pub contract: IncomingContract,
+    pub expiry_secs: u32,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum SwapStatus {
+    /// The gateway waits for the outgoing contract in the source federation.
+    AwaitingPayment,
+    /// The gateway is funding the incoming contract in the target federation.
+    Funding,
+    /// The incoming contract has been funded and the preimage released.
+    Completed,
+    /// The incoming contract was not funded or has been refunded to the
+    /// gateway, so the outgoing contract is refunded to the sender.
+    Failed,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct RoutingInfo {
     /// The public key of the gateways lightning node. Since this key signs the
@@ -172,16 +586,75 @@ pub struct RoutingInfo {
     pub expiration_delta_default: u64,
     /// This is the fee the gateway charges for an incoming payment.
     pub receive_fee: PaymentFee,
+    /// These are discounted fees the gateway charges for larger payments. A
+    /// gateway only offers tiers that are cheaper than its untiered fees, so
+    /// a client that ignores them still pays a fee the gateway accepts.
+    ///
+    /// This field is optional for backwards-compatibility with older gateways
+    /// that do not yet provide fee tiers in their `routing_info` responses.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fee_tiers: Vec<RoutingFeeTier>,
+    /// Whether the gateway's lightning node can pay a part of an invoice as
+    /// one shard of a multi-path payment.
+    ///
+    /// This field is optional for backwards-compatibility with older gateways
+    /// that do not yet report it, which are treated as not supporting it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub supports_partial_payments: bool,
+    /// Whether the gateway's lightning node can pay invoices fetched from
+    /// BOLT12 offers and issue offers for incoming contracts.
+    ///
+    /// This field is optional for backwards-compatibility with older gateways
+    /// that do not yet report it, which are treated as not supporting it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub supports_bolt12: bool,
 }
 
 impl RoutingInfo {
-    pub fn send_parameters(&self, invoice: &Bolt11Invoice) -> (PaymentFee, u64) {
-        if invoice.recover_payee_pub_key() == self.lightning_public_key {
+    /// Returns the fee and expiration delta for paying `msats` as a part of a
+    /// multi-path payment. Parts are always paid over the Lightning network.
+    pub fn send_part_parameters(&self, msats: u64) -> (PaymentFee, u64) {
+        let send_fee = self
+            .fee_tier(msats)
+            .map_or(self.send_fee_default, |tier| tier.send_fee_default);
+
+        (send_fee, self.expiration_delta_default)
+    }
+
+    pub fn send_parameters(&self, invoice: &LightningInvoice) -> (PaymentFee, u64) {
+        if invoice.payee_pub_key() == self.lightning_public_key {
             (self.send_fee_minimum, self.expiration_delta_minimum)
         } else {
-            (self.send_fee_default, self.expiration_delta_default)
+            let send_fee = invoice
+                .amount_milli_satoshis()
+                .and_then(|msats| self.fee_tier(msats))
+                .map_or(self.send_fee_default, |tier| tier.send_fee_default);
+
+            (send_fee, self.expiration_delta_default)
         }
     }
+
+    /// Returns the fee the gateway charges for an incoming payment of `msats`.
+    pub fn receive_fee_for(&self, msats: u64) -> PaymentFee {
+        self.fee_tier(msats)
+            .map_or(self.receive_fee, |tier| tier.receive_fee)
+    }
+
+    fn fee_tier(&self, msats: u64) -> Option<&RoutingFeeTier> {
+        self.fee_tiers
+            .iter()
+            .filter(|tier| tier.min_amount.msats <= msats)
+            .max_by_key(|tier| tier.min_amount)
+    }
+}
+
+/// The fees a gateway charges for payments of at least `min_amount`, replacing
+/// the corresponding fees of its [`RoutingInfo`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct RoutingFeeTier {
+    pub min_amount: Amount,
+    pub send_fee_default: PaymentFee,
+    pub receive_fee: PaymentFee,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable, Copy)]
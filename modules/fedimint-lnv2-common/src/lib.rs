@@ -16,7 +16,8 @@
 pub mod lnurl;
 pub mod tweak;
 
-use bitcoin::hashes::sha256;
+use bitcoin::hashes::{Hash as _, sha256};
+use bitcoin::secp256k1::PublicKey;
 use bitcoin::secp256k1::schnorr::Signature;
 use config::LightningClientConfig;
 use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
@@ -26,6 +27,7 @@
     Amount, OutPoint, extensible_associated_module_type, plugin_types_trait_impl_common,
 };
 pub use fedimint_ln_common::client::GatewayApi;
+use lightning::offers::invoice::Bolt12Invoice;
 use lightning_invoice::Bolt11Invoice;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -42,6 +44,46 @@ pub enum Bolt11InvoiceDescription {
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Decodable, Encodable)]
 pub enum LightningInvoice {
     Bolt11(Bolt11Invoice),
+    /// An invoice the gateway has requested from a BOLT12 offer on behalf of
+    /// the client.
+    Bolt12(#[serde(with = "fedimint_core::encoding::as_hex")] Bolt12Invoice),
+}
+
+impl LightningInvoice {
+    pub fn payment_hash(&self) -> sha256::Hash {
+        match self {
+            LightningInvoice::Bolt11(invoice) => *invoice.payment_hash(),
+            LightningInvoice::Bolt12(invoice) => {
+                sha256::Hash::from_byte_array(invoice.payment_hash().0)
+            }
+        }
+    }
+
+    /// Returns the amount of the invoice, which is always present for BOLT12
+    /// invoices but optional for BOLT11 invoices.
+    pub fn amount_milli_satoshis(&self) -> Option<u64> {
+        match self {
+            LightningInvoice::Bolt11(invoice) => invoice.amount_milli_satoshis(),
+            LightningInvoice::Bolt12(invoice) => Some(invoice.amount_msats()),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        match self {
+            LightningInvoice::Bolt11(invoice) => invoice.is_expired(),
+            LightningInvoice::Bolt12(invoice) => invoice.is_expired(),
+        }
+    }
+
+    /// Returns the public key the invoice has been signed with. For BOLT11
+    /// invoices this is the node id of the payee, whereas BOLT12 invoices may
+    /// be signed with a key derived for the offer.
+    pub fn payee_pub_key(&self) -> PublicKey {
+        match self {
+            LightningInvoice::Bolt11(invoice) => invoice.recover_payee_pub_key(),
+            LightningInvoice::Bolt12(invoice) => invoice.signing_pubkey(),
+        }
+    }
 }
 
 pub const KIND: ModuleKind = ModuleKind::from_static_str("lnv2");
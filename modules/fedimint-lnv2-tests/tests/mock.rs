@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 use anyhow::anyhow;
+use bitcoin::Network;
 use bitcoin::hashes::{Hash, sha256};
 use bitcoin::secp256k1::{SECP256K1, SecretKey};
 use fedimint_api_client::api::ServerError;
@@ -12,8 +13,13 @@
 use fedimint_core::{Amount, OutPoint, apply, async_trait_maybe_send};
 use fedimint_ln_common::bitcoin;
 use fedimint_lnv2_common::contracts::{IncomingContract, OutgoingContract, PaymentImage};
-use fedimint_lnv2_common::gateway_api::{GatewayConnection, PaymentFee, RoutingInfo};
+use fedimint_lnv2_common::gateway_api::{
+    GatewayConnection, HoldInvoiceStatus, PaymentFee, ResolveHoldInvoicePayload, RoutingInfo,
+    SwapQuote, SwapQuoteRequest, SwapStatus,
+};
 use fedimint_lnv2_common::{Bolt11InvoiceDescription, LightningInvoice};
+use lightning::offers::invoice::Bolt12Invoice;
+use lightning::offers::offer::{Offer, OfferBuilder};
 use lightning_invoice::{
     Bolt11Invoice, Currency, DEFAULT_EXPIRY_TIME, InvoiceBuilder, PaymentSecret,
 };
@@ -24,6 +30,10 @@
 
 const UNPAYABLE_PAYMENT_SECRET: [u8; 32] = [212; 32];
 
+const OTHER_GATEWAY_NODE_SECRET: [u8; 32] = [214; 32];
+
+const LDK_GATEWAY_NODE_SECRET: [u8; 32] = [215; 32];
+
 const GATEWAY_CRASH_PAYMENT_SECRET: [u8; 32] = [213; 32];
 
 pub const MOCK_INVOICE_PREIMAGE: [u8; 32] = [1; 32];
@@ -32,6 +42,18 @@ pub fn gateway() -> SafeUrl {
     SafeUrl::parse("https://gateway.xyz").expect("Valid Url")
 }
 
+/// A second gateway with its own lightning node, such that payments can be
+/// split across both gateways.
+pub fn other_gateway() -> SafeUrl {
+    SafeUrl::parse("https://other-gateway.xyz").expect("Valid Url")
+}
+
+/// A gateway whose lightning node cannot pay parts of an invoice, like a
+/// gateway running LDK Node.
+pub fn ldk_gateway() -> SafeUrl {
+    SafeUrl::parse("https://ldk-gateway.xyz").expect("Valid Url")
+}
+
 pub fn gateway_keypair() -> Keypair {
     SecretKey::from_slice(&GATEWAY_SECRET)
         .expect("32 bytes; within curve order")
@@ -50,6 +72,14 @@ pub fn crash_invoice() -> Bolt11Invoice {
     bolt_11_invoice(GATEWAY_CRASH_PAYMENT_SECRET, Currency::Regtest)
 }
 
+pub fn offer() -> Offer {
+    OfferBuilder::new(SecretKey::new(&mut OsRng).public_key(SECP256K1))
+        .chain(Network::Regtest)
+        .amount_msats(1_000_000)
+        .build()
+        .expect("Offer creation failed")
+}
+
 fn bolt_11_invoice(payment_secret: [u8; 32], currency: Currency) -> Bolt11Invoice {
     let sk = SecretKey::new(&mut OsRng);
     let payment_hash = sha256::Hash::hash(&MOCK_INVOICE_PREIMAGE);
@@ -87,11 +117,23 @@ fn default() -> Self {
 impl GatewayConnection for MockGatewayConnection {
     async fn routing_info(
         &self,
-        _gateway_api: SafeUrl,
+        gateway_api: SafeUrl,
         _federation_id: &FederationId,
     ) -> Result<Option<RoutingInfo>, ServerError> {
+        let lightning_public_key = if gateway_api == other_gateway() {
+            SecretKey::from_slice(&OTHER_GATEWAY_NODE_SECRET)
+                .expect("32 bytes; within curve order")
+                .public_key(SECP256K1)
+        } else if gateway_api == ldk_gateway() {
+            SecretKey::from_slice(&LDK_GATEWAY_NODE_SECRET)
+                .expect("32 bytes; within curve order")
+                .public_key(SECP256K1)
+        } else {
+            self.keypair.public_key()
+        };
+
         Ok(Some(RoutingInfo {
-            lightning_public_key: self.keypair.public_key(),
+            lightning_public_key,
             lightning_alias: Some("mock-gateway".to_string()),
             module_public_key: self.keypair.public_key(),
             send_fee_default: PaymentFee::TRANSACTION_FEE_DEFAULT,
@@ -99,6 +141,9 @@ async fn routing_info(
             expiration_delta_default: 500,
             expiration_delta_minimum: 144,
             receive_fee: PaymentFee::TRANSACTION_FEE_DEFAULT,
+            fee_tiers: Vec::new(),
+            supports_partial_payments: gateway_api != ldk_gateway(),
+            supports_bolt12: false,
         }))
     }
 
@@ -128,6 +173,73 @@ async fn bolt11_invoice(
             .unwrap())
     }
 
+    async fn hold_invoice(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        contract: IncomingContract,
+        invoice_amount: Amount,
+        description: Bolt11InvoiceDescription,
+        expiry_time: u32,
+    ) -> Result<Bolt11Invoice, ServerError> {
+        self.bolt11_invoice(
+            gateway_api,
+            federation_id,
+            contract,
+            invoice_amount,
+            description,
+            expiry_time,
+        )
+        .await
+    }
+
+    async fn hold_invoice_status(
+        &self,
+        _gateway_api: SafeUrl,
+        _payment_image: PaymentImage,
+    ) -> Result<Option<HoldInvoiceStatus>, ServerError> {
+        Ok(Some(HoldInvoiceStatus::Open))
+    }
+
+    async fn resolve_hold_invoice(
+        &self,
+        _gateway_api: SafeUrl,
+        payload: ResolveHoldInvoicePayload,
+    ) -> Result<HoldInvoiceStatus, ServerError> {
+        Ok(if payload.settle {
+            HoldInvoiceStatus::Settled
+        } else {
+            HoldInvoiceStatus::Cancelled
+        })
+    }
+
+    async fn bolt12_offer(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        _contract: IncomingContract,
+        _amount: Amount,
+        _description: String,
+        _expiry_secs: u32,
+    ) -> Result<Offer, ServerError> {
+        Err(ServerError::InvalidRequest(anyhow!(
+            "Mock gateway does not support BOLT12"
+        )))
+    }
+
+    async fn fetch_bolt12_invoice(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        _offer: Offer,
+        _amount: Option<Amount>,
+        _payer_note: Option<String>,
+    ) -> Result<Bolt12Invoice, ServerError> {
+        Err(ServerError::InvalidRequest(anyhow!(
+            "Mock gateway does not support BOLT12"
+        )))
+    }
+
     async fn send_payment(
         &self,
         _gateway_api: SafeUrl,
@@ -135,6 +247,7 @@ async fn send_payment(
         _outpoint: OutPoint,
         contract: OutgoingContract,
         invoice: LightningInvoice,
+        _part_amount: Option<Amount>,
         _auth: Signature,
     ) -> Result<Result<[u8; 32], Signature>, ServerError> {
         match invoice {
@@ -151,6 +264,81 @@ async fn send_payment(
 
                 Ok(Ok(MOCK_INVOICE_PREIMAGE))
             }
+            LightningInvoice::Bolt12(..) => Err(ServerError::InvalidRequest(anyhow!(
+                "Mock gateway does not support BOLT12"
+            ))),
+        }
+    }
+
+    async fn swap_quote(
+        &self,
+        gateway_api: SafeUrl,
+        source_federation_id: FederationId,
+        target_federation_id: FederationId,
+        amount: Amount,
+    ) -> Result<Option<SwapQuote>, ServerError> {
+        let source = self
+            .routing_info(gateway_api.clone(), &source_federation_id)
+            .await?
+            .expect("Mock gateway supports every federation");
+        let target = self
+            .routing_info(gateway_api, &target_federation_id)
+            .await?
+            .expect("Mock gateway supports every federation");
+
+        Ok(Some(SwapQuote::new(
+            SwapQuoteRequest {
+                source_federation_id,
+                target_federation_id,
+                amount,
+            },
+            &source,
+            &target,
+        )))
+    }
+
+    async fn swap(
+        &self,
+        gateway_api: SafeUrl,
+        quote: SwapQuote,
+        contract: IncomingContract,
+        expiry_secs: u32,
+    ) -> Result<Bolt11Invoice, ServerError> {
+        let current = self
+            .swap_quote(
+                gateway_api.clone(),
+                quote.source_federation_id,
+                quote.target_federation_id,
+                quote.amount,
+            )
+            .await?;
+
+        if current != Some(quote.clone()) {
+            return Err(ServerError::InvalidRequest(anyhow!(
+                "The swap quote is no longer valid"
+            )));
         }
+
+        self.bolt11_invoice(
+            gateway_api,
+            quote.target_federation_id,
+            contract,
+            quote.amount,
+            Bolt11InvoiceDescription::Direct(String::new()),
+            expiry_secs,
+        )
+        .await
+    }
+
+    async fn swap_status(
+        &self,
+        _gateway_api: SafeUrl,
+        payment_image: PaymentImage,
+    ) -> Result<Option<SwapStatus>, ServerError> {
+        // The mock gateway never funds the incoming contract of a swap
+        Ok(match payment_image {
+            PaymentImage::Hash(..) => Some(SwapStatus::AwaitingPayment),
+            PaymentImage::Point(..) => None,
+        })
     }
 }
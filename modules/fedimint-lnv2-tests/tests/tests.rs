@@ -18,9 +18,11 @@
     ReceivePaymentEvent, SendPaymentEvent, SendPaymentStatus, SendPaymentUpdateEvent,
 };
 use fedimint_lnv2_client::{
-    InvoiceSendStatus, LightningClientInit, LightningClientModule, LightningOperationMeta,
-    ReceiveOperationState, SendOperationState, SendPaymentError,
+    FinalSendOperationState, GatewayStats, InvoiceSendStatus, LightningClientInit,
+    LightningClientModule, LightningOperationMeta, ReceiveError, ReceiveOperationState,
+    ResolveHeldPaymentError, SendOperationState, SendPaymentError, SwapError,
 };
+use fedimint_lnv2_common::gateway_api::{PaymentFee, SwapStatus};
 use fedimint_lnv2_common::{
     Bolt11InvoiceDescription, KIND, LightningInput, LightningInputV0, OutgoingWitness,
 };
@@ -182,6 +184,129 @@ async fn can_pay_external_invoice_exactly_once() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn offers_require_a_gateway_supporting_bolt12() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+    let client = fed.new_client().await;
+
+    client
+        .get_first_module::<DummyClientModule>()?
+        .mock_receive(sats(10_000), AmountUnit::BITCOIN)
+        .await?;
+
+    let lightning = client.get_first_module::<LightningClientModule>()?;
+
+    // The mock gateways do not report BOLT12 support, so no contract is funded
+    assert_eq!(
+        lightning
+            .send_offer(
+                mock::offer(),
+                None,
+                None,
+                Some(mock::gateway()),
+                Value::Null
+            )
+            .await,
+        Err(SendPaymentError::Bolt12NotSupported),
+    );
+
+    assert_eq!(
+        lightning
+            .receive_offer(
+                Amount::from_sats(1000),
+                3600,
+                String::new(),
+                Some(mock::gateway()),
+                Value::Null,
+            )
+            .await
+            .map(|(_, operation_id)| operation_id),
+        Err(ReceiveError::Bolt12NotSupported),
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn can_pay_invoice_in_multiple_parts() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+    let client = fed.new_client().await;
+
+    // Give client initial balance
+    client
+        .get_first_module::<DummyClientModule>()?
+        .mock_receive(sats(10_000), AmountUnit::BITCOIN)
+        .await?;
+
+    let lightning = client.get_first_module::<LightningClientModule>()?;
+    let invoice = mock::payable_invoice();
+
+    // Both parts have to be paid by distinct lightning nodes
+    assert_eq!(
+        lightning
+            .send_multi_path(
+                invoice.clone(),
+                2,
+                Some(vec![mock::gateway(), mock::gateway()]),
+                Value::Null
+            )
+            .await,
+        Err(SendPaymentError::NotEnoughGateways),
+    );
+
+    // Gateways that cannot pay a part of an invoice are skipped
+    assert_eq!(
+        lightning
+            .send_multi_path(
+                invoice.clone(),
+                2,
+                Some(vec![mock::gateway(), mock::ldk_gateway()]),
+                Value::Null
+            )
+            .await,
+        Err(SendPaymentError::NotEnoughGateways),
+    );
+
+    assert_eq!(
+        lightning
+            .send_multi_path(invoice.clone(), 1, Some(vec![mock::gateway()]), Value::Null)
+            .await,
+        Err(SendPaymentError::InvalidNumberOfParts),
+    );
+
+    let operation_id = lightning
+        .send_multi_path(
+            invoice.clone(),
+            2,
+            Some(vec![mock::gateway(), mock::other_gateway()]),
+            Value::Null,
+        )
+        .await?;
+
+    assert_eq!(
+        lightning
+            .send(invoice.clone(), Some(mock::gateway()), Value::Null)
+            .await,
+        Err(SendPaymentError::DuplicatePaymentAttempt(operation_id)),
+    );
+
+    let mut sub = lightning
+        .subscribe_send_operation_state_updates(operation_id)
+        .await?
+        .into_stream();
+
+    assert_eq!(sub.ok().await?, SendOperationState::Funding);
+    assert_eq!(sub.ok().await?, SendOperationState::Funded);
+    assert_eq!(
+        sub.ok().await?,
+        SendOperationState::Success(MOCK_INVOICE_PREIMAGE)
+    );
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn refund_failed_payment() -> anyhow::Result<()> {
     let fixtures = fixtures();
@@ -236,6 +361,83 @@ async fn refund_failed_payment() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn payment_outcomes_are_recorded_in_gateway_scores() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+    let client = fed.new_client().await;
+
+    // Give client initial balance
+    client
+        .get_first_module::<DummyClientModule>()?
+        .mock_receive(sats(10_000), AmountUnit::BITCOIN)
+        .await?;
+
+    let lightning = client.get_first_module::<LightningClientModule>()?;
+
+    let operation_id = lightning
+        .send(mock::payable_invoice(), Some(mock::gateway()), Value::Null)
+        .await?;
+
+    assert_eq!(
+        lightning
+            .await_final_send_operation_state(operation_id)
+            .await?,
+        FinalSendOperationState::Success(MOCK_INVOICE_PREIMAGE)
+    );
+
+    let operation_id = lightning
+        .send(
+            mock::unpayable_invoice(),
+            Some(mock::other_gateway()),
+            Value::Null,
+        )
+        .await?;
+
+    assert_eq!(
+        lightning
+            .await_final_send_operation_state(operation_id)
+            .await?,
+        FinalSendOperationState::Refunded
+    );
+
+    let scores = lightning.gateway_scores().await;
+    let unused_score = GatewayStats::default().score();
+
+    let reliable = &scores[&mock::gateway()];
+
+    assert_eq!(reliable.stats.successes, 1);
+    assert_eq!(reliable.stats.refunds, 0);
+    assert_eq!(reliable.stats.amount_sent, Amount::from_sats(1000));
+    assert!(unused_score < reliable.score());
+
+    let flaky = &scores[&mock::other_gateway()];
+
+    assert_eq!(flaky.stats.successes, 0);
+    assert_eq!(flaky.stats.refunds, 1);
+    assert!(flaky.score() < unused_score);
+
+    lightning
+        .set_gateway_score_override(mock::gateway(), Some(0))
+        .await;
+
+    assert_eq!(
+        lightning.gateway_scores().await[&mock::gateway()].score(),
+        0
+    );
+
+    lightning
+        .set_gateway_score_override(mock::gateway(), None)
+        .await;
+
+    assert_eq!(
+        lightning.gateway_scores().await[&mock::gateway()].override_score,
+        None
+    );
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn unilateral_refund_of_outgoing_contracts() -> anyhow::Result<()> {
     if Fixtures::is_real_test() {
@@ -353,6 +555,12 @@ async fn claiming_outgoing_contract_triggers_success() -> anyhow::Result<()> {
         LightningOperationMeta::LnurlReceive(..) => {
             panic!("Operation Meta is a LnurlReceive variant")
         }
+        LightningOperationMeta::OfferReceive(..) => {
+            panic!("Operation Meta is an OfferReceive variant")
+        }
+        LightningOperationMeta::MultiPathSend(..) => {
+            panic!("Operation Meta is a MultiPathSend variant")
+        }
     };
 
     let client_input = ClientInput::<LightningInput> {
@@ -427,6 +635,150 @@ async fn receive_operation_expires() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn hold_receive_operation_can_be_cancelled() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+    let client = fed.new_client().await;
+    let lightning = client.get_first_module::<LightningClientModule>()?;
+
+    let op = lightning
+        .receive_hold(
+            Amount::from_sats(1000),
+            5, // receive operation expires in 5 seconds
+            0,
+            Bolt11InvoiceDescription::Direct(String::new()),
+            Some(mock::gateway()),
+            Value::Null,
+        )
+        .await?
+        .1;
+
+    let mut sub = lightning
+        .subscribe_receive_operation_state_updates(op)
+        .await?
+        .into_stream();
+
+    assert_eq!(sub.ok().await?, ReceiveOperationState::Pending);
+
+    lightning.cancel_held_payment(op).await?;
+
+    assert_eq!(sub.ok().await?, ReceiveOperationState::Expired);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_resolving_payment_without_hold_invoice() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+    let client = fed.new_client().await;
+    let lightning = client.get_first_module::<LightningClientModule>()?;
+
+    let op = lightning
+        .receive(
+            Amount::from_sats(1000),
+            3600,
+            Bolt11InvoiceDescription::Direct(String::new()),
+            Some(mock::gateway()),
+            Value::Null,
+        )
+        .await?
+        .1;
+
+    assert_eq!(
+        lightning.settle_held_payment(op).await,
+        Err(ResolveHeldPaymentError::UnknownHoldReceive)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn swap_is_bound_to_quote() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let source_fed = fixtures.new_fed_degraded().await;
+    let target_fed = fixtures.new_fed_degraded().await;
+    let source_client = source_fed.new_client().await;
+    let target_client = target_fed.new_client().await;
+
+    source_client
+        .get_first_module::<DummyClientModule>()?
+        .mock_receive(sats(10_000), AmountUnit::BITCOIN)
+        .await?;
+
+    let source = source_client.get_first_module::<LightningClientModule>()?;
+    let target = target_client.get_first_module::<LightningClientModule>()?;
+
+    assert_eq!(
+        source
+            .swap_quote(
+                source_client.federation_id(),
+                Amount::from_sats(1000),
+                Some(mock::gateway()),
+            )
+            .await,
+        Err(SwapError::SameFederation)
+    );
+
+    let (gateway, quote) = source
+        .swap_quote(
+            target_client.federation_id(),
+            Amount::from_sats(1000),
+            Some(mock::gateway()),
+        )
+        .await?;
+
+    assert_eq!(quote.send_fee, PaymentFee::TRANSACTION_FEE_DEFAULT);
+
+    // A quote for another federation cannot be used
+    let mut wrong_target = quote.clone();
+    wrong_target.target_federation_id = source_client.federation_id();
+
+    assert_eq!(
+        source
+            .swap(&target, gateway.clone(), wrong_target, Value::Null)
+            .await,
+        Err(SwapError::InvalidQuote)
+    );
+
+    // The gateway rejects a quote that no longer matches its fees
+    let mut stale_quote = quote.clone();
+    stale_quote.send_fee = PaymentFee {
+        base: Amount::ZERO,
+        parts_per_million: 0,
+    };
+
+    assert!(matches!(
+        source
+            .swap(&target, gateway.clone(), stale_quote, Value::Null)
+            .await,
+        Err(SwapError::FailedToConnectToGateway(..))
+    ));
+
+    let operation_ids = source.swap(&target, gateway, quote, Value::Null).await?;
+
+    let mut sub = source
+        .subscribe_send_operation_state_updates(operation_ids.send_operation_id)
+        .await?
+        .into_stream();
+
+    assert_eq!(sub.ok().await?, SendOperationState::Funding);
+    assert_eq!(sub.ok().await?, SendOperationState::Funded);
+
+    assert_eq!(
+        target.swap_status(operation_ids.receive_operation_id).await,
+        Ok(Some(SwapStatus::AwaitingPayment))
+    );
+
+    assert_eq!(
+        target.swap_status(operation_ids.send_operation_id).await,
+        Err(SwapError::UnknownSwap)
+    );
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn rejects_wrong_network_invoice() -> anyhow::Result<()> {
     let fixtures = fixtures();
@@ -875,6 +1227,10 @@ async fn test_client_db_migrations() -> anyhow::Result<()> {
                                 "the stream index must round-trip unchanged, got {index}"
                             );
                         }
+                        db::DbKeyPrefix::GatewayStats | db::DbKeyPrefix::GatewayScoreOverride => {
+                            // Introduced after v0, so the snapshot holds no
+                            // rows under these prefixes.
+                        }
                         db::DbKeyPrefix::ExternalReservedStart
                         | db::DbKeyPrefix::CoreInternalReservedStart
                         | db::DbKeyPrefix::CoreInternalReservedEnd => {
@@ -54,7 +54,10 @@
     DbKeyPrefix, NoteKeyPrefix, RecoveryFinalizedKey, RecoveryStateKey, RecoveryStateV2Key,
     ReusedNoteIndices, migrate_state_to_v2, migrate_to_v1,
 };
-use events::{NoteSpent, OOBNotesReissued, OOBNotesSpent, ReceivePaymentEvent, SendPaymentEvent};
+use events::{
+    NoteSpent, OOBNotesReissued, OOBNotesSpent, ReceivePaymentEvent, ReceivePaymentStatus,
+    ReceivePaymentUpdateEvent, SendPaymentEvent,
+};
 use fedimint_api_client::api::DynModuleApi;
 use fedimint_client_module::db::{ClientModuleMigrationFn, migrate_state};
 use fedimint_client_module::module::init::{
@@ -66,6 +69,9 @@
     PrimaryModuleSupport,
 };
 use fedimint_client_module::oplog::{OperationLogEntry, UpdateStreamOrOutcome};
+use fedimint_client_module::payment_history::{
+    PaymentDirection, PaymentHistoryUpdate, PaymentInfo, PaymentStatus,
+};
 use fedimint_client_module::sm::{Context, DynState, ModuleNotifier, State, StateTransition};
 use fedimint_client_module::transaction::{
     ClientInput, ClientInputBundle, ClientInputSM, ClientOutput, ClientOutputBundle,
@@ -94,6 +100,7 @@
     async_trait_maybe_send, base32, push_db_pair_items,
 };
 use fedimint_derive_secret::{ChildId, DerivableSecret};
+use fedimint_eventlog::EventLogEntry;
 use fedimint_logging::LOG_CLIENT_MODULE_MINT;
 pub use fedimint_mint_common as common;
 use fedimint_mint_common::config::{FeeConsensus, MintClientConfig};
@@ -1018,6 +1025,45 @@ fn output_fee(
         ))
     }
 
+    async fn payment_history_update(&self, event: &EventLogEntry) -> Option<PaymentHistoryUpdate> {
+        // Spending notes out of band completes the operation right away
+        if let Some(event) = event.to_event_of_kind::<SendPaymentEvent>() {
+            return Some(PaymentHistoryUpdate::Created(PaymentInfo {
+                operation_id: event.operation_id,
+                direction: PaymentDirection::Send,
+                amount: event.amount,
+                fee: Amount::ZERO,
+                status: PaymentStatus::Success,
+                counterparty: None,
+                payment_hash: None,
+            }));
+        }
+
+        if let Some(event) = event.to_event_of_kind::<ReceivePaymentEvent>() {
+            return Some(PaymentHistoryUpdate::Created(PaymentInfo {
+                operation_id: event.operation_id,
+                direction: PaymentDirection::Receive,
+                amount: event.amount,
+                fee: Amount::ZERO,
+                status: PaymentStatus::Pending,
+                counterparty: None,
+                payment_hash: None,
+            }));
+        }
+
+        if let Some(event) = event.to_event_of_kind::<ReceivePaymentUpdateEvent>() {
+            return Some(PaymentHistoryUpdate::Status {
+                operation_id: event.operation_id,
+                status: match event.status {
+                    ReceivePaymentStatus::Success => PaymentStatus::Success,
+                    ReceivePaymentStatus::Rejected => PaymentStatus::Failed,
+                },
+            });
+        }
+
+        None
+    }
+
     #[cfg(feature = "cli")]
     async fn handle_cli_command(
         &self,
@@ -22,6 +22,10 @@ enum Opts {
     },
     /// Receive the `ECash` by reissuing the notes and return the amount.
     Receive { ecash: String },
+    /// Show the reissuance a note consolidation would perform and its fee.
+    ConsolidationPlan,
+    /// Reissue surplus notes into the target distribution.
+    Consolidate,
 }
 
 pub(crate) async fn handle_cli_command(
@@ -52,6 +56,8 @@ pub(crate) async fn handle_cli_command(
 
             Ok(json(state))
         }
+        Opts::ConsolidationPlan => Ok(json(mint.consolidation_plan().await)),
+        Opts::Consolidate => Ok(json(mint.consolidate().await?)),
     }
 }
 
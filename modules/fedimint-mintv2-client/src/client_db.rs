@@ -8,6 +8,7 @@
 use strum_macros::EnumIter;
 
 use crate::SpendableNote;
+use crate::consolidation::ConsolidationConfig;
 use crate::issuance::NoteIssuanceRequest;
 
 #[repr(u8)]
@@ -15,6 +16,7 @@
 pub enum DbKeyPrefix {
     Note = 0x20,
     RecoveryState = 0x21,
+    ConsolidationConfig = 0x22,
 }
 
 #[derive(Debug, Clone, Encodable, Decodable)]
@@ -62,3 +64,13 @@ pub struct RecoveryState {
     value = RecoveryState,
     db_prefix = DbKeyPrefix::RecoveryState,
 );
+
+/// Key for the user's note consolidation settings, absent while disabled
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct ConsolidationConfigKey;
+
+impl_db_record!(
+    key = ConsolidationConfigKey,
+    value = ConsolidationConfig,
+    db_prefix = DbKeyPrefix::ConsolidationConfig,
+);
@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+
+use fedimint_core::Amount;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_mintv2_common::Denomination;
+use fedimint_mintv2_common::config::{FeeConsensus, client_denominations};
+use serde::{Deserialize, Serialize};
+
+use crate::{TARGET_PER_DENOMINATION, represent_amount_with_fees};
+
+/// Configures the background consolidation of the client's notes. While the
+/// client is idle it reissues surplus notes into the target distribution as
+/// long as the federation fees for doing so stay within `max_fee`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct ConsolidationConfig {
+    /// Number of notes to hold per denomination. Denominations that are not
+    /// listed default to three notes.
+    pub target: BTreeMap<Denomination, u64>,
+    /// Maximum total fee a single background consolidation may pay.
+    pub max_fee: Amount,
+}
+
+impl ConsolidationConfig {
+    pub fn target(&self, denomination: Denomination) -> u64 {
+        self.target
+            .get(&denomination)
+            .copied()
+            .unwrap_or(TARGET_PER_DENOMINATION)
+    }
+}
+
+/// The reissuance a consolidation would perform given the client's current
+/// notes, as returned by the dry-run API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsolidationPlan {
+    /// Number of notes spent per denomination.
+    pub inputs: BTreeMap<Denomination, u64>,
+    /// Number of notes issued per denomination.
+    pub outputs: BTreeMap<Denomination, u64>,
+    /// Value lost to federation fees, including any remainder too small to be
+    /// represented by a note after fees.
+    pub fee: Amount,
+}
+
+impl ConsolidationPlan {
+    pub fn input_count(&self) -> u64 {
+        self.inputs.values().sum()
+    }
+
+    pub fn output_count(&self) -> u64 {
+        self.outputs.values().sum()
+    }
+}
+
+/// Plans a reissuance of all notes exceeding twice their denomination's
+/// target. The freed value first tops up denominations below target, smallest
+/// first, and the remainder is issued greedily. Returns `None` if there is
+/// nothing to consolidate or the reissuance would not reduce the number of
+/// notes held, so repeated runs converge instead of churning.
+pub(crate) fn plan_consolidation(
+    counts: &BTreeMap<Denomination, u64>,
+    target: impl Fn(Denomination) -> u64,
+    fee_consensus: &FeeConsensus,
+) -> Option<ConsolidationPlan> {
+    let count = |d: Denomination| counts.get(&d).copied().unwrap_or(0);
+
+    let mut inputs = BTreeMap::new();
+    let mut input_amount = Amount::ZERO;
+    let mut excess = Amount::ZERO;
+
+    for d in client_denominations() {
+        if count(d) <= target(d).saturating_mul(2) {
+            continue;
+        }
+
+        // Notes that do not cover their own input fee are not worth spending
+        let Some(note_value) = d
+            .amount()
+            .checked_sub(fee_consensus.fee(d.amount()))
+            .filter(|value| *value > Amount::ZERO)
+        else {
+            continue;
+        };
+
+        let n_surplus = count(d) - target(d);
+
+        inputs.insert(d, n_surplus);
+        input_amount += n_surplus * d.amount();
+        excess += n_surplus * note_value;
+    }
+
+    if inputs.is_empty() {
+        return None;
+    }
+
+    let mut outputs = BTreeMap::new();
+
+    for d in client_denominations() {
+        let cost = d.amount() + fee_consensus.fee(d.amount());
+
+        let n_add = target(d).saturating_sub(count(d)).min(excess / cost);
+
+        if n_add > 0 {
+            outputs.insert(d, n_add);
+            excess -= n_add * cost;
+        }
+    }
+
+    for d in represent_amount_with_fees(excess, fee_consensus) {
+        *outputs.entry(d).or_default() += 1;
+    }
+
+    let output_amount = outputs.iter().map(|(d, n)| *n * d.amount()).sum::<Amount>();
+
+    let plan = ConsolidationPlan {
+        inputs,
+        outputs,
+        fee: input_amount - output_amount,
+    };
+
+    (plan.output_count() < plan.input_count()).then_some(plan)
+}
@@ -13,6 +13,7 @@
 #[cfg(feature = "cli")]
 mod cli;
 pub mod client_db;
+mod consolidation;
 mod ecash;
 mod events;
 mod input;
@@ -27,7 +28,10 @@
 
 use anyhow::{Context as _, anyhow};
 use bitcoin_hashes::sha256;
-use client_db::{RecoveryState, RecoveryStateKey, SpendableNoteAmountPrefix, SpendableNotePrefix};
+use client_db::{
+    ConsolidationConfigKey, RecoveryState, RecoveryStateKey, SpendableNoteAmountPrefix,
+    SpendableNotePrefix,
+};
 pub use events::*;
 use fedimint_api_client::api::DynModuleApi;
 use fedimint_client::module::ClientModule;
@@ -43,21 +47,30 @@
 use fedimint_client_module::module::{
     ClientContext, OutPointRange, PrimaryModulePriority, PrimaryModuleSupport,
 };
+use fedimint_client_module::payment_history::{
+    PaymentDirection, PaymentHistoryUpdate, PaymentInfo, PaymentStatus,
+};
 use fedimint_client_module::sm::{Context, DynState, ModuleNotifier, State, StateTransition};
 use fedimint_client_module::{DynGlobalClientContext, sm_enum_variant_translation};
 use fedimint_core::base32::{self, FEDIMINT_PREFIX};
 use fedimint_core::config::FederationId;
 use fedimint_core::core::{IntoDynInstance, ModuleInstanceId, ModuleKind, OperationId};
-use fedimint_core::db::{DatabaseTransaction, DatabaseVersion, IDatabaseTransactionOpsCoreTyped};
+use fedimint_core::db::{
+    AutocommitError, DatabaseTransaction, DatabaseVersion, IDatabaseTransactionOpsCoreTyped,
+};
 use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::envs::is_running_in_test_env;
 use fedimint_core::module::{
     AmountUnit, Amounts, ApiVersion, CommonModuleInit, ModuleCommon, ModuleInit, MultiApiVersion,
 };
 use fedimint_core::secp256k1::rand::{Rng, thread_rng};
 use fedimint_core::secp256k1::{Keypair, PublicKey};
-use fedimint_core::util::{BoxStream, NextOrPending};
+use fedimint_core::task::timeout;
+use fedimint_core::util::{BoxStream, FmtCompactAnyhow as _, NextOrPending};
 use fedimint_core::{Amount, OutPoint, PeerId, apply, async_trait_maybe_send};
 use fedimint_derive_secret::DerivableSecret;
+use fedimint_eventlog::EventLogEntry;
+use fedimint_logging::LOG_CLIENT_MODULE_MINT;
 use fedimint_mintv2_common::config::{FeeConsensus, MintClientConfig, client_denominations};
 use fedimint_mintv2_common::{
     Denomination, KIND, MintCommonInit, MintInput, MintModuleTypes, MintOutput, Note, RecoveryItem,
@@ -69,16 +82,18 @@
 use serde_json::Value;
 use tbs::AggregatePublicKey;
 use thiserror::Error;
+use tracing::warn;
 
 use crate::api::MintV2ModuleApi;
 use crate::client_db::SpendableNoteKey;
+pub use crate::consolidation::{ConsolidationConfig, ConsolidationPlan};
 pub use crate::ecash::ECash;
 use crate::input::{InputSMCommon, InputSMState, InputStateMachine};
 use crate::issuance::NoteIssuanceRequest;
 use crate::output::{MintOutputStateMachine, OutputSMCommon, OutputSMState};
 use crate::receive::{ReceiveSMState, ReceiveStateMachine};
 
-const TARGET_PER_DENOMINATION: usize = 3;
+const TARGET_PER_DENOMINATION: u64 = 3;
 const SLICE_SIZE: u64 = 10000;
 const PARALLEL_HASH_REQUESTS: usize = 10;
 const PARALLEL_SLICE_REQUESTS: usize = 10;
@@ -126,6 +141,10 @@ pub enum MintOperationMeta {
         ecash: String,
         custom_meta: Value,
     },
+    Consolidate {
+        change_outpoint_range: OutPointRange,
+        fee: Amount,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -326,13 +345,37 @@ async fn init(&self, args: &ClientModuleInitArgs<Self>) -> anyhow::Result<Self::
             }
         });
 
+        let (balance_update_sender, mut balance_updates) = tokio::sync::watch::channel(());
+
+        let client_ctx = args.context();
+
+        // Consolidating spends notes, so we wait for the balance to settle to
+        // avoid competing with the user's own transactions for them.
+        args.spawn_cancellable("mintv2-note-consolidation", async move {
+            loop {
+                match timeout(consolidation_idle_duration(), balance_updates.changed()).await {
+                    Ok(Ok(())) => continue,
+                    Ok(Err(_)) => return,
+                    Err(_) => {}
+                }
+
+                if let Err(err) = client_ctx.self_ref().consolidate_inner(true).await {
+                    warn!(
+                        target: LOG_CLIENT_MODULE_MINT,
+                        err = %err.fmt_compact_anyhow(),
+                        "Failed to consolidate notes"
+                    );
+                }
+            }
+        });
+
         Ok(MintClientModule {
             federation_id: *args.federation_id(),
             cfg: args.cfg().clone(),
             root_secret: args.module_root_secret().clone(),
             notifier: args.notifier().clone(),
             client_ctx: args.context(),
-            balance_update_sender: tokio::sync::watch::channel(()).0,
+            balance_update_sender,
             tweak_receiver,
         })
     }
@@ -406,6 +449,45 @@ fn output_fee(
         Some(Amounts::new_custom(unit, fee))
     }
 
+    async fn payment_history_update(&self, event: &EventLogEntry) -> Option<PaymentHistoryUpdate> {
+        // Spending ecash completes the operation right away
+        if let Some(event) = event.to_event_of_kind::<SendPaymentEvent>() {
+            return Some(PaymentHistoryUpdate::Created(PaymentInfo {
+                operation_id: event.operation_id,
+                direction: PaymentDirection::Send,
+                amount: event.amount,
+                fee: Amount::ZERO,
+                status: PaymentStatus::Success,
+                counterparty: None,
+                payment_hash: None,
+            }));
+        }
+
+        if let Some(event) = event.to_event_of_kind::<ReceivePaymentEvent>() {
+            return Some(PaymentHistoryUpdate::Created(PaymentInfo {
+                operation_id: event.operation_id,
+                direction: PaymentDirection::Receive,
+                amount: event.amount,
+                fee: Amount::ZERO,
+                status: PaymentStatus::Pending,
+                counterparty: None,
+                payment_hash: None,
+            }));
+        }
+
+        if let Some(event) = event.to_event_of_kind::<ReceivePaymentUpdateEvent>() {
+            return Some(PaymentHistoryUpdate::Status {
+                operation_id: event.operation_id,
+                status: match event.status {
+                    ReceivePaymentStatus::Success => PaymentStatus::Success,
+                    ReceivePaymentStatus::Rejected => PaymentStatus::Failed,
+                },
+            });
+        }
+
+        None
+    }
+
     #[cfg(feature = "cli")]
     async fn handle_cli_command(
         &self,
@@ -538,11 +620,15 @@ async fn select_funding_input(
         dbtx: &mut DatabaseTransaction<'_>,
         mut excess_output: Amount,
     ) -> Option<Vec<SpendableNote>> {
+        let target = Self::target_distribution(dbtx).await;
+
         let mut selected_notes = Vec::new();
         let mut target_notes = Vec::new();
         let mut excess_notes = Vec::new();
 
         for amount in client_denominations().rev() {
+            let target = target[&amount] as usize;
+
             let notes_amount = dbtx
                 .find_by_prefix(&SpendableNoteAmountPrefix(amount))
                 .await
@@ -550,10 +636,10 @@ async fn select_funding_input(
                 .collect::<Vec<SpendableNote>>()
                 .await;
 
-            target_notes.extend(notes_amount.iter().take(TARGET_PER_DENOMINATION).cloned());
+            target_notes.extend(notes_amount.iter().take(target).cloned());
 
-            if notes_amount.len() > 2 * TARGET_PER_DENOMINATION {
-                for note in notes_amount.into_iter().skip(TARGET_PER_DENOMINATION) {
+            if notes_amount.len() > target.saturating_mul(2) {
+                for note in notes_amount.into_iter().skip(target) {
                     let note_fee = self.cfg.fee_consensus.fee(note.amount());
 
                     let note_value = note
@@ -566,7 +652,7 @@ async fn select_funding_input(
                     selected_notes.push(note);
                 }
             } else {
-                excess_notes.extend(notes_amount.into_iter().skip(TARGET_PER_DENOMINATION));
+                excess_notes.extend(notes_amount.into_iter().skip(target));
             }
         }
 
@@ -599,6 +685,7 @@ async fn rebalance(
         mut excess_input: Amount,
     ) -> (Vec<SpendableNote>, Vec<Denomination>) {
         let n_denominations = self.get_count_by_denomination_dbtx(dbtx).await;
+        let target = Self::target_distribution(dbtx).await;
 
         let mut notes = dbtx
             .find_by_prefix_sorted_descending(&SpendableNotePrefix)
@@ -612,7 +699,7 @@ async fn rebalance(
         for d in client_denominations() {
             let n_denomination = n_denominations.get(&d).copied().unwrap_or(0);
 
-            let n_missing = TARGET_PER_DENOMINATION.saturating_sub(n_denomination as usize);
+            let n_missing = target[&d].saturating_sub(n_denomination);
 
             for _ in 0..n_missing {
                 match excess_input.checked_sub(d.amount() + fee.fee(d.amount())) {
@@ -638,6 +725,24 @@ async fn rebalance(
         (input_notes, output_denominations)
     }
 
+    /// The number of notes to hold per denomination, taken from the
+    /// consolidation config if one is set.
+    async fn target_distribution(
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> BTreeMap<Denomination, u64> {
+        let config = dbtx.get_value(&ConsolidationConfigKey).await;
+
+        client_denominations()
+            .map(|d| {
+                let target = config
+                    .as_ref()
+                    .map_or(TARGET_PER_DENOMINATION, |config| config.target(d));
+
+                (d, target)
+            })
+            .collect()
+    }
+
     fn create_input_bundle(
         operation_id: OperationId,
         notes: Vec<SpendableNote>,
@@ -772,6 +877,144 @@ async fn get_count_by_denomination_dbtx(
             .await
     }
 
+    /// Set the target distribution the client consolidates its notes towards
+    /// while idle, or disable background consolidation by passing `None`.
+    pub async fn set_consolidation_config(&self, config: Option<ConsolidationConfig>) {
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+        match config {
+            Some(config) => {
+                dbtx.insert_entry(&ConsolidationConfigKey, &config).await;
+            }
+            None => {
+                dbtx.remove_entry(&ConsolidationConfigKey).await;
+            }
+        }
+
+        dbtx.commit_tx().await;
+    }
+
+    pub async fn consolidation_config(&self) -> Option<ConsolidationConfig> {
+        self.client_ctx
+            .module_db()
+            .begin_transaction_nc()
+            .await
+            .get_value(&ConsolidationConfigKey)
+            .await
+    }
+
+    /// Dry run of [`Self::consolidate`]: return the reissuance it would
+    /// currently perform and the fee it would pay without submitting anything.
+    pub async fn consolidation_plan(&self) -> Option<ConsolidationPlan> {
+        self.consolidation_plan_dbtx(&mut self.client_ctx.module_db().begin_transaction_nc().await)
+            .await
+    }
+
+    async fn consolidation_plan_dbtx(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> Option<ConsolidationPlan> {
+        let counts = self.get_count_by_denomination_dbtx(dbtx).await;
+        let target = Self::target_distribution(dbtx).await;
+
+        consolidation::plan_consolidation(&counts, |d| target[&d], &self.cfg.fee_consensus)
+    }
+
+    /// Reissue surplus notes into the target distribution and return the
+    /// operation ID, or `None` if there is nothing to consolidate. Unlike the
+    /// background consolidation this ignores the configured maximum fee.
+    pub async fn consolidate(&self) -> anyhow::Result<Option<OperationId>> {
+        self.consolidate_inner(false).await
+    }
+
+    async fn consolidate_inner(&self, background: bool) -> anyhow::Result<Option<OperationId>> {
+        self.client_ctx
+            .module_db()
+            .autocommit(
+                |dbtx, _| Box::pin(self.consolidate_dbtx(dbtx, background)),
+                Some(100),
+            )
+            .await
+            .map_err(|e| match e {
+                AutocommitError::ClosureError { error, .. } => error,
+                AutocommitError::CommitFailed { last_error, .. } => {
+                    anyhow!("Commit to DB failed: {last_error}")
+                }
+            })
+    }
+
+    async fn consolidate_dbtx(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        background: bool,
+    ) -> anyhow::Result<Option<OperationId>> {
+        let config = dbtx.get_value(&ConsolidationConfigKey).await;
+
+        if background && config.is_none() {
+            return Ok(None);
+        }
+
+        let Some(plan) = self.consolidation_plan_dbtx(dbtx).await else {
+            return Ok(None);
+        };
+
+        if background && config.is_some_and(|config| plan.fee > config.max_fee) {
+            return Ok(None);
+        }
+
+        let mut notes = Vec::new();
+
+        for (denomination, n_notes) in &plan.inputs {
+            notes.extend(
+                dbtx.find_by_prefix(&SpendableNoteAmountPrefix(*denomination))
+                    .await
+                    .map(|entry| entry.0.0)
+                    .take(*n_notes as usize)
+                    .collect::<Vec<SpendableNote>>()
+                    .await,
+            );
+        }
+
+        for note in &notes {
+            self.remove_spendable_note(dbtx, note).await;
+        }
+
+        let operation_id = OperationId::new_random();
+
+        let input = Self::create_input_bundle(operation_id, notes, false, self.cfg.amount_unit);
+        let input = self.client_ctx.make_client_inputs(input);
+
+        let denominations = plan
+            .outputs
+            .iter()
+            .flat_map(|(denomination, n_notes)| {
+                std::iter::repeat_n(*denomination, *n_notes as usize)
+            })
+            .collect();
+
+        let output = self.create_output_bundle(operation_id, denominations).await;
+        let output = self.client_ctx.make_client_outputs(output);
+
+        let fee = plan.fee;
+
+        self.client_ctx
+            .finalize_and_submit_transaction_dbtx(
+                dbtx,
+                operation_id,
+                MintCommonInit::KIND.as_str(),
+                move |change_outpoint_range| MintOperationMeta::Consolidate {
+                    change_outpoint_range,
+                    fee,
+                },
+                TransactionBuilder::new()
+                    .with_inputs(input)
+                    .with_outputs(output),
+            )
+            .await?;
+
+        Ok(Some(operation_id))
+    }
+
     /// Send `ECash` for the given amount and return the send operation ID. The
     /// amount will be rounded up to a multiple of 512 msats which is the
     /// smallest denomination used throughout the client. If the rounded
@@ -1308,6 +1551,16 @@ fn operation_id(&self) -> OperationId {
     }
 }
 
+/// How long the balance has to remain unchanged before the client considers
+/// itself idle and consolidates its notes.
+fn consolidation_idle_duration() -> Duration {
+    if is_running_in_test_env() {
+        Duration::from_secs(1)
+    } else {
+        Duration::from_secs(60)
+    }
+}
+
 fn round_to_multiple(amount: Amount, min_denomiation: Amount) -> Amount {
     Amount::from_msats(amount.msats.next_multiple_of(min_denomiation.msats))
 }
@@ -12,14 +12,16 @@
 use fedimint_core::db::mem_impl::MemDatabase;
 use fedimint_core::module::Amounts;
 use fedimint_core::secp256k1::{Keypair, SECP256K1};
+use fedimint_core::task::sleep_in_test;
 use fedimint_dummy_client::{DummyClientInit, DummyClientModule};
 use fedimint_dummy_server::DummyInit;
 use fedimint_eventlog::{Event, EventLogEntry, EventLogId};
 use fedimint_mintv2_client::{
-    ECash, FinalReceiveOperationState, MintClientInit, MintClientModule, MintOperationMeta,
-    ReceiveECashError, ReceivePaymentEvent, ReceivePaymentStatus, ReceivePaymentUpdateEvent,
-    SendECashError, SendPaymentEvent, SpendableNote,
+    ConsolidationConfig, ECash, FinalReceiveOperationState, MintClientInit, MintClientModule,
+    MintOperationMeta, ReceiveECashError, ReceivePaymentEvent, ReceivePaymentStatus,
+    ReceivePaymentUpdateEvent, SendECashError, SendPaymentEvent, SpendableNote,
 };
+use fedimint_mintv2_common::config::client_denominations;
 use fedimint_mintv2_common::{Denomination, KIND};
 use fedimint_mintv2_server::MintInit;
 use fedimint_testing::federation::FederationTest;
@@ -661,6 +663,69 @@ async fn send_without_funds_reports_insufficient_balance() -> anyhow::Result<()>
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn idle_client_consolidates_notes_within_fee_budget() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_not_degraded().await;
+    let client = fed.new_client().await;
+
+    issue_ecash(&client, Amount::from_sats(11_000)).await?;
+    client.wait_for_all_active_state_machines().await?;
+
+    let mint = client.get_first_module::<MintClientModule>()?;
+
+    // Issuance already rebalances towards the default target
+    ensure!(mint.consolidation_plan().await.is_none());
+
+    let mut config = ConsolidationConfig {
+        target: client_denominations().map(|d| (d, 1)).collect(),
+        max_fee: Amount::ZERO,
+    };
+
+    mint.set_consolidation_config(Some(config.clone())).await;
+
+    let plan = mint
+        .consolidation_plan()
+        .await
+        .expect("Notes exceed the lowered target");
+
+    ensure!(plan.output_count() < plan.input_count());
+    ensure!(plan.fee > Amount::ZERO);
+
+    let before = client.get_balance_for_btc().await?;
+    let count_before: u64 = mint.get_count_by_denomination().await.values().sum();
+
+    // The plan exceeds the fee budget, so the idle client must leave it alone
+    sleep_in_test(
+        "give the client time to become idle",
+        std::time::Duration::from_secs(3),
+    )
+    .await;
+
+    ensure!(mint.consolidation_plan().await == Some(plan.clone()));
+
+    config.max_fee = plan.fee;
+
+    mint.set_consolidation_config(Some(config)).await;
+
+    while mint.consolidation_plan().await.is_some() {
+        sleep_in_test(
+            "waiting for the idle client to consolidate",
+            std::time::Duration::from_millis(100),
+        )
+        .await;
+    }
+
+    client.wait_for_all_active_state_machines().await?;
+
+    let count_after: u64 = mint.get_count_by_denomination().await.values().sum();
+
+    ensure!(client.get_balance_for_btc().await? == before - plan.fee);
+    ensure!(count_after == count_before - plan.input_count() + plan.output_count());
+
+    Ok(())
+}
+
 mod db {
     use std::collections::{BTreeMap, BTreeSet};
 
@@ -1017,6 +1082,8 @@ async fn test_client_db_migrations() -> anyhow::Result<()> {
                                 "the seen nonces must round-trip unchanged"
                             );
                         }
+                        // Consolidation settings postdate the v0 snapshot
+                        client_db::DbKeyPrefix::ConsolidationConfig => {}
                     }
                 }
 
@@ -19,7 +19,7 @@
 /// but retained for time being to ensure existing peg-ins complete.
 mod deposit;
 pub mod events;
-use events::SendPaymentEvent;
+use events::{ReceivePaymentEvent, SendPaymentEvent, SendPaymentStatus, SendPaymentStatusEvent};
 #[cfg(feature = "uniffi")]
 pub mod ffi;
 /// Peg-in monitor: a task monitoring deposit addresses for peg-ins.
@@ -46,6 +46,9 @@
 use fedimint_client_module::module::recovery::RecoveryProgress;
 use fedimint_client_module::module::{ClientContext, ClientModule, IClientModule, OutPointRange};
 use fedimint_client_module::oplog::UpdateStreamOrOutcome;
+use fedimint_client_module::payment_history::{
+    PaymentDirection, PaymentHistoryUpdate, PaymentInfo, PaymentStatus,
+};
 use fedimint_client_module::sm::{Context, DynState, ModuleNotifier, State, StateTransition};
 use fedimint_client_module::transaction::{
     ClientOutput, ClientOutputBundle, ClientOutputSM, FeeQuote, FeeQuoteRequest,
@@ -70,6 +73,7 @@
     runtime, secp256k1,
 };
 use fedimint_derive_secret::{ChildId, DerivableSecret};
+use fedimint_eventlog::EventLogEntry;
 use fedimint_logging::LOG_CLIENT_MODULE_WALLET;
 pub use fedimint_wallet_common as common;
 use fedimint_wallet_common::config::{FeeConsensus, WalletClientConfig};
@@ -667,6 +671,55 @@ fn output_fee(
         Some(Amounts::new_bitcoin(self.cfg().fee_consensus.peg_out_abs))
     }
 
+    async fn payment_history_update(&self, event: &EventLogEntry) -> Option<PaymentHistoryUpdate> {
+        if let Some(event) = event.to_event_of_kind::<SendPaymentEvent>() {
+            let address = match self.operation_meta(event.operation_id).await {
+                Some(WalletOperationMetaVariant::Withdraw { address, .. }) => Some(address),
+                _ => None,
+            };
+
+            return Some(PaymentHistoryUpdate::Created(PaymentInfo {
+                operation_id: event.operation_id,
+                direction: PaymentDirection::Send,
+                amount: fedimint_core::Amount::from_sats(event.amount.to_sat()),
+                fee: fedimint_core::Amount::from_sats(event.fee.to_sat()),
+                status: PaymentStatus::Pending,
+                counterparty: address.map(|address| address.assume_checked().to_string()),
+                payment_hash: None,
+            }));
+        }
+
+        if let Some(event) = event.to_event_of_kind::<SendPaymentStatusEvent>() {
+            return Some(PaymentHistoryUpdate::Status {
+                operation_id: event.operation_id,
+                status: match event.status {
+                    SendPaymentStatus::Success(..) => PaymentStatus::Success,
+                    SendPaymentStatus::Aborted => PaymentStatus::Failed,
+                },
+            });
+        }
+
+        // The event is only logged once the deposit is confirmed and claimed
+        if let Some(event) = event.to_event_of_kind::<ReceivePaymentEvent>() {
+            let address = match self.operation_meta(event.operation_id).await {
+                Some(WalletOperationMetaVariant::Deposit { address, .. }) => Some(address),
+                _ => None,
+            };
+
+            return Some(PaymentHistoryUpdate::Created(PaymentInfo {
+                operation_id: event.operation_id,
+                direction: PaymentDirection::Receive,
+                amount: event.amount,
+                fee: fedimint_core::Amount::ZERO,
+                status: PaymentStatus::Success,
+                counterparty: address.map(|address| address.assume_checked().to_string()),
+                payment_hash: None,
+            }));
+        }
+
+        None
+    }
+
     async fn handle_rpc(
         &self,
         method: String,
@@ -791,6 +844,18 @@ impl Context for WalletClientContext {
 }
 
 impl WalletClientModule {
+    async fn operation_meta(
+        &self,
+        operation_id: OperationId,
+    ) -> Option<WalletOperationMetaVariant> {
+        self.client_ctx
+            .get_operation(operation_id)
+            .await
+            .ok()
+            .and_then(|operation| operation.try_meta::<WalletOperationMeta>().ok())
+            .map(|meta| meta.variant)
+    }
+
     fn cfg(&self) -> &WalletClientConfig {
         &self.data.cfg
     }
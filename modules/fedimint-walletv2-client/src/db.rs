@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::{impl_db_lookup, impl_db_record};
 use serde::Serialize;
@@ -8,6 +10,8 @@
 pub enum DbKeyPrefix {
     NextOutputIndex = 0x31,
     ValidAddressIndex = 0x32,
+    AddressDeposits = 0x33,
+    DeferredOutput = 0x34,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -41,3 +45,55 @@ fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
     key = ValidAddressIndexKey,
     query_prefix = ValidAddressIndexPrefix
 );
+
+/// The deposits we have discovered for the receive address of an index, which
+/// we use to detect reuse of the address.
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct AddressDepositsKey(pub u64);
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct AddressDeposits {
+    /// Time since the unix epoch of the block containing the first deposit, or
+    /// of its discovery if the federation does not know the block time
+    pub first_deposit: Duration,
+    /// Number of deposits discovered for the address
+    pub count: u64,
+}
+
+impl_db_record!(
+    key = AddressDepositsKey,
+    value = AddressDeposits,
+    db_prefix = DbKeyPrefix::AddressDeposits
+);
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct AddressDepositsPrefix;
+
+impl_db_lookup!(
+    key = AddressDepositsKey,
+    query_prefix = AddressDepositsPrefix
+);
+
+/// Deposits we have not claimed yet since their value did not exceed the dust
+/// threshold derived from the receive fee, keyed by their output index. We
+/// claim them once the receive fee has dropped sufficiently.
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct DeferredOutputKey(pub u64);
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct DeferredOutput {
+    pub address_index: u64,
+    pub value: bitcoin::Amount,
+    pub outpoint: Option<bitcoin::OutPoint>,
+}
+
+impl_db_record!(
+    key = DeferredOutputKey,
+    value = DeferredOutput,
+    db_prefix = DbKeyPrefix::DeferredOutput
+);
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct DeferredOutputPrefix;
+
+impl_db_lookup!(key = DeferredOutputKey, query_prefix = DeferredOutputPrefix);
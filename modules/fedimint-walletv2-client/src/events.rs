@@ -57,6 +57,59 @@ impl Event for ReceivePaymentEvent {
     const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
 }
 
+/// Event emitted when we discover another deposit to a receive address which
+/// has received funds before. Every deposit is claimed by its own receive
+/// operation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReceiveAddressReuseEvent {
+    pub address: Address<NetworkUnchecked>,
+    pub outpoint: Option<bitcoin::OutPoint>,
+    pub value: bitcoin::Amount,
+    /// Number of deposits to the address including this one
+    pub deposits: u64,
+}
+
+impl Event for ReceiveAddressReuseEvent {
+    const MODULE: Option<ModuleKind> = Some(fedimint_walletv2_common::KIND);
+    const KIND: EventKind = EventKind::from_static("receive-address-reuse");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
+
+/// Event emitted when we discover a deposit to a receive address after its
+/// lifetime has passed. The deposit is claimed nonetheless, yet the sender
+/// should be advised not to reuse the address.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReceiveExpiredAddressEvent {
+    pub address: Address<NetworkUnchecked>,
+    pub outpoint: Option<bitcoin::OutPoint>,
+    pub value: bitcoin::Amount,
+    /// Number of deposits to the address including this one
+    pub deposits: u64,
+}
+
+impl Event for ReceiveExpiredAddressEvent {
+    const MODULE: Option<ModuleKind> = Some(fedimint_walletv2_common::KIND);
+    const KIND: EventKind = EventKind::from_static("receive-expired-address");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
+
+/// Event emitted when we defer claiming a deposit since its value does not
+/// exceed the dust threshold derived from the current receive fee. The deposit
+/// is claimed once the receive fee has dropped sufficiently.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReceiveDustEvent {
+    pub address: Address<NetworkUnchecked>,
+    pub outpoint: Option<bitcoin::OutPoint>,
+    pub value: bitcoin::Amount,
+    pub dust_threshold: bitcoin::Amount,
+}
+
+impl Event for ReceiveDustEvent {
+    const MODULE: Option<ModuleKind> = Some(fedimint_walletv2_common::KIND);
+    const KIND: EventKind = EventKind::from_static("receive-dust");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
+
 /// Status of a receive (pegin) operation.
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub enum ReceivePaymentStatus {
@@ -22,8 +22,15 @@
 use api::WalletFederationApi;
 use bitcoin::address::NetworkUnchecked;
 use bitcoin::{Address, ScriptBuf};
-use db::{NextOutputIndexKey, ValidAddressIndexKey, ValidAddressIndexPrefix};
-use events::{ReceivePaymentEvent, SendPaymentEvent};
+use db::{
+    AddressDeposits, AddressDepositsKey, DeferredOutput, DeferredOutputKey, DeferredOutputPrefix,
+    NextOutputIndexKey, ValidAddressIndexKey, ValidAddressIndexPrefix,
+};
+use events::{
+    ReceiveAddressReuseEvent, ReceiveDustEvent, ReceiveExpiredAddressEvent, ReceivePaymentEvent,
+    ReceivePaymentStatus, ReceivePaymentUpdateEvent, SendPaymentEvent, SendPaymentStatus,
+    SendPaymentUpdateEvent,
+};
 use fedimint_api_client::api::{DynModuleApi, FederationResult};
 use fedimint_client::DynGlobalClientContext;
 use fedimint_client::transaction::{
@@ -34,6 +41,9 @@
 use fedimint_client_module::module::init::{ClientModuleInit, ClientModuleInitArgs};
 use fedimint_client_module::module::recovery::NoModuleBackup;
 use fedimint_client_module::module::{ClientContext, ClientModule, OutPointRange};
+use fedimint_client_module::payment_history::{
+    PaymentDirection, PaymentHistoryUpdate, PaymentInfo, PaymentStatus,
+};
 use fedimint_client_module::sm::{Context, DynState, ModuleNotifier, State, StateTransition};
 use fedimint_client_module::sm_enum_variant_translation;
 use fedimint_core::core::{IntoDynInstance, ModuleInstanceId, ModuleKind, OperationId};
@@ -45,18 +55,20 @@
     AmountUnit, Amounts, ApiVersion, CommonModuleInit, ModuleCommon, ModuleInit, MultiApiVersion,
 };
 use fedimint_core::task::{TaskGroup, TaskHandle, sleep};
+use fedimint_core::time::duration_since_epoch;
 use fedimint_core::{Amount, OutPoint, TransactionId, apply, async_trait_maybe_send};
 use fedimint_derive_secret::{ChildId, DerivableSecret};
-use fedimint_eventlog::{Event, EventLogId};
+use fedimint_eventlog::{Event, EventLogEntry, EventLogId};
 use fedimint_logging::LOG_CLIENT_MODULE_WALLETV2;
-use fedimint_walletv2_common::config::WalletClientConfig;
+use fedimint_walletv2_common::config::{WalletClientConfig, WalletDescriptor};
+use fedimint_walletv2_common::frost::{TaprootKey, group_public_key};
 use fedimint_walletv2_common::{
     KIND, OutputInfo, StandardScript, TxInfo, WalletCommonInit, WalletInput, WalletInputV0,
     WalletModuleTypes, WalletOutput, WalletOutputV0, descriptor, is_potential_receive,
 };
 use futures::StreamExt;
 use receive_sm::{ReceiveSMCommon, ReceiveSMState, ReceiveStateMachine};
-use secp256k1::Keypair;
+use secp256k1::{Keypair, PublicKey};
 use send_sm::{SendSMCommon, SendSMState, SendStateMachine};
 use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator as _;
@@ -69,6 +81,10 @@
 /// Number of event log entries to read per batch.
 const EVENT_LOG_PAGE_SIZE: u64 = 1000;
 
+/// Default duration for which a receive address may receive repeat deposits
+/// after its first deposit.
+pub const DEFAULT_ADDRESS_LIFETIME: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum WalletOperationMeta {
     Send(SendMeta),
@@ -118,6 +134,10 @@ pub enum FinalReceiveOperationState {
 pub struct WalletClientModule {
     root_secret: DerivableSecret,
     cfg: WalletClientConfig,
+    /// The group key of a federation using the taproot descriptor, which we
+    /// cache since the output scanner derives a large number of addresses.
+    group_public_key: Option<PublicKey>,
+    address_lifetime: Duration,
     notifier: ModuleNotifier<WalletClientStateMachines>,
     client_ctx: ClientContext<Self>,
     db: Database,
@@ -167,6 +187,55 @@ fn output_fee(
             .map(|a| Amounts::new_bitcoin(self.cfg.fee_consensus.fee(*a)))
     }
 
+    async fn payment_history_update(&self, event: &EventLogEntry) -> Option<PaymentHistoryUpdate> {
+        if let Some(event) = event.to_event_of_kind::<SendPaymentEvent>() {
+            return Some(PaymentHistoryUpdate::Created(PaymentInfo {
+                operation_id: event.operation_id,
+                direction: PaymentDirection::Send,
+                amount: Amount::from_sats(event.value.to_sat()),
+                fee: Amount::from_sats(event.fee.to_sat()),
+                status: PaymentStatus::Pending,
+                counterparty: Some(event.address.assume_checked().to_string()),
+                payment_hash: None,
+            }));
+        }
+
+        if let Some(event) = event.to_event_of_kind::<SendPaymentUpdateEvent>() {
+            return Some(PaymentHistoryUpdate::Status {
+                operation_id: event.operation_id,
+                status: match event.status {
+                    SendPaymentStatus::Success(..) => PaymentStatus::Success,
+                    SendPaymentStatus::Aborted => PaymentStatus::Failed,
+                },
+            });
+        }
+
+        // The fee is deducted from the value of the deposit
+        if let Some(event) = event.to_event_of_kind::<ReceivePaymentEvent>() {
+            return Some(PaymentHistoryUpdate::Created(PaymentInfo {
+                operation_id: event.operation_id,
+                direction: PaymentDirection::Receive,
+                amount: Amount::from_sats(event.value.to_sat().saturating_sub(event.fee.to_sat())),
+                fee: Amount::from_sats(event.fee.to_sat()),
+                status: PaymentStatus::Pending,
+                counterparty: Some(event.address.assume_checked().to_string()),
+                payment_hash: None,
+            }));
+        }
+
+        if let Some(event) = event.to_event_of_kind::<ReceivePaymentUpdateEvent>() {
+            return Some(PaymentHistoryUpdate::Status {
+                operation_id: event.operation_id,
+                status: match event.status {
+                    ReceivePaymentStatus::Success => PaymentStatus::Success,
+                    ReceivePaymentStatus::Aborted => PaymentStatus::Failed,
+                },
+            });
+        }
+
+        None
+    }
+
     #[cfg(feature = "cli")]
     async fn handle_cli_command(
         &self,
@@ -176,8 +245,21 @@ async fn handle_cli_command(
     }
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct WalletClientInit;
+#[derive(Debug, Clone)]
+pub struct WalletClientInit {
+    /// Duration for which a receive address may receive repeat deposits after
+    /// its first deposit. Deposits to the address after its lifetime has
+    /// passed are still claimed, but emit a [`ReceiveExpiredAddressEvent`].
+    pub address_lifetime: Duration,
+}
+
+impl Default for WalletClientInit {
+    fn default() -> Self {
+        Self {
+            address_lifetime: DEFAULT_ADDRESS_LIFETIME,
+        }
+    }
+}
 
 impl ModuleInit for WalletClientInit {
     type Common = WalletCommonInit;
@@ -204,6 +286,11 @@ async fn init(&self, args: &ClientModuleInitArgs<Self>) -> anyhow::Result<Self::
         let module = WalletClientModule {
             root_secret: args.module_root_secret().clone(),
             cfg: args.cfg().clone(),
+            group_public_key: match args.cfg().descriptor {
+                WalletDescriptor::Wsh => None,
+                WalletDescriptor::Tr => Some(group_public_key(&args.cfg().bitcoin_pks)),
+            },
+            address_lifetime: self.address_lifetime,
             notifier: args.notifier().clone(),
             client_ctx: args.context(),
             db: args.db().clone(),
@@ -365,6 +452,25 @@ pub async fn receive_fee(&self) -> Result<bitcoin::Amount, ReceiveError> {
             .ok_or(ReceiveError::NoConsensusFeerateAvailable)
     }
 
+    /// Fetch the current dust threshold for deposits. Deposits which do not
+    /// exceed it are not claimed until the receive fee has dropped, since the
+    /// remainder after the receive fee cannot cover the fee of the federation.
+    pub async fn receive_dust_threshold(&self) -> Result<bitcoin::Amount, ReceiveError> {
+        Ok(self.dust_threshold(self.receive_fee().await?))
+    }
+
+    fn dust_threshold(&self, receive_fee: bitcoin::Amount) -> bitcoin::Amount {
+        let fee_consensus = &self.cfg.fee_consensus;
+
+        // The smallest claim which exceeds its own relative and base fee
+        let min_claim_msats = fee_consensus.base.msats.saturating_mul(1_000_000)
+            / 1_000_000_u64
+                .saturating_sub(fee_consensus.parts_per_million)
+                .max(1);
+
+        receive_fee + bitcoin::Amount::from_sat(min_claim_msats.div_ceil(1000))
+    }
+
     /// Send an onchain payment with the given fee.
     pub async fn send(
         &self,
@@ -565,6 +671,12 @@ async fn valid_index(&self) -> Option<u64> {
 
     /// Returns the next unused receive address.
     ///
+    /// Every deposit to the address is claimed by its own receive operation,
+    /// including repeat deposits before and after the address lifetime
+    /// configured in [`WalletClientInit`]. Deposits which do not exceed
+    /// [`Self::receive_dust_threshold`] are claimed once the receive fee has
+    /// dropped sufficiently.
+    ///
     /// To wait for a payment to this address race-free, read the client's
     /// current event log position (via the global `get_next_event_log_id`)
     /// *before* calling this, then pass that position to
@@ -669,11 +781,15 @@ async fn next_receive_operation(&self, position: EventLogId) -> (OperationId, Ev
     }
 
     fn derive_address(&self, index: u64) -> Address {
-        descriptor(
-            &self.cfg.bitcoin_pks,
-            &self.derive_tweak(index).public_key().consensus_hash(),
-        )
-        .address(self.cfg.network)
+        let tweak = self.derive_tweak(index).public_key().consensus_hash();
+
+        match self.group_public_key {
+            Some(group_public_key) => Address::p2tr_tweaked(
+                TaprootKey::new(&group_public_key, &tweak).output_key,
+                self.cfg.network,
+            ),
+            None => descriptor(&self.cfg.bitcoin_pks, &tweak).address(self.cfg.network),
+        }
     }
 
     fn derive_tweak(&self, index: u64) -> Keypair {
@@ -840,6 +956,10 @@ fn spawn_output_scanner(&self, task_group: &TaskGroup, client_span: &tracing::Sp
     }
 
     async fn check_outputs(&self, handle: &TaskHandle) -> anyhow::Result<bool> {
+        if !self.claim_deferred_outputs().await? {
+            return Ok(false);
+        }
+
         let mut dbtx = self.db.begin_transaction_nc().await;
 
         let next_output_index = dbtx.get_value(&NextOutputIndexKey).await.unwrap_or(0);
@@ -851,6 +971,10 @@ async fn check_outputs(&self, handle: &TaskHandle) -> anyhow::Result<bool> {
             .collect()
             .await;
 
+        let now = duration_since_epoch();
+
+        // We keep monitoring an address after its lifetime has passed, since a
+        // late deposit is still the user's money.
         let mut address_map: BTreeMap<ScriptBuf, u64> = valid_indices
             .iter()
             .map(|&i| (self.derive_address(i).script_pubkey(), i))
@@ -865,7 +989,9 @@ async fn check_outputs(&self, handle: &TaskHandle) -> anyhow::Result<bool> {
         let mut matched_num: usize = 0;
 
         for output in &outputs {
-            if let Some(&address_index) = address_map.get(&output.script) {
+            let address_index = address_map.get(&output.script).copied();
+
+            if let Some(address_index) = address_index {
                 matched_num += 1;
 
                 // Claim before extending the valid index list: the index search
@@ -902,10 +1028,50 @@ async fn check_outputs(&self, handle: &TaskHandle) -> anyhow::Result<bool> {
 
             let mut dbtx = self.db.begin_transaction().await;
 
+            let deposit_time = output
+                .block_time
+                .map_or(now, |time| Duration::from_secs(time.into()));
+
+            let address_deposits = match address_index {
+                Some(address_index) => {
+                    let address_deposits = dbtx
+                        .get_value(&AddressDepositsKey(address_index))
+                        .await
+                        .map_or(
+                            AddressDeposits {
+                                first_deposit: deposit_time,
+                                count: 1,
+                            },
+                            |deposits| AddressDeposits {
+                                first_deposit: deposits.first_deposit,
+                                count: deposits.count + 1,
+                            },
+                        );
+
+                    dbtx.insert_entry(&AddressDepositsKey(address_index), &address_deposits)
+                        .await;
+
+                    let expired = address_deposits
+                        .first_deposit
+                        .saturating_add(self.address_lifetime)
+                        <= deposit_time;
+
+                    Some((address_index, address_deposits.count, expired))
+                }
+                None => None,
+            };
+
             dbtx.insert_entry(&NextOutputIndexKey, &(output.index + 1))
                 .await;
 
             dbtx.commit_tx_result().await?;
+
+            if let Some((address_index, deposits, expired)) = address_deposits
+                && deposits > 1
+            {
+                self.log_address_reuse(output, address_index, deposits, expired)
+                    .await;
+            }
         }
 
         debug!(
@@ -920,6 +1086,115 @@ async fn check_outputs(&self, handle: &TaskHandle) -> anyhow::Result<bool> {
         Ok(!outputs.is_empty())
     }
 
+    async fn log_address_reuse(
+        &self,
+        output: &OutputInfo,
+        address_index: u64,
+        deposits: u64,
+        expired: bool,
+    ) {
+        debug!(
+            target: LOG_CLIENT_MODULE_WALLETV2,
+            output_index = output.index,
+            address_index,
+            deposits,
+            expired,
+            "Discovered repeat deposit to walletv2 receive address"
+        );
+
+        let address = self.derive_address(address_index).as_unchecked().clone();
+
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+        if expired {
+            self.client_ctx
+                .log_event(
+                    &mut dbtx,
+                    ReceiveExpiredAddressEvent {
+                        address,
+                        outpoint: output.outpoint,
+                        value: output.value,
+                        deposits,
+                    },
+                )
+                .await;
+        } else {
+            self.client_ctx
+                .log_event(
+                    &mut dbtx,
+                    ReceiveAddressReuseEvent {
+                        address,
+                        outpoint: output.outpoint,
+                        value: output.value,
+                        deposits,
+                    },
+                )
+                .await;
+        }
+
+        dbtx.commit_tx().await;
+    }
+
+    /// Claims the deposits we have deferred as dust once their value exceeds
+    /// the dust threshold of the current receive fee. Returns false if we have
+    /// to wait for the pending transaction chain to clear.
+    async fn claim_deferred_outputs(&self) -> anyhow::Result<bool> {
+        let deferred_outputs = self
+            .db
+            .begin_transaction_nc()
+            .await
+            .find_by_prefix(&DeferredOutputPrefix)
+            .await
+            .collect::<Vec<(DeferredOutputKey, DeferredOutput)>>()
+            .await;
+
+        if deferred_outputs.is_empty() {
+            return Ok(true);
+        }
+
+        let Some(receive_fee) = self.module_api.receive_fee().await? else {
+            return Ok(true);
+        };
+
+        let dust_threshold = self.dust_threshold(receive_fee);
+
+        for (key, deferred_output) in deferred_outputs {
+            if deferred_output.value <= dust_threshold {
+                continue;
+            }
+
+            let Some(output) = self
+                .module_api
+                .output_info_slice(key.0, key.0 + 1)
+                .await?
+                .into_iter()
+                .next()
+            else {
+                continue;
+            };
+
+            // The deposit has been claimed by another client using our seed
+            if output.spent {
+                let mut dbtx = self.db.begin_transaction().await;
+
+                dbtx.remove_entry(&key).await;
+
+                dbtx.commit_tx_result().await?;
+
+                continue;
+            }
+
+            if !self
+                .process_unspent_output(&output, deferred_output.address_index)
+                .await?
+            {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
     async fn process_unspent_output(
         &self,
         output: &OutputInfo,
@@ -953,6 +1228,50 @@ async fn process_unspent_output(
             .await?
             .ok_or(anyhow!("No consensus feerate is available"))?;
 
+        let dust_threshold = self.dust_threshold(receive_fee);
+
+        if output.value <= dust_threshold {
+            debug!(
+                target: LOG_CLIENT_MODULE_WALLETV2,
+                output_index = output.index,
+                value_sat = output.value.to_sat(),
+                dust_threshold_sat = dust_threshold.to_sat(),
+                "Deferring walletv2 receive claim; value does not exceed the dust threshold"
+            );
+
+            let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+            // The threshold follows the receive fee, so we retry the claim once
+            // the fee has dropped and only log the event when we first defer it.
+            let deferred_output = DeferredOutput {
+                address_index,
+                value: output.value,
+                outpoint: output.outpoint,
+            };
+
+            if dbtx
+                .insert_entry(&DeferredOutputKey(output.index), &deferred_output)
+                .await
+                .is_none()
+            {
+                self.client_ctx
+                    .log_event(
+                        &mut dbtx,
+                        ReceiveDustEvent {
+                            address: self.derive_address(address_index).as_unchecked().clone(),
+                            outpoint: output.outpoint,
+                            value: output.value,
+                            dust_threshold,
+                        },
+                    )
+                    .await;
+            }
+
+            dbtx.commit_tx().await;
+
+            return Ok(true);
+        }
+
         if let Some((operation_id, txid)) = self
             .receive_output(
                 output.index,
@@ -993,6 +1312,12 @@ async fn process_unspent_output(
             );
         }
 
+        let mut dbtx = self.db.begin_transaction().await;
+
+        dbtx.remove_entry(&DeferredOutputKey(output.index)).await;
+
+        dbtx.commit_tx_result().await?;
+
         Ok(true)
     }
 }
@@ -3,6 +3,8 @@
 use fedimint_core::OutPoint;
 use fedimint_core::core::OperationId;
 use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::task::sleep;
+use fedimint_walletv2_common::sleep_duration;
 
 use crate::WalletClientContext;
 use crate::api::WalletFederationApi;
@@ -76,7 +78,6 @@ fn operation_id(&self) -> OperationId {
 enum AwaitFundingResult {
     Success(bitcoin::Txid),
     Aborted(String),
-    Failure,
 }
 
 impl SendStateMachine {
@@ -88,9 +89,14 @@ async fn await_funding(
             return AwaitFundingResult::Aborted(error);
         }
 
-        match global_context.module_api().tx_id(outpoint).await {
-            Some(txid) => AwaitFundingResult::Success(txid),
-            None => AwaitFundingResult::Failure,
+        // If the federation batches pegouts the transaction id is only assigned
+        // once the batch containing our pegout is closed.
+        loop {
+            if let Some(txid) = global_context.module_api().tx_id(outpoint).await {
+                return AwaitFundingResult::Success(txid);
+            }
+
+            sleep(sleep_duration()).await;
         }
     }
 
@@ -129,7 +135,6 @@ async fn transition_funding(
 
                 old_state.update(SendSMState::Aborted(error))
             }
-            AwaitFundingResult::Failure => old_state.update(SendSMState::Failure),
         }
     }
 }
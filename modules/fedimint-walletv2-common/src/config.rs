@@ -1,13 +1,16 @@
 use std::collections::BTreeMap;
+use std::io::{Read, Write};
 
-use bitcoin::Network;
 use bitcoin::hashes::{Hash, sha256};
+use bitcoin::{Network, Script, ScriptBuf};
 use fedimint_core::core::ModuleKind;
-use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::encoding::{Decodable, DecodeError, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::{Amount, PeerId, plugin_types_trait_impl_config, weight_to_vbytes};
 use secp256k1::{PublicKey, SecretKey};
 use serde::{Deserialize, Serialize};
 
+use crate::frost::{TaprootKey, group_public_key};
 use crate::{WalletCommonInit, descriptor};
 
 plugin_types_trait_impl_config!(
@@ -29,7 +32,7 @@ pub struct WalletConfigPrivate {
     pub bitcoin_sk: SecretKey,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, Encodable, Decodable)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WalletConfigConsensus {
     /// The public keys for the bitcoin multisig
     pub bitcoin_pks: BTreeMap<PeerId, PublicKey>,
@@ -48,6 +51,83 @@ pub struct WalletConfigConsensus {
     pub fee_consensus: FeeConsensus,
     /// Bitcoin network (e.g. testnet, bitcoin)
     pub network: Network,
+    /// Number of consensus blocks pegouts are collected for before they are
+    /// paid out in a single transaction, zero disables batching
+    #[serde(default)]
+    pub batch_window: u64,
+    /// Additional vbytes of a pegout batched into an existing transaction
+    #[serde(default = "default_batch_output_vbytes")]
+    pub batch_output_vbytes: u64,
+}
+
+/// Weight of a pegout output paying to a script of at most 34 bytes
+const DESTINATION_OUTPUT_WEIGHT: u64 = 8 * 4 // nValue
+    + 4 // scriptPubKey length
+    + 34 * 4; // scriptPubKey
+
+fn default_batch_output_vbytes() -> u64 {
+    weight_to_vbytes(DESTINATION_OUTPUT_WEIGHT)
+}
+
+/// Marks the encoding of the pegout batching fields, which configs generated
+/// before pegout batching do not contain
+const BATCHING_EXTENSION_V1: u8 = 1;
+
+// The consensus config is always decoded from a standalone byte vector, so
+// configs without the trailing batching fields can be told apart by reaching
+// the end of the input right after the network.
+impl Encodable for WalletConfigConsensus {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        self.bitcoin_pks.consensus_encode(writer)?;
+        self.descriptor.consensus_encode(writer)?;
+        self.send_tx_vbytes.consensus_encode(writer)?;
+        self.receive_tx_vbytes.consensus_encode(writer)?;
+        self.feerate_base.consensus_encode(writer)?;
+        self.dust_limit.consensus_encode(writer)?;
+        self.fee_consensus.consensus_encode(writer)?;
+        self.network.consensus_encode(writer)?;
+        BATCHING_EXTENSION_V1.consensus_encode(writer)?;
+        self.batch_window.consensus_encode(writer)?;
+        self.batch_output_vbytes.consensus_encode(writer)
+    }
+}
+
+impl Decodable for WalletConfigConsensus {
+    fn consensus_decode_partial_from_finite_reader<R: Read>(
+        r: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let mut config = Self {
+            bitcoin_pks: Decodable::consensus_decode_partial_from_finite_reader(r, modules)?,
+            descriptor: Decodable::consensus_decode_partial_from_finite_reader(r, modules)?,
+            send_tx_vbytes: Decodable::consensus_decode_partial_from_finite_reader(r, modules)?,
+            receive_tx_vbytes: Decodable::consensus_decode_partial_from_finite_reader(r, modules)?,
+            feerate_base: Decodable::consensus_decode_partial_from_finite_reader(r, modules)?,
+            dust_limit: Decodable::consensus_decode_partial_from_finite_reader(r, modules)?,
+            fee_consensus: Decodable::consensus_decode_partial_from_finite_reader(r, modules)?,
+            network: Decodable::consensus_decode_partial_from_finite_reader(r, modules)?,
+            batch_window: 0,
+            batch_output_vbytes: default_batch_output_vbytes(),
+        };
+
+        let mut extension = [0; 1];
+
+        if r.read(&mut extension).map_err(DecodeError::from_err)? == 0 {
+            return Ok(config);
+        }
+
+        if extension[0] != BATCHING_EXTENSION_V1 {
+            return Err(DecodeError::from_str(
+                "Unknown wallet config consensus extension",
+            ));
+        }
+
+        config.batch_window = Decodable::consensus_decode_partial_from_finite_reader(r, modules)?;
+        config.batch_output_vbytes =
+            Decodable::consensus_decode_partial_from_finite_reader(r, modules)?;
+
+        Ok(config)
+    }
 }
 
 impl WalletConfigConsensus {
@@ -74,8 +154,16 @@ impl WalletConfigConsensus {
     /// | 18        | 530  | 920     |
     /// | 19        | 539  | 937     |
     /// | 20        | 565  | 991     |
+    ///
+    /// For the `Tr` descriptor the witness is a single Schnorr signature
+    /// regardless of the number of guardians, such that a send transaction
+    /// has 154 and a receive transaction 169 vbytes.
+    ///
+    /// Every additional pegout in a batch adds 43 vbytes for its output.
     pub fn new(
         bitcoin_pks: BTreeMap<PeerId, PublicKey>,
+        descriptor: WalletDescriptor,
+        batch_window: u64,
         fee_consensus: FeeConsensus,
         network: Network,
     ) -> Self {
@@ -86,10 +174,7 @@ pub fn new(
             + 4 // up to 2 outputs
             + 4 * 4; // nLockTime
 
-        let change_witness_weight = descriptor(&bitcoin_pks, &sha256::Hash::all_zeros())
-            .max_weight_to_satisfy()
-            .expect("Cannot satisfy the change descriptor.")
-            .to_wu();
+        let change_witness_weight = descriptor.max_witness_weight(&bitcoin_pks);
 
         let change_input_weight = 32 * 4 // txid
             + 4 * 4 // vout
@@ -101,18 +186,14 @@ pub fn new(
             + 4 // scriptPubKey length
             + 34 * 4; // scriptPubKey
 
-        let destination_output_weight = 8 * 4 // nValue
-            + 4 // scriptPubKey length
-            + 34 * 4; // scriptPubKey
-
         Self {
             bitcoin_pks,
-            descriptor: WalletDescriptor::Wsh,
+            descriptor,
             send_tx_vbytes: weight_to_vbytes(
                 tx_overhead_weight
                     + change_input_weight
                     + change_output_weight
-                    + destination_output_weight,
+                    + DESTINATION_OUTPUT_WEIGHT,
             ),
             receive_tx_vbytes: weight_to_vbytes(
                 tx_overhead_weight
@@ -128,6 +209,8 @@ pub fn new(
             dust_limit: bitcoin::Amount::from_sat(10_000),
             fee_consensus,
             network,
+            batch_window,
+            batch_output_vbytes: default_batch_output_vbytes(),
         }
     }
 }
@@ -204,11 +287,136 @@ fn test_fee_consensus() {
     );
 }
 
-/// Which kind of bitcoin descriptor the federation uses. Currently only `Wsh`
-/// is defined, we can expand in the future.
+#[test]
+fn test_taproot_tx_vbytes() {
+    let pks = (0..4_u8)
+        .map(|i| {
+            let sk = SecretKey::from_slice(&[i + 1; 32]).expect("Valid secret key");
+
+            (
+                PeerId::from(u16::from(i)),
+                sk.public_key(secp256k1::SECP256K1),
+            )
+        })
+        .collect::<BTreeMap<PeerId, PublicKey>>();
+
+    let config = WalletConfigConsensus::new(
+        pks,
+        WalletDescriptor::Tr,
+        0,
+        FeeConsensus::new(0).expect("Relative fee is within range"),
+        Network::Regtest,
+    );
+
+    assert_eq!(config.send_tx_vbytes, 154);
+    assert_eq!(config.receive_tx_vbytes, 169);
+    assert_eq!(config.batch_output_vbytes, 43);
+}
+
+#[test]
+fn test_config_without_batching_decodes() {
+    /// Layout of the consensus config before pegout batching was introduced
+    #[derive(Encodable)]
+    struct LegacyWalletConfigConsensus {
+        bitcoin_pks: BTreeMap<PeerId, PublicKey>,
+        descriptor: WalletDescriptor,
+        send_tx_vbytes: u64,
+        receive_tx_vbytes: u64,
+        feerate_base: u64,
+        dust_limit: bitcoin::Amount,
+        fee_consensus: FeeConsensus,
+        network: Network,
+    }
+
+    let sk = SecretKey::from_slice(&[1; 32]).expect("Valid secret key");
+
+    let config = WalletConfigConsensus::new(
+        BTreeMap::from([(PeerId::from(0), sk.public_key(secp256k1::SECP256K1))]),
+        WalletDescriptor::Wsh,
+        6,
+        FeeConsensus::new(0).expect("Relative fee is within range"),
+        Network::Regtest,
+    );
+
+    let legacy = LegacyWalletConfigConsensus {
+        bitcoin_pks: config.bitcoin_pks.clone(),
+        descriptor: config.descriptor.clone(),
+        send_tx_vbytes: config.send_tx_vbytes,
+        receive_tx_vbytes: config.receive_tx_vbytes,
+        feerate_base: config.feerate_base,
+        dust_limit: config.dust_limit,
+        fee_consensus: config.fee_consensus.clone(),
+        network: config.network,
+    };
+
+    let decoded = WalletConfigConsensus::consensus_decode_whole(
+        &legacy.consensus_encode_to_vec(),
+        &ModuleDecoderRegistry::default(),
+    )
+    .expect("Legacy config decodes");
+
+    assert_eq!(decoded.send_tx_vbytes, config.send_tx_vbytes);
+    assert_eq!(decoded.network, config.network);
+    assert_eq!(decoded.batch_window, 0);
+    assert_eq!(decoded.batch_output_vbytes, config.batch_output_vbytes);
+
+    let decoded = WalletConfigConsensus::consensus_decode_whole(
+        &config.consensus_encode_to_vec(),
+        &ModuleDecoderRegistry::default(),
+    )
+    .expect("Config decodes");
+
+    assert_eq!(decoded.batch_window, 6);
+}
+
+/// Which kind of bitcoin descriptor the federation uses. The `Wsh` descriptor
+/// is a sorted multisig of the guardians keys while the `Tr` descriptor is a
+/// key path spend of a group key the guardians hold threshold shares of, see
+/// [`crate::frost`].
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
 pub enum WalletDescriptor {
     Wsh,
+    Tr,
+}
+
+impl WalletDescriptor {
+    /// The script pubkey of the federation wallet for the tweak
+    pub fn script_pubkey(
+        &self,
+        pks: &BTreeMap<PeerId, PublicKey>,
+        tweak: &sha256::Hash,
+    ) -> ScriptBuf {
+        match self {
+            Self::Wsh => descriptor(pks, tweak).script_pubkey(),
+            Self::Tr => ScriptBuf::new_p2tr_tweaked(
+                TaprootKey::new(&group_public_key(pks), tweak).output_key,
+            ),
+        }
+    }
+
+    /// Returns true if the script has the type of the federation wallet's
+    /// scripts
+    pub fn is_script_type(&self, script: &Script) -> bool {
+        match self {
+            Self::Wsh => script.is_p2wsh(),
+            Self::Tr => script.is_p2tr(),
+        }
+    }
+
+    /// Returns the maximum weight of the witness spending a wallet output
+    pub fn max_witness_weight(&self, pks: &BTreeMap<PeerId, PublicKey>) -> u64 {
+        match self {
+            Self::Wsh => descriptor(pks, &sha256::Hash::all_zeros())
+                .max_weight_to_satisfy()
+                .expect("Cannot satisfy the change descriptor.")
+                .to_wu(),
+            Self::Tr => {
+                1 // Number of witness elements
+                    + 1 // Signature length
+                    + 64 // Schnorr signature with the default sighash type
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
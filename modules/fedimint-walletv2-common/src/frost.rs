@@ -0,0 +1,666 @@
+//! Threshold Schnorr signatures for the taproot descriptor of the federation
+//! wallet.
+//!
+//! The guardians hold Shamir shares of a single secp256k1 key, which are
+//! generated by a Pedersen style distributed key generation during config
+//! setup. The `bitcoin_sk` of a guardian is its secret share and the
+//! `bitcoin_pks` of the federation are the corresponding public verification
+//! shares, such that the group key is their Lagrange interpolation at zero.
+//!
+//! Transactions are signed with the two round FROST protocol as specified in
+//! RFC 9591, adapted to produce BIP340 signatures for a taproot key path spend.
+//! The first round is the exchange of nonce commitments, the second round the
+//! exchange of signature shares, both via consensus.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::key::{TapTweak, TweakedPublicKey};
+use bitcoin::taproot::TapTweakHash;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{NumPeersExt, PeerId};
+use secp256k1::constants::CURVE_ORDER;
+use secp256k1::{Parity, PublicKey, SECP256K1, Scalar, SecretKey, XOnlyPublicKey, schnorr};
+use serde::{Deserialize, Serialize};
+
+use crate::tweak_public_key;
+
+/// The commitments to the two nonces a guardian uses to sign a single input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct NonceCommitment {
+    pub hiding: PublicKey,
+    pub binding: PublicKey,
+}
+
+/// The share of a guardian of the signature for a single input. This is a
+/// scalar which is zero with negligible probability, we therefore do not use
+/// [`SecretKey`] to encode it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct SignatureShare(pub [u8; 32]);
+
+/// A Schnorr proof of knowledge of the discrete logarithm of a guardians
+/// constant polynomial commitment, which prevents rogue key attacks during
+/// the distributed key generation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct ProofOfKnowledge {
+    pub nonce: PublicKey,
+    pub response: SecretKey,
+}
+
+/// The nonces of a guardian for a single input. The nonces are derived
+/// deterministically from the secret share, the transaction, the signing
+/// attempt and the input, which is safe since the consensus fixes the signing
+/// set and therefore the binding factors of an attempt before any guardian
+/// creates its signature share. If the federation selects a new signing set
+/// the attempt is incremented, hence a guardian never signs with the same
+/// nonces twice.
+pub struct SigningNonces {
+    hiding: SecretKey,
+    binding: SecretKey,
+}
+
+impl SigningNonces {
+    pub fn new(sk: &SecretKey, txid: &bitcoin::Txid, attempt: u64, input: u64) -> Self {
+        Self {
+            hiding: hash_to_scalar(
+                "fedimint-walletv2-frost-hiding-nonce",
+                &(sk, txid, attempt, input),
+            ),
+            binding: hash_to_scalar(
+                "fedimint-walletv2-frost-binding-nonce",
+                &(sk, txid, attempt, input),
+            ),
+        }
+    }
+
+    pub fn commitment(&self) -> NonceCommitment {
+        NonceCommitment {
+            hiding: self.hiding.public_key(SECP256K1),
+            binding: self.binding.public_key(SECP256K1),
+        }
+    }
+}
+
+/// The taproot output key of the federation wallet for a tweak. We tweak the
+/// group key with the same tweak as the keys of the `Wsh` descriptor to obtain
+/// the internal key, which is then tweaked according to BIP341 without a
+/// script tree to obtain the output key.
+#[derive(Clone, Copy, Debug)]
+pub struct TaprootKey {
+    tweak: Scalar,
+    internal_key_parity: Parity,
+    tap_tweak: Scalar,
+    output_key_parity: Parity,
+    pub output_key: TweakedPublicKey,
+}
+
+impl TaprootKey {
+    pub fn new(group_public_key: &PublicKey, tweak: &sha256::Hash) -> Self {
+        let (internal_key, internal_key_parity) =
+            tweak_public_key(group_public_key, tweak).x_only_public_key();
+
+        let (output_key, output_key_parity) = internal_key.tap_tweak(SECP256K1, None);
+
+        Self {
+            tweak: Scalar::from_be_bytes(tweak.to_byte_array())
+                .expect("Hash is within field order"),
+            internal_key_parity,
+            tap_tweak: TapTweakHash::from_key_and_tweak(internal_key, None).to_scalar(),
+            output_key_parity,
+            output_key,
+        }
+    }
+
+    /// Maps the secret share of a guardian to its share of the secret key of
+    /// the output key. Since the Lagrange coefficients of a signing set sum to
+    /// one, the tweaks and negations commute with the interpolation.
+    pub fn tweak_secret_share(&self, sk: &SecretKey) -> SecretKey {
+        let sk = sk
+            .add_tweak(&self.tweak)
+            .expect("Failed to tweak bitcoin secret key");
+
+        let sk = match self.internal_key_parity {
+            Parity::Even => sk,
+            Parity::Odd => sk.negate(),
+        };
+
+        let sk = sk
+            .add_tweak(&self.tap_tweak)
+            .expect("Failed to tweak bitcoin secret key");
+
+        match self.output_key_parity {
+            Parity::Even => sk,
+            Parity::Odd => sk.negate(),
+        }
+    }
+
+    /// Maps the public verification share of a guardian to its share of the
+    /// output key, see [`Self::tweak_secret_share`].
+    pub fn tweak_public_share(&self, pk: &PublicKey) -> PublicKey {
+        let pk = pk
+            .add_exp_tweak(SECP256K1, &self.tweak)
+            .expect("Failed to tweak bitcoin public key");
+
+        let pk = match self.internal_key_parity {
+            Parity::Even => pk,
+            Parity::Odd => pk.negate(SECP256K1),
+        };
+
+        let pk = pk
+            .add_exp_tweak(SECP256K1, &self.tap_tweak)
+            .expect("Failed to tweak bitcoin public key");
+
+        match self.output_key_parity {
+            Parity::Even => pk,
+            Parity::Odd => pk.negate(SECP256K1),
+        }
+    }
+
+    /// Creates the signature share of a guardian for the message, given the
+    /// nonce commitments of the signing set.
+    pub fn sign_share(
+        &self,
+        sk: &SecretKey,
+        nonces: &SigningNonces,
+        peer: PeerId,
+        commitments: &BTreeMap<PeerId, NonceCommitment>,
+        message: &[u8; 32],
+    ) -> SignatureShare {
+        let (nonce, nonce_parity) = self.group_commitment(commitments, message);
+
+        let nonce_share = add(
+            &nonces.hiding,
+            &mul(
+                &nonces.binding,
+                &binding_factor(peer, commitments, &self.output_key, message),
+            ),
+        );
+
+        let nonce_share = match nonce_parity {
+            Parity::Even => nonce_share,
+            Parity::Odd => nonce_share.negate(),
+        };
+
+        let response = mul(
+            &mul(
+                &self.challenge(&nonce, message),
+                &lagrange_coefficient(peer, &commitments.keys().copied().collect()),
+            ),
+            &self.tweak_secret_share(sk),
+        );
+
+        SignatureShare(add(&nonce_share, &response).secret_bytes())
+    }
+
+    /// Verifies the signature share of a guardian against its public
+    /// verification share.
+    pub fn verify_share(
+        &self,
+        share: &SignatureShare,
+        pk: &PublicKey,
+        peer: PeerId,
+        commitments: &BTreeMap<PeerId, NonceCommitment>,
+        message: &[u8; 32],
+    ) -> bool {
+        let Ok(share) = SecretKey::from_slice(&share.0) else {
+            return false;
+        };
+
+        let Some(commitment) = commitments.get(&peer) else {
+            return false;
+        };
+
+        let (nonce, nonce_parity) = self.group_commitment(commitments, message);
+
+        let nonce_share = commitment
+            .binding
+            .mul_tweak(
+                SECP256K1,
+                &scalar(&binding_factor(
+                    peer,
+                    commitments,
+                    &self.output_key,
+                    message,
+                )),
+            )
+            .and_then(|binding| binding.combine(&commitment.hiding))
+            .expect("Failed to compute nonce share");
+
+        let nonce_share = match nonce_parity {
+            Parity::Even => nonce_share,
+            Parity::Odd => nonce_share.negate(SECP256K1),
+        };
+
+        let challenge = mul(
+            &self.challenge(&nonce, message),
+            &lagrange_coefficient(peer, &commitments.keys().copied().collect()),
+        );
+
+        self.tweak_public_share(pk)
+            .mul_tweak(SECP256K1, &scalar(&challenge))
+            .and_then(|response| response.combine(&nonce_share))
+            .is_ok_and(|expected| expected == share.public_key(SECP256K1))
+    }
+
+    /// Aggregates the verified signature shares of the signing set into a
+    /// BIP340 signature for the output key.
+    pub fn aggregate(
+        &self,
+        commitments: &BTreeMap<PeerId, NonceCommitment>,
+        shares: &BTreeMap<PeerId, SignatureShare>,
+        message: &[u8; 32],
+    ) -> schnorr::Signature {
+        let (nonce, ..) = self.group_commitment(commitments, message);
+
+        let response = shares
+            .values()
+            .map(|share| SecretKey::from_slice(&share.0).expect("Share has been verified"))
+            .reduce(|a, b| add(&a, &b))
+            .expect("We have at least one signature share");
+
+        let mut signature = [0; 64];
+
+        signature[..32].copy_from_slice(&nonce.serialize());
+        signature[32..].copy_from_slice(&response.secret_bytes());
+
+        schnorr::Signature::from_slice(&signature).expect("Signature has 64 bytes")
+    }
+
+    fn group_commitment(
+        &self,
+        commitments: &BTreeMap<PeerId, NonceCommitment>,
+        message: &[u8; 32],
+    ) -> (XOnlyPublicKey, Parity) {
+        let nonce_shares = commitments
+            .iter()
+            .map(|(peer, commitment)| {
+                commitment
+                    .binding
+                    .mul_tweak(
+                        SECP256K1,
+                        &scalar(&binding_factor(
+                            *peer,
+                            commitments,
+                            &self.output_key,
+                            message,
+                        )),
+                    )
+                    .and_then(|binding| binding.combine(&commitment.hiding))
+                    .expect("Failed to compute nonce share")
+            })
+            .collect::<Vec<PublicKey>>();
+
+        PublicKey::combine_keys(&nonce_shares.iter().collect::<Vec<&PublicKey>>())
+            .expect("Failed to compute group commitment")
+            .x_only_public_key()
+    }
+
+    fn challenge(&self, nonce: &XOnlyPublicKey, message: &[u8; 32]) -> SecretKey {
+        let tag = sha256::Hash::hash(b"BIP0340/challenge");
+
+        let mut data = Vec::with_capacity(160);
+
+        data.extend_from_slice(tag.as_byte_array());
+        data.extend_from_slice(tag.as_byte_array());
+        data.extend_from_slice(&nonce.serialize());
+        data.extend_from_slice(&self.output_key.to_inner().serialize());
+        data.extend_from_slice(message);
+
+        reduce(sha256::Hash::hash(&data).to_byte_array())
+    }
+}
+
+/// Returns the group key of the federation, which is the interpolation of the
+/// public verification shares of any threshold of guardians.
+pub fn group_public_key(pks: &BTreeMap<PeerId, PublicKey>) -> PublicKey {
+    interpolate(
+        pks,
+        &pks.keys()
+            .take(pks.to_num_peers().threshold())
+            .copied()
+            .collect(),
+    )
+}
+
+/// Returns true if the public verification shares of all guardians lie on a
+/// polynomial of degree threshold minus one, such that any threshold of
+/// guardians interpolates to the same group key.
+pub fn verify_public_shares(pks: &BTreeMap<PeerId, PublicKey>) -> bool {
+    let threshold = pks.to_num_peers().threshold();
+
+    let group_public_key = group_public_key(pks);
+
+    pks.keys().skip(threshold).all(|peer| {
+        let signers = pks
+            .keys()
+            .take(threshold - 1)
+            .chain(std::iter::once(peer))
+            .copied()
+            .collect();
+
+        interpolate(pks, &signers) == group_public_key
+    })
+}
+
+/// Returns the secret key of the federation, which is the interpolation of the
+/// secret shares of any threshold of guardians. This is only used to recover
+/// the funds of a decommissioned federation.
+pub fn interpolate_secret_shares(sks: &BTreeMap<PeerId, SecretKey>) -> SecretKey {
+    let signers = sks.keys().copied().collect();
+
+    sks.iter()
+        .map(|(peer, sk)| mul(sk, &lagrange_coefficient(*peer, &signers)))
+        .reduce(|a, b| add(&a, &b))
+        .expect("We have at least one secret share")
+}
+
+/// Evaluates the secret polynomial of the distributed key generation at the
+/// index of a guardian.
+pub fn evaluate_polynomial(coefficients: &[SecretKey], peer: PeerId) -> SecretKey {
+    let x = peer_scalar(peer);
+
+    coefficients
+        .iter()
+        .copied()
+        .rev()
+        .reduce(|acc, coefficient| add(&mul(&acc, &x), &coefficient))
+        .expect("We have at least one coefficient")
+}
+
+/// Evaluates the commitment to a polynomial of the distributed key generation
+/// at the index of a guardian.
+pub fn evaluate_commitment(commitments: &[PublicKey], peer: PeerId) -> PublicKey {
+    let x = scalar(&peer_scalar(peer));
+
+    commitments
+        .iter()
+        .copied()
+        .rev()
+        .reduce(|acc, commitment| {
+            acc.mul_tweak(SECP256K1, &x)
+                .and_then(|acc| acc.combine(&commitment))
+                .expect("Failed to evaluate polynomial commitment")
+        })
+        .expect("We have at least one commitment")
+}
+
+/// Proves knowledge of the secret of a commitment. The context binds the proof
+/// to the guardian that created it.
+pub fn prove_knowledge(secret: &SecretKey, context: &PublicKey) -> ProofOfKnowledge {
+    let nonce = hash_to_scalar("fedimint-walletv2-frost-pok-nonce", &(secret, context));
+
+    let challenge = hash_to_scalar(
+        "fedimint-walletv2-frost-pok",
+        &(
+            nonce.public_key(SECP256K1),
+            secret.public_key(SECP256K1),
+            context,
+        ),
+    );
+
+    ProofOfKnowledge {
+        nonce: nonce.public_key(SECP256K1),
+        response: add(&nonce, &mul(&challenge, secret)),
+    }
+}
+
+pub fn verify_knowledge(
+    proof: &ProofOfKnowledge,
+    commitment: &PublicKey,
+    context: &PublicKey,
+) -> bool {
+    let challenge = hash_to_scalar(
+        "fedimint-walletv2-frost-pok",
+        &(proof.nonce, commitment, context),
+    );
+
+    commitment
+        .mul_tweak(SECP256K1, &scalar(&challenge))
+        .and_then(|response| response.combine(&proof.nonce))
+        .is_ok_and(|expected| expected == proof.response.public_key(SECP256K1))
+}
+
+fn interpolate(pks: &BTreeMap<PeerId, PublicKey>, signers: &BTreeSet<PeerId>) -> PublicKey {
+    let pks = signers
+        .iter()
+        .map(|peer| {
+            pks.get(peer)
+                .expect("Signer is a guardian")
+                .mul_tweak(SECP256K1, &scalar(&lagrange_coefficient(*peer, signers)))
+                .expect("Failed to interpolate public key")
+        })
+        .collect::<Vec<PublicKey>>();
+
+    PublicKey::combine_keys(&pks.iter().collect::<Vec<&PublicKey>>())
+        .expect("Failed to interpolate public key")
+}
+
+/// Returns the Lagrange coefficient of a guardian for the interpolation at zero
+/// over the signing set.
+fn lagrange_coefficient(peer: PeerId, signers: &BTreeSet<PeerId>) -> SecretKey {
+    let x = peer_scalar(peer);
+
+    signers
+        .iter()
+        .filter(|signer| **signer != peer)
+        .map(|signer| peer_scalar(*signer))
+        .fold(u64_scalar(1), |acc, x_signer| {
+            let denominator = add(&x_signer, &x.negate());
+
+            mul(&acc, &mul(&x_signer, &invert(&denominator)))
+        })
+}
+
+/// The polynomial of the key generation is evaluated at the peer id plus one,
+/// since its evaluation at zero is the secret key.
+fn peer_scalar(peer: PeerId) -> SecretKey {
+    u64_scalar(u64::from(u16::from(peer)) + 1)
+}
+
+fn u64_scalar(value: u64) -> SecretKey {
+    let mut bytes = [0; 32];
+
+    bytes[24..].copy_from_slice(&value.to_be_bytes());
+
+    SecretKey::from_slice(&bytes).expect("Value is non-zero")
+}
+
+fn scalar(sk: &SecretKey) -> Scalar {
+    Scalar::from_be_bytes(sk.secret_bytes()).expect("Secret key is within field order")
+}
+
+fn add(a: &SecretKey, b: &SecretKey) -> SecretKey {
+    a.add_tweak(&scalar(b)).expect("Sum of scalars is zero")
+}
+
+fn mul(a: &SecretKey, b: &SecretKey) -> SecretKey {
+    a.mul_tweak(&scalar(b))
+        .expect("Product of non-zero scalars is non-zero")
+}
+
+/// Inverts a scalar via Fermat's little theorem by raising it to the power of
+/// the curve order minus two.
+fn invert(a: &SecretKey) -> SecretKey {
+    let mut exponent = CURVE_ORDER;
+
+    // The curve order ends with 0x41, hence there is no borrow
+    exponent[31] -= 2;
+
+    let mut result: Option<SecretKey> = None;
+
+    for byte in exponent {
+        for bit in (0..8).rev() {
+            result = result.map(|r| mul(&r, &r));
+
+            if (byte >> bit) & 1 == 1 {
+                result = Some(result.map_or(*a, |r| mul(&r, a)));
+            }
+        }
+    }
+
+    result.expect("Exponent is non-zero")
+}
+
+fn binding_factor(
+    peer: PeerId,
+    commitments: &BTreeMap<PeerId, NonceCommitment>,
+    output_key: &TweakedPublicKey,
+    message: &[u8; 32],
+) -> SecretKey {
+    hash_to_scalar(
+        "fedimint-walletv2-frost-binding-factor",
+        &(peer, commitments, output_key.to_inner(), message),
+    )
+}
+
+fn hash_to_scalar<T: Encodable>(tag: &str, data: &T) -> SecretKey {
+    reduce((tag, data).consensus_hash::<sha256::Hash>().to_byte_array())
+}
+
+/// Reduces a 256 bit integer modulo the curve order. Since the curve order is
+/// larger than half of the integer range, subtracting it once is sufficient.
+fn reduce(bytes: [u8; 32]) -> SecretKey {
+    if let Ok(sk) = SecretKey::from_slice(&bytes) {
+        return sk;
+    }
+
+    let mut reduced = [0; 32];
+    let mut borrow = false;
+
+    for i in (0..32).rev() {
+        let (difference, borrow_order) = bytes[i].overflowing_sub(CURVE_ORDER[i]);
+        let (difference, borrow_carry) = difference.overflowing_sub(u8::from(borrow));
+
+        reduced[i] = difference;
+        borrow = borrow_order || borrow_carry;
+    }
+
+    SecretKey::from_slice(&reduced).expect("Hash is not a multiple of the curve order")
+}
+
+#[test]
+fn test_threshold_signature() {
+    let coefficients = (0..3_u64)
+        .map(|i| hash_to_scalar("test-coefficient", &i))
+        .collect::<Vec<SecretKey>>();
+
+    let sks = (0..4)
+        .map(PeerId::from)
+        .map(|peer| (peer, evaluate_polynomial(&coefficients, peer)))
+        .collect::<BTreeMap<PeerId, SecretKey>>();
+
+    let pks = sks
+        .iter()
+        .map(|(peer, sk)| (*peer, sk.public_key(SECP256K1)))
+        .collect::<BTreeMap<PeerId, PublicKey>>();
+
+    assert!(verify_public_shares(&pks));
+
+    assert_eq!(
+        group_public_key(&pks),
+        coefficients[0].public_key(SECP256K1)
+    );
+
+    let commitments_pk = coefficients
+        .iter()
+        .map(|c| c.public_key(SECP256K1))
+        .collect::<Vec<PublicKey>>();
+
+    for (peer, pk) in &pks {
+        assert_eq!(evaluate_commitment(&commitments_pk, *peer), *pk);
+    }
+
+    let tweak = sha256::Hash::hash(b"tweak");
+    let txid = bitcoin::Txid::all_zeros();
+    let message = [42; 32];
+
+    let key = TaprootKey::new(&group_public_key(&pks), &tweak);
+
+    // Any threshold of guardians can sign, we skip the first one
+    let signers = sks
+        .iter()
+        .skip(1)
+        .collect::<BTreeMap<&PeerId, &SecretKey>>();
+
+    let commitments = signers
+        .iter()
+        .map(|(peer, sk)| (**peer, SigningNonces::new(sk, &txid, 0, 0).commitment()))
+        .collect::<BTreeMap<PeerId, NonceCommitment>>();
+
+    let shares = signers
+        .iter()
+        .map(|(peer, sk)| {
+            let nonces = SigningNonces::new(sk, &txid, 0, 0);
+
+            // A new signing attempt uses fresh nonces
+            assert_ne!(
+                nonces.commitment(),
+                SigningNonces::new(sk, &txid, 1, 0).commitment()
+            );
+
+            let share = key.sign_share(sk, &nonces, **peer, &commitments, &message);
+
+            assert!(key.verify_share(&share, &pks[*peer], **peer, &commitments, &message));
+
+            assert!(!key.verify_share(
+                &share,
+                &pks[&PeerId::from(0)],
+                **peer,
+                &commitments,
+                &message
+            ));
+
+            (**peer, share)
+        })
+        .collect::<BTreeMap<PeerId, SignatureShare>>();
+
+    let signature = key.aggregate(&commitments, &shares, &message);
+
+    SECP256K1
+        .verify_schnorr(
+            &signature,
+            &secp256k1::Message::from_digest(message),
+            &key.output_key.to_inner(),
+        )
+        .expect("Aggregated signature is valid");
+
+    let group_sk =
+        interpolate_secret_shares(&signers.into_iter().map(|(p, sk)| (*p, *sk)).collect());
+
+    assert_eq!(group_sk, coefficients[0]);
+
+    assert_eq!(
+        key.tweak_secret_share(&group_sk)
+            .x_only_public_key(SECP256K1)
+            .0,
+        key.output_key.to_inner()
+    );
+}
+
+#[test]
+fn test_proof_of_knowledge() {
+    let secret = hash_to_scalar("test-secret", &0_u64);
+    let context = hash_to_scalar("test-context", &0_u64).public_key(SECP256K1);
+    let other = hash_to_scalar("test-context", &1_u64).public_key(SECP256K1);
+
+    let proof = prove_knowledge(&secret, &context);
+
+    assert!(verify_knowledge(
+        &proof,
+        &secret.public_key(SECP256K1),
+        &context
+    ));
+
+    assert!(!verify_knowledge(
+        &proof,
+        &secret.public_key(SECP256K1),
+        &other
+    ));
+}
+
+#[test]
+fn test_invert() {
+    let a = hash_to_scalar("test-invert", &0_u64);
+
+    assert_eq!(mul(&a, &invert(&a)), u64_scalar(1));
+}
@@ -18,6 +18,7 @@
 use fedimint_core::{
     NumPeersExt, PeerId, extensible_associated_module_type, plugin_types_trait_impl_common,
 };
+use frost::{NonceCommitment, SignatureShare};
 use miniscript::descriptor::Wsh;
 use secp256k1::ecdsa::Signature;
 use secp256k1::{PublicKey, Scalar, XOnlyPublicKey};
@@ -26,10 +27,31 @@
 
 pub mod config;
 pub mod endpoint_constants;
+pub mod frost;
 
 pub const KIND: ModuleKind = ModuleKind::from_static_str("walletv2");
 
-pub const MODULE_CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion::new(1, 0);
+pub const MODULE_CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion::new(1, 1);
+
+/// From this module consensus version on, the federation wallet may use the
+/// taproot descriptor, which is signed with the nonce commitment and signature
+/// share consensus items over expiring signing attempts.
+///
+/// The module consensus version is fixed in the config at generation, so
+/// federations set up before this version keep the P2WSH descriptor.
+pub const TAPROOT_MODULE_CONSENSUS_VERSION: ModuleConsensusVersion =
+    ModuleConsensusVersion::new(1, 1);
+
+/// From this module consensus version on, the fees of walletv2 inputs and
+/// outputs accrue to a fee budget the federation spends to accelerate a stuck
+/// pending transaction chain.
+pub const FEE_BUMP_MODULE_CONSENSUS_VERSION: ModuleConsensusVersion =
+    ModuleConsensusVersion::new(1, 1);
+
+/// From this module consensus version on, pegouts are collected for the batch
+/// window of the config and paid out by a single transaction.
+pub const PEGOUT_BATCHING_MODULE_CONSENSUS_VERSION: ModuleConsensusVersion =
+    ModuleConsensusVersion::new(1, 1);
 
 /// Returns a sleep duration of 1 second in test environments or 60 seconds in
 /// production. Used for polling intervals where faster feedback is needed
@@ -61,7 +83,7 @@ pub fn tweak_public_key(pk: &PublicKey, tweak: &sha256::Hash) -> PublicKey {
 }
 
 /// Returns true if the script pubkey potentially belongs to the federation.
-/// This uses a probabilistic filter - only ~1/65536 of P2WSH scripts pass.
+/// This uses a probabilistic filter - only ~1/65536 of scripts pass.
 pub fn is_potential_receive(script_pubkey: &ScriptBuf, pks_hash: &sha256::Hash) -> bool {
     (script_pubkey, pks_hash)
         .consensus_hash::<sha256::Hash>()
@@ -102,6 +124,10 @@ pub struct OutputInfo {
     pub value: bitcoin::Amount,
     pub spent: bool,
     pub outpoint: Option<bitcoin::OutPoint>,
+    /// Timestamp of the block which created the output, which is unknown for
+    /// outputs recorded before the guardians started to track it.
+    #[serde(default)]
+    pub block_time: Option<u32>,
 }
 
 #[derive(Debug)]
@@ -137,6 +163,8 @@ pub enum WalletConsensusItem {
     BlockCount(u64),
     Feerate(Option<u64>),
     Signatures(Txid, Vec<Signature>),
+    NonceCommitments(Txid, u64, Vec<NonceCommitment>),
+    SignatureShares(Txid, Vec<SignatureShare>),
     #[encodable_default]
     Default {
         variant: u64,
@@ -156,6 +184,12 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             WalletConsensusItem::Signatures(..) => {
                 write!(f, "Wallet Signatures")
             }
+            WalletConsensusItem::NonceCommitments(..) => {
+                write!(f, "Wallet Nonce Commitments")
+            }
+            WalletConsensusItem::SignatureShares(..) => {
+                write!(f, "Wallet Signature Shares")
+            }
             WalletConsensusItem::Default { variant, .. } => {
                 write!(f, "Unknown Wallet CI variant={variant}")
             }
@@ -2,11 +2,12 @@
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::{PeerId, impl_db_lookup, impl_db_record};
 use fedimint_walletv2_common::TxInfo;
+use fedimint_walletv2_common::frost::{NonceCommitment, SignatureShare};
 use secp256k1::ecdsa::Signature;
 use serde::Serialize;
 use strum_macros::EnumIter;
 
-use crate::{FederationTx, FederationWallet};
+use crate::{FederationTx, FederationWallet, PendingPegOut, SigningAttempt};
 
 #[repr(u8)]
 #[derive(Clone, EnumIter, Debug)]
@@ -21,6 +22,12 @@ pub enum DbKeyPrefix {
     Signatures = 0x37,
     UnconfirmedTx = 0x38,
     FederationWallet = 0x39,
+    NonceCommitments = 0x3a,
+    SignatureShares = 0x3b,
+    FeeBudget = 0x3c,
+    PendingPegOut = 0x3d,
+    SigningAttempt = 0x3e,
+    OutputBlockTime = 0x3f,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -179,3 +186,120 @@ fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
 );
 
 impl_db_lookup!(key = FeeRateVoteKey, query_prefix = FeeRateVotePrefix);
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct NonceCommitmentsKey(pub Txid, pub PeerId);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct NonceCommitmentsTxidPrefix(pub Txid);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct NonceCommitmentsPrefix;
+
+impl_db_record!(
+    key = NonceCommitmentsKey,
+    value = Vec<NonceCommitment>,
+    db_prefix = DbKeyPrefix::NonceCommitments,
+);
+
+impl_db_lookup!(
+    key = NonceCommitmentsKey,
+    query_prefix = NonceCommitmentsTxidPrefix
+);
+
+impl_db_lookup!(
+    key = NonceCommitmentsKey,
+    query_prefix = NonceCommitmentsPrefix
+);
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct SignatureSharesKey(pub Txid, pub PeerId);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct SignatureSharesTxidPrefix(pub Txid);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct SignatureSharesPrefix;
+
+impl_db_record!(
+    key = SignatureSharesKey,
+    value = Vec<SignatureShare>,
+    db_prefix = DbKeyPrefix::SignatureShares,
+);
+
+impl_db_lookup!(
+    key = SignatureSharesKey,
+    query_prefix = SignatureSharesTxidPrefix
+);
+
+impl_db_lookup!(
+    key = SignatureSharesKey,
+    query_prefix = SignatureSharesPrefix
+);
+
+/// The fees collected for walletv2 inputs and outputs which have not been spent
+/// on accelerating the pending transaction chain yet.
+#[derive(Clone, Debug, Eq, PartialEq, Encodable, Decodable, Serialize)]
+pub struct FeeBudgetKey;
+
+#[derive(Clone, Debug, Eq, PartialEq, Encodable, Decodable, Serialize)]
+pub struct FeeBudgetPrefix;
+
+impl_db_record!(
+    key = FeeBudgetKey,
+    value = fedimint_core::Amount,
+    db_prefix = DbKeyPrefix::FeeBudget,
+);
+
+impl_db_lookup!(key = FeeBudgetKey, query_prefix = FeeBudgetPrefix);
+
+/// Pegouts which have been accepted by the federation but not yet been paid out
+/// by a transaction in the chain, keyed by the outpoint of their wallet output.
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct PendingPegOutKey(pub fedimint_core::OutPoint);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct PendingPegOutPrefix;
+
+impl_db_record!(
+    key = PendingPegOutKey,
+    value = PendingPegOut,
+    db_prefix = DbKeyPrefix::PendingPegOut,
+);
+
+impl_db_lookup!(key = PendingPegOutKey, query_prefix = PendingPegOutPrefix);
+
+/// The current signing attempt of an unsigned transaction of a taproot
+/// federation, which is replaced once its signing set fails to sign in time.
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct SigningAttemptKey(pub Txid);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct SigningAttemptPrefix;
+
+impl_db_record!(
+    key = SigningAttemptKey,
+    value = SigningAttempt,
+    db_prefix = DbKeyPrefix::SigningAttempt,
+);
+
+impl_db_lookup!(key = SigningAttemptKey, query_prefix = SigningAttemptPrefix);
+
+/// The timestamp of the block which created the output of an index, which
+/// clients use as the time of the deposit.
+#[derive(Clone, Debug, Eq, PartialEq, Encodable, Decodable, Serialize)]
+pub struct OutputBlockTimeKey(pub u64);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct OutputBlockTimePrefix;
+
+impl_db_record!(
+    key = OutputBlockTimeKey,
+    value = u32,
+    db_prefix = DbKeyPrefix::OutputBlockTime,
+);
+
+impl_db_lookup!(
+    key = OutputBlockTimeKey,
+    query_prefix = OutputBlockTimePrefix
+);
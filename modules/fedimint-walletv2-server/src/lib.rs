@@ -18,18 +18,21 @@
 use bitcoin::absolute::LockTime;
 use bitcoin::hashes::{Hash, sha256};
 use bitcoin::secp256k1::Secp256k1;
-use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
 use bitcoin::transaction::Version;
-use bitcoin::{Amount, Network, Sequence, Transaction, TxIn, TxOut, Txid};
+use bitcoin::{Amount, Network, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
 use common::config::WalletConfigConsensus;
 use common::{
     OutputInfo, WalletCommonInit, WalletConsensusItem, WalletInput, WalletModuleTypes,
     WalletOutput, WalletOutputOutcome,
 };
 use db::{
-    DbKeyPrefix, FederationWalletKey, FederationWalletPrefix, Output, OutputKey, OutputPrefix,
-    SignaturesKey, SignaturesPrefix, SignaturesTxidPrefix, SpentOutputKey, SpentOutputPrefix,
-    TxInfoIndexKey, TxInfoIndexPrefix,
+    DbKeyPrefix, FederationWalletKey, FederationWalletPrefix, FeeBudgetKey, FeeBudgetPrefix,
+    NonceCommitmentsKey, NonceCommitmentsPrefix, NonceCommitmentsTxidPrefix, Output,
+    OutputBlockTimeKey, OutputBlockTimePrefix, OutputKey, OutputPrefix, PendingPegOutKey,
+    PendingPegOutPrefix, SignatureSharesKey, SignatureSharesPrefix, SignatureSharesTxidPrefix,
+    SignaturesKey, SignaturesPrefix, SignaturesTxidPrefix, SigningAttemptKey, SigningAttemptPrefix,
+    SpentOutputKey, SpentOutputPrefix, TxInfoIndexKey, TxInfoIndexPrefix,
 };
 use fedimint_core::config::{
     ServerModuleConfig, ServerModuleConsensusConfig, TypedServerModuleConfig,
@@ -41,7 +44,8 @@
 };
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::envs::{
-    FM_ENABLE_MODULE_WALLETV2_ENV, is_env_var_set_opt, is_running_in_test_env,
+    FM_ENABLE_MODULE_WALLETV2_ENV, FM_WALLETV2_BATCH_WINDOW_ENV, FM_WALLETV2_TAPROOT_ENV,
+    is_env_var_set, is_env_var_set_opt, is_running_in_test_env,
 };
 use fedimint_core::module::audit::Audit;
 use fedimint_core::module::{
@@ -64,22 +68,26 @@
 };
 pub use fedimint_walletv2_common as common;
 use fedimint_walletv2_common::config::{
-    FeeConsensus, WalletClientConfig, WalletConfig, WalletConfigPrivate,
+    FeeConsensus, WalletClientConfig, WalletConfig, WalletConfigPrivate, WalletDescriptor,
 };
 use fedimint_walletv2_common::endpoint_constants::{
     CONSENSUS_BLOCK_COUNT_ENDPOINT, CONSENSUS_FEERATE_ENDPOINT, FEDERATION_WALLET_ENDPOINT,
     OUTPUT_INFO_SLICE_ENDPOINT, PENDING_TRANSACTION_CHAIN_ENDPOINT, RECEIVE_FEE_ENDPOINT,
     SEND_FEE_ENDPOINT, TRANSACTION_CHAIN_ENDPOINT, TRANSACTION_ID_ENDPOINT,
 };
+use fedimint_walletv2_common::frost::{
+    self, NonceCommitment, ProofOfKnowledge, SignatureShare, SigningNonces, TaprootKey,
+};
 use fedimint_walletv2_common::{
-    FederationWallet, MODULE_CONSENSUS_VERSION, TxInfo, WalletInputError, WalletOutputError,
-    descriptor, is_potential_receive, tweak_public_key,
+    FEE_BUMP_MODULE_CONSENSUS_VERSION, FederationWallet, MODULE_CONSENSUS_VERSION,
+    PEGOUT_BATCHING_MODULE_CONSENSUS_VERSION, TAPROOT_MODULE_CONSENSUS_VERSION, TxInfo,
+    WalletInputError, WalletOutputError, descriptor, is_potential_receive, tweak_public_key,
 };
 use futures::StreamExt;
 use miniscript::descriptor::Wsh;
 use rand::rngs::OsRng;
 use secp256k1::ecdsa::Signature;
-use secp256k1::{PublicKey, Scalar, SecretKey};
+use secp256k1::{PublicKey, SECP256K1, Scalar, SecretKey};
 use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 use tracing::{debug, info};
@@ -106,6 +114,30 @@
 /// below what Bitcoin Core will relay.
 const MIN_FEERATE_VOTE_SATS_PER_KVB: u64 = 1000;
 
+/// The federation accelerates its pending transaction chain once the consensus
+/// feerate exceeds the feerate of the chain by this factor.
+pub const FEE_BUMP_FEERATE_RATIO: u64 = 2;
+
+/// Number of consensus blocks the tip of the pending transaction chain has to
+/// be pending before the federation accelerates the chain. Since the consensus
+/// block count trails the chain tip by the finality delay, this ensures the
+/// transaction was not mined for several blocks after its broadcast.
+pub const FEE_BUMP_DELAY: u64 = 12;
+
+/// Maximum number of pending transactions for the federation to append a fee
+/// bumping transaction, beyond which the doubling minimum feerate makes new
+/// peg-ins and peg-outs accelerate the chain on their own.
+const MAX_PENDING_TXS_FOR_FEE_BUMP: usize = 8;
+
+/// Maximum number of pegouts paid out by a single batch transaction. A full
+/// batch is closed immediately, regardless of the batch window.
+pub const MAX_PEGOUT_BATCH_SIZE: usize = 100;
+
+/// Number of consensus blocks the signing set of a taproot federation has to
+/// submit its signature shares once it is complete. Afterwards the federation
+/// excludes the guardians that did not and selects a new signing set.
+pub const SIGNING_TIMEOUT: u64 = 6;
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Encodable, Decodable)]
 pub struct FederationTx {
     pub tx: Transaction,
@@ -120,6 +152,24 @@ pub struct SpentTxOut {
     pub tweak: sha256::Hash,
 }
 
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Encodable, Decodable)]
+pub struct PendingPegOut {
+    pub tx_out: TxOut,
+    pub fee: Amount,
+    pub created: u64,
+}
+
+/// The signing attempt of an unsigned transaction of a taproot federation. The
+/// deadline is set once the signing set of the attempt is complete, while the
+/// excluded guardians failed to submit their signature shares in time during
+/// a previous attempt.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Encodable, Decodable)]
+pub struct SigningAttempt {
+    pub attempt: u64,
+    pub deadline: Option<u64>,
+    pub excluded: BTreeSet<PeerId>,
+}
+
 async fn pending_txs_unordered(dbtx: &mut DatabaseTransaction<'_>) -> Vec<FederationTx> {
     let unsigned: Vec<FederationTx> = dbtx
         .find_by_prefix(&UnsignedTxPrefix)
@@ -257,6 +307,66 @@ async fn dump_database(
                         "Federation Wallet"
                     );
                 }
+                DbKeyPrefix::NonceCommitments => {
+                    push_db_pair_items!(
+                        dbtx,
+                        NonceCommitmentsPrefix,
+                        NonceCommitmentsKey,
+                        Vec<NonceCommitment>,
+                        wallet,
+                        "Wallet Nonce Commitments"
+                    );
+                }
+                DbKeyPrefix::SignatureShares => {
+                    push_db_pair_items!(
+                        dbtx,
+                        SignatureSharesPrefix,
+                        SignatureSharesKey,
+                        Vec<SignatureShare>,
+                        wallet,
+                        "Wallet Signature Shares"
+                    );
+                }
+                DbKeyPrefix::FeeBudget => {
+                    push_db_pair_items!(
+                        dbtx,
+                        FeeBudgetPrefix,
+                        FeeBudgetKey,
+                        fedimint_core::Amount,
+                        wallet,
+                        "Wallet Fee Budget"
+                    );
+                }
+                DbKeyPrefix::PendingPegOut => {
+                    push_db_pair_items!(
+                        dbtx,
+                        PendingPegOutPrefix,
+                        PendingPegOutKey,
+                        PendingPegOut,
+                        wallet,
+                        "Wallet Pending Peg-Outs"
+                    );
+                }
+                DbKeyPrefix::SigningAttempt => {
+                    push_db_pair_items!(
+                        dbtx,
+                        SigningAttemptPrefix,
+                        SigningAttemptKey,
+                        SigningAttempt,
+                        wallet,
+                        "Wallet Signing Attempts"
+                    );
+                }
+                DbKeyPrefix::OutputBlockTime => {
+                    push_db_pair_items!(
+                        dbtx,
+                        OutputBlockTimePrefix,
+                        OutputBlockTimeKey,
+                        u32,
+                        wallet,
+                        "Wallet Output Block Times"
+                    );
+                }
             }
         }
 
@@ -277,15 +387,27 @@ fn is_enabled_by_default(&self) -> bool {
     }
 
     fn get_documented_env_vars(&self) -> Vec<EnvVarDoc> {
-        vec![EnvVarDoc {
-            name: FM_ENABLE_MODULE_WALLETV2_ENV,
-            description: "Set to 0/false to disable the WalletV2 module. Enabled by default.",
-        }]
+        vec![
+            EnvVarDoc {
+                name: FM_ENABLE_MODULE_WALLETV2_ENV,
+                description: "Set to 0/false to disable the WalletV2 module. Enabled by default.",
+            },
+            EnvVarDoc {
+                name: FM_WALLETV2_TAPROOT_ENV,
+                description: "Set to 1/true to generate a taproot federation wallet with threshold Schnorr signatures instead of a P2WSH multisig. Has to be set for all guardians during config generation.",
+            },
+            EnvVarDoc {
+                name: FM_WALLETV2_BATCH_WINDOW_ENV,
+                description: "Number of consensus blocks to collect peg-outs for before paying them out in a single transaction. Defaults to 0 which disables batching. Has to be set to the same value for all guardians during config generation.",
+            },
+        ]
     }
 
     async fn init(&self, args: &ServerModuleInitArgs<Self>) -> anyhow::Result<Self::Module> {
         Ok(Wallet::new(
             args.cfg().to_typed()?,
+            args.cfg().consensus.version,
+            args.our_peer_id(),
             args.db(),
             args.task_group(),
             args.server_bitcoin_rpc_monitor(),
@@ -299,10 +421,27 @@ fn trusted_dealer_gen(
     ) -> BTreeMap<PeerId, ServerModuleConfig> {
         let fee_consensus = FeeConsensus::new(0).expect("Relative fee is within range");
 
-        let bitcoin_sks = peers
-            .iter()
-            .map(|peer| (*peer, SecretKey::new(&mut secp256k1::rand::thread_rng())))
-            .collect::<BTreeMap<PeerId, SecretKey>>();
+        let descriptor = wallet_descriptor();
+
+        // The trusted dealer can not return an error, it is only used in tests
+        let batch_window = batch_window().expect("Malformed batch window");
+
+        let bitcoin_sks = match descriptor {
+            WalletDescriptor::Wsh => peers
+                .iter()
+                .map(|peer| (*peer, SecretKey::new(&mut secp256k1::rand::thread_rng())))
+                .collect::<BTreeMap<PeerId, SecretKey>>(),
+            WalletDescriptor::Tr => {
+                let coefficients = (0..peers.to_num_peers().threshold())
+                    .map(|_| SecretKey::new(&mut secp256k1::rand::thread_rng()))
+                    .collect::<Vec<SecretKey>>();
+
+                peers
+                    .iter()
+                    .map(|peer| (*peer, frost::evaluate_polynomial(&coefficients, *peer)))
+                    .collect::<BTreeMap<PeerId, SecretKey>>()
+            }
+        };
 
         let bitcoin_pks = bitcoin_sks
             .iter()
@@ -316,6 +455,8 @@ fn trusted_dealer_gen(
                     private: WalletConfigPrivate { bitcoin_sk },
                     consensus: WalletConfigConsensus::new(
                         bitcoin_pks.clone(),
+                        descriptor.clone(),
+                        batch_window,
                         fee_consensus.clone(),
                         args.network,
                     ),
@@ -333,23 +474,60 @@ async fn distributed_gen(
     ) -> anyhow::Result<ServerModuleConfig> {
         let fee_consensus = FeeConsensus::new(0).expect("Relative fee is within range");
 
-        let (bitcoin_sk, bitcoin_pk) = secp256k1::generate_keypair(&mut OsRng);
+        let descriptor = wallet_descriptor();
 
-        let bitcoin_pks: BTreeMap<PeerId, PublicKey> = peers
-            .exchange_encodable(bitcoin_pk)
-            .await?
-            .into_iter()
-            .collect();
+        ensure!(
+            peers
+                .exchange_encodable(descriptor.clone())
+                .await?
+                .values()
+                .all(|peer_descriptor| *peer_descriptor == descriptor),
+            "Guardians disagree on the wallet descriptor, {FM_WALLETV2_TAPROOT_ENV} has to be set for all guardians"
+        );
+
+        let batch_window = batch_window()?;
+
+        ensure!(
+            peers
+                .exchange_encodable(batch_window)
+                .await?
+                .values()
+                .all(|peer_batch_window| *peer_batch_window == batch_window),
+            "Guardians disagree on the batch window, {FM_WALLETV2_BATCH_WINDOW_ENV} has to be set to the same value for all guardians"
+        );
+
+        let (bitcoin_sk, bitcoin_pks) = match descriptor {
+            WalletDescriptor::Wsh => {
+                let (bitcoin_sk, bitcoin_pk) = secp256k1::generate_keypair(&mut OsRng);
+
+                let bitcoin_pks: BTreeMap<PeerId, PublicKey> = peers
+                    .exchange_encodable(bitcoin_pk)
+                    .await?
+                    .into_iter()
+                    .collect();
+
+                (bitcoin_sk, bitcoin_pks)
+            }
+            WalletDescriptor::Tr => run_taproot_dkg(peers).await?,
+        };
 
         let config = WalletConfig {
             private: WalletConfigPrivate { bitcoin_sk },
-            consensus: WalletConfigConsensus::new(bitcoin_pks, fee_consensus, args.network),
+            consensus: WalletConfigConsensus::new(
+                bitcoin_pks,
+                descriptor,
+                batch_window,
+                fee_consensus,
+                args.network,
+            ),
         };
 
         Ok(config.to_erased())
     }
 
     fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
+        let consensus_version = config.consensus.version;
+
         let config = config.to_typed::<WalletConfig>()?;
 
         ensure!(
@@ -362,6 +540,18 @@ fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyh
             "Bitcoin wallet private key doesn't match multisig pubkey"
         );
 
+        if config.consensus.descriptor == WalletDescriptor::Tr {
+            ensure!(
+                TAPROOT_MODULE_CONSENSUS_VERSION <= consensus_version,
+                "The taproot descriptor requires module consensus version {TAPROOT_MODULE_CONSENSUS_VERSION}"
+            );
+
+            ensure!(
+                frost::verify_public_shares(&config.consensus.bitcoin_pks),
+                "Bitcoin wallet public keys are not shares of a single group key"
+            );
+        }
+
         Ok(())
     }
 
@@ -403,23 +593,33 @@ async fn consensus_proposal<'a>(
         &'a self,
         dbtx: &mut DatabaseTransaction<'_>,
     ) -> Vec<WalletConsensusItem> {
-        let mut items = dbtx
+        let unsigned_txs = dbtx
             .find_by_prefix(&UnsignedTxPrefix)
             .await
-            .map(|(key, unsigned_tx)| {
-                let signatures = self.sign_tx(&unsigned_tx);
+            .collect::<Vec<(UnsignedTxKey, FederationTx)>>()
+            .await;
 
-                self.verify_signatures(
-                    &unsigned_tx,
-                    &signatures,
-                    self.cfg.private.bitcoin_sk.public_key(secp256k1::SECP256K1),
-                )
-                .expect("Our signatures failed verification against our private key");
+        let mut items = Vec::new();
 
-                WalletConsensusItem::Signatures(key.0, signatures)
-            })
-            .collect::<Vec<WalletConsensusItem>>()
-            .await;
+        for (key, unsigned_tx) in unsigned_txs {
+            match self.cfg.consensus.descriptor {
+                WalletDescriptor::Wsh => {
+                    let signatures = self.sign_tx(&unsigned_tx);
+
+                    self.verify_signatures(
+                        &unsigned_tx,
+                        &signatures,
+                        self.cfg.private.bitcoin_sk.public_key(secp256k1::SECP256K1),
+                    )
+                    .expect("Our signatures failed verification against our private key");
+
+                    items.push(WalletConsensusItem::Signatures(key.0, signatures));
+                }
+                WalletDescriptor::Tr => {
+                    items.extend(self.taproot_proposal(dbtx, key.0, &unsigned_tx).await);
+                }
+            }
+        }
 
         if let Some(status) = self.btc_rpc.status() {
             assert_eq!(status.network, self.cfg.consensus.network);
@@ -475,8 +675,33 @@ async fn process_consensus_item<'a, 'b>(
                 Ok(())
             }
             WalletConsensusItem::Signatures(txid, signatures) => {
+                ensure!(
+                    self.cfg.consensus.descriptor == WalletDescriptor::Wsh,
+                    "Signatures are only used by the P2WSH descriptor"
+                );
+
                 self.process_signatures(dbtx, txid, signatures, peer).await
             }
+            WalletConsensusItem::NonceCommitments(txid, attempt, commitments) => {
+                ensure!(
+                    self.is_consensus_version_active(TAPROOT_MODULE_CONSENSUS_VERSION)
+                        && self.cfg.consensus.descriptor == WalletDescriptor::Tr,
+                    "Nonce commitments are only used by the taproot descriptor"
+                );
+
+                self.process_nonce_commitments(dbtx, txid, attempt, commitments, peer)
+                    .await
+            }
+            WalletConsensusItem::SignatureShares(txid, shares) => {
+                ensure!(
+                    self.is_consensus_version_active(TAPROOT_MODULE_CONSENSUS_VERSION)
+                        && self.cfg.consensus.descriptor == WalletDescriptor::Tr,
+                    "Signature shares are only used by the taproot descriptor"
+                );
+
+                self.process_signature_shares(dbtx, txid, shares, peer)
+                    .await
+            }
             WalletConsensusItem::Default { variant, .. } => Err(anyhow!(
                 "Received wallet consensus item with unknown variant {variant}"
             )),
@@ -504,9 +729,7 @@ async fn process_input<'a, 'b, 'c>(
             .await
             .ok_or(WalletInputError::UnknownOutputIndex)?;
 
-        let tweaked_pubkey = self
-            .descriptor(&input.tweak.consensus_hash())
-            .script_pubkey();
+        let tweaked_pubkey = self.script_pubkey(&input.tweak.consensus_hash());
 
         if tracked_output.script_pubkey != tweaked_pubkey {
             return Err(WalletInputError::WrongTweak);
@@ -558,7 +781,7 @@ async fn process_input<'a, 'b, 'c>(
                 ],
                 output: vec![TxOut {
                     value: change_value,
-                    script_pubkey: self.descriptor(&wallet.consensus_hash()).script_pubkey(),
+                    script_pubkey: self.script_pubkey(&wallet.consensus_hash()),
                 }],
             };
 
@@ -630,10 +853,16 @@ async fn process_input<'a, 'b, 'c>(
             .map(fedimint_core::Amount::from_msats)
             .ok_or(WalletInputError::ArithmeticOverflow)?;
 
+        let fee = self.cfg.consensus.fee_consensus.fee(amount);
+
+        if self.is_consensus_version_active(FEE_BUMP_MODULE_CONSENSUS_VERSION) {
+            self.add_fee_budget(dbtx, fee).await;
+        }
+
         Ok(InputMeta {
             amount: TransactionItemAmounts {
                 amounts: Amounts::new_bitcoin(amount),
-                fees: Amounts::new_bitcoin(self.cfg.consensus.fee_consensus.fee(amount)),
+                fees: Amounts::new_bitcoin(fee),
             },
             pub_key: input.tweak,
         })
@@ -652,7 +881,7 @@ async fn process_output<'a, 'b>(
         }
 
         let wallet = dbtx
-            .remove_entry(&FederationWalletKey)
+            .get_value(&FederationWalletKey)
             .await
             .ok_or(WalletOutputError::NoFederationUTXO)?;
 
@@ -674,9 +903,12 @@ async fn process_output<'a, 'b>(
             .checked_add(output.fee)
             .ok_or(WalletOutputError::ArithmeticOverflow)?;
 
+        // The value of pending pegouts is reserved such that the change of the
+        // batch transaction is guaranteed to be above the dust limit.
         let change_value = wallet
             .value
-            .checked_sub(output_value)
+            .checked_sub(self.pegout_batch_value(dbtx).await)
+            .and_then(|value| value.checked_sub(output_value))
             .ok_or(WalletOutputError::ArithmeticOverflow)?;
 
         if change_value < self.cfg.consensus.dust_limit {
@@ -688,74 +920,28 @@ async fn process_output<'a, 'b>(
             .script_pubkey()
             .ok_or(WalletOutputError::UnknownScriptVariant)?;
 
-        let tx = Transaction {
-            version: Version(2),
-            lock_time: LockTime::ZERO,
-            input: vec![TxIn {
-                previous_output: wallet.outpoint,
-                script_sig: Default::default(),
-                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
-                witness: bitcoin::Witness::new(),
-            }],
-            output: vec![
-                TxOut {
-                    value: change_value,
-                    script_pubkey: self.descriptor(&wallet.consensus_hash()).script_pubkey(),
-                },
-                TxOut {
-                    value: output.value,
-                    script_pubkey,
-                },
-            ],
-        };
-
-        dbtx.insert_new_entry(
-            &FederationWalletKey,
-            &FederationWallet {
-                value: change_value,
-                outpoint: bitcoin::OutPoint {
-                    txid: tx.compute_txid(),
-                    vout: 0,
-                },
-                tweak: wallet.consensus_hash(),
-            },
-        )
-        .await;
-
-        let tx_index = self.total_txs(dbtx).await;
-
         let created = self.consensus_block_count(dbtx).await;
 
         dbtx.insert_new_entry(
-            &TxInfoKey(tx_index),
-            &TxInfo {
-                index: tx_index,
-                txid: tx.compute_txid(),
-                input: wallet.value,
-                output: change_value,
-                vbytes: self.cfg.consensus.send_tx_vbytes,
+            &PendingPegOutKey(outpoint),
+            &PendingPegOut {
+                tx_out: TxOut {
+                    value: output.value,
+                    script_pubkey,
+                },
                 fee: output.fee,
                 created,
             },
         )
         .await;
 
-        dbtx.insert_new_entry(&TxInfoIndexKey(outpoint), &tx_index)
-            .await;
-
-        dbtx.insert_new_entry(
-            &UnsignedTxKey(tx.compute_txid()),
-            &FederationTx {
-                tx,
-                spent_tx_outs: vec![SpentTxOut {
-                    value: wallet.value,
-                    tweak: wallet.tweak,
-                }],
-                vbytes: self.cfg.consensus.send_tx_vbytes,
-                fee: output.fee,
-            },
-        )
-        .await;
+        // Without a batch window every pegout is paid out by its own transaction
+        if !self.is_consensus_version_active(PEGOUT_BATCHING_MODULE_CONSENSUS_VERSION)
+            || self.cfg.consensus.batch_window == 0
+            || self.pegout_batch(dbtx).await.len() >= MAX_PEGOUT_BATCH_SIZE
+        {
+            self.close_pegout_batch(dbtx).await;
+        }
 
         let amount = output_value
             .to_sat()
@@ -763,9 +949,15 @@ async fn process_output<'a, 'b>(
             .map(fedimint_core::Amount::from_msats)
             .ok_or(WalletOutputError::ArithmeticOverflow)?;
 
+        let fee = self.cfg.consensus.fee_consensus.fee(amount);
+
+        if self.is_consensus_version_active(FEE_BUMP_MODULE_CONSENSUS_VERSION) {
+            self.add_fee_budget(dbtx, fee).await;
+        }
+
         Ok(TransactionItemAmounts {
             amounts: Amounts::new_bitcoin(amount),
-            fees: Amounts::new_bitcoin(self.cfg.consensus.fee_consensus.fee(amount)),
+            fees: Amounts::new_bitcoin(fee),
         })
     }
 
@@ -791,6 +983,15 @@ async fn audit(
                 |_, wallet| 1000 * wallet.value.to_sat() as i64,
             )
             .await;
+
+        audit
+            .add_items(
+                dbtx,
+                module_instance_id,
+                &PendingPegOutPrefix,
+                |_, pegout| -1000 * (pegout.tx_out.value + pegout.fee).to_sat() as i64,
+            )
+            .await;
     }
 
     fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
@@ -888,13 +1089,17 @@ fn supported_api_versions(&self) -> MultiApiVersion {
 #[derive(Debug)]
 pub struct Wallet {
     cfg: WalletConfig,
+    consensus_version: ModuleConsensusVersion,
+    our_peer_id: PeerId,
     db: Database,
     btc_rpc: ServerBitcoinRpcMonitor,
 }
 
 impl Wallet {
-    fn new(
+    pub fn new(
         cfg: WalletConfig,
+        consensus_version: ModuleConsensusVersion,
+        our_peer_id: PeerId,
         db: &Database,
         task_group: &TaskGroup,
         btc_rpc: ServerBitcoinRpcMonitor,
@@ -903,6 +1108,8 @@ fn new(
 
         Wallet {
             cfg,
+            consensus_version,
+            our_peer_id,
             btc_rpc,
             db: db.clone(),
         }
@@ -1013,6 +1220,8 @@ async fn process_block_count(
 
             let pks_hash = self.cfg.consensus.bitcoin_pks.consensus_hash();
 
+            let block_time = block.header.time;
+
             let txs_num = block.txdata.len();
             let mut potential_receives_num: usize = 0;
 
@@ -1043,6 +1252,9 @@ async fn process_block_count(
                         dbtx.insert_new_entry(&OutputKey(index), &Output(outpoint, tx_out.clone()))
                             .await;
 
+                        dbtx.insert_new_entry(&OutputBlockTimeKey(index), &block_time)
+                            .await;
+
                         debug!(
                             target: LOG_MODULE_WALLETV2,
                             output_index = index,
@@ -1066,85 +1278,693 @@ async fn process_block_count(
             );
         }
 
+        if old_consensus_block_count < new_consensus_block_count {
+            if self.is_consensus_version_active(PEGOUT_BATCHING_MODULE_CONSENSUS_VERSION)
+                && let Some(opened) = self.pegout_batch_opened(dbtx).await
+                && opened + self.cfg.consensus.batch_window <= new_consensus_block_count
+            {
+                self.close_pegout_batch(dbtx).await;
+            }
+
+            if self.is_consensus_version_active(FEE_BUMP_MODULE_CONSENSUS_VERSION) {
+                self.bump_stuck_tx_chain(dbtx, new_consensus_block_count)
+                    .await;
+            }
+
+            if self.is_consensus_version_active(TAPROOT_MODULE_CONSENSUS_VERSION) {
+                self.expire_signing_attempts(dbtx, new_consensus_block_count)
+                    .await;
+            }
+        }
+
         Ok(())
     }
 
-    async fn process_signatures(
+    /// Whether the rules introduced with `version` apply to this federation.
+    /// The module consensus version is fixed in the config at generation.
+    fn is_consensus_version_active(&self, version: ModuleConsensusVersion) -> bool {
+        version <= self.consensus_version
+    }
+
+    /// Replaces the signing attempts of taproot transactions whose signing set
+    /// did not submit all signature shares before the deadline. The guardians
+    /// which failed to sign are excluded from the next signing set, unless this
+    /// leaves less than a threshold of guardians, in which case all guardians
+    /// may join the next signing set again.
+    async fn expire_signing_attempts(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
-        txid: bitcoin::Txid,
-        signatures: Vec<Signature>,
-        peer: PeerId,
-    ) -> anyhow::Result<()> {
-        let mut unsigned = dbtx
-            .get_value(&UnsignedTxKey(txid))
+        consensus_block_count: u64,
+    ) {
+        let expired = dbtx
+            .find_by_prefix(&SigningAttemptPrefix)
             .await
-            .context("Unsigned transaction does not exist")?;
+            .filter(|(_, attempt)| {
+                std::future::ready(
+                    attempt
+                        .deadline
+                        .is_some_and(|deadline| deadline <= consensus_block_count),
+                )
+            })
+            .collect::<Vec<(SigningAttemptKey, SigningAttempt)>>()
+            .await;
 
-        let pk = self
-            .cfg
-            .consensus
-            .bitcoin_pks
-            .get(&peer)
-            .expect("Failed to get public key of peer from config");
+        let num_peers = self.cfg.consensus.bitcoin_pks.to_num_peers();
 
-        self.verify_signatures(&unsigned, &signatures, *pk)?;
+        for (key, mut attempt) in expired {
+            let signed = dbtx
+                .find_by_prefix(&SignatureSharesTxidPrefix(key.0))
+                .await
+                .map(|(key, _)| key.1)
+                .collect::<BTreeSet<PeerId>>()
+                .await;
 
-        if dbtx
-            .insert_entry(&SignaturesKey(txid, peer), &signatures)
-            .await
-            .is_some()
-        {
-            bail!("Already received valid signatures from this peer")
-        }
+            let unresponsive = self
+                .nonce_commitments(dbtx, key.0)
+                .await
+                .into_keys()
+                .filter(|peer| !signed.contains(peer));
 
-        let signatures = dbtx
-            .find_by_prefix(&SignaturesTxidPrefix(txid))
-            .await
-            .map(|(key, signatures)| (key.1, signatures))
-            .collect::<BTreeMap<PeerId, Vec<Signature>>>()
-            .await;
+            attempt.excluded.extend(unresponsive);
 
-        if signatures.len() == self.cfg.consensus.bitcoin_pks.to_num_peers().threshold() {
-            dbtx.remove_entry(&UnsignedTxKey(txid)).await;
+            if num_peers.total() - attempt.excluded.len() < num_peers.threshold() {
+                attempt.excluded.clear();
+            }
 
-            dbtx.remove_by_prefix(&SignaturesTxidPrefix(txid)).await;
+            attempt.attempt += 1;
+            attempt.deadline = None;
 
-            self.finalize_tx(&mut unsigned, &signatures);
+            info!(
+                target: LOG_MODULE_WALLETV2,
+                txid = %key.0,
+                attempt = attempt.attempt,
+                excluded = ?attempt.excluded,
+                "Signing set failed to sign in time, selecting a new signing set"
+            );
 
-            dbtx.insert_new_entry(&UnconfirmedTxKey(txid), &unsigned)
+            dbtx.remove_by_prefix(&NonceCommitmentsTxidPrefix(key.0))
                 .await;
 
-            if let Err(err) = self.btc_rpc.submit_transaction(unsigned.tx).await {
-                debug!(
-                    target: LOG_MODULE_WALLETV2,
-                    err = %err.fmt_compact_anyhow(),
-                    "Error broadcasting finalized transaction"
-                );
-            }
+            dbtx.remove_by_prefix(&SignatureSharesTxidPrefix(key.0))
+                .await;
+
+            dbtx.insert_entry(&key, &attempt).await;
         }
+    }
 
-        Ok(())
+    async fn signing_attempt(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        txid: bitcoin::Txid,
+    ) -> SigningAttempt {
+        dbtx.get_value(&SigningAttemptKey(txid))
+            .await
+            .unwrap_or_default()
     }
 
-    async fn await_local_sync_to_block_count(&self, block_count: u64) {
-        loop {
-            if self
-                .btc_rpc
-                .status()
-                .is_some_and(|status| status.block_count >= block_count)
-            {
-                break;
-            }
+    /// Accelerates the pending transaction chain via child pays for parent once
+    /// its feerate has fallen far below the consensus feerate, by appending a
+    /// transaction that spends the federation UTXO back to the federation and
+    /// pays the fee missing for the entire chain. The fee is paid from the fee
+    /// budget, such that the federation never spends more than it collected
+    /// in fees for walletv2 inputs and outputs.
+    async fn bump_stuck_tx_chain(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        consensus_block_count: u64,
+    ) {
+        // We wait for the guardians to sign the pending chain before extending it
+        if dbtx
+            .find_by_prefix(&UnsignedTxPrefix)
+            .await
+            .next()
+            .await
+            .is_some()
+        {
+            return;
+        }
 
-            info!(target: LOG_MODULE_WALLETV2, "Waiting for local bitcoin backend to sync to block count {block_count}");
+        let pending_txs = pending_txs_unordered(dbtx).await;
 
-            sleep(common::sleep_duration()).await;
+        if pending_txs.is_empty() || pending_txs.len() >= MAX_PENDING_TXS_FOR_FEE_BUMP {
+            return;
         }
-    }
 
-    pub async fn consensus_block_count(&self, dbtx: &mut DatabaseTransaction<'_>) -> u64 {
-        let num_peers = self.cfg.consensus.bitcoin_pks.to_num_peers();
+        let Some(consensus_feerate) = self.consensus_feerate(dbtx).await else {
+            return;
+        };
+
+        if !is_stuck(&pending_txs, consensus_feerate) {
+            return;
+        }
+
+        let tip_created = self
+            .pending_tx_chain(dbtx)
+            .await
+            .first()
+            .expect("The pending transaction chain is not empty")
+            .created;
+
+        if consensus_block_count < tip_created + FEE_BUMP_DELAY {
+            return;
+        }
+
+        let wallet = dbtx
+            .get_value(&FederationWalletKey)
+            .await
+            .expect("The federation wallet exists while transactions are pending");
+
+        let fee = self
+            .consensus_fee(dbtx, self.cfg.consensus.send_tx_vbytes)
+            .await
+            .expect("The consensus feerate is available");
+
+        let fee_msats = fedimint_core::Amount::from_sats(fee.to_sat());
+
+        let fee_budget = self.fee_budget(dbtx).await;
+
+        if fee_budget < fee_msats {
+            info!(
+                target: LOG_MODULE_WALLETV2,
+                %fee,
+                %fee_budget,
+                "Fee budget is insufficient to accelerate the pending transaction chain"
+            );
+
+            return;
+        }
+
+        // The value of pending pegouts stays reserved for their batch transaction
+        let reserved_value = self.pegout_batch_value(dbtx).await;
+
+        let Some(change_value) = wallet.value.checked_sub(fee).filter(|change_value| {
+            change_value
+                .checked_sub(reserved_value)
+                .is_some_and(|value| value >= self.cfg.consensus.dust_limit)
+        }) else {
+            return;
+        };
+
+        let tx = Transaction {
+            version: Version(2),
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: wallet.outpoint,
+                script_sig: Default::default(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: bitcoin::Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: change_value,
+                script_pubkey: self.script_pubkey(&wallet.consensus_hash()),
+            }],
+        };
+
+        dbtx.insert_entry(&FeeBudgetKey, &(fee_budget - fee_msats))
+            .await;
+
+        dbtx.insert_entry(
+            &FederationWalletKey,
+            &FederationWallet {
+                value: change_value,
+                outpoint: bitcoin::OutPoint {
+                    txid: tx.compute_txid(),
+                    vout: 0,
+                },
+                tweak: wallet.consensus_hash(),
+            },
+        )
+        .await;
+
+        let tx_index = self.total_txs(dbtx).await;
+
+        dbtx.insert_new_entry(
+            &TxInfoKey(tx_index),
+            &TxInfo {
+                index: tx_index,
+                txid: tx.compute_txid(),
+                input: wallet.value,
+                output: change_value,
+                vbytes: self.cfg.consensus.send_tx_vbytes,
+                fee,
+                created: consensus_block_count,
+            },
+        )
+        .await;
+
+        info!(
+            target: LOG_MODULE_WALLETV2,
+            txid = %tx.compute_txid(),
+            %fee,
+            "Accelerating the pending transaction chain"
+        );
+
+        dbtx.insert_new_entry(
+            &UnsignedTxKey(tx.compute_txid()),
+            &FederationTx {
+                tx,
+                spent_tx_outs: vec![SpentTxOut {
+                    value: wallet.value,
+                    tweak: wallet.tweak,
+                }],
+                vbytes: self.cfg.consensus.send_tx_vbytes,
+                fee,
+            },
+        )
+        .await;
+    }
+
+    async fn fee_budget(&self, dbtx: &mut DatabaseTransaction<'_>) -> fedimint_core::Amount {
+        dbtx.get_value(&FeeBudgetKey)
+            .await
+            .unwrap_or(fedimint_core::Amount::ZERO)
+    }
+
+    async fn add_fee_budget(&self, dbtx: &mut DatabaseTransaction<'_>, fee: fedimint_core::Amount) {
+        let fee_budget = self.fee_budget(dbtx).await + fee;
+
+        dbtx.insert_entry(&FeeBudgetKey, &fee_budget).await;
+    }
+
+    async fn pegout_batch(&self, dbtx: &mut DatabaseTransaction<'_>) -> Vec<PendingPegOut> {
+        dbtx.find_by_prefix(&PendingPegOutPrefix)
+            .await
+            .map(|entry| entry.1)
+            .collect()
+            .await
+    }
+
+    /// The total value of the pending pegouts including their fees
+    async fn pegout_batch_value(&self, dbtx: &mut DatabaseTransaction<'_>) -> Amount {
+        self.pegout_batch(dbtx)
+            .await
+            .iter()
+            .map(|pegout| pegout.tx_out.value + pegout.fee)
+            .sum()
+    }
+
+    /// The consensus block count at which the open pegout batch was created
+    async fn pegout_batch_opened(&self, dbtx: &mut DatabaseTransaction<'_>) -> Option<u64> {
+        self.pegout_batch(dbtx)
+            .await
+            .iter()
+            .map(|pegout| pegout.created)
+            .min()
+    }
+
+    /// Pays out all pending pegouts with a single transaction appended to the
+    /// chain which spends the federation UTXO. The transaction pays the sum of
+    /// the fees paid by the individual pegouts.
+    async fn close_pegout_batch(&self, dbtx: &mut DatabaseTransaction<'_>) {
+        let batch = dbtx
+            .find_by_prefix(&PendingPegOutPrefix)
+            .await
+            .collect::<Vec<(PendingPegOutKey, PendingPegOut)>>()
+            .await;
+
+        if batch.is_empty() {
+            return;
+        }
+
+        let wallet = dbtx
+            .get_value(&FederationWalletKey)
+            .await
+            .expect("The federation wallet exists while pegouts are pending");
+
+        let batch_value = batch
+            .iter()
+            .map(|(_, pegout)| pegout.tx_out.value + pegout.fee)
+            .sum::<Amount>();
+
+        let change_value = wallet
+            .value
+            .checked_sub(batch_value)
+            .expect("The value of pending pegouts is reserved in the federation wallet");
+
+        let fee = batch.iter().map(|(_, pegout)| pegout.fee).sum::<Amount>();
+
+        let vbytes = self.cfg.consensus.send_tx_vbytes
+            + (batch.len() as u64 - 1) * self.cfg.consensus.batch_output_vbytes;
+
+        let tx = Transaction {
+            version: Version(2),
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: wallet.outpoint,
+                script_sig: Default::default(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: bitcoin::Witness::new(),
+            }],
+            output: std::iter::once(TxOut {
+                value: change_value,
+                script_pubkey: self.script_pubkey(&wallet.consensus_hash()),
+            })
+            .chain(batch.iter().map(|(_, pegout)| pegout.tx_out.clone()))
+            .collect(),
+        };
+
+        dbtx.insert_entry(
+            &FederationWalletKey,
+            &FederationWallet {
+                value: change_value,
+                outpoint: bitcoin::OutPoint {
+                    txid: tx.compute_txid(),
+                    vout: 0,
+                },
+                tweak: wallet.consensus_hash(),
+            },
+        )
+        .await;
+
+        let tx_index = self.total_txs(dbtx).await;
+
+        let created = self.consensus_block_count(dbtx).await;
+
+        dbtx.insert_new_entry(
+            &TxInfoKey(tx_index),
+            &TxInfo {
+                index: tx_index,
+                txid: tx.compute_txid(),
+                input: wallet.value,
+                output: change_value,
+                vbytes,
+                fee,
+                created,
+            },
+        )
+        .await;
+
+        for (key, _) in &batch {
+            dbtx.remove_entry(key).await;
+
+            dbtx.insert_new_entry(&TxInfoIndexKey(key.0), &tx_index)
+                .await;
+        }
+
+        debug!(
+            target: LOG_MODULE_WALLETV2,
+            txid = %tx.compute_txid(),
+            pegouts = batch.len(),
+            %fee,
+            "Closed pegout batch"
+        );
+
+        dbtx.insert_new_entry(
+            &UnsignedTxKey(tx.compute_txid()),
+            &FederationTx {
+                tx,
+                spent_tx_outs: vec![SpentTxOut {
+                    value: wallet.value,
+                    tweak: wallet.tweak,
+                }],
+                vbytes,
+                fee,
+            },
+        )
+        .await;
+    }
+
+    async fn process_signatures(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        txid: bitcoin::Txid,
+        signatures: Vec<Signature>,
+        peer: PeerId,
+    ) -> anyhow::Result<()> {
+        let mut unsigned = dbtx
+            .get_value(&UnsignedTxKey(txid))
+            .await
+            .context("Unsigned transaction does not exist")?;
+
+        let pk = self
+            .cfg
+            .consensus
+            .bitcoin_pks
+            .get(&peer)
+            .expect("Failed to get public key of peer from config");
+
+        self.verify_signatures(&unsigned, &signatures, *pk)?;
+
+        if dbtx
+            .insert_entry(&SignaturesKey(txid, peer), &signatures)
+            .await
+            .is_some()
+        {
+            bail!("Already received valid signatures from this peer")
+        }
+
+        let signatures = dbtx
+            .find_by_prefix(&SignaturesTxidPrefix(txid))
+            .await
+            .map(|(key, signatures)| (key.1, signatures))
+            .collect::<BTreeMap<PeerId, Vec<Signature>>>()
+            .await;
+
+        if signatures.len() == self.cfg.consensus.bitcoin_pks.to_num_peers().threshold() {
+            dbtx.remove_by_prefix(&SignaturesTxidPrefix(txid)).await;
+
+            self.finalize_tx(&mut unsigned, &signatures);
+
+            self.submit_finalized_tx(dbtx, txid, unsigned).await;
+        }
+
+        Ok(())
+    }
+
+    async fn process_nonce_commitments(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        txid: bitcoin::Txid,
+        attempt: u64,
+        commitments: Vec<NonceCommitment>,
+        peer: PeerId,
+    ) -> anyhow::Result<()> {
+        let unsigned = dbtx
+            .get_value(&UnsignedTxKey(txid))
+            .await
+            .context("Unsigned transaction does not exist")?;
+
+        ensure!(
+            unsigned.spent_tx_outs.len() == commitments.len(),
+            "Incorrect number of nonce commitments"
+        );
+
+        let mut signing_attempt = self.signing_attempt(dbtx, txid).await;
+
+        ensure!(
+            signing_attempt.attempt == attempt,
+            "Nonce commitments are for a different signing attempt"
+        );
+
+        ensure!(
+            !signing_attempt.excluded.contains(&peer),
+            "Peer is excluded from this signing attempt"
+        );
+
+        let threshold = self.cfg.consensus.bitcoin_pks.to_num_peers().threshold();
+
+        // The first threshold of guardians to commit to their nonces form the
+        // signing set of this attempt, which may not change afterwards since
+        // the guardians derive their nonces deterministically. Only a new
+        // attempt with fresh nonces may select a different signing set.
+        ensure!(
+            self.nonce_commitments(dbtx, txid).await.len() < threshold,
+            "The signing set is already complete"
+        );
+
+        if dbtx
+            .insert_entry(&NonceCommitmentsKey(txid, peer), &commitments)
+            .await
+            .is_some()
+        {
+            bail!("Already received nonce commitments from this peer")
+        }
+
+        if self.nonce_commitments(dbtx, txid).await.len() == threshold {
+            signing_attempt.deadline =
+                Some(self.consensus_block_count(dbtx).await + SIGNING_TIMEOUT);
+
+            dbtx.insert_entry(&SigningAttemptKey(txid), &signing_attempt)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    async fn process_signature_shares(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        txid: bitcoin::Txid,
+        shares: Vec<SignatureShare>,
+        peer: PeerId,
+    ) -> anyhow::Result<()> {
+        let mut unsigned = dbtx
+            .get_value(&UnsignedTxKey(txid))
+            .await
+            .context("Unsigned transaction does not exist")?;
+
+        let commitments = self.nonce_commitments(dbtx, txid).await;
+
+        ensure!(
+            commitments.len() == self.cfg.consensus.bitcoin_pks.to_num_peers().threshold(),
+            "The signing set is not complete yet"
+        );
+
+        ensure!(
+            commitments.contains_key(&peer),
+            "Peer is not part of the signing set"
+        );
+
+        let pk = self
+            .cfg
+            .consensus
+            .bitcoin_pks
+            .get(&peer)
+            .expect("Failed to get public key of peer from config");
+
+        self.verify_signature_shares(&unsigned, &commitments, &shares, peer, pk)?;
+
+        if dbtx
+            .insert_entry(&SignatureSharesKey(txid, peer), &shares)
+            .await
+            .is_some()
+        {
+            bail!("Already received valid signature shares from this peer")
+        }
+
+        let shares = dbtx
+            .find_by_prefix(&SignatureSharesTxidPrefix(txid))
+            .await
+            .map(|(key, shares)| (key.1, shares))
+            .collect::<BTreeMap<PeerId, Vec<SignatureShare>>>()
+            .await;
+
+        if shares.len() == commitments.len() {
+            dbtx.remove_by_prefix(&NonceCommitmentsTxidPrefix(txid))
+                .await;
+
+            dbtx.remove_by_prefix(&SignatureSharesTxidPrefix(txid))
+                .await;
+
+            dbtx.remove_entry(&SigningAttemptKey(txid)).await;
+
+            self.finalize_taproot_tx(&mut unsigned, &commitments, &shares);
+
+            self.submit_finalized_tx(dbtx, txid, unsigned).await;
+        }
+
+        Ok(())
+    }
+
+    async fn submit_finalized_tx(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        txid: bitcoin::Txid,
+        finalized: FederationTx,
+    ) {
+        dbtx.remove_entry(&UnsignedTxKey(txid)).await;
+
+        dbtx.insert_new_entry(&UnconfirmedTxKey(txid), &finalized)
+            .await;
+
+        if let Err(err) = self.btc_rpc.submit_transaction(finalized.tx).await {
+            debug!(
+                target: LOG_MODULE_WALLETV2,
+                err = %err.fmt_compact_anyhow(),
+                "Error broadcasting finalized transaction"
+            );
+        }
+    }
+
+    /// Returns our nonce commitments for the current signing attempt of the
+    /// transaction until the signing set is complete and our signature shares
+    /// afterwards if we are part of it.
+    async fn taproot_proposal(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        txid: bitcoin::Txid,
+        unsigned_tx: &FederationTx,
+    ) -> Option<WalletConsensusItem> {
+        let signing_attempt = self.signing_attempt(dbtx, txid).await;
+
+        if signing_attempt.excluded.contains(&self.our_peer_id) {
+            return None;
+        }
+
+        let commitments = self.nonce_commitments(dbtx, txid).await;
+
+        if commitments.len() < self.cfg.consensus.bitcoin_pks.to_num_peers().threshold() {
+            if commitments.contains_key(&self.our_peer_id) {
+                return None;
+            }
+
+            let commitments = (0..unsigned_tx.spent_tx_outs.len())
+                .map(|index| {
+                    self.signing_nonces(unsigned_tx, signing_attempt.attempt, index)
+                        .commitment()
+                })
+                .collect();
+
+            return Some(WalletConsensusItem::NonceCommitments(
+                txid,
+                signing_attempt.attempt,
+                commitments,
+            ));
+        }
+
+        if !commitments.contains_key(&self.our_peer_id)
+            || dbtx
+                .get_value(&SignatureSharesKey(txid, self.our_peer_id))
+                .await
+                .is_some()
+        {
+            return None;
+        }
+
+        let shares = self.sign_shares(unsigned_tx, signing_attempt.attempt, &commitments);
+
+        self.verify_signature_shares(
+            unsigned_tx,
+            &commitments,
+            &shares,
+            self.our_peer_id,
+            &self.cfg.private.bitcoin_sk.public_key(SECP256K1),
+        )
+        .expect("Our signature shares failed verification against our private key");
+
+        Some(WalletConsensusItem::SignatureShares(txid, shares))
+    }
+
+    async fn nonce_commitments(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        txid: bitcoin::Txid,
+    ) -> BTreeMap<PeerId, Vec<NonceCommitment>> {
+        dbtx.find_by_prefix(&NonceCommitmentsTxidPrefix(txid))
+            .await
+            .map(|(key, commitments)| (key.1, commitments))
+            .collect()
+            .await
+    }
+
+    async fn await_local_sync_to_block_count(&self, block_count: u64) {
+        loop {
+            if self
+                .btc_rpc
+                .status()
+                .is_some_and(|status| status.block_count >= block_count)
+            {
+                break;
+            }
+
+            info!(target: LOG_MODULE_WALLETV2, "Waiting for local bitcoin backend to sync to block count {block_count}");
+
+            sleep(common::sleep_duration()).await;
+        }
+    }
+
+    pub async fn consensus_block_count(&self, dbtx: &mut DatabaseTransaction<'_>) -> u64 {
+        let num_peers = self.cfg.consensus.bitcoin_pks.to_num_peers();
 
         let mut counts = dbtx
             .find_by_prefix(&BlockCountVotePrefix)
@@ -1223,9 +2043,30 @@ pub async fn consensus_fee(
         Some(Amount::from_sat(tx_fee.max(stack_fee)))
     }
 
+    /// The fee of a pegout. While a pegout batch is open a pegout only pays for
+    /// its output, since the pegout which opened the batch has already paid for
+    /// the remainder of the batch transaction.
     pub async fn send_fee(&self, dbtx: &mut DatabaseTransaction<'_>) -> Option<Amount> {
-        self.consensus_fee(dbtx, self.cfg.consensus.send_tx_vbytes)
-            .await
+        if self.pegout_batch(dbtx).await.is_empty() {
+            return self
+                .consensus_fee(dbtx, self.cfg.consensus.send_tx_vbytes)
+                .await;
+        }
+
+        let pending_txs = pending_txs_unordered(dbtx).await;
+
+        let feerate = self
+            .consensus_feerate(dbtx)
+            .await?
+            .max(self.cfg.consensus.feerate_base << pending_txs.len());
+
+        Some(Amount::from_sat(
+            self.cfg
+                .consensus
+                .batch_output_vbytes
+                .saturating_mul(feerate)
+                .saturating_div(1000),
+        ))
     }
 
     pub async fn receive_fee(&self, dbtx: &mut DatabaseTransaction<'_>) -> Option<Amount> {
@@ -1237,6 +2078,154 @@ fn descriptor(&self, tweak: &sha256::Hash) -> Wsh<secp256k1::PublicKey> {
         descriptor(&self.cfg.consensus.bitcoin_pks, tweak)
     }
 
+    fn script_pubkey(&self, tweak: &sha256::Hash) -> ScriptBuf {
+        self.cfg
+            .consensus
+            .descriptor
+            .script_pubkey(&self.cfg.consensus.bitcoin_pks, tweak)
+    }
+
+    fn taproot_key(&self, tweak: &sha256::Hash) -> TaprootKey {
+        TaprootKey::new(
+            &frost::group_public_key(&self.cfg.consensus.bitcoin_pks),
+            tweak,
+        )
+    }
+
+    fn signing_nonces(
+        &self,
+        unsigned_tx: &FederationTx,
+        attempt: u64,
+        index: usize,
+    ) -> SigningNonces {
+        SigningNonces::new(
+            &self.cfg.private.bitcoin_sk,
+            &unsigned_tx.tx.compute_txid(),
+            attempt,
+            index as u64,
+        )
+    }
+
+    fn taproot_sighashes(&self, unsigned_tx: &FederationTx) -> Vec<[u8; 32]> {
+        let prevouts = unsigned_tx
+            .spent_tx_outs
+            .iter()
+            .map(|utxo| TxOut {
+                value: utxo.value,
+                script_pubkey: self.script_pubkey(&utxo.tweak),
+            })
+            .collect::<Vec<TxOut>>();
+
+        let mut sighash_cache = SighashCache::new(unsigned_tx.tx.clone());
+
+        (0..prevouts.len())
+            .map(|index| {
+                sighash_cache
+                    .taproot_key_spend_signature_hash(
+                        index,
+                        &Prevouts::All(&prevouts),
+                        TapSighashType::Default,
+                    )
+                    .expect("Failed to compute taproot sighash")
+                    .to_byte_array()
+            })
+            .collect()
+    }
+
+    fn sign_shares(
+        &self,
+        unsigned_tx: &FederationTx,
+        attempt: u64,
+        commitments: &BTreeMap<PeerId, Vec<NonceCommitment>>,
+    ) -> Vec<SignatureShare> {
+        self.taproot_sighashes(unsigned_tx)
+            .iter()
+            .zip(unsigned_tx.spent_tx_outs.iter())
+            .enumerate()
+            .map(|(index, (sighash, utxo))| {
+                self.taproot_key(&utxo.tweak).sign_share(
+                    &self.cfg.private.bitcoin_sk,
+                    &self.signing_nonces(unsigned_tx, attempt, index),
+                    self.our_peer_id,
+                    &input_commitments(commitments, index),
+                    sighash,
+                )
+            })
+            .collect()
+    }
+
+    fn verify_signature_shares(
+        &self,
+        unsigned_tx: &FederationTx,
+        commitments: &BTreeMap<PeerId, Vec<NonceCommitment>>,
+        shares: &[SignatureShare],
+        peer: PeerId,
+        pk: &PublicKey,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            unsigned_tx.spent_tx_outs.len() == shares.len(),
+            "Incorrect number of signature shares"
+        );
+
+        for (index, ((sighash, utxo), share)) in self
+            .taproot_sighashes(unsigned_tx)
+            .iter()
+            .zip(unsigned_tx.spent_tx_outs.iter())
+            .zip(shares.iter())
+            .enumerate()
+        {
+            ensure!(
+                self.taproot_key(&utxo.tweak).verify_share(
+                    share,
+                    pk,
+                    peer,
+                    &input_commitments(commitments, index),
+                    sighash,
+                ),
+                "Invalid signature share"
+            );
+        }
+
+        Ok(())
+    }
+
+    fn finalize_taproot_tx(
+        &self,
+        federation_tx: &mut FederationTx,
+        commitments: &BTreeMap<PeerId, Vec<NonceCommitment>>,
+        shares: &BTreeMap<PeerId, Vec<SignatureShare>>,
+    ) {
+        assert_eq!(
+            federation_tx.spent_tx_outs.len(),
+            federation_tx.tx.input.len()
+        );
+
+        let sighashes = self.taproot_sighashes(federation_tx);
+
+        for (index, (sighash, utxo)) in sighashes
+            .iter()
+            .zip(federation_tx.spent_tx_outs.iter())
+            .enumerate()
+        {
+            let shares = shares
+                .iter()
+                .map(|(peer, shares)| (*peer, shares[index]))
+                .collect();
+
+            let signature = self.taproot_key(&utxo.tweak).aggregate(
+                &input_commitments(commitments, index),
+                &shares,
+                sighash,
+            );
+
+            federation_tx.tx.input[index].witness =
+                Witness::p2tr_key_spend(&bitcoin::taproot::Signature {
+                    signature,
+                    sighash_type: TapSighashType::Default,
+                });
+        }
+    }
+
     fn sign_tx(&self, unsigned_tx: &FederationTx) -> Vec<Signature> {
         let mut sighash_cache = SighashCache::new(unsigned_tx.tx.clone());
 
@@ -1355,15 +2344,29 @@ async fn get_outputs(
             .collect()
             .await;
 
+        let block_times: BTreeMap<u64, u32> = dbtx
+            .find_by_range(OutputBlockTimeKey(start_index)..OutputBlockTimeKey(end_index))
+            .await
+            .map(|entry| (entry.0.0, entry.1))
+            .collect()
+            .await;
+
         dbtx.find_by_range(OutputKey(start_index)..OutputKey(end_index))
             .await
             .filter_map(|entry| {
-                std::future::ready(entry.1.1.script_pubkey.is_p2wsh().then(|| OutputInfo {
+                let is_script_type = self
+                    .cfg
+                    .consensus
+                    .descriptor
+                    .is_script_type(&entry.1.1.script_pubkey);
+
+                std::future::ready(is_script_type.then(|| OutputInfo {
                     index: entry.0.0,
                     script: entry.1.1.script_pubkey,
                     value: entry.1.1.value,
                     spent: spent.contains(&entry.0.0),
                     outpoint: Some(entry.1.0),
+                    block_time: block_times.get(&entry.0.0).copied(),
                 }))
             })
             .collect()
@@ -1436,6 +2439,38 @@ pub async fn receive_fee_ui(&self) -> Option<Amount> {
             .await
     }
 
+    /// Get the fee budget available to accelerate the pending transaction
+    /// chain for UI display
+    pub async fn fee_budget_ui(&self) -> fedimint_core::Amount {
+        self.fee_budget(&mut self.db.begin_transaction_nc().await)
+            .await
+    }
+
+    /// Returns true if the pending transaction chain is stuck such that the
+    /// federation will accelerate it, for UI display
+    pub async fn pending_tx_chain_stuck_ui(&self) -> bool {
+        if !self.is_consensus_version_active(FEE_BUMP_MODULE_CONSENSUS_VERSION) {
+            return false;
+        }
+
+        let mut dbtx = self.db.begin_transaction_nc().await;
+
+        let pending_txs = pending_txs_unordered(&mut dbtx).await;
+
+        match self.consensus_feerate(&mut dbtx).await {
+            Some(consensus_feerate) => {
+                !pending_txs.is_empty() && is_stuck(&pending_txs, consensus_feerate)
+            }
+            None => false,
+        }
+    }
+
+    /// Get the pegouts waiting for the open batch to close for UI display
+    pub async fn pegout_batch_ui(&self) -> Vec<PendingPegOut> {
+        self.pegout_batch(&mut self.db.begin_transaction_nc().await)
+            .await
+    }
+
     /// Get the current pending transaction info for UI display
     pub async fn pending_tx_chain_ui(&self) -> Vec<TxInfo> {
         self.pending_tx_chain(&mut self.db.begin_transaction_nc().await)
@@ -1449,10 +2484,30 @@ pub async fn tx_chain_ui(&self) -> Vec<TxInfo> {
     }
 
     /// Export recovery keys for federation shutdown. Returns None if the
-    /// federation wallet has not been initialized yet.
+    /// federation wallet has not been initialized yet. For the taproot
+    /// descriptor the keys are shares of the key of the federation UTXO, which
+    /// any threshold of guardians can interpolate.
     pub async fn recovery_keys_ui(&self) -> Option<(BTreeMap<PeerId, String>, String)> {
         let wallet = self.federation_wallet_ui().await?;
 
+        if self.cfg.consensus.descriptor == WalletDescriptor::Tr {
+            let taproot_key = self.taproot_key(&wallet.tweak);
+
+            let pks = self
+                .cfg
+                .consensus
+                .bitcoin_pks
+                .iter()
+                .map(|(peer, pk)| (*peer, taproot_key.tweak_public_share(pk).to_string()))
+                .collect();
+
+            let sk = taproot_key.tweak_secret_share(&self.cfg.private.bitcoin_sk);
+
+            let sk = bitcoin::PrivateKey::new(sk, self.cfg.consensus.network).to_wif();
+
+            return Some((pks, sk));
+        }
+
         let pks = self
             .cfg
             .consensus
@@ -1476,3 +2531,210 @@ pub async fn recovery_keys_ui(&self) -> Option<(BTreeMap<PeerId, String>, String
         Some((pks, sk))
     }
 }
+
+/// Reads the pegout batch window in consensus blocks for config generation,
+/// which defaults to zero and thereby disables batching
+fn batch_window() -> anyhow::Result<u64> {
+    match std::env::var(FM_WALLETV2_BATCH_WINDOW_ENV) {
+        Ok(batch_window) => batch_window.parse().with_context(|| {
+            format!(
+                "{FM_WALLETV2_BATCH_WINDOW_ENV} has to be a number of blocks, got {batch_window}"
+            )
+        }),
+        Err(_) => Ok(0),
+    }
+}
+
+/// Selects the wallet descriptor for config generation
+fn wallet_descriptor() -> WalletDescriptor {
+    if is_env_var_set(FM_WALLETV2_TAPROOT_ENV) {
+        WalletDescriptor::Tr
+    } else {
+        WalletDescriptor::Wsh
+    }
+}
+
+/// Returns the nonce commitments of the signing set for a single input
+fn input_commitments(
+    commitments: &BTreeMap<PeerId, Vec<NonceCommitment>>,
+    index: usize,
+) -> BTreeMap<PeerId, NonceCommitment> {
+    commitments
+        .iter()
+        .map(|(peer, commitments)| (*peer, commitments[index]))
+        .collect()
+}
+
+/// Runs a Pedersen distributed key generation for the group key of the taproot
+/// descriptor and returns our secret share together with the public
+/// verification shares of all guardians. Every guardian deals a random
+/// polynomial of degree threshold minus one by broadcasting commitments to its
+/// coefficients together with a proof of knowledge of its constant term, and
+/// then sends every guardian its evaluation of the polynomial, encrypted with
+/// a key derived from an ephemeral Diffie-Hellman exchange. Since the module
+/// does not know our peer id during config generation, we identify ourselves
+/// by our ephemeral public key.
+async fn run_taproot_dkg(
+    peers: &(dyn PeerHandleOps + Send + Sync),
+) -> anyhow::Result<(SecretKey, BTreeMap<PeerId, PublicKey>)> {
+    let threshold = peers.num_peers().threshold();
+
+    let (ephemeral_sk, ephemeral_pk) = secp256k1::generate_keypair(&mut OsRng);
+
+    let coefficients = (0..threshold)
+        .map(|_| SecretKey::new(&mut OsRng))
+        .collect::<Vec<SecretKey>>();
+
+    let commitment = coefficients
+        .iter()
+        .map(|coefficient| coefficient.public_key(SECP256K1))
+        .collect::<Vec<PublicKey>>();
+
+    let proof = frost::prove_knowledge(&coefficients[0], &ephemeral_pk);
+
+    let dealings: BTreeMap<PeerId, (PublicKey, Vec<PublicKey>, ProofOfKnowledge)> = peers
+        .exchange_encodable((ephemeral_pk, commitment, proof))
+        .await?;
+
+    let our_peers = dealings
+        .iter()
+        .filter(|(_, dealing)| dealing.0 == ephemeral_pk)
+        .map(|(peer, _)| *peer)
+        .collect::<Vec<PeerId>>();
+
+    let [our_peer_id] = our_peers.as_slice() else {
+        bail!("Failed to identify ourselves by our ephemeral public key");
+    };
+
+    let our_peer_id = *our_peer_id;
+
+    for (peer, (ephemeral_pk, commitment, proof)) in &dealings {
+        ensure!(
+            commitment.len() == threshold,
+            "Peer {peer} committed to a polynomial of the wrong degree"
+        );
+
+        ensure!(
+            frost::verify_knowledge(proof, &commitment[0], ephemeral_pk),
+            "Peer {peer} sent an invalid proof of knowledge"
+        );
+    }
+
+    let encrypted_shares = dealings
+        .iter()
+        .map(|(peer, (peer_ephemeral_pk, ..))| {
+            let share = frost::evaluate_polynomial(&coefficients, *peer);
+
+            let pad = share_pad(&ephemeral_sk, peer_ephemeral_pk, our_peer_id, *peer);
+
+            (*peer, xor(&share.secret_bytes(), &pad))
+        })
+        .collect::<BTreeMap<PeerId, [u8; 32]>>();
+
+    let encrypted_shares: BTreeMap<PeerId, BTreeMap<PeerId, [u8; 32]>> =
+        peers.exchange_encodable(encrypted_shares).await?;
+
+    let mut shares = Vec::new();
+
+    for (peer, encrypted_shares) in &encrypted_shares {
+        let (peer_ephemeral_pk, commitment, _) = &dealings[peer];
+
+        let encrypted_share = encrypted_shares
+            .get(&our_peer_id)
+            .with_context(|| format!("Peer {peer} did not send us a share"))?;
+
+        let pad = share_pad(&ephemeral_sk, peer_ephemeral_pk, *peer, our_peer_id);
+
+        let share = SecretKey::from_slice(&xor(encrypted_share, &pad))
+            .ok()
+            .filter(|share| {
+                share.public_key(SECP256K1) == frost::evaluate_commitment(commitment, our_peer_id)
+            })
+            .with_context(|| format!("Peer {peer} sent us an invalid share"))?;
+
+        shares.push(share);
+    }
+
+    let bitcoin_sk = shares
+        .into_iter()
+        .reduce(|a, b| {
+            a.add_tweak(&Scalar::from_be_bytes(b.secret_bytes()).expect("Within field order"))
+                .expect("Sum of shares is zero")
+        })
+        .expect("We have at least one share");
+
+    let bitcoin_pks = dealings
+        .keys()
+        .map(|peer| {
+            let pks = dealings
+                .values()
+                .map(|(_, commitment, _)| frost::evaluate_commitment(commitment, *peer))
+                .collect::<Vec<PublicKey>>();
+
+            let pk = PublicKey::combine_keys(&pks.iter().collect::<Vec<&PublicKey>>())
+                .expect("Failed to combine public key shares");
+
+            (*peer, pk)
+        })
+        .collect();
+
+    Ok((bitcoin_sk, bitcoin_pks))
+}
+
+/// Derives the key to encrypt the share a dealer sends to a recipient during
+/// the distributed key generation
+fn share_pad(sk: &SecretKey, pk: &PublicKey, dealer: PeerId, recipient: PeerId) -> [u8; 32] {
+    let shared_point = pk
+        .mul_tweak(
+            SECP256K1,
+            &Scalar::from_be_bytes(sk.secret_bytes()).expect("Within field order"),
+        )
+        .expect("Failed to compute shared point");
+
+    (shared_point, dealer, recipient)
+        .consensus_hash::<sha256::Hash>()
+        .to_byte_array()
+}
+
+fn xor(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    std::array::from_fn(|i| a[i] ^ b[i])
+}
+
+/// Returns true if the consensus feerate in sats per kvB exceeds the feerate of
+/// the pending transaction chain by the fee bump ratio
+fn is_stuck(pending_txs: &[FederationTx], consensus_feerate: u64) -> bool {
+    let fee = pending_txs.iter().map(|tx| tx.fee.to_sat()).sum::<u64>();
+
+    let vbytes = pending_txs.iter().map(|tx| tx.vbytes).sum::<u64>();
+
+    fee.saturating_mul(1000)
+        .saturating_mul(FEE_BUMP_FEERATE_RATIO)
+        < consensus_feerate.saturating_mul(vbytes)
+}
+
+#[test]
+fn test_is_stuck() {
+    let tx = |vbytes: u64, fee: u64| FederationTx {
+        tx: Transaction {
+            version: Version(2),
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        },
+        spent_tx_outs: vec![],
+        vbytes,
+        fee: Amount::from_sat(fee),
+    };
+
+    // A transaction paying two sats per vbyte is stuck once the consensus
+    // feerate exceeds four sats per vbyte
+    assert!(!is_stuck(&[tx(100, 200)], 4_000));
+    assert!(is_stuck(&[tx(100, 200)], 4_001));
+
+    // The feerate of the chain is the feerate of all its transactions combined
+    assert!(!is_stuck(&[tx(100, 100), tx(100, 300)], 4_000));
+    assert!(is_stuck(&[tx(100, 100), tx(100, 300)], 4_001));
+
+    // An overpaying transaction accelerates the entire chain
+    assert!(!is_stuck(&[tx(100, 0), tx(100, 2_000)], 20_000));
+}
@@ -9,10 +9,12 @@
 use fedimint_dummy_client::DummyClientInit;
 use fedimint_dummy_server::DummyInit;
 use fedimint_eventlog::{Event, EventLogEntry, EventLogId};
+use fedimint_logging::LOG_TEST;
 use fedimint_testing::btc::BitcoinTest;
 use fedimint_testing::fixtures::Fixtures;
 use fedimint_walletv2_client::events::{
-    ReceivePaymentEvent, ReceivePaymentUpdateEvent, SendPaymentEvent, SendPaymentStatus,
+    ReceiveAddressReuseEvent, ReceiveDustEvent, ReceiveExpiredAddressEvent, ReceivePaymentEvent,
+    ReceivePaymentStatus, ReceivePaymentUpdateEvent, SendPaymentEvent, SendPaymentStatus,
     SendPaymentUpdateEvent,
 };
 use fedimint_walletv2_client::{
@@ -21,7 +23,7 @@
 use fedimint_walletv2_common::KIND;
 use fedimint_walletv2_server::{CONFIRMATION_FINALITY_DELAY, WalletInit};
 use futures::StreamExt;
-use tracing::info;
+use tracing::{info, warn};
 
 #[derive(Debug)]
 enum WalletEvent {
@@ -29,6 +31,9 @@ enum WalletEvent {
     SendStatus(SendPaymentUpdateEvent),
     Receive(ReceivePaymentEvent),
     ReceiveStatus(ReceivePaymentUpdateEvent),
+    AddressReuse(ReceiveAddressReuseEvent),
+    ExpiredAddress(ReceiveExpiredAddressEvent),
+    Dust(ReceiveDustEvent),
 }
 
 fn wallet_event_stream(client: &ClientHandleArc) -> impl futures::Stream<Item = WalletEvent> {
@@ -74,11 +79,24 @@ fn try_parse_wallet_event(entry: &EventLogEntry) -> Option<WalletEvent> {
         return entry.to_event().map(WalletEvent::ReceiveStatus);
     }
 
+    if entry.kind == ReceiveAddressReuseEvent::KIND {
+        return entry.to_event().map(WalletEvent::AddressReuse);
+    }
+
+    if entry.kind == ReceiveExpiredAddressEvent::KIND {
+        return entry.to_event().map(WalletEvent::ExpiredAddress);
+    }
+
+    if entry.kind == ReceiveDustEvent::KIND {
+        return entry.to_event().map(WalletEvent::Dust);
+    }
+
     None
 }
 
 fn fixtures() -> Fixtures {
-    Fixtures::new_primary(DummyClientInit, DummyInit).with_module(WalletClientInit, WalletInit)
+    Fixtures::new_primary(DummyClientInit, DummyInit)
+        .with_module(WalletClientInit::default(), WalletInit)
 }
 
 // We need the consensus block count to reach a non-zero value before we send in
@@ -238,6 +256,199 @@ async fn fee_exceeds_one_bitcoin_with_many_pending_txs() -> anyhow::Result<()> {
     panic!("Transaction fee did not exceed one bitcoin")
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn repeat_deposits_to_an_address_are_claimed_separately() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_not_degraded().await;
+    let client = fed.new_client().await;
+    let bitcoin = fixtures.bitcoin();
+
+    initialize_consensus(&client, &bitcoin).await?;
+
+    let address = client
+        .get_first_module::<WalletClientModule>()?
+        .receive()
+        .await;
+
+    info!("Deposit twice to the same address...");
+
+    bitcoin
+        .send_and_mine_block(&address, Amount::from_sat(1_000_000))
+        .await;
+
+    bitcoin
+        .send_and_mine_block(&address, Amount::from_sat(1_000_000))
+        .await;
+
+    await_finality_delay(&client, &bitcoin).await?;
+
+    let mut events = pin!(wallet_event_stream(&client));
+
+    let mut receives = 0;
+    let mut reuse = None;
+
+    while receives < 2 || reuse.is_none() {
+        match events.next().await.expect("Event stream is infinite") {
+            WalletEvent::ReceiveStatus(status) => {
+                assert_eq!(status.status, ReceivePaymentStatus::Success);
+
+                receives += 1;
+            }
+            WalletEvent::AddressReuse(event) => reuse = Some(event),
+            _ => {}
+        }
+    }
+
+    let reuse = reuse.expect("Loop exits once the reuse event is found");
+
+    assert_eq!(reuse.address, address.as_unchecked().clone());
+    assert_eq!(reuse.deposits, 2);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deposits_after_the_address_lifetime_are_claimed() -> anyhow::Result<()> {
+    // Every repeat deposit is made after the lifetime of the address
+    let fixtures = Fixtures::new_primary(DummyClientInit, DummyInit).with_module(
+        WalletClientInit {
+            address_lifetime: Duration::ZERO,
+        },
+        WalletInit,
+    );
+
+    let fed = fixtures.new_fed_not_degraded().await;
+    let client = fed.new_client().await;
+    let bitcoin = fixtures.bitcoin();
+
+    initialize_consensus(&client, &bitcoin).await?;
+
+    let address = client
+        .get_first_module::<WalletClientModule>()?
+        .receive()
+        .await;
+
+    info!("Deposit twice to the same address...");
+
+    bitcoin
+        .send_and_mine_block(&address, Amount::from_sat(1_000_000))
+        .await;
+
+    bitcoin
+        .send_and_mine_block(&address, Amount::from_sat(1_000_000))
+        .await;
+
+    await_finality_delay(&client, &bitcoin).await?;
+
+    let mut events = pin!(wallet_event_stream(&client));
+
+    let mut receives = 0;
+    let mut expired = None;
+
+    while receives < 2 || expired.is_none() {
+        match events.next().await.expect("Event stream is infinite") {
+            WalletEvent::ReceiveStatus(status) => {
+                assert_eq!(status.status, ReceivePaymentStatus::Success);
+
+                receives += 1;
+            }
+            WalletEvent::ExpiredAddress(event) => expired = Some(event),
+            WalletEvent::AddressReuse(_) => panic!("The address lifetime has passed"),
+            _ => {}
+        }
+    }
+
+    let expired = expired.expect("Loop exits once the expired address event is found");
+
+    assert_eq!(expired.address, address.as_unchecked().clone());
+    assert_eq!(expired.deposits, 2);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dust_deposits_are_deferred_without_blocking_later_deposits() -> anyhow::Result<()> {
+    if Fixtures::is_real_test() {
+        warn!(
+            target: LOG_TEST,
+            "Skipping test as bitcoind does not relay outputs below its own dust limit"
+        );
+        return Ok(());
+    }
+
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_not_degraded().await;
+    let client = fed.new_client().await;
+    let bitcoin = fixtures.bitcoin();
+
+    initialize_consensus(&client, &bitcoin).await?;
+
+    let module = client.get_first_module::<WalletClientModule>()?;
+
+    let dust_address = module.receive().await;
+
+    let dust_threshold = module.receive_dust_threshold().await?;
+
+    info!("Deposit dust...");
+
+    bitcoin
+        .send_and_mine_block(&dust_address, dust_threshold)
+        .await;
+
+    await_finality_delay(&client, &bitcoin).await?;
+
+    let mut events = pin!(wallet_event_stream(&client));
+
+    let dust = loop {
+        match events.next().await.expect("Event stream is infinite") {
+            WalletEvent::Dust(event) => break event,
+            WalletEvent::Receive(_) => panic!("Dust deposit must not be claimed"),
+            _ => {}
+        }
+    };
+
+    assert_eq!(dust.address, dust_address.as_unchecked().clone());
+    assert_eq!(dust.value, dust_threshold);
+    assert_eq!(dust.dust_threshold, dust_threshold);
+
+    info!("Deposit to the next address...");
+
+    // The scanner derives the next address once it has processed the dust
+    let address = loop {
+        let address = module.receive().await;
+
+        if address != dust_address {
+            break address;
+        }
+
+        sleep_in_test(
+            "Waiting for the next receive address",
+            Duration::from_secs(1),
+        )
+        .await;
+    };
+
+    bitcoin
+        .send_and_mine_block(&address, Amount::from_sat(1_000_000))
+        .await;
+
+    await_finality_delay(&client, &bitcoin).await?;
+
+    loop {
+        match events.next().await.expect("Event stream is infinite") {
+            WalletEvent::ReceiveStatus(status) => {
+                assert_eq!(status.status, ReceivePaymentStatus::Success);
+
+                break;
+            }
+            WalletEvent::Dust(_) => panic!("Deferred dust deposit is only reported once"),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn send_to_a_mainnet_address_is_rejected() -> anyhow::Result<()> {
     let fixtures = fixtures();
@@ -286,6 +497,640 @@ async fn send_below_the_dust_limit_is_rejected() -> anyhow::Result<()> {
     Ok(())
 }
 
+mod replay {
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use bitcoin::absolute::LockTime;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::consensus::serialize;
+    use bitcoin::hashes::Hash as _;
+    use bitcoin::transaction::Version;
+    use bitcoin::{
+        Amount, Block, BlockHash, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+        Witness,
+    };
+    use fedimint_core::task::sleep_in_test;
+    use fedimint_core::time::duration_since_epoch;
+    use fedimint_server_bitcoin_rpc::block_file::BlockFileClient;
+    use fedimint_walletv2_client::WalletClientModule;
+    use fedimint_walletv2_server::CONFIRMATION_FINALITY_DELAY;
+    use tracing::info;
+
+    use crate::{await_consensus_block_count, await_federation_total_value, fixtures};
+
+    /// Chain segment recorded as block files, as served by [`BlockFileClient`]
+    struct RecordedChain {
+        dir: PathBuf,
+        tip: BlockHash,
+        block_count: u64,
+    }
+
+    impl RecordedChain {
+        fn new(dir: &Path) -> Self {
+            Self {
+                dir: dir.to_path_buf(),
+                tip: BlockHash::all_zeros(),
+                block_count: 0,
+            }
+        }
+
+        /// Records a block confirming `txdata` on top of the segment
+        fn record_block(&mut self, txdata: Vec<Transaction>) {
+            let mut block = genesis_block(Network::Regtest);
+
+            block.header.prev_blockhash = self.tip;
+            block.header.time =
+                u32::try_from(duration_since_epoch().as_secs()).expect("Block time fits into u32");
+            let coinbase = transaction(
+                self.block_count,
+                vec![TxOut {
+                    value: Amount::from_int_btc(50),
+                    script_pubkey: ScriptBuf::new(),
+                }],
+            );
+
+            block.txdata = [coinbase].into_iter().chain(txdata).collect();
+            block.header.merkle_root = block.compute_merkle_root().expect("Block has transactions");
+
+            write_block(&self.dir, self.block_count, &block);
+
+            self.tip = block.block_hash();
+            self.block_count += 1;
+        }
+
+        fn record_blocks(&mut self, n: u64) {
+            for _ in 0..n {
+                self.record_block(vec![]);
+            }
+        }
+    }
+
+    fn write_block(dir: &Path, height: u64, block: &Block) {
+        std::fs::write(dir.join(format!("{height}.block")), serialize(block))
+            .expect("Failed to write block file");
+    }
+
+    /// Returns a transaction that is made unique by `tag`, since the block
+    /// file backend does not verify its inputs
+    fn transaction(tag: u64, output: Vec<TxOut>) -> Transaction {
+        Transaction {
+            version: Version(2),
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::builder()
+                    .push_slice(tag.to_be_bytes())
+                    .into_script(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn deposit_is_claimed_when_replaying_a_recorded_chain() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        let mut chain = RecordedChain::new(dir.path());
+
+        // The federation votes on the block count minus the finality delay
+        chain.record_blocks(2 + CONFIRMATION_FINALITY_DELAY);
+
+        let fixtures = fixtures().with_block_file_rpc(dir.path());
+        let fed = fixtures.new_fed_not_degraded().await;
+        let client = fed.new_client().await;
+
+        await_consensus_block_count(&client, 1).await?;
+
+        info!("Record a deposit into the federation...");
+
+        let address = client
+            .get_first_module::<WalletClientModule>()?
+            .receive()
+            .await;
+
+        let deposit = transaction(
+            u64::MAX,
+            vec![TxOut {
+                value: Amount::from_int_btc(1),
+                script_pubkey: address.script_pubkey(),
+            }],
+        );
+
+        let deposit_outpoint = OutPoint::new(deposit.compute_txid(), 0);
+
+        chain.record_block(vec![deposit]);
+
+        chain.record_blocks(CONFIRMATION_FINALITY_DELAY);
+
+        await_consensus_block_count(&client, chain.block_count - CONFIRMATION_FINALITY_DELAY)
+            .await?;
+
+        info!("Wait for deposit to be auto-claimed...");
+
+        await_federation_total_value(&client, Amount::from_sat(99_000_000)).await?;
+
+        info!("Wait for the federation to submit the transaction claiming the deposit...");
+
+        let block_file_client = BlockFileClient::new(dir.path())?;
+
+        loop {
+            let claimed = block_file_client.outbox()?.values().any(|tx| {
+                tx.input
+                    .iter()
+                    .any(|input| input.previous_output == deposit_outpoint)
+            });
+
+            if claimed {
+                return Ok(());
+            }
+
+            sleep_in_test(
+                "Waiting for the federation to submit its transaction",
+                Duration::from_secs(1),
+            )
+            .await;
+        }
+    }
+}
+
+mod fee_bump {
+    use std::time::Duration;
+
+    use bitcoin::hashes::{Hash as _, sha256};
+    use bitcoin::{Amount, ScriptBuf, Transaction, TxIn, TxOut};
+    use fedimint_core::PeerId;
+    use fedimint_core::db::mem_impl::MemDatabase;
+    use fedimint_core::db::{Database, IDatabaseTransactionOpsCoreTyped, IRawDatabaseExt};
+    use fedimint_core::task::TaskGroup;
+    use fedimint_server_core::bitcoin_rpc::ServerBitcoinRpcMonitor;
+    use fedimint_server_core::{ConfigGenModuleArgs, ServerModule, ServerModuleInit};
+    use fedimint_testing::btc::BitcoinTest as _;
+    use fedimint_walletv2_common::config::WalletConfig;
+    use fedimint_walletv2_common::{
+        FederationWallet, MODULE_CONSENSUS_VERSION, TxInfo, WalletConsensusItem,
+    };
+    use fedimint_walletv2_server::db::{
+        FederationWalletKey, FeeBudgetKey, TxInfoKey, UnconfirmedTxKey,
+    };
+    use fedimint_walletv2_server::{
+        CONFIRMATION_FINALITY_DELAY, FEE_BUMP_DELAY, FederationTx, SpentTxOut, Wallet,
+    };
+
+    use crate::{WalletInit, fixtures};
+
+    /// The consensus block count at which the pending transaction was created
+    const CREATED: u64 = 1;
+
+    /// The feerate vote in sats per kvB, ten times the feerate of the pending
+    /// transaction
+    const RAISED_FEERATE: u64 = 20_000;
+
+    /// Creates the wallet of the first guardian of a federation with a single
+    /// pending transaction paying two sats per vbyte. The wallet is driven by
+    /// processing consensus items directly.
+    async fn wallet_with_pending_tx(
+        fee_budget: fedimint_core::Amount,
+    ) -> anyhow::Result<(Wallet, Database)> {
+        let fixtures = fixtures();
+
+        // The wallet requires its bitcoin backend to be synced beyond the
+        // consensus block count by the finality delay
+        fixtures
+            .bitcoin()
+            .mine_blocks(CREATED + FEE_BUMP_DELAY + CONFIRMATION_FINALITY_DELAY)
+            .await;
+
+        let peers = (0..4).map(PeerId::from).collect::<Vec<_>>();
+
+        let args = ConfigGenModuleArgs {
+            network: bitcoin::Network::Regtest,
+            disable_base_fees: false,
+        };
+
+        let cfg: WalletConfig =
+            WalletInit.trusted_dealer_gen(&peers, &args)[&PeerId::from(0)].to_typed()?;
+
+        let vbytes = cfg.consensus.receive_tx_vbytes;
+        let fee = Amount::from_sat(2 * vbytes);
+        let value = Amount::from_int_btc(1);
+        let tweak = sha256::Hash::hash(b"federation-wallet");
+
+        // The pending transaction is never broadcast successfully, since it
+        // spends an output that does not exist
+        let tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value,
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+
+        let txid = tx.compute_txid();
+
+        let db = MemDatabase::new().into_database();
+
+        let mut dbtx = db.begin_transaction().await;
+
+        dbtx.insert_new_entry(
+            &FederationWalletKey,
+            &FederationWallet {
+                value,
+                outpoint: bitcoin::OutPoint { txid, vout: 0 },
+                tweak,
+            },
+        )
+        .await;
+
+        dbtx.insert_new_entry(
+            &UnconfirmedTxKey(txid),
+            &FederationTx {
+                tx,
+                spent_tx_outs: vec![SpentTxOut {
+                    value: value + fee,
+                    tweak,
+                }],
+                vbytes,
+                fee,
+            },
+        )
+        .await;
+
+        dbtx.insert_new_entry(
+            &TxInfoKey(0),
+            &TxInfo {
+                index: 0,
+                txid,
+                input: value + fee,
+                output: value,
+                fee,
+                vbytes,
+                created: CREATED,
+            },
+        )
+        .await;
+
+        dbtx.insert_new_entry(&FeeBudgetKey, &fee_budget).await;
+
+        dbtx.commit_tx().await;
+
+        let task_group = TaskGroup::new();
+
+        let wallet = Wallet::new(
+            cfg,
+            MODULE_CONSENSUS_VERSION,
+            PeerId::from(0),
+            &db,
+            &task_group,
+            ServerBitcoinRpcMonitor::new(
+                fixtures.server_bitcoin_rpc(),
+                Duration::from_millis(100),
+                &task_group,
+            ),
+        );
+
+        vote(&wallet, &db, WalletConsensusItem::BlockCount(CREATED)).await?;
+
+        Ok((wallet, db))
+    }
+
+    /// Processes the same consensus item for a threshold of guardians
+    async fn vote(wallet: &Wallet, db: &Database, item: WalletConsensusItem) -> anyhow::Result<()> {
+        let mut dbtx = db.begin_transaction().await;
+
+        for peer in (0..3).map(PeerId::from) {
+            wallet
+                .process_consensus_item(&mut dbtx.to_ref_nc(), item.clone(), peer)
+                .await?;
+        }
+
+        dbtx.commit_tx().await;
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stuck_tx_chain_is_accelerated_from_the_fee_budget() -> anyhow::Result<()> {
+        let fee_budget = fedimint_core::Amount::from_sats(100_000);
+
+        let (wallet, db) = wallet_with_pending_tx(fee_budget).await?;
+
+        assert!(!wallet.pending_tx_chain_stuck_ui().await);
+
+        vote(
+            &wallet,
+            &db,
+            WalletConsensusItem::Feerate(Some(RAISED_FEERATE)),
+        )
+        .await?;
+
+        assert!(wallet.pending_tx_chain_stuck_ui().await);
+
+        // The federation waits for the transaction to be mined at the old feerate
+        vote(
+            &wallet,
+            &db,
+            WalletConsensusItem::BlockCount(CREATED + FEE_BUMP_DELAY - 1),
+        )
+        .await?;
+
+        assert_eq!(wallet.pending_tx_chain_ui().await.len(), 1);
+        assert_eq!(wallet.fee_budget_ui().await, fee_budget);
+
+        vote(
+            &wallet,
+            &db,
+            WalletConsensusItem::BlockCount(CREATED + FEE_BUMP_DELAY),
+        )
+        .await?;
+
+        let pending_tx_chain = wallet.pending_tx_chain_ui().await;
+
+        assert_eq!(pending_tx_chain.len(), 2);
+
+        let bump = &pending_tx_chain[0];
+
+        assert_eq!(bump.created, CREATED + FEE_BUMP_DELAY);
+        assert_eq!(bump.input, bump.output + bump.fee);
+
+        assert_eq!(
+            wallet.fee_budget_ui().await,
+            fee_budget - fedimint_core::Amount::from_sats(bump.fee.to_sat())
+        );
+
+        // The appended transaction pays the fee missing for the entire chain
+        assert!(!wallet.pending_tx_chain_stuck_ui().await);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stuck_tx_chain_is_not_accelerated_beyond_the_fee_budget() -> anyhow::Result<()> {
+        // The fee budget collected for a single peg-in
+        let fee_budget = fedimint_core::Amount::from_sats(100);
+
+        let (wallet, db) = wallet_with_pending_tx(fee_budget).await?;
+
+        vote(
+            &wallet,
+            &db,
+            WalletConsensusItem::Feerate(Some(RAISED_FEERATE)),
+        )
+        .await?;
+
+        vote(
+            &wallet,
+            &db,
+            WalletConsensusItem::BlockCount(CREATED + FEE_BUMP_DELAY),
+        )
+        .await?;
+
+        assert_eq!(wallet.pending_tx_chain_ui().await.len(), 1);
+        assert_eq!(wallet.fee_budget_ui().await, fee_budget);
+        assert!(wallet.pending_tx_chain_stuck_ui().await);
+
+        Ok(())
+    }
+}
+
+mod pegout_batch {
+    use std::time::Duration;
+
+    use bitcoin::hashes::{Hash as _, hash160, sha256};
+    use bitcoin::{Amount, Txid};
+    use fedimint_core::db::mem_impl::MemDatabase;
+    use fedimint_core::db::{Database, IDatabaseTransactionOpsCoreTyped, IRawDatabaseExt};
+    use fedimint_core::module::ModuleConsensusVersion;
+    use fedimint_core::task::TaskGroup;
+    use fedimint_core::{OutPoint, PeerId, TransactionId};
+    use fedimint_server_core::bitcoin_rpc::ServerBitcoinRpcMonitor;
+    use fedimint_server_core::{ConfigGenModuleArgs, ServerModule, ServerModuleInit};
+    use fedimint_testing::btc::BitcoinTest as _;
+    use fedimint_walletv2_common::config::WalletConfig;
+    use fedimint_walletv2_common::{
+        FederationWallet, MODULE_CONSENSUS_VERSION, StandardScript, WalletConsensusItem,
+        WalletOutput, WalletOutputV0,
+    };
+    use fedimint_walletv2_server::db::{FederationWalletKey, UnconfirmedTxKey};
+    use fedimint_walletv2_server::{CONFIRMATION_FINALITY_DELAY, MAX_PEGOUT_BATCH_SIZE, Wallet};
+
+    use crate::{WalletInit, fixtures};
+
+    /// The consensus block count at which the first pegout opens the batch
+    const OPENED: u64 = 1;
+
+    /// The number of consensus blocks a pegout batch stays open
+    const BATCH_WINDOW: u64 = 3;
+
+    /// Creates the wallet of the first guardian of a federation with a batch
+    /// window and a confirmed federation UTXO of one bitcoin. The wallet is
+    /// driven by processing consensus items and outputs directly.
+    async fn wallet_with_batch_window(
+        consensus_version: ModuleConsensusVersion,
+    ) -> anyhow::Result<(Wallet, Database)> {
+        let fixtures = fixtures();
+
+        // The wallet requires its bitcoin backend to be synced beyond the
+        // consensus block count by the finality delay
+        fixtures
+            .bitcoin()
+            .mine_blocks(OPENED + BATCH_WINDOW + CONFIRMATION_FINALITY_DELAY)
+            .await;
+
+        let peers = (0..4).map(PeerId::from).collect::<Vec<_>>();
+
+        let args = ConfigGenModuleArgs {
+            network: bitcoin::Network::Regtest,
+            disable_base_fees: false,
+        };
+
+        let mut cfg: WalletConfig =
+            WalletInit.trusted_dealer_gen(&peers, &args)[&PeerId::from(0)].to_typed()?;
+
+        cfg.consensus.batch_window = BATCH_WINDOW;
+
+        let db = MemDatabase::new().into_database();
+
+        let mut dbtx = db.begin_transaction().await;
+
+        dbtx.insert_new_entry(
+            &FederationWalletKey,
+            &FederationWallet {
+                value: Amount::from_int_btc(1),
+                outpoint: bitcoin::OutPoint {
+                    txid: Txid::all_zeros(),
+                    vout: 0,
+                },
+                tweak: sha256::Hash::hash(b"federation-wallet"),
+            },
+        )
+        .await;
+
+        dbtx.commit_tx().await;
+
+        let task_group = TaskGroup::new();
+
+        let wallet = Wallet::new(
+            cfg,
+            consensus_version,
+            PeerId::from(0),
+            &db,
+            &task_group,
+            ServerBitcoinRpcMonitor::new(
+                fixtures.server_bitcoin_rpc(),
+                Duration::from_millis(100),
+                &task_group,
+            ),
+        );
+
+        vote(&wallet, &db, WalletConsensusItem::BlockCount(OPENED)).await?;
+        vote(&wallet, &db, WalletConsensusItem::Feerate(Some(1000))).await?;
+
+        Ok((wallet, db))
+    }
+
+    /// Processes the same consensus item for a threshold of guardians
+    async fn vote(wallet: &Wallet, db: &Database, item: WalletConsensusItem) -> anyhow::Result<()> {
+        let mut dbtx = db.begin_transaction().await;
+
+        for peer in (0..3).map(PeerId::from) {
+            wallet
+                .process_consensus_item(&mut dbtx.to_ref_nc(), item.clone(), peer)
+                .await?;
+        }
+
+        dbtx.commit_tx().await;
+
+        Ok(())
+    }
+
+    /// Processes a pegout of fifty thousand sats as the output of a distinct
+    /// federation transaction
+    async fn pegout(wallet: &Wallet, db: &Database, index: u8) -> anyhow::Result<()> {
+        let output = WalletOutput::V0(WalletOutputV0 {
+            destination: StandardScript::P2WPKH(hash160::Hash::hash(&[index])),
+            value: Amount::from_sat(50_000),
+            fee: wallet
+                .send_fee_ui()
+                .await
+                .expect("The consensus feerate is available"),
+        });
+
+        let outpoint = OutPoint {
+            txid: TransactionId::from_byte_array([index; 32]),
+            out_idx: 0,
+        };
+
+        let mut dbtx = db.begin_transaction().await;
+
+        wallet
+            .process_output(&mut dbtx.to_ref_nc(), &output, outpoint)
+            .await?;
+
+        dbtx.commit_tx().await;
+
+        Ok(())
+    }
+
+    /// Returns the number of outputs of the single pending transaction
+    async fn pending_tx_outputs(wallet: &Wallet, db: &Database) -> usize {
+        let pending_tx_chain = wallet.pending_tx_chain_ui().await;
+
+        assert_eq!(pending_tx_chain.len(), 1);
+
+        db.begin_transaction_nc()
+            .await
+            .get_value(&UnconfirmedTxKey(pending_tx_chain[0].txid))
+            .await
+            .expect("The pending transaction is unconfirmed")
+            .tx
+            .output
+            .len()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pegouts_within_the_batch_window_share_a_transaction() -> anyhow::Result<()> {
+        let (wallet, db) = wallet_with_batch_window(MODULE_CONSENSUS_VERSION).await?;
+
+        pegout(&wallet, &db, 0).await?;
+        pegout(&wallet, &db, 1).await?;
+
+        assert_eq!(wallet.pegout_batch_ui().await.len(), 2);
+        assert!(wallet.pending_tx_chain_ui().await.is_empty());
+
+        vote(
+            &wallet,
+            &db,
+            WalletConsensusItem::BlockCount(OPENED + BATCH_WINDOW - 1),
+        )
+        .await?;
+
+        assert_eq!(wallet.pegout_batch_ui().await.len(), 2);
+        assert!(wallet.pending_tx_chain_ui().await.is_empty());
+
+        vote(
+            &wallet,
+            &db,
+            WalletConsensusItem::BlockCount(OPENED + BATCH_WINDOW),
+        )
+        .await?;
+
+        assert!(wallet.pegout_batch_ui().await.is_empty());
+
+        // The change output followed by both pegouts
+        assert_eq!(pending_tx_outputs(&wallet, &db).await, 3);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn full_pegout_batch_is_closed_before_the_batch_window() -> anyhow::Result<()> {
+        let (wallet, db) = wallet_with_batch_window(MODULE_CONSENSUS_VERSION).await?;
+
+        for index in 0..MAX_PEGOUT_BATCH_SIZE - 1 {
+            pegout(&wallet, &db, u8::try_from(index)?).await?;
+        }
+
+        assert_eq!(
+            wallet.pegout_batch_ui().await.len(),
+            MAX_PEGOUT_BATCH_SIZE - 1
+        );
+        assert!(wallet.pending_tx_chain_ui().await.is_empty());
+
+        pegout(&wallet, &db, u8::try_from(MAX_PEGOUT_BATCH_SIZE - 1)?).await?;
+
+        assert!(wallet.pegout_batch_ui().await.is_empty());
+
+        // The change output followed by the pegouts of the full batch
+        assert_eq!(
+            pending_tx_outputs(&wallet, &db).await,
+            MAX_PEGOUT_BATCH_SIZE + 1
+        );
+
+        assert_eq!(wallet.consensus_block_count_ui().await, OPENED);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pegouts_are_not_batched_before_the_consensus_version() -> anyhow::Result<()> {
+        let (wallet, db) = wallet_with_batch_window(ModuleConsensusVersion::new(1, 0)).await?;
+
+        pegout(&wallet, &db, 0).await?;
+
+        assert!(wallet.pegout_batch_ui().await.is_empty());
+
+        // The change output followed by the pegout
+        assert_eq!(pending_tx_outputs(&wallet, &db).await, 2);
+
+        Ok(())
+    }
+}
+
 mod db {
     use anyhow::{Context, bail, ensure};
     use bitcoin::hashes::{Hash as _, sha256};
@@ -670,6 +1515,16 @@ async fn test_server_db_migrations() -> anyhow::Result<()> {
                             "the federation wallet must round-trip unchanged"
                         );
                     }
+                    DbKeyPrefix::NonceCommitments
+                    | DbKeyPrefix::SignatureShares
+                    | DbKeyPrefix::FeeBudget
+                    | DbKeyPrefix::PendingPegOut
+                    | DbKeyPrefix::SigningAttempt
+                    | DbKeyPrefix::OutputBlockTime => {
+                        // The snapshot holds no rows under these prefixes, since
+                        // they are only used by taproot federations or were
+                        // introduced after the snapshot was taken.
+                    }
                 }
             }
 
@@ -708,7 +1563,7 @@ async fn snapshot_client_db_migrations() -> anyhow::Result<()> {
     #[tokio::test(flavor = "multi_thread")]
     async fn test_client_db_migrations() -> anyhow::Result<()> {
         let _ = TracingSetup::default().init();
-        let module = DynClientModuleInit::from(WalletClientInit);
+        let module = DynClientModuleInit::from(WalletClientInit::default());
 
         validate_migrations_client::<_, _, WalletClientModule>(
             module,
@@ -743,6 +1598,10 @@ async fn test_client_db_migrations() -> anyhow::Result<()> {
                                  got {indices:?}"
                             );
                         }
+                        db::DbKeyPrefix::AddressDeposits | db::DbKeyPrefix::DeferredOutput => {
+                            // Introduced after the snapshot was taken, so they hold
+                            // no rows under these prefixes.
+                        }
                     }
                 }
 